@@ -0,0 +1,121 @@
+//! Batch operations on 2D float32 arrays, one key per row.
+//!
+//! The rows are copied out of the array buffer at once, so no Python object is created
+//! per row.
+
+use numpy::PyArray1;
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::types::PyAnyMethods;
+use pyo3::{Bound, FromPyObject, PyAny, PyResult};
+
+use crate::dlpack;
+use crate::insert_tolerance;
+use crate::valuepy::ValuePy;
+use crate::vecpy::VecPy;
+
+/// Values found for a batch of queries, and their distances to the queries.
+pub type FoundRows<'py, V = ValuePy> = (Vec<Option<V>>, Bound<'py, PyArray1<f32>>);
+
+/// Tolerance of every inserted row, or one per row.
+#[derive(FromPyObject)]
+pub enum Tolerances {
+    One(f32),
+    Each(Vec<f32>),
+}
+
+/// Splits a C-contiguous float32 array or tensor of shape (N, D) into N keys.
+fn key_rows(array: &Bound<'_, PyAny>) -> PyResult<Vec<VecPy>> {
+    let (flat, shape) = match PyBuffer::<f32>::get(array) {
+        Ok(buffer) if buffer.is_c_contiguous() => {
+            (buffer.to_vec(array.py())?, buffer.shape().to_vec())
+        }
+        Ok(_) => return Err(PyTypeError::new_err("expected a C-contiguous array")),
+        Err(_) => match dlpack::copy_f32(array)? {
+            Some(tensor) => (tensor.data, tensor.shape),
+            None => {
+                return Err(PyTypeError::new_err(format!(
+                    "expected a float32 array or tensor, got {}",
+                    array.get_type()
+                )))
+            }
+        },
+    };
+    let [rows, dim] = shape[..] else {
+        return Err(PyTypeError::new_err("expected a 2-dimensional array"));
+    };
+    if dim == 0 {
+        return Ok((0..rows)
+            .map(|_| VecPy {
+                inner: Vec::new(),
+                guard: None,
+            })
+            .collect());
+    }
+    Ok(flat
+        .chunks_exact(dim)
+        .map(|row| VecPy {
+            inner: row.to_vec(),
+            guard: None,
+        })
+        .collect())
+}
+
+/// Looks up every row of `queries` with `find_all`, which returns the closest match of
+/// each key along with its distance. Misses come back as None, with a NaN distance.
+pub fn find_rows<'py, V>(
+    queries: &Bound<'py, PyAny>,
+    find_all: impl FnOnce(Vec<VecPy>) -> PyResult<Vec<Option<(V, f32)>>>,
+) -> PyResult<FoundRows<'py, V>> {
+    // raise an ImportError rather than panic when building the result without numpy
+    queries.py().import("numpy")?;
+    let found = find_all(key_rows(queries)?)?;
+    let distances = found
+        .iter()
+        .map(|found| found.as_ref().map_or(f32::NAN, |(_, distance)| *distance))
+        .collect();
+    let values = found
+        .into_iter()
+        .map(|found| found.map(|(value, _)| value))
+        .collect();
+    Ok((values, PyArray1::from_vec(queries.py(), distances)))
+}
+
+/// Pairs every row of `keys` with its value and tolerance, after running `check` on
+/// each key. Nothing is returned unless every row passes, so that a bad row does not
+/// leave the batch half inserted.
+pub fn insert_rows<V>(
+    keys: &Bound<'_, PyAny>,
+    values: Vec<V>,
+    tolerances: Option<Tolerances>,
+    default: Option<f32>,
+    mut check: impl FnMut(&mut VecPy) -> PyResult<()>,
+) -> PyResult<Vec<(VecPy, V, f32)>> {
+    let mut keys = key_rows(keys)?;
+    if values.len() != keys.len() {
+        return Err(PyValueError::new_err(format!(
+            "got {} keys but {} values",
+            keys.len(),
+            values.len()
+        )));
+    }
+    let tolerances = match tolerances {
+        Some(Tolerances::Each(tolerances)) if tolerances.len() != keys.len() => {
+            return Err(PyValueError::new_err(format!(
+                "got {} keys but {} tolerances",
+                keys.len(),
+                tolerances.len()
+            )))
+        }
+        Some(Tolerances::Each(tolerances)) => tolerances,
+        Some(Tolerances::One(tol)) => vec![tol; keys.len()],
+        None => vec![insert_tolerance(None, default)?; keys.len()],
+    };
+    keys.iter_mut().try_for_each(&mut check)?;
+    Ok(keys
+        .into_iter()
+        .zip(values)
+        .zip(tolerances)
+        .map(|((key, value), tol)| (key, value, tol))
+        .collect())
+}
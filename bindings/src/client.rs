@@ -0,0 +1,85 @@
+use proximity::ipc::IpcClient;
+use pyo3::types::{PyDict, PyDictMethods};
+use pyo3::{pyclass, pymethods, Bound, PyAny, PyObject, PyResult, Python};
+
+use crate::pickle::{dumps, loads};
+use crate::to_py_err;
+use crate::vecpy::VecPy;
+
+/// Connection to a `proximity-daemon` listening on the Unix socket `path`.
+/// The cache lives in the daemon, so it survives restarts of this interpreter.
+/// Values are pickled.
+#[pyclass(module = "proximipy")]
+pub struct CacheClient {
+    inner: IpcClient,
+}
+
+#[pymethods]
+impl CacheClient {
+    #[new]
+    pub fn new(path: &str) -> PyResult<Self> {
+        Ok(Self {
+            inner: IpcClient::connect(path).map_err(to_py_err)?,
+        })
+    }
+
+    fn find(&mut self, py: Python<'_>, k: VecPy) -> PyResult<Option<PyObject>> {
+        k.check_unguarded()?;
+        let found = py
+            .allow_threads(|| self.inner.find(&k.inner))
+            .map_err(to_py_err)?;
+        found.map(|bytes| loads(py, &bytes)).transpose()
+    }
+
+    /// Looks up every key in a single round trip to the daemon.
+    fn batch_find(&mut self, py: Python<'_>, ks: Vec<VecPy>) -> PyResult<Vec<Option<PyObject>>> {
+        let keys: Vec<Vec<f32>> = ks
+            .into_iter()
+            .map(|k| k.check_unguarded().map(|()| k.inner))
+            .collect::<PyResult<_>>()?;
+        let found = py
+            .allow_threads(|| self.inner.batch_find(&keys))
+            .map_err(to_py_err)?;
+        found
+            .into_iter()
+            .map(|value| value.map(|bytes| loads(py, &bytes)).transpose())
+            .collect()
+    }
+
+    /// Inserts with the given tolerance, or else the one the daemon was started with.
+    /// Returns how many entries were evicted to make room.
+    #[pyo3(signature = (key, value, tolerance=None))]
+    fn insert(
+        &mut self,
+        key: VecPy,
+        value: &Bound<'_, PyAny>,
+        tolerance: Option<f32>,
+    ) -> PyResult<usize> {
+        key.check_unguarded()?;
+        let py = value.py();
+        let value = dumps(value)?;
+        py.allow_threads(|| self.inner.insert(&key.inner, &value, tolerance))
+            .map_err(to_py_err)
+    }
+
+    /// Empties the daemon cache, for every client.
+    fn clear(&mut self) -> PyResult<()> {
+        self.inner.clear().map_err(to_py_err)
+    }
+
+    /// `len`, `hits`, `misses` and `recent_hit_rate` of the daemon cache, where hits and
+    /// misses count the lookups of every client.
+    fn stats<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let stats = self.inner.stats().map_err(to_py_err)?;
+        let dict = PyDict::new(py);
+        dict.set_item("len", stats.len)?;
+        dict.set_item("hits", stats.hits)?;
+        dict.set_item("misses", stats.misses)?;
+        dict.set_item("recent_hit_rate", stats.recent_hit_rate)?;
+        Ok(dict)
+    }
+
+    fn __len__(&mut self) -> PyResult<usize> {
+        self.inner.len().map_err(to_py_err)
+    }
+}
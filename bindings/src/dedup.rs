@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use proximity::caching::{Deduplicator as DeduplicatorInternal, NonFinitePolicy};
+use pyo3::exceptions::PyValueError;
+use pyo3::{pyclass, pymethods, PyResult, Python};
+
+use crate::vecpy::VecPy;
+use crate::{to_py_err, DEFAULT_NON_FINITE_POLICY};
+
+/// Filters near-identical embeddings out of a stream, e.g. of training data: a key is a
+/// duplicate if it is within `tolerance` of one of the last `window` keys kept, and, if
+/// `ttl` seconds are given, kept less than that long ago.
+#[pyclass(module = "proximipy")]
+pub struct Deduplicator {
+    inner: DeduplicatorInternal<VecPy>,
+    non_finite: NonFinitePolicy,
+}
+
+#[pymethods]
+impl Deduplicator {
+    #[new]
+    #[pyo3(signature = (window, tolerance, ttl=None, non_finite=DEFAULT_NON_FINITE_POLICY))]
+    pub fn new(
+        window: usize,
+        tolerance: f32,
+        ttl: Option<f64>,
+        non_finite: &str,
+    ) -> PyResult<Self> {
+        let mut inner = DeduplicatorInternal::new(window, tolerance).map_err(to_py_err)?;
+        if let Some(ttl) = ttl {
+            let ttl = Duration::try_from_secs_f64(ttl)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            inner = inner.with_ttl(ttl).map_err(to_py_err)?;
+        }
+        Ok(Self {
+            inner,
+            non_finite: non_finite.parse().map_err(to_py_err)?,
+        })
+    }
+
+    /// Whether `k` duplicates a remembered key. If not, `k` is remembered.
+    fn is_duplicate(&mut self, py: Python<'_>, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(py.allow_threads(|| self.inner.is_duplicate(k)))
+    }
+
+    /// Same as `is_duplicate` for every key in order, so that duplicates within the
+    /// batch are caught too.
+    fn batch_is_duplicate(&mut self, py: Python<'_>, ks: Vec<VecPy>) -> PyResult<Vec<bool>> {
+        py.allow_threads(|| {
+            ks.into_iter()
+                .map(|mut k| {
+                    self.inner.check_dim(&k).map_err(to_py_err)?;
+                    self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                    Ok(self.inner.is_duplicate(k))
+                })
+                .collect()
+        })
+    }
+
+    /// Forgets every key, e.g. between epochs.
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+}
@@ -0,0 +1,127 @@
+//! Copies float32 tensors out of objects exposing the DLPack protocol, such as torch
+//! tensors, which support neither lists nor the buffer protocol without a round trip.
+//!
+//! The capsule returned by `__dlpack__` is only borrowed, never renamed to
+//! `used_dltensor`, so the producer frees the tensor itself once the capsule is gone.
+
+use std::ffi::c_void;
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::types::{PyAnyMethods, PyCapsule, PyCapsuleMethods, PyDict, PyDictMethods};
+use pyo3::{intern, Bound, PyAny, PyResult};
+
+const DL_CPU: i32 = 1;
+const DL_CUDA_HOST: i32 = 3;
+const DL_FLOAT: u8 = 2;
+
+#[repr(C)]
+struct DlDevice {
+    device_type: i32,
+    device_id: i32,
+}
+
+#[repr(C)]
+struct DlDataType {
+    code: u8,
+    bits: u8,
+    lanes: u16,
+}
+
+/// Head of a `DLManagedTensor`, whose remaining fields belong to the producer.
+#[repr(C)]
+struct DlTensor {
+    data: *mut c_void,
+    device: DlDevice,
+    ndim: i32,
+    dtype: DlDataType,
+    shape: *const i64,
+    strides: *const i64,
+    byte_offset: u64,
+}
+
+/// A tensor copied to host memory, in row-major order.
+pub struct HostTensor {
+    pub data: Vec<f32>,
+    pub shape: Vec<usize>,
+}
+
+/// Returns the capsule of a tensor in host memory, asking the producer for a copy if
+/// the tensor lives on a device, e.g. a GPU.
+fn host_capsule<'py>(ob: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+    let py = ob.py();
+    let (device_type, _): (i32, i32) = ob
+        .call_method0(intern!(py, "__dlpack_device__"))?
+        .extract()?;
+    if device_type == DL_CPU || device_type == DL_CUDA_HOST {
+        return ob.call_method0(intern!(py, "__dlpack__"));
+    }
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("dl_device", (DL_CPU, 0))?;
+    kwargs.set_item("copy", true)?;
+    match ob.call_method(intern!(py, "__dlpack__"), (), Some(&kwargs)) {
+        // producers older than DLPack 1.0 cannot copy, but torch tensors can move
+        Err(e) if e.is_instance_of::<PyTypeError>(py) && ob.hasattr(intern!(py, "cpu"))? => ob
+            .call_method0(intern!(py, "cpu"))?
+            .call_method0(intern!(py, "__dlpack__")),
+        capsule => capsule,
+    }
+}
+
+/// Copies `ob` if it implements `__dlpack__`, and returns None if it does not.
+pub fn copy_f32(ob: &Bound<'_, PyAny>) -> PyResult<Option<HostTensor>> {
+    if !ob.hasattr(intern!(ob.py(), "__dlpack__"))? {
+        return Ok(None);
+    }
+    let capsule = host_capsule(ob)?;
+    let capsule = capsule.downcast::<PyCapsule>()?;
+    if capsule.name()? != Some(c"dltensor") {
+        return Err(PyValueError::new_err(
+            "__dlpack__ did not return an unused DLPack capsule",
+        ));
+    }
+    // SAFETY: a capsule named "dltensor" holds a DLManagedTensor, which starts with a
+    // DLTensor, and the producer keeps it alive as long as the capsule
+    let tensor = unsafe { &*(capsule.pointer() as *const DlTensor) };
+    if tensor.device.device_type != DL_CPU && tensor.device.device_type != DL_CUDA_HOST {
+        return Err(PyValueError::new_err(format!(
+            "could not copy the tensor from device type {} to the host",
+            tensor.device.device_type
+        )));
+    }
+    let dtype = &tensor.dtype;
+    if dtype.code != DL_FLOAT || dtype.bits != 32 || dtype.lanes != 1 {
+        return Err(PyTypeError::new_err("tensor keys must be of dtype float32"));
+    }
+    let ndim = usize::try_from(tensor.ndim).unwrap_or_default();
+    // SAFETY: shape and strides, when not null, point to ndim integers
+    let shape: Vec<usize> = match ndim {
+        0 => Vec::new(),
+        _ => unsafe { std::slice::from_raw_parts(tensor.shape, ndim) }
+            .iter()
+            .map(|&len| len as usize)
+            .collect(),
+    };
+    let len = shape.iter().product();
+    if !tensor.strides.is_null() && len > 0 {
+        let strides = unsafe { std::slice::from_raw_parts(tensor.strides, ndim) };
+        let mut expected = 1;
+        for (&stride, &dim) in strides.iter().zip(&shape).rev() {
+            if dim != 1 && stride != expected {
+                return Err(PyValueError::new_err(
+                    "tensor keys must be contiguous, call .contiguous() first",
+                ));
+            }
+            expected *= dim as i64;
+        }
+    }
+    let mut data = vec![0.0_f32; len];
+    if len > 0 {
+        // SAFETY: a contiguous float32 tensor holds len floats from data + byte_offset,
+        // copied bytewise since nothing guarantees their alignment
+        unsafe {
+            let start = tensor.data.cast::<u8>().add(tensor.byte_offset as usize);
+            std::ptr::copy_nonoverlapping(start, data.as_mut_ptr().cast::<u8>(), len * 4);
+        }
+    }
+    Ok(Some(HostTensor { data, shape }))
+}
@@ -1,33 +1,380 @@
-use proximity::caching::{ApproximateCache, FifoCache as FifoInternal};
-use pyo3::{pyclass, pymethods, PyObject};
+use std::path::PathBuf;
+use std::time::Duration;
 
+use proximity::caching::{
+    ApproximateCache, FifoCache as FifoInternal, NegativeCache, NonFinitePolicy,
+};
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::types::{PyAnyMethods, PyIterator, PyList};
+use pyo3::{pyclass, pymethods, Bound, IntoPyObject, PyAny, PyObject, PyRefMut, PyResult, Python};
+
+use crate::array::{self, FoundRows, Tolerances};
+use crate::state::{self, CacheState};
+use crate::valuepy::ValuePy;
 use crate::vecpy::VecPy;
+use crate::view::CacheView;
+use crate::warm;
+use crate::{
+    check_default_tolerance, check_merge_dims, insert_tolerance, notify_evicted, to_py_err,
+    DEFAULT_NEGATIVE_CAPACITY, DEFAULT_NON_FINITE_POLICY,
+};
 
-#[pyclass]
+#[pyclass(module = "proximipy")]
 pub struct FifoCache {
-    inner: FifoInternal<VecPy, PyObject>,
+    inner: NegativeCache<VecPy, FifoInternal<VecPy, ValuePy>>,
+    non_finite: NonFinitePolicy,
+    tolerance: Option<f32>,
+    /// Called with `(key, value)` for every entry evicted by an insert, e.g. to persist
+    /// it elsewhere. None by default.
+    #[pyo3(get, set)]
+    on_evict: Option<PyObject>,
 }
 
 #[pymethods]
 impl FifoCache {
     #[new]
-    pub fn new(max_capacity: usize) -> Self {
-        Self {
-            inner: FifoInternal::new(max_capacity),
-        }
+    #[pyo3(signature = (max_capacity, negative_capacity=DEFAULT_NEGATIVE_CAPACITY, non_finite=DEFAULT_NON_FINITE_POLICY, tolerance=None))]
+    pub fn new(
+        max_capacity: usize,
+        negative_capacity: usize,
+        non_finite: &str,
+        tolerance: Option<f32>,
+    ) -> PyResult<Self> {
+        let cache = FifoInternal::new(max_capacity).map_err(to_py_err)?;
+        Ok(Self {
+            inner: NegativeCache::new(cache, negative_capacity).map_err(to_py_err)?,
+            non_finite: non_finite.parse().map_err(to_py_err)?,
+            tolerance: check_default_tolerance(tolerance)?,
+            on_evict: None,
+        })
     }
 
-    fn find(&mut self, k: VecPy) -> Option<PyObject> {
-        self.inner.find(&k)
+    fn find(&mut self, py: Python<'_>, mut k: VecPy) -> PyResult<Option<ValuePy>> {
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        py.allow_threads(|| self.inner.try_find(&k))
+            .map_err(to_py_err)
     }
 
-    fn batch_find(&mut self, ks: Vec<VecPy>) -> Vec<Option<PyObject>> {
-        // more efficient than a python for loop
-        ks.into_iter().map(|k| self.find(k)).collect()
+    fn batch_find(&mut self, py: Python<'_>, ks: Vec<VecPy>) -> PyResult<Vec<Option<ValuePy>>> {
+        // more efficient than a python for loop, and other threads run in the meantime
+        py.allow_threads(|| {
+            ks.into_iter()
+                .map(|mut k| {
+                    self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                    self.inner.try_find(&k).map_err(to_py_err)
+                })
+                .collect()
+        })
     }
 
-    fn insert(&mut self, key: VecPy, value: PyObject, tolerance: f32) {
-        self.inner.insert(key, value, tolerance)
+    /// Looks up every row of a float32 array of shape (N, D). Returns the N values,
+    /// None on a miss, and an array of their distances to the queries, NaN on a miss.
+    fn batch_find_arr<'py>(&mut self, queries: &Bound<'py, PyAny>) -> PyResult<FoundRows<'py>> {
+        let py = queries.py();
+        array::find_rows(queries, |keys| {
+            py.allow_threads(|| {
+                keys.into_iter()
+                    .map(|mut k| {
+                        self.inner.check_dim(&k).map_err(to_py_err)?;
+                        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                        Ok(self.inner.find_k(&k, 1).into_iter().next())
+                    })
+                    .collect()
+            })
+        })
+    }
+
+    /// Returns up to k matches as (value, distance) pairs, closest first,
+    /// so that the caller can interpolate between neighbours.
+    fn find_aggregate(
+        &mut self,
+        py: Python<'_>,
+        mut k: VecPy,
+        count: usize,
+    ) -> PyResult<Vec<(ValuePy, f32)>> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(py.allow_threads(|| self.inner.find_k(&k, count)))
+    }
+
+    /// Returns the closest match and its distance to `k`, or None on a miss, to tell
+    /// how close a lookup came to its tolerance.
+    fn find_with_distance(
+        &mut self,
+        py: Python<'_>,
+        mut k: VecPy,
+    ) -> PyResult<Option<(ValuePy, f32)>> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(py.allow_threads(|| self.inner.find_k(&k, 1).into_iter().next()))
+    }
+
+    /// Inserts with the given tolerance, or else the one the cache was built with.
+    #[pyo3(signature = (key, value, tolerance=None))]
+    fn insert(
+        &mut self,
+        py: Python<'_>,
+        mut key: VecPy,
+        value: ValuePy,
+        tolerance: Option<f32>,
+    ) -> PyResult<()> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        self.non_finite.apply(&mut key).map_err(to_py_err)?;
+        let evicted = self
+            .inner
+            .try_insert(key, value, tolerance)
+            .map_err(to_py_err)?;
+        notify_evicted(py, self.on_evict.as_ref(), evicted)
+    }
+
+    /// Inserts an entry that eviction spares as long as entries of a lower priority
+    /// remain, e.g. one that is expensive to recompute. `insert` uses priority 0.
+    #[pyo3(signature = (key, value, priority, tolerance=None))]
+    fn insert_with_priority(
+        &mut self,
+        py: Python<'_>,
+        mut key: VecPy,
+        value: ValuePy,
+        priority: u32,
+        tolerance: Option<f32>,
+    ) -> PyResult<()> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        self.inner.check_dim(&key).map_err(to_py_err)?;
+        self.non_finite.apply(&mut key).map_err(to_py_err)?;
+        let evicted = self
+            .inner
+            .insert_with_priority(key, value, tolerance, priority);
+        notify_evicted(py, self.on_evict.as_ref(), evicted)
+    }
+
+    /// Inserts every row of a float32 array of shape (N, D) with the matching value,
+    /// under one tolerance, one per row, or else the one the cache was built with.
+    /// Nothing is inserted if any row is rejected.
+    #[pyo3(signature = (keys, values, tolerances=None))]
+    fn batch_insert_arr(
+        &mut self,
+        keys: &Bound<'_, PyAny>,
+        values: Vec<ValuePy>,
+        tolerances: Option<Tolerances>,
+    ) -> PyResult<()> {
+        let rows = array::insert_rows(keys, values, tolerances, self.tolerance, |key| {
+            self.inner.check_dim(key).map_err(to_py_err)?;
+            self.non_finite.apply(key).map_err(to_py_err)
+        })?;
+        let py = keys.py();
+        let evicted = py.allow_threads(|| {
+            rows.into_iter()
+                .flat_map(|(key, value, tolerance)| {
+                    self.inner.insert_evicting(key, value, tolerance)
+                })
+                .collect()
+        });
+        notify_evicted(py, self.on_evict.as_ref(), evicted)
+    }
+
+    /// Inserts every row of a `.npy` array of shape (N, D) with the matching value of a
+    /// `.npy` array of N values, e.g. a dataset computed offline, under one tolerance, or
+    /// else the one the cache was built with. The files are read a chunk of rows at a
+    /// time, calling `progress(inserted, total)` after every chunk. Returns the number of
+    /// rows inserted; those before a rejected row stay inserted.
+    #[pyo3(signature = (keys_path, values_path, tolerance=None, progress=None))]
+    fn warm_from_npy(
+        &mut self,
+        py: Python<'_>,
+        keys_path: PathBuf,
+        values_path: PathBuf,
+        tolerance: Option<f32>,
+        progress: Option<PyObject>,
+    ) -> PyResult<usize> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        warm::warm_rows(
+            py,
+            &keys_path,
+            &values_path,
+            progress.as_ref(),
+            |mut key, value: ValuePy| {
+                self.non_finite.apply(&mut key).map_err(to_py_err)?;
+                let evicted = self
+                    .inner
+                    .try_insert(key, value, tolerance)
+                    .map_err(to_py_err)?;
+                notify_evicted(py, self.on_evict.as_ref(), evicted)
+            },
+        )
+    }
+
+    /// Remembers for `ttl` seconds that queries close to `key` have no useful answer.
+    #[pyo3(signature = (key, ttl, tolerance=None))]
+    fn insert_negative(
+        &mut self,
+        mut key: VecPy,
+        ttl: f64,
+        tolerance: Option<f32>,
+    ) -> PyResult<()> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        self.inner.check_dim(&key).map_err(to_py_err)?;
+        self.non_finite.apply(&mut key).map_err(to_py_err)?;
+        let ttl =
+            Duration::try_from_secs_f64(ttl).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        self.inner.insert_negative(key, tolerance, ttl);
+        Ok(())
+    }
+
+    fn is_known_miss(&mut self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.is_known_miss(&k))
+    }
+
+    fn pin(&mut self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.pin(&k))
+    }
+
+    fn unpin(&mut self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.unpin(&k))
+    }
+
+    /// Moves every entry of `other` into this cache, calling `on_evict` for those that do
+    /// not fit, the least recently used of both caches. `other` is left empty, and its
+    /// negative entries are dropped, e.g. to fold caches filled by separate workers.
+    fn merge(&mut self, py: Python<'_>, mut other: PyRefMut<'_, Self>) -> PyResult<()> {
+        check_merge_dims(self.inner.key_dim(), other.inner.key_dim())?;
+        let empty = FifoInternal::new(other.inner.get_ref().capacity()).map_err(to_py_err)?;
+        let entries = std::mem::replace(other.inner.get_mut(), empty);
+        let evicted = py.allow_threads(|| self.inner.get_mut().merge(entries));
+        notify_evicted(py, self.on_evict.as_ref(), evicted)
+    }
+
+    /// Immutable copy of the cache that later inserts and evictions do not affect.
+    fn snapshot(&self) -> CacheView {
+        CacheView::new(self.inner.snapshot(), self.non_finite)
+    }
+
+    fn keys(&self) -> Vec<VecPy> {
+        self.inner.iter().map(|(k, _, _)| k.clone()).collect()
+    }
+
+    fn values(&self) -> Vec<ValuePy> {
+        self.inner.iter().map(|(_, v, _)| v).collect()
+    }
+
+    fn items(&self) -> Vec<(VecPy, ValuePy)> {
+        self.inner.iter().map(|(k, v, _)| (k.clone(), v)).collect()
+    }
+
+    /// Drops expired negative entries and releases unused memory. Lookups never do this,
+    /// so call it now and then, e.g. from a background thread.
+    fn maintain(&mut self) {
+        self.inner.maintain();
+    }
+
+    /// Releases the memory left over by evictions, e.g. after heavy churn, without
+    /// changing what the cache holds. It may reallocate every table, so call it rarely.
+    fn compact(&mut self) {
+        self.inner.compact();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.inner.recent_hit_rate()
+    }
+
+    /// Estimated heap memory of the cache in bytes, keys, values and index included.
+    /// Values are measured with `sys.getsizeof`, so the objects they refer to are not.
+    fn memory_bytes(&self) -> usize {
+        self.inner.memory_bytes()
+    }
+
+    /// Maximum number of entries. Setting it evicts entries until the cache fits, calling `on_evict` for each of them, e.g. to
+    /// shrink the cache under memory pressure without losing the rest of it.
+    #[getter]
+    fn capacity(&self) -> usize {
+        self.inner.get_ref().capacity()
+    }
+
+    #[setter]
+    fn set_capacity(&mut self, py: Python<'_>, capacity: usize) -> PyResult<()> {
+        let evicted = self
+            .inner
+            .get_mut()
+            .set_capacity(capacity)
+            .map_err(to_py_err)?;
+        notify_evicted(py, self.on_evict.as_ref(), evicted)
+    }
+
+    /// Tolerance of inserts that do not give one, None if they must give one.
+    #[getter]
+    fn default_tolerance(&self) -> Option<f32> {
+        self.tolerance
+    }
+
+    /// Dimension of the keys, None until the first insert.
+    #[getter]
+    fn dim(&self) -> Option<usize> {
+        self.inner.key_dim()
+    }
+
+    /// Whether `find(k)` would hit. It is not counted as a lookup, and does not make
+    /// the matching entry more recent.
+    fn __contains__(&self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.entry_info(&k).is_some())
+    }
+
+    /// `find(key)`, raising KeyError on a miss like a dict.
+    fn __getitem__(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<ValuePy> {
+        self.find(py, key.extract()?)?
+            .ok_or_else(|| PyKeyError::new_err(key.clone().unbind()))
+    }
+
+    /// `insert(key, value)`, with the tolerance the cache was built with.
+    fn __setitem__(&mut self, py: Python<'_>, key: VecPy, value: ValuePy) -> PyResult<()> {
+        self.insert(py, key, value, None)
+    }
+
+    /// `find(k)`, or `default` on a miss.
+    #[pyo3(signature = (k, default=None))]
+    fn get(
+        &mut self,
+        py: Python<'_>,
+        k: VecPy,
+        default: Option<PyObject>,
+    ) -> PyResult<Option<PyObject>> {
+        Ok(match self.find(py, k)? {
+            Some(value) => Some(value.into_pyobject(py)?.unbind()),
+            None => default,
+        })
+    }
+
+    /// Iterates over the keys, like a dict.
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        PyList::new(py, self.keys())?.try_iter()
+    }
+
+    /// Constructor arguments, for pickling along with `__getstate__`.
+    fn __getnewargs__(&self) -> (usize, usize, String, Option<f32>) {
+        (
+            self.inner.get_ref().capacity(),
+            self.inner.max_negatives(),
+            self.non_finite.to_string(),
+            self.tolerance,
+        )
+    }
+
+    /// Entries oldest first, see `CacheState`.
+    fn __getstate__(&self) -> CacheState {
+        CacheState::new(state::oldest_first(&self.inner), None)
+    }
+
+    fn __setstate__(&mut self, state: CacheState) -> PyResult<()> {
+        for (key, value, tolerance) in state.entries()? {
+            self.inner.insert(key, value, tolerance);
+        }
+        Ok(())
     }
 
     fn __len__(&self) -> usize {
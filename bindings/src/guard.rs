@@ -0,0 +1,140 @@
+use proximity::caching::HeapSize;
+use pyo3::exceptions::PyTypeError;
+use pyo3::types::{
+    PyAnyMethods, PyBool, PyBytes, PyBytesMethods, PyFloat, PyInt, PyString, PyTuple,
+};
+use pyo3::{pyclass, pymethods, Bound, FromPyObject, IntoPyObject, PyAny, PyErr, PyResult, Python};
+
+use crate::vecpy::VecPy;
+
+/// Exact-match fields of a key, e.g. a model version and a temperature bucket, parsed
+/// from a Python value so that they are compared without the GIL.
+///
+/// Values of different types never compare equal, so that `1`, `1.0` and `True` are
+/// three different guards.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Guard {
+    None,
+    Bool(bool),
+    Int(i64),
+    /// Bits of the float, so that guards are `Eq` and a NaN guard matches itself.
+    Float(u64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Tuple(Vec<Guard>),
+}
+
+impl<'a> FromPyObject<'a> for Guard {
+    fn extract_bound(ob: &Bound<'a, PyAny>) -> PyResult<Self> {
+        if ob.is_none() {
+            Ok(Guard::None)
+        } else if let Ok(flag) = ob.downcast::<PyBool>() {
+            Ok(Guard::Bool(flag.extract()?))
+        } else if ob.is_instance_of::<PyInt>() {
+            Ok(Guard::Int(ob.extract()?))
+        } else if ob.is_instance_of::<PyFloat>() {
+            Ok(Guard::Float(ob.extract::<f64>()?.to_bits()))
+        } else if ob.is_instance_of::<PyString>() {
+            Ok(Guard::Str(ob.extract()?))
+        } else if let Ok(bytes) = ob.downcast::<PyBytes>() {
+            Ok(Guard::Bytes(bytes.as_bytes().to_vec()))
+        } else if let Ok(tuple) = ob.downcast::<PyTuple>() {
+            Ok(Guard::Tuple(tuple.extract()?))
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "guards must be None, bools, ints, floats, strings, bytes or tuples of them, got {}",
+                ob.get_type()
+            )))
+        }
+    }
+}
+
+impl<'py> IntoPyObject<'py> for Guard {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        Ok(match self {
+            Guard::None => py.None().into_bound(py),
+            Guard::Bool(flag) => PyBool::new(py, flag).to_owned().into_any(),
+            Guard::Int(int) => int.into_pyobject(py)?.into_any(),
+            Guard::Float(bits) => f64::from_bits(bits).into_pyobject(py)?.into_any(),
+            Guard::Str(string) => string.into_pyobject(py)?.into_any(),
+            Guard::Bytes(bytes) => PyBytes::new(py, &bytes).into_any(),
+            Guard::Tuple(items) => PyTuple::new(py, items)?.into_any(),
+        })
+    }
+}
+
+impl HeapSize for Guard {
+    fn heap_bytes(&self) -> usize {
+        match self {
+            Guard::Str(string) => string.heap_bytes(),
+            Guard::Bytes(bytes) => bytes.heap_bytes(),
+            Guard::Tuple(items) => items.heap_bytes(),
+            _ => 0,
+        }
+    }
+}
+
+/// A key that only matches keys of an equal `guard`, e.g. `Guarded(embedding,
+/// ("model-v2", 3))`, so that a cache never answers with a value computed under
+/// another model version or temperature bucket. Plain keys have no guard, and only
+/// match plain keys.
+///
+/// Any cache accepts guarded keys, except the shared-memory cache and the daemon
+/// client, and returns the guarded keys it stores as `Guarded` too.
+#[pyclass(frozen, module = "proximipy")]
+pub struct Guarded {
+    key: VecPy,
+}
+
+impl Guarded {
+    pub fn key(&self) -> &VecPy {
+        &self.key
+    }
+}
+
+impl From<VecPy> for Guarded {
+    fn from(key: VecPy) -> Self {
+        Self { key }
+    }
+}
+
+#[pymethods]
+impl Guarded {
+    #[new]
+    fn new(key: VecPy, guard: Guard) -> Self {
+        Self {
+            key: VecPy {
+                inner: key.inner,
+                guard: Some(guard),
+            },
+        }
+    }
+
+    /// The vector of the key, as a list of floats.
+    #[getter(key)]
+    fn vector(&self) -> Vec<f32> {
+        self.key.inner.clone()
+    }
+
+    #[getter]
+    fn guard(&self) -> Guard {
+        self.key.guard.clone().unwrap_or(Guard::None)
+    }
+
+    fn __getnewargs__(&self) -> (Vec<f32>, Guard) {
+        (self.vector(), self.guard())
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let guard = self.guard().into_pyobject(py)?;
+        Ok(format!("Guarded({:?}, {})", self.key.inner, guard.repr()?))
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
@@ -1,21 +1,175 @@
+#[cfg(unix)]
+use client::CacheClient;
+use dedup::Deduplicator;
 use fifo::FifoCache;
+use guard::Guarded;
+use linear::LinearCache;
 use lru::LruCache;
+use lsh::LshCache;
 use lsh_fifo::LshFifoCache;
 use lsh_lru::LshLruCache;
+use memoize::{ApproxCacheDecorator, ApproxMemoized};
+use proximity::caching::ApproximateCache;
+use proximity::numerics::ApproxComparable;
+use proximity::ProximityError;
+use pyo3::exceptions::{PyIOError, PyValueError};
 use pyo3::prelude::*;
+use sharded::ShardedCache;
+#[cfg(unix)]
+use shared::SharedLshCache;
+use typed::{
+    FifoCacheBytes, FifoCacheF32, FifoCacheF32Vec, FifoCacheI64, LruCacheBytes, LruCacheF32,
+    LruCacheF32Vec, LruCacheI64,
+};
+use valuepy::ValuePy;
+use vecpy::VecPy;
+use view::CacheView;
 
+mod array;
+#[cfg(unix)]
+mod client;
+mod dedup;
+mod dlpack;
 mod fifo;
+mod guard;
+mod linear;
 mod lru;
+mod lsh;
 mod lsh_fifo;
 mod lsh_lru;
+mod memoize;
+#[cfg(unix)]
+mod pickle;
+mod sharded;
+#[cfg(unix)]
+mod shared;
+mod state;
+mod typed;
+mod valuepy;
 mod vecpy;
+mod view;
+mod warm;
+
+/// How many negative entries a cache remembers unless told otherwise.
+const DEFAULT_NEGATIVE_CAPACITY: usize = 1024;
+
+/// How keys with NaN or infinite components are handled unless told otherwise,
+/// see `NonFinitePolicy`.
+const DEFAULT_NON_FINITE_POLICY: &str = "reject";
+
+/// Constructor arguments of the LSH caches, as returned by their `__getnewargs__`.
+type LshArgs = (usize, usize, usize, Option<u64>, usize, String, Option<f32>);
+
+/// Checks the default tolerance given to a cache constructor.
+fn check_default_tolerance(tolerance: Option<f32>) -> PyResult<Option<f32>> {
+    match tolerance {
+        Some(tol) if tol.is_nan() || tol < 0.0 => Err(PyValueError::new_err(format!(
+            "tolerance must be non-negative, got {tol}"
+        ))),
+        _ => Ok(tolerance),
+    }
+}
+
+/// Tolerance of an insert: the one passed to it, or else the cache's default.
+fn insert_tolerance(given: Option<f32>, default: Option<f32>) -> PyResult<f32> {
+    given.or(default).ok_or_else(|| {
+        PyValueError::new_err("no tolerance given and the cache has no default tolerance")
+    })
+}
+
+/// Checks that two caches about to be merged hold keys of the same dimension.
+fn check_merge_dims(dim: Option<usize>, other: Option<usize>) -> PyResult<()> {
+    match (dim, other) {
+        (Some(expected), Some(found)) if expected != found => {
+            Err(to_py_err(ProximityError::DimensionMismatch {
+                expected,
+                found,
+            }))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Calls `on_evict(key, value)` for every evicted entry, keys as lists of floats.
+/// An exception raised by the callback skips the remaining entries.
+fn notify_evicted(
+    py: Python<'_>,
+    on_evict: Option<&PyObject>,
+    evicted: Vec<(VecPy, ValuePy, f32)>,
+) -> PyResult<()> {
+    let Some(callback) = on_evict else {
+        return Ok(());
+    };
+    for (key, value, _) in evicted {
+        callback.call1(py, (key, value))?;
+    }
+    Ok(())
+}
+
+/// Packs an LSH signature into a Python int, the first hyperplane giving the most
+/// significant bit. Goes through a bit string since signatures may exceed 128 bits.
+fn signature_int(py: Python<'_>, signature: Vec<bool>) -> PyResult<Bound<'_, PyAny>> {
+    let mut bits: String = signature
+        .iter()
+        .map(|&bit| if bit { '1' } else { '0' })
+        .collect();
+    if bits.is_empty() {
+        bits.push('0');
+    }
+    py.get_type::<pyo3::types::PyInt>().call1((bits, 2))
+}
+
+/// Keys of an LSH bucket along with their distance to `key`, closest first.
+fn bucket_distances<C>(bucket: Option<&C>, key: &VecPy) -> Vec<(VecPy, f32)>
+where
+    C: ApproximateCache<VecPy, ValuePy>,
+{
+    let mut keys: Vec<_> = bucket
+        .into_iter()
+        .flat_map(|bucket| bucket.iter())
+        .map(|(k, _, _)| (k.clone(), key.fuzziness(k)))
+        .collect();
+    keys.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    keys
+}
+
+/// Raises I/O failures as `IOError` and everything else as `ValueError`.
+fn to_py_err(err: ProximityError) -> PyErr {
+    match err {
+        ProximityError::Io(e) => PyIOError::new_err(e.to_string()),
+        other => PyValueError::new_err(other.to_string()),
+    }
+}
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn proximipy(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<LruCache>()?;
     m.add_class::<FifoCache>()?;
+    m.add_class::<LinearCache>()?;
     m.add_class::<LshFifoCache>()?;
     m.add_class::<LshLruCache>()?;
+    m.add_class::<LshCache>()?;
+    m.add_class::<LruCacheF32>()?;
+    m.add_class::<LruCacheI64>()?;
+    m.add_class::<LruCacheF32Vec>()?;
+    m.add_class::<LruCacheBytes>()?;
+    m.add_class::<FifoCacheF32>()?;
+    m.add_class::<FifoCacheI64>()?;
+    m.add_class::<FifoCacheF32Vec>()?;
+    m.add_class::<FifoCacheBytes>()?;
+    m.add_class::<ShardedCache>()?;
+    #[cfg(unix)]
+    m.add_class::<SharedLshCache>()?;
+    m.add_class::<CacheView>()?;
+    m.add_class::<ApproxCacheDecorator>()?;
+    m.add_class::<ApproxMemoized>()?;
+    m.add_class::<Deduplicator>()?;
+    m.add_class::<Guarded>()?;
+    m.add_function(wrap_pyfunction!(memoize::approx_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(warm::read_hnswlib, m)?)?;
+    m.add_function(wrap_pyfunction!(warm::read_annoy, m)?)?;
+    #[cfg(unix)]
+    m.add_class::<CacheClient>()?;
     Ok(())
 }
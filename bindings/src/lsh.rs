@@ -0,0 +1,588 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use proximity::caching::{
+    ApproximateCache, EvictionPolicy, LshCache as LshInternal, LshClockCache as LshClockInternal,
+    LshFifoCache as LshFifoInternal, LshGdsfCache as LshGdsfInternal,
+    LshLfuCache as LshLfuInternal, LshLruCache as LshLruInternal, LshLruKCache as LshLruKInternal,
+    LshS3FifoCache as LshS3FifoInternal, LshWTinyLfuCache as LshWTinyLfuInternal, NegativeCache,
+    NonFinitePolicy,
+};
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::types::{PyAnyMethods, PyIterator, PyList};
+use pyo3::{pyclass, pymethods, Bound, IntoPyObject, PyAny, PyObject, PyRefMut, PyResult, Python};
+
+use crate::array::{self, FoundRows, Tolerances};
+use crate::state::{self, CacheState};
+use crate::valuepy::ValuePy;
+use crate::vecpy::VecPy;
+use crate::view::CacheView;
+use crate::warm;
+use crate::{
+    bucket_distances, check_default_tolerance, check_merge_dims, insert_tolerance, notify_evicted,
+    signature_int, to_py_err, DEFAULT_NEGATIVE_CAPACITY, DEFAULT_NON_FINITE_POLICY,
+};
+
+/// Constructor arguments, as returned by `__getnewargs__`: those of the other LSH caches,
+/// followed by the policy.
+type Args = (
+    usize,
+    usize,
+    usize,
+    Option<u64>,
+    usize,
+    String,
+    Option<f32>,
+    String,
+);
+
+/// An LSH cache whatever the eviction policy of its buckets, so that `LshCache` can
+/// pick one at runtime.
+trait LshBuckets: ApproximateCache<VecPy, ValuePy> {
+    fn signature(&self, key: &[f32]) -> Vec<bool>;
+    /// Keys of the bucket `key` lands in, with their distance to `key`.
+    fn bucket_of(&self, key: &VecPy) -> Vec<(VecPy, f32)>;
+    fn projections(&self) -> &[Vec<f32>];
+    fn bucket_capacity(&self) -> usize;
+    fn set_bucket_capacity(
+        &mut self,
+        bucket_capacity: usize,
+    ) -> proximity::Result<Vec<(VecPy, ValuePy, f32)>>;
+}
+
+/// Implements `LshBuckets` for every LSH cache of a bucket policy.
+macro_rules! lsh_buckets {
+    ($($cache:ty),*) => {$(
+        impl LshBuckets for $cache {
+            fn signature(&self, key: &[f32]) -> Vec<bool> {
+                LshInternal::signature(self, key)
+            }
+
+            fn bucket_of(&self, key: &VecPy) -> Vec<(VecPy, f32)> {
+                bucket_distances(self.bucket(&key.inner), key)
+            }
+
+            fn projections(&self) -> &[Vec<f32>] {
+                LshInternal::projections(self)
+            }
+
+            fn bucket_capacity(&self) -> usize {
+                LshInternal::bucket_capacity(self)
+            }
+
+            fn set_bucket_capacity(
+                &mut self,
+                bucket_capacity: usize,
+            ) -> proximity::Result<Vec<(VecPy, ValuePy, f32)>> {
+                LshInternal::set_bucket_capacity(self, bucket_capacity)
+            }
+        }
+    )*};
+}
+
+lsh_buckets!(
+    LshLruInternal<VecPy, ValuePy>,
+    LshFifoInternal<VecPy, ValuePy>,
+    LshLruKInternal<VecPy, ValuePy>,
+    LshLfuInternal<VecPy, ValuePy>,
+    LshClockInternal<VecPy, ValuePy>,
+    LshWTinyLfuInternal<VecPy, ValuePy>,
+    LshGdsfInternal<VecPy, ValuePy>,
+    LshS3FifoInternal<VecPy, ValuePy>
+);
+
+fn new_buckets(
+    policy: EvictionPolicy,
+    num_hash: usize,
+    dim: usize,
+    bucket_capacity: usize,
+    seed: Option<u64>,
+) -> proximity::Result<Box<dyn LshBuckets>> {
+    Ok(match policy {
+        EvictionPolicy::Lru => Box::new(LshLruInternal::new(num_hash, dim, bucket_capacity, seed)?),
+        EvictionPolicy::Fifo => {
+            Box::new(LshFifoInternal::new(num_hash, dim, bucket_capacity, seed)?)
+        }
+        EvictionPolicy::LruK => {
+            Box::new(LshLruKInternal::new(num_hash, dim, bucket_capacity, seed)?)
+        }
+        EvictionPolicy::Lfu => Box::new(LshLfuInternal::new(num_hash, dim, bucket_capacity, seed)?),
+        EvictionPolicy::Clock => {
+            Box::new(LshClockInternal::new(num_hash, dim, bucket_capacity, seed)?)
+        }
+        EvictionPolicy::WTinyLfu => Box::new(LshWTinyLfuInternal::new(
+            num_hash,
+            dim,
+            bucket_capacity,
+            seed,
+        )?),
+        EvictionPolicy::Gdsf => {
+            Box::new(LshGdsfInternal::new(num_hash, dim, bucket_capacity, seed)?)
+        }
+        EvictionPolicy::S3Fifo => Box::new(LshS3FifoInternal::new(
+            num_hash,
+            dim,
+            bucket_capacity,
+            seed,
+        )?),
+    })
+}
+
+fn restored_buckets(
+    policy: EvictionPolicy,
+    projections: Vec<Vec<f32>>,
+    dim: usize,
+    bucket_capacity: usize,
+) -> proximity::Result<Box<dyn LshBuckets>> {
+    Ok(match policy {
+        EvictionPolicy::Lru => Box::new(LshLruInternal::with_projections(
+            projections,
+            dim,
+            bucket_capacity,
+        )?),
+        EvictionPolicy::Fifo => Box::new(LshFifoInternal::with_projections(
+            projections,
+            dim,
+            bucket_capacity,
+        )?),
+        EvictionPolicy::LruK => Box::new(LshLruKInternal::with_projections(
+            projections,
+            dim,
+            bucket_capacity,
+        )?),
+        EvictionPolicy::Lfu => Box::new(LshLfuInternal::with_projections(
+            projections,
+            dim,
+            bucket_capacity,
+        )?),
+        EvictionPolicy::Clock => Box::new(LshClockInternal::with_projections(
+            projections,
+            dim,
+            bucket_capacity,
+        )?),
+        EvictionPolicy::WTinyLfu => Box::new(LshWTinyLfuInternal::with_projections(
+            projections,
+            dim,
+            bucket_capacity,
+        )?),
+        EvictionPolicy::Gdsf => Box::new(LshGdsfInternal::with_projections(
+            projections,
+            dim,
+            bucket_capacity,
+        )?),
+        EvictionPolicy::S3Fifo => Box::new(LshS3FifoInternal::with_projections(
+            projections,
+            dim,
+            bucket_capacity,
+        )?),
+    })
+}
+
+/// An LSH cache whose buckets evict by `policy`, "lru", "fifo", "lru-k" (LRU-2), "lfu",
+/// "clock", "w-tinylfu", "gdsf" (at unit cost and size) or "s3-fifo" (also "s3fifo"),
+/// picked at runtime.
+///
+/// Unlike `LshFifoCache`, it holds the GIL during lookups whatever its policy.
+#[pyclass(unsendable, module = "proximipy")]
+pub struct LshCache {
+    inner: NegativeCache<VecPy, Box<dyn LshBuckets>>,
+    policy: EvictionPolicy,
+    non_finite: NonFinitePolicy,
+    tolerance: Option<f32>,
+    /// Called with `(key, value)` for every entry evicted by an insert, e.g. to persist
+    /// it elsewhere. None by default.
+    #[pyo3(get, set)]
+    on_evict: Option<PyObject>,
+}
+
+#[pymethods]
+impl LshCache {
+    #[new]
+    #[pyo3(signature = (num_hash, dim, bucket_capacity, seed=None, negative_capacity=DEFAULT_NEGATIVE_CAPACITY, non_finite=DEFAULT_NON_FINITE_POLICY, tolerance=None, policy="w-tinylfu"))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        num_hash: usize,
+        dim: usize,
+        bucket_capacity: usize,
+        seed: Option<u64>,
+        negative_capacity: usize,
+        non_finite: &str,
+        tolerance: Option<f32>,
+        policy: &str,
+    ) -> PyResult<Self> {
+        let policy = policy.parse().map_err(to_py_err)?;
+        let cache = new_buckets(policy, num_hash, dim, bucket_capacity, seed).map_err(to_py_err)?;
+        Ok(Self {
+            inner: NegativeCache::new(cache, negative_capacity).map_err(to_py_err)?,
+            policy,
+            non_finite: non_finite.parse().map_err(to_py_err)?,
+            tolerance: check_default_tolerance(tolerance)?,
+            on_evict: None,
+        })
+    }
+
+    /// Eviction policy of the buckets.
+    #[getter]
+    fn policy(&self) -> String {
+        self.policy.to_string()
+    }
+
+    fn find(&mut self, mut k: VecPy) -> PyResult<Option<ValuePy>> {
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        self.inner.try_find(&k).map_err(to_py_err)
+    }
+
+    fn batch_find(&mut self, ks: Vec<VecPy>) -> PyResult<Vec<Option<ValuePy>>> {
+        // more efficient than a python for loop
+        ks.into_iter().map(|k| self.find(k)).collect()
+    }
+
+    /// Looks up every row of a float32 array of shape (N, D). Returns the N values,
+    /// None on a miss, and an array of their distances to the queries, NaN on a miss.
+    fn batch_find_arr<'py>(&mut self, queries: &Bound<'py, PyAny>) -> PyResult<FoundRows<'py>> {
+        array::find_rows(queries, |keys| {
+            keys.into_iter()
+                .map(|mut k| {
+                    self.inner.check_dim(&k).map_err(to_py_err)?;
+                    self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                    Ok(self.inner.find_k(&k, 1).into_iter().next())
+                })
+                .collect()
+        })
+    }
+
+    /// Returns up to k matches as (value, distance) pairs, closest first,
+    /// so that the caller can interpolate between neighbours.
+    fn find_aggregate(&mut self, mut k: VecPy, count: usize) -> PyResult<Vec<(ValuePy, f32)>> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.find_k(&k, count))
+    }
+
+    /// Returns the closest match and its distance to `k`, or None on a miss, to tell
+    /// how close a lookup came to its tolerance.
+    fn find_with_distance(&mut self, mut k: VecPy) -> PyResult<Option<(ValuePy, f32)>> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.find_k(&k, 1).into_iter().next())
+    }
+
+    /// Signature of `k` as an int, one bit per hyperplane, the first one being the most
+    /// significant. Keys with different signatures land in different buckets.
+    fn signature<'py>(&self, py: Python<'py>, mut k: VecPy) -> PyResult<Bound<'py, PyAny>> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        signature_int(py, self.inner.get_ref().signature(&k.inner))
+    }
+
+    /// Keys stored in the bucket `k` lands in, with their distance to `k`, closest
+    /// first. A lookup of `k` only ever compares it to these keys.
+    fn bucket_of(&self, mut k: VecPy) -> PyResult<Vec<(VecPy, f32)>> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.get_ref().bucket_of(&k))
+    }
+
+    /// Inserts with the given tolerance, or else the one the cache was built with.
+    #[pyo3(signature = (key, value, tolerance=None))]
+    fn insert(
+        &mut self,
+        py: Python<'_>,
+        mut key: VecPy,
+        value: ValuePy,
+        tolerance: Option<f32>,
+    ) -> PyResult<()> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        self.non_finite.apply(&mut key).map_err(to_py_err)?;
+        let evicted = self
+            .inner
+            .try_insert(key, value, tolerance)
+            .map_err(to_py_err)?;
+        notify_evicted(py, self.on_evict.as_ref(), evicted)
+    }
+
+    /// Inserts an entry that eviction spares as long as entries of a lower priority
+    /// remain, e.g. one that is expensive to recompute. `insert` uses priority 0.
+    #[pyo3(signature = (key, value, priority, tolerance=None))]
+    fn insert_with_priority(
+        &mut self,
+        py: Python<'_>,
+        mut key: VecPy,
+        value: ValuePy,
+        priority: u32,
+        tolerance: Option<f32>,
+    ) -> PyResult<()> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        self.inner.check_dim(&key).map_err(to_py_err)?;
+        self.non_finite.apply(&mut key).map_err(to_py_err)?;
+        let evicted = self
+            .inner
+            .insert_with_priority(key, value, tolerance, priority);
+        notify_evicted(py, self.on_evict.as_ref(), evicted)
+    }
+
+    /// Inserts every row of a float32 array of shape (N, D) with the matching value,
+    /// under one tolerance, one per row, or else the one the cache was built with.
+    /// Nothing is inserted if any row is rejected.
+    #[pyo3(signature = (keys, values, tolerances=None))]
+    fn batch_insert_arr(
+        &mut self,
+        keys: &Bound<'_, PyAny>,
+        values: Vec<ValuePy>,
+        tolerances: Option<Tolerances>,
+    ) -> PyResult<()> {
+        let rows = array::insert_rows(keys, values, tolerances, self.tolerance, |key| {
+            self.inner.check_dim(key).map_err(to_py_err)?;
+            self.non_finite.apply(key).map_err(to_py_err)
+        })?;
+        let evicted = rows
+            .into_iter()
+            .flat_map(|(key, value, tolerance)| self.inner.insert_evicting(key, value, tolerance))
+            .collect();
+        notify_evicted(keys.py(), self.on_evict.as_ref(), evicted)
+    }
+
+    /// Inserts every row of a `.npy` array of shape (N, D) with the matching value of a
+    /// `.npy` array of N values, e.g. a dataset computed offline, under one tolerance, or
+    /// else the one the cache was built with. The files are read a chunk of rows at a
+    /// time, calling `progress(inserted, total)` after every chunk. Returns the number of
+    /// rows inserted; those before a rejected row stay inserted.
+    #[pyo3(signature = (keys_path, values_path, tolerance=None, progress=None))]
+    fn warm_from_npy(
+        &mut self,
+        py: Python<'_>,
+        keys_path: PathBuf,
+        values_path: PathBuf,
+        tolerance: Option<f32>,
+        progress: Option<PyObject>,
+    ) -> PyResult<usize> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        warm::warm_rows(
+            py,
+            &keys_path,
+            &values_path,
+            progress.as_ref(),
+            |mut key, value: ValuePy| {
+                self.non_finite.apply(&mut key).map_err(to_py_err)?;
+                let evicted = self
+                    .inner
+                    .try_insert(key, value, tolerance)
+                    .map_err(to_py_err)?;
+                notify_evicted(py, self.on_evict.as_ref(), evicted)
+            },
+        )
+    }
+
+    /// Remembers for `ttl` seconds that queries close to `key` have no useful answer.
+    #[pyo3(signature = (key, ttl, tolerance=None))]
+    fn insert_negative(
+        &mut self,
+        mut key: VecPy,
+        ttl: f64,
+        tolerance: Option<f32>,
+    ) -> PyResult<()> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        self.inner.check_dim(&key).map_err(to_py_err)?;
+        self.non_finite.apply(&mut key).map_err(to_py_err)?;
+        let ttl =
+            Duration::try_from_secs_f64(ttl).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        self.inner.insert_negative(key, tolerance, ttl);
+        Ok(())
+    }
+
+    fn is_known_miss(&mut self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.is_known_miss(&k))
+    }
+
+    fn pin(&mut self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.pin(&k))
+    }
+
+    fn unpin(&mut self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.unpin(&k))
+    }
+
+    /// Moves every entry of `other` into this cache, calling `on_evict` for those that do
+    /// not fit, the least recently used of both caches. This cache's eviction policy
+    /// decides which entries those are. `other` is left empty, and its negative entries
+    /// are dropped, e.g. to fold caches filled by separate workers.
+    fn merge(&mut self, py: Python<'_>, mut other: PyRefMut<'_, Self>) -> PyResult<()> {
+        check_merge_dims(self.inner.key_dim(), other.inner.key_dim())?;
+        let lsh = other.inner.get_ref();
+        let empty = restored_buckets(
+            other.policy,
+            lsh.projections().to_vec(),
+            other.inner.key_dim().unwrap_or_default(),
+            lsh.bucket_capacity(),
+        )
+        .map_err(to_py_err)?;
+        let entries = std::mem::replace(other.inner.get_mut(), empty);
+        let evicted = self.inner.get_mut().merge(entries);
+        notify_evicted(py, self.on_evict.as_ref(), evicted)
+    }
+
+    /// Immutable copy of the cache that later inserts and evictions do not affect.
+    fn snapshot(&self) -> CacheView {
+        CacheView::new(self.inner.snapshot(), self.non_finite)
+    }
+
+    fn keys(&self) -> Vec<VecPy> {
+        self.inner.iter().map(|(k, _, _)| k.clone()).collect()
+    }
+
+    fn values(&self) -> Vec<ValuePy> {
+        self.inner.iter().map(|(_, v, _)| v).collect()
+    }
+
+    fn items(&self) -> Vec<(VecPy, ValuePy)> {
+        self.inner.iter().map(|(k, v, _)| (k.clone(), v)).collect()
+    }
+
+    /// Drops expired negative entries and releases unused memory. Lookups never do this,
+    /// so call it now and then, e.g. from a background thread.
+    fn maintain(&mut self) {
+        self.inner.maintain();
+    }
+
+    /// Releases the memory left over by evictions, e.g. after heavy churn, without
+    /// changing what the cache holds. It may reallocate every table, so call it rarely.
+    fn compact(&mut self) {
+        self.inner.compact();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.inner.recent_hit_rate()
+    }
+
+    /// Estimated heap memory of the cache in bytes, keys, values and index included.
+    /// Values are measured with `sys.getsizeof`, so the objects they refer to are not.
+    fn memory_bytes(&self) -> usize {
+        self.inner.memory_bytes()
+    }
+
+    /// Maximum number of entries of each bucket. Setting it evicts entries from the buckets above it until the cache fits, calling `on_evict` for each of them, e.g. to
+    /// shrink the cache under memory pressure without losing the rest of it.
+    #[getter]
+    fn capacity(&self) -> usize {
+        self.inner.get_ref().bucket_capacity()
+    }
+
+    #[setter]
+    fn set_capacity(&mut self, py: Python<'_>, capacity: usize) -> PyResult<()> {
+        let evicted = self
+            .inner
+            .get_mut()
+            .set_bucket_capacity(capacity)
+            .map_err(to_py_err)?;
+        notify_evicted(py, self.on_evict.as_ref(), evicted)
+    }
+
+    /// Tolerance of inserts that do not give one, None if they must give one.
+    #[getter]
+    fn default_tolerance(&self) -> Option<f32> {
+        self.tolerance
+    }
+
+    /// Dimension of the keys, None until the first insert.
+    #[getter]
+    fn dim(&self) -> Option<usize> {
+        self.inner.key_dim()
+    }
+
+    /// Whether `find(k)` would hit. It is not counted as a lookup, and does not make
+    /// the matching entry more recent.
+    fn __contains__(&self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.entry_info(&k).is_some())
+    }
+
+    /// `find(key)`, raising KeyError on a miss like a dict.
+    fn __getitem__(&mut self, key: &Bound<'_, PyAny>) -> PyResult<ValuePy> {
+        self.find(key.extract()?)?
+            .ok_or_else(|| PyKeyError::new_err(key.clone().unbind()))
+    }
+
+    /// `insert(key, value)`, with the tolerance the cache was built with.
+    fn __setitem__(&mut self, py: Python<'_>, key: VecPy, value: ValuePy) -> PyResult<()> {
+        self.insert(py, key, value, None)
+    }
+
+    /// `find(k)`, or `default` on a miss.
+    #[pyo3(signature = (k, default=None))]
+    fn get(
+        &mut self,
+        py: Python<'_>,
+        k: VecPy,
+        default: Option<PyObject>,
+    ) -> PyResult<Option<PyObject>> {
+        Ok(match self.find(k)? {
+            Some(value) => Some(value.into_pyobject(py)?.unbind()),
+            None => default,
+        })
+    }
+
+    /// Iterates over the keys, like a dict.
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        PyList::new(py, self.keys())?.try_iter()
+    }
+
+    /// Constructor arguments, for pickling along with `__getstate__`. The seed is left
+    /// out, since the state holds the hyperplanes themselves.
+    fn __getnewargs__(&self) -> Args {
+        let lsh = self.inner.get_ref();
+        (
+            lsh.projections().len(),
+            self.inner.key_dim().unwrap_or_default(),
+            lsh.bucket_capacity(),
+            None,
+            self.inner.max_negatives(),
+            self.non_finite.to_string(),
+            self.tolerance,
+            self.policy.to_string(),
+        )
+    }
+
+    /// Entries in the order they would be evicted in, and the LSH projections, see
+    /// `CacheState`.
+    fn __getstate__(&self) -> CacheState {
+        let projections = self.inner.get_ref().projections().to_vec();
+        let entries = match self.policy {
+            // the hand or queue order, reinserting in it rebuilds the ring or the queues,
+            // though S3-FIFO entries all start over in the small queue
+            EvictionPolicy::Fifo | EvictionPolicy::Clock | EvictionPolicy::S3Fifo => {
+                state::oldest_first(&self.inner)
+            }
+            // reference histories, counts, scores and sketches are lost, recency is the best
+            // that is kept
+            EvictionPolicy::Lru
+            | EvictionPolicy::LruK
+            | EvictionPolicy::Lfu
+            | EvictionPolicy::WTinyLfu
+            | EvictionPolicy::Gdsf => state::least_recent_first(&self.inner),
+        };
+        CacheState::new(entries, Some(projections))
+    }
+
+    fn __setstate__(&mut self, mut state: CacheState) -> PyResult<()> {
+        let dim = self.inner.key_dim().unwrap_or_default();
+        let bucket_capacity = self.inner.get_ref().bucket_capacity();
+        let cache = restored_buckets(self.policy, state.take_projections()?, dim, bucket_capacity)
+            .map_err(to_py_err)?;
+        self.inner = NegativeCache::new(cache, self.inner.max_negatives()).map_err(to_py_err)?;
+        for (key, value, tolerance) in state.entries()? {
+            self.inner.insert(key, value, tolerance);
+        }
+        Ok(())
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+}
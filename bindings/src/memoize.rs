@@ -0,0 +1,181 @@
+//! `approx_cache`, a decorator that memoizes a function on one of its vector arguments,
+//! like `functools.lru_cache` except that calls with a nearby vector reuse the result.
+
+use proximity::caching::{ApproximateCache, CacheBuilder, EvictionPolicy};
+use proximity::numerics::SIMD_LANECOUNT;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple};
+
+use crate::valuepy::ValuePy;
+use crate::vecpy::VecPy;
+use crate::{check_default_tolerance, to_py_err, DEFAULT_NON_FINITE_POLICY};
+
+/// Hyperplanes of the LSH policies unless told otherwise, for 256 buckets.
+const DEFAULT_NUM_HASH: usize = 8;
+
+/// How the cache of a memoized function is built once the key dimension is known.
+#[derive(Clone)]
+struct MemoConfig {
+    builder: CacheBuilder,
+    /// Number of hyperplanes and seed of the LSH policies.
+    lsh: Option<(usize, Option<u64>)>,
+    tolerance: f32,
+    /// Position of the key among the positional arguments.
+    arg: usize,
+}
+
+impl MemoConfig {
+    fn build(&self, dim: usize) -> PyResult<Box<dyn ApproximateCache<VecPy, ValuePy>>> {
+        let mut builder = self.builder.clone();
+        if let Some((num_hash, seed)) = self.lsh {
+            builder = builder.lsh(num_hash, dim, seed);
+        }
+        builder.build().map_err(to_py_err)
+    }
+}
+
+/// Memoizes the decorated function on its positional argument `arg`, a vector: a call
+/// whose key is within `tolerance` of an earlier one returns the earlier result instead.
+///
+/// `policy` is one of "lru", "fifo", "lru-k", "lfu", "clock", "w-tinylfu", "gdsf" or
+/// "s3-fifo", optionally prefixed with "lsh-", e.g. "lsh-lru", and `capacity` bounds the
+/// whole cache, or each of the `2 ** num_hash` buckets with an LSH policy. Keys refused by `non_finite` are
+/// never cached, the function is then always called.
+#[pyfunction]
+#[pyo3(signature = (tolerance, capacity=128, policy="lsh-w-tinylfu", num_hash=DEFAULT_NUM_HASH, seed=None, arg=0, non_finite=DEFAULT_NON_FINITE_POLICY))]
+pub fn approx_cache(
+    tolerance: f32,
+    capacity: usize,
+    policy: &str,
+    num_hash: usize,
+    seed: Option<u64>,
+    arg: usize,
+    non_finite: &str,
+) -> PyResult<ApproxCacheDecorator> {
+    let (eviction, lsh) = match policy.strip_prefix("lsh-") {
+        Some(eviction) => (eviction, Some((num_hash, seed))),
+        None => (policy, None),
+    };
+    let eviction: EvictionPolicy = eviction.parse().map_err(|_| {
+        PyValueError::new_err(format!(
+            "unknown policy '{policy}', expected lru, fifo, lru-k, lfu, clock, w-tinylfu, gdsf or s3-fifo, optionally prefixed with lsh-"
+        ))
+    })?;
+    check_default_tolerance(Some(tolerance))?;
+    let builder = CacheBuilder::new()
+        .policy(eviction)
+        .capacity(capacity)
+        .non_finite(non_finite.parse().map_err(to_py_err)?);
+    let config = MemoConfig {
+        builder,
+        lsh,
+        tolerance,
+        arg,
+    };
+    // build once, so that a bad configuration fails here rather than on the first call
+    config.build(SIMD_LANECOUNT)?;
+    Ok(ApproxCacheDecorator { config })
+}
+
+/// What `approx_cache` returns, to be applied to the function to memoize.
+#[pyclass(module = "proximipy", frozen)]
+pub struct ApproxCacheDecorator {
+    config: MemoConfig,
+}
+
+#[pymethods]
+impl ApproxCacheDecorator {
+    fn __call__<'py>(
+        &self,
+        py: Python<'py>,
+        func: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, ApproxMemoized>> {
+        let memoized = Bound::new(
+            py,
+            ApproxMemoized {
+                func: func.clone().unbind(),
+                config: self.config.clone(),
+                cache: None,
+                hits: 0,
+                misses: 0,
+            },
+        )?;
+        py.import("functools")?
+            .call_method1("update_wrapper", (&memoized, func))?;
+        Ok(memoized)
+    }
+}
+
+/// A function memoized by `approx_cache`.
+#[pyclass(module = "proximipy", unsendable, dict)]
+pub struct ApproxMemoized {
+    func: PyObject,
+    config: MemoConfig,
+    /// Built on the first call, once the key dimension is known.
+    cache: Option<Box<dyn ApproximateCache<VecPy, ValuePy>>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ApproxMemoized {
+    fn cache(&mut self, key: &VecPy) -> PyResult<&mut Box<dyn ApproximateCache<VecPy, ValuePy>>> {
+        let cache = match &mut self.cache {
+            Some(cache) => cache,
+            empty => empty.insert(self.config.build(key.inner.len())?),
+        };
+        cache.check_dim(key).map_err(to_py_err)?;
+        Ok(cache)
+    }
+
+    fn find(&mut self, key: &VecPy) -> PyResult<Option<ValuePy>> {
+        let found = self.cache(key)?.find(key);
+        match found {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        Ok(found)
+    }
+}
+
+#[pymethods]
+impl ApproxMemoized {
+    #[pyo3(signature = (*args, **kwargs))]
+    fn __call__<'py>(
+        slf: &Bound<'py, Self>,
+        args: &Bound<'py, PyTuple>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = slf.py();
+        let arg = slf.borrow().config.arg;
+        let key: VecPy = args
+            .get_item(arg)
+            .map_err(|_| {
+                PyTypeError::new_err(format!("expected the key as positional argument {arg}"))
+            })?
+            .extract()?;
+        if let Some(value) = slf.borrow_mut().find(&key)? {
+            return Ok(value.into_pyobject(py)?);
+        }
+        // not borrowed while the function runs, so that it may call itself
+        let func = slf.borrow().func.clone_ref(py);
+        let value = func.bind(py).call(args, kwargs)?;
+        let mut this = slf.borrow_mut();
+        let tolerance = this.config.tolerance;
+        this.cache(&key)?.insert(key, value.extract()?, tolerance);
+        Ok(value)
+    }
+
+    /// Returns `(hits, misses, size)`, as `functools.lru_cache` does.
+    fn cache_info(&self) -> (u64, u64, usize) {
+        let size = self.cache.as_ref().map_or(0, |cache| cache.len());
+        (self.hits, self.misses, size)
+    }
+
+    /// Forgets every result and resets the statistics.
+    fn cache_clear(&mut self) {
+        self.cache = None;
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
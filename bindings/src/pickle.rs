@@ -0,0 +1,19 @@
+//! Values of the caches that live outside the interpreter are stored pickled.
+
+use pyo3::types::{PyAnyMethods, PyBytes, PyBytesMethods};
+use pyo3::{Bound, PyAny, PyObject, PyResult, Python};
+
+pub fn dumps(value: &Bound<'_, PyAny>) -> PyResult<Vec<u8>> {
+    let pickled = value
+        .py()
+        .import("pickle")?
+        .call_method1("dumps", (value,))?;
+    Ok(pickled.downcast::<PyBytes>()?.as_bytes().to_vec())
+}
+
+pub fn loads(py: Python<'_>, bytes: &[u8]) -> PyResult<PyObject> {
+    let value = py
+        .import("pickle")?
+        .call_method1("loads", (PyBytes::new(py, bytes),))?;
+    Ok(value.unbind())
+}
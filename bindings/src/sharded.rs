@@ -0,0 +1,375 @@
+use std::path::PathBuf;
+
+use proximity::caching::{FifoCache, NonFinitePolicy, ShardedCache as ShardedInternal};
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::types::{PyAnyMethods, PyIterator, PyList};
+use pyo3::{pyclass, pymethods, Bound, IntoPyObject, PyAny, PyObject, PyResult, Python};
+
+use crate::array::{self, FoundRows, Tolerances};
+use crate::state::CacheState;
+use crate::valuepy::ValuePy;
+use crate::vecpy::VecPy;
+use crate::view::CacheView;
+use crate::warm;
+use crate::{check_default_tolerance, insert_tolerance, to_py_err, DEFAULT_NON_FINITE_POLICY};
+
+/// What `__reduce__` returns: the constructor to call and its arguments.
+type Reduced<'py> = (
+    Bound<'py, PyAny>,
+    (usize, usize, usize, String, Option<f32>, CacheState),
+);
+
+/// FIFO caches behind one lock each, which keys are routed to by their LSH signature.
+/// Safe to share between threads: lookups that land in different shards never
+/// wait on each other.
+// frozen == methods only take &self, so concurrent calls never fail to borrow
+#[pyclass(frozen, module = "proximipy")]
+pub struct ShardedCache {
+    inner: ShardedInternal<VecPy, ValuePy, FifoCache<VecPy, ValuePy>>,
+    non_finite: NonFinitePolicy,
+    tolerance: Option<f32>,
+}
+
+#[pymethods]
+impl ShardedCache {
+    #[new]
+    #[pyo3(signature = (num_shards, dim, shard_capacity, seed=None, non_finite=DEFAULT_NON_FINITE_POLICY, tolerance=None))]
+    pub fn new(
+        num_shards: usize,
+        dim: usize,
+        shard_capacity: usize,
+        seed: Option<u64>,
+        non_finite: &str,
+        tolerance: Option<f32>,
+    ) -> PyResult<Self> {
+        if num_shards == 0 {
+            return Err(PyValueError::new_err("num_shards must be positive"));
+        }
+        let shards = (0..num_shards)
+            .map(|_| FifoCache::new(shard_capacity))
+            .collect::<proximity::Result<_>>()
+            .map_err(to_py_err)?;
+        Ok(Self {
+            inner: ShardedInternal::new(shards, dim, seed).map_err(to_py_err)?,
+            non_finite: non_finite.parse().map_err(to_py_err)?,
+            tolerance: check_default_tolerance(tolerance)?,
+        })
+    }
+
+    fn find(&self, py: Python<'_>, mut k: VecPy) -> PyResult<Option<ValuePy>> {
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        py.allow_threads(|| self.inner.try_find(&k))
+            .map_err(to_py_err)
+    }
+
+    fn batch_find(&self, py: Python<'_>, ks: Vec<VecPy>) -> PyResult<Vec<Option<ValuePy>>> {
+        // more efficient than a python for loop, and other threads run in the meantime
+        py.allow_threads(|| {
+            ks.into_iter()
+                .map(|mut k| {
+                    self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                    self.inner.try_find(&k).map_err(to_py_err)
+                })
+                .collect()
+        })
+    }
+
+    /// Looks up every row of a float32 array of shape (N, D). Returns the N values,
+    /// None on a miss, and an array of their distances to the queries, NaN on a miss.
+    fn batch_find_arr<'py>(&self, queries: &Bound<'py, PyAny>) -> PyResult<FoundRows<'py>> {
+        let py = queries.py();
+        array::find_rows(queries, |keys| {
+            py.allow_threads(|| {
+                keys.into_iter()
+                    .map(|mut k| {
+                        self.inner.check_dim(&k).map_err(to_py_err)?;
+                        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                        Ok(self.inner.find_k(&k, 1).into_iter().next())
+                    })
+                    .collect()
+            })
+        })
+    }
+
+    /// Returns up to k matches as (value, distance) pairs, closest first,
+    /// so that the caller can interpolate between neighbours.
+    fn find_aggregate(
+        &self,
+        py: Python<'_>,
+        mut k: VecPy,
+        count: usize,
+    ) -> PyResult<Vec<(ValuePy, f32)>> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(py.allow_threads(|| self.inner.find_k(&k, count)))
+    }
+
+    /// Returns the closest match and its distance to `k`, or None on a miss, to tell
+    /// how close a lookup came to its tolerance.
+    fn find_with_distance(&self, py: Python<'_>, mut k: VecPy) -> PyResult<Option<(ValuePy, f32)>> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(py.allow_threads(|| self.inner.find_k(&k, 1).into_iter().next()))
+    }
+
+    /// Inserts with the given tolerance, or else the one the cache was built with.
+    #[pyo3(signature = (key, value, tolerance=None))]
+    fn insert(&self, mut key: VecPy, value: ValuePy, tolerance: Option<f32>) -> PyResult<()> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        self.non_finite.apply(&mut key).map_err(to_py_err)?;
+        self.inner
+            .try_insert(key, value, tolerance)
+            .map_err(to_py_err)?;
+        Ok(())
+    }
+
+    /// Inserts an entry that eviction spares as long as entries of a lower priority
+    /// remain in its shard. `insert` uses priority 0.
+    #[pyo3(signature = (key, value, priority, tolerance=None))]
+    fn insert_with_priority(
+        &self,
+        mut key: VecPy,
+        value: ValuePy,
+        priority: u32,
+        tolerance: Option<f32>,
+    ) -> PyResult<()> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        self.inner.check_dim(&key).map_err(to_py_err)?;
+        self.non_finite.apply(&mut key).map_err(to_py_err)?;
+        self.inner
+            .insert_with_priority(key, value, tolerance, priority);
+        Ok(())
+    }
+
+    /// Inserts every row of a float32 array of shape (N, D) with the matching value,
+    /// under one tolerance, one per row, or else the one the cache was built with.
+    /// Nothing is inserted if any row is rejected.
+    #[pyo3(signature = (keys, values, tolerances=None))]
+    fn batch_insert_arr(
+        &self,
+        keys: &Bound<'_, PyAny>,
+        values: Vec<ValuePy>,
+        tolerances: Option<Tolerances>,
+    ) -> PyResult<()> {
+        let rows = array::insert_rows(keys, values, tolerances, self.tolerance, |key| {
+            self.inner.check_dim(key).map_err(to_py_err)?;
+            self.non_finite.apply(key).map_err(to_py_err)
+        })?;
+        keys.py().allow_threads(|| {
+            for (key, value, tolerance) in rows {
+                self.inner.insert(key, value, tolerance);
+            }
+        });
+        Ok(())
+    }
+
+    /// Inserts every row of a `.npy` array of shape (N, D) with the matching value of a
+    /// `.npy` array of N values, e.g. a dataset computed offline, under one tolerance, or
+    /// else the one the cache was built with. The files are read a chunk of rows at a
+    /// time, calling `progress(inserted, total)` after every chunk. Returns the number of
+    /// rows inserted; those before a rejected row stay inserted.
+    #[pyo3(signature = (keys_path, values_path, tolerance=None, progress=None))]
+    fn warm_from_npy(
+        &self,
+        py: Python<'_>,
+        keys_path: PathBuf,
+        values_path: PathBuf,
+        tolerance: Option<f32>,
+        progress: Option<PyObject>,
+    ) -> PyResult<usize> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        warm::warm_rows(
+            py,
+            &keys_path,
+            &values_path,
+            progress.as_ref(),
+            |mut key, value: ValuePy| {
+                self.non_finite.apply(&mut key).map_err(to_py_err)?;
+                self.inner
+                    .try_insert(key, value, tolerance)
+                    .map_err(to_py_err)?;
+                Ok(())
+            },
+        )
+    }
+
+    fn pin(&self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.pin(&k))
+    }
+
+    fn unpin(&self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.unpin(&k))
+    }
+
+    fn num_shards(&self) -> usize {
+        self.inner.num_shards()
+    }
+
+    /// Immutable copy of the cache that later inserts and evictions do not affect.
+    fn snapshot(&self) -> CacheView {
+        CacheView::new(self.inner.snapshot(), self.non_finite)
+    }
+
+    fn keys(&self) -> Vec<VecPy> {
+        self.inner
+            .entries()
+            .into_iter()
+            .map(|(k, _, _)| k)
+            .collect()
+    }
+
+    fn values(&self) -> Vec<ValuePy> {
+        self.inner
+            .entries()
+            .into_iter()
+            .map(|(_, v, _)| v)
+            .collect()
+    }
+
+    fn items(&self) -> Vec<(VecPy, ValuePy)> {
+        self.inner
+            .entries()
+            .into_iter()
+            .map(|(k, v, _)| (k, v))
+            .collect()
+    }
+
+    /// Releases unused memory, one shard at a time. Lookups never do this,
+    /// so call it now and then, e.g. from a background thread.
+    fn maintain(&self) {
+        self.inner.maintain();
+    }
+
+    /// Releases the memory left over by evictions, e.g. after heavy churn, one shard at
+    /// a time. It may reallocate every table, so call it rarely.
+    fn compact(&self) {
+        self.inner.compact();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.inner.recent_hit_rate()
+    }
+
+    /// Estimated heap memory of the cache in bytes, keys, values and index included.
+    /// Values are measured with `sys.getsizeof`, so the objects they refer to are not.
+    fn memory_bytes(&self) -> usize {
+        self.inner.memory_bytes()
+    }
+
+    /// Maximum number of entries of each shard. Setting it evicts entries from the
+    /// shards above it, one shard at a time, so lookups in other shards go on meanwhile.
+    #[getter]
+    fn capacity(&self) -> usize {
+        self.inner.shard_capacity()
+    }
+
+    #[setter]
+    fn set_capacity(&self, py: Python<'_>, capacity: usize) -> PyResult<()> {
+        py.allow_threads(|| self.inner.set_shard_capacity(capacity))
+            .map_err(to_py_err)?;
+        Ok(())
+    }
+
+    /// Tolerance of inserts that do not give one, None if they must give one.
+    #[getter]
+    fn default_tolerance(&self) -> Option<f32> {
+        self.tolerance
+    }
+
+    /// Dimension of the keys.
+    #[getter]
+    fn dim(&self) -> usize {
+        self.inner.key_dim()
+    }
+
+    /// Whether `find(k)` would hit. It is not counted as a lookup, and does not make
+    /// the matching entry more recent.
+    fn __contains__(&self, mut k: VecPy) -> PyResult<bool> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(self.inner.entry_info(&k).is_some())
+    }
+
+    /// `find(key)`, raising KeyError on a miss like a dict.
+    fn __getitem__(&self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<ValuePy> {
+        self.find(py, key.extract()?)?
+            .ok_or_else(|| PyKeyError::new_err(key.clone().unbind()))
+    }
+
+    /// `insert(key, value)`, with the tolerance the cache was built with.
+    fn __setitem__(&self, key: VecPy, value: ValuePy) -> PyResult<()> {
+        self.insert(key, value, None)
+    }
+
+    /// `find(k)`, or `default` on a miss.
+    #[pyo3(signature = (k, default=None))]
+    fn get(
+        &self,
+        py: Python<'_>,
+        k: VecPy,
+        default: Option<PyObject>,
+    ) -> PyResult<Option<PyObject>> {
+        Ok(match self.find(py, k)? {
+            Some(value) => Some(value.into_pyobject(py)?.unbind()),
+            None => default,
+        })
+    }
+
+    /// Iterates over the keys, like a dict.
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        PyList::new(py, self.keys())?.try_iter()
+    }
+
+    /// Pickles the cache as a call to `_from_state`. Being frozen, the cache could not
+    /// take the routing hyperplanes of the original in `__setstate__`.
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> PyResult<Reduced<'py>> {
+        let cache = slf.get();
+        let state = CacheState::new(
+            cache.inner.entries(),
+            Some(cache.inner.projections().to_vec()),
+        );
+        let args = (
+            cache.inner.num_shards(),
+            cache.inner.key_dim(),
+            cache.inner.shard_capacity(),
+            cache.non_finite.to_string(),
+            cache.tolerance,
+            state,
+        );
+        Ok((slf.get_type().getattr("_from_state")?, args))
+    }
+
+    /// Rebuilds a cache pickled by `__reduce__`.
+    #[staticmethod]
+    fn _from_state(
+        num_shards: usize,
+        dim: usize,
+        shard_capacity: usize,
+        non_finite: &str,
+        tolerance: Option<f32>,
+        mut state: CacheState,
+    ) -> PyResult<Self> {
+        let shards = (0..num_shards)
+            .map(|_| FifoCache::new(shard_capacity))
+            .collect::<proximity::Result<_>>()
+            .map_err(to_py_err)?;
+        let inner = ShardedInternal::with_projections(shards, dim, state.take_projections()?)
+            .map_err(to_py_err)?;
+        for (key, value, tolerance) in state.entries()? {
+            inner.insert(key, value, tolerance);
+        }
+        Ok(Self {
+            inner,
+            non_finite: non_finite.parse().map_err(to_py_err)?,
+            tolerance: check_default_tolerance(tolerance)?,
+        })
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+}
@@ -0,0 +1,133 @@
+use proximity::caching::{NonFinitePolicy, SharedLshCache as SharedInternal};
+use pyo3::{pyclass, pymethods, Bound, PyAny, PyObject, PyResult, Python};
+
+use crate::pickle::{dumps, loads};
+use crate::vecpy::VecPy;
+use crate::{check_default_tolerance, insert_tolerance, to_py_err, DEFAULT_NON_FINITE_POLICY};
+
+/// Largest pickled value a slot holds unless told otherwise.
+const DEFAULT_MAX_VALUE_SIZE: usize = 4096;
+
+/// LSH cache stored in the named shared-memory segment `name`, which every process that
+/// opens the same name shares, e.g. forked web workers. Values are pickled, and must
+/// fit in `max_value_size` bytes once pickled.
+/// The segment outlives the processes that use it, until `SharedLshCache.unlink(name)`.
+// frozen == methods only take &self, so concurrent calls never fail to borrow
+#[pyclass(frozen, module = "proximipy")]
+pub struct SharedLshCache {
+    inner: SharedInternal,
+    non_finite: NonFinitePolicy,
+    tolerance: Option<f32>,
+}
+
+impl SharedLshCache {
+    fn checked(&self, mut k: VecPy) -> PyResult<VecPy> {
+        k.check_unguarded()?;
+        self.inner.check_dim(&k.inner).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(k)
+    }
+}
+
+#[pymethods]
+impl SharedLshCache {
+    #[new]
+    #[pyo3(signature = (name, num_hash, dim, bucket_capacity, max_value_size=DEFAULT_MAX_VALUE_SIZE, seed=None, non_finite=DEFAULT_NON_FINITE_POLICY, tolerance=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: &str,
+        num_hash: usize,
+        dim: usize,
+        bucket_capacity: usize,
+        max_value_size: usize,
+        seed: Option<u64>,
+        non_finite: &str,
+        tolerance: Option<f32>,
+    ) -> PyResult<Self> {
+        Ok(Self {
+            inner: SharedInternal::open(name, num_hash, dim, bucket_capacity, max_value_size, seed)
+                .map_err(to_py_err)?,
+            non_finite: non_finite.parse().map_err(to_py_err)?,
+            tolerance: check_default_tolerance(tolerance)?,
+        })
+    }
+
+    /// Removes the segment `name`. Caches already open keep working, but the next
+    /// one opened under that name starts empty.
+    #[staticmethod]
+    fn unlink(name: &str) -> PyResult<()> {
+        SharedInternal::unlink(name).map_err(to_py_err)
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn find(&self, py: Python<'_>, k: VecPy) -> PyResult<Option<PyObject>> {
+        let k = self.checked(k)?;
+        // other processes hold the segment lock, not the GIL
+        let found = py
+            .allow_threads(|| self.inner.find(&k.inner))
+            .map_err(to_py_err)?;
+        found.map(|bytes| loads(py, &bytes)).transpose()
+    }
+
+    fn batch_find(&self, py: Python<'_>, ks: Vec<VecPy>) -> PyResult<Vec<Option<PyObject>>> {
+        // more efficient than a python for loop
+        ks.into_iter().map(|k| self.find(py, k)).collect()
+    }
+
+    /// Inserts with the given tolerance, or else the one the cache was built with.
+    #[pyo3(signature = (key, value, tolerance=None))]
+    fn insert(&self, key: VecPy, value: &Bound<'_, PyAny>, tolerance: Option<f32>) -> PyResult<()> {
+        let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+        let key = self.checked(key)?;
+        let py = value.py();
+        let value = dumps(value)?;
+        py.allow_threads(|| self.inner.insert(&key.inner, &value, tolerance))
+            .map_err(to_py_err)
+    }
+
+    /// Empties the cache for every process that shares it.
+    fn clear(&self) {
+        self.inner.clear();
+    }
+
+    /// Lookups that found a match, from every process.
+    fn hits(&self) -> u64 {
+        self.inner.hits()
+    }
+
+    /// Lookups that found no match, from every process.
+    fn misses(&self) -> u64 {
+        self.inner.misses()
+    }
+
+    /// Hit rate over the most recent lookups of this process only.
+    fn recent_hit_rate(&self) -> f32 {
+        self.inner.recent_hit_rate()
+    }
+
+    /// Maximum number of entries of each bucket, fixed by the layout of the segment.
+    #[getter]
+    fn capacity(&self) -> usize {
+        self.inner.bucket_capacity()
+    }
+
+    /// Tolerance of inserts that do not give one, None if they must give one.
+    #[getter]
+    fn default_tolerance(&self) -> Option<f32> {
+        self.tolerance
+    }
+
+    /// Dimension of the keys.
+    #[getter]
+    fn dim(&self) -> usize {
+        self.inner.key_dim()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+}
@@ -0,0 +1,110 @@
+use proximity::caching::ApproximateCache;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyDict, PyDictMethods};
+use pyo3::{Bound, FromPyObject, IntoPyObject, PyErr, PyResult, Python};
+
+use crate::valuepy::ValuePy;
+use crate::vecpy::VecPy;
+
+/// What the caches return from `__getstate__`, as a dict: their entries, in the order
+/// to insert them back in, and for the LSH caches the hyperplanes that route keys,
+/// so that a cache built without a seed routes keys the same way once unpickled.
+///
+/// Values are pickled along with the state. Negative entries, pins and hit statistics
+/// are not kept.
+#[derive(FromPyObject)]
+#[pyo3(from_item_all)]
+pub struct CacheState<V = ValuePy> {
+    pub keys: Vec<VecPy>,
+    pub values: Vec<V>,
+    pub tolerances: Vec<f32>,
+    pub projections: Option<Vec<Vec<f32>>>,
+}
+
+impl<'py, V> IntoPyObject<'py> for CacheState<V>
+where
+    V: IntoPyObject<'py>,
+{
+    type Target = PyDict;
+    type Output = Bound<'py, PyDict>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        let dict = PyDict::new(py);
+        dict.set_item("keys", self.keys)?;
+        dict.set_item("values", self.values)?;
+        dict.set_item("tolerances", self.tolerances)?;
+        dict.set_item("projections", self.projections)?;
+        Ok(dict)
+    }
+}
+
+impl<V> CacheState<V> {
+    pub fn new(
+        entries: impl IntoIterator<Item = (VecPy, V, f32)>,
+        projections: Option<Vec<Vec<f32>>>,
+    ) -> Self {
+        let mut state = CacheState {
+            keys: Vec::new(),
+            values: Vec::new(),
+            tolerances: Vec::new(),
+            projections,
+        };
+        for (key, value, tolerance) in entries {
+            state.keys.push(key);
+            state.values.push(value);
+            state.tolerances.push(tolerance);
+        }
+        state
+    }
+
+    /// Takes the projections out of the state of an LSH cache.
+    pub fn take_projections(&mut self) -> PyResult<Vec<Vec<f32>>> {
+        self.projections
+            .take()
+            .ok_or_else(|| PyValueError::new_err("the pickled cache has no LSH projections"))
+    }
+
+    pub fn entries(self) -> PyResult<impl Iterator<Item = (VecPy, V, f32)>> {
+        if self.keys.len() != self.values.len() || self.keys.len() != self.tolerances.len() {
+            return Err(PyValueError::new_err(
+                "the pickled keys, values and tolerances have different lengths",
+            ));
+        }
+        Ok(self
+            .keys
+            .into_iter()
+            .zip(self.values)
+            .zip(self.tolerances)
+            .map(|((key, value), tolerance)| (key, value, tolerance)))
+    }
+}
+
+/// Entries of an LRU cache, least recently used first, so that inserting them back in
+/// this order restores which one is evicted next.
+pub fn least_recent_first<C, V>(cache: &C) -> Vec<(VecPy, V, f32)>
+where
+    C: ApproximateCache<VecPy, V> + ?Sized,
+{
+    let mut entries: Vec<_> = cache
+        .iter()
+        .map(|(key, value, tolerance)| {
+            let last_access = cache.entry_info(key).map(|info| info.last_access);
+            (last_access, (key.clone(), value, tolerance))
+        })
+        .collect();
+    entries.sort_by_key(|(last_access, _)| *last_access);
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Entries of a FIFO cache, oldest first, so that inserting them back in this order
+/// restores which one is evicted next.
+pub fn oldest_first<C, V>(cache: &C) -> Vec<(VecPy, V, f32)>
+where
+    C: ApproximateCache<VecPy, V> + ?Sized,
+{
+    cache
+        .iter()
+        .map(|(key, value, tolerance)| (key.clone(), value, tolerance))
+        .collect()
+}
@@ -0,0 +1,351 @@
+//! Caches whose values are stored natively rather than as Python objects: `f32`, `i64`,
+//! vectors of `f32` or bytes. A value then takes its own size, without a Python object
+//! header, and is only converted to a Python object when it is returned.
+//!
+//! Their pickled state holds plain numbers, lists and bytes, so that snapshots never
+//! pickle arbitrary Python objects.
+
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use proximity::caching::{
+    ApproximateCache, FifoCache as FifoInternal, HeapSize, LruCache as LruInternal, NonFinitePolicy,
+};
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyKeyError, PyTypeError};
+use pyo3::types::{PyAnyMethods, PyBytes, PyBytesMethods, PyIterator, PyList};
+use pyo3::{
+    pyclass, pymethods, Bound, FromPyObject, IntoPyObject, IntoPyObjectExt, PyAny, PyObject,
+    PyResult, Python,
+};
+
+use crate::array::{self, FoundRows, Tolerances};
+use crate::state::{self, CacheState};
+use crate::vecpy::VecPy;
+use crate::warm;
+use crate::{check_default_tolerance, insert_tolerance, to_py_err, DEFAULT_NON_FINITE_POLICY};
+
+/// A bytes value. Cloning it on a hit does not copy the bytes.
+#[derive(Clone)]
+pub struct BytesPy(Arc<[u8]>);
+
+impl<'a> FromPyObject<'a> for BytesPy {
+    fn extract_bound(ob: &Bound<'a, PyAny>) -> PyResult<Self> {
+        if let Ok(bytes) = ob.downcast::<PyBytes>() {
+            return Ok(BytesPy(bytes.as_bytes().into()));
+        }
+        match PyBuffer::<u8>::get(ob) {
+            Ok(buffer) => Ok(BytesPy(buffer.to_vec(ob.py())?.into())),
+            Err(_) => Err(PyTypeError::new_err(format!(
+                "values must be bytes or byte buffers, got {}",
+                ob.get_type()
+            ))),
+        }
+    }
+}
+
+impl HeapSize for BytesPy {
+    fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes()
+    }
+}
+
+impl<'py> IntoPyObject<'py> for BytesPy {
+    type Target = PyBytes;
+    type Output = Bound<'py, PyBytes>;
+    type Error = Infallible;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        Ok(PyBytes::new(py, &self.0))
+    }
+}
+
+/// Defines a cache class over `$internal`, storing values of type `$value`, whose
+/// pickled entries are listed by `$ordered` in the order to insert them back in.
+macro_rules! typed_cache {
+    ($(#[$attr:meta])* $name:ident, $internal:ident, $value:ty, $ordered:path) => {
+        $(#[$attr])*
+        pub struct $name {
+            inner: $internal<VecPy, $value>,
+            non_finite: NonFinitePolicy,
+            tolerance: Option<f32>,
+        }
+
+        #[pymethods]
+        impl $name {
+            #[new]
+            #[pyo3(signature = (max_capacity, non_finite=DEFAULT_NON_FINITE_POLICY, tolerance=None))]
+            pub fn new(
+                max_capacity: usize,
+                non_finite: &str,
+                tolerance: Option<f32>,
+            ) -> PyResult<Self> {
+                Ok(Self {
+                    inner: $internal::new(max_capacity).map_err(to_py_err)?,
+                    non_finite: non_finite.parse().map_err(to_py_err)?,
+                    tolerance: check_default_tolerance(tolerance)?,
+                })
+            }
+
+            fn find(&mut self, mut k: VecPy) -> PyResult<Option<$value>> {
+                self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                self.inner.try_find(&k).map_err(to_py_err)
+            }
+
+            fn batch_find(&mut self, ks: Vec<VecPy>) -> PyResult<Vec<Option<$value>>> {
+                ks.into_iter().map(|k| self.find(k)).collect()
+            }
+
+            /// Looks up every row of a float32 array of shape (N, D). Returns the N
+            /// values, None on a miss, and an array of their distances to the queries,
+            /// NaN on a miss.
+            fn batch_find_arr<'py>(
+                &mut self,
+                queries: &Bound<'py, PyAny>,
+            ) -> PyResult<FoundRows<'py, $value>> {
+                array::find_rows(queries, |keys| {
+                    keys.into_iter()
+                        .map(|k| self.find_with_distance(k))
+                        .collect()
+                })
+            }
+
+            /// Returns up to k matches as (value, distance) pairs, closest first.
+            fn find_aggregate(
+                &mut self,
+                mut k: VecPy,
+                count: usize,
+            ) -> PyResult<Vec<($value, f32)>> {
+                self.inner.check_dim(&k).map_err(to_py_err)?;
+                self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                Ok(self.inner.find_k(&k, count))
+            }
+
+            /// Returns the closest match and its distance to `k`, or None on a miss.
+            fn find_with_distance(&mut self, k: VecPy) -> PyResult<Option<($value, f32)>> {
+                Ok(self.find_aggregate(k, 1)?.into_iter().next())
+            }
+
+            /// Inserts with the given tolerance, or else the one the cache was built with.
+            #[pyo3(signature = (key, value, tolerance=None))]
+            fn insert(
+                &mut self,
+                mut key: VecPy,
+                value: $value,
+                tolerance: Option<f32>,
+            ) -> PyResult<()> {
+                let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+                self.non_finite.apply(&mut key).map_err(to_py_err)?;
+                self.inner.try_insert(key, value, tolerance).map_err(to_py_err)?;
+                Ok(())
+            }
+
+            /// Inserts every row of a float32 array of shape (N, D) with the matching
+            /// value, under one tolerance, one per row, or else the one the cache was
+            /// built with. Nothing is inserted if any row is rejected.
+            #[pyo3(signature = (keys, values, tolerances=None))]
+            fn batch_insert_arr(
+                &mut self,
+                keys: &Bound<'_, PyAny>,
+                values: Vec<$value>,
+                tolerances: Option<Tolerances>,
+            ) -> PyResult<()> {
+                let rows = array::insert_rows(keys, values, tolerances, self.tolerance, |key| {
+                    self.inner.check_dim(key).map_err(to_py_err)?;
+                    self.non_finite.apply(key).map_err(to_py_err)
+                })?;
+                for (key, value, tolerance) in rows {
+                    self.inner.insert(key, value, tolerance);
+                }
+                Ok(())
+            }
+
+            /// Inserts every row of a `.npy` array of shape (N, D) with the matching value
+            /// of a `.npy` array of N values, e.g. a dataset computed offline, under one
+            /// tolerance, or else the one the cache was built with. The files are read a
+            /// chunk of rows at a time, calling `progress(inserted, total)` after every
+            /// chunk. Returns the number of rows inserted; those before a rejected row
+            /// stay inserted.
+            #[pyo3(signature = (keys_path, values_path, tolerance=None, progress=None))]
+            fn warm_from_npy(
+                &mut self,
+                py: Python<'_>,
+                keys_path: PathBuf,
+                values_path: PathBuf,
+                tolerance: Option<f32>,
+                progress: Option<PyObject>,
+            ) -> PyResult<usize> {
+                let tolerance = insert_tolerance(tolerance, self.tolerance)?;
+                warm::warm_rows(
+                    py,
+                    &keys_path,
+                    &values_path,
+                    progress.as_ref(),
+                    |mut key, value: $value| {
+                        self.non_finite.apply(&mut key).map_err(to_py_err)?;
+                        self.inner.try_insert(key, value, tolerance).map_err(to_py_err)?;
+                        Ok(())
+                    },
+                )
+            }
+
+            fn keys(&self) -> Vec<VecPy> {
+                self.inner.iter().map(|(k, _, _)| k.clone()).collect()
+            }
+
+            fn values(&self) -> Vec<$value> {
+                self.inner.iter().map(|(_, v, _)| v).collect()
+            }
+
+            fn items(&self) -> Vec<(VecPy, $value)> {
+                self.inner.iter().map(|(k, v, _)| (k.clone(), v)).collect()
+            }
+
+            /// Releases the memory left over by evictions, e.g. after heavy churn,
+            /// without changing what the cache holds.
+            fn compact(&mut self) {
+                self.inner.compact();
+            }
+
+            fn recent_hit_rate(&self) -> f32 {
+                self.inner.recent_hit_rate()
+            }
+
+            /// Estimated heap memory of the cache in bytes, keys, values and index
+            /// included.
+            fn memory_bytes(&self) -> usize {
+                self.inner.memory_bytes()
+            }
+
+            /// Maximum number of entries. Setting it evicts entries until the cache
+            /// fits.
+            #[getter]
+            fn capacity(&self) -> usize {
+                self.inner.capacity()
+            }
+
+            #[setter]
+            fn set_capacity(&mut self, capacity: usize) -> PyResult<()> {
+                self.inner.set_capacity(capacity).map_err(to_py_err)?;
+                Ok(())
+            }
+
+            /// Tolerance of inserts that do not give one, None if they must give one.
+            #[getter]
+            fn default_tolerance(&self) -> Option<f32> {
+                self.tolerance
+            }
+
+            /// Dimension of the keys, None until the first insert.
+            #[getter]
+            fn dim(&self) -> Option<usize> {
+                self.inner.key_dim()
+            }
+
+            /// Whether `find(k)` would hit. It is not counted as a lookup.
+            fn __contains__(&self, mut k: VecPy) -> PyResult<bool> {
+                self.inner.check_dim(&k).map_err(to_py_err)?;
+                self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                Ok(self.inner.entry_info(&k).is_some())
+            }
+
+            /// `find(key)`, raising KeyError on a miss like a dict.
+            fn __getitem__(&mut self, key: &Bound<'_, PyAny>) -> PyResult<$value> {
+                self.find(key.extract()?)?
+                    .ok_or_else(|| PyKeyError::new_err(key.clone().unbind()))
+            }
+
+            /// `insert(key, value)`, with the tolerance the cache was built with.
+            fn __setitem__(&mut self, key: VecPy, value: $value) -> PyResult<()> {
+                self.insert(key, value, None)
+            }
+
+            /// `find(k)`, or `default` on a miss.
+            #[pyo3(signature = (k, default=None))]
+            fn get(
+                &mut self,
+                py: Python<'_>,
+                k: VecPy,
+                default: Option<PyObject>,
+            ) -> PyResult<Option<PyObject>> {
+                Ok(match self.find(k)? {
+                    Some(value) => Some(value.into_py_any(py)?),
+                    None => default,
+                })
+            }
+
+            /// Iterates over the keys, like a dict.
+            fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+                PyList::new(py, self.keys())?.try_iter()
+            }
+
+            /// Constructor arguments, for pickling along with `__getstate__`.
+            fn __getnewargs__(&self) -> (usize, String, Option<f32>) {
+                (
+                    self.inner.capacity(),
+                    self.non_finite.to_string(),
+                    self.tolerance,
+                )
+            }
+
+            fn __getstate__(&self) -> CacheState<$value> {
+                CacheState::new($ordered(&self.inner), None)
+            }
+
+            fn __setstate__(&mut self, state: CacheState<$value>) -> PyResult<()> {
+                for (key, value, tolerance) in state.entries()? {
+                    self.inner.insert(key, value, tolerance);
+                }
+                Ok(())
+            }
+
+            fn __len__(&self) -> usize {
+                self.inner.len()
+            }
+        }
+    };
+}
+
+typed_cache!(
+    /// An LRU cache of float values.
+    #[pyclass(unsendable, module = "proximipy")]
+    LruCacheF32, LruInternal, f32, state::least_recent_first
+);
+typed_cache!(
+    /// An LRU cache of integer values.
+    #[pyclass(unsendable, module = "proximipy")]
+    LruCacheI64, LruInternal, i64, state::least_recent_first
+);
+typed_cache!(
+    /// An LRU cache of float32 vectors, e.g. embeddings. Values are taken from lists,
+    /// float32 buffers or tensors like keys, and returned as lists.
+    #[pyclass(unsendable, module = "proximipy")]
+    LruCacheF32Vec, LruInternal, VecPy, state::least_recent_first
+);
+typed_cache!(
+    /// An LRU cache of bytes values.
+    #[pyclass(unsendable, module = "proximipy")]
+    LruCacheBytes, LruInternal, BytesPy, state::least_recent_first
+);
+typed_cache!(
+    /// A FIFO cache of float values.
+    #[pyclass(module = "proximipy")]
+    FifoCacheF32, FifoInternal, f32, state::oldest_first
+);
+typed_cache!(
+    /// A FIFO cache of integer values.
+    #[pyclass(module = "proximipy")]
+    FifoCacheI64, FifoInternal, i64, state::oldest_first
+);
+typed_cache!(
+    /// A FIFO cache of float32 vectors, e.g. embeddings. Values are taken from lists,
+    /// float32 buffers or tensors like keys, and returned as lists.
+    #[pyclass(module = "proximipy")]
+    FifoCacheF32Vec, FifoInternal, VecPy, state::oldest_first
+);
+typed_cache!(
+    /// A FIFO cache of bytes values.
+    #[pyclass(module = "proximipy")]
+    FifoCacheBytes, FifoInternal, BytesPy, state::oldest_first
+);
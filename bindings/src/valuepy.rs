@@ -0,0 +1,53 @@
+use std::convert::Infallible;
+use std::mem::size_of;
+use std::sync::Arc;
+
+use proximity::caching::HeapSize;
+use pyo3::types::{PyAnyMethods, PyMemoryView};
+use pyo3::{Bound, FromPyObject, IntoPyObject, PyAny, PyObject, PyResult, Python};
+
+/// A Python object stored in a cache.
+///
+/// Cloning a `PyObject` bumps its Python reference count, which needs the GIL, while
+/// cloning this only bumps an atomic count. Lookups can thus run with the GIL released,
+/// and the Python reference is only taken when the value is handed back to Python.
+#[derive(Clone)]
+pub struct ValuePy(Arc<PyObject>);
+
+impl<'a> FromPyObject<'a> for ValuePy {
+    fn extract_bound(ob: &Bound<'a, PyAny>) -> PyResult<Self> {
+        Ok(ValuePy(Arc::new(ob.clone().unbind())))
+    }
+}
+
+/// The shared allocation holding the reference, and the object as measured by
+/// `sys.getsizeof`, which calls its `__sizeof__`, or by the length of the buffer it
+/// exposes if larger, e.g. for a view on an array. 0 if it cannot be measured.
+impl HeapSize for ValuePy {
+    fn heap_bytes(&self) -> usize {
+        let object = Python::with_gil(|py| -> PyResult<usize> {
+            let object = self.0.bind(py);
+            let size: usize = py
+                .import("sys")?
+                .call_method1("getsizeof", (object, 0))?
+                .extract()?;
+            let buffer = PyMemoryView::from(object)
+                .and_then(|view| view.getattr("nbytes")?.extract::<usize>())
+                .unwrap_or(0);
+            Ok(size.max(buffer))
+        });
+        // the Arc's counts and the reference itself
+        2 * size_of::<usize>() + size_of::<PyObject>() + object.unwrap_or(0)
+    }
+}
+
+impl<'py> IntoPyObject<'py> for ValuePy {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = Infallible;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        let object = Arc::try_unwrap(self.0).unwrap_or_else(|shared| shared.clone_ref(py));
+        Ok(object.into_bound(py))
+    }
+}
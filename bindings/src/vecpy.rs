@@ -1,19 +1,40 @@
 use std::hash::{Hash, Hasher};
 
+use proximity::caching::HeapSize;
 use proximity::numerics::ApproxComparable;
 
+use crate::dlpack;
+use crate::guard::{Guard, Guarded};
+
 use pyo3::{
+    buffer::PyBuffer,
+    exceptions::{PyTypeError, PyValueError},
     types::{PyAnyMethods, PyList},
-    Bound, FromPyObject, IntoPyObject, PyErr,
+    Bound, FromPyObject, IntoPyObject, PyAny, PyErr, PyResult,
 };
 
 pub struct VecPy {
     pub inner: Vec<f32>,
+    /// Exact-match fields, if the key was given as a `Guarded`.
+    pub guard: Option<Guard>,
+}
+
+impl VecPy {
+    /// Refuses guarded keys, for the caches that only store vectors.
+    pub fn check_unguarded(&self) -> PyResult<()> {
+        if self.guard.is_some() {
+            return Err(PyValueError::new_err(
+                "guarded keys are not supported by this cache",
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl PartialEq for VecPy {
     fn eq(&self, other: &Self) -> bool {
-        self.inner.len() == other.inner.len()
+        self.guard == other.guard
+            && self.inner.len() == other.inner.len()
             && self
                 .inner
                 .iter()
@@ -26,6 +47,7 @@ impl Eq for VecPy {}
 
 impl Hash for VecPy {
     fn hash<H: Hasher>(&self, state: &mut H) {
+        self.guard.hash(state);
         for &val in &self.inner {
             state.write_u32(val.to_bits());
         }
@@ -42,25 +64,62 @@ impl AsRef<[f32]> for VecPy {
 /// This involves new allocations because Python cannot be trusted to keep this
 /// reference alive.
 ///
-/// This can fail if the random object in question is not a list,
-/// in which case it is automatically reported by raising a TypeError exception
-/// in the Python code
+/// Besides lists, anything exposing a 1-D, C-contiguous float32 buffer is accepted,
+/// such as a `numpy.ndarray` of dtype float32, and so are float32 tensors supporting
+/// DLPack, such as torch tensors, which are first copied to the host if need be.
+/// Their contents are copied in one go instead of converting every element to a
+/// Python float first.
+///
+/// This can fail if the random object in question is neither, in which case it is
+/// reported by raising a TypeError exception in the Python code
 impl<'a> FromPyObject<'a> for VecPy {
     fn extract_bound(ob: &pyo3::Bound<'a, pyo3::PyAny>) -> pyo3::PyResult<Self> {
-        let list: Vec<f32> = ob.downcast::<PyList>()?.extract()?;
-        Ok(VecPy { inner: list })
+        if let Ok(guarded) = ob.downcast::<Guarded>() {
+            return Ok(guarded.get().key().clone());
+        }
+        if let Ok(list) = ob.downcast::<PyList>() {
+            return Ok(VecPy {
+                inner: list.extract()?,
+                guard: None,
+            });
+        }
+        let Ok(buffer) = PyBuffer::<f32>::get(ob) else {
+            return match dlpack::copy_f32(ob)? {
+                Some(tensor) if tensor.shape.len() == 1 => Ok(VecPy {
+                    inner: tensor.data,
+                    guard: None,
+                }),
+                Some(_) => Err(PyTypeError::new_err("tensor keys must be 1-dimensional")),
+                None => Err(PyTypeError::new_err(format!(
+                    "keys must be lists, float32 arrays or tensors, got {}",
+                    ob.get_type()
+                ))),
+            };
+        };
+        if buffer.dimensions() != 1 || !buffer.is_c_contiguous() {
+            return Err(PyTypeError::new_err(
+                "array keys must be 1-dimensional and C-contiguous",
+            ));
+        }
+        Ok(VecPy {
+            inner: buffer.to_vec(ob.py())?,
+            guard: None,
+        })
     }
 }
 
-// Cast back the list of T's to a Python list
+// Cast back the list of T's to a Python list, or to a `Guarded` if the key has a guard
 impl<'a> IntoPyObject<'a> for VecPy {
-    type Target = PyList;
-    type Output = Bound<'a, PyList>;
+    type Target = PyAny;
+    type Output = Bound<'a, PyAny>;
     type Error = PyErr;
 
     fn into_pyobject(self, py: pyo3::Python<'a>) -> Result<Self::Output, Self::Error> {
+        if self.guard.is_some() {
+            return Ok(Bound::new(py, Guarded::from(self))?.into_any());
+        }
         let internal = self.inner;
-        PyList::new(py, internal)
+        Ok(PyList::new(py, internal)?.into_any())
     }
 }
 
@@ -68,17 +127,44 @@ impl Clone for VecPy {
     fn clone(&self) -> Self {
         VecPy {
             inner: self.inner.clone(),
+            guard: self.guard.clone(),
         }
     }
 }
 
+impl HeapSize for VecPy {
+    fn heap_bytes(&self) -> usize {
+        self.inner.heap_bytes() + self.guard.as_ref().map_or(0, Guard::heap_bytes)
+    }
+}
+
 impl ApproxComparable for VecPy {
     #[inline]
     fn roughly_matches(&self, instore: &Self, tolerance: f32) -> bool {
-        (&self.inner as &[f32]).roughly_matches(&instore.inner, tolerance)
+        self.guard == instore.guard
+            && (&self.inner as &[f32]).roughly_matches(&instore.inner, tolerance)
     }
     #[inline]
     fn fuzziness(&self, instore: &Self) -> f32 {
+        if self.guard != instore.guard {
+            return f32::INFINITY;
+        }
         (&self.inner as &[f32]).fuzziness(&instore.inner)
     }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.inner.len())
+    }
+
+    fn is_finite(&self) -> bool {
+        (&self.inner as &[f32]).is_finite()
+    }
+
+    fn sanitize(&mut self) {
+        (&mut self.inner as &mut [f32]).sanitize()
+    }
+
+    fn normalize(&mut self) {
+        (&mut self.inner as &mut [f32]).normalize()
+    }
 }
@@ -0,0 +1,139 @@
+use proximity::caching::{CacheView as ViewInternal, NonFinitePolicy};
+use pyo3::exceptions::PyKeyError;
+use pyo3::types::{PyAnyMethods, PyIterator, PyList};
+use pyo3::{pyclass, pymethods, Bound, IntoPyObject, PyAny, PyObject, PyResult, Python};
+
+use crate::array::{self, FoundRows};
+use crate::to_py_err;
+use crate::valuepy::ValuePy;
+use crate::vecpy::VecPy;
+
+/// Immutable copy of a cache, returned by its `snapshot()` method.
+/// Later changes to the cache do not show up in the view, and lookups never lock,
+/// so a view can be queried from many threads at once.
+// frozen == methods only take &self, so concurrent calls never fail to borrow
+#[pyclass(frozen, module = "proximipy")]
+pub struct CacheView {
+    inner: ViewInternal<VecPy, ValuePy>,
+    non_finite: NonFinitePolicy,
+}
+
+impl CacheView {
+    pub fn new(inner: ViewInternal<VecPy, ValuePy>, non_finite: NonFinitePolicy) -> Self {
+        Self { inner, non_finite }
+    }
+}
+
+#[pymethods]
+impl CacheView {
+    fn find(&self, py: Python<'_>, mut k: VecPy) -> PyResult<Option<ValuePy>> {
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        py.allow_threads(|| self.inner.try_find(&k))
+            .map_err(to_py_err)
+    }
+
+    fn batch_find(&self, py: Python<'_>, ks: Vec<VecPy>) -> PyResult<Vec<Option<ValuePy>>> {
+        // more efficient than a python for loop, and other threads run in the meantime
+        py.allow_threads(|| {
+            ks.into_iter()
+                .map(|mut k| {
+                    self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                    self.inner.try_find(&k).map_err(to_py_err)
+                })
+                .collect()
+        })
+    }
+
+    /// Looks up every row of a float32 array of shape (N, D). Returns the N values,
+    /// None on a miss, and an array of their distances to the queries, NaN on a miss.
+    fn batch_find_arr<'py>(&self, queries: &Bound<'py, PyAny>) -> PyResult<FoundRows<'py>> {
+        let py = queries.py();
+        array::find_rows(queries, |keys| {
+            py.allow_threads(|| {
+                keys.into_iter()
+                    .map(|mut k| {
+                        self.inner.check_dim(&k).map_err(to_py_err)?;
+                        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+                        Ok(self.inner.find_k(&k, 1).into_iter().next())
+                    })
+                    .collect()
+            })
+        })
+    }
+
+    /// Returns up to k matches as (value, distance) pairs, closest first,
+    /// so that the caller can interpolate between neighbours.
+    fn find_aggregate(
+        &self,
+        py: Python<'_>,
+        mut k: VecPy,
+        count: usize,
+    ) -> PyResult<Vec<(ValuePy, f32)>> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(py.allow_threads(|| self.inner.find_k(&k, count)))
+    }
+
+    /// Returns the closest match and its distance to `k`, or None on a miss, to tell
+    /// how close a lookup came to its tolerance.
+    fn find_with_distance(&self, py: Python<'_>, mut k: VecPy) -> PyResult<Option<(ValuePy, f32)>> {
+        self.inner.check_dim(&k).map_err(to_py_err)?;
+        self.non_finite.apply(&mut k).map_err(to_py_err)?;
+        Ok(py.allow_threads(|| self.inner.find_k(&k, 1).into_iter().next()))
+    }
+
+    fn keys(&self) -> Vec<VecPy> {
+        self.inner.iter().map(|(k, _, _)| k.clone()).collect()
+    }
+
+    fn values(&self) -> Vec<ValuePy> {
+        self.inner.iter().map(|(_, v, _)| v.clone()).collect()
+    }
+
+    fn items(&self) -> Vec<(VecPy, ValuePy)> {
+        self.inner
+            .iter()
+            .map(|(k, v, _)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Whether `find(k)` would hit.
+    fn __contains__(&self, py: Python<'_>, k: VecPy) -> PyResult<bool> {
+        Ok(self.find(py, k)?.is_some())
+    }
+
+    /// `find(key)`, raising KeyError on a miss like a dict.
+    fn __getitem__(&self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<ValuePy> {
+        self.find(py, key.extract()?)?
+            .ok_or_else(|| PyKeyError::new_err(key.clone().unbind()))
+    }
+
+    /// `find(k)`, or `default` on a miss.
+    #[pyo3(signature = (k, default=None))]
+    fn get(
+        &self,
+        py: Python<'_>,
+        k: VecPy,
+        default: Option<PyObject>,
+    ) -> PyResult<Option<PyObject>> {
+        Ok(match self.find(py, k)? {
+            Some(value) => Some(value.into_pyobject(py)?.unbind()),
+            None => default,
+        })
+    }
+
+    /// Iterates over the keys, like a dict.
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        PyList::new(py, self.keys())?.try_iter()
+    }
+
+    /// Dimension of the keys, None if the view is empty.
+    #[getter]
+    fn dim(&self) -> Option<usize> {
+        self.inner.key_dim()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+}
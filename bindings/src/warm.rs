@@ -0,0 +1,105 @@
+//! Filling a cache from a dataset of `.npy` files, a chunk of rows at a time, or from
+//! the vectors of an existing ANN index.
+
+use std::path::{Path, PathBuf};
+
+use numpy::{PyArray1, PyArray2, PyArrayMethods};
+use proximity::fs::{self, stream_npy, AnnoyMetric, IndexVectors};
+use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyAnyMethods, PyDict, PySlice};
+use pyo3::{pyfunction, Bound, FromPyObject, PyObject, PyResult, Python};
+
+use crate::to_py_err;
+use crate::vecpy::VecPy;
+
+/// Rows read at a time, and between two calls of `progress`.
+const WARM_CHUNK_ROWS: usize = 4096;
+
+/// Calls `insert` on every row of the `.npy` array of shape (N, D) at `keys_path`, with
+/// the matching value of the `.npy` array of N values at `values_path`, and returns the
+/// number of rows inserted.
+///
+/// Keys are streamed from the file, and values read from a memory map by numpy and
+/// converted a chunk at a time, so that only a chunk of rows is held in memory. Calls
+/// `progress(inserted, total)` after every chunk. Rows before one that `insert` rejects
+/// stay inserted.
+pub fn warm_rows<V>(
+    py: Python<'_>,
+    keys_path: &Path,
+    values_path: &Path,
+    progress: Option<&PyObject>,
+    mut insert: impl FnMut(VecPy, V) -> PyResult<()>,
+) -> PyResult<usize>
+where
+    V: for<'py> FromPyObject<'py>,
+{
+    let keys = stream_npy(keys_path, WARM_CHUNK_ROWS).map_err(to_py_err)?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("mmap_mode", "r")?;
+    let values = py
+        .import("numpy")?
+        .call_method("load", (values_path,), Some(&kwargs))?;
+    let (total, dim) = (keys.rows(), keys.dim());
+    if values.len()? != total {
+        return Err(PyValueError::new_err(format!(
+            "got {total} keys but {} values",
+            values.len()?
+        )));
+    }
+    let mut inserted = 0;
+    for chunk in keys {
+        let chunk = chunk.map_err(to_py_err)?;
+        let end = inserted + chunk.len() / dim;
+        let slice = PySlice::new(py, inserted as isize, end as isize, 1);
+        let chunk_values: Vec<V> = values.get_item(slice)?.call_method0("tolist")?.extract()?;
+        for (row, value) in chunk.chunks_exact(dim).zip(chunk_values) {
+            let key = VecPy {
+                inner: row.to_vec(),
+                guard: None,
+            };
+            insert(key, value)?;
+        }
+        inserted = end;
+        if let Some(progress) = progress {
+            progress.call1(py, (inserted, total))?;
+        }
+    }
+    Ok(inserted)
+}
+
+/// Vectors of an index as an array of shape (N, D), and their labels.
+type IndexArrays<'py> = (Bound<'py, PyArray2<f32>>, Vec<u64>);
+
+fn index_arrays(py: Python<'_>, index: IndexVectors) -> PyResult<IndexArrays<'_>> {
+    let rows = index.len();
+    let vectors = PyArray1::from_vec(py, index.vectors).reshape([rows, index.dim])?;
+    Ok((vectors, index.labels))
+}
+
+/// Reads the vectors and labels of an index saved by hnswlib with a float space, to be
+/// inserted with `batch_insert_arr`. Deleted elements are skipped.
+#[pyfunction]
+pub fn read_hnswlib(py: Python<'_>, path: PathBuf) -> PyResult<IndexArrays<'_>> {
+    let index = py
+        .allow_threads(|| fs::read_hnswlib(&path))
+        .map_err(to_py_err)?;
+    index_arrays(py, index)
+}
+
+/// Reads the vectors of an index saved by Annoy, and their item ids as labels, to be
+/// inserted with `batch_insert_arr`. The dimension and metric are not recorded in the
+/// file, so they must be those the index was built with.
+#[pyfunction]
+#[pyo3(signature = (path, dim, metric = "angular"))]
+pub fn read_annoy<'py>(
+    py: Python<'py>,
+    path: PathBuf,
+    dim: usize,
+    metric: &str,
+) -> PyResult<IndexArrays<'py>> {
+    let metric: AnnoyMetric = metric.parse().map_err(to_py_err)?;
+    let index = py
+        .allow_threads(|| fs::read_annoy(&path, dim, metric))
+        .map_err(to_py_err)?;
+    index_arrays(py, index)
+}
@@ -0,0 +1,13 @@
+fn main() {
+    // the schema is compiled in pure Rust, so building the server does not need protoc
+    #[cfg(feature = "server")]
+    {
+        println!("cargo:rerun-if-changed=proto/proximity.proto");
+        let descriptors = protox::compile(["proto/proximity.proto"], ["proto"])
+            .expect("invalid proto/proximity.proto");
+        tonic_build::configure()
+            .build_client(false)
+            .compile_fds(descriptors)
+            .expect("failed to generate the gRPC service");
+    }
+}
@@ -0,0 +1,460 @@
+//! Benchmarking harness for the approximate caches.
+//!
+//! ```text
+//! proximity-bench generate --workload zipf --dim 128 --count 100000 --out trace.fvecs
+//! proximity-bench replay   --dataset trace.fvecs --cache lsh-lru --capacity 64 --tolerance 0.1
+//! proximity-bench sweep    --dataset trace.fvecs --cache lru,fifo --capacity 100,1000 --tolerance 0.05,0.1
+//! proximity-bench evaluate --dataset base.fvecs --queries queries.fvecs --cache lsh-lru --capacity 64 --tolerance 0.1
+//! proximity-bench report   --input results.csv
+//! ```
+//!
+//! `replay` and `sweep` print one result row per configuration, as CSV (default) or JSON.
+//! With `--workload` instead of `--dataset`, queries are generated on the fly.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::{env, fs};
+
+use proximity::caching::{ApproximateCache, CacheBuilder, EvictionPolicy, MaybeSync};
+use proximity::eval::{evaluate, populate};
+use proximity::fs::file_manager::{read_fvecs, read_vecs_dim, write_fvecs};
+use proximity::fs::stream_vectors;
+use proximity::simulation::{simulate, SimKey, TraceGenerator, Workload};
+
+const USAGE: &str =
+    "usage: proximity-bench <generate|replay|sweep|evaluate|report> [--option value]...
+
+generate  --workload zipf|clusters|bursty --dim D --count N [--seed S] --out FILE.fvecs
+replay    (--dataset FILE.fvecs | --workload W --dim D --count N [--seed S])
+          --cache lru|fifo|lru-k|lfu|clock|w-tinylfu|gdsf|s3-fifo|lsh-lru|lsh-fifo|lsh-lru-k|lsh-lfu|lsh-clock|lsh-w-tinylfu|lsh-gdsf|lsh-s3-fifo --capacity C --tolerance T
+          [--num-hash H] [--lsh-seed S] [--format csv|json]
+sweep     same as replay, but --cache, --capacity, --tolerance and --num-hash
+          accept comma-separated lists and every combination is run
+evaluate  --dataset FILE.fvecs --queries FILE.fvecs --cache C --capacity C --tolerance T
+          [--num-hash H] [--lsh-seed S] [--format csv|json]
+          fills the cache with the dataset and scores its answers to the queries
+          against an exact search; with the hdf5 feature, --ann-benchmark FILE.hdf5
+          replaces --dataset and --queries
+report    --input FILE.csv [--format table|json]
+
+For LSH caches, --capacity is the capacity of each bucket.";
+
+const EVAL_COLUMNS: [&str; 10] = [
+    "cache",
+    "capacity",
+    "tolerance",
+    "num_hash",
+    "queries",
+    "answerable",
+    "hits",
+    "recall",
+    "false_accept_rate",
+    "mean_distance_error",
+];
+
+const COLUMNS: [&str; 9] = [
+    "cache",
+    "capacity",
+    "tolerance",
+    "num_hash",
+    "lookups",
+    "hits",
+    "hit_rate",
+    "mean_scan",
+    "mean_latency_ns",
+];
+
+type Row = Vec<String>;
+
+/// `--key value` pairs following the subcommand.
+struct Options(HashMap<String, String>);
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut map = HashMap::new();
+        let mut iter = args.iter();
+        while let Some(flag) = iter.next() {
+            let key = flag
+                .strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument `{flag}`"))?;
+            let value = iter
+                .next()
+                .ok_or_else(|| format!("missing value for `{flag}`"))?;
+            map.insert(key.to_string(), value.clone());
+        }
+        Ok(Options(map))
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    fn required(&self, key: &str) -> Result<&str, String> {
+        self.get(key).ok_or_else(|| format!("missing --{key}"))
+    }
+
+    fn parsed<T: std::str::FromStr>(&self, key: &str, default: Option<T>) -> Result<T, String> {
+        match (self.get(key), default) {
+            (Some(raw), _) => raw
+                .parse()
+                .map_err(|_| format!("invalid value `{raw}` for --{key}")),
+            (None, Some(default)) => Ok(default),
+            (None, None) => Err(format!("missing --{key}")),
+        }
+    }
+
+    fn list<T: std::str::FromStr>(
+        &self,
+        key: &str,
+        default: Option<&str>,
+    ) -> Result<Vec<T>, String> {
+        let raw = match (self.get(key), default) {
+            (Some(raw), _) => raw,
+            (None, Some(default)) => default,
+            (None, None) => return Err(format!("missing --{key}")),
+        };
+        raw.split(',')
+            .map(|item| {
+                item.trim()
+                    .parse()
+                    .map_err(|_| format!("invalid value `{item}` for --{key}"))
+            })
+            .collect()
+    }
+}
+
+fn workload(opts: &Options) -> Result<Workload, String> {
+    let dim = opts.parsed("dim", None)?;
+    match opts.required("workload")? {
+        "zipf" => Ok(Workload::Zipf {
+            pool_size: opts.parsed("pool-size", Some(10_000))?,
+            exponent: opts.parsed("exponent", Some(1.1))?,
+            dim,
+            noise: opts.parsed("noise", Some(0.01))?,
+        }),
+        "clusters" => Ok(Workload::DriftingClusters {
+            clusters: opts.parsed("clusters", Some(32))?,
+            dim,
+            spread: opts.parsed("spread", Some(0.05))?,
+            drift: opts.parsed("drift", Some(0.001))?,
+        }),
+        "bursty" => Ok(Workload::Bursty {
+            pool_size: opts.parsed("pool-size", Some(10_000))?,
+            burst_len: opts.parsed("burst-len", Some(8))?,
+            dim,
+            noise: opts.parsed("noise", Some(0.01))?,
+        }),
+        other => Err(format!("unknown workload `{other}`")),
+    }
+}
+
+/// Rows read from disk at a time when replaying a dataset.
+const STREAM_CHUNK_ROWS: usize = 4096;
+
+/// Where the queries to replay come from. Each replay streams them anew,
+/// so datasets are never fully loaded in memory.
+enum QuerySource {
+    Dataset(PathBuf),
+    Workload {
+        workload: Workload,
+        count: usize,
+        seed: u64,
+    },
+}
+
+impl QuerySource {
+    fn from_options(opts: &Options) -> Result<Self, String> {
+        if let Some(path) = opts.get("dataset") {
+            return Ok(QuerySource::Dataset(PathBuf::from(path)));
+        }
+        Ok(QuerySource::Workload {
+            workload: workload(opts)?,
+            count: opts.parsed("count", None)?,
+            seed: opts.parsed("seed", Some(0))?,
+        })
+    }
+
+    fn dim(&self) -> Result<usize, String> {
+        match self {
+            QuerySource::Dataset(path) => {
+                read_vecs_dim(path).map_err(|e| format!("cannot read `{}`: {e}", path.display()))
+            }
+            QuerySource::Workload { workload, .. } => Ok(workload.dim()),
+        }
+    }
+
+    fn queries(&self) -> Result<Box<dyn Iterator<Item = Vec<f32>>>, String> {
+        match self {
+            QuerySource::Dataset(path) => {
+                let dim = self.dim()?;
+                let stream = stream_vectors(path, dim, STREAM_CHUNK_ROWS)
+                    .map_err(|e| format!("cannot read `{}`: {e}", path.display()))?;
+                Ok(Box::new(stream.flat_map(move |chunk| {
+                    let chunk = chunk.expect("failed to read dataset");
+                    chunk
+                        .chunks_exact(dim)
+                        .map(<[f32]>::to_vec)
+                        .collect::<Vec<_>>()
+                })))
+            }
+            QuerySource::Workload {
+                workload,
+                count,
+                seed,
+            } => Ok(Box::new(
+                TraceGenerator::new(workload.clone(), *seed).take(*count),
+            )),
+        }
+    }
+}
+
+fn build_cache<V: Clone + MaybeSync + 'static>(
+    kind: &str,
+    capacity: usize,
+    num_hash: usize,
+    dim: usize,
+    lsh_seed: u64,
+) -> Result<Box<dyn ApproximateCache<SimKey, V>>, String> {
+    let (policy, routed) = match kind.strip_prefix("lsh-") {
+        Some(policy) => (policy, true),
+        None => (kind, false),
+    };
+    let policy: EvictionPolicy = policy
+        .parse()
+        .map_err(|_| format!("unknown cache `{kind}`"))?;
+    let mut builder = CacheBuilder::new().policy(policy).capacity(capacity);
+    if routed {
+        builder = builder.lsh(num_hash, dim, Some(lsh_seed));
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+fn run_grid(opts: &Options, sweep: bool) -> Result<Vec<Row>, String> {
+    let source = QuerySource::from_options(opts)?;
+    let dim = source.dim()?;
+    let (kinds, capacities, tolerances, hashes): (Vec<String>, Vec<usize>, Vec<f32>, Vec<usize>) =
+        if sweep {
+            (
+                opts.list("cache", None)?,
+                opts.list("capacity", None)?,
+                opts.list("tolerance", None)?,
+                opts.list("num-hash", Some("8"))?,
+            )
+        } else {
+            (
+                vec![opts.required("cache")?.to_string()],
+                vec![opts.parsed("capacity", None)?],
+                vec![opts.parsed("tolerance", None)?],
+                vec![opts.parsed("num-hash", Some(8))?],
+            )
+        };
+    let lsh_seed = opts.parsed("lsh-seed", Some(0))?;
+
+    let mut rows = Vec::new();
+    for kind in &kinds {
+        // the number of hyperplanes only matters for LSH caches
+        let hashes: &[usize] = if kind.starts_with("lsh") {
+            &hashes
+        } else {
+            &[0]
+        };
+        for &capacity in &capacities {
+            for &tolerance in &tolerances {
+                for &num_hash in hashes {
+                    let mut cache = build_cache(kind, capacity, num_hash, dim, lsh_seed)?;
+                    let report = simulate(cache.as_mut(), source.queries()?, tolerance);
+                    rows.push(vec![
+                        kind.clone(),
+                        capacity.to_string(),
+                        tolerance.to_string(),
+                        num_hash.to_string(),
+                        report.lookups.to_string(),
+                        report.hits.to_string(),
+                        format!("{:.6}", report.hit_rate()),
+                        format!("{:.3}", report.mean_scan()),
+                        report.mean_latency().as_nanos().to_string(),
+                    ]);
+                }
+            }
+        }
+    }
+    Ok(rows)
+}
+
+fn to_csv(header: &[String], rows: &[Row]) -> String {
+    let mut out = header.join(",");
+    out.push('\n');
+    for row in rows {
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Numeric cells are emitted as JSON numbers, everything else as strings.
+fn to_json(header: &[String], rows: &[Row]) -> String {
+    let objects: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            let fields: Vec<String> = header
+                .iter()
+                .zip(row)
+                .map(|(column, cell)| match cell.parse::<f64>() {
+                    Ok(num) if num.is_finite() => format!("\"{column}\": {cell}"),
+                    _ => format!("\"{column}\": \"{}\"", cell.replace('"', "\\\"")),
+                })
+                .collect();
+            format!("  {{{}}}", fields.join(", "))
+        })
+        .collect();
+    format!("[\n{}\n]\n", objects.join(",\n"))
+}
+
+fn to_table(header: &[String], rows: &[Row]) -> String {
+    let widths: Vec<usize> = (0..header.len())
+        .map(|col| {
+            rows.iter()
+                .map(|row| row.get(col).map_or(0, String::len))
+                .chain([header[col].len()])
+                .max()
+                .unwrap()
+        })
+        .collect();
+    let line = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &w)| format!("{cell:>w$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    let mut out = line(header);
+    out.push('\n');
+    for row in rows {
+        out.push_str(&line(row));
+        out.push('\n');
+    }
+    out
+}
+
+/// The vectors to fill the cache with, the queries, and their dimension.
+fn evaluation_data(opts: &Options) -> Result<(Vec<f32>, Vec<f32>, usize), String> {
+    #[cfg(feature = "hdf5")]
+    if let Some(path) = opts.get("ann-benchmark") {
+        let bench = proximity::fs::AnnBenchmark::open(Path::new(path))
+            .map_err(|e| format!("cannot read `{path}`: {e}"))?;
+        return Ok((bench.train, bench.test, bench.dim));
+    }
+    let path = opts.required("dataset")?;
+    let (dataset, dim) =
+        read_fvecs(Path::new(path)).map_err(|e| format!("cannot read `{path}`: {e}"))?;
+    let queries_path = opts.required("queries")?;
+    let (queries, queries_dim) = read_fvecs(Path::new(queries_path))
+        .map_err(|e| format!("cannot read `{queries_path}`: {e}"))?;
+    if dim == 0 || dim != queries_dim {
+        return Err(format!(
+            "dataset dimension {dim} and query dimension {queries_dim} do not match"
+        ));
+    }
+    Ok((dataset, queries, dim))
+}
+
+fn run_evaluation(opts: &Options) -> Result<Vec<Row>, String> {
+    let (dataset, queries, dim) = evaluation_data(opts)?;
+    let kind = opts.required("cache")?;
+    let capacity = opts.parsed("capacity", None)?;
+    let tolerance = opts.parsed("tolerance", None)?;
+    let num_hash = if kind.starts_with("lsh") {
+        opts.parsed("num-hash", Some(8))?
+    } else {
+        0
+    };
+    let lsh_seed = opts.parsed("lsh-seed", Some(0))?;
+
+    let mut cache = build_cache(kind, capacity, num_hash, dim, lsh_seed)?;
+    populate(cache.as_mut(), &dataset, dim, tolerance);
+    let report = evaluate(cache.as_mut(), &dataset, &queries, dim, tolerance);
+    Ok(vec![vec![
+        kind.to_string(),
+        capacity.to_string(),
+        tolerance.to_string(),
+        num_hash.to_string(),
+        report.queries.to_string(),
+        report.answerable.to_string(),
+        report.hits.to_string(),
+        format!("{:.6}", report.recall()),
+        format!("{:.6}", report.false_accept_rate()),
+        format!("{:.6}", report.mean_distance_error()),
+    ]])
+}
+
+fn print_rows(opts: &Options, columns: &[&str], rows: &[Row]) -> Result<(), String> {
+    let header: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+    match opts.get("format").unwrap_or("csv") {
+        "csv" => print!("{}", to_csv(&header, rows)),
+        "json" => print!("{}", to_json(&header, rows)),
+        other => return Err(format!("unknown format `{other}`")),
+    }
+    Ok(())
+}
+
+fn generate(opts: &Options) -> Result<(), String> {
+    let workload = workload(opts)?;
+    let dim = workload.dim();
+    let count: usize = opts.parsed("count", None)?;
+    let seed = opts.parsed("seed", Some(0))?;
+    let out = opts.required("out")?;
+    let flat: Vec<f32> = TraceGenerator::new(workload, seed)
+        .take(count)
+        .flatten()
+        .collect();
+    write_fvecs(Path::new(out), &flat, dim).map_err(|e| format!("cannot write `{out}`: {e}"))?;
+    eprintln!("wrote {count} queries of dimension {dim} to {out}");
+    Ok(())
+}
+
+fn report(opts: &Options) -> Result<(), String> {
+    let path = opts.required("input")?;
+    let content = fs::read_to_string(path).map_err(|e| format!("cannot read `{path}`: {e}"))?;
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<String> = lines
+        .next()
+        .ok_or_else(|| format!("`{path}` is empty"))?
+        .split(',')
+        .map(str::to_string)
+        .collect();
+    let rows: Vec<Row> = lines
+        .map(|line| line.split(',').map(str::to_string).collect())
+        .collect();
+    match opts.get("format").unwrap_or("table") {
+        "table" => print!("{}", to_table(&header, &rows)),
+        "json" => print!("{}", to_json(&header, &rows)),
+        other => return Err(format!("unknown format `{other}`")),
+    }
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let (command, rest) = args.split_first().ok_or("missing subcommand")?;
+    let opts = Options::parse(rest)?;
+    match command.as_str() {
+        "generate" => generate(&opts),
+        "replay" => print_rows(&opts, &COLUMNS, &run_grid(&opts, false)?),
+        "sweep" => print_rows(&opts, &COLUMNS, &run_grid(&opts, true)?),
+        "evaluate" => print_rows(&opts, &EVAL_COLUMNS, &run_evaluation(&opts)?),
+        "report" => report(&opts),
+        other => Err(format!("unknown subcommand `{other}`")),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(msg) => {
+            eprintln!("error: {msg}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Daemon owning one approximate cache, served over a Unix socket with the compact
+//! protocol of [`proximity::ipc`], so that the cache outlives the processes using it.
+//!
+//! ```text
+//! proximity-daemon --socket /tmp/proximity.sock --cache lsh-lru --capacity 64 --dim 128 --tolerance 0.1
+//! ```
+
+use std::collections::HashMap;
+use std::env;
+use std::process::ExitCode;
+
+use proximity::caching::{CacheBuilder, EvictionPolicy, NonFinitePolicy};
+use proximity::ipc::IpcServer;
+use proximity::numerics::PlainVector;
+
+const USAGE: &str = "usage: proximity-daemon --socket PATH --cache lru|fifo|lru-k|lfu|clock|w-tinylfu|gdsf|s3-fifo|lsh-lru|lsh-fifo|lsh-lru-k|lsh-lfu|lsh-clock|lsh-w-tinylfu|lsh-gdsf|lsh-s3-fifo --capacity C [--option value]...
+
+--dim D          dimension of the keys, required for LSH caches and refused by others
+--tolerance T    tolerance of inserts that do not set one
+--num-hash H     hyperplanes of LSH caches (default 8)
+--lsh-seed S     seed of the LSH hyperplanes (default 0)
+--non-finite P   what to do with NaN or infinite keys: reject, sanitize or allow (default reject)
+
+For LSH caches, --capacity is the capacity of each bucket.";
+
+/// `--key value` pairs.
+struct Options(HashMap<String, String>);
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut map = HashMap::new();
+        let mut iter = args.iter();
+        while let Some(flag) = iter.next() {
+            let key = flag
+                .strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument `{flag}`"))?;
+            let value = iter
+                .next()
+                .ok_or_else(|| format!("missing value for `{flag}`"))?;
+            map.insert(key.to_string(), value.clone());
+        }
+        Ok(Options(map))
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    fn required(&self, key: &str) -> Result<&str, String> {
+        self.get(key).ok_or_else(|| format!("missing --{key}"))
+    }
+
+    fn parsed<T: std::str::FromStr>(&self, key: &str, default: Option<T>) -> Result<T, String> {
+        match (self.get(key), default) {
+            (Some(raw), _) => raw
+                .parse()
+                .map_err(|_| format!("invalid value `{raw}` for --{key}")),
+            (None, Some(default)) => Ok(default),
+            (None, None) => Err(format!("missing --{key}")),
+        }
+    }
+}
+
+fn builder(opts: &Options) -> Result<CacheBuilder, String> {
+    let kind = opts.required("cache")?;
+    let (policy, routed) = match kind.strip_prefix("lsh-") {
+        Some(policy) => (policy, true),
+        None => (kind, false),
+    };
+    let policy: EvictionPolicy = policy
+        .parse()
+        .map_err(|_| format!("unknown cache `{kind}`"))?;
+    let mut builder = CacheBuilder::new()
+        .policy(policy)
+        .capacity(opts.parsed("capacity", None)?);
+    if routed {
+        builder = builder.lsh(
+            opts.parsed("num-hash", Some(8))?,
+            opts.parsed("dim", None)?,
+            Some(opts.parsed("lsh-seed", Some(0))?),
+        );
+    } else if opts.get("dim").is_some() {
+        return Err(format!("--dim only applies to LSH caches, not `{kind}`"));
+    }
+    if let Some(tolerance) = opts.get("tolerance") {
+        let tolerance = tolerance
+            .parse()
+            .map_err(|_| format!("invalid value `{tolerance}` for --tolerance"))?;
+        builder = builder.tolerance(tolerance);
+    }
+    Ok(builder)
+}
+
+// errors are messages about invalid arguments
+fn run(args: &[String]) -> Result<(), String> {
+    let opts = Options::parse(args)?;
+    let non_finite: NonFinitePolicy = opts
+        .get("non-finite")
+        .unwrap_or("reject")
+        .parse()
+        .map_err(|e: proximity::ProximityError| e.to_string())?;
+    let mut cache = builder(&opts)?
+        .build::<PlainVector, Vec<u8>>()
+        .map_err(|e| e.to_string())?;
+    let server = IpcServer::bind(opts.required("socket")?)
+        .map_err(|e| e.to_string())?
+        .non_finite(non_finite);
+    eprintln!("listening on {}", server.path().display());
+    server.serve(cache.as_mut()).map_err(|e| e.to_string())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(msg) => {
+            eprintln!("error: {msg}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Serves one approximate cache over gRPC, so that workers on different machines
+//! share the same warmed cache.
+//!
+//! ```text
+//! proximity-server --addr 0.0.0.0:50051 --cache lsh-lru --capacity 64 --dim 128 --tolerance 0.1
+//! ```
+//!
+//! The service is described in `proto/proximity.proto`. Keys are float vectors and
+//! values are opaque bytes, e.g. pickled Python objects.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::sync::mpsc;
+use std::{env, thread};
+
+use tokio::sync::oneshot;
+use tonic::{transport::Server, Request, Response, Status};
+
+use proximity::caching::{ApproximateCache, CacheBuilder, EvictionPolicy, NonFinitePolicy};
+use proximity::numerics::PlainVector;
+use proximity::ProximityError;
+
+mod proto {
+    tonic::include_proto!("proximity");
+}
+
+use proto::cache_server::{Cache, CacheServer};
+use proto::{
+    BatchFindRequest, BatchFindResponse, Entry, FindRequest, FindResponse, InsertRequest,
+    InsertResponse, Key, SnapshotRequest, SnapshotResponse, StatsRequest, StatsResponse,
+};
+
+const USAGE: &str =
+    "usage: proximity-server --cache lru|fifo|lru-k|lfu|clock|w-tinylfu|gdsf|s3-fifo|lsh-lru|lsh-fifo|lsh-lru-k|lsh-lfu|lsh-clock|lsh-w-tinylfu|lsh-gdsf|lsh-s3-fifo --capacity C [--option value]...
+
+--addr A         address to listen on (default 127.0.0.1:50051)
+--dim D          dimension of the keys, required for LSH caches, others refuse keys of
+                 another dimension
+--tolerance T    tolerance of inserts that do not set one
+--num-hash H     hyperplanes of LSH caches (default 8)
+--lsh-seed S     seed of the LSH hyperplanes (default 0)
+--non-finite P   what to do with NaN or infinite keys: reject, sanitize or allow (default reject)
+
+For LSH caches, --capacity is the capacity of each bucket.";
+
+/// `--key value` pairs.
+struct Options(HashMap<String, String>);
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut map = HashMap::new();
+        let mut iter = args.iter();
+        while let Some(flag) = iter.next() {
+            let key = flag
+                .strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument `{flag}`"))?;
+            let value = iter
+                .next()
+                .ok_or_else(|| format!("missing value for `{flag}`"))?;
+            map.insert(key.to_string(), value.clone());
+        }
+        Ok(Options(map))
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    fn required(&self, key: &str) -> Result<&str, String> {
+        self.get(key).ok_or_else(|| format!("missing --{key}"))
+    }
+
+    fn parsed<T: std::str::FromStr>(&self, key: &str, default: Option<T>) -> Result<T, String> {
+        match (self.get(key), default) {
+            (Some(raw), _) => raw
+                .parse()
+                .map_err(|_| format!("invalid value `{raw}` for --{key}")),
+            (None, Some(default)) => Ok(default),
+            (None, None) => Err(format!("missing --{key}")),
+        }
+    }
+}
+
+fn builder(opts: &Options) -> Result<CacheBuilder, String> {
+    let kind = opts.required("cache")?;
+    let (policy, routed) = match kind.strip_prefix("lsh-") {
+        Some(policy) => (policy, true),
+        None => (kind, false),
+    };
+    let policy: EvictionPolicy = policy
+        .parse()
+        .map_err(|_| format!("unknown cache `{kind}`"))?;
+    let mut builder = CacheBuilder::new()
+        .policy(policy)
+        .capacity(opts.parsed("capacity", None)?);
+    if routed {
+        builder = builder.lsh(
+            opts.parsed("num-hash", Some(8))?,
+            opts.parsed("dim", None)?,
+            Some(opts.parsed("lsh-seed", Some(0))?),
+        );
+    }
+    if let Some(tolerance) = opts.get("tolerance") {
+        let tolerance = tolerance
+            .parse()
+            .map_err(|_| format!("invalid value `{tolerance}` for --tolerance"))?;
+        builder = builder.tolerance(tolerance);
+    }
+    Ok(builder)
+}
+
+// errors are messages about invalid arguments
+type Reply<T> = oneshot::Sender<Result<T, String>>;
+
+enum Command {
+    Find(Vec<Key>, Reply<Vec<FindResponse>>),
+    Insert(InsertRequest, Reply<InsertResponse>),
+    Stats(Reply<StatsResponse>),
+    Snapshot(Reply<SnapshotResponse>),
+}
+
+/// Owns the cache on a dedicated thread, since the caches are not [`Send`], and
+/// serves requests one at a time, in the order they arrive.
+struct Worker {
+    cache: Box<dyn ApproximateCache<PlainVector, Vec<u8>>>,
+    non_finite: NonFinitePolicy,
+    dim: Option<usize>,
+    hits: u64,
+    misses: u64,
+    inserts: u64,
+}
+
+impl Worker {
+    fn key(&self, key: Option<Key>) -> Result<PlainVector, String> {
+        let mut key = PlainVector(key.map(|k| k.components).unwrap_or_default());
+        if key.0.is_empty() {
+            return Err("missing key".to_string());
+        }
+        if let Some(expected) = self.dim.filter(|&dim| dim != key.0.len()) {
+            let found = key.0.len();
+            return Err(ProximityError::DimensionMismatch { expected, found }.to_string());
+        }
+        let checked = self
+            .cache
+            .check_dim(&key)
+            .and_then(|()| self.non_finite.apply(&mut key));
+        checked.map_err(|e| e.to_string())?;
+        Ok(key)
+    }
+
+    fn find(&mut self, key: Key) -> Result<FindResponse, String> {
+        let key = self.key(Some(key))?;
+        let value = self.cache.find(&key);
+        if value.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        Ok(FindResponse { value })
+    }
+
+    fn insert(&mut self, request: InsertRequest) -> Result<InsertResponse, String> {
+        let tolerance = match request.tolerance.or(self.cache.default_tolerance()) {
+            Some(tolerance) if tolerance.is_finite() && tolerance >= 0.0 => tolerance,
+            Some(tolerance) => return Err(format!("invalid tolerance {tolerance}")),
+            None => return Err("no tolerance given and the server has no default".to_string()),
+        };
+        let key = self.key(request.key)?;
+        let evicted = self.cache.insert_evicting(key, request.value, tolerance);
+        self.inserts += 1;
+        Ok(InsertResponse {
+            evicted: evicted.len() as u32,
+        })
+    }
+
+    fn stats(&self) -> StatsResponse {
+        StatsResponse {
+            len: self.cache.len() as u64,
+            key_dim: self.cache.key_dim().or(self.dim).map(|dim| dim as u64),
+            recent_hit_rate: self.cache.recent_hit_rate(),
+            hits: self.hits,
+            misses: self.misses,
+            inserts: self.inserts,
+        }
+    }
+
+    fn snapshot(&self) -> SnapshotResponse {
+        let entries = self
+            .cache
+            .iter()
+            .map(|(key, value, tolerance)| Entry {
+                key: Some(Key {
+                    components: key.0.clone(),
+                }),
+                value,
+                tolerance,
+            })
+            .collect();
+        SnapshotResponse { entries }
+    }
+
+    fn serve(mut self, commands: mpsc::Receiver<Command>) {
+        // a closed reply channel means the client went away, which is none of our business
+        for command in commands {
+            match command {
+                Command::Find(keys, reply) => {
+                    let found = keys.into_iter().map(|key| self.find(key)).collect();
+                    let _ = reply.send(found);
+                }
+                Command::Insert(request, reply) => {
+                    let _ = reply.send(self.insert(request));
+                }
+                Command::Stats(reply) => {
+                    let _ = reply.send(Ok(self.stats()));
+                }
+                Command::Snapshot(reply) => {
+                    let _ = reply.send(Ok(self.snapshot()));
+                }
+            }
+        }
+    }
+}
+
+/// Builds the cache on its own thread and hands out a channel to it.
+fn spawn_worker(opts: &Options) -> Result<mpsc::Sender<Command>, String> {
+    let builder = builder(opts)?;
+    let non_finite: NonFinitePolicy = opts
+        .get("non-finite")
+        .unwrap_or("reject")
+        .parse()
+        .map_err(|e: ProximityError| e.to_string())?;
+    let dim = opts
+        .get("dim")
+        .map(|_| opts.parsed("dim", None))
+        .transpose()?;
+
+    let (commands, received) = mpsc::channel();
+    let (built, build_result) = mpsc::channel();
+    thread::spawn(move || match builder.build() {
+        Ok(cache) => {
+            let _ = built.send(Ok(()));
+            Worker {
+                cache,
+                non_finite,
+                dim,
+                hits: 0,
+                misses: 0,
+                inserts: 0,
+            }
+            .serve(received);
+        }
+        Err(e) => {
+            let _ = built.send(Err(e.to_string()));
+        }
+    });
+    build_result
+        .recv()
+        .map_err(|_| "cache thread exited".to_string())??;
+    Ok(commands)
+}
+
+struct CacheService {
+    worker: mpsc::Sender<Command>,
+}
+
+impl CacheService {
+    async fn call<T>(&self, command: impl FnOnce(Reply<T>) -> Command) -> Result<T, Status> {
+        let (reply, response) = oneshot::channel();
+        self.worker
+            .send(command(reply))
+            .map_err(|_| Status::unavailable("cache thread exited"))?;
+        response
+            .await
+            .map_err(|_| Status::unavailable("cache thread exited"))?
+            .map_err(Status::invalid_argument)
+    }
+}
+
+#[tonic::async_trait]
+impl Cache for CacheService {
+    async fn find(&self, request: Request<FindRequest>) -> Result<Response<FindResponse>, Status> {
+        let key = request
+            .into_inner()
+            .key
+            .ok_or_else(|| Status::invalid_argument("missing key"))?;
+        let mut found = self.call(|reply| Command::Find(vec![key], reply)).await?;
+        Ok(Response::new(found.pop().unwrap_or_default()))
+    }
+
+    async fn batch_find(
+        &self,
+        request: Request<BatchFindRequest>,
+    ) -> Result<Response<BatchFindResponse>, Status> {
+        let keys = request.into_inner().keys;
+        let results = self.call(|reply| Command::Find(keys, reply)).await?;
+        Ok(Response::new(BatchFindResponse { results }))
+    }
+
+    async fn insert(
+        &self,
+        request: Request<InsertRequest>,
+    ) -> Result<Response<InsertResponse>, Status> {
+        let request = request.into_inner();
+        let inserted = self.call(|reply| Command::Insert(request, reply)).await?;
+        Ok(Response::new(inserted))
+    }
+
+    async fn stats(&self, _: Request<StatsRequest>) -> Result<Response<StatsResponse>, Status> {
+        Ok(Response::new(self.call(Command::Stats).await?))
+    }
+
+    async fn snapshot(
+        &self,
+        _: Request<SnapshotRequest>,
+    ) -> Result<Response<SnapshotResponse>, Status> {
+        Ok(Response::new(self.call(Command::Snapshot).await?))
+    }
+}
+
+async fn run(args: &[String]) -> Result<(), String> {
+    let opts = Options::parse(args)?;
+    let addr: SocketAddr = opts.parsed("addr", Some(([127, 0, 0, 1], 50051).into()))?;
+    let worker = spawn_worker(&opts)?;
+    eprintln!("listening on {addr}");
+    Server::builder()
+        .add_service(CacheServer::new(CacheService { worker }))
+        .serve_with_shutdown(addr, async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(msg) => {
+            eprintln!("error: {msg}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,80 @@
+/// Combines weighted matches (value, normalized weight) into a single value.
+pub type Reducer<V> = dyn Fn(&[(V, f32)]) -> V;
+
+/// How the matches returned by [`find_k`](crate::caching::ApproximateCache::find_k)
+/// are weighted before being combined by `find_aggregate`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Weighting {
+    /// Every match has the same weight.
+    Uniform,
+    /// Weights are proportional to `1 / distance`. Exact matches take all the weight.
+    InverseDistance,
+    /// Weights are `softmax(-distance / temperature)`.
+    Softmax { temperature: f32 },
+}
+
+impl Weighting {
+    /// Normalized weights (summing to one) for matches at the given distances.
+    pub fn weights(&self, distances: &[f32]) -> Vec<f32> {
+        if distances.is_empty() {
+            return Vec::new();
+        }
+        let raw: Vec<f32> = match *self {
+            Weighting::Uniform => vec![1.0; distances.len()],
+            Weighting::InverseDistance => {
+                if distances.contains(&0.0) {
+                    distances
+                        .iter()
+                        .map(|&d| if d == 0.0 { 1.0 } else { 0.0 })
+                        .collect()
+                } else {
+                    distances.iter().map(|&d| 1.0 / d).collect()
+                }
+            }
+            Weighting::Softmax { temperature } => {
+                assert!(temperature > 0.0, "softmax temperature must be positive");
+                // shift by the smallest distance for numerical stability
+                let closest = distances.iter().copied().fold(f32::INFINITY, f32::min);
+                distances
+                    .iter()
+                    .map(|&d| (-(d - closest) / temperature).exp())
+                    .collect()
+            }
+        };
+        let total: f32 = raw.iter().sum();
+        raw.into_iter().map(|w| w / total).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-6)
+    }
+
+    #[test]
+    fn test_uniform() {
+        assert!(close(&Weighting::Uniform.weights(&[0.1, 5.0]), &[0.5, 0.5]));
+        assert!(Weighting::Uniform.weights(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_inverse_distance() {
+        let w = Weighting::InverseDistance.weights(&[1.0, 3.0]);
+        assert!(close(&w, &[0.75, 0.25]));
+        let exact = Weighting::InverseDistance.weights(&[0.0, 3.0, 0.0]);
+        assert!(close(&exact, &[0.5, 0.0, 0.5]));
+    }
+
+    #[test]
+    fn test_softmax() {
+        let w = Weighting::Softmax { temperature: 1.0 }.weights(&[0.0, 1.0]);
+        let e = (-1.0f32).exp();
+        assert!(close(&w, &[1.0 / (1.0 + e), e / (1.0 + e)]));
+        // very low temperatures converge to the nearest match
+        let sharp = Weighting::Softmax { temperature: 1e-3 }.weights(&[0.5, 1.0]);
+        assert!(sharp[0] > 0.999);
+    }
+}
@@ -1,4 +1,14 @@
+use std::mem::size_of;
+use std::time::Duration;
+
+use crate::caching::memory::entry_bytes;
+use crate::caching::CacheView;
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::{Reducer, Weighting};
+use crate::clock::Instant;
 use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
 
 pub type Tolerance = f32;
 
@@ -7,11 +17,364 @@ where
     K: ApproxComparable,
 {
     fn find(&mut self, target: &K) -> Option<V>;
-    fn insert(&mut self, key: K, value: V, tolerance: f32);
+    /// Like [`find`](Self::find), failing with [`ProximityError::DimensionMismatch`]
+    /// instead of panicking when `target` does not have the dimension of the stored keys.
+    fn try_find(&mut self, target: &K) -> Result<Option<V>> {
+        self.check_dim(target)?;
+        Ok(self.find(target))
+    }
+    /// Up to `k` matching values along with their distance to `target`, closest first.
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)>;
+    /// Like [`find`](Self::find), along with the age of the matching entry, so that
+    /// callers can tell stale hits apart, e.g. to serve them while recomputing them.
+    fn find_with_age(&mut self, target: &K) -> Option<(V, Duration)> {
+        let value = self.find(target)?;
+        let age = self
+            .entry_info(target)
+            .map_or(Duration::ZERO, |info| info.age());
+        Some((value, age))
+    }
+    /// Combines up to `k` matches into a single value.
+    /// `reducer` receives each matching value with its normalized weight.
+    fn find_aggregate(
+        &mut self,
+        target: &K,
+        k: usize,
+        weighting: Weighting,
+        reducer: &Reducer<V>,
+    ) -> Option<V> {
+        let matches = self.find_k(target, k);
+        if matches.is_empty() {
+            return None;
+        }
+        let distances: Vec<f32> = matches.iter().map(|(_, d)| *d).collect();
+        let weighted: Vec<(V, f32)> = matches
+            .into_iter()
+            .zip(weighting.weights(&distances))
+            .map(|((v, _), w)| (v, w))
+            .collect();
+        Some(reducer(&weighted))
+    }
+    fn insert(&mut self, key: K, value: V, tolerance: f32) {
+        self.insert_evicting(key, value, tolerance);
+    }
+    /// Like [`insert_evicting`](Self::insert_evicting), failing with
+    /// [`ProximityError::DimensionMismatch`] instead of panicking when `key` does not have
+    /// the dimension of the stored keys.
+    fn try_insert(&mut self, key: K, value: V, tolerance: f32) -> Result<Vec<(K, V, Tolerance)>> {
+        self.check_dim(&key)?;
+        Ok(self.insert_evicting(key, value, tolerance))
+    }
+    /// Inserts an entry and returns the entries that left the cache to make room for it.
+    /// If the new entry could not be admitted at all, it is returned instead.
+    fn insert_evicting(&mut self, key: K, value: V, tolerance: f32) -> Vec<(K, V, Tolerance)> {
+        self.insert_with_priority(key, value, tolerance, 0)
+    }
+    /// Like [`insert_evicting`](Self::insert_evicting), for an entry that eviction spares
+    /// as long as entries of a lower priority remain, e.g. one that is expensive to
+    /// recompute. Among entries of the same priority, the cache's policy decides.
+    /// Entries inserted without a priority have priority 0.
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)>;
+    /// Tolerance of entries inserted without one, see [`DefaultTolerance`](crate::caching::DefaultTolerance).
+    fn default_tolerance(&self) -> Option<Tolerance> {
+        None
+    }
+    /// Inserts an entry with the cache's default tolerance.
+    /// Fails if the cache was not given one.
+    fn insert_default(&mut self, key: K, value: V) -> Result<()> {
+        let tolerance = self.default_tolerance().ok_or_else(|| {
+            ProximityError::InvalidArgument("the cache has no default tolerance".into())
+        })?;
+        self.insert(key, value, tolerance);
+        Ok(())
+    }
     fn len(&self) -> usize;
+    /// Number of stored entries that a lookup of `target` compares against.
+    fn candidates(&self, _target: &K) -> usize {
+        self.len()
+    }
+    /// Dimension that every key must have, or `None` if the cache does not constrain it yet.
+    fn key_dim(&self) -> Option<usize> {
+        None
+    }
+    /// Checks that `key` has the dimension of the keys this cache works with.
+    /// Lookups and inserts with a mismatched key panic with this error instead of comparing
+    /// vectors of different lengths; [`try_find`](Self::try_find) and
+    /// [`try_insert`](Self::try_insert) return it instead.
+    fn check_dim(&self, key: &K) -> Result<()> {
+        match (self.key_dim(), key.dimension()) {
+            (Some(expected), Some(found)) if expected != found => {
+                Err(ProximityError::DimensionMismatch { expected, found })
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Distance from `target` to the closest stored key, along with that entry's tolerance,
+    /// whether or not it is close enough to match.
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)>;
+    /// Key of the entry that inserting `incoming` would evict, or `None` if it would not evict.
+    /// Admission policies use this to weigh the newcomer against its victim.
+    fn next_victim(&self, incoming: &K) -> Option<&K>;
+    /// Pins the entry that `target` would be matched to, exempting it from eviction.
+    /// Returns `false` if no stored entry matches `target`, or if the cache cannot pin.
+    fn pin(&mut self, _target: &K) -> bool {
+        false
+    }
+    /// Makes a previously pinned entry evictable again.
+    /// Returns `false` if no stored entry matches `target`, or if the cache cannot pin.
+    fn unpin(&mut self, _target: &K) -> bool {
+        false
+    }
+    /// Usage metadata of the entry that `target` would be matched to, or `None` if the
+    /// cache does not track it. Looking it up does not count as a hit.
+    fn entry_info(&self, _target: &K) -> Option<EntryInfo> {
+        None
+    }
+    /// Iterates over every stored key along with its usage metadata, in the order of
+    /// [`iter`](Self::iter). Caches that do not track it yield nothing.
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(std::iter::empty())
+    }
+    /// Iterates over every stored entry as `(key, value, tolerance)`, without affecting eviction.
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_>;
+    /// Removes every entry from the cache and yields them in eviction order.
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_>;
+    /// Removes every entry that `keep` returns false for, and returns them. The other
+    /// entries stay as they are, pins and usage metadata included.
+    ///
+    /// The default drains the cache and inserts the kept entries back by increasing
+    /// priority, then least recently used first, so that their pins and hit counts start
+    /// over. Caches that can remove entries in place override it.
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)>
+    where
+        K: Clone,
+    {
+        let (kept, dropped): (Vec<_>, Vec<_>) = ranked_entries(&*self)
+            .into_iter()
+            .partition(|(_, key, value, _)| keep(key, value));
+        if dropped.is_empty() {
+            return Vec::new();
+        }
+        self.drain().for_each(drop);
+        let mut removed: Vec<_> = dropped
+            .into_iter()
+            .map(|(_, key, value, tol)| (key, value, tol))
+            .collect();
+        removed.extend(insert_ranked(self, kept));
+        removed
+    }
+    /// Housekeeping kept off the lookup path: drops expired entries and ages frequency
+    /// sketches. Call it periodically, e.g. from a
+    /// [`MaintenanceThread`](crate::caching::MaintenanceThread). It leaves memory
+    /// alone, see [`compact`](Self::compact).
+    fn maintain(&mut self) {}
+    /// Releases the memory left over by evictions and removals, e.g. after heavy churn,
+    /// by shrinking internal tables to their current contents. Unlike
+    /// [`maintain`](Self::maintain), it never changes what the cache holds, but it may
+    /// reallocate every table, so call it rarely.
+    fn compact(&mut self) {}
+    /// Hit rate over the most recent lookups, see [`HitRateTracker`](crate::caching::HitRateTracker).
+    /// Caches that do not track lookups report 0, like one that was never queried.
+    fn recent_hit_rate(&self) -> f32 {
+        0.0
+    }
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
+    /// Estimated heap memory used by the cache, in bytes: its keys and values, the nodes
+    /// or slots holding them and any index over them. Allocator overhead is not counted.
+    ///
+    /// The default counts each entry in a slot of its own, caches override it to count
+    /// their actual layout.
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.iter().map(|(k, v, _)| entry_bytes(k, &v)).sum()
+    }
+    /// Copies every entry into an immutable [`CacheView`] that can be queried from
+    /// other threads while this cache keeps being updated.
+    fn snapshot(&self) -> CacheView<K, V>
+    where
+        K: Clone,
+    {
+        CacheView::new(
+            self.iter().map(|(k, v, tol)| (k.clone(), v, tol)),
+            self.key_dim(),
+        )
+    }
+    /// Folds every entry of `other` into this cache, e.g. to combine caches filled by
+    /// separate workers, and returns the entries that did not fit.
+    ///
+    /// Entries of both caches are inserted back by increasing priority, then least
+    /// recently used first, so that when both do not fit, this cache's policy evicts
+    /// the stalest ones and keeps those used last, whichever cache they come from.
+    /// Priorities are kept, but pins and hit counts start over.
+    ///
+    /// # Panics
+    /// If the keys of `other` are not of the dimension of this cache.
+    fn merge(&mut self, other: Self) -> Vec<(K, V, Tolerance)>
+    where
+        Self: Sized,
+        K: Clone,
+    {
+        let mut entries = ranked_entries(&*self);
+        entries.extend(ranked_entries(&other));
+        drop(other);
+        self.drain().for_each(drop);
+        insert_ranked(self, entries)
+    }
+}
+
+/// Rank of an entry when inserting entries back: its priority, then when it was last
+/// used. Entries of caches that do not track usage have none.
+type Rank = Option<(u32, Instant)>;
+
+/// Copies every entry of `cache` along with its rank.
+fn ranked_entries<K, V, C>(cache: &C) -> Vec<(Rank, K, V, Tolerance)>
+where
+    K: ApproxComparable + Clone,
+    C: ApproximateCache<K, V> + ?Sized,
+{
+    // in the order of `iter`, or empty
+    let mut infos = cache.entry_infos();
+    cache
+        .iter()
+        .map(|(key, value, tol)| {
+            let rank = infos
+                .next()
+                .map(|(_, info)| (info.priority, info.last_access));
+            (rank, key.clone(), value, tol)
+        })
+        .collect()
+}
+
+/// Inserts `entries` back into `cache` by increasing rank, so that its policy evicts
+/// the stalest ones first, and returns the entries that did not fit.
+fn insert_ranked<K, V, C>(
+    cache: &mut C,
+    mut entries: Vec<(Rank, K, V, Tolerance)>,
+) -> Vec<(K, V, Tolerance)>
+where
+    K: ApproxComparable,
+    C: ApproximateCache<K, V> + ?Sized,
+{
+    // stable, so that entries without usage metadata keep their order
+    entries.sort_by_key(|(rank, ..)| *rank);
+    entries
+        .into_iter()
+        .flat_map(|(rank, key, value, tol)| {
+            let priority = rank.map_or(0, |(priority, _)| priority);
+            cache.insert_with_priority(key, value, tol, priority)
+        })
+        .collect()
+}
+
+/// Lets a boxed cache, e.g. one picked at runtime, be wrapped like any other cache.
+impl<K, V, C> ApproximateCache<K, V> for Box<C>
+where
+    K: ApproxComparable,
+    C: ApproximateCache<K, V> + ?Sized,
+{
+    fn find(&mut self, target: &K) -> Option<V> {
+        (**self).find(target)
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        (**self).find_k(target, k)
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        (**self).insert_with_priority(key, value, tolerance, priority)
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn candidates(&self, target: &K) -> usize {
+        (**self).candidates(target)
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        (**self).key_dim()
+    }
+
+    fn default_tolerance(&self) -> Option<Tolerance> {
+        (**self).default_tolerance()
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        (**self).nearest(target)
+    }
+
+    fn next_victim(&self, incoming: &K) -> Option<&K> {
+        (**self).next_victim(incoming)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        (**self).pin(target)
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        (**self).unpin(target)
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        (**self).entry_info(target)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        (**self).entry_infos()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        (**self).iter()
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        (**self).drain()
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)>
+    where
+        K: Clone,
+    {
+        (**self).retain(keep)
+    }
+
+    fn maintain(&mut self) {
+        (**self).maintain();
+    }
+
+    fn compact(&mut self) {
+        (**self).compact();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        (**self).recent_hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        (**self).memory_bytes()
+    }
 }
 
 pub trait DefaultApproximateCache<K, V>: ApproximateCache<K, V>
@@ -19,4 +382,22 @@ where
     K: ApproxComparable,
 {
     fn from_capacity(cap: usize) -> Self;
+    fn capacity(&self) -> usize;
+    /// Changes the capacity, evicting entries until the cache fits in it, and returns
+    /// the evicted entries. Pinned entries are never evicted, so a cache may stay above
+    /// a smaller capacity until they are unpinned.
+    fn set_capacity(&mut self, cap: usize) -> Result<Vec<(K, V, Tolerance)>>;
+    /// Evicts the entry the policy would evict next, if any is unpinned.
+    fn evict(&mut self) -> Option<(K, V, Tolerance)>;
+    /// Evicts up to `k` entries in the order [`evict`](Self::evict) would, and returns
+    /// them. Caches that scan every entry to pick a victim override it to pick all of
+    /// them in a single pass.
+    fn evict_k(&mut self, k: usize) -> Vec<(K, V, Tolerance)> {
+        (0..k).map_while(|_| self.evict()).collect()
+    }
+    /// Counts a lookup of `target` known to miss without scanning for it, e.g. one whose
+    /// miss an [`LshCache`](crate::caching::LshCache) memoized. Policies that weigh keys
+    /// by how often they are looked up override it to count it as [`find`](ApproximateCache::find)
+    /// would.
+    fn count_miss(&mut self, _target: &K) {}
 }
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Slower tier behind a cache, e.g. Redis or files on disk, which a [`BackedCache`]
+/// falls through to on a miss and writes inserts to. Keys are looked up exactly as
+/// given, approximate matching is left to the cache.
+///
+/// Stores that are asynchronous can block on their futures, e.g. with a runtime handle,
+/// since the cache calls them from synchronous code.
+pub trait BackingStore<K, V> {
+    fn get(&mut self, key: &K) -> Result<Option<V>>;
+    fn put(&mut self, key: &K, value: &V) -> Result<()>;
+    /// Writes several entries, by default one at a time. Stores that can, e.g. in one
+    /// round trip, override it.
+    fn put_batch(&mut self, entries: &[(K, V)]) -> Result<()> {
+        entries
+            .iter()
+            .try_for_each(|(key, value)| self.put(key, value))
+    }
+}
+
+/// In-memory store, e.g. for tests or keys that hash exactly.
+impl<K, V> BackingStore<K, V> for HashMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn get(&mut self, key: &K) -> Result<Option<V>> {
+        Ok(HashMap::get(self, key).cloned())
+    }
+
+    fn put(&mut self, key: &K, value: &V) -> Result<()> {
+        self.insert(key.clone(), value.clone());
+        Ok(())
+    }
+}
+
+/// A cache in front of a [`BackingStore`]: lookups that miss fall through to the store
+/// and the value found there is inserted into the cache on the way back.
+///
+/// Inserts go to both. Write-through caches write to the store first and only insert
+/// into the cache once the store took the value. Write-back caches insert into the
+/// cache at once and buffer up to `max_pending` writes before handing them to the store
+/// in one [`put_batch`](BackingStore::put_batch), so call [`flush`](Self::flush) before
+/// dropping one, or the buffered writes are lost.
+///
+/// # Example Usage
+/// ```
+/// use std::collections::HashMap;
+/// use proximity::caching::{BackedCache, FifoCache};
+///
+/// let store = HashMap::from([(10 as i16, "Value 1")]);
+/// let mut cache = BackedCache::write_through(FifoCache::new(4).unwrap(), store, 2.0).unwrap();
+///
+/// assert_eq!(cache.get(&10).unwrap(), Some("Value 1"));
+/// // found in the cache this time
+/// assert_eq!(cache.get(&11).unwrap(), Some("Value 1"));
+/// cache.put(20, "Value 2", 2.0).unwrap();
+/// assert_eq!(cache.store().get(&20), Some(&"Value 2"));
+/// ```
+pub struct BackedCache<K, V, C, S> {
+    cache: C,
+    store: S,
+    /// Tolerance of the entries loaded from the store.
+    tolerance: Tolerance,
+    /// Writes buffered before reaching the store, if writing back.
+    max_pending: Option<usize>,
+    pending: Vec<(K, V)>,
+}
+
+impl<K, V, C, S> BackedCache<K, V, C, S> {
+    /// Fronts `store` with `cache`, writing every insert to the store before the cache.
+    /// Values loaded from the store are inserted with `tolerance`.
+    pub fn write_through(cache: C, store: S, tolerance: Tolerance) -> Result<Self> {
+        Self::with_policy(cache, store, tolerance, None)
+    }
+
+    /// Fronts `store` with `cache`, writing inserts to the store by batches of
+    /// `max_pending`. Values loaded from the store are inserted with `tolerance`.
+    pub fn write_back(
+        cache: C,
+        store: S,
+        tolerance: Tolerance,
+        max_pending: usize,
+    ) -> Result<Self> {
+        if max_pending == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "write-back batches must hold at least one write".into(),
+            ));
+        }
+        Self::with_policy(cache, store, tolerance, Some(max_pending))
+    }
+
+    fn with_policy(
+        cache: C,
+        store: S,
+        tolerance: Tolerance,
+        max_pending: Option<usize>,
+    ) -> Result<Self> {
+        if tolerance.is_nan() || tolerance < 0.0 {
+            return Err(ProximityError::InvalidArgument(format!(
+                "tolerance must be non-negative, got {tolerance}"
+            )));
+        }
+        Ok(Self {
+            cache,
+            store,
+            tolerance,
+            max_pending,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+
+    /// The cache, e.g. to pin entries. Entries inserted into it directly are not written
+    /// to the store.
+    pub fn cache_mut(&mut self) -> &mut C {
+        &mut self.cache
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Number of writes buffered until the next flush, always 0 when writing through.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl<K, V, C, S> BackedCache<K, V, C, S>
+where
+    K: ApproxComparable + Clone,
+    V: Clone,
+    C: ApproximateCache<K, V>,
+    S: BackingStore<K, V>,
+{
+    /// Looks `key` up in the cache, then in the store on a miss, inserting the value
+    /// found there into the cache. Buffered writes are flushed before the store is
+    /// read, so that it answers with the latest values.
+    pub fn get(&mut self, key: &K) -> Result<Option<V>> {
+        if let Some(value) = self.cache.find(key) {
+            return Ok(Some(value));
+        }
+        self.flush()?;
+        let value = self.store.get(key)?;
+        if let Some(value) = &value {
+            self.cache
+                .insert(key.clone(), value.clone(), self.tolerance);
+        }
+        Ok(value)
+    }
+
+    /// Inserts an entry into the cache and writes it to the store, at once when
+    /// writing through, or else once the batch is full. Fails if the store does, in
+    /// which case a write-through cache is left as it was.
+    pub fn put(&mut self, key: K, value: V, tolerance: Tolerance) -> Result<()> {
+        match self.max_pending {
+            None => {
+                self.store.put(&key, &value)?;
+                self.cache.insert(key, value, tolerance);
+                Ok(())
+            }
+            Some(max_pending) => {
+                self.pending.push((key.clone(), value.clone()));
+                self.cache.insert(key, value, tolerance);
+                if self.pending.len() >= max_pending {
+                    self.flush()?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the buffered writes to the store. They stay buffered if the store fails,
+    /// so that the flush can be retried.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.store.put_batch(&self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::LruCache;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    /// Counts batches, and fails every write while `down`.
+    #[derive(Default)]
+    struct FlakyStore {
+        entries: HashMap<i16, i16>,
+        batches: usize,
+        down: bool,
+    }
+
+    impl BackingStore<i16, i16> for FlakyStore {
+        fn get(&mut self, key: &i16) -> Result<Option<i16>> {
+            Ok(self.entries.get(key).copied())
+        }
+
+        fn put(&mut self, key: &i16, value: &i16) -> Result<()> {
+            if self.down {
+                return Err(std::io::Error::other("store is down").into());
+            }
+            self.entries.insert(*key, *value);
+            Ok(())
+        }
+
+        fn put_batch(&mut self, entries: &[(i16, i16)]) -> Result<()> {
+            self.batches += 1;
+            entries
+                .iter()
+                .try_for_each(|(key, value)| self.put(key, value))
+        }
+    }
+
+    #[test]
+    fn test_miss_falls_through_to_the_store() {
+        let store = HashMap::from([(1, 10), (2, 20)]);
+        let mut cache =
+            BackedCache::write_through(LruCache::new(1).unwrap(), store, TEST_TOLERANCE).unwrap();
+        assert_eq!(cache.get(&1).unwrap(), Some(10));
+        assert_eq!(cache.get(&2).unwrap(), Some(20));
+        // evicted from the cache, still in the store
+        assert_eq!(cache.cache().len(), 1);
+        assert_eq!(cache.get(&1).unwrap(), Some(10));
+        assert_eq!(cache.get(&3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_write_through_failure_leaves_the_cache() {
+        let mut cache = BackedCache::write_through(
+            LruCache::new(4).unwrap(),
+            FlakyStore::default(),
+            TEST_TOLERANCE,
+        )
+        .unwrap();
+        cache.put(1, 10, TEST_TOLERANCE).unwrap();
+        assert_eq!(cache.store().entries.get(&1), Some(&10));
+        cache.store.down = true;
+        assert!(cache.put(2, 20, TEST_TOLERANCE).is_err());
+        assert!(cache.cache_mut().find(&2).is_none());
+    }
+
+    #[test]
+    fn test_write_back_batches() {
+        let mut cache = BackedCache::write_back(
+            LruCache::new(4).unwrap(),
+            FlakyStore::default(),
+            TEST_TOLERANCE,
+            3,
+        )
+        .unwrap();
+        cache.put(1, 10, TEST_TOLERANCE).unwrap();
+        cache.put(2, 20, TEST_TOLERANCE).unwrap();
+        assert_eq!(cache.pending(), 2);
+        assert!(cache.store().entries.is_empty());
+        assert_eq!(cache.get(&1).unwrap(), Some(10));
+        cache.put(3, 30, TEST_TOLERANCE).unwrap();
+        assert_eq!((cache.pending(), cache.store().batches), (0, 1));
+        assert_eq!(cache.store().entries.len(), 3);
+
+        // a failed flush keeps the writes for the next one
+        cache.store.down = true;
+        cache.put(4, 40, TEST_TOLERANCE).unwrap();
+        assert!(cache.flush().is_err());
+        cache.store.down = false;
+        cache.flush().unwrap();
+        assert_eq!(cache.store().entries.get(&4), Some(&40));
+        assert!(BackedCache::<i16, i16, _, _>::write_back(
+            LruCache::<i16, i16>::new(4).unwrap(),
+            FlakyStore::default(),
+            TEST_TOLERANCE,
+            0
+        )
+        .is_err());
+    }
+}
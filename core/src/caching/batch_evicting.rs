@@ -0,0 +1,170 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Wraps a cache so that, once full, an insert evicts a batch of entries instead of a
+/// single one, e.g. 5% of the capacity, so that the inserts of a sustained storm, e.g.
+/// while warming up, evict once per batch instead of every time.
+///
+/// Entries are evicted in the order the cache's policy picks them, and pinned entries
+/// are never evicted. A newcomer the cache would refuse, e.g. one of a lower priority
+/// than every evictable entry, or one matching a stored key, does not evict a batch:
+/// the cache handles it as it would unwrapped.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, BatchEvictingCache, FifoCache};
+///
+/// let mut cache = BatchEvictingCache::new(FifoCache::new(4).unwrap(), 2).unwrap();
+/// for key in 0..4 {
+///     cache.insert(key as i16, key, 0.5);
+/// }
+/// let evicted = cache.insert_evicting(4, 4, 0.5);
+/// assert_eq!(evicted, [(0, 0, 0.5), (1, 1, 0.5)]);
+///
+/// // there is room left, so the next insert evicts nothing
+/// assert!(cache.insert_evicting(5, 5, 0.5).is_empty());
+/// ```
+pub struct BatchEvictingCache<C> {
+    inner: C,
+    batch: usize,
+}
+
+impl<C> BatchEvictingCache<C> {
+    /// Evicts `batch` entries at once when the cache is full.
+    pub fn new(inner: C, batch: usize) -> Result<Self> {
+        Ok(Self {
+            inner,
+            batch: checked_batch(batch)?,
+        })
+    }
+
+    /// Evicts a `fraction` of the capacity at once, rounded up, when the cache is full.
+    pub fn with_fraction<K, V>(inner: C, fraction: f32) -> Result<Self>
+    where
+        K: ApproxComparable,
+        C: DefaultApproximateCache<K, V>,
+    {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(ProximityError::InvalidArgument(format!(
+                "batch fraction must be in (0, 1], got {fraction}"
+            )));
+        }
+        let batch = (inner.capacity() as f32 * fraction).ceil() as usize;
+        Self::new(inner, batch.max(1))
+    }
+
+    /// Entries evicted at once when the cache is full.
+    pub fn batch(&self) -> usize {
+        self.batch
+    }
+
+    pub fn set_batch(&mut self, batch: usize) -> Result<()> {
+        self.batch = checked_batch(batch)?;
+        Ok(())
+    }
+
+    /// The wrapped cache.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+fn checked_batch(batch: usize) -> Result<usize> {
+    if batch == 0 {
+        return Err(ProximityError::InvalidArgument(
+            "eviction batch must be positive".into(),
+        ));
+    }
+    Ok(batch)
+}
+
+impl<K, V, C> ApproximateCache<K, V> for BatchEvictingCache<C>
+where
+    K: ApproxComparable,
+    C: DefaultApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        find, find_k, default_tolerance, len, candidates, key_dim, nearest, next_victim, pin,
+        unpin, entry_info, entry_infos, iter, drain, retain, maintain, compact, recent_hit_rate,
+        memory_bytes,
+    );
+
+    /// Evicts a batch first if the cache is full and would evict for the newcomer.
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        let mut evicted = Vec::new();
+        let evicts = self.inner.len() >= self.inner.capacity()
+            && self.inner.entry_info(&key).is_none()
+            && self
+                .inner
+                .next_victim(&key)
+                .and_then(|victim| self.inner.entry_info(victim))
+                .is_some_and(|victim| victim.priority <= priority);
+        if evicts {
+            evicted.extend(self.inner.evict_k(self.batch));
+        }
+        evicted.extend(
+            self.inner
+                .insert_with_priority(key, value, tolerance, priority),
+        );
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::LruCache;
+
+    #[test]
+    fn test_batch_evicted_once_full() {
+        let mut cache =
+            BatchEvictingCache::with_fraction(LruCache::new(20).unwrap(), 0.05).unwrap();
+        assert_eq!(cache.batch(), 1);
+        cache.set_batch(5).unwrap();
+        for key in 0..20i16 {
+            cache.insert(key, key, 0.1);
+        }
+        cache.find(&0);
+        cache.pin(&1);
+        let evicted: Vec<_> = cache.insert_evicting(20, 20, 0.1);
+        let keys: Vec<_> = evicted.iter().map(|(key, ..)| *key).collect();
+        assert_eq!(keys, [2, 3, 4, 5, 6]);
+        assert_eq!(cache.len(), 16);
+        for key in 21..25 {
+            assert!(cache.insert_evicting(key, key, 0.1).is_empty());
+        }
+        assert_eq!(cache.insert_evicting(25, 25, 0.1).len(), 5);
+
+        assert!(cache.set_batch(0).is_err());
+        assert!(
+            BatchEvictingCache::with_fraction(LruCache::<i16, i16>::new(2).unwrap(), 0.0).is_err()
+        );
+    }
+
+    #[test]
+    fn test_refused_and_stored_keys_evict_nothing() {
+        let mut cache = BatchEvictingCache::new(LruCache::new(3).unwrap(), 2).unwrap();
+        for key in 0..3i16 {
+            cache.insert_with_priority(key, key, 0.1, 1);
+        }
+        // the newcomer is of a lower priority than every entry, so it is refused
+        assert_eq!(cache.insert_evicting(10, 10, 0.1), [(10, 10, 0.1)]);
+        assert_eq!(cache.len(), 3);
+        // re-inserting a stored key replaces it in place
+        assert!(cache.insert_with_priority(1, 11, 0.1, 1).is_empty());
+        assert_eq!(cache.find(&1), Some(11));
+    }
+}
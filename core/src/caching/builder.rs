@@ -0,0 +1,367 @@
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::lru_k::DEFAULT_LRU_K;
+use crate::caching::sketch::{AdmissionFilter, CountMinSketch};
+use crate::caching::MaybeSync;
+use crate::caching::{
+    ClockCache, DefaultTolerance, FifoCache, FiniteKeys, GdsfCache, LfuCache, LruCache, LruKCache,
+    LshClockCache, LshFifoCache, LshGdsfCache, LshLfuCache, LshLruCache, LshLruKCache,
+    LshS3FifoCache, LshWTinyLfuCache, NonFinitePolicy, S3FifoCache, ScanResistantCache,
+    WTinyLfuCache,
+};
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Which entry a full cache (or LSH bucket) evicts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "config",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum EvictionPolicy {
+    /// Evicts the least recently used entry.
+    Lru,
+    /// Evicts the oldest entry.
+    Fifo,
+    /// Evicts the entry whose second most recent use is the oldest, see [`LruKCache`].
+    #[cfg_attr(feature = "config", serde(rename = "lru-k"))]
+    LruK,
+    /// Evicts the least frequently used entry, frequencies aging over time, see
+    /// [`LfuCache`].
+    Lfu,
+    /// Evicts the first entry not used since the clock hand last passed it, see
+    /// [`ClockCache`].
+    Clock,
+    /// Keeps newcomers in a small LRU window, then admits them to a segmented LRU only
+    /// if they are queried more often than its victim, see [`WTinyLfuCache`]. A good
+    /// choice when the workload is unknown.
+    #[default]
+    #[cfg_attr(feature = "config", serde(rename = "w-tinylfu"))]
+    WTinyLfu,
+    /// Evicts the entry of the lowest Greedy-Dual-Size-Frequency score, see
+    /// [`GdsfCache`]. Built caches insert at cost 1 and size 1.
+    Gdsf,
+    /// Keeps newcomers in a small FIFO queue, moving those looked up more than once to
+    /// the main FIFO queue, see [`S3FifoCache`]. Suits workloads where most keys are
+    /// looked up once.
+    #[cfg_attr(feature = "config", serde(rename = "s3-fifo", alias = "s3fifo"))]
+    S3Fifo,
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = ProximityError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lru" => Ok(EvictionPolicy::Lru),
+            "fifo" => Ok(EvictionPolicy::Fifo),
+            "lru-k" => Ok(EvictionPolicy::LruK),
+            "lfu" => Ok(EvictionPolicy::Lfu),
+            "clock" => Ok(EvictionPolicy::Clock),
+            "w-tinylfu" => Ok(EvictionPolicy::WTinyLfu),
+            "gdsf" => Ok(EvictionPolicy::Gdsf),
+            "s3-fifo" | "s3fifo" => Ok(EvictionPolicy::S3Fifo),
+            other => Err(ProximityError::InvalidArgument(format!(
+                "unknown eviction policy '{other}', expected lru, fifo, lru-k, lfu, clock, w-tinylfu, gdsf or s3-fifo"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EvictionPolicy::Lru => "lru",
+            EvictionPolicy::Fifo => "fifo",
+            EvictionPolicy::LruK => "lru-k",
+            EvictionPolicy::Lfu => "lfu",
+            EvictionPolicy::Clock => "clock",
+            EvictionPolicy::WTinyLfu => "w-tinylfu",
+            EvictionPolicy::Gdsf => "gdsf",
+            EvictionPolicy::S3Fifo => "s3-fifo",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct LshRouting {
+    num_hash: usize,
+    dim: usize,
+    seed: Option<u64>,
+}
+
+/// Configures and builds any of the caches of this crate behind a single
+/// `Box<dyn ApproximateCache>`, so that callers can pick one at runtime.
+///
+/// Only the capacity is required. Without LSH routing, the capacity bounds the
+/// whole cache. With it, it bounds each bucket.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, CacheBuilder, EvictionPolicy, NonFinitePolicy};
+/// use proximity::simulation::SimKey;
+///
+/// let mut cache = CacheBuilder::new()
+///     .policy(EvictionPolicy::Fifo)
+///     .capacity(64)
+///     .lsh(4, 8, Some(42))
+///     .non_finite(NonFinitePolicy::Reject)
+///     .build::<SimKey, &str>()
+///     .unwrap();
+/// cache.insert(SimKey(vec![1.0; 8]), "Value 1", 0.5);
+/// assert_eq!(cache.find(&SimKey(vec![1.0; 8])), Some("Value 1"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CacheBuilder {
+    policy: EvictionPolicy,
+    capacity: Option<usize>,
+    tolerance: Option<Tolerance>,
+    lsh: Option<LshRouting>,
+    admission: Option<CountMinSketch>,
+    doorkeeper: Option<usize>,
+    non_finite: Option<NonFinitePolicy>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<metrics::SharedString>,
+}
+
+impl CacheBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn policy(mut self, policy: EvictionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Tolerance of entries inserted with
+    /// [`insert_default`](ApproximateCache::insert_default), see [`DefaultTolerance`].
+    pub fn tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = Some(tolerance);
+        self
+    }
+
+    /// Routes keys of dimension `dim` into buckets by `num_hash` random hyperplanes,
+    /// see [`LshCache`](crate::caching::LshCache).
+    pub fn lsh(mut self, num_hash: usize, dim: usize, seed: Option<u64>) -> Self {
+        self.lsh = Some(LshRouting {
+            num_hash,
+            dim,
+            seed,
+        });
+        self
+    }
+
+    /// Admits newcomers by frequency, see [`AdmissionFilter`].
+    pub fn admission(mut self, sketch: CountMinSketch) -> Self {
+        self.admission = Some(sketch);
+        self
+    }
+
+    /// Keeps keys inserted for the first time from evicting entries, remembering up to
+    /// `doorkeeper_capacity` of them, see [`ScanResistantCache`].
+    pub fn scan_resistant(mut self, doorkeeper_capacity: usize) -> Self {
+        self.doorkeeper = Some(doorkeeper_capacity);
+        self
+    }
+
+    /// Enforces `policy` on keys with NaN or infinite components, see [`FiniteKeys`].
+    pub fn non_finite(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite = Some(policy);
+        self
+    }
+
+    /// Reports activity under the `cache` label `name`, see [`MetricsCache`](crate::caching::MetricsCache).
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, name: impl Into<metrics::SharedString>) -> Self {
+        self.metrics = Some(name.into());
+        self
+    }
+
+    pub fn build<K, V>(self) -> Result<Box<dyn ApproximateCache<K, V>>>
+    where
+        K: ApproxComparable + AsRef<[f32]> + Eq + Hash + Clone + MaybeSync + 'static,
+        V: Clone + MaybeSync + 'static,
+    {
+        let capacity = self
+            .capacity
+            .ok_or_else(|| ProximityError::InvalidArgument("capacity must be set".into()))?;
+        let mut cache: Box<dyn ApproximateCache<K, V>> = match (self.policy, self.lsh) {
+            (EvictionPolicy::Lru, None) => Box::new(LruCache::new(capacity)?),
+            (EvictionPolicy::Fifo, None) => Box::new(FifoCache::new(capacity)?),
+            (EvictionPolicy::LruK, None) => Box::new(LruKCache::new(capacity, DEFAULT_LRU_K)?),
+            (EvictionPolicy::Lfu, None) => Box::new(LfuCache::new(capacity)?),
+            (EvictionPolicy::Clock, None) => Box::new(ClockCache::new(capacity)?),
+            (EvictionPolicy::WTinyLfu, None) => Box::new(WTinyLfuCache::new(capacity)?),
+            (EvictionPolicy::Gdsf, None) => Box::new(GdsfCache::new(capacity)?),
+            (EvictionPolicy::S3Fifo, None) => Box::new(S3FifoCache::new(capacity)?),
+            (EvictionPolicy::Lru, Some(lsh)) => {
+                Box::new(LshLruCache::new(lsh.num_hash, lsh.dim, capacity, lsh.seed)?)
+            }
+            (EvictionPolicy::Fifo, Some(lsh)) => Box::new(LshFifoCache::new(
+                lsh.num_hash,
+                lsh.dim,
+                capacity,
+                lsh.seed,
+            )?),
+            (EvictionPolicy::LruK, Some(lsh)) => Box::new(LshLruKCache::new(
+                lsh.num_hash,
+                lsh.dim,
+                capacity,
+                lsh.seed,
+            )?),
+            (EvictionPolicy::Lfu, Some(lsh)) => {
+                Box::new(LshLfuCache::new(lsh.num_hash, lsh.dim, capacity, lsh.seed)?)
+            }
+            (EvictionPolicy::Clock, Some(lsh)) => Box::new(LshClockCache::new(
+                lsh.num_hash,
+                lsh.dim,
+                capacity,
+                lsh.seed,
+            )?),
+            (EvictionPolicy::WTinyLfu, Some(lsh)) => Box::new(LshWTinyLfuCache::new(
+                lsh.num_hash,
+                lsh.dim,
+                capacity,
+                lsh.seed,
+            )?),
+            (EvictionPolicy::Gdsf, Some(lsh)) => Box::new(LshGdsfCache::new(
+                lsh.num_hash,
+                lsh.dim,
+                capacity,
+                lsh.seed,
+            )?),
+            (EvictionPolicy::S3Fifo, Some(lsh)) => Box::new(LshS3FifoCache::new(
+                lsh.num_hash,
+                lsh.dim,
+                capacity,
+                lsh.seed,
+            )?),
+        };
+        if let Some(tolerance) = self.tolerance {
+            cache = Box::new(DefaultTolerance::new(cache, tolerance)?);
+        }
+        if let Some(doorkeeper_capacity) = self.doorkeeper {
+            cache = Box::new(ScanResistantCache::new(cache, doorkeeper_capacity)?);
+        }
+        if let Some(sketch) = self.admission {
+            cache = Box::new(AdmissionFilter::new(cache, sketch));
+        }
+        // outermost, so that refused keys never reach the admission sketch
+        if let Some(policy) = self.non_finite {
+            cache = Box::new(FiniteKeys::new(cache, policy));
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(name) = self.metrics {
+            cache = Box::new(crate::caching::MetricsCache::new(cache, name));
+        }
+        Ok(cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::numerics::SIMD_LANECOUNT;
+    use crate::simulation::SimKey;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    fn key(x: f32) -> SimKey {
+        SimKey(vec![x; SIMD_LANECOUNT])
+    }
+
+    #[test]
+    fn test_builder_eviction_policy() {
+        for (policy, survivor) in [
+            (EvictionPolicy::Lru, 1),
+            (EvictionPolicy::Fifo, 2),
+            (EvictionPolicy::LruK, 1),
+            (EvictionPolicy::Lfu, 1),
+            (EvictionPolicy::Clock, 1),
+            (EvictionPolicy::WTinyLfu, 1),
+            (EvictionPolicy::Gdsf, 1),
+            (EvictionPolicy::S3Fifo, 2),
+        ] {
+            let mut cache = CacheBuilder::new()
+                .policy(policy)
+                .capacity(2)
+                .build::<SimKey, i32>()
+                .unwrap();
+            cache.insert(key(1.0), 1, TEST_TOLERANCE);
+            cache.insert(key(2.0), 2, TEST_TOLERANCE);
+            cache.find(&key(1.0));
+            cache.insert(key(3.0), 3, TEST_TOLERANCE);
+            assert_eq!(cache.len(), 2);
+            assert_eq!(
+                cache.find(&key(survivor as f32)),
+                Some(survivor),
+                "{policy:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_builder_lsh_and_wrappers() {
+        let mut cache = CacheBuilder::new()
+            .capacity(1)
+            .lsh(4, SIMD_LANECOUNT, Some(7))
+            .tolerance(0.5)
+            .admission(CountMinSketch::new(64, 4))
+            .scan_resistant(16)
+            .non_finite(NonFinitePolicy::Reject)
+            .build::<SimKey, i32>()
+            .unwrap();
+        assert_eq!(cache.key_dim(), Some(SIMD_LANECOUNT));
+        assert_eq!(cache.default_tolerance(), Some(0.5));
+        cache.insert(key(f32::NAN), 0, TEST_TOLERANCE);
+        assert!(cache.is_empty());
+        cache.insert_default(key(1.0), 1).unwrap();
+        assert_eq!(cache.find(&key(1.1)), Some(1));
+    }
+
+    #[test]
+    fn test_builder_errors() {
+        let missing = CacheBuilder::new().build::<SimKey, i32>();
+        assert!(matches!(missing, Err(ProximityError::InvalidArgument(_))));
+        let bad_dim = CacheBuilder::new()
+            .capacity(4)
+            .lsh(4, 3, None)
+            .build::<SimKey, i32>();
+        assert!(matches!(bad_dim, Err(ProximityError::InvalidArgument(_))));
+        assert_eq!(
+            "fifo".parse::<EvictionPolicy>().unwrap(),
+            EvictionPolicy::Fifo
+        );
+        assert_eq!(
+            "s3fifo".parse::<EvictionPolicy>().unwrap(),
+            EvictionPolicy::S3Fifo
+        );
+        assert!("arc".parse::<EvictionPolicy>().is_err());
+        for policy in [
+            EvictionPolicy::Lru,
+            EvictionPolicy::Fifo,
+            EvictionPolicy::LruK,
+            EvictionPolicy::Lfu,
+            EvictionPolicy::Clock,
+            EvictionPolicy::WTinyLfu,
+            EvictionPolicy::Gdsf,
+            EvictionPolicy::S3Fifo,
+        ] {
+            assert_eq!(
+                policy.to_string().parse::<EvictionPolicy>().unwrap(),
+                policy
+            );
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::entry_bytes;
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Wraps a cache to bound the bytes of its entries, as estimated by [`HeapSize`], on top
+/// of its number of entries: inserts evict entries by the cache's policy until they fit.
+///
+/// Entries are measured once, when inserted, so that values whose size is costly to
+/// estimate, e.g. Python objects, are not measured again on every insert. A key that the
+/// cache replaces in place when re-inserted is counted twice until
+/// [`maintain`](ApproximateCache::maintain) measures every entry again.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, ByteBoundedCache, FifoCache};
+///
+/// let mut cache = ByteBoundedCache::new(FifoCache::new(16).unwrap(), 200).unwrap();
+/// cache.insert(1 as i16, vec![0u8; 64], 0.5);
+/// cache.insert(2, vec![0u8; 64], 0.5);
+/// let evicted = cache.insert_evicting(3, vec![0u8; 64], 0.5);
+///
+/// assert_eq!(evicted[0].0, 1);
+/// assert!(cache.bytes() <= 200);
+/// ```
+pub struct ByteBoundedCache<C> {
+    inner: C,
+    max_bytes: usize,
+    bytes: usize,
+}
+
+impl<C> ByteBoundedCache<C> {
+    /// Bounds an empty cache to `max_bytes`.
+    pub fn new(inner: C, max_bytes: usize) -> Result<Self> {
+        if max_bytes == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "byte budget must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            inner,
+            max_bytes,
+            bytes: 0,
+        })
+    }
+
+    /// Estimated bytes of the stored entries.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Changes the budget, evicting entries until the cache fits in it, and returns the
+    /// evicted entries.
+    pub fn set_max_bytes<K, V>(&mut self, max_bytes: usize) -> Result<Vec<(K, V, Tolerance)>>
+    where
+        K: ApproxComparable + HeapSize,
+        V: HeapSize,
+        C: DefaultApproximateCache<K, V>,
+    {
+        if max_bytes == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "byte budget must be positive".into(),
+            ));
+        }
+        self.max_bytes = max_bytes;
+        let mut evicted = Vec::new();
+        self.fit(&mut evicted);
+        Ok(evicted)
+    }
+
+    /// Evicts entries while over budget. Pinned entries are never evicted, so the cache
+    /// may stay over budget until they are unpinned.
+    fn fit<K, V>(&mut self, evicted: &mut Vec<(K, V, Tolerance)>)
+    where
+        K: ApproxComparable + HeapSize,
+        V: HeapSize,
+        C: DefaultApproximateCache<K, V>,
+    {
+        while self.bytes > self.max_bytes {
+            let Some(entry) = self.inner.evict() else {
+                break;
+            };
+            self.bytes = self.bytes.saturating_sub(entry_bytes(&entry.0, &entry.1));
+            evicted.push(entry);
+        }
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for ByteBoundedCache<C>
+where
+    K: ApproxComparable + HeapSize,
+    V: HeapSize,
+    C: DefaultApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        find, find_k, default_tolerance, len, candidates, key_dim, nearest, next_victim, pin,
+        unpin, entry_info, entry_infos, iter, compact, recent_hit_rate, memory_bytes,
+    );
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        // a newcomer that is not admitted comes back among the evicted entries
+        self.bytes += entry_bytes(&key, &value);
+        let mut evicted = self
+            .inner
+            .insert_with_priority(key, value, tolerance, priority);
+        for (key, value, _) in &evicted {
+            self.bytes = self.bytes.saturating_sub(entry_bytes(key, value));
+        }
+        self.fit(&mut evicted);
+        evicted
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        self.bytes = 0;
+        self.inner.drain()
+    }
+
+    /// Also measures every entry again, and evicts entries if that puts the cache over
+    /// budget.
+    fn maintain(&mut self) {
+        self.inner.maintain();
+        self.bytes = self
+            .inner
+            .iter()
+            .map(|(key, value, _)| entry_bytes(key, &value))
+            .sum();
+        self.fit(&mut Vec::new());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LruCache};
+
+    #[test]
+    fn test_budget_evicts_by_policy() {
+        let entry = entry_bytes(&0i16, &vec![0u8; 100]);
+        let budget = 3 * entry + 50;
+        let mut cache = ByteBoundedCache::new(FifoCache::new(16).unwrap(), budget).unwrap();
+        for i in 0..3 {
+            assert!(cache.insert_evicting(i, vec![0u8; 100], 0.5).is_empty());
+        }
+        // the oldest entry makes room for a larger one
+        let evicted = cache.insert_evicting(3, vec![0u8; 150], 0.5);
+        let evicted: Vec<i16> = evicted.into_iter().map(|(key, _, _)| key).collect();
+        assert_eq!(evicted, [0]);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.bytes(), budget);
+
+        let evicted = cache.set_max_bytes(2 * entry + 50).unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.set_max_bytes(0).is_err());
+
+        // a newcomer over the whole budget does not stay
+        let evicted = cache.insert_evicting(4, vec![0u8; 4 * entry], 0.5);
+        assert!(evicted.iter().any(|(key, _, _)| *key == 4));
+        assert!(cache.bytes() <= 2 * entry + 50);
+    }
+
+    #[test]
+    fn test_retain_inserts_the_others_back() {
+        let mut cache = ByteBoundedCache::new(LruCache::new(4).unwrap(), 1 << 20).unwrap();
+        cache.insert_with_priority(1i16, String::from("a"), 0.5, 1);
+        cache.insert(2, String::from("b"), 0.5);
+        cache.insert(3, String::from("c"), 0.5);
+        let used = cache.bytes();
+
+        let removed = cache.retain(&mut |key, _| *key != 2);
+        assert_eq!(removed, [(2, String::from("b"), 0.5)]);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.bytes() < used);
+        assert_eq!(cache.entry_info(&1).unwrap().priority, 1);
+        assert_eq!(cache.find(&3), Some(String::from("c")));
+    }
+
+    #[test]
+    fn test_maintain_measures_again() {
+        let mut cache = ByteBoundedCache::new(LruCache::new(16).unwrap(), 1 << 20).unwrap();
+        cache.insert(1i16, String::from("value"), 0.5);
+        // replaced in place, but counted twice
+        cache.insert(1, String::from("value"), 0.5);
+        let once = entry_bytes(&1i16, &String::from("value"));
+        assert_eq!(cache.bytes(), 2 * once);
+        cache.maintain();
+        assert_eq!(cache.bytes(), once);
+        cache.drain().for_each(drop);
+        assert_eq!(cache.bytes(), 0);
+    }
+}
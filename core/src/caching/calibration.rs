@@ -0,0 +1,193 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// A logistic curve from the fuzziness of a hit to the probability of accepting it,
+/// `1 / (1 + exp((fuzziness - midpoint) / temperature))`, e.g. fit on whether past hits
+/// were close enough to their recomputed value.
+///
+/// The probability is one half at `midpoint` and falls from near 1 to near 0 over a few
+/// `temperature`s around it, so that the lower the temperature, the closer to a hard
+/// threshold at `midpoint`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Calibration {
+    midpoint: f32,
+    temperature: f32,
+}
+
+impl Calibration {
+    pub fn new(midpoint: f32, temperature: f32) -> Result<Self> {
+        if !midpoint.is_finite() {
+            return Err(ProximityError::InvalidArgument(format!(
+                "calibration midpoint must be finite, got {midpoint}"
+            )));
+        }
+        if !(temperature.is_finite() && temperature > 0.0) {
+            return Err(ProximityError::InvalidArgument(format!(
+                "calibration temperature must be positive and finite, got {temperature}"
+            )));
+        }
+        Ok(Self {
+            midpoint,
+            temperature,
+        })
+    }
+
+    pub fn midpoint(&self) -> f32 {
+        self.midpoint
+    }
+
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+
+    /// Probability of accepting a hit this fuzzy.
+    pub fn acceptance(&self, fuzziness: f32) -> f32 {
+        1.0 / (1.0 + ((fuzziness - self.midpoint) / self.temperature).exp())
+    }
+}
+
+/// Wraps a cache to accept its hits at random, with the probability that a
+/// [`Calibration`] gives their fuzziness, and treat rejected hits as misses.
+///
+/// Under a hard tolerance, a query that sits right at the edge of a stored key is
+/// accepted or recomputed depending on noise. Calibrated, the borderline hits are
+/// accepted about as often as they were found good, and the hits well within tolerance
+/// almost always. Hits still need to be within the tolerance of their entry, and the
+/// inner cache counts rejected hits as hits.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, CalibratedCache, Calibration, LruCache};
+///
+/// let calibration = Calibration::new(1.0, 0.01).unwrap();
+/// let mut cache = CalibratedCache::new(LruCache::new(4).unwrap(), calibration, 7);
+/// cache.insert(10 as i16, "Value 1", 5.0);
+///
+/// assert_eq!(cache.find_calibrated(&10), Some("Value 1"));
+/// assert_eq!(cache.find_calibrated(&13), None);
+/// assert_eq!((cache.accepted(), cache.rejected()), (1, 1));
+/// ```
+pub struct CalibratedCache<C> {
+    inner: C,
+    calibration: Calibration,
+    rng: StdRng,
+    accepted: u64,
+    rejected: u64,
+}
+
+impl<C> CalibratedCache<C> {
+    /// Draws acceptances from a generator seeded with `seed`, so that runs repeat.
+    pub fn new(inner: C, calibration: Calibration, seed: u64) -> Self {
+        Self {
+            inner,
+            calibration,
+            rng: StdRng::seed_from_u64(seed),
+            accepted: 0,
+            rejected: 0,
+        }
+    }
+
+    /// The value of the nearest hit, if accepted.
+    pub fn find_calibrated<K, V>(&mut self, target: &K) -> Option<V>
+    where
+        K: ApproxComparable,
+        C: ApproximateCache<K, V>,
+    {
+        let (value, fuzziness) = self.inner.find_k(target, 1).into_iter().next()?;
+        if self.rng.random::<f32>() < self.calibration.acceptance(fuzziness) {
+            self.accepted += 1;
+            Some(value)
+        } else {
+            self.rejected += 1;
+            None
+        }
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    /// Swaps the curve, e.g. once refit on more hits.
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    /// Hits accepted so far.
+    pub fn accepted(&self) -> u64 {
+        self.accepted
+    }
+
+    /// Hits rejected so far, which the caller recomputed.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for CalibratedCache<C>
+where
+    K: ApproxComparable,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        insert_with_priority, default_tolerance, len, candidates, key_dim, nearest, next_victim,
+        pin, unpin, entry_info, entry_infos, iter, drain, retain, maintain, compact, recent_hit_rate,
+        memory_bytes,
+    );
+
+    /// Same as [`find_calibrated`](CalibratedCache::find_calibrated).
+    fn find(&mut self, target: &K) -> Option<V> {
+        self.find_calibrated(target)
+    }
+
+    /// Every match within tolerance, not calibrated, since their distances are returned.
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        self.inner.find_k(target, k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::FifoCache;
+
+    #[test]
+    fn test_acceptance_curve() {
+        let calibration = Calibration::new(0.5, 0.1).unwrap();
+        assert_eq!(calibration.acceptance(0.5), 0.5);
+        assert!(calibration.acceptance(0.0) > 0.99);
+        assert!(calibration.acceptance(1.0) < 0.01);
+        assert!(calibration.acceptance(0.4) > calibration.acceptance(0.6));
+
+        assert!(Calibration::new(0.5, 0.0).is_err());
+        assert!(Calibration::new(0.5, -1.0).is_err());
+        assert!(Calibration::new(f32::NAN, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_borderline_hits_accepted_at_their_probability() {
+        let calibration = Calibration::new(1.0, 0.2).unwrap();
+        let mut cache = CalibratedCache::new(FifoCache::new(4).unwrap(), calibration, 11);
+        cache.insert(0.0f32, 1, 2.0);
+        // at the midpoint, about half the hits go through
+        let accepted = (0..1000)
+            .filter(|_| cache.find_calibrated(&1.0).is_some())
+            .count();
+        assert!((400..600).contains(&accepted), "{accepted}");
+        assert_eq!(cache.accepted() + cache.rejected(), 1000);
+
+        // misses are not drawn for, and hits well within the midpoint barely rejected
+        assert_eq!(cache.find(&3.0), None);
+        assert_eq!(cache.accepted() + cache.rejected(), 1000);
+        let accepted = (0..100).filter(|_| cache.find(&0.0).is_some()).count();
+        assert!(accepted >= 98, "{accepted}");
+        assert_eq!(cache.find_k(&1.0, 1), vec![(1, 1.0)]);
+    }
+}
@@ -0,0 +1,429 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::slots_bytes;
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::numerics::topk::{self, TotalF32};
+use crate::numerics::ApproxComparable;
+use crate::numerics::VectorLike;
+use crate::numerics::SIMD_LANECOUNT;
+use crate::{ProximityError, Result};
+
+/// A key-value store that routes keys into fixed-size buckets by their nearest centroid,
+/// the centroids being learned online from the inserted keys, k-means style.
+///
+/// Unlike the random hyperplanes of an [`LshCache`](crate::caching::LshCache), the
+/// centroids follow the data: on clustered keys, e.g. embeddings, each bucket mostly
+/// holds keys of a single cluster, and few buckets stay empty.
+///
+/// The first `num_centroids` inserted keys seed one centroid each. Every later insert
+/// lands in the bucket of its nearest centroid, which then moves towards the key by
+/// the inverse of the number of keys it was assigned. Entries stay in the bucket they
+/// were inserted into as centroids move, so lookups probe the buckets of the `probes`
+/// nearest centroids, and answer from the one holding the closest match.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, CentroidCache, FifoCache};
+/// use proximity::simulation::SimKey;
+///
+/// let mut cache: CentroidCache<FifoCache<SimKey, u32>> = CentroidCache::new(2, 8, 4, 1).unwrap();
+/// cache.insert(SimKey(vec![1.0; 8]), 1, 0.5);
+/// cache.insert(SimKey(vec![-1.0; 8]), 2, 0.5);
+/// cache.insert(SimKey(vec![1.1; 8]), 3, 0.5);
+///
+/// assert_eq!(cache.centroids().len(), 2);
+/// assert_eq!(cache.find(&SimKey(vec![-1.05; 8])), Some(2));
+/// ```
+pub struct CentroidCache<C> {
+    dim: usize,
+    num_centroids: usize,
+    probes: usize,
+    centroids: Vec<Vec<f32>>,
+    /// Keys assigned to each centroid so far, which weigh its updates.
+    assigned: Vec<u64>,
+    /// The bucket of each centroid, by index.
+    buckets: Vec<C>,
+    bucket_capacity: usize,
+    hit_rate: HitRateTracker,
+}
+
+impl<C> CentroidCache<C> {
+    /// Builds an empty cache of up to `num_centroids` buckets of `bucket_capacity`
+    /// entries each, for keys of dimension `dim`, whose lookups probe the buckets of the
+    /// `probes` nearest centroids.
+    pub fn new(
+        num_centroids: usize,
+        dim: usize,
+        bucket_capacity: usize,
+        probes: usize,
+    ) -> Result<Self> {
+        if dim == 0 || !dim.is_multiple_of(SIMD_LANECOUNT) {
+            return Err(ProximityError::InvalidArgument(format!(
+                "dimension must be a positive multiple of {SIMD_LANECOUNT}, got {dim}"
+            )));
+        }
+        if num_centroids == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "number of centroids must be positive".into(),
+            ));
+        }
+        if bucket_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "bucket capacity must be positive".into(),
+            ));
+        }
+        let mut cache = Self {
+            dim,
+            num_centroids,
+            probes: 1,
+            centroids: Vec::with_capacity(num_centroids),
+            assigned: Vec::with_capacity(num_centroids),
+            buckets: Vec::with_capacity(num_centroids),
+            bucket_capacity,
+            hit_rate: HitRateTracker::default(),
+        };
+        cache.set_probes(probes)?;
+        Ok(cache)
+    }
+
+    /// The centroids learned so far, at most `num_centroids` of them.
+    pub fn centroids(&self) -> &[Vec<f32>] {
+        &self.centroids
+    }
+
+    pub fn num_centroids(&self) -> usize {
+        self.num_centroids
+    }
+
+    pub fn bucket_capacity(&self) -> usize {
+        self.bucket_capacity
+    }
+
+    /// Number of buckets a lookup probes.
+    pub fn probes(&self) -> usize {
+        self.probes
+    }
+
+    /// Makes lookups probe the buckets of the `probes` nearest centroids, between 1 and
+    /// the number of centroids. More probes find more of the entries inserted before
+    /// their centroid moved, at the cost of scanning more buckets.
+    pub fn set_probes(&mut self, probes: usize) -> Result<()> {
+        if probes == 0 || probes > self.num_centroids {
+            return Err(ProximityError::InvalidArgument(format!(
+                "probes must be between 1 and the number of centroids {}, got {probes}",
+                self.num_centroids
+            )));
+        }
+        self.probes = probes;
+        Ok(())
+    }
+
+    /// Indices of the `n` centroids nearest to `key`, nearest first.
+    ///
+    /// # Panics
+    /// If `key` is not of the cache dimension.
+    fn nearest_centroids(&self, key: &[f32], n: usize) -> Vec<usize> {
+        if key.len() != self.dim {
+            let err = ProximityError::DimensionMismatch {
+                expected: self.dim,
+                found: key.len(),
+            };
+            panic!("{err}");
+        }
+        let by_distance = self
+            .centroids
+            .iter()
+            .enumerate()
+            .map(|(idx, centroid)| (TotalF32(key.l2_dist_squared(centroid)), idx));
+        topk::smallest_k(by_distance, n)
+            .into_iter()
+            .map(|(_, idx)| idx)
+            .collect()
+    }
+
+    /// Indices of the buckets a lookup of `key` probes.
+    fn probed(&self, key: &[f32]) -> Vec<usize> {
+        self.nearest_centroids(key, self.probes)
+    }
+
+    /// Index of the bucket `key` is inserted into, if there is a centroid yet.
+    fn home(&self, key: &[f32]) -> Option<usize> {
+        self.nearest_centroids(key, 1).first().copied()
+    }
+
+    /// Moves the centroid `idx` towards `key`, which was just assigned to it.
+    fn learn(&mut self, idx: usize, key: &[f32]) {
+        self.assigned[idx] += 1;
+        let rate = 1.0 / self.assigned[idx] as f32;
+        for (c, x) in self.centroids[idx].iter_mut().zip(key) {
+            *c += (x - *c) * rate;
+        }
+    }
+
+    /// Index of the probed bucket holding the closest match of `target`, if any.
+    fn holder<K, V>(&self, target: &K) -> Option<usize>
+    where
+        K: ApproxComparable + AsRef<[f32]>,
+        C: ApproximateCache<K, V>,
+    {
+        self.probed(target.as_ref())
+            .into_iter()
+            .filter(|&idx| self.buckets[idx].entry_info(target).is_some())
+            .filter_map(|idx| Some((idx, self.buckets[idx].nearest(target)?.0)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(idx, _)| idx)
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for CentroidCache<C>
+where
+    V: Clone + 'static,
+    K: ApproxComparable + AsRef<[f32]> + 'static,
+    C: DefaultApproximateCache<K, V>,
+{
+    fn find(&mut self, target: &K) -> Option<V> {
+        trace_span!("find", cache = "centroid");
+        let found = self
+            .holder(target)
+            .and_then(|idx| self.buckets[idx].find(target));
+        self.hit_rate.record(found.is_some());
+        found
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "centroid", k);
+        let mut found: Vec<(V, f32)> = self
+            .probed(target.as_ref())
+            .into_iter()
+            .flat_map(|idx| self.buckets[idx].find_k(target, k))
+            .collect();
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found.truncate(k);
+        self.hit_rate.record(!found.is_empty());
+        found
+    }
+
+    /// Inserts into the bucket of the nearest centroid, which moves towards `key`, or
+    /// into a bucket of its own while there are fewer centroids than `num_centroids`.
+    /// Priorities only weigh against the entries of the same bucket.
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tol: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "centroid", tolerance = tol, priority);
+        let home = self.home(key.as_ref());
+        let idx = match home {
+            Some(idx) if self.centroids.len() == self.num_centroids => {
+                self.learn(idx, key.as_ref());
+                idx
+            }
+            _ => {
+                self.centroids.push(key.as_ref().to_vec());
+                self.assigned.push(1);
+                self.buckets.push(C::from_capacity(self.bucket_capacity));
+                self.centroids.len() - 1
+            }
+        };
+        trace_event!(centroid = idx, "routed");
+        self.buckets[idx].insert_with_priority(key, value, tol, priority)
+    }
+
+    /// Every probed bucket is scanned.
+    fn candidates(&self, target: &K) -> usize {
+        self.probed(target.as_ref())
+            .into_iter()
+            .map(|idx| self.buckets[idx].len())
+            .sum()
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        Some(self.dim)
+    }
+
+    fn len(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.len()).sum()
+    }
+
+    /// Only the probed buckets are considered.
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        self.probed(target.as_ref())
+            .into_iter()
+            .filter_map(|idx| self.buckets[idx].nearest(target))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    fn next_victim(&self, incoming: &K) -> Option<&K> {
+        let idx = self.home(incoming.as_ref())?;
+        if self.centroids.len() < self.num_centroids {
+            return None;
+        }
+        self.buckets[idx].next_victim(incoming)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        self.holder(target)
+            .is_some_and(|idx| self.buckets[idx].pin(target))
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        self.holder(target)
+            .is_some_and(|idx| self.buckets[idx].unpin(target))
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        self.buckets[self.holder(target)?].entry_info(target)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(self.buckets.iter().flat_map(|bucket| bucket.entry_infos()))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(self.buckets.iter().flat_map(|bucket| bucket.iter()))
+    }
+
+    /// Drains every bucket, the centroids are kept.
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        let drained: Vec<(K, V, Tolerance)> = self
+            .buckets
+            .iter_mut()
+            .flat_map(|bucket| bucket.drain().collect::<Vec<_>>())
+            .collect();
+        Box::new(drained.into_iter())
+    }
+
+    fn maintain(&mut self) {
+        self.buckets.iter_mut().for_each(|bucket| bucket.maintain());
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)>
+    where
+        K: Clone,
+    {
+        self.buckets
+            .iter_mut()
+            .flat_map(|bucket| bucket.retain(&mut *keep))
+            .collect()
+    }
+
+    fn compact(&mut self) {
+        self.buckets.iter_mut().for_each(|bucket| bucket.compact());
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let buckets: usize = self.buckets.iter().map(|b| b.memory_bytes()).sum();
+        self.centroids.heap_bytes()
+            + slots_bytes::<u64>(self.assigned.capacity())
+            + slots_bytes::<C>(self.buckets.capacity())
+            + buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::FifoCache;
+    use crate::simulation::SimKey;
+
+    const DIM: usize = 8;
+    const TOL: f32 = 0.5;
+
+    type Cache = CentroidCache<FifoCache<SimKey, i32>>;
+
+    /// A key of the cluster around `center` in the first dimension, `offset` away.
+    fn key(center: f32, offset: f32) -> SimKey {
+        let mut key = vec![0.0; DIM];
+        key[0] = center + offset;
+        key[1] = offset;
+        SimKey(key)
+    }
+
+    #[test]
+    fn test_centroids_follow_clusters() {
+        let mut cache = Cache::new(2, DIM, 16, 1).unwrap();
+        for i in 0..8 {
+            let offset = (i as f32 - 3.5) / 10.0;
+            cache.insert(key(-10.0, offset), i, TOL);
+            cache.insert(key(10.0, offset), 100 + i, TOL);
+        }
+        assert_eq!(cache.len(), 16);
+        let mut centers: Vec<f32> = cache.centroids().iter().map(|c| c[0]).collect();
+        centers.sort_by(f32::total_cmp);
+        assert!((centers[0] + 10.0).abs() < 0.5, "{centers:?}");
+        assert!((centers[1] - 10.0).abs() < 0.5, "{centers:?}");
+        // every bucket holds a single cluster
+        for bucket in &cache.buckets {
+            let clusters: Vec<bool> = bucket.iter().map(|(k, _, _)| k.0[0] > 0.0).collect();
+            assert!(clusters.iter().all(|&c| c == clusters[0]));
+        }
+        for i in 0..8 {
+            let offset = (i as f32 - 3.5) / 10.0;
+            assert_eq!(cache.find(&key(-10.0, offset)), Some(i));
+            assert_eq!(cache.find(&key(10.0, offset)), Some(100 + i));
+        }
+        assert_eq!(cache.candidates(&key(10.0, 0.0)), 8);
+    }
+
+    #[test]
+    fn test_probes_find_entries_left_behind() {
+        let mut cache = Cache::new(2, DIM, 16, 1).unwrap();
+        cache.insert(key(0.0, 0.0), 1, TOL);
+        cache.insert(key(4.0, 0.0), 2, TOL);
+        // the second centroid moves away from the entry it was seeded with
+        for _ in 0..8 {
+            cache.insert(key(10.0, 0.0), 3, TOL);
+        }
+        assert!(cache.centroids()[1][0] > 8.0);
+        // key 2 is now closer to the first centroid than to its own
+        assert_eq!(cache.find(&key(4.0, 0.0)), None);
+        cache.set_probes(2).unwrap();
+        assert_eq!(cache.find(&key(4.0, 0.0)), Some(2));
+        assert!(cache.pin(&key(4.0, 0.0)));
+        assert!(cache.entry_info(&key(4.0, 0.0)).is_some());
+    }
+
+    #[test]
+    fn test_bucket_eviction_and_drain() {
+        let mut cache = Cache::new(1, DIM, 2, 1).unwrap();
+        cache.insert(key(0.0, 0.0), 1, TOL);
+        cache.insert(key(1.0, 0.0), 2, TOL);
+        assert_eq!(cache.next_victim(&key(2.0, 0.0)), Some(&key(0.0, 0.0)));
+        let evicted = cache.insert_evicting(key(2.0, 0.0), 3, TOL);
+        assert_eq!(evicted, vec![(key(0.0, 0.0), 1, TOL)]);
+        let drained: Vec<i32> = cache.drain().map(|(_, v, _)| v).collect();
+        assert_eq!(drained, vec![2, 3]);
+        assert!(cache.is_empty());
+        assert_eq!(cache.centroids().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(Cache::new(0, DIM, 2, 1).is_err());
+        assert!(Cache::new(2, 3, 2, 1).is_err());
+        assert!(Cache::new(2, DIM, 0, 1).is_err());
+        assert!(Cache::new(2, DIM, 2, 0).is_err());
+        assert!(Cache::new(2, DIM, 2, 3).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_find_with_wrong_dimension() {
+        let mut cache = Cache::new(2, DIM, 2, 1).unwrap();
+        cache.insert(key(0.0, 0.0), 1, TOL);
+        cache.find(&SimKey(vec![0.0; 2 * DIM]));
+    }
+}
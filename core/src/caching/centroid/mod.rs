@@ -0,0 +1,2 @@
+mod centroid_cache;
+pub use centroid_cache::CentroidCache;
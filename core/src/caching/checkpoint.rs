@@ -0,0 +1,252 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use npyz::{AutoSerialize, Deserialize};
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::NpzPersistence;
+use crate::clock::Instant;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Wraps a cache to save it to a `.npz` archive, in the format of
+/// [`NpzPersistence`], every so many inserts or every so often, so that a worker that
+/// crashed restarts warm from the last checkpoint.
+///
+/// Checkpoints are written next to the archive and renamed over it, so a crash while
+/// writing one leaves the previous checkpoint intact. They are taken after inserts and
+/// on [`maintain`](ApproximateCache::maintain), so pair a cache checkpointing by time
+/// with a [`MaintenanceThread`](crate::caching::MaintenanceThread) if it may sit idle.
+/// Inserts cannot fail, so a failed checkpoint is kept for
+/// [`take_error`](Self::take_error) and retried on the next insert.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, Checkpointed, FifoCache};
+/// use proximity::simulation::SimKey;
+///
+/// let path = std::env::temp_dir().join("proximity_doc_checkpoint.npz");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut cache = Checkpointed::open(FifoCache::new(4).unwrap(), &path)
+///     .unwrap()
+///     .every_inserts(2)
+///     .unwrap();
+/// cache.insert(SimKey(vec![1.0; 8]), 1.0f32, 1.0);
+/// cache.insert(SimKey(vec![2.0; 8]), 2.0f32, 1.0);
+///
+/// // after a crash
+/// let mut restarted = Checkpointed::open(FifoCache::new(4).unwrap(), &path).unwrap();
+/// assert_eq!(restarted.find(&SimKey(vec![2.1; 8])), Some(2.0f32));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct Checkpointed<C> {
+    inner: C,
+    path: PathBuf,
+    every_inserts: Option<usize>,
+    every: Option<Duration>,
+    /// Inserts since the last checkpoint.
+    inserts: usize,
+    last_checkpoint: Instant,
+    error: Option<ProximityError>,
+}
+
+impl<C> Checkpointed<C> {
+    /// Checkpoints only when asked to, until [`every_inserts`](Self::every_inserts)
+    /// or [`every`](Self::every) is set.
+    pub fn new(inner: C, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: path.into(),
+            every_inserts: None,
+            every: None,
+            inserts: 0,
+            last_checkpoint: Instant::now(),
+            error: None,
+        }
+    }
+
+    /// Checkpoints after every `inserts` inserts.
+    pub fn every_inserts(mut self, inserts: usize) -> Result<Self> {
+        if inserts == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "checkpoints must be at least one insert apart".into(),
+            ));
+        }
+        self.every_inserts = Some(inserts);
+        Ok(self)
+    }
+
+    /// Checkpoints once `interval` has passed since the last checkpoint, if anything
+    /// was inserted since.
+    pub fn every(mut self, interval: Duration) -> Self {
+        self.every = Some(interval);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The error of the last automatic checkpoint if it failed, clearing it.
+    pub fn take_error(&mut self) -> Option<ProximityError> {
+        self.error.take()
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn due(&self) -> bool {
+        self.inserts > 0
+            && (self.every_inserts.is_some_and(|n| self.inserts >= n)
+                || self
+                    .every
+                    .is_some_and(|t| self.last_checkpoint.elapsed() >= t))
+    }
+
+    /// Restores `inner` from the checkpoint at `path` if there is one, and checkpoints
+    /// to it from then on.
+    pub fn open<K, V>(mut inner: C, path: impl Into<PathBuf>) -> Result<Self>
+    where
+        K: ApproxComparable + AsRef<[f32]> + From<Vec<f32>>,
+        V: AutoSerialize + Deserialize,
+        C: ApproximateCache<K, V>,
+    {
+        let path = path.into();
+        if path.exists() {
+            inner.import_npz(&path)?;
+        }
+        Ok(Self::new(inner, path))
+    }
+
+    /// Saves the cache now, whatever the policy.
+    pub fn checkpoint<K, V>(&mut self) -> Result<()>
+    where
+        K: ApproxComparable + AsRef<[f32]> + From<Vec<f32>>,
+        V: AutoSerialize + Deserialize,
+        C: ApproximateCache<K, V>,
+    {
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        self.inner.export_npz(&partial)?;
+        std::fs::rename(&partial, &self.path)?;
+        self.inserts = 0;
+        self.last_checkpoint = Instant::now();
+        Ok(())
+    }
+
+    fn checkpoint_if_due<K, V>(&mut self)
+    where
+        K: ApproxComparable + AsRef<[f32]> + From<Vec<f32>>,
+        V: AutoSerialize + Deserialize,
+        C: ApproximateCache<K, V>,
+    {
+        if self.due() {
+            if let Err(e) = self.checkpoint() {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for Checkpointed<C>
+where
+    K: ApproxComparable + AsRef<[f32]> + From<Vec<f32>>,
+    V: AutoSerialize + Deserialize,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        find, find_k, default_tolerance, len, candidates, key_dim, nearest, next_victim, pin,
+        unpin, entry_info, entry_infos, iter, drain, retain, compact, recent_hit_rate, memory_bytes,
+    );
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        let evicted = self
+            .inner
+            .insert_with_priority(key, value, tolerance, priority);
+        self.inserts += 1;
+        self.checkpoint_if_due();
+        evicted
+    }
+
+    fn maintain(&mut self) {
+        self.inner.maintain();
+        self.checkpoint_if_due();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LruCache};
+    use crate::simulation::SimKey;
+
+    fn key(i: usize) -> SimKey {
+        SimKey(vec![i as f32; 8])
+    }
+
+    #[test]
+    fn test_checkpoint_every_inserts() {
+        let path = std::env::temp_dir().join("proximity_test_checkpoint_inserts.npz");
+        let _ = std::fs::remove_file(&path);
+        let mut cache = Checkpointed::open(LruCache::new(8).unwrap(), &path)
+            .unwrap()
+            .every_inserts(3)
+            .unwrap();
+        for i in 0..5 {
+            cache.insert(key(i), i as f32, 0.5);
+        }
+        assert!(cache.take_error().is_none());
+
+        // only the first three made it into the checkpoint
+        let restored: Checkpointed<FifoCache<SimKey, f32>> =
+            Checkpointed::open(FifoCache::new(8).unwrap(), &path).unwrap();
+        assert_eq!(restored.len(), 3);
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            Checkpointed::new(LruCache::<SimKey, f32>::new(8).unwrap(), &path)
+                .every_inserts(0)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_every_interval() {
+        let path = std::env::temp_dir().join("proximity_test_checkpoint_interval.npz");
+        let _ = std::fs::remove_file(&path);
+        let mut cache =
+            Checkpointed::new(FifoCache::new(8).unwrap(), &path).every(Duration::from_millis(5));
+        cache.insert(key(1), 1.0f32, 0.5);
+        assert!(!path.exists());
+        std::thread::sleep(Duration::from_millis(10));
+        cache.maintain();
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_checkpoint_is_reported() {
+        let path = std::env::temp_dir()
+            .join("proximity_test_checkpoint_missing_dir")
+            .join("cache.npz");
+        let mut cache = Checkpointed::new(FifoCache::new(8).unwrap(), &path)
+            .every_inserts(1)
+            .unwrap();
+        cache.insert(key(1), 1.0f32, 0.5);
+        assert!(matches!(cache.take_error(), Some(ProximityError::Io(_))));
+        assert!(cache.take_error().is_none());
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,496 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::slots_bytes;
+use crate::caching::priority::PriorityCounts;
+use crate::caching::scan::MaybeSync;
+use crate::caching::slots::{self, Slot};
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+struct CacheLine<K, V> {
+    key: K,
+    tol: Tolerance,
+    value: V,
+    pinned: bool,
+    info: EntryInfo,
+    /// Set by hits, cleared when the hand passes, which gives the entry a second chance.
+    referenced: bool,
+}
+
+impl<K, V> Slot<K> for CacheLine<K, V> {
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn tolerance(&self) -> Tolerance {
+        self.tol
+    }
+
+    fn pinned(&self) -> bool {
+        self.pinned
+    }
+
+    fn pinned_mut(&mut self) -> &mut bool {
+        &mut self.pinned
+    }
+}
+
+impl<K, V> CacheLine<K, V> {
+    /// Whether the hand may take the entry, given the lowest priority of the unpinned
+    /// entries.
+    fn evictable(&self, floor: u32) -> bool {
+        !self.pinned && self.info.priority == floor
+    }
+}
+
+/// `ClockCache` is a bounded cache with approximate key matching support and CLOCK
+/// (second-chance) eviction, an approximation of LRU.
+///
+/// Entries sit in a ring swept by a hand. A hit only sets the reference bit of the
+/// entry, and the hand evicts the first entry whose bit is clear, clearing the bits
+/// it passes. Unlike [`LruCache`](crate::caching::LruCache), there is no list or map
+/// to keep in order, which suits small caches such as LSH buckets.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, ClockCache};
+///
+/// let mut cache = ClockCache::new(2).unwrap();
+/// const TEST_TOL: f32 = 2.0;
+///
+/// cache.insert(10 as i16, "Value 1", TEST_TOL);
+/// cache.insert(20, "Value 2", TEST_TOL);
+/// cache.find(&10); // Key 10 gets a second chance
+/// cache.insert(30, "Value 3", TEST_TOL); // Evicts key 20
+///
+/// assert_eq!(cache.find(&11), Some("Value 1"));
+/// assert!(cache.find(&20).is_none());
+/// ```
+pub struct ClockCache<K, V> {
+    max_capacity: usize,
+    items: Vec<CacheLine<K, V>>,
+    /// Position of the next entry to consider for eviction.
+    hand: usize,
+    hit_rate: HitRateTracker,
+    /// Priorities of the unpinned entries, the ones the hand may take.
+    unpinned: PriorityCounts,
+    /// Dimension of the first vector key inserted.
+    dim: Option<usize>,
+}
+
+impl<K, V> ApproximateCache<K, V> for ClockCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn find(&mut self, target: &K) -> Option<V> {
+        trace_span!("find", cache = "clock");
+        let idx = slots::find(&self.items, self.dim, target, &mut self.hit_rate)?;
+        let entry = &mut self.items[idx];
+        entry.referenced = true;
+        entry.info.record_hit();
+        Some(entry.value.clone())
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "clock", k);
+        let matches = slots::find_k(&self.items, self.dim, target, k, &mut self.hit_rate);
+        matches
+            .into_iter()
+            .map(|(idx, dist)| {
+                let entry = &mut self.items[idx];
+                entry.referenced = true;
+                entry.info.record_hit();
+                (entry.value.clone(), dist)
+            })
+            .collect()
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "clock", tolerance, priority);
+        self.check_dim(&key).unwrap_or_else(|e| panic!("{e}"));
+        self.dim = self.dim.or(key.dimension());
+        // a newcomer of a lower priority than every evictable entry goes first
+        let full = self.items.len() >= self.max_capacity;
+        if full && self.unpinned.floor().is_some_and(|floor| floor > priority) {
+            return vec![(key, value, tolerance)];
+        }
+        let mut evicted = self.evict_down_to(self.max_capacity - 1);
+        if self.items.len() >= self.max_capacity {
+            evicted.push((key, value, tolerance));
+            return evicted;
+        }
+        // behind the hand, so that the newcomer is considered last
+        self.items.insert(
+            self.hand,
+            CacheLine {
+                key,
+                tol: tolerance,
+                value,
+                pinned: false,
+                info: EntryInfo::with_priority(priority),
+                referenced: false,
+            },
+        );
+        self.unpinned.add(priority);
+        self.hand = (self.hand + 1) % self.items.len();
+        evicted
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        slots::nearest(&self.items, self.dim, target)
+    }
+
+    fn next_victim(&self, _incoming: &K) -> Option<&K> {
+        if self.items.len() < self.max_capacity {
+            return None;
+        }
+        // the first unreferenced entry after the hand, or once the hand has cleared
+        // every bit, the first evictable one
+        let floor = self.unpinned.floor()?;
+        let mut sweep = self.sweep().filter(|entry| entry.evictable(floor));
+        let first = sweep.clone().next()?;
+        Some(&sweep.find(|entry| !entry.referenced).unwrap_or(first).key)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        self.set_pinned(target, true)
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        self.set_pinned(target, false)
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let (candidate, _) = slots::best_match(&self.items, self.dim, target)?;
+        Some(self.items[candidate].info)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(self.sweep().map(|entry| (&entry.key, entry.info)))
+    }
+
+    /// Entries in the order the hand reaches them.
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(
+            self.sweep()
+                .map(|entry| (&entry.key, entry.value.clone(), entry.tol)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        self.items.rotate_left(self.hand);
+        self.hand = 0;
+        // the hand takes unreferenced entries on its first pass, the others on its second
+        self.items
+            .sort_by_key(|entry| (entry.info.priority, entry.referenced));
+        self.unpinned.clear();
+        Box::new(
+            self.items
+                .drain(..)
+                .map(|entry| (entry.key, entry.value, entry.tol)),
+        )
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)> {
+        let (hand, mut position, mut passed) = (self.hand, 0, 0);
+        let removed: Vec<_> = self
+            .items
+            .extract_if(.., |entry| {
+                let remove = !keep(&entry.key, &entry.value);
+                if remove {
+                    passed += usize::from(position < hand);
+                    if !entry.pinned {
+                        self.unpinned.remove(entry.info.priority);
+                    }
+                }
+                position += 1;
+                remove
+            })
+            .map(|entry| (entry.key, entry.value, entry.tol))
+            .collect();
+        // the hand keeps pointing to the same entry, or the next one kept
+        self.hand -= passed;
+        if self.hand >= self.items.len() {
+            self.hand = 0;
+        }
+        removed
+    }
+
+    fn compact(&mut self) {
+        self.items.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let entries: usize = self
+            .items
+            .iter()
+            .map(|line| line.key.heap_bytes() + line.value.heap_bytes())
+            .sum();
+        slots_bytes::<CacheLine<K, V>>(self.items.capacity()) + entries
+    }
+}
+
+impl<K, V> DefaultApproximateCache<K, V> for ClockCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn from_capacity(cap: usize) -> ClockCache<K, V> {
+        ClockCache::new(cap).expect("bucket capacity is checked by the owning cache")
+    }
+
+    fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    fn set_capacity(&mut self, cap: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        ClockCache::set_capacity(self, cap)
+    }
+
+    fn evict(&mut self) -> Option<(K, V, Tolerance)> {
+        self.evict_next()
+    }
+}
+
+impl<K, V> ClockCache<K, V> {
+    pub fn new(max_capacity: usize) -> Result<Self> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            max_capacity,
+            items: Vec::with_capacity(max_capacity),
+            hand: 0,
+            hit_rate: HitRateTracker::default(),
+            unpinned: PriorityCounts::default(),
+            dim: None,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    /// Changes the capacity, sweeping the hand to evict unpinned entries until the cache
+    /// fits in it, and returns them. Pinned entries stay, even above the new capacity.
+    pub fn set_capacity(&mut self, max_capacity: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        self.max_capacity = max_capacity;
+        Ok(self.evict_down_to(max_capacity))
+    }
+
+    /// Entries from the hand around the ring.
+    fn sweep(&self) -> impl Clone + Iterator<Item = &CacheLine<K, V>> {
+        let (before, after) = self.items.split_at(self.hand);
+        after.iter().chain(before)
+    }
+
+    /// Evicts unpinned entries while the cache holds more than `len` of them.
+    fn evict_down_to(&mut self, len: usize) -> Vec<(K, V, Tolerance)> {
+        let mut evicted = Vec::new();
+        while self.items.len() > len {
+            match self.evict_next() {
+                Some(entry) => evicted.push(entry),
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    /// Moves the hand to the next unreferenced, evictable entry, clearing the bits of
+    /// the evictable entries it passes, and evicts that entry. The hand then points to
+    /// the entry after it.
+    fn evict_next(&mut self) -> Option<(K, V, Tolerance)> {
+        let floor = self.unpinned.floor()?;
+        loop {
+            let entry = &mut self.items[self.hand];
+            if entry.evictable(floor) {
+                if !entry.referenced {
+                    break;
+                }
+                entry.referenced = false;
+            }
+            self.hand = (self.hand + 1) % self.items.len();
+        }
+        let entry = self.items.remove(self.hand);
+        self.unpinned.remove(floor);
+        if self.hand == self.items.len() {
+            self.hand = 0;
+        }
+        trace_event!(
+            position = self.hand,
+            tolerance = entry.tol,
+            hits = entry.info.hits,
+            "evicted"
+        );
+        Some((entry.key, entry.value, entry.tol))
+    }
+}
+
+impl<K, V> ClockCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn set_pinned(&mut self, target: &K, pinned: bool) -> bool {
+        let Some((idx, changed)) = slots::set_pinned(&mut self.items, self.dim, target, pinned)
+        else {
+            return false;
+        };
+        if changed {
+            let priority = self.items[idx].info.priority;
+            match pinned {
+                true => self.unpinned.remove(priority),
+                false => self.unpinned.add(priority),
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_clock_second_chance() {
+        let mut cache = ClockCache::new(3).unwrap();
+        for i in 1..=3 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        cache.find(&1);
+        cache.find(&2);
+        // the hand spares 1 and 2, clearing their bits, and takes 3
+        assert_eq!(cache.next_victim(&4), Some(&3));
+        assert_eq!(
+            cache.insert_evicting(4, 4, TEST_TOLERANCE),
+            vec![(3, 3, TEST_TOLERANCE)]
+        );
+        // then 1 and 2 have lost their second chance
+        assert_eq!(
+            cache.insert_evicting(5, 5, TEST_TOLERANCE),
+            vec![(1, 1, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.find(&4), Some(4));
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_clock_without_hits_is_fifo() {
+        let mut cache = ClockCache::new(2).unwrap();
+        let mut evicted = Vec::new();
+        for i in 1..=5 {
+            evicted.extend(cache.insert_evicting(i, i, TEST_TOLERANCE));
+        }
+        let evicted: Vec<_> = evicted.into_iter().map(|(k, _, _)| k).collect();
+        assert_eq!(evicted, vec![1, 2, 3]);
+        let order: Vec<_> = cache.iter().map(|(k, _, _)| *k).collect();
+        assert_eq!(order, vec![4, 5]);
+    }
+
+    #[test]
+    fn test_clock_all_referenced() {
+        let mut cache = ClockCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        cache.find(&1);
+        cache.find(&2);
+        // a full sweep clears every bit, the hand then takes the entry it started at
+        assert_eq!(cache.next_victim(&3), Some(&1));
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(1, 1, TEST_TOLERANCE)]
+        );
+    }
+
+    #[test]
+    fn test_clock_pinned_and_capacity() {
+        let mut cache = ClockCache::new(3).unwrap();
+        for i in 1..=3 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        cache.pin(&1);
+        cache.find(&2);
+        assert_eq!(
+            cache.set_capacity(1).unwrap(),
+            vec![(3, 3, TEST_TOLERANCE), (2, 2, TEST_TOLERANCE)]
+        );
+        let rejected = cache.insert_evicting(4, 4, TEST_TOLERANCE);
+        assert_eq!(rejected, vec![(4, 4, TEST_TOLERANCE)]);
+        assert_eq!(cache.next_victim(&4), None);
+        assert!(cache.set_capacity(0).is_err());
+    }
+
+    #[test]
+    fn test_clock_retain_keeps_the_hand() {
+        let mut cache = ClockCache::new(4).unwrap();
+        for i in 1..=4 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        let keys =
+            |cache: &ClockCache<i16, i16>| cache.iter().map(|(k, _, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys(&cache), vec![1, 2, 3, 4]);
+        assert_eq!(
+            cache.retain(&mut |key, _| *key != 2),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        // the hand still points to key 1, and the newcomer goes behind it
+        cache.insert(5, 5, TEST_TOLERANCE);
+        assert_eq!(keys(&cache), vec![1, 3, 4, 5]);
+        assert_eq!(cache.next_victim(&6), Some(&1));
+    }
+
+    #[test]
+    fn test_clock_priority() {
+        let mut cache = ClockCache::new(2).unwrap();
+        cache.insert_with_priority(1, 1, TEST_TOLERANCE, 1);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        // the hand reaches key 1 first, but it is of a higher priority
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.entry_info(&1).unwrap().priority, 1);
+        cache.insert_with_priority(4, 4, TEST_TOLERANCE, 1);
+        // a newcomer of a lower priority than every entry goes first
+        assert_eq!(
+            cache.insert_evicting(5, 5, TEST_TOLERANCE),
+            vec![(5, 5, TEST_TOLERANCE)]
+        );
+    }
+}
@@ -0,0 +1,2 @@
+mod clock_cache;
+pub use clock_cache::ClockCache;
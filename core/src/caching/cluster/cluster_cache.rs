@@ -0,0 +1,405 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::slots_bytes;
+use crate::caching::scan::{self, MaybeSync};
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::clock::Instant;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+struct Cluster<K, V> {
+    /// Mean of the keys merged into the cluster.
+    key: K,
+    tol: Tolerance,
+    /// Value of the key that started the cluster.
+    value: V,
+    size: u64,
+    pinned: bool,
+    info: EntryInfo,
+}
+
+/// A key-value store where inserts within tolerance of a stored entry merge into it
+/// instead of being stored next to it, streaming k-means style.
+///
+/// Each entry is a cluster: its key is the running mean of the keys merged into it,
+/// and its value and tolerance those of the key that started it. Redundant traffic
+/// thus takes a single entry, and lookups return the value of the representative along
+/// with the number of keys it stands for. When full, the cluster idle the longest,
+/// merges counting as uses, is evicted.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, ClusterCache};
+/// use proximity::simulation::SimKey;
+///
+/// let mut cache = ClusterCache::new(4).unwrap();
+/// cache.insert(SimKey(vec![1.0; 8]), "first", 0.5);
+/// cache.insert(SimKey(vec![1.1; 8]), "second", 0.5);
+///
+/// assert_eq!(cache.len(), 1);
+/// assert_eq!(cache.find_cluster(&SimKey(vec![1.05; 8])), Some(("first", 2)));
+/// ```
+pub struct ClusterCache<K, V> {
+    max_capacity: usize,
+    clusters: Vec<Cluster<K, V>>,
+    hit_rate: HitRateTracker,
+    /// Dimension of the first vector key inserted.
+    dim: Option<usize>,
+}
+
+impl<K, V> ApproximateCache<K, V> for ClusterCache<K, V>
+where
+    K: ApproxComparable + AsMut<[f32]> + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn find(&mut self, target: &K) -> Option<V> {
+        self.find_cluster(target).map(|(value, _)| value)
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "cluster", k);
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        let matches: Vec<(usize, f32)> = scan::k_closest(&self.clusters, k, |cluster| {
+            cluster
+                .key
+                .roughly_matches(target, cluster.tol)
+                .then(|| target.fuzziness(&cluster.key))
+        })
+        .into_iter()
+        .map(|(idx, _, dist)| (idx, dist))
+        .collect();
+        self.hit_rate.record(!matches.is_empty());
+        matches
+            .into_iter()
+            .map(|(idx, dist)| {
+                let cluster = &mut self.clusters[idx];
+                cluster.info.record_hit();
+                (cluster.value.clone(), dist)
+            })
+            .collect()
+    }
+
+    /// Merges `key` into the closest cluster that matches it, which then moves towards
+    /// it, or else starts a cluster of its own. A merged key and value are not stored, so
+    /// they come back among the evicted entries.
+    fn insert_with_priority(
+        &mut self,
+        mut key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "cluster", tolerance, priority);
+        if let Some(idx) = self.best_match(&key) {
+            let cluster = &mut self.clusters[idx];
+            cluster.size += 1;
+            let rate = 1.0 / cluster.size as f32;
+            for (c, x) in cluster.key.as_mut().iter_mut().zip(key.as_mut()) {
+                *c += (*x - *c) * rate;
+            }
+            cluster.info.last_access = Instant::now();
+            cluster.info.priority = cluster.info.priority.max(priority);
+            trace_event!(position = idx, size = cluster.size, "merged");
+            return vec![(key, value, tolerance)];
+        }
+        self.dim = self.dim.or(key.dimension());
+        self.clusters.push(Cluster {
+            key,
+            tol: tolerance,
+            value,
+            size: 1,
+            pinned: false,
+            info: EntryInfo::with_priority(priority),
+        });
+        // the new cluster is never pinned, so there is always something to evict
+        self.evict_overflow()
+    }
+
+    fn len(&self) -> usize {
+        self.clusters.len()
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        scan::closest(&self.clusters, |cluster| {
+            Some(target.fuzziness(&cluster.key))
+        })
+        .map(|(_, cluster, dist)| (dist, cluster.tol))
+    }
+
+    fn next_victim(&self, incoming: &K) -> Option<&K> {
+        if self.clusters.len() < self.max_capacity || self.best_match(incoming).is_some() {
+            return None;
+        }
+        self.victim().map(|idx| &self.clusters[idx].key)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        self.set_pinned(target, true)
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        self.set_pinned(target, false)
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let candidate = self.best_match(target)?;
+        Some(self.clusters[candidate].info)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(
+            self.clusters
+                .iter()
+                .map(|cluster| (&cluster.key, cluster.info)),
+        )
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(
+            self.clusters
+                .iter()
+                .map(|cluster| (&cluster.key, cluster.value.clone(), cluster.tol)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        // stable, so that clusters of the same priority stay oldest first
+        self.clusters.sort_by_key(|cluster| cluster.info.priority);
+        Box::new(
+            self.clusters
+                .drain(..)
+                .map(|cluster| (cluster.key, cluster.value, cluster.tol)),
+        )
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)> {
+        self.clusters
+            .extract_if(.., |cluster| !keep(&cluster.key, &cluster.value))
+            .map(|cluster| (cluster.key, cluster.value, cluster.tol))
+            .collect()
+    }
+
+    fn compact(&mut self) {
+        self.clusters.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let entries: usize = self
+            .clusters
+            .iter()
+            .map(|cluster| cluster.key.heap_bytes() + cluster.value.heap_bytes())
+            .sum();
+        slots_bytes::<Cluster<K, V>>(self.clusters.capacity()) + entries
+    }
+}
+
+impl<K, V> DefaultApproximateCache<K, V> for ClusterCache<K, V>
+where
+    K: ApproxComparable + AsMut<[f32]> + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn from_capacity(cap: usize) -> ClusterCache<K, V> {
+        ClusterCache::new(cap).expect("bucket capacity is checked by the owning cache")
+    }
+
+    fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    fn set_capacity(&mut self, cap: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        ClusterCache::set_capacity(self, cap)
+    }
+
+    fn evict(&mut self) -> Option<(K, V, Tolerance)> {
+        self.evict_idlest()
+    }
+}
+
+impl<K, V> ClusterCache<K, V> {
+    pub fn new(max_capacity: usize) -> Result<Self> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            max_capacity,
+            clusters: Vec::with_capacity(max_capacity),
+            hit_rate: HitRateTracker::default(),
+            dim: None,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    /// Changes the capacity, evicting the idlest unpinned clusters until the cache fits
+    /// in it, and returns them. Pinned clusters stay, even above the new capacity.
+    pub fn set_capacity(&mut self, max_capacity: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        self.max_capacity = max_capacity;
+        Ok(self.evict_overflow())
+    }
+
+    /// Iterates over every cluster representative along with the number of keys merged
+    /// into it.
+    pub fn cluster_sizes(&self) -> impl Iterator<Item = (&K, u64)> {
+        self.clusters
+            .iter()
+            .map(|cluster| (&cluster.key, cluster.size))
+    }
+
+    /// Number of keys inserted so far that the stored clusters stand for.
+    pub fn total_size(&self) -> u64 {
+        self.clusters.iter().map(|cluster| cluster.size).sum()
+    }
+
+    /// Index of the unpinned cluster of the lowest priority idle the longest, if any.
+    fn victim(&self) -> Option<usize> {
+        self.clusters
+            .iter()
+            .enumerate()
+            .filter(|(_, cluster)| !cluster.pinned)
+            .min_by_key(|(_, cluster)| (cluster.info.priority, cluster.info.last_access))
+            .map(|(idx, _)| idx)
+    }
+
+    /// Evicts the idlest unpinned clusters while the cache is above its capacity.
+    fn evict_overflow(&mut self) -> Vec<(K, V, Tolerance)> {
+        let mut evicted = Vec::new();
+        while self.clusters.len() > self.max_capacity {
+            match self.evict_idlest() {
+                Some(entry) => evicted.push(entry),
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    /// Evicts the idlest unpinned cluster of the lowest priority, if any.
+    fn evict_idlest(&mut self) -> Option<(K, V, Tolerance)> {
+        let idlest = self.victim()?;
+        let cluster = self.clusters.remove(idlest);
+        trace_event!(
+            position = idlest,
+            tolerance = cluster.tol,
+            size = cluster.size,
+            "evicted"
+        );
+        Some((cluster.key, cluster.value, cluster.tol))
+    }
+}
+
+impl<K, V> ClusterCache<K, V>
+where
+    K: ApproxComparable + AsMut<[f32]> + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    /// The value of the representative of the closest cluster that matches `target`,
+    /// along with the number of keys merged into that cluster.
+    pub fn find_cluster(&mut self, target: &K) -> Option<(V, u64)> {
+        trace_span!("find", cache = "cluster");
+        let candidate = self.best_match(target);
+        self.hit_rate.record(candidate.is_some());
+        let cluster = &mut self.clusters[candidate?];
+        cluster.info.record_hit();
+        Some((cluster.value.clone(), cluster.size))
+    }
+
+    /// Index of the closest cluster that matches `target` within its own tolerance.
+    fn best_match(&self, target: &K) -> Option<usize> {
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        scan::closest(&self.clusters, |cluster| {
+            cluster
+                .key
+                .roughly_matches(target, cluster.tol)
+                .then(|| target.fuzziness(&cluster.key))
+        })
+        .map(|(idx, _, _)| idx)
+    }
+
+    fn set_pinned(&mut self, target: &K, pinned: bool) -> bool {
+        match self.best_match(target) {
+            Some(idx) => {
+                self.clusters[idx].pinned = pinned;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::SimKey;
+
+    const TOL: f32 = 1.0;
+
+    fn key(x: f32) -> SimKey {
+        SimKey(vec![x; 8])
+    }
+
+    #[test]
+    fn test_inserts_merge_into_clusters() {
+        let mut cache = ClusterCache::new(4).unwrap();
+        assert!(cache.insert_evicting(key(0.0), 1, TOL).is_empty());
+        let merged = cache.insert_evicting(key(0.2), 2, TOL);
+        assert_eq!(merged, vec![(key(0.2), 2, TOL)]);
+        cache.insert(key(0.1), 3, TOL);
+        cache.insert(key(5.0), 4, TOL);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.total_size(), 4);
+
+        // the centroid is the mean of the merged keys
+        let (centroid, size) = cache.cluster_sizes().next().unwrap();
+        assert_eq!(size, 3);
+        assert!(centroid.0.iter().all(|&x| (x - 0.1).abs() < 1e-6));
+        assert_eq!(cache.find_cluster(&key(0.15)), Some((1, 3)));
+        assert_eq!(cache.find(&key(5.0)), Some(4));
+        assert_eq!(cache.find_k(&key(5.0), 2), vec![(4, 0.0)]);
+        assert_eq!(cache.find(&key(2.5)), None);
+    }
+
+    #[test]
+    fn test_idlest_cluster_evicted() {
+        let mut cache = ClusterCache::new(2).unwrap();
+        cache.insert(key(0.0), 1, TOL);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        cache.insert(key(5.0), 2, TOL);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        // merging into the first cluster makes the second one the idlest
+        cache.insert(key(0.1), 3, TOL);
+        assert_eq!(cache.next_victim(&key(0.05)), None);
+        assert_eq!(cache.next_victim(&key(10.0)), Some(&key(5.0)));
+        let evicted = cache.insert_evicting(key(10.0), 4, TOL);
+        assert_eq!(evicted, vec![(key(5.0), 2, TOL)]);
+
+        assert!(cache.pin(&key(10.0)));
+        let evicted = cache.set_capacity(1).unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(cache.find(&key(10.0)), Some(4));
+        assert!(cache.set_capacity(0).is_err());
+    }
+}
@@ -0,0 +1,2 @@
+mod cluster_cache;
+pub use cluster_cache::ClusterCache;
@@ -0,0 +1,392 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::numerics::ApproxComparable;
+
+struct InFlight<K, V> {
+    id: u64,
+    key: K,
+    tol: Tolerance,
+    result: watch::Receiver<Option<V>>,
+}
+
+/// Asynchronous front to a cache that coalesces concurrent misses: while a value is
+/// being computed for a key, lookups of keys that match it within its tolerance wait
+/// for that computation instead of starting their own.
+///
+/// The cache lock is never held across an `.await`, so the cache itself can be any
+/// [`ApproximateCache`] that is [`Send`].
+///
+/// With [`serve_stale_after`](Self::serve_stale_after), hits on entries older than some
+/// age are still served but flagged by
+/// [`find_or_refresh_async`](Self::find_or_refresh_async), which recomputes them in the
+/// background, e.g. for answers that drift slowly and are slow to recompute.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{AsyncCache, FifoCache};
+///
+/// # tokio_test(async {
+/// let cache = AsyncCache::new(FifoCache::new(16).unwrap());
+/// let (a, b) = tokio::join!(
+///     cache.find_or_compute_async(10 as i16, 2.0, || async { "expensive" }),
+///     cache.find_or_compute_async(11, 2.0, || async { unreachable!() }),
+/// );
+/// assert_eq!((a, b), ("expensive", "expensive"));
+/// # });
+/// # fn tokio_test(f: impl std::future::Future<Output = ()>) {
+/// #     tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(f)
+/// # }
+/// ```
+pub struct AsyncCache<K, V, C> {
+    state: Mutex<State<K, V, C>>,
+    stale_after: Option<Duration>,
+}
+
+/// A value found or computed by an [`AsyncCache`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Aged<V> {
+    pub value: V,
+    /// Age of the entry that answered, zero for a value just computed.
+    pub age: Duration,
+    /// Whether the entry was older than the cache serves fresh, and is being refreshed.
+    pub stale: bool,
+}
+
+struct State<K, V, C> {
+    cache: C,
+    in_flight: Vec<InFlight<K, V>>,
+    next_id: u64,
+}
+
+enum Lookup<V> {
+    Hit(V, Duration),
+    /// A stale hit, along with the refresh to run unless a matching one is in flight.
+    Stale(V, Duration, Option<(u64, watch::Sender<Option<V>>)>),
+    Wait(watch::Receiver<Option<V>>),
+    Compute(u64, watch::Sender<Option<V>>),
+}
+
+/// Withdraws an in-flight computation once it is done, or when its future is dropped
+/// before it finished, so that its waiters retry instead of waiting forever.
+struct FlightGuard<'a, K, V, C> {
+    front: &'a AsyncCache<K, V, C>,
+    id: u64,
+}
+
+impl<K, V, C> Drop for FlightGuard<'_, K, V, C> {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.front.lock().in_flight.retain(|flight| flight.id != id);
+    }
+}
+
+impl<K, V, C> AsyncCache<K, V, C> {
+    pub fn new(cache: C) -> Self {
+        Self {
+            state: Mutex::new(State {
+                cache,
+                in_flight: Vec::new(),
+                next_id: 0,
+            }),
+            stale_after: None,
+        }
+    }
+
+    /// Flags hits on entries older than `age` as stale, see
+    /// [`find_or_refresh_async`](Self::find_or_refresh_async).
+    pub fn serve_stale_after(mut self, age: Duration) -> Self {
+        self.stale_after = Some(age);
+        self
+    }
+
+    /// Number of computations currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.lock().in_flight.len()
+    }
+
+    /// Gives the cache back.
+    pub fn into_inner(self) -> C {
+        self.state
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cache
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<K, V, C>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<K, V, C> AsyncCache<K, V, C>
+where
+    K: ApproxComparable + Clone,
+    V: Clone,
+    C: ApproximateCache<K, V>,
+{
+    /// Runs `f` with exclusive access to the cache, e.g. to insert or inspect entries.
+    pub fn with_cache<T>(&self, f: impl FnOnce(&mut C) -> T) -> T {
+        f(&mut self.lock().cache)
+    }
+
+    /// Returns the cached value for `key`, or else the value of a matching computation
+    /// already in flight, or else computes it with `compute` and caches it with
+    /// `tolerance`.
+    ///
+    /// If the computation this call waits for is cancelled, the call starts over,
+    /// and may end up running `compute` itself.
+    pub async fn find_or_compute_async<F, Fut>(&self, key: K, tolerance: Tolerance, compute: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        loop {
+            match self.lookup(&key, tolerance, false) {
+                Lookup::Hit(value, _) | Lookup::Stale(value, _, _) => return value,
+                Lookup::Wait(result) => {
+                    if let Some(value) = Self::wait(result).await {
+                        return value;
+                    }
+                }
+                Lookup::Compute(id, sender) => {
+                    return self.run(id, sender, key, tolerance, compute()).await;
+                }
+            }
+        }
+    }
+
+    /// Like [`find_or_compute_async`](Self::find_or_compute_async), along with the age of
+    /// the entry that answered. A hit older than set by
+    /// [`serve_stale_after`](Self::serve_stale_after) is served as stale, and unless a
+    /// matching computation is already in flight, `compute` is spawned on the tokio
+    /// runtime to cache a fresh value for `key` with `tolerance`, as on a miss. The stale
+    /// entry is left to eviction, and still answers the lookups closer to it.
+    ///
+    /// # Panics
+    /// If a refresh is due outside of a tokio runtime.
+    pub async fn find_or_refresh_async<F, Fut>(
+        self: &Arc<Self>,
+        key: K,
+        tolerance: Tolerance,
+        compute: F,
+    ) -> Aged<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V> + Send + 'static,
+        K: Send + 'static,
+        V: Send + Sync + 'static,
+        C: Send + 'static,
+    {
+        loop {
+            let (value, age) = match self.lookup(&key, tolerance, true) {
+                Lookup::Hit(value, age) => (value, age),
+                Lookup::Stale(value, age, refresh) => {
+                    if let Some((id, sender)) = refresh {
+                        let front = Arc::clone(self);
+                        let computation = compute();
+                        tokio::spawn(async move {
+                            front.run(id, sender, key, tolerance, computation).await;
+                        });
+                    }
+                    return Aged {
+                        value,
+                        age,
+                        stale: true,
+                    };
+                }
+                Lookup::Wait(result) => match Self::wait(result).await {
+                    Some(value) => (value, Duration::ZERO),
+                    None => continue,
+                },
+                Lookup::Compute(id, sender) => {
+                    let value = self.run(id, sender, key, tolerance, compute()).await;
+                    (value, Duration::ZERO)
+                }
+            };
+            return Aged {
+                value,
+                age,
+                stale: false,
+            };
+        }
+    }
+
+    /// Value of the computation in flight, none if it was cancelled.
+    async fn wait(mut result: watch::Receiver<Option<V>>) -> Option<V> {
+        result
+            .wait_for(Option::is_some)
+            .await
+            .ok()
+            .and_then(|value| value.clone())
+    }
+
+    /// Runs the computation registered as `id` and caches its value.
+    async fn run<Fut>(
+        &self,
+        id: u64,
+        sender: watch::Sender<Option<V>>,
+        key: K,
+        tolerance: Tolerance,
+        computation: Fut,
+    ) -> V
+    where
+        Fut: Future<Output = V>,
+    {
+        let guard = FlightGuard { front: self, id };
+        let value = computation.await;
+        self.lock().cache.insert(key, value.clone(), tolerance);
+        drop(guard);
+        sender.send_replace(Some(value.clone()));
+        value
+    }
+
+    // looks up the cache and the in-flight computations under one lock, so that
+    // a computation always shows up in exactly one of them, and ages hits if `aged`
+    fn lookup(&self, key: &K, tolerance: Tolerance, aged: bool) -> Lookup<V> {
+        let mut state = self.lock();
+        let hit = if aged {
+            state.cache.find_with_age(key)
+        } else {
+            state.cache.find(key).map(|value| (value, Duration::ZERO))
+        };
+        if let Some((value, age)) = hit {
+            if !self.stale_after.is_some_and(|after| age > after) {
+                return Lookup::Hit(value, age);
+            }
+            let in_flight = state
+                .in_flight
+                .iter()
+                .any(|flight| flight.key.roughly_matches(key, flight.tol));
+            let refresh = (!in_flight).then(|| state.register(key.clone(), tolerance));
+            return Lookup::Stale(value, age, refresh);
+        }
+        if let Some(flight) = state
+            .in_flight
+            .iter()
+            .find(|flight| flight.key.roughly_matches(key, flight.tol))
+        {
+            return Lookup::Wait(flight.result.clone());
+        }
+        let (id, sender) = state.register(key.clone(), tolerance);
+        Lookup::Compute(id, sender)
+    }
+}
+
+impl<K, V, C> State<K, V, C> {
+    /// Registers a computation in flight for `key`.
+    fn register(&mut self, key: K, tol: Tolerance) -> (u64, watch::Sender<Option<V>>) {
+        let (sender, result) = watch::channel(None);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.in_flight.push(InFlight {
+            id,
+            key,
+            tol,
+            result,
+        });
+        (id, sender)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::caching::FifoCache;
+    use crate::numerics::SIMD_LANECOUNT;
+    use crate::simulation::SimKey;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_misses_are_coalesced() {
+        let front = Arc::new(AsyncCache::new(FifoCache::new(16).unwrap()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..16)
+            .map(|i| {
+                let (front, calls) = (Arc::clone(&front), Arc::clone(&calls));
+                tokio::spawn(async move {
+                    let key = SimKey(vec![1.0 + i as f32 * 1e-3; SIMD_LANECOUNT]);
+                    front
+                        .find_or_compute_async(key, 0.5, || async {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            42
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), 42);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(front.in_flight(), 0);
+        assert_eq!(front.with_cache(|cache| cache.len()), 1);
+    }
+
+    #[tokio::test]
+    async fn test_distinct_keys_compute_separately() {
+        let front = AsyncCache::new(FifoCache::new(16).unwrap());
+        let (a, b) = tokio::join!(
+            front.find_or_compute_async(0_i16, 1.0, || async { 'a' }),
+            front.find_or_compute_async(100, 1.0, || async { 'b' }),
+        );
+        assert_eq!((a, b), ('a', 'b'));
+        assert_eq!(front.into_inner().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stale_hits_are_served_while_refreshed() {
+        let front = Arc::new(
+            AsyncCache::new(FifoCache::new(16).unwrap())
+                .serve_stale_after(Duration::from_millis(20)),
+        );
+        let calls = Arc::new(AtomicUsize::new(0));
+        let refresh = |value| {
+            let calls = Arc::clone(&calls);
+            move || async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                value
+            }
+        };
+        front.with_cache(|cache| cache.insert(0_i16, 'a', 2.0));
+        let fresh = front.find_or_refresh_async(1, 1.0, refresh('b')).await;
+        assert_eq!((fresh.value, fresh.stale), ('a', false));
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let stale = front.find_or_refresh_async(1, 1.0, refresh('b')).await;
+        assert_eq!((stale.value, stale.stale), ('a', true));
+        assert!(stale.age >= Duration::from_millis(30));
+        // the refresh in flight covers this lookup too
+        let again = front.find_or_refresh_async(1, 1.0, refresh('c')).await;
+        assert_eq!((again.value, again.stale), ('a', true));
+        assert_eq!(front.in_flight(), 1);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(front.in_flight(), 0);
+        let refreshed = front.find_or_refresh_async(1, 1.0, refresh('d')).await;
+        assert_eq!((refreshed.value, refreshed.stale), ('b', false));
+        assert!(refreshed.age < Duration::from_millis(20));
+        assert_eq!(front.with_cache(|cache| cache.len()), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_computation_is_retried() {
+        let front = AsyncCache::new(FifoCache::new(16).unwrap());
+        let stalled = front.find_or_compute_async(0_i16, 1.0, std::future::pending::<u8>);
+        let cancelled = tokio::time::timeout(Duration::from_millis(10), stalled).await;
+        assert!(cancelled.is_err());
+        assert_eq!(front.in_flight(), 0);
+        let value = front.find_or_compute_async(0, 1.0, || async { 7 }).await;
+        assert_eq!(value, 7);
+    }
+}
@@ -0,0 +1,239 @@
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+use npyz::{AutoSerialize, Deserialize};
+use serde::Serialize;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::sketch::CountMinSketch;
+use crate::caching::{CacheBuilder, EvictionPolicy, MaybeSync, NonFinitePolicy, NpzPersistence};
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Distance between keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// Euclidean distance, the only one the caches support so far.
+    #[default]
+    L2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LshConfig {
+    pub num_hash: usize,
+    pub dim: usize,
+    pub seed: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdmissionConfig {
+    pub width: usize,
+    pub depth: usize,
+}
+
+/// Declarative description of a cache, read from a TOML or JSON file.
+///
+/// Every field but `capacity` is optional and mirrors a [`CacheBuilder`] setting.
+/// `persistence` names a `.npz` archive that [`load`](Self::load) restores the cache from.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, CacheConfig};
+/// use proximity::simulation::SimKey;
+///
+/// let config = CacheConfig::from_toml(
+///     r#"
+///     kind = "fifo"
+///     capacity = 64
+///     metric = "l2"
+///     non_finite = "sanitize"
+///
+///     [lsh]
+///     num_hash = 4
+///     dim = 8
+///     seed = 42
+///     "#,
+/// )
+/// .unwrap();
+/// let mut cache = config.build::<SimKey, u32>().unwrap();
+/// cache.insert(SimKey(vec![1.0; 8]), 1, 0.5);
+/// assert_eq!(cache.len(), 1);
+/// ```
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    #[serde(default)]
+    pub kind: EvictionPolicy,
+    pub capacity: usize,
+    pub tolerance: Option<f32>,
+    #[serde(default)]
+    pub metric: Metric,
+    pub lsh: Option<LshConfig>,
+    pub admission: Option<AdmissionConfig>,
+    pub non_finite: Option<NonFinitePolicy>,
+    pub persistence: Option<PathBuf>,
+}
+
+fn config_error(err: impl std::fmt::Display) -> ProximityError {
+    ProximityError::InvalidData(format!("invalid cache config: {err}"))
+}
+
+impl CacheConfig {
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(config_error)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str(text).map_err(config_error)
+    }
+
+    /// Reads a `.toml` or `.json` config file, depending on its extension.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("json") => Self::from_json(&text),
+            _ => Err(ProximityError::InvalidArgument(format!(
+                "cannot tell the format of {}, expected a .toml or .json file",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(config_error)
+    }
+
+    pub fn builder(&self) -> Result<CacheBuilder> {
+        let mut builder = CacheBuilder::new()
+            .policy(self.kind)
+            .capacity(self.capacity);
+        if let Some(tolerance) = self.tolerance {
+            builder = builder.tolerance(tolerance);
+        }
+        if let Some(lsh) = self.lsh {
+            builder = builder.lsh(lsh.num_hash, lsh.dim, lsh.seed);
+        }
+        if let Some(admission) = self.admission {
+            if admission.width == 0 || admission.depth == 0 {
+                return Err(ProximityError::InvalidArgument(
+                    "admission sketch width and depth must be positive".into(),
+                ));
+            }
+            builder = builder.admission(CountMinSketch::new(admission.width, admission.depth));
+        }
+        if let Some(policy) = self.non_finite {
+            builder = builder.non_finite(policy);
+        }
+        Ok(builder)
+    }
+
+    /// Builds an empty cache, ignoring `persistence`.
+    pub fn build<K, V>(&self) -> Result<Box<dyn ApproximateCache<K, V>>>
+    where
+        K: ApproxComparable + AsRef<[f32]> + Eq + Hash + Clone + MaybeSync + 'static,
+        V: Clone + MaybeSync + 'static,
+    {
+        self.builder()?.build()
+    }
+
+    /// Builds the cache and fills it from the `persistence` archive, if that file exists.
+    pub fn load<K, V>(&self) -> Result<Box<dyn ApproximateCache<K, V>>>
+    where
+        K: ApproxComparable
+            + AsRef<[f32]>
+            + From<Vec<f32>>
+            + Eq
+            + Hash
+            + Clone
+            + MaybeSync
+            + 'static,
+        V: AutoSerialize + Deserialize + Clone + MaybeSync + 'static,
+    {
+        let mut cache = self.build()?;
+        if let Some(path) = self.persistence.as_deref().filter(|p| p.exists()) {
+            cache.import_npz(path)?;
+        }
+        Ok(cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::numerics::SIMD_LANECOUNT;
+    use crate::simulation::SimKey;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_config_formats_agree() {
+        let toml = CacheConfig::from_toml(
+            r#"
+            capacity = 16
+            tolerance = 0.25
+            non_finite = "reject"
+            [admission]
+            width = 64
+            depth = 4
+            "#,
+        )
+        .unwrap();
+        let json = CacheConfig::from_json(
+            r#"{"capacity": 16, "tolerance": 0.25, "non_finite": "reject", "admission": {"width": 64, "depth": 4}}"#,
+        )
+        .unwrap();
+        assert_eq!(toml, json);
+        assert_eq!(toml.kind, EvictionPolicy::WTinyLfu);
+        assert_eq!(toml.metric, Metric::L2);
+        assert_eq!(
+            CacheConfig::from_toml(&toml.to_toml().unwrap()).unwrap(),
+            toml
+        );
+        let lru_k = CacheConfig::from_toml("capacity = 16\nkind = \"lru-k\"").unwrap();
+        assert_eq!(lru_k.kind, EvictionPolicy::LruK);
+        let s3fifo = CacheConfig::from_toml("capacity = 16\nkind = \"s3fifo\"").unwrap();
+        assert_eq!(s3fifo.kind, EvictionPolicy::S3Fifo);
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_settings() {
+        for text in [
+            "capacity = 16\nmetric = \"cosine\"",
+            "capacity = 16\nkind = \"arc\"",
+            "capacity = 16\nttl = 3",
+            "kind = \"lru\"",
+        ] {
+            assert!(
+                matches!(
+                    CacheConfig::from_toml(text),
+                    Err(ProximityError::InvalidData(_))
+                ),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_config_load_from_persistence() {
+        let path = std::env::temp_dir().join("proximity_test_config.npz");
+        let key = SimKey(vec![1.0; SIMD_LANECOUNT]);
+        let config = CacheConfig::from_json(&format!(
+            r#"{{"kind": "fifo", "capacity": 4, "persistence": {:?}}}"#,
+            path.to_str().unwrap()
+        ))
+        .unwrap();
+
+        let mut cache = config.load::<SimKey, u32>().unwrap();
+        assert!(cache.is_empty());
+        cache.insert(key.clone(), 7, TEST_TOLERANCE);
+        cache.export_npz(&path).unwrap();
+
+        let mut restored = config.load::<SimKey, u32>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored.find(&key), Some(7));
+    }
+}
@@ -0,0 +1,199 @@
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::HeapSize;
+use crate::clock::Instant;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// How the counts of an [`ApproxCounter`] fade over time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Decay {
+    /// Counts never fade.
+    #[default]
+    None,
+    /// Counts halve every given duration, so that they track a recent rate.
+    HalfLife(Duration),
+}
+
+impl Decay {
+    fn factor(&self, elapsed: Duration) -> f64 {
+        match self {
+            Decay::None => 1.0,
+            Decay::HalfLife(half_life) => {
+                0.5f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64())
+            }
+        }
+    }
+}
+
+/// The count of an entry of an [`ApproxCounter`], shared by the clones that the cache
+/// hands out so that it is updated in place.
+#[derive(Clone, Debug)]
+pub struct SharedCount(Arc<Mutex<(f64, Instant)>>);
+
+impl SharedCount {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new((0.0, Instant::now()))))
+    }
+
+    /// Decays the count to now, adds `amount` and returns it.
+    fn add(&self, decay: Decay, amount: f64) -> f64 {
+        let mut count = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        count.0 = count.0 * decay.factor(now.duration_since(count.1)) + amount;
+        count.1 = now;
+        count.0
+    }
+}
+
+impl HeapSize for SharedCount {
+    fn heap_bytes(&self) -> usize {
+        // the Arc's counts, the lock and the count
+        2 * size_of::<usize>() + size_of::<Mutex<(f64, Instant)>>()
+    }
+}
+
+/// Counts observations of keys, near-identical keys being counted together, e.g. to rate
+/// limit near-duplicate requests.
+///
+/// It is a front to a cache whose values are the counts, so that any policy, bounds or
+/// LSH routing of the caches apply. An observation that no stored key matches starts a
+/// count of its own, and the cache's policy evicts counts when it is full.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproxCounter, Decay, LruCache};
+///
+/// let mut counter = ApproxCounter::new(LruCache::new(16).unwrap(), Decay::None).unwrap();
+/// counter.observe(10 as i16, 2.0);
+/// counter.observe(11, 2.0);
+///
+/// assert_eq!(counter.observe(9, 2.0), 3.0);
+/// assert_eq!(counter.count(&50), 0.0);
+/// ```
+pub struct ApproxCounter<K, C> {
+    inner: C,
+    decay: Decay,
+    keys: PhantomData<fn() -> K>,
+}
+
+impl<K, C> ApproxCounter<K, C>
+where
+    K: ApproxComparable,
+    C: ApproximateCache<K, SharedCount>,
+{
+    /// Counts over an empty cache.
+    pub fn new(inner: C, decay: Decay) -> Result<Self> {
+        if let Decay::HalfLife(half_life) = decay {
+            if half_life.is_zero() {
+                return Err(ProximityError::InvalidArgument(
+                    "count half-life must be positive".into(),
+                ));
+            }
+        }
+        Ok(Self {
+            inner,
+            decay,
+            keys: PhantomData,
+        })
+    }
+
+    /// Counts an observation of `key` towards the stored key that matches it, or else
+    /// towards a new count matching keys within `tolerance` of `key`, and returns that
+    /// count.
+    pub fn observe(&mut self, key: K, tolerance: Tolerance) -> f64 {
+        self.observe_many(key, tolerance, 1.0)
+    }
+
+    /// Like [`observe`](Self::observe), for `amount` observations at once, e.g. a request
+    /// weighted by its cost.
+    pub fn observe_many(&mut self, key: K, tolerance: Tolerance, amount: f64) -> f64 {
+        if let Some(count) = self.inner.find(&key) {
+            return count.add(self.decay, amount);
+        }
+        let count = SharedCount::new();
+        let observed = count.add(self.decay, amount);
+        self.inner.insert(key, count, tolerance);
+        observed
+    }
+
+    /// The count of the stored key that matches `key`, 0 if none does.
+    pub fn count(&mut self, key: &K) -> f64 {
+        self.inner
+            .find(key)
+            .map_or(0.0, |count| count.add(self.decay, 0.0))
+    }
+
+    /// Iterates over every stored key along with its count.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, f64)> {
+        let decay = self.decay;
+        self.inner
+            .iter()
+            .map(move |(key, count, _)| (key, count.add(decay, 0.0)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// The cache holding the counts, e.g. to maintain it.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LshFifoCache};
+    use crate::simulation::SimKey;
+
+    #[test]
+    fn test_near_duplicates_counted_together() {
+        let mut counter = ApproxCounter::new(FifoCache::new(2).unwrap(), Decay::None).unwrap();
+        for key in [0i16, 1, 0, 10, 11, 0] {
+            counter.observe(key, 2.0);
+        }
+        assert_eq!(counter.count(&1), 4.0);
+        assert_eq!(counter.count(&10), 2.0);
+        assert_eq!(counter.observe_many(11, 2.0, 0.5), 2.5);
+
+        // a third count evicts the oldest one
+        counter.observe(100, 2.0);
+        assert_eq!(counter.count(&0), 0.0);
+        let mut counts: Vec<(i16, f64)> = counter.iter().map(|(k, c)| (*k, c)).collect();
+        counts.sort_by_key(|(k, _)| *k);
+        assert_eq!(counts, [(10, 2.5), (100, 1.0)]);
+    }
+
+    #[test]
+    fn test_counts_decay() {
+        let half_life = Duration::from_millis(20);
+        let lsh = LshFifoCache::new(4, 8, 16, Some(3)).unwrap();
+        let mut counter = ApproxCounter::new(lsh, Decay::HalfLife(half_life)).unwrap();
+        for i in 0..4 {
+            counter.observe(SimKey(vec![1.0 + i as f32 * 1e-3; 8]), 0.1);
+        }
+        assert!(counter.count(&SimKey(vec![1.0; 8])) > 3.5);
+        std::thread::sleep(2 * half_life);
+        assert!(counter.count(&SimKey(vec![1.0; 8])) < 1.01);
+        assert!(ApproxCounter::<i16, FifoCache<i16, SharedCount>>::new(
+            FifoCache::new(2).unwrap(),
+            Decay::HalfLife(Duration::ZERO)
+        )
+        .is_err());
+    }
+}
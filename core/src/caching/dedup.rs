@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::{ApproxSet, FifoCache, MaybeSync};
+use crate::clock::Instant;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Filters near-identical items out of a stream, e.g. embeddings of training data: an
+/// item is a duplicate if it is within `tolerance` of one of the last `window` items
+/// kept, and, with a TTL, kept less than that long ago.
+///
+/// Duplicates are not kept, so that a slow drift of near-identical items does not keep
+/// the first of them alive. Kept items are held in an [`ApproxSet`] over a
+/// [`FifoCache`], which forgets the oldest ones first.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::Deduplicator;
+///
+/// let mut dedup = Deduplicator::new(2, 2.0).unwrap();
+/// let kept: Vec<i16> = [10, 11, 30, 50, 9]
+///     .into_iter()
+///     .filter(|&x| !dedup.is_duplicate(x))
+///     .collect();
+///
+/// // 10 left the window before 9 came
+/// assert_eq!(kept, [10, 30, 50, 9]);
+/// ```
+pub struct Deduplicator<K> {
+    seen: ApproxSet<K, FifoCache<K, ()>>,
+    tolerance: Tolerance,
+    ttl: Option<Duration>,
+    /// When each kept item was kept, oldest first, in the order of the FIFO.
+    kept_at: VecDeque<Instant>,
+}
+
+impl<K> Deduplicator<K>
+where
+    K: ApproxComparable + MaybeSync,
+{
+    /// Remembers the last `window` kept items.
+    pub fn new(window: usize, tolerance: Tolerance) -> Result<Self> {
+        if tolerance.is_nan() || tolerance < 0.0 {
+            return Err(ProximityError::InvalidArgument(format!(
+                "tolerance must be non-negative, got {tolerance}"
+            )));
+        }
+        Ok(Self {
+            seen: ApproxSet::new(FifoCache::new(window)?),
+            tolerance,
+            ttl: None,
+            kept_at: VecDeque::new(),
+        })
+    }
+
+    /// Also forgets kept items once they are `ttl` old.
+    pub fn with_ttl(mut self, ttl: Duration) -> Result<Self> {
+        if ttl.is_zero() {
+            return Err(ProximityError::InvalidArgument(
+                "deduplication TTL must be positive".into(),
+            ));
+        }
+        self.ttl = Some(ttl);
+        Ok(self)
+    }
+
+    /// Whether `item` duplicates a remembered one. If not, it is kept, and the oldest
+    /// kept item is forgotten if the window is full.
+    pub fn is_duplicate(&mut self, item: K) -> bool {
+        self.expire();
+        if self.seen.contains(&item) {
+            return true;
+        }
+        let forgotten = self.seen.insert(item, self.tolerance).len();
+        self.kept_at.push_back(Instant::now());
+        self.kept_at.drain(..forgotten.min(self.kept_at.len()));
+        false
+    }
+
+    /// Forgets the kept items older than the TTL.
+    fn expire(&mut self) {
+        let Some(ttl) = self.ttl else {
+            return;
+        };
+        while self
+            .kept_at
+            .front()
+            .is_some_and(|kept_at| kept_at.elapsed() >= ttl)
+        {
+            self.seen.get_mut().evict();
+            self.kept_at.pop_front();
+        }
+    }
+
+    /// Checks that `item` has the dimension of the items seen so far.
+    pub fn check_dim(&self, item: &K) -> Result<()> {
+        self.seen.get_ref().check_dim(item)
+    }
+
+    pub fn tolerance(&self) -> Tolerance {
+        self.tolerance
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Number of remembered items, including those past the TTL until the next check.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// Forgets every item, e.g. between epochs.
+    pub fn clear(&mut self) {
+        self.seen.drain().for_each(drop);
+        self.kept_at.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::SimKey;
+
+    #[test]
+    fn test_window_slides_over_kept_items() {
+        let mut dedup = Deduplicator::new(2, 0.1).unwrap();
+        let key = |x: f32| SimKey(vec![x; 8]);
+        assert!(!dedup.is_duplicate(key(0.0)));
+        assert!(dedup.is_duplicate(key(0.01)));
+        assert!(!dedup.is_duplicate(key(1.0)));
+        // duplicates do not count towards the window
+        assert!(dedup.is_duplicate(key(1.01)));
+        assert!(dedup.is_duplicate(key(0.0)));
+        assert!(!dedup.is_duplicate(key(2.0)));
+        assert!(!dedup.is_duplicate(key(0.0)));
+        assert_eq!(dedup.len(), 2);
+        assert!(dedup.check_dim(&SimKey(vec![0.0; 3])).is_err());
+
+        dedup.clear();
+        assert!(dedup.is_empty());
+        assert!(!dedup.is_duplicate(key(2.0)));
+        assert!(Deduplicator::<SimKey>::new(0, 0.1).is_err());
+        assert!(Deduplicator::<SimKey>::new(2, -1.0).is_err());
+    }
+
+    #[test]
+    fn test_items_expire_after_ttl() {
+        let ttl = Duration::from_millis(30);
+        let mut dedup = Deduplicator::new(16, 2.0).unwrap().with_ttl(ttl).unwrap();
+        assert!(!dedup.is_duplicate(10i16));
+        std::thread::sleep(ttl / 2);
+        assert!(!dedup.is_duplicate(20));
+        assert!(dedup.is_duplicate(11));
+        std::thread::sleep(ttl / 2 + ttl / 5);
+        // 10 expired, 20 did not
+        assert!(!dedup.is_duplicate(11));
+        assert!(dedup.is_duplicate(21));
+        assert_eq!(dedup.len(), 2);
+        assert!(Deduplicator::<i16>::new(2, 1.0)
+            .unwrap()
+            .with_ttl(Duration::ZERO)
+            .is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Wraps a cache to give it a default tolerance, used by
+/// [`insert_default`](ApproximateCache::insert_default).
+/// Inserts that pass their own tolerance keep it.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, DefaultTolerance, FifoCache};
+///
+/// let mut cache = DefaultTolerance::new(FifoCache::new(4).unwrap(), 2.0).unwrap();
+/// cache.insert_default(10 as i16, "Value 1").unwrap();
+/// cache.insert(20, "Value 2", 0.5);
+///
+/// assert_eq!(cache.find(&11), Some("Value 1"));
+/// assert_eq!(cache.find(&21), None);
+/// ```
+pub struct DefaultTolerance<C> {
+    inner: C,
+    tolerance: Tolerance,
+}
+
+impl<C> DefaultTolerance<C> {
+    pub fn new(inner: C, tolerance: Tolerance) -> Result<Self> {
+        if tolerance.is_nan() || tolerance < 0.0 {
+            return Err(ProximityError::InvalidArgument(format!(
+                "tolerance must be non-negative, got {tolerance}"
+            )));
+        }
+        Ok(Self { inner, tolerance })
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for DefaultTolerance<C>
+where
+    K: ApproxComparable,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        find, find_k, insert_with_priority, len, candidates, key_dim, nearest, next_victim, pin,
+        unpin, entry_info, entry_infos, iter, drain, retain, maintain, compact, recent_hit_rate,
+        memory_bytes,
+    );
+
+    fn default_tolerance(&self) -> Option<Tolerance> {
+        Some(self.tolerance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{LruCache, NegativeCache};
+
+    #[test]
+    fn test_default_tolerance_and_override() {
+        let mut cache = DefaultTolerance::new(LruCache::new(4).unwrap(), 1.5).unwrap();
+        cache.insert_default(10, 10).unwrap();
+        cache.insert(20, 20, 0.5);
+        let tolerances: Vec<Tolerance> = cache.iter().map(|(_, _, tol)| tol).collect();
+        assert_eq!(tolerances.len(), 2);
+        assert!(tolerances.contains(&1.5) && tolerances.contains(&0.5));
+        assert_eq!(cache.find(&11), Some(10));
+        assert_eq!(cache.find(&21), None);
+    }
+
+    #[test]
+    fn test_default_tolerance_seen_through_wrappers() {
+        let inner = DefaultTolerance::new(LruCache::new(4).unwrap(), 1.0).unwrap();
+        let mut cache = NegativeCache::new(inner, 4).unwrap();
+        assert_eq!(cache.default_tolerance(), Some(1.0));
+        cache.insert_default(1, 1).unwrap();
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_missing_or_invalid_default_tolerance() {
+        let mut cache: LruCache<i16, i16> = LruCache::new(4).unwrap();
+        assert!(matches!(
+            cache.insert_default(1, 1),
+            Err(ProximityError::InvalidArgument(_))
+        ));
+        assert!(DefaultTolerance::new(LruCache::<i16, i16>::new(4).unwrap(), f32::NAN).is_err());
+        assert!(DefaultTolerance::new(LruCache::<i16, i16>::new(4).unwrap(), -1.0).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::ApproximateCache;
+use crate::clock::Instant;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Format of the deltas written by [`export_delta`](DeltaSync::export_delta). Deltas of
+/// another version are refused.
+const DELTA_VERSION: u8 = 1;
+
+/// Exchanging the entries of vector caches with peers, e.g. nodes of a decentralized
+/// deployment that gossip their hottest entries without a central server.
+///
+/// A delta is a byte string: a version byte, a `u32` entry count, then for every entry
+/// its key as a `u32` dimension followed by that many `f32`, its tolerance as an `f32`,
+/// its priority as a `u32` and its value as a `u32` length followed by the bytes, all
+/// little-endian. Values are bytes, so serialize richer values before inserting them.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, DeltaSync, FifoCache};
+/// use proximity::simulation::SimKey;
+///
+/// let mut node = FifoCache::new(4).unwrap();
+/// let mut peer = FifoCache::new(4).unwrap();
+/// node.insert(SimKey(vec![1.0; 8]), b"Value 1".to_vec(), 1.0);
+/// let (delta, watermark) = node.export_delta(None, usize::MAX);
+/// node.insert(SimKey(vec![2.0; 8]), b"Value 2".to_vec(), 1.0);
+///
+/// assert_eq!(peer.apply_delta(&delta).unwrap(), 1);
+/// // applying a delta again changes nothing
+/// assert_eq!(peer.apply_delta(&delta).unwrap(), 0);
+/// // the next delta only holds the entries inserted since
+/// let (delta, _) = node.export_delta(Some(watermark), usize::MAX);
+/// assert_eq!(peer.apply_delta(&delta).unwrap(), 1);
+/// assert_eq!(peer.find(&SimKey(vec![2.1; 8])), Some(b"Value 2".to_vec()));
+/// ```
+pub trait DeltaSync<K, V>: ApproximateCache<K, V>
+where
+    K: ApproxComparable,
+{
+    /// Encodes up to `max_entries` of the entries inserted since `since`, or of every
+    /// entry if `None`, those with the most hits first. Returns the delta along with
+    /// the watermark to pass to the next export, so that it only holds newer entries:
+    /// the entries left out over `max_entries` are not exported again.
+    fn export_delta(&self, since: Option<Instant>, max_entries: usize) -> (Vec<u8>, Instant);
+    /// Inserts the entries of a delta whose key this cache does not answer yet,
+    /// evicting as its policy decides, and returns how many were inserted. Applying a
+    /// delta twice, or deltas that overlap, inserts every entry once.
+    ///
+    /// Nothing is inserted if the delta is malformed or holds keys of another dimension.
+    fn apply_delta(&mut self, delta: &[u8]) -> Result<usize>;
+}
+
+impl<K, V, C> DeltaSync<K, V> for C
+where
+    K: ApproxComparable + AsRef<[f32]> + From<Vec<f32>>,
+    V: AsRef<[u8]> + for<'a> From<&'a [u8]>,
+    C: ApproximateCache<K, V> + ?Sized,
+{
+    fn export_delta(&self, since: Option<Instant>, max_entries: usize) -> (Vec<u8>, Instant) {
+        // taken first, so that entries inserted at that instant are exported next time
+        let watermark = Instant::now();
+        let mut infos = self.entry_infos();
+        let mut entries: Vec<_> = self
+            .iter()
+            .filter_map(|(key, value, tolerance)| {
+                let info = infos.next().map(|(_, info)| info);
+                let fresh = match (since, info) {
+                    (Some(since), Some(info)) => info.inserted_at >= since,
+                    _ => true,
+                };
+                fresh.then(|| {
+                    let (hits, priority) = info.map_or((0, 0), |info| (info.hits, info.priority));
+                    (hits, key, value, tolerance, priority)
+                })
+            })
+            .collect();
+        // stable, so that entries with as many hits keep the order of the cache
+        entries.sort_by_key(|(hits, ..)| std::cmp::Reverse(*hits));
+        entries.truncate(max_entries);
+
+        let mut delta = vec![DELTA_VERSION];
+        put_u32(&mut delta, entries.len() as u32);
+        for (_, key, value, tolerance, priority) in entries {
+            let key = key.as_ref();
+            put_u32(&mut delta, key.len() as u32);
+            key.iter()
+                .for_each(|x| delta.extend_from_slice(&x.to_le_bytes()));
+            delta.extend_from_slice(&tolerance.to_le_bytes());
+            put_u32(&mut delta, priority);
+            let value = value.as_ref();
+            put_u32(&mut delta, value.len() as u32);
+            delta.extend_from_slice(value);
+        }
+        (delta, watermark)
+    }
+
+    fn apply_delta(&mut self, delta: &[u8]) -> Result<usize> {
+        let entries: Vec<(K, V, Tolerance, u32)> = decode(delta)?;
+        // an empty cache takes the dimension of the first key
+        let dim = self
+            .key_dim()
+            .or_else(|| entries.first().and_then(|(key, ..)| key.dimension()));
+        for (key, ..) in &entries {
+            match (dim, key.dimension()) {
+                (Some(expected), Some(found)) if expected != found => {
+                    return Err(ProximityError::DimensionMismatch { expected, found })
+                }
+                _ => {}
+            }
+        }
+        let mut inserted = 0;
+        for (key, value, tolerance, priority) in entries {
+            if self.entry_info(&key).is_some() {
+                continue;
+            }
+            self.insert_with_priority(key, value, tolerance, priority);
+            inserted += 1;
+        }
+        Ok(inserted)
+    }
+}
+
+pub(crate) fn put_u32(delta: &mut Vec<u8>, x: u32) {
+    delta.extend_from_slice(&x.to_le_bytes());
+}
+
+fn malformed(what: &str) -> ProximityError {
+    ProximityError::InvalidData(format!("malformed delta: {what}"))
+}
+
+pub(crate) fn take<'a>(delta: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if delta.len() < n {
+        return Err(malformed("truncated"));
+    }
+    let (head, rest) = delta.split_at(n);
+    *delta = rest;
+    Ok(head)
+}
+
+pub(crate) fn take_u32(delta: &mut &[u8]) -> Result<u32> {
+    Ok(u32::from_le_bytes(take(delta, 4)?.try_into().unwrap()))
+}
+
+pub(crate) fn take_f32(delta: &mut &[u8]) -> Result<f32> {
+    Ok(f32::from_le_bytes(take(delta, 4)?.try_into().unwrap()))
+}
+
+fn decode<K, V>(mut delta: &[u8]) -> Result<Vec<(K, V, Tolerance, u32)>>
+where
+    K: From<Vec<f32>>,
+    V: for<'a> From<&'a [u8]>,
+{
+    match take(&mut delta, 1)?[0] {
+        DELTA_VERSION => {}
+        version => {
+            return Err(ProximityError::InvalidData(format!(
+                "unsupported delta version {version}, expected {DELTA_VERSION}"
+            )))
+        }
+    }
+    let count = take_u32(&mut delta)? as usize;
+    // every entry takes at least 16 bytes, which bounds preallocation
+    let mut entries = Vec::with_capacity(count.min(delta.len() / 16));
+    for _ in 0..count {
+        let dim = take_u32(&mut delta)? as usize;
+        let raw = take(
+            &mut delta,
+            dim.checked_mul(4)
+                .ok_or_else(|| malformed("key too long"))?,
+        )?;
+        let key: Vec<f32> = raw
+            .chunks_exact(4)
+            .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+            .collect();
+        let tolerance = take_f32(&mut delta)?;
+        let priority = take_u32(&mut delta)?;
+        let len = take_u32(&mut delta)? as usize;
+        let value = V::from(take(&mut delta, len)?);
+        entries.push((K::from(key), value, tolerance, priority));
+    }
+    if !delta.is_empty() {
+        return Err(malformed("trailing bytes"));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LruCache};
+    use crate::simulation::SimKey;
+
+    const DIM: usize = 8;
+
+    fn key(i: usize) -> SimKey {
+        SimKey(vec![i as f32; DIM])
+    }
+
+    #[test]
+    fn test_delta_keeps_the_hottest_entries() {
+        let mut cache = LruCache::new(8).unwrap();
+        for i in 0..4 {
+            cache.insert_with_priority(key(i), vec![i as u8], 0.5, i as u32);
+        }
+        for _ in 0..3 {
+            cache.find(&key(2));
+        }
+        cache.find(&key(1));
+        let (delta, _) = cache.export_delta(None, 2);
+
+        let mut peer: FifoCache<SimKey, Vec<u8>> = FifoCache::new(8).unwrap();
+        assert_eq!(peer.apply_delta(&delta).unwrap(), 2);
+        assert_eq!(peer.find(&key(2)), Some(vec![2]));
+        assert_eq!(peer.find(&key(1)), Some(vec![1]));
+        assert_eq!(peer.entry_info(&key(2)).unwrap().priority, 2);
+        assert!(peer.find(&key(0)).is_none());
+    }
+
+    #[test]
+    fn test_apply_respects_capacity() {
+        let mut cache = FifoCache::new(8).unwrap();
+        for i in 0..6 {
+            cache.insert(key(i), vec![i as u8], 0.5);
+        }
+        let (delta, _) = cache.export_delta(None, usize::MAX);
+        let mut peer: FifoCache<SimKey, Vec<u8>> = FifoCache::new(4).unwrap();
+        assert_eq!(peer.apply_delta(&delta).unwrap(), 6);
+        assert_eq!(peer.len(), 4);
+    }
+
+    #[test]
+    fn test_apply_rejects_bad_deltas() {
+        let mut cache = FifoCache::new(4).unwrap();
+        cache.insert(key(1), vec![1], 0.5);
+        let (delta, _) = cache.export_delta(None, usize::MAX);
+
+        let mut peer: FifoCache<SimKey, Vec<u8>> = FifoCache::new(4).unwrap();
+        for bad in [&delta[..delta.len() - 1], &[2, 0, 0, 0, 0][..]] {
+            assert!(matches!(
+                peer.apply_delta(bad),
+                Err(ProximityError::InvalidData(_))
+            ));
+        }
+        peer.insert(SimKey(vec![0.0; 2 * DIM]), vec![0], 0.5);
+        assert!(matches!(
+            peer.apply_delta(&delta),
+            Err(ProximityError::DimensionMismatch { .. })
+        ));
+        assert_eq!(peer.len(), 1);
+    }
+}
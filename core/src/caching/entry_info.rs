@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use crate::clock::Instant;
+
+/// Usage metadata tracked for every entry of a cache.
+#[derive(Clone, Copy, Debug)]
+pub struct EntryInfo {
+    /// When the entry was inserted.
+    pub inserted_at: Instant,
+    /// When the entry was last returned by a `find` (or inserted, if never found).
+    pub last_access: Instant,
+    /// How many `find` calls were answered by this entry.
+    pub hits: u64,
+    /// Eviction takes entries of the lowest priority first, see
+    /// [`insert_with_priority`](crate::caching::ApproximateCache::insert_with_priority).
+    pub priority: u32,
+}
+
+impl EntryInfo {
+    pub(crate) fn new() -> Self {
+        Self::with_priority(0)
+    }
+
+    pub(crate) fn with_priority(priority: u32) -> Self {
+        let now = Instant::now();
+        Self {
+            inserted_at: now,
+            last_access: now,
+            hits: 0,
+            priority,
+        }
+    }
+
+    pub(crate) fn record_hit(&mut self) {
+        self.hits += 1;
+        self.last_access = Instant::now();
+    }
+
+    /// Time elapsed since the entry was inserted.
+    pub fn age(&self) -> Duration {
+        self.inserted_at.elapsed()
+    }
+
+    /// Time elapsed since the entry was last accessed.
+    pub fn idle(&self) -> Duration {
+        self.last_access.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_hit() {
+        let mut info = EntryInfo::new();
+        assert_eq!(info.hits, 0);
+        assert_eq!(info.inserted_at, info.last_access);
+
+        info.record_hit();
+        info.record_hit();
+        assert_eq!(info.hits, 2);
+        assert!(info.last_access >= info.inserted_at);
+        // read idle first: both clocks keep running between the two calls
+        let idle = info.idle();
+        assert!(info.age() >= idle);
+    }
+}
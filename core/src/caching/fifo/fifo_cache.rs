@@ -3,72 +3,339 @@ use std::collections::VecDeque;
 use crate::caching::approximate_cache::ApproximateCache;
 use crate::caching::approximate_cache::DefaultApproximateCache;
 use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::slots_bytes;
+use crate::caching::priority::PriorityCounts;
+use crate::caching::scan::{self, MaybeSync};
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::caching::Weighting;
 use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
 
 #[derive(Clone)]
 struct CacheLine<K, V> {
     key: K,
     tol: Tolerance,
     value: V,
+    pinned: bool,
+    info: EntryInfo,
 }
 
 pub struct FifoCache<K, V> {
     max_capacity: usize,
     items: VecDeque<CacheLine<K, V>>,
+    hit_rate: HitRateTracker,
+    /// Priorities of the unpinned entries, the ones eviction takes from.
+    unpinned: PriorityCounts,
+    /// Dimension of the first vector key inserted.
+    dim: Option<usize>,
 }
 
 impl<K, V> ApproximateCache<K, V> for FifoCache<K, V>
 where
-    K: ApproxComparable,
-    V: Clone,
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
 {
     fn find(&mut self, target: &K) -> Option<V> {
-        let candidate = self
-            .items
-            .iter()
-            .filter(|&entry| entry.key.roughly_matches(target, entry.tol))
-            .min_by(|&x, &y| {
-                target
-                    .fuzziness(&x.key)
-                    .partial_cmp(&target.fuzziness(&y.key))
-                    .unwrap() // finding NaNs here should crash the program
-            })?;
-        Some(candidate.value.clone())
+        trace_span!("find", cache = "fifo");
+        let candidate = self.best_match(target);
+        trace_event!(
+            candidates = self.items.len(),
+            hit = candidate.is_some(),
+            best_fuzziness = ?candidate
+                .map(|(_, dist)| dist)
+                .or_else(|| self.nearest(target).map(|(dist, _)| dist)),
+            tolerance = ?candidate
+                .is_none()
+                .then(|| self.nearest(target).map(|(_, tol)| tol))
+                .flatten(),
+            "scanned"
+        );
+        self.hit_rate.record(candidate.is_some());
+        let entry = &mut self.items[candidate?.0];
+        entry.info.record_hit();
+        Some(entry.value.clone())
     }
 
-    fn insert(&mut self, key: K, value: V, tolerance: f32) {
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "fifo", k);
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        let matches: Vec<(usize, f32)> = scan::k_closest(&self.items, k, |entry| {
+            entry
+                .key
+                .roughly_matches(target, entry.tol)
+                .then(|| target.fuzziness(&entry.key))
+        })
+        .into_iter()
+        .map(|(idx, _, dist)| (idx, dist))
+        .collect();
+        trace_event!(
+            candidates = self.items.len(),
+            matches = matches.len(),
+            best_fuzziness = ?matches
+                .first()
+                .map(|(_, dist)| *dist)
+                .or_else(|| self.nearest(target).map(|(dist, _)| dist)),
+            tolerance = ?matches
+                .is_empty()
+                .then(|| self.nearest(target).map(|(_, tol)| tol))
+                .flatten(),
+            "scanned"
+        );
+        self.hit_rate.record(!matches.is_empty());
+        matches
+            .into_iter()
+            .map(|(idx, dist)| {
+                let entry = &mut self.items[idx];
+                entry.info.record_hit();
+                (entry.value.clone(), dist)
+            })
+            .collect()
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "fifo", tolerance, priority);
+        self.check_dim(&key).unwrap_or_else(|e| panic!("{e}"));
+        self.dim = self.dim.or(key.dimension());
         let new_entry = CacheLine {
             key,
             tol: tolerance,
             value,
+            pinned: false,
+            info: EntryInfo::with_priority(priority),
         };
         self.items.push_back(new_entry);
-        if self.items.len() > self.max_capacity {
-            self.items.pop_front();
-        }
+        self.unpinned.add(priority);
+        // the new entry is never pinned, so there is always something to evict
+        self.evict_overflow()
     }
 
     fn len(&self) -> usize {
         self.items.len()
     }
+
+    fn key_dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        scan::closest(&self.items, |entry| Some(target.fuzziness(&entry.key)))
+            .map(|(_, entry, dist)| (dist, entry.tol))
+    }
+
+    fn next_victim(&self, _incoming: &K) -> Option<&K> {
+        if self.items.len() < self.max_capacity {
+            return None;
+        }
+        self.victim().map(|idx| &self.items[idx].key)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        self.set_pinned(target, true)
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        self.set_pinned(target, false)
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let (candidate, _) = self.best_match(target)?;
+        Some(self.items[candidate].info)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(self.items.iter().map(|entry| (&entry.key, entry.info)))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(
+            self.items
+                .iter()
+                .map(|entry| (&entry.key, entry.value.clone(), entry.tol)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        // stable, so that entries of the same priority stay oldest first
+        self.items
+            .make_contiguous()
+            .sort_by_key(|entry| entry.info.priority);
+        self.unpinned.clear();
+        Box::new(
+            self.items
+                .drain(..)
+                .map(|entry| (entry.key, entry.value, entry.tol)),
+        )
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)> {
+        // rotates the entries through once, so that the kept ones stay in order
+        let mut removed = Vec::new();
+        for _ in 0..self.items.len() {
+            let entry = self.items.pop_front().unwrap();
+            if keep(&entry.key, &entry.value) {
+                self.items.push_back(entry);
+                continue;
+            }
+            if !entry.pinned {
+                self.unpinned.remove(entry.info.priority);
+            }
+            removed.push((entry.key, entry.value, entry.tol));
+        }
+        removed
+    }
+
+    fn compact(&mut self) {
+        self.items.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let entries: usize = self
+            .items
+            .iter()
+            .map(|line| line.key.heap_bytes() + line.value.heap_bytes())
+            .sum();
+        slots_bytes::<CacheLine<K, V>>(self.items.capacity()) + entries
+    }
 }
 
 impl<K, V> DefaultApproximateCache<K, V> for FifoCache<K, V>
 where
-    K: ApproxComparable,
-    V: Clone,
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
 {
     fn from_capacity(cap: usize) -> FifoCache<K, V> {
-        FifoCache::new(cap)
+        FifoCache::new(cap).expect("bucket capacity is checked by the owning cache")
+    }
+
+    fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    fn set_capacity(&mut self, cap: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        FifoCache::set_capacity(self, cap)
+    }
+
+    fn evict(&mut self) -> Option<(K, V, Tolerance)> {
+        self.evict_oldest()
     }
 }
 
 impl<K, V> FifoCache<K, V> {
-    pub fn new(max_capacity: usize) -> Self {
-        assert!(max_capacity > 0);
-        Self {
+    pub fn new(max_capacity: usize) -> Result<Self> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        Ok(Self {
             max_capacity,
             items: VecDeque::with_capacity(max_capacity),
+            hit_rate: HitRateTracker::default(),
+            unpinned: PriorityCounts::default(),
+            dim: None,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    /// Changes the capacity, evicting the oldest unpinned entries until the cache fits
+    /// in it, and returns them. Pinned entries stay, even above the new capacity.
+    pub fn set_capacity(&mut self, max_capacity: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        self.max_capacity = max_capacity;
+        Ok(self.evict_overflow())
+    }
+
+    /// Index of the oldest unpinned entry of the lowest priority, if any.
+    fn victim(&self) -> Option<usize> {
+        let floor = self.unpinned.floor()?;
+        self.items
+            .iter()
+            .position(|entry| !entry.pinned && entry.info.priority == floor)
+    }
+
+    /// Evicts the oldest unpinned entries while the cache is above its capacity.
+    fn evict_overflow(&mut self) -> Vec<(K, V, Tolerance)> {
+        let mut evicted = Vec::new();
+        while self.items.len() > self.max_capacity {
+            match self.evict_oldest() {
+                Some(entry) => evicted.push(entry),
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    /// Evicts the oldest unpinned entry of the lowest priority, if any.
+    fn evict_oldest(&mut self) -> Option<(K, V, Tolerance)> {
+        let oldest = self.victim()?;
+        let entry = self.items.remove(oldest).unwrap();
+        self.unpinned.remove(entry.info.priority);
+        trace_event!(
+            position = oldest,
+            tolerance = entry.tol,
+            hits = entry.info.hits,
+            "evicted"
+        );
+        Some((entry.key, entry.value, entry.tol))
+    }
+}
+
+impl<K, V> FifoCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    /// Index of the closest entry that matches `target` within its own tolerance, along
+    /// with its distance to `target`.
+    fn best_match(&self, target: &K) -> Option<(usize, f32)> {
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        scan::closest(&self.items, |entry| {
+            entry
+                .key
+                .roughly_matches(target, entry.tol)
+                .then(|| target.fuzziness(&entry.key))
+        })
+        .map(|(idx, _, dist)| (idx, dist))
+    }
+
+    fn set_pinned(&mut self, target: &K, pinned: bool) -> bool {
+        match self.best_match(target) {
+            Some((idx, _)) => {
+                let entry = &mut self.items[idx];
+                if entry.pinned != pinned {
+                    match pinned {
+                        true => self.unpinned.remove(entry.info.priority),
+                        false => self.unpinned.add(entry.info.priority),
+                    }
+                    entry.pinned = pinned;
+                }
+                true
+            }
+            None => false,
         }
     }
 }
@@ -80,7 +347,7 @@ mod tests {
     const TEST_TOLERANCE: f32 = 1e-8;
     #[test]
     fn test_fifo_cache_basic_operations() {
-        let mut cache = FifoCache::new(2);
+        let mut cache = FifoCache::new(2).unwrap();
         cache.insert(1, 1, TEST_TOLERANCE); // Cache is {1=1}
         cache.insert(2, 2, TEST_TOLERANCE); // Cache is {1=1, 2=2}
         assert_eq!(cache.find(&1), Some(1)); // Returns 1, Cache is {1=1, 2=2}
@@ -92,7 +359,7 @@ mod tests {
 
     #[test]
     fn test_fifo_cache_eviction_order() {
-        let mut cache = FifoCache::new(3);
+        let mut cache = FifoCache::new(3).unwrap();
         cache.insert(1, 1, TEST_TOLERANCE); // Cache is {1=1}
         cache.insert(2, 2, TEST_TOLERANCE); // Cache is {1=1, 2=2}
         cache.insert(3, 3, TEST_TOLERANCE); // Cache is {1=1, 2=2, 3=3}
@@ -105,7 +372,7 @@ mod tests {
 
     #[test]
     fn test_fifo_cache_overwrite() {
-        let mut cache = FifoCache::new(2);
+        let mut cache = FifoCache::new(2).unwrap();
         cache.insert(1, 1, TEST_TOLERANCE); // Cache is {1=1}
         cache.insert(2, 2, TEST_TOLERANCE); // Cache is {1=1, 2=2}
         cache.insert(1, 10, TEST_TOLERANCE); // Overwrites key 1, Cache is {2=2, 1=10}
@@ -117,7 +384,7 @@ mod tests {
 
     #[test]
     fn test_fifo_cache_capacity_one() {
-        let mut cache = FifoCache::new(1);
+        let mut cache = FifoCache::new(1).unwrap();
         cache.insert(1, 1, TEST_TOLERANCE); // Cache is {1=1}
         assert_eq!(cache.find(&1), Some(1)); // Returns 1
         cache.insert(2, 2, TEST_TOLERANCE); // Evicts key 1, Cache is {2=2}
@@ -126,8 +393,339 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    fn test_fifo_cache_pinned_entry_survives() {
+        let mut cache = FifoCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE); // Cache is {1=1}
+        assert!(cache.pin(&1));
+        cache.insert(2, 2, TEST_TOLERANCE); // Cache is {1=1 (pinned), 2=2}
+        cache.insert(3, 3, TEST_TOLERANCE); // Evicts key 2, the oldest unpinned entry
+        assert_eq!(cache.find(&1), Some(1));
+        assert_eq!(cache.find(&2), None);
+        assert_eq!(cache.find(&3), Some(3));
+
+        assert!(cache.unpin(&1));
+        cache.insert(4, 4, TEST_TOLERANCE); // Key 1 is evictable again
+        assert_eq!(cache.find(&1), None);
+        assert!(!cache.pin(&1));
+    }
+
+    #[test]
+    fn test_fifo_cache_all_pinned_rejects_newcomer() {
+        let mut cache = FifoCache::new(1).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.pin(&1);
+        cache.insert(2, 2, TEST_TOLERANCE); // No room: the unpinned newcomer is dropped
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.find(&1), Some(1));
+        assert_eq!(cache.find(&2), None);
+    }
+
+    #[test]
+    fn test_fifo_cache_set_capacity() {
+        let mut cache = FifoCache::new(4).unwrap();
+        for i in 1..=4 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        cache.pin(&1);
+        let evicted = cache.set_capacity(2).unwrap();
+        // the oldest unpinned entries go first
+        assert_eq!(
+            evicted,
+            vec![(2, 2, TEST_TOLERANCE), (3, 3, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.capacity(), 2);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.find(&1), Some(1));
+        assert_eq!(cache.find(&4), Some(4));
+
+        assert!(cache.set_capacity(3).unwrap().is_empty());
+        cache.insert(5, 5, TEST_TOLERANCE);
+        assert_eq!(cache.len(), 3);
+        assert!(cache.set_capacity(0).is_err());
+    }
+
+    #[test]
+    fn test_fifo_cache_memory_bytes() {
+        let mut cache: FifoCache<i16, String> = FifoCache::new(4).unwrap();
+        cache.insert(1, String::with_capacity(100), TEST_TOLERANCE);
+        let one = cache.memory_bytes();
+        assert!(one >= 100 + size_of::<String>());
+
+        // a larger value takes more, its slot was already allocated
+        cache.insert(2, String::with_capacity(300), TEST_TOLERANCE);
+        assert_eq!(cache.memory_bytes(), one + 300);
+    }
+
+    #[test]
+    fn test_fifo_cache_compact() {
+        let mut cache = FifoCache::new(64).unwrap();
+        for i in 0..64 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        cache.set_capacity(2).unwrap();
+        let before = cache.memory_bytes();
+        // maintenance runs on every tick, so it leaves the tables alone
+        cache.maintain();
+        assert_eq!(cache.memory_bytes(), before);
+        cache.compact();
+        assert!(cache.memory_bytes() < before);
+        assert_eq!(cache.find(&63), Some(63));
+    }
+
+    #[test]
+    fn test_fifo_cache_entry_info() {
+        let mut cache = FifoCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        cache.find(&1);
+        cache.find(&1);
+        assert_eq!(cache.entry_info(&1).unwrap().hits, 2);
+        assert_eq!(cache.entry_info(&2).unwrap().hits, 0);
+        assert!(cache.entry_info(&3).is_none());
+
+        let hits: Vec<(i16, u64)> = cache.entry_infos().map(|(k, i)| (*k, i.hits)).collect();
+        assert_eq!(hits, vec![(1, 2), (2, 0)]);
+    }
+
+    #[test]
+    fn test_fifo_cache_find_with_age() {
+        let mut cache = FifoCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        cache.insert(2, 2, TEST_TOLERANCE);
+        let (value, old) = cache.find_with_age(&1).unwrap();
+        let (_, young) = cache.find_with_age(&2).unwrap();
+        assert_eq!(value, 1);
+        assert!(old >= std::time::Duration::from_millis(5));
+        assert!(young < old);
+        assert!(cache.find_with_age(&3).is_none());
+    }
+
+    #[test]
+    fn test_fifo_cache_iter_and_drain() {
+        let mut cache = FifoCache::new(3).unwrap();
+        cache.insert(1, 10, TEST_TOLERANCE);
+        cache.insert(2, 20, 2.0);
+        let items: Vec<(i16, i16, f32)> = cache.iter().map(|(k, v, t)| (*k, v, t)).collect();
+        assert_eq!(items, vec![(1, 10, TEST_TOLERANCE), (2, 20, 2.0)]);
+        assert_eq!(cache.len(), 2);
+
+        let drained: Vec<(i16, i16, f32)> = cache.drain().collect();
+        assert_eq!(drained, vec![(1, 10, TEST_TOLERANCE), (2, 20, 2.0)]);
+        assert!(cache.is_empty());
+        assert_eq!(cache.find(&1), None);
+    }
+
+    #[test]
+    fn test_fifo_cache_recent_hit_rate() {
+        let mut cache = FifoCache::new(2).unwrap();
+        assert_eq!(cache.recent_hit_rate(), 0.0);
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.find(&1);
+        assert_eq!(cache.recent_hit_rate(), 1.0);
+        cache.find(&2);
+        assert!((cache.recent_hit_rate() - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_fifo_cache_find_k() {
+        let mut cache = FifoCache::new(4).unwrap();
+        cache.insert(10, 10, 3.0);
+        cache.insert(12, 12, 3.0);
+        cache.insert(11, 11, 3.0);
+        cache.insert(20, 20, 3.0);
+        assert_eq!(cache.find_k(&10, 2), vec![(10, 0.0), (11, 1.0)]);
+        assert_eq!(cache.find_k(&10, 5), vec![(10, 0.0), (11, 1.0), (12, 2.0)]);
+        assert!(cache.find_k(&50, 3).is_empty());
+        assert_eq!(cache.entry_info(&11).unwrap().hits, 2);
+    }
+
+    #[test]
+    fn test_fifo_cache_find_aggregate() {
+        let mut cache: FifoCache<i16, f32> = FifoCache::new(3).unwrap();
+        cache.insert(10, 1.0, 5.0);
+        cache.insert(12, 3.0, 5.0);
+        let blend = |matches: &[(f32, f32)]| matches.iter().map(|(v, w)| v * w).sum();
+        let mean = cache.find_aggregate(&11, 2, Weighting::Uniform, &blend);
+        assert_eq!(mean, Some(2.0));
+        let nearest = cache.find_aggregate(&10, 2, Weighting::InverseDistance, &blend);
+        assert_eq!(nearest, Some(1.0));
+        assert_eq!(
+            cache.find_aggregate(&50, 2, Weighting::Uniform, &blend),
+            None
+        );
+    }
+
+    #[test]
+    fn test_fifo_cache_next_victim() {
+        let mut cache = FifoCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        assert_eq!(cache.next_victim(&3), None); // Not full yet
+        cache.insert(2, 2, TEST_TOLERANCE);
+        assert_eq!(cache.next_victim(&3), Some(&1));
+        cache.pin(&1);
+        assert_eq!(cache.next_victim(&3), Some(&2));
+    }
+
+    #[test]
+    fn test_fifo_cache_insert_evicting() {
+        let mut cache = FifoCache::new(1).unwrap();
+        assert!(cache.insert_evicting(1, 1, TEST_TOLERANCE).is_empty());
+        assert_eq!(
+            cache.insert_evicting(2, 2, TEST_TOLERANCE),
+            vec![(1, 1, TEST_TOLERANCE)]
+        );
+        cache.pin(&2);
+        // No room for the newcomer, which is handed back
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(3, 3, TEST_TOLERANCE)]
+        );
+    }
+
+    #[test]
+    fn test_fifo_cache_nearest() {
+        let mut cache = FifoCache::new(2).unwrap();
+        assert_eq!(cache.nearest(&1), None);
+        cache.insert(10, 10, 1.0);
+        cache.insert(20, 20, 2.0);
+        assert_eq!(cache.nearest(&17), Some((3.0, 2.0)));
+        assert_eq!(cache.find(&17), None);
+    }
+
+    #[test]
     fn test_fifo_cache_empty() {
-        let _cache: FifoCache<i16, i16> = FifoCache::new(0);
+        let cache: Result<FifoCache<i16, i16>> = FifoCache::new(0);
+        assert!(matches!(cache, Err(ProximityError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_dimension_is_fixed_by_first_insert() {
+        use crate::simulation::SimKey;
+
+        let mut cache = FifoCache::new(2).unwrap();
+        assert_eq!(cache.key_dim(), None);
+        cache.insert(SimKey(vec![0.0; 8]), 1, TEST_TOLERANCE);
+        assert_eq!(cache.key_dim(), Some(8));
+        let err = cache.check_dim(&SimKey(vec![0.0; 16])).unwrap_err();
+        assert!(matches!(
+            err,
+            ProximityError::DimensionMismatch {
+                expected: 8,
+                found: 16
+            }
+        ));
+    }
+
+    #[test]
+    fn test_try_find_and_try_insert_report_mismatch() {
+        use crate::simulation::SimKey;
+
+        let mut cache = FifoCache::new(2).unwrap();
+        cache
+            .try_insert(SimKey(vec![0.0; 8]), 1, TEST_TOLERANCE)
+            .unwrap();
+        let short = SimKey(vec![0.0; 4]);
+        assert!(matches!(
+            cache.try_find(&short),
+            Err(ProximityError::DimensionMismatch {
+                expected: 8,
+                found: 4
+            })
+        ));
+        assert!(cache.try_insert(short, 2, TEST_TOLERANCE).is_err());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.try_find(&SimKey(vec![0.0; 8])).unwrap(), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a key of dimension 8, got 16")]
+    fn test_find_with_wrong_dimension() {
+        use crate::simulation::SimKey;
+
+        let mut cache = FifoCache::new(2).unwrap();
+        cache.insert(SimKey(vec![0.0; 8]), 1, TEST_TOLERANCE);
+        cache.find(&SimKey(vec![0.0; 16]));
+    }
+
+    #[test]
+    fn test_fifo_cache_priority() {
+        let mut cache = FifoCache::new(2).unwrap();
+        cache.insert_with_priority(1, 1, TEST_TOLERANCE, 1);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        // key 1 is the oldest, but of a higher priority
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.entry_info(&1).unwrap().priority, 1);
+        cache.insert_with_priority(4, 4, TEST_TOLERANCE, 1);
+        // a newcomer of a lower priority than every entry goes first
+        assert_eq!(
+            cache.insert_evicting(5, 5, TEST_TOLERANCE),
+            vec![(5, 5, TEST_TOLERANCE)]
+        );
+
+        cache.set_capacity(3).unwrap();
+        cache.insert(6, 6, TEST_TOLERANCE);
+        // the lowest priority first, then the oldest
+        let drained: Vec<_> = cache.drain().map(|(k, _, _)| k).collect();
+        assert_eq!(drained, vec![6, 1, 4]);
+    }
+
+    #[test]
+    fn test_fifo_cache_merge_keeps_priorities_of_equal_keys() {
+        let mut cache = FifoCache::new(3).unwrap();
+        cache.insert_with_priority(1, 1, TEST_TOLERANCE, 2);
+        cache.insert(1, 10, TEST_TOLERANCE);
+        let mut other = FifoCache::new(1).unwrap();
+        other.insert_with_priority(2, 2, TEST_TOLERANCE, 1);
+
+        assert!(cache.merge(other).is_empty());
+        let drained: Vec<_> = cache.drain().map(|(_, v, _)| v).collect();
+        assert_eq!(drained, vec![10, 2, 1]);
+    }
+
+    #[test]
+    fn test_fifo_cache_retain() {
+        let mut cache = FifoCache::new(3).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert_with_priority(2, 2, TEST_TOLERANCE, 1);
+        cache.insert(3, 3, TEST_TOLERANCE);
+        cache.pin(&3);
+        assert_eq!(
+            cache.retain(&mut |key, _| *key != 1),
+            vec![(1, 1, TEST_TOLERANCE)]
+        );
+        // key 3 is still pinned, so only key 2 can be evicted
+        cache.insert(4, 4, TEST_TOLERANCE);
+        assert_eq!(cache.next_victim(&5), Some(&4));
+        cache.insert_with_priority(5, 5, TEST_TOLERANCE, 1);
+        assert_eq!(
+            cache.iter().map(|(k, _, _)| *k).collect::<Vec<_>>(),
+            vec![2, 3, 5]
+        );
+    }
+
+    #[test]
+    fn test_fifo_cache_priority_of_pinned_entries() {
+        let mut cache = FifoCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert_with_priority(2, 2, TEST_TOLERANCE, 1);
+        // once key 1 is pinned, the lowest priority that can be evicted is 1
+        assert!(cache.pin(&1));
+        assert_eq!(cache.next_victim(&3), Some(&2));
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(3, 3, TEST_TOLERANCE)]
+        );
+        assert!(cache.unpin(&1));
+        assert_eq!(cache.next_victim(&3), Some(&1));
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(1, 1, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.drain().count(), 2);
+        assert_eq!(cache.next_victim(&3), None);
     }
 }
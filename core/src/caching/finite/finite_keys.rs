@@ -0,0 +1,177 @@
+use std::borrow::Cow;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::finite::NonFinitePolicy;
+use crate::caching::EntryInfo;
+use crate::numerics::ApproxComparable;
+use crate::Result;
+
+/// Wraps a cache to enforce a [`NonFinitePolicy`] on every inserted and queried key.
+///
+/// Rejected inserts are not admitted and come back from `insert_evicting`,
+/// and rejected lookups miss. Use [`FiniteKeys::check_finite`] to report a
+/// rejected key as an error instead.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, FifoCache, FiniteKeys, NonFinitePolicy};
+///
+/// let mut cache = FiniteKeys::new(FifoCache::new(4).unwrap(), NonFinitePolicy::Sanitize);
+/// cache.insert(f32::NAN, "Value 1", 0.5); // Stored under key 0
+/// assert_eq!(cache.find(&0.1), Some("Value 1"));
+///
+/// let mut cache = FiniteKeys::new(FifoCache::new(4).unwrap(), NonFinitePolicy::Reject);
+/// cache.insert(f32::INFINITY, "Value 1", 0.5); // Not admitted
+/// assert!(cache.is_empty());
+/// assert!(cache.check_finite(&f32::NAN).is_err());
+/// ```
+pub struct FiniteKeys<C> {
+    inner: C,
+    policy: NonFinitePolicy,
+}
+
+impl<C> FiniteKeys<C> {
+    pub fn new(inner: C, policy: NonFinitePolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    pub fn policy(&self) -> NonFinitePolicy {
+        self.policy
+    }
+
+    /// Fails with [`ProximityError::NonFiniteKey`](crate::ProximityError::NonFiniteKey)
+    /// if the policy refuses `key`.
+    pub fn check_finite<K: ApproxComparable>(&self, key: &K) -> Result<()> {
+        self.policy.check(key)
+    }
+
+    /// The key `target` is looked up as, or `None` if the policy refuses it.
+    fn admit<'a, K: ApproxComparable + Clone>(&self, target: &'a K) -> Option<Cow<'a, K>> {
+        if target.is_finite() || self.policy == NonFinitePolicy::Allow {
+            return Some(Cow::Borrowed(target));
+        }
+        let mut owned = target.clone();
+        self.policy.apply(&mut owned).ok()?;
+        Some(Cow::Owned(owned))
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for FiniteKeys<C>
+where
+    K: ApproxComparable + Clone,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        default_tolerance, len, candidates, key_dim, entry_infos, iter, drain, retain, maintain, compact,
+        recent_hit_rate, memory_bytes,
+    );
+
+    fn find(&mut self, target: &K) -> Option<V> {
+        let target = self.admit(target)?;
+        self.inner.find(&target)
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        match self.admit(target) {
+            Some(target) => self.inner.find_k(&target, k),
+            None => Vec::new(),
+        }
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        mut key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        if self.policy.apply(&mut key).is_err() {
+            return vec![(key, value, tolerance)];
+        }
+        self.inner
+            .insert_with_priority(key, value, tolerance, priority)
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        self.inner.nearest(&*self.admit(target)?)
+    }
+
+    fn next_victim(&self, incoming: &K) -> Option<&K> {
+        self.inner.next_victim(&*self.admit(incoming)?)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        match self.admit(target) {
+            Some(target) => self.inner.pin(&target),
+            None => false,
+        }
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        match self.admit(target) {
+            Some(target) => self.inner.unpin(&target),
+            None => false,
+        }
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        self.inner.entry_info(&*self.admit(target)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LruCache};
+    use crate::numerics::SIMD_LANECOUNT;
+    use crate::simulation::SimKey;
+    use crate::ProximityError;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    fn key_with(x: f32) -> SimKey {
+        let mut v = vec![1.0; SIMD_LANECOUNT];
+        v[0] = x;
+        SimKey::from(v)
+    }
+
+    #[test]
+    fn test_finite_keys_reject() {
+        let mut cache = FiniteKeys::new(FifoCache::new(2).unwrap(), NonFinitePolicy::Reject);
+        let rejected = cache.insert_evicting(key_with(f32::NAN), 1, TEST_TOLERANCE);
+        assert_eq!(rejected.len(), 1);
+        assert!(cache.is_empty());
+
+        cache.insert(key_with(1.0), 1, TEST_TOLERANCE);
+        assert_eq!(cache.find(&key_with(f32::INFINITY)), None);
+        assert_eq!(cache.nearest(&key_with(f32::NAN)), None);
+        assert!(matches!(
+            cache.check_finite(&key_with(f32::NAN)),
+            Err(ProximityError::NonFiniteKey)
+        ));
+        assert!(cache.check_finite(&key_with(1.0)).is_ok());
+    }
+
+    #[test]
+    fn test_finite_keys_sanitize() {
+        let mut cache = FiniteKeys::new(LruCache::new(2).unwrap(), NonFinitePolicy::Sanitize);
+        cache.insert(key_with(f32::NAN), 1, TEST_TOLERANCE);
+        assert_eq!(cache.find(&key_with(0.0)), Some(1));
+        assert_eq!(cache.find(&key_with(f32::NAN)), Some(1));
+        assert!(cache.check_finite(&key_with(f32::NAN)).is_ok());
+    }
+
+    #[test]
+    fn test_finite_keys_allow_does_not_panic() {
+        let mut cache = FiniteKeys::new(FifoCache::new(2).unwrap(), NonFinitePolicy::Allow);
+        cache.insert(key_with(f32::NAN), 1, TEST_TOLERANCE);
+        cache.insert(key_with(1.0), 2, TEST_TOLERANCE);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.find(&key_with(f32::NAN)), None);
+        assert_eq!(cache.find(&key_with(1.0)), Some(2));
+        assert_eq!(cache.find_k(&key_with(1.0), 2).len(), 1);
+        let (dist, _) = cache.nearest(&key_with(2.0)).unwrap();
+        assert_eq!(dist, 1.0);
+    }
+}
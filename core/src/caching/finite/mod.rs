@@ -0,0 +1,5 @@
+mod finite_keys;
+mod policy;
+
+pub use finite_keys::FiniteKeys;
+pub use policy::NonFinitePolicy;
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// What to do with keys that have NaN or infinite components.
+///
+/// A single NaN component makes every distance to that key NaN, so such keys
+/// can never be matched and silently waste a cache slot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "config",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum NonFinitePolicy {
+    /// Refuse the key: inserts are not admitted and lookups miss.
+    #[default]
+    Reject,
+    /// Replace NaN components with zero and infinite ones with the largest finite
+    /// value of the same sign, see [`ApproxComparable::sanitize`].
+    Sanitize,
+    /// Use the key as is. Entries with NaN keys never match but can still be evicted.
+    Allow,
+}
+
+impl NonFinitePolicy {
+    /// Fails with [`ProximityError::NonFiniteKey`] if this policy refuses `key`.
+    pub fn check<K: ApproxComparable + ?Sized>(self, key: &K) -> Result<()> {
+        if self == NonFinitePolicy::Reject && !key.is_finite() {
+            return Err(ProximityError::NonFiniteKey);
+        }
+        Ok(())
+    }
+
+    /// Checks `key`, then sanitizes it in place if this policy asks for it.
+    pub fn apply<K: ApproxComparable + ?Sized>(self, key: &mut K) -> Result<()> {
+        self.check(key)?;
+        if self == NonFinitePolicy::Sanitize && !key.is_finite() {
+            key.sanitize();
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for NonFinitePolicy {
+    type Err = ProximityError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "reject" => Ok(NonFinitePolicy::Reject),
+            "sanitize" => Ok(NonFinitePolicy::Sanitize),
+            "allow" => Ok(NonFinitePolicy::Allow),
+            other => Err(ProximityError::InvalidArgument(format!(
+                "unknown non-finite key policy '{other}', expected reject, sanitize or allow"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for NonFinitePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NonFinitePolicy::Reject => "reject",
+            NonFinitePolicy::Sanitize => "sanitize",
+            NonFinitePolicy::Allow => "allow",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_apply() {
+        let mut key = vec![1.0, f32::NAN, f32::INFINITY, f32::NEG_INFINITY];
+        assert!(matches!(
+            NonFinitePolicy::Reject.apply(key.as_mut_slice()),
+            Err(ProximityError::NonFiniteKey)
+        ));
+        NonFinitePolicy::Allow.apply(key.as_mut_slice()).unwrap();
+        assert!(key[1].is_nan());
+        NonFinitePolicy::Sanitize.apply(key.as_mut_slice()).unwrap();
+        assert_eq!(key, vec![1.0, 0.0, f32::MAX, f32::MIN]);
+        NonFinitePolicy::Reject.apply(key.as_mut_slice()).unwrap();
+    }
+
+    #[test]
+    fn test_policy_parse() {
+        for policy in [
+            NonFinitePolicy::Reject,
+            NonFinitePolicy::Sanitize,
+            NonFinitePolicy::Allow,
+        ] {
+            assert_eq!(
+                policy.to_string().parse::<NonFinitePolicy>().unwrap(),
+                policy
+            );
+        }
+        assert!("drop".parse::<NonFinitePolicy>().is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Forwarding of [`ApproximateCache`](crate::caching::ApproximateCache) methods from a
+//! wrapper to the cache it wraps, so that wrappers only spell out what they change.
+
+/// Implements the listed methods by calling them on the field `$inner`, inside an
+/// `impl ApproximateCache<K, V>` block. Caches of other keys or values name them after
+/// the field, e.g. `forward_cache!(inner: Scoped<N, K>, V => len, key_dim)`.
+macro_rules! forward_cache {
+    (@method $inner:ident, $k:ty, $v:ty, find) => {
+        fn find(&mut self, target: &$k) -> Option<$v> {
+            self.$inner.find(target)
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, find_k) => {
+        fn find_k(&mut self, target: &$k, k: usize) -> Vec<($v, f32)> {
+            self.$inner.find_k(target, k)
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, insert_with_priority) => {
+        fn insert_with_priority(
+            &mut self,
+            key: $k,
+            value: $v,
+            tolerance: f32,
+            priority: u32,
+        ) -> Vec<($k, $v, $crate::caching::approximate_cache::Tolerance)> {
+            self.$inner
+                .insert_with_priority(key, value, tolerance, priority)
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, default_tolerance) => {
+        fn default_tolerance(&self) -> Option<$crate::caching::approximate_cache::Tolerance> {
+            self.$inner.default_tolerance()
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, len) => {
+        fn len(&self) -> usize {
+            self.$inner.len()
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, candidates) => {
+        fn candidates(&self, target: &$k) -> usize {
+            self.$inner.candidates(target)
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, key_dim) => {
+        fn key_dim(&self) -> Option<usize> {
+            self.$inner.key_dim()
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, nearest) => {
+        fn nearest(
+            &self,
+            target: &$k,
+        ) -> Option<(f32, $crate::caching::approximate_cache::Tolerance)> {
+            self.$inner.nearest(target)
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, next_victim) => {
+        fn next_victim(&self, incoming: &$k) -> Option<&$k> {
+            self.$inner.next_victim(incoming)
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, pin) => {
+        fn pin(&mut self, target: &$k) -> bool {
+            self.$inner.pin(target)
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, unpin) => {
+        fn unpin(&mut self, target: &$k) -> bool {
+            self.$inner.unpin(target)
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, entry_info) => {
+        fn entry_info(&self, target: &$k) -> Option<$crate::caching::EntryInfo> {
+            self.$inner.entry_info(target)
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, entry_infos) => {
+        fn entry_infos(
+            &self,
+        ) -> Box<dyn Iterator<Item = (&$k, $crate::caching::EntryInfo)> + '_> {
+            self.$inner.entry_infos()
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, iter) => {
+        fn iter(
+            &self,
+        ) -> Box<
+            dyn Iterator<Item = (&$k, $v, $crate::caching::approximate_cache::Tolerance)> + '_,
+        > {
+            self.$inner.iter()
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, drain) => {
+        fn drain(
+            &mut self,
+        ) -> Box<
+            dyn Iterator<Item = ($k, $v, $crate::caching::approximate_cache::Tolerance)> + '_,
+        > {
+            self.$inner.drain()
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, retain) => {
+        fn retain(
+            &mut self,
+            keep: &mut dyn FnMut(&$k, &$v) -> bool,
+        ) -> Vec<($k, $v, $crate::caching::approximate_cache::Tolerance)>
+        where
+            $k: Clone,
+        {
+            self.$inner.retain(keep)
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, maintain) => {
+        fn maintain(&mut self) {
+            self.$inner.maintain();
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, compact) => {
+        fn compact(&mut self) {
+            self.$inner.compact();
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, recent_hit_rate) => {
+        fn recent_hit_rate(&self) -> f32 {
+            self.$inner.recent_hit_rate()
+        }
+    };
+    (@method $inner:ident, $k:ty, $v:ty, memory_bytes) => {
+        fn memory_bytes(&self) -> usize
+        where
+            $k: $crate::caching::HeapSize,
+            $v: $crate::caching::HeapSize,
+        {
+            self.$inner.memory_bytes()
+        }
+    };
+    ($inner:ident: $k:ty, $v:ty => $($method:ident),+ $(,)?) => {
+        $(forward_cache!(@method $inner, $k, $v, $method);)+
+    };
+    ($inner:ident => $($method:ident),+ $(,)?) => {
+        forward_cache!($inner: K, V => $($method),+);
+    };
+}
@@ -0,0 +1,573 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::lfu::DEFAULT_AGING_FACTOR;
+use crate::caching::memory::slots_bytes;
+use crate::caching::scan::MaybeSync;
+use crate::caching::slots::{self, Slot};
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+struct CacheLine<K, V> {
+    key: K,
+    tol: Tolerance,
+    value: V,
+    pinned: bool,
+    info: EntryInfo,
+    /// Recomputation cost over size, what a single reference is worth.
+    weight: f64,
+    /// References since the insert, the insert included, halved on every aging.
+    count: u32,
+    /// The cache inflation at the last reference.
+    base: f64,
+    /// Logical time of the last reference.
+    last_reference: u64,
+}
+
+impl<K, V> Slot<K> for CacheLine<K, V> {
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn tolerance(&self) -> Tolerance {
+        self.tol
+    }
+
+    fn pinned(&self) -> bool {
+        self.pinned
+    }
+
+    fn pinned_mut(&mut self) -> &mut bool {
+        &mut self.pinned
+    }
+}
+
+impl<K, V> CacheLine<K, V> {
+    fn score(&self) -> f64 {
+        self.base + f64::from(self.count) * self.weight
+    }
+
+    fn rank(&self) -> (u32, f64, u64) {
+        (self.info.priority, self.score(), self.last_reference)
+    }
+}
+
+/// What a reference to an entry of the given cost and size is worth.
+pub(crate) fn weight(cost: f32, size: usize) -> Result<f64> {
+    if !cost.is_finite() || cost < 0.0 {
+        return Err(ProximityError::InvalidArgument(format!(
+            "cost must be finite and non-negative, got {cost}"
+        )));
+    }
+    if size == 0 {
+        return Err(ProximityError::InvalidArgument(
+            "size must be positive".into(),
+        ));
+    }
+    Ok(f64::from(cost) / size as f64)
+}
+
+fn by_rank<K, V>(a: &CacheLine<K, V>, b: &CacheLine<K, V>) -> std::cmp::Ordering {
+    let (a, b) = (a.rank(), b.rank());
+    a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)).then(a.2.cmp(&b.2))
+}
+
+/// `GdsfCache` is a bounded cache with approximate key matching support that evicts by
+/// Greedy-Dual-Size-Frequency: the entry with the lowest score goes first, where
+///
+/// `score = inflation + frequency * cost / size`
+///
+/// Entries that are cheap to recompute, large, or rarely used leave first. The
+/// inflation is the score of the last evicted entry, added to an entry's score whenever
+/// it is referenced, so that entries not referenced for a while fall behind and
+/// eventually leave, however costly. Frequencies also age as in [`LfuCache`]: every
+/// `aging_period` references, they are halved.
+///
+/// Cost and size are given with [`insert_with_cost`](Self::insert_with_cost); a plain
+/// insert has cost 1 and size 1, the policy then being LFU with dynamic aging.
+///
+/// Finding a victim scans every entry, as lookups do.
+///
+/// [`LfuCache`]: crate::caching::LfuCache
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, GdsfCache};
+///
+/// let mut cache = GdsfCache::new(2).unwrap();
+/// const TEST_TOL: f32 = 2.0;
+///
+/// cache.insert_with_cost(10 as i16, "Expensive", TEST_TOL, 50.0, 1).unwrap();
+/// cache.insert(20, "Cheap", TEST_TOL);
+/// cache.find(&20);
+/// cache.insert(30, "Value 3", TEST_TOL); // Evicts key 20, used more but cheaper
+///
+/// assert_eq!(cache.find(&11), Some("Expensive"));
+/// assert!(cache.find(&20).is_none());
+/// ```
+pub struct GdsfCache<K, V> {
+    max_capacity: usize,
+    aging_period: u64,
+    /// References since the counts were last halved.
+    since_aging: u64,
+    /// Logical clock, ticking on every reference.
+    clock: u64,
+    /// Score of the last evicted entry, never decreasing.
+    inflation: f64,
+    items: Vec<CacheLine<K, V>>,
+    hit_rate: HitRateTracker,
+    /// Dimension of the first vector key inserted.
+    dim: Option<usize>,
+}
+
+impl<K, V> ApproximateCache<K, V> for GdsfCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn find(&mut self, target: &K) -> Option<V> {
+        trace_span!("find", cache = "gdsf");
+        let idx = slots::find(&self.items, self.dim, target, &mut self.hit_rate)?;
+        self.reference(idx);
+        Some(self.items[idx].value.clone())
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "gdsf", k);
+        let matches = slots::find_k(&self.items, self.dim, target, k, &mut self.hit_rate);
+        matches
+            .into_iter()
+            .map(|(idx, dist)| {
+                self.reference(idx);
+                (self.items[idx].value.clone(), dist)
+            })
+            .collect()
+    }
+
+    /// Inserts with cost 1 and size 1, see [`insert_with_cost`](GdsfCache::insert_with_cost).
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        self.insert_weighted(key, value, tolerance, priority, 1.0)
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        slots::nearest(&self.items, self.dim, target)
+    }
+
+    fn next_victim(&self, _incoming: &K) -> Option<&K> {
+        if self.items.len() < self.max_capacity {
+            return None;
+        }
+        self.victim().map(|idx| &self.items[idx].key)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        slots::set_pinned(&mut self.items, self.dim, target, true).is_some()
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        slots::set_pinned(&mut self.items, self.dim, target, false).is_some()
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let (candidate, _) = slots::best_match(&self.items, self.dim, target)?;
+        Some(self.items[candidate].info)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(self.items.iter().map(|entry| (&entry.key, entry.info)))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(
+            self.items
+                .iter()
+                .map(|entry| (&entry.key, entry.value.clone(), entry.tol)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        self.items.sort_by(by_rank);
+        Box::new(
+            self.items
+                .drain(..)
+                .map(|entry| (entry.key, entry.value, entry.tol)),
+        )
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)> {
+        self.items
+            .extract_if(.., |entry| !keep(&entry.key, &entry.value))
+            .map(|entry| (entry.key, entry.value, entry.tol))
+            .collect()
+    }
+
+    fn compact(&mut self) {
+        self.items.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let entries: usize = self
+            .items
+            .iter()
+            .map(|line| line.key.heap_bytes() + line.value.heap_bytes())
+            .sum();
+        slots_bytes::<CacheLine<K, V>>(self.items.capacity()) + entries
+    }
+}
+
+impl<K, V> DefaultApproximateCache<K, V> for GdsfCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn from_capacity(cap: usize) -> GdsfCache<K, V> {
+        GdsfCache::new(cap).expect("bucket capacity is checked by the owning cache")
+    }
+
+    fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    fn set_capacity(&mut self, cap: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        GdsfCache::set_capacity(self, cap)
+    }
+
+    fn evict(&mut self) -> Option<(K, V, Tolerance)> {
+        self.evict_victim()
+    }
+
+    fn evict_k(&mut self, k: usize) -> Vec<(K, V, Tolerance)> {
+        let victims = slots::lowest(&self.items, k, by_rank);
+        slots::remove_all(&mut self.items, &victims)
+            .into_iter()
+            .map(|entry| self.retire(entry))
+            .collect()
+    }
+}
+
+impl<K, V> GdsfCache<K, V> {
+    pub fn new(max_capacity: usize) -> Result<Self> {
+        let aging_period = (max_capacity as u64).saturating_mul(DEFAULT_AGING_FACTOR);
+        Self::with_aging_period(max_capacity, aging_period)
+    }
+
+    /// Halves every reference count once every `aging_period` references.
+    pub fn with_aging_period(max_capacity: usize, aging_period: u64) -> Result<Self> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        if aging_period == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "aging period must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            max_capacity,
+            aging_period,
+            since_aging: 0,
+            clock: 0,
+            inflation: 0.0,
+            items: Vec::with_capacity(max_capacity),
+            hit_rate: HitRateTracker::default(),
+            dim: None,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    pub fn aging_period(&self) -> u64 {
+        self.aging_period
+    }
+
+    /// Score of the last evicted entry, which every referenced entry gets on top of its
+    /// own.
+    pub fn inflation(&self) -> f64 {
+        self.inflation
+    }
+
+    /// Changes the capacity, evicting the lowest scored unpinned entries until the cache
+    /// fits in it, and returns them. Pinned entries stay, even above the new capacity.
+    pub fn set_capacity(&mut self, max_capacity: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        self.max_capacity = max_capacity;
+        Ok(self.evict_down_to(max_capacity))
+    }
+
+    /// Evicts unpinned entries while the cache holds more than `len` of them.
+    fn evict_down_to(&mut self, len: usize) -> Vec<(K, V, Tolerance)> {
+        let mut evicted = Vec::new();
+        while self.items.len() > len {
+            match self.evict_victim() {
+                Some(entry) => evicted.push(entry),
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    /// Whether the cache is full of entries of a higher priority than a newcomer's,
+    /// which eviction would then take first.
+    fn outranks_newcomer(&self, priority: u32) -> bool {
+        self.items.len() >= self.max_capacity
+            && self
+                .victim()
+                .is_some_and(|idx| self.items[idx].info.priority > priority)
+    }
+
+    /// Index of the unpinned entry to evict first, if any.
+    fn victim(&self) -> Option<usize> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.pinned)
+            .min_by(|(_, a), (_, b)| by_rank(a, b))
+            .map(|(idx, _)| idx)
+    }
+
+    fn evict_victim(&mut self) -> Option<(K, V, Tolerance)> {
+        let victim = self.victim()?;
+        let entry = self.items.swap_remove(victim);
+        Some(self.retire(entry))
+    }
+
+    /// Raises the inflation to the score of the evicted `entry`, and returns it.
+    fn retire(&mut self, entry: CacheLine<K, V>) -> (K, V, Tolerance) {
+        self.inflation = self.inflation.max(entry.score());
+        trace_event!(
+            score = entry.score(),
+            tolerance = entry.tol,
+            hits = entry.info.hits,
+            "evicted"
+        );
+        (entry.key, entry.value, entry.tol)
+    }
+
+    /// Counts a reference to the entry at `idx`, the insert included.
+    fn reference(&mut self, idx: usize) {
+        self.clock += 1;
+        let entry = &mut self.items[idx];
+        if entry.count > 0 {
+            entry.info.record_hit();
+        }
+        entry.count = entry.count.saturating_add(1);
+        entry.base = self.inflation;
+        entry.last_reference = self.clock;
+        self.since_aging += 1;
+        if self.since_aging >= self.aging_period {
+            self.since_aging = 0;
+            for entry in &mut self.items {
+                entry.count /= 2;
+            }
+        }
+    }
+}
+
+impl<K, V> GdsfCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    /// Inserts an entry that costs `cost` to recompute, in any unit, and takes `size`,
+    /// e.g. in bytes. Both only matter relative to those of the other entries.
+    ///
+    /// Returns the evicted entries, or an error if `cost` is negative or not finite, or
+    /// if `size` is 0.
+    pub fn insert_with_cost(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        cost: f32,
+        size: usize,
+    ) -> Result<Vec<(K, V, Tolerance)>> {
+        let weight = weight(cost, size)?;
+        Ok(self.insert_weighted(key, value, tolerance, 0, weight))
+    }
+
+    pub(crate) fn insert_weighted(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+        weight: f64,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "gdsf", tolerance, priority, weight);
+        self.check_dim(&key).unwrap_or_else(|e| panic!("{e}"));
+        self.dim = self.dim.or(key.dimension());
+        if self.outranks_newcomer(priority) {
+            return vec![(key, value, tolerance)];
+        }
+        // room is made before inserting, the newcomer is not weighed against the victim
+        let mut evicted = self.evict_down_to(self.max_capacity - 1);
+        if self.items.len() >= self.max_capacity {
+            evicted.push((key, value, tolerance));
+            return evicted;
+        }
+        self.items.push(CacheLine {
+            key,
+            tol: tolerance,
+            value,
+            pinned: false,
+            info: EntryInfo::with_priority(priority),
+            weight,
+            count: 0,
+            base: 0.0,
+            last_reference: 0,
+        });
+        self.reference(self.items.len() - 1);
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_gdsf_weighs_cost_and_size() {
+        let mut cache = GdsfCache::new(2).unwrap();
+        cache
+            .insert_with_cost(1, 1, TEST_TOLERANCE, 10.0, 1)
+            .unwrap();
+        cache.insert(2, 2, TEST_TOLERANCE);
+        cache.find(&2);
+        // key 2 is used twice as often, but ten times cheaper
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+
+        let mut cache = GdsfCache::new(2).unwrap();
+        cache
+            .insert_with_cost(1, 1, TEST_TOLERANCE, 2.0, 4)
+            .unwrap();
+        cache.insert(2, 2, TEST_TOLERANCE);
+        // key 1 costs twice as much, but takes four times the room
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(1, 1, TEST_TOLERANCE)]
+        );
+    }
+
+    #[test]
+    fn test_gdsf_inflation_lets_stale_entries_go() {
+        let mut cache = GdsfCache::new(2).unwrap();
+        cache
+            .insert_with_cost(1, 1, TEST_TOLERANCE, 5.0, 1)
+            .unwrap();
+        let mut evicted = Vec::new();
+        for key in 2..10 {
+            evicted.extend(cache.insert_evicting(key, key, TEST_TOLERANCE));
+        }
+        // every cheap newcomer starts from the score of the previous victim, until one
+        // outscores the expensive key 1, never referenced again
+        assert_eq!(
+            evicted.iter().map(|(key, _, _)| *key).collect::<Vec<_>>(),
+            vec![2, 3, 4, 5, 1, 6, 7]
+        );
+        assert!(cache.inflation() >= 5.0);
+    }
+
+    #[test]
+    fn test_gdsf_pinned_and_capacity() {
+        let mut cache = GdsfCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        cache.pin(&1);
+        cache.pin(&2);
+        let rejected = cache.insert_evicting(3, 3, TEST_TOLERANCE);
+        assert_eq!(rejected, vec![(3, 3, TEST_TOLERANCE)]);
+
+        cache.unpin(&1);
+        assert_eq!(cache.next_victim(&3), Some(&1));
+        assert_eq!(cache.set_capacity(1).unwrap(), vec![(1, 1, TEST_TOLERANCE)]);
+        assert!(cache.set_capacity(0).is_err());
+        assert!(GdsfCache::<i16, i32>::with_aging_period(2, 0).is_err());
+        assert!(cache
+            .insert_with_cost(4, 4, TEST_TOLERANCE, -1.0, 1)
+            .is_err());
+        assert!(cache
+            .insert_with_cost(4, 4, TEST_TOLERANCE, f32::NAN, 1)
+            .is_err());
+        assert!(cache
+            .insert_with_cost(4, 4, TEST_TOLERANCE, 1.0, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_gdsf_priority() {
+        let mut cache = GdsfCache::new(2).unwrap();
+        cache.insert_with_priority(1, 1, TEST_TOLERANCE, 1);
+        cache
+            .insert_with_cost(2, 2, TEST_TOLERANCE, 10.0, 1)
+            .unwrap();
+        // key 1 is cheaper, but of a higher priority
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.entry_info(&1).unwrap().priority, 1);
+    }
+
+    #[test]
+    fn test_gdsf_evict_k_in_eviction_order() {
+        let filled = || {
+            let mut cache = GdsfCache::new(6).unwrap();
+            for i in 1..=5 {
+                cache.insert(i, i, TEST_TOLERANCE);
+            }
+            cache
+                .insert_with_cost(6, 6, TEST_TOLERANCE, 10.0, 1)
+                .unwrap();
+            for i in [5, 1, 5, 3] {
+                cache.find(&i);
+            }
+            cache.pin(&4);
+            cache
+        };
+        let mut one_by_one = filled();
+        let evicted: Vec<_> = (0..3).map_while(|_| one_by_one.evict()).collect();
+        let mut cache = filled();
+        assert_eq!(cache.evict_k(3), evicted);
+        assert_eq!(cache.len(), 3);
+        // the pinned entry stays however many are asked for
+        assert_eq!(cache.evict_k(5).len(), 2);
+        assert_eq!(cache.find(&4), Some(4));
+    }
+}
@@ -0,0 +1,3 @@
+mod gdsf_cache;
+pub(crate) use gdsf_cache::weight;
+pub use gdsf_cache::GdsfCache;
@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+
+/// Bounded record of recently evicted keys, without their values.
+///
+/// Once full, recording a new key forgets the oldest one.
+pub struct GhostList<K> {
+    max_capacity: usize,
+    ghosts: VecDeque<(K, Tolerance)>,
+}
+
+impl<K> GhostList<K>
+where
+    K: ApproxComparable,
+{
+    pub fn new(max_capacity: usize) -> Self {
+        assert!(max_capacity > 0);
+        Self {
+            max_capacity,
+            ghosts: VecDeque::with_capacity(max_capacity),
+        }
+    }
+
+    pub fn record(&mut self, key: K, tolerance: Tolerance) {
+        self.ghosts.push_back((key, tolerance));
+        if self.ghosts.len() > self.max_capacity {
+            self.ghosts.pop_front();
+        }
+    }
+
+    /// Whether `target` would have matched one of the evicted keys.
+    pub fn matches(&self, target: &K) -> bool {
+        self.ghosts
+            .iter()
+            .any(|(key, tol)| key.roughly_matches(target, *tol))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ghosts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ghosts.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.ghosts.clear();
+    }
+
+    /// Releases the memory of ghosts that were forgotten or cleared.
+    pub fn shrink_to_fit(&mut self) {
+        self.ghosts.shrink_to_fit();
+    }
+}
+
+impl<K: HeapSize> HeapSize for GhostList<K> {
+    fn heap_bytes(&self) -> usize {
+        self.ghosts.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_ghost_list_bounded() {
+        let mut ghosts = GhostList::new(2);
+        ghosts.record(1i16, TEST_TOLERANCE);
+        ghosts.record(2, TEST_TOLERANCE);
+        ghosts.record(3, TEST_TOLERANCE); // Forgets key 1
+        assert_eq!(ghosts.len(), 2);
+        assert!(!ghosts.matches(&1));
+        assert!(ghosts.matches(&2));
+        assert!(ghosts.matches(&3));
+    }
+
+    #[test]
+    fn test_ghost_list_matches_within_tolerance() {
+        let mut ghosts = GhostList::new(2);
+        ghosts.record(10i16, 2.0);
+        assert!(ghosts.matches(&11));
+        assert!(!ghosts.matches(&13));
+        ghosts.clear();
+        assert!(ghosts.is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::ghost::GhostList;
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+use crate::ProximityError;
+
+/// Why a lookup missed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissKind {
+    /// Nothing similar was ever stored: no cache configuration would have helped.
+    Cold,
+    /// A matching entry was stored but got evicted: a larger capacity would have hit.
+    Capacity,
+    /// A stored entry is close but outside its tolerance: a looser tolerance would have hit.
+    Tolerance,
+}
+
+/// Number of misses of each kind observed so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MissBreakdown {
+    pub cold: u64,
+    pub capacity: u64,
+    pub tolerance: u64,
+}
+
+impl MissBreakdown {
+    pub fn total(&self) -> u64 {
+        self.cold + self.capacity + self.tolerance
+    }
+
+    fn record(&mut self, kind: MissKind) {
+        match kind {
+            MissKind::Cold => self.cold += 1,
+            MissKind::Capacity => self.capacity += 1,
+            MissKind::Tolerance => self.tolerance += 1,
+        }
+    }
+}
+
+/// Wraps a cache to classify each of its misses.
+///
+/// Evicted keys are remembered in a [`GhostList`]. A miss that would have matched
+/// a ghost is a capacity miss. Otherwise, a miss whose nearest stored key lies
+/// within `tolerance_slack` times that entry's tolerance is a tolerance miss.
+/// Everything else is a cold miss.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::ghost::{MissAnalyzer, MissKind};
+/// use proximity::caching::{ApproximateCache, FifoCache};
+///
+/// let mut cache = MissAnalyzer::new(FifoCache::new(1).unwrap(), 16, 2.0).unwrap();
+/// cache.insert(10 as i16, "Value 1", 1.0);
+/// cache.insert(20, "Value 2", 1.0); // Evicts key 10
+///
+/// assert_eq!(cache.find_classified(&10), Err(MissKind::Capacity));
+/// assert_eq!(cache.find_classified(&21), Err(MissKind::Tolerance));
+/// assert_eq!(cache.find_classified(&50), Err(MissKind::Cold));
+/// assert_eq!(cache.miss_breakdown().total(), 3);
+/// ```
+pub struct MissAnalyzer<K, C> {
+    inner: C,
+    ghosts: GhostList<K>,
+    tolerance_slack: f32,
+    breakdown: MissBreakdown,
+}
+
+impl<K, C> MissAnalyzer<K, C>
+where
+    K: ApproxComparable,
+{
+    /// Remembers up to `ghost_capacity` evicted keys.
+    pub fn new(inner: C, ghost_capacity: usize, tolerance_slack: f32) -> crate::Result<Self> {
+        if ghost_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "ghost capacity must be positive".into(),
+            ));
+        }
+        if tolerance_slack.is_nan() || tolerance_slack < 1.0 {
+            return Err(ProximityError::InvalidArgument(format!(
+                "tolerance slack must be at least 1, got {tolerance_slack}"
+            )));
+        }
+        Ok(Self {
+            inner,
+            ghosts: GhostList::new(ghost_capacity),
+            tolerance_slack,
+            breakdown: MissBreakdown::default(),
+        })
+    }
+
+    /// Classifies a lookup of `target`, assuming it misses.
+    pub fn classify_miss<V>(&self, target: &K) -> MissKind
+    where
+        C: ApproximateCache<K, V>,
+    {
+        if self.ghosts.matches(target) {
+            return MissKind::Capacity;
+        }
+        match self.inner.nearest(target) {
+            Some((dist, tol)) if dist < tol * self.tolerance_slack => MissKind::Tolerance,
+            _ => MissKind::Cold,
+        }
+    }
+
+    /// Like `find`, but reports why the lookup missed.
+    pub fn find_classified<V>(&mut self, target: &K) -> Result<V, MissKind>
+    where
+        C: ApproximateCache<K, V>,
+    {
+        match self.inner.find(target) {
+            Some(value) => Ok(value),
+            None => {
+                let kind = self.classify_miss(target);
+                self.breakdown.record(kind);
+                Err(kind)
+            }
+        }
+    }
+
+    pub fn miss_breakdown(&self) -> MissBreakdown {
+        self.breakdown
+    }
+
+    pub fn ghosts(&self) -> &GhostList<K> {
+        &self.ghosts
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for MissAnalyzer<K, C>
+where
+    K: ApproxComparable + Clone,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        default_tolerance, len, candidates, key_dim, nearest, next_victim, pin, unpin, entry_info,
+        entry_infos, iter, maintain, recent_hit_rate,
+    );
+
+    fn find(&mut self, target: &K) -> Option<V> {
+        self.find_classified(target).ok()
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        let found = self.inner.find_k(target, k);
+        if found.is_empty() {
+            let kind = self.classify_miss(target);
+            self.breakdown.record(kind);
+        }
+        found
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        let evicted = self
+            .inner
+            .insert_with_priority(key, value, tolerance, priority);
+        for (key, _, tol) in evicted.iter() {
+            self.ghosts.record(key.clone(), *tol);
+        }
+        evicted
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        self.ghosts.clear();
+        self.inner.drain()
+    }
+
+    fn compact(&mut self) {
+        self.ghosts.shrink_to_fit();
+        self.inner.compact();
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.inner.memory_bytes() + self.ghosts.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::LruCache;
+
+    #[test]
+    fn test_miss_analyzer_breakdown() {
+        let mut cache = MissAnalyzer::new(LruCache::new(2).unwrap(), 4, 2.0).unwrap();
+        cache.insert(10, 10, 1.0);
+        cache.insert(20, 20, 1.0);
+        cache.insert(30, 30, 1.0); // Evicts key 10
+
+        assert_eq!(cache.find(&20), Some(20));
+        assert_eq!(cache.find(&10), None); // Capacity
+        assert_eq!(cache.find(&31), None); // Tolerance, 1 < 31 - 30 < 2
+        assert_eq!(cache.find(&-50), None); // Cold
+        assert_eq!(cache.find(&-51), None); // Cold
+
+        assert_eq!(
+            cache.miss_breakdown(),
+            MissBreakdown {
+                cold: 2,
+                capacity: 1,
+                tolerance: 1
+            }
+        );
+        assert_eq!(cache.ghosts().len(), 1);
+    }
+
+    #[test]
+    fn test_miss_analyzer_rejected_newcomer_is_a_ghost() {
+        let mut cache = MissAnalyzer::new(LruCache::new(1).unwrap(), 4, 1.0).unwrap();
+        cache.insert(10, 10, 1.0);
+        cache.pin(&10);
+        cache.insert(20, 20, 1.0); // Cannot be admitted
+        assert_eq!(cache.find_classified(&20), Err(MissKind::Capacity));
+    }
+}
@@ -0,0 +1,5 @@
+mod ghost_list;
+mod miss_analyzer;
+
+pub use ghost_list::GhostList;
+pub use miss_analyzer::{MissAnalyzer, MissBreakdown, MissKind};
@@ -0,0 +1,140 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::intern::{InternedVec, KeyInterner};
+use crate::caching::HeapSize;
+use crate::Result;
+
+/// Wraps a cache to deduplicate the keys it stores: every inserted key goes through a
+/// [`KeyInterner`], so that keys within `dedup_tolerance` of a key already stored share
+/// its vector instead of storing their own.
+///
+/// This pays off when many entries have nearly identical keys, e.g. clusters of
+/// embeddings. A merged entry is stored under the shared vector, so it matches queries
+/// around a key up to `dedup_tolerance` away from the one it was inserted with. Caches
+/// that index entries by exact key, like [`LruCache`](crate::caching::LruCache), then
+/// replace an entry merged with another of the same tolerance rather than keep both.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, FifoCache, InternedKeys, InternedVec};
+///
+/// let mut cache = InternedKeys::new(FifoCache::new(4).unwrap(), 0.01).unwrap();
+/// cache.insert(InternedVec::from(vec![1.0; 8]), "Value 1", 0.5);
+/// cache.insert(InternedVec::from(vec![1.001; 8]), "Value 2", 0.5); // Shares the first key
+///
+/// assert_eq!(cache.interner().len(), 1);
+/// assert_eq!(cache.len(), 2);
+/// ```
+pub struct InternedKeys<C> {
+    inner: C,
+    interner: KeyInterner,
+}
+
+impl<C> InternedKeys<C> {
+    pub fn new(inner: C, dedup_tolerance: f32) -> Result<Self> {
+        Ok(Self {
+            inner,
+            interner: KeyInterner::new(dedup_tolerance)?,
+        })
+    }
+
+    pub fn interner(&self) -> &KeyInterner {
+        &self.interner
+    }
+}
+
+impl<V, C> ApproximateCache<InternedVec, V> for InternedKeys<C>
+where
+    C: ApproximateCache<InternedVec, V>,
+{
+    forward_cache!(inner: InternedVec, V =>
+        find, find_k, default_tolerance, len, candidates, key_dim, nearest, next_victim, pin,
+        unpin, entry_info, entry_infos, iter, drain, retain, recent_hit_rate,
+    );
+
+    fn insert_with_priority(
+        &mut self,
+        key: InternedVec,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(InternedVec, V, Tolerance)> {
+        let key = self.interner.intern(key);
+        self.inner
+            .insert_with_priority(key, value, tolerance, priority)
+    }
+
+    fn maintain(&mut self) {
+        self.inner.maintain();
+        self.interner.purge();
+    }
+
+    fn compact(&mut self) {
+        self.inner.compact();
+        self.interner.shrink_to_fit();
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        V: HeapSize,
+    {
+        self.inner.memory_bytes() + self.interner.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LshFifoCache};
+    use crate::numerics::SIMD_LANECOUNT;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+    const DEDUP_TOLERANCE: f32 = 0.01;
+
+    fn key_with(x: f32) -> InternedVec {
+        let mut v = vec![1.0; 4 * SIMD_LANECOUNT];
+        v[0] = x;
+        InternedVec::from(v)
+    }
+
+    #[test]
+    fn test_close_keys_share_storage() {
+        let mut cache = InternedKeys::new(FifoCache::new(8).unwrap(), DEDUP_TOLERANCE).unwrap();
+        for i in 0..4 {
+            cache.insert(key_with(1.0 + i as f32 * 1e-3), i, TEST_TOLERANCE);
+        }
+        cache.insert(key_with(2.0), 4, TEST_TOLERANCE);
+        assert_eq!(cache.len(), 5);
+        assert_eq!(cache.interner().len(), 2);
+
+        let keys: Vec<_> = cache.iter().map(|(k, _, _)| k.clone()).collect();
+        assert!(keys[1..4].iter().all(|k| k.shares_storage_with(&keys[0])));
+        assert!(!keys[4].shares_storage_with(&keys[0]));
+        // merged entries are stored under the first key
+        assert_eq!(cache.find(&key_with(1.0)), Some(0));
+    }
+
+    #[test]
+    fn test_interning_saves_memory() {
+        let mut plain = FifoCache::new(8).unwrap();
+        let mut interned = InternedKeys::new(FifoCache::new(8).unwrap(), DEDUP_TOLERANCE).unwrap();
+        for i in 0..8 {
+            let key = key_with(1.0 + i as f32 * 1e-3);
+            plain.insert(InternedVec::from(key.as_ref()), i, TEST_TOLERANCE);
+            interned.insert(key, i, TEST_TOLERANCE);
+        }
+        assert!(interned.memory_bytes() < plain.memory_bytes());
+    }
+
+    #[test]
+    fn test_evicted_keys_leave_the_pool() {
+        let lsh = LshFifoCache::new(4, 4 * SIMD_LANECOUNT, 1, Some(7)).unwrap();
+        let mut cache = InternedKeys::new(lsh, DEDUP_TOLERANCE).unwrap();
+        cache.insert(key_with(1.0), 1, TEST_TOLERANCE);
+        // same bucket, not merged: evicts the first key
+        cache.insert(key_with(1.5), 2, TEST_TOLERANCE);
+        cache.maintain();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.interner().len(), 1);
+    }
+}
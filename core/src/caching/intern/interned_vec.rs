@@ -0,0 +1,103 @@
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::sync::Arc;
+
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+
+/// Vector key whose components are reference-counted, so that entries with the same
+/// key, or keys merged by a [`KeyInterner`](crate::caching::KeyInterner), share one
+/// stored vector. Cloning it does not copy the components.
+#[derive(Clone, Debug)]
+pub struct InternedVec(pub(crate) Arc<[f32]>);
+
+impl InternedVec {
+    /// Whether both keys point to the same stored vector.
+    pub fn shares_storage_with(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl PartialEq for InternedVec {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(other.0.iter())
+                .all(|(a, b)| a.to_bits() == b.to_bits())
+    }
+}
+
+impl Eq for InternedVec {}
+
+impl Hash for InternedVec {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for &val in self.0.iter() {
+            state.write_u32(val.to_bits());
+        }
+    }
+}
+
+impl From<Vec<f32>> for InternedVec {
+    fn from(vector: Vec<f32>) -> Self {
+        InternedVec(vector.into())
+    }
+}
+
+impl From<&[f32]> for InternedVec {
+    fn from(vector: &[f32]) -> Self {
+        InternedVec(vector.into())
+    }
+}
+
+impl AsRef<[f32]> for InternedVec {
+    fn as_ref(&self) -> &[f32] {
+        &self.0
+    }
+}
+
+impl ApproxComparable for InternedVec {
+    #[inline]
+    fn roughly_matches(&self, instore: &Self, tolerance: f32) -> bool {
+        self.0.roughly_matches(&instore.0, tolerance)
+    }
+
+    #[inline]
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        self.0.fuzziness(&instore.0)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+
+    fn is_finite(&self) -> bool {
+        self.0.is_finite()
+    }
+
+    /// Sanitizes a copy, which other holders of the vector do not see.
+    fn sanitize(&mut self) {
+        if !self.is_finite() {
+            let mut owned = self.0.to_vec();
+            owned.sanitize();
+            self.0 = owned.into();
+        }
+    }
+
+    /// Normalizes a copy, which other holders of the vector do not see.
+    fn normalize(&mut self) {
+        let mut owned = self.0.to_vec();
+        owned.normalize();
+        self.0 = owned.into();
+    }
+}
+
+/// The share of the stored vector held by this key: the vector is split evenly
+/// between the keys pointing to it, so that summing over all of them counts it once.
+impl HeapSize for InternedVec {
+    fn heap_bytes(&self) -> usize {
+        let stored = 2 * size_of::<usize>() + size_of::<f32>() * self.0.len();
+        stored / Arc::strong_count(&self.0)
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::{Arc, Weak};
+
+use crate::caching::intern::InternedVec;
+use crate::caching::memory::slots_bytes;
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Pool of the vectors stored by live [`InternedVec`] keys, which hands out an already
+/// stored vector for any key within `tolerance` of it instead of storing a new one.
+///
+/// The pool only keeps weak references: a vector is freed once no key points to it
+/// anymore, and forgotten on the next [`intern`](Self::intern) or [`purge`](Self::purge).
+/// Interning scans the whole pool, like a lookup in an LRU or FIFO cache does.
+pub struct KeyInterner {
+    tolerance: f32,
+    pool: Vec<Weak<[f32]>>,
+}
+
+impl KeyInterner {
+    /// A `tolerance` of 0 only merges keys with identical components.
+    pub fn new(tolerance: f32) -> Result<Self> {
+        if !tolerance.is_finite() || tolerance < 0.0 {
+            return Err(ProximityError::InvalidArgument(format!(
+                "dedup tolerance must be finite and non-negative, got {tolerance}"
+            )));
+        }
+        Ok(Self {
+            tolerance,
+            pool: Vec::new(),
+        })
+    }
+
+    pub fn tolerance(&self) -> f32 {
+        self.tolerance
+    }
+
+    /// Returns `key`, or the closest stored vector within the tolerance of it.
+    /// In the latter case `key` itself is dropped, so the result may differ from it by
+    /// up to the tolerance.
+    pub fn intern(&mut self, key: InternedVec) -> InternedVec {
+        self.purge();
+        let closest = self
+            .pool
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|stored| stored.len() == key.0.len())
+            .map(|stored| {
+                let dist = stored.fuzziness(&key.0);
+                (stored, dist)
+            })
+            .filter(|(_, dist)| *dist == 0.0 || *dist < self.tolerance)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        match closest {
+            Some((stored, _)) => InternedVec(stored),
+            None => {
+                self.pool.push(Arc::downgrade(&key.0));
+                key
+            }
+        }
+    }
+
+    /// Number of stored vectors that are still pointed to.
+    pub fn len(&self) -> usize {
+        self.pool
+            .iter()
+            .filter(|weak| weak.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets the vectors no key points to anymore, releasing their memory.
+    pub fn purge(&mut self) {
+        self.pool.retain(|weak| weak.strong_count() > 0);
+    }
+
+    /// Purges the pool and releases the memory it no longer needs.
+    pub fn shrink_to_fit(&mut self) {
+        self.purge();
+        self.pool.shrink_to_fit();
+    }
+}
+
+/// The pool itself, the vectors are counted by the keys pointing to them.
+impl HeapSize for KeyInterner {
+    fn heap_bytes(&self) -> usize {
+        slots_bytes::<Weak<[f32]>>(self.pool.capacity())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::numerics::SIMD_LANECOUNT;
+
+    fn key_with(x: f32) -> InternedVec {
+        let mut v = vec![1.0; SIMD_LANECOUNT];
+        v[0] = x;
+        InternedVec::from(v)
+    }
+
+    #[test]
+    fn test_intern_merges_close_keys() {
+        let mut interner = KeyInterner::new(0.1).unwrap();
+        let first = interner.intern(key_with(1.0));
+        let close = interner.intern(key_with(1.05));
+        let far = interner.intern(key_with(2.0));
+        assert!(close.shares_storage_with(&first));
+        assert_eq!(close, key_with(1.0));
+        assert!(!far.shares_storage_with(&first));
+        assert_eq!(interner.len(), 2);
+
+        // the closest stored vector wins
+        let between = interner.intern(key_with(1.95));
+        assert!(between.shares_storage_with(&far));
+    }
+
+    #[test]
+    fn test_intern_exact_only() {
+        let mut interner = KeyInterner::new(0.0).unwrap();
+        let first = interner.intern(key_with(1.0));
+        assert!(interner.intern(key_with(1.0)).shares_storage_with(&first));
+        assert!(!interner.intern(key_with(1.01)).shares_storage_with(&first));
+        assert!(KeyInterner::new(-1.0).is_err());
+        assert!(KeyInterner::new(f32::NAN).is_err());
+    }
+
+    #[test]
+    fn test_dropped_keys_are_forgotten() {
+        let mut interner = KeyInterner::new(0.1).unwrap();
+        let first = interner.intern(key_with(1.0));
+        let second = interner.intern(key_with(1.0));
+        drop(first);
+        assert_eq!(interner.len(), 1);
+        drop(second);
+        assert_eq!(interner.len(), 0);
+        interner.purge();
+        assert!(interner.pool.is_empty());
+    }
+}
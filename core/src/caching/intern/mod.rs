@@ -0,0 +1,7 @@
+mod interned_keys;
+mod interned_vec;
+mod interner;
+
+pub use interned_keys::InternedKeys;
+pub use interned_vec::InternedVec;
+pub use interner::KeyInterner;
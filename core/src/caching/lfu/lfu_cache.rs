@@ -0,0 +1,483 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::slots_bytes;
+use crate::caching::scan::MaybeSync;
+use crate::caching::slots::{self, Slot};
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// References between two agings, per entry of capacity, unless told otherwise.
+pub const DEFAULT_AGING_FACTOR: u64 = 10;
+
+struct CacheLine<K, V> {
+    key: K,
+    tol: Tolerance,
+    value: V,
+    pinned: bool,
+    info: EntryInfo,
+    /// References since the insert, the insert included, halved on every aging.
+    count: u32,
+    /// Logical time of the last reference.
+    last_reference: u64,
+}
+
+impl<K, V> CacheLine<K, V> {
+    /// Eviction order: entries of a lower priority go first, then the least frequently
+    /// used, ties going to the least recently used.
+    fn rank(&self) -> (u32, u32, u64) {
+        (self.info.priority, self.count, self.last_reference)
+    }
+}
+
+impl<K, V> Slot<K> for CacheLine<K, V> {
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn tolerance(&self) -> Tolerance {
+        self.tol
+    }
+
+    fn pinned(&self) -> bool {
+        self.pinned
+    }
+
+    fn pinned_mut(&mut self) -> &mut bool {
+        &mut self.pinned
+    }
+}
+
+/// `LfuCache` is a bounded cache with approximate key matching support that evicts the
+/// least frequently used entry, ties going to the least recently used one.
+///
+/// Frequencies age: every `aging_period` references, the reference count of every entry
+/// is halved, so that entries popular in the past but no longer used eventually leave.
+/// By default, the period is [`DEFAULT_AGING_FACTOR`] times the capacity.
+///
+/// Finding a victim scans every entry, as lookups do.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, LfuCache};
+///
+/// let mut cache = LfuCache::new(2).unwrap();
+/// const TEST_TOL: f32 = 2.0;
+///
+/// cache.insert(10 as i16, "Value 1", TEST_TOL);
+/// cache.insert(20, "Value 2", TEST_TOL);
+/// cache.find(&10);
+/// cache.insert(30, "Value 3", TEST_TOL); // Evicts key 20, used less often
+///
+/// assert_eq!(cache.find(&11), Some("Value 1"));
+/// assert!(cache.find(&20).is_none());
+/// ```
+pub struct LfuCache<K, V> {
+    max_capacity: usize,
+    aging_period: u64,
+    /// References since the counts were last halved.
+    since_aging: u64,
+    /// Logical clock, ticking on every reference.
+    clock: u64,
+    items: Vec<CacheLine<K, V>>,
+    hit_rate: HitRateTracker,
+    /// Dimension of the first vector key inserted.
+    dim: Option<usize>,
+}
+
+impl<K, V> ApproximateCache<K, V> for LfuCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn find(&mut self, target: &K) -> Option<V> {
+        trace_span!("find", cache = "lfu");
+        let idx = slots::find(&self.items, self.dim, target, &mut self.hit_rate)?;
+        self.reference(idx);
+        Some(self.items[idx].value.clone())
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "lfu", k);
+        let matches = slots::find_k(&self.items, self.dim, target, k, &mut self.hit_rate);
+        matches
+            .into_iter()
+            .map(|(idx, dist)| {
+                self.reference(idx);
+                (self.items[idx].value.clone(), dist)
+            })
+            .collect()
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "lfu", tolerance, priority);
+        self.check_dim(&key).unwrap_or_else(|e| panic!("{e}"));
+        self.dim = self.dim.or(key.dimension());
+        if self.outranks_newcomer(priority) {
+            return vec![(key, value, tolerance)];
+        }
+        // room is made before inserting, as the newcomer, referenced once, would
+        // otherwise be the first victim
+        let mut evicted = self.evict_down_to(self.max_capacity - 1);
+        if self.items.len() >= self.max_capacity {
+            evicted.push((key, value, tolerance));
+            return evicted;
+        }
+        self.items.push(CacheLine {
+            key,
+            tol: tolerance,
+            value,
+            pinned: false,
+            info: EntryInfo::with_priority(priority),
+            count: 0,
+            last_reference: 0,
+        });
+        self.reference(self.items.len() - 1);
+        evicted
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        slots::nearest(&self.items, self.dim, target)
+    }
+
+    fn next_victim(&self, _incoming: &K) -> Option<&K> {
+        if self.items.len() < self.max_capacity {
+            return None;
+        }
+        self.victim().map(|idx| &self.items[idx].key)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        slots::set_pinned(&mut self.items, self.dim, target, true).is_some()
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        slots::set_pinned(&mut self.items, self.dim, target, false).is_some()
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let (candidate, _) = slots::best_match(&self.items, self.dim, target)?;
+        Some(self.items[candidate].info)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(self.items.iter().map(|entry| (&entry.key, entry.info)))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(
+            self.items
+                .iter()
+                .map(|entry| (&entry.key, entry.value.clone(), entry.tol)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        self.items
+            .sort_by_key(|entry| (entry.info.priority, entry.count, entry.last_reference));
+        Box::new(
+            self.items
+                .drain(..)
+                .map(|entry| (entry.key, entry.value, entry.tol)),
+        )
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)> {
+        self.items
+            .extract_if(.., |entry| !keep(&entry.key, &entry.value))
+            .map(|entry| (entry.key, entry.value, entry.tol))
+            .collect()
+    }
+
+    fn compact(&mut self) {
+        self.items.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let entries: usize = self
+            .items
+            .iter()
+            .map(|line| line.key.heap_bytes() + line.value.heap_bytes())
+            .sum();
+        slots_bytes::<CacheLine<K, V>>(self.items.capacity()) + entries
+    }
+}
+
+impl<K, V> DefaultApproximateCache<K, V> for LfuCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn from_capacity(cap: usize) -> LfuCache<K, V> {
+        LfuCache::new(cap).expect("bucket capacity is checked by the owning cache")
+    }
+
+    fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    fn set_capacity(&mut self, cap: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        LfuCache::set_capacity(self, cap)
+    }
+
+    fn evict(&mut self) -> Option<(K, V, Tolerance)> {
+        self.evict_victim()
+    }
+
+    fn evict_k(&mut self, k: usize) -> Vec<(K, V, Tolerance)> {
+        let victims = slots::lowest(&self.items, k, |a, b| a.rank().cmp(&b.rank()));
+        slots::remove_all(&mut self.items, &victims)
+            .into_iter()
+            .map(Self::retire)
+            .collect()
+    }
+}
+
+impl<K, V> LfuCache<K, V> {
+    pub fn new(max_capacity: usize) -> Result<Self> {
+        let aging_period = (max_capacity as u64).saturating_mul(DEFAULT_AGING_FACTOR);
+        Self::with_aging_period(max_capacity, aging_period)
+    }
+
+    /// Halves every reference count once every `aging_period` references.
+    pub fn with_aging_period(max_capacity: usize, aging_period: u64) -> Result<Self> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        if aging_period == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "aging period must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            max_capacity,
+            aging_period,
+            since_aging: 0,
+            clock: 0,
+            items: Vec::with_capacity(max_capacity),
+            hit_rate: HitRateTracker::default(),
+            dim: None,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    pub fn aging_period(&self) -> u64 {
+        self.aging_period
+    }
+
+    /// Changes the capacity, evicting the least frequently used unpinned entries until
+    /// the cache fits in it, and returns them. Pinned entries stay, even above the new
+    /// capacity. The aging period is kept.
+    pub fn set_capacity(&mut self, max_capacity: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        self.max_capacity = max_capacity;
+        Ok(self.evict_down_to(max_capacity))
+    }
+
+    /// Evicts unpinned entries while the cache holds more than `len` of them.
+    fn evict_down_to(&mut self, len: usize) -> Vec<(K, V, Tolerance)> {
+        let mut evicted = Vec::new();
+        while self.items.len() > len {
+            match self.evict_victim() {
+                Some(entry) => evicted.push(entry),
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    /// Whether the cache is full of entries of a higher priority than a newcomer's,
+    /// which eviction would then take first.
+    fn outranks_newcomer(&self, priority: u32) -> bool {
+        self.items.len() >= self.max_capacity
+            && self
+                .victim()
+                .is_some_and(|idx| self.items[idx].info.priority > priority)
+    }
+
+    /// Index of the unpinned entry to evict first, if any.
+    fn victim(&self) -> Option<usize> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.pinned)
+            .min_by_key(|(_, entry)| entry.rank())
+            .map(|(idx, _)| idx)
+    }
+
+    fn evict_victim(&mut self) -> Option<(K, V, Tolerance)> {
+        let victim = self.victim()?;
+        Some(Self::retire(self.items.swap_remove(victim)))
+    }
+
+    /// Traces the eviction of `entry`, and returns it.
+    fn retire(entry: CacheLine<K, V>) -> (K, V, Tolerance) {
+        trace_event!(
+            count = entry.count,
+            tolerance = entry.tol,
+            hits = entry.info.hits,
+            "evicted"
+        );
+        (entry.key, entry.value, entry.tol)
+    }
+
+    /// Counts a reference to the entry at `idx`, the insert included.
+    fn reference(&mut self, idx: usize) {
+        self.clock += 1;
+        let entry = &mut self.items[idx];
+        if entry.count > 0 {
+            entry.info.record_hit();
+        }
+        entry.count = entry.count.saturating_add(1);
+        entry.last_reference = self.clock;
+        self.since_aging += 1;
+        if self.since_aging >= self.aging_period {
+            self.since_aging = 0;
+            for entry in &mut self.items {
+                entry.count /= 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_lfu_evicts_least_frequent() {
+        let mut cache = LfuCache::new(3).unwrap();
+        for i in 1..=3 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        for _ in 0..3 {
+            cache.find(&1);
+        }
+        cache.find(&3);
+        assert_eq!(
+            cache.insert_evicting(4, 4, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        // counts tie between 3 and 4, the least recently used goes
+        cache.find(&4);
+        assert_eq!(
+            cache.insert_evicting(5, 5, TEST_TOLERANCE),
+            vec![(3, 3, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.find(&1), Some(1));
+        assert_eq!(cache.entry_info(&1).unwrap().hits, 4);
+    }
+
+    #[test]
+    fn test_lfu_aging_lets_stale_entries_go() {
+        let mut cache = LfuCache::with_aging_period(2, 4).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        for _ in 0..20 {
+            cache.find(&1);
+        }
+        // without aging, key 1 would hold its count of 21 against any newcomer
+        cache.insert(2, 2, TEST_TOLERANCE);
+        for _ in 0..12 {
+            cache.find(&2);
+        }
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(1, 1, TEST_TOLERANCE)]
+        );
+    }
+
+    #[test]
+    fn test_lfu_pinned_and_capacity() {
+        let mut cache = LfuCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        cache.pin(&1);
+        cache.pin(&2);
+        let rejected = cache.insert_evicting(3, 3, TEST_TOLERANCE);
+        assert_eq!(rejected, vec![(3, 3, TEST_TOLERANCE)]);
+
+        cache.unpin(&1);
+        assert_eq!(cache.next_victim(&3), Some(&1));
+        assert_eq!(cache.set_capacity(1).unwrap(), vec![(1, 1, TEST_TOLERANCE)]);
+        assert!(cache.set_capacity(0).is_err());
+        assert!(LfuCache::<i16, i32>::with_aging_period(2, 0).is_err());
+    }
+
+    #[test]
+    fn test_lfu_priority() {
+        let mut cache = LfuCache::new(2).unwrap();
+        cache.insert_with_priority(1, 1, TEST_TOLERANCE, 1);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        // key 1 is used as rarely and earlier, but of a higher priority
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.entry_info(&1).unwrap().priority, 1);
+        cache.insert_with_priority(4, 4, TEST_TOLERANCE, 1);
+        // a newcomer of a lower priority than every entry goes first
+        assert_eq!(
+            cache.insert_evicting(5, 5, TEST_TOLERANCE),
+            vec![(5, 5, TEST_TOLERANCE)]
+        );
+    }
+
+    #[test]
+    fn test_lfu_evict_k_in_eviction_order() {
+        let filled = || {
+            let mut cache = LfuCache::new(5).unwrap();
+            for i in 1..=5 {
+                cache.insert(i, i, TEST_TOLERANCE);
+            }
+            for i in [5, 1, 5, 3] {
+                cache.find(&i);
+            }
+            cache.pin(&4);
+            cache
+        };
+        let mut one_by_one = filled();
+        let evicted: Vec<_> = (0..3).map_while(|_| one_by_one.evict()).collect();
+        let mut cache = filled();
+        assert_eq!(cache.evict_k(3), evicted);
+        assert_eq!(cache.len(), 2);
+        // the pinned entry stays however many are asked for
+        assert_eq!(cache.evict_k(5).len(), 1);
+        assert_eq!(cache.find(&4), Some(4));
+    }
+}
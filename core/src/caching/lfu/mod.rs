@@ -0,0 +1,2 @@
+mod lfu_cache;
+pub use lfu_cache::{LfuCache, DEFAULT_AGING_FACTOR};
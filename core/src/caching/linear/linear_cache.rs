@@ -0,0 +1,298 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::slots_bytes;
+use crate::caching::scan::{self, MaybeSync};
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::numerics::ApproxComparable;
+
+struct LinearEntry<K, V> {
+    key: K,
+    tol: Tolerance,
+    value: V,
+    info: EntryInfo,
+}
+
+/// A cache that never evicts and answers every lookup by scanning all its entries.
+///
+/// With no index to maintain, it is the fastest cache for up to a few thousand entries,
+/// and the exact baseline the other caches are measured against.
+/// With the `parallel` feature, large caches are scanned in parallel.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, UnboundedLinearCache};
+///
+/// let mut cache = UnboundedLinearCache::new();
+/// for i in 0..100 {
+///     cache.insert(10 * i as i16, i, 1.0);
+/// }
+/// assert_eq!(cache.len(), 100);
+/// assert_eq!(cache.find(&0), Some(0));
+/// assert_eq!(cache.find(&990), Some(99));
+/// assert_eq!(cache.find(&5), None);
+/// ```
+pub struct UnboundedLinearCache<K, V> {
+    entries: Vec<LinearEntry<K, V>>,
+    hit_rate: HitRateTracker,
+    /// Dimension of the first vector key inserted.
+    dim: Option<usize>,
+}
+
+impl<K, V> Default for UnboundedLinearCache<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> UnboundedLinearCache<K, V> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            hit_rate: HitRateTracker::default(),
+            dim: None,
+        }
+    }
+}
+
+impl<K, V> UnboundedLinearCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    /// Index of the closest entry that matches `target` within its own tolerance, along
+    /// with its distance to `target`.
+    fn best_match(&self, target: &K) -> Option<(usize, f32)> {
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        scan::closest(&self.entries, |entry| {
+            entry
+                .key
+                .roughly_matches(target, entry.tol)
+                .then(|| target.fuzziness(&entry.key))
+        })
+        .map(|(idx, _, dist)| (idx, dist))
+    }
+}
+
+impl<K, V> ApproximateCache<K, V> for UnboundedLinearCache<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn find(&mut self, target: &K) -> Option<V> {
+        trace_span!("find", cache = "linear");
+        let candidate = self.best_match(target);
+        trace_event!(
+            candidates = self.entries.len(),
+            hit = candidate.is_some(),
+            best_fuzziness = ?candidate
+                .map(|(_, dist)| dist)
+                .or_else(|| self.nearest(target).map(|(dist, _)| dist)),
+            tolerance = ?candidate
+                .is_none()
+                .then(|| self.nearest(target).map(|(_, tol)| tol))
+                .flatten(),
+            "scanned"
+        );
+        self.hit_rate.record(candidate.is_some());
+        let entry = &mut self.entries[candidate?.0];
+        entry.info.record_hit();
+        Some(entry.value.clone())
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "linear", k);
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        let matches: Vec<(usize, f32)> = scan::k_closest(&self.entries, k, |entry| {
+            entry
+                .key
+                .roughly_matches(target, entry.tol)
+                .then(|| target.fuzziness(&entry.key))
+        })
+        .into_iter()
+        .map(|(idx, _, dist)| (idx, dist))
+        .collect();
+        trace_event!(
+            candidates = self.entries.len(),
+            matches = matches.len(),
+            best_fuzziness = ?matches
+                .first()
+                .map(|(_, dist)| *dist)
+                .or_else(|| self.nearest(target).map(|(dist, _)| dist)),
+            tolerance = ?matches
+                .is_empty()
+                .then(|| self.nearest(target).map(|(_, tol)| tol))
+                .flatten(),
+            "scanned"
+        );
+        self.hit_rate.record(!matches.is_empty());
+        matches
+            .into_iter()
+            .map(|(idx, dist)| {
+                let entry = &mut self.entries[idx];
+                entry.info.record_hit();
+                (entry.value.clone(), dist)
+            })
+            .collect()
+    }
+
+    /// Never evicts: the returned vector is always empty, and the priority is only kept
+    /// in the entry's [`EntryInfo`].
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "linear", tolerance, priority);
+        self.check_dim(&key).unwrap_or_else(|e| panic!("{e}"));
+        self.dim = self.dim.or(key.dimension());
+        self.entries.push(LinearEntry {
+            key,
+            tol: tolerance,
+            value,
+            info: EntryInfo::with_priority(priority),
+        });
+        Vec::new()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        scan::closest(&self.entries, |entry| Some(target.fuzziness(&entry.key)))
+            .map(|(_, entry, dist)| (dist, entry.tol))
+    }
+
+    fn next_victim(&self, _incoming: &K) -> Option<&K> {
+        None
+    }
+
+    /// Nothing is ever evicted, so pinning only reports whether `target` matches.
+    fn pin(&mut self, target: &K) -> bool {
+        self.best_match(target).is_some()
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        self.best_match(target).is_some()
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let (candidate, _) = self.best_match(target)?;
+        Some(self.entries[candidate].info)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(self.entries.iter().map(|entry| (&entry.key, entry.info)))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(
+            self.entries
+                .iter()
+                .map(|entry| (&entry.key, entry.value.clone(), entry.tol)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        Box::new(
+            self.entries
+                .drain(..)
+                .map(|entry| (entry.key, entry.value, entry.tol)),
+        )
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)> {
+        self.entries
+            .extract_if(.., |entry| !keep(&entry.key, &entry.value))
+            .map(|entry| (entry.key, entry.value, entry.tol))
+            .collect()
+    }
+
+    fn compact(&mut self) {
+        self.entries.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let entries: usize = self
+            .entries
+            .iter()
+            .map(|entry| entry.key.heap_bytes() + entry.value.heap_bytes())
+            .sum();
+        slots_bytes::<LinearEntry<K, V>>(self.entries.capacity()) + entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::scan::PARALLEL_SCAN_THRESHOLD;
+    use crate::numerics::SIMD_LANECOUNT;
+    use crate::simulation::SimKey;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_linear_cache_never_evicts() {
+        let mut cache = UnboundedLinearCache::new();
+        for i in 0..1000 {
+            assert!(cache.insert_evicting(i, i, TEST_TOLERANCE).is_empty());
+        }
+        assert_eq!(cache.len(), 1000);
+        assert_eq!(cache.find(&0), Some(0));
+        assert_eq!(cache.find(&999), Some(999));
+        assert_eq!(cache.next_victim(&1000), None);
+    }
+
+    #[test]
+    fn test_linear_cache_closest_match() {
+        let mut cache = UnboundedLinearCache::new();
+        cache.insert(10, 10, 5.0);
+        cache.insert(14, 14, 5.0);
+        assert_eq!(cache.find(&13), Some(14));
+        let found: Vec<i16> = cache.find_k(&11, 5).into_iter().map(|(v, _)| v).collect();
+        assert_eq!(found, vec![10, 14]);
+        assert_eq!(cache.nearest(&30), Some((16.0, 5.0)));
+        assert_eq!(cache.entry_info(&11).unwrap().hits, 1);
+    }
+
+    #[test]
+    fn test_parallel_scan_matches_sequential() {
+        let key = |i: usize| {
+            let mut v = vec![0.0; SIMD_LANECOUNT];
+            v[0] = i as f32;
+            v[1] = (i % 7) as f32;
+            SimKey(v)
+        };
+        let mut small = UnboundedLinearCache::new();
+        let mut large = UnboundedLinearCache::new();
+        for i in 0..PARALLEL_SCAN_THRESHOLD * 2 {
+            if i < PARALLEL_SCAN_THRESHOLD / 2 {
+                small.insert(key(i), i, 3.0);
+            }
+            large.insert(key(i), i, 3.0);
+        }
+        for i in [0, 17, PARALLEL_SCAN_THRESHOLD / 4] {
+            let query = key(i);
+            assert_eq!(small.find(&query), large.find(&query));
+            assert_eq!(small.find_k(&query, 3), large.find_k(&query, 3));
+            assert_eq!(small.nearest(&query), large.nearest(&query));
+        }
+    }
+}
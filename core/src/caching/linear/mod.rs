@@ -0,0 +1,2 @@
+mod linear_cache;
+pub use linear_cache::UnboundedLinearCache;
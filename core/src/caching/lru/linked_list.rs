@@ -1,6 +1,6 @@
 use std::rc::Rc;
 
-use crate::caching::lru::list_node::SharedNode;
+use crate::caching::lru::list_node::{Node, SharedNode};
 
 pub struct DoublyLinkedList<K, V> {
     head: Option<SharedNode<K, V>>,
@@ -55,12 +55,36 @@ impl<K, V> DoublyLinkedList<K, V> {
             None
         }
     }
+
+    /// Node closest to the tail for which `predicate` holds.
+    pub(crate) fn last_where<F>(&self, predicate: F) -> Option<SharedNode<K, V>>
+    where
+        F: Fn(&Node<K, V>) -> bool,
+    {
+        let mut current = self.tail.clone();
+        while let Some(node) = current {
+            if predicate(&node.borrow()) {
+                return Some(node);
+            }
+            current = node.borrow().prev.as_ref().and_then(|weak| weak.upgrade());
+        }
+        None
+    }
+
+    /// Removes the node closest to the tail for which `predicate` holds.
+    pub(crate) fn remove_last_where<F>(&mut self, predicate: F) -> Option<SharedNode<K, V>>
+    where
+        F: Fn(&Node<K, V>) -> bool,
+    {
+        let node = self.last_where(predicate)?;
+        self.remove(node.clone());
+        Some(node)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::caching::lru::list_node::Node;
 
     #[test]
     fn test_add_to_head() {
@@ -153,4 +177,25 @@ mod tests {
         assert_eq!(list.head.as_ref().unwrap().borrow().key, 1);
         assert_eq!(list.tail.as_ref().unwrap().borrow().key, 2);
     }
+
+    #[test]
+    fn test_remove_last_where() {
+        let mut list = DoublyLinkedList::new();
+        let node1 = Node::new(1, 10);
+        let node2 = Node::new(2, 20);
+        let node3 = Node::new(3, 30);
+
+        list.add_to_head(node1.clone());
+        list.add_to_head(node2.clone());
+        list.add_to_head(node3.clone());
+
+        // List is now: {3, 2, 1}
+        let removed = list.remove_last_where(|node| node.key != 1).unwrap();
+        assert_eq!(removed.borrow().key, 2);
+        // List should now be: {3, 1}
+        assert_eq!(list.head.as_ref().unwrap().borrow().key, 3);
+        assert_eq!(list.tail.as_ref().unwrap().borrow().key, 1);
+
+        assert!(list.remove_last_where(|node| node.key > 5).is_none());
+    }
 }
@@ -3,6 +3,8 @@ use std::{
     rc::{Rc, Weak},
 };
 
+use crate::caching::EntryInfo;
+
 pub(crate) type SharedNode<K, V> = Rc<RefCell<Node<K, V>>>;
 pub(crate) type WeakSharedNode<K, V> = Weak<RefCell<Node<K, V>>>;
 
@@ -10,6 +12,8 @@ pub(crate) type WeakSharedNode<K, V> = Weak<RefCell<Node<K, V>>>;
 pub struct Node<K, V> {
     pub(crate) key: K,
     pub(crate) value: V,
+    pub(crate) pinned: bool,
+    pub(crate) info: EntryInfo,
     pub(crate) prev: Option<WeakSharedNode<K, V>>,
     pub(crate) next: Option<SharedNode<K, V>>,
 }
@@ -19,6 +23,8 @@ impl<K, V> Node<K, V> {
         Rc::new(RefCell::new(Node {
             key,
             value,
+            pinned: false,
+            info: EntryInfo::new(),
             prev: None,
             next: None,
         }))
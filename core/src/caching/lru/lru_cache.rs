@@ -1,9 +1,19 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::mem::size_of;
+use std::rc::Rc;
 
 use crate::numerics::ApproxComparable;
 
-use crate::caching::approximate_cache::{ApproximateCache, DefaultApproximateCache};
+use crate::caching::approximate_cache::{ApproximateCache, DefaultApproximateCache, Tolerance};
+use crate::caching::memory::table_bytes;
+use crate::caching::priority::PriorityCounts;
+use crate::caching::scan::{self, MaybeSync};
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::{ProximityError, Result};
 
 use super::linked_list::DoublyLinkedList;
 use super::list_node::{Node, SharedNode};
@@ -20,7 +30,7 @@ use super::map_entry::MapEntry;
 /// use proximity::caching::LruCache;
 /// use proximity::caching::ApproximateCache;
 ///
-/// let mut cache = LruCache::new(3);
+/// let mut cache = LruCache::new(3).unwrap();
 /// const TEST_TOL: f32 = 2.0;
 ///
 /// cache.insert(10 as i16, "Value 1", TEST_TOL);
@@ -47,68 +57,401 @@ pub struct LruCache<K, V> {
     max_capacity: usize,
     map: HashMap<MapEntry<K>, SharedNode<MapEntry<K>, V>>,
     list: DoublyLinkedList<MapEntry<K>, V>,
+    hit_rate: HitRateTracker,
+    /// Priorities of the unpinned entries, the ones eviction takes from.
+    unpinned: PriorityCounts,
+    /// Dimension of the first vector key inserted.
+    dim: Option<usize>,
 }
 
 impl<K, V> ApproximateCache<K, V> for LruCache<K, V>
 where
-    K: ApproxComparable + Eq + Hash + Clone,
+    K: ApproxComparable + Eq + Hash + Clone + MaybeSync,
     V: Clone,
 {
     fn find(&mut self, target: &K) -> Option<V> {
-        let candidate = self
-            .map
-            .keys()
-            .filter(|&entry| entry.key.roughly_matches(target, entry.tolerance))
-            .min_by(|&xentry, &yentry| {
-                target
-                    .fuzziness(&xentry.key)
-                    .partial_cmp(&target.fuzziness(&yentry.key))
-                    .unwrap()
-            })?;
-
-        let node: SharedNode<MapEntry<K>, V> = self.map.get(candidate).cloned()?;
+        trace_span!("find", cache = "lru");
+        let candidate = self.best_match(target);
+        trace_event!(
+            candidates = self.map.len(),
+            hit = candidate.is_some(),
+            best_fuzziness = ?candidate
+                .as_ref().map(|(_, dist)| *dist)
+                .or_else(|| self.nearest(target).map(|(dist, _)| dist)),
+            tolerance = ?candidate
+                .is_none()
+                .then(|| self.nearest(target).map(|(_, tol)| tol))
+                .flatten(),
+            "scanned"
+        );
+        self.hit_rate.record(candidate.is_some());
+        let (node, _) = candidate?;
         self.list.remove(node.clone());
         self.list.add_to_head(node.clone());
+        node.borrow_mut().info.record_hit();
         return Some(node.borrow().value.clone());
     }
 
-    fn insert(&mut self, key: K, value: V, tolerance: f32) {
-        if self.len() >= self.max_capacity {
-            if let Some(tail) = self.list.remove_tail() {
-                self.map.remove(&tail.borrow().key);
-            }
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "lru", k);
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        let matches = self.k_closest_keys(k, |entry| {
+            entry
+                .key
+                .roughly_matches(target, entry.tolerance)
+                .then(|| target.fuzziness(&entry.key))
+        });
+        trace_event!(
+            candidates = self.map.len(),
+            matches = matches.len(),
+            best_fuzziness = ?matches
+                .first()
+                .map(|(_, dist)| *dist)
+                .or_else(|| self.nearest(target).map(|(dist, _)| dist)),
+            tolerance = ?matches
+                .is_empty()
+                .then(|| self.nearest(target).map(|(_, tol)| tol))
+                .flatten(),
+            "scanned"
+        );
+        let nodes: Vec<(SharedNode<MapEntry<K>, V>, f32)> = matches
+            .into_iter()
+            .map(|(entry, dist)| (self.map[entry].clone(), dist))
+            .collect();
+        self.hit_rate.record(!nodes.is_empty());
+        // promote the furthest first so that the closest match ends up most recent
+        for (node, _) in nodes.iter().rev() {
+            self.list.remove(node.clone());
+            self.list.add_to_head(node.clone());
+            node.borrow_mut().info.record_hit();
         }
+        nodes
+            .into_iter()
+            .map(|(node, dist)| (node.borrow().value.clone(), dist))
+            .collect()
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "lru", tolerance, priority);
+        self.check_dim(&key).unwrap_or_else(|e| panic!("{e}"));
+        self.dim = self.dim.or(key.dimension());
         let map_entry = MapEntry {
             key: key.clone(),
             tolerance,
         };
+        // re-inserting an existing key replaces its node but keeps it pinned
+        let mut pinned = false;
+        if let Some(old) = self.map.remove(&map_entry) {
+            pinned = old.borrow().pinned;
+            if !pinned {
+                self.unpinned.remove(old.borrow().info.priority);
+            }
+            self.list.remove(old);
+        }
+        let mut evicted = Vec::new();
+        if self.len() >= self.max_capacity {
+            // a newcomer of a lower priority than every evictable entry goes first
+            if self.unpinned.floor().is_some_and(|floor| floor > priority) {
+                return vec![(key, value, tolerance)];
+            }
+            match self.evict_least_recent() {
+                Some(entry) => evicted.push(entry),
+                // every entry is pinned, there is no room for the newcomer
+                None => {
+                    trace_event!("refused, every entry is pinned");
+                    return vec![(key, value, tolerance)];
+                }
+            }
+        }
         let new_node = Node::new(map_entry.clone(), value);
+        new_node.borrow_mut().pinned = pinned;
+        new_node.borrow_mut().info.priority = priority;
+        if !pinned {
+            self.unpinned.add(priority);
+        }
         self.list.add_to_head(new_node.clone());
         self.map.insert(map_entry, new_node);
+        evicted
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        self.dim
     }
 
     fn len(&self) -> usize {
         self.map.len()
     }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        self.closest_key(|entry| Some(target.fuzziness(&entry.key)))
+            .map(|(entry, dist)| (dist, entry.tolerance))
+    }
+
+    fn next_victim(&self, _incoming: &K) -> Option<&K> {
+        if self.len() < self.max_capacity {
+            return None;
+        }
+        let floor = self.unpinned.floor()?;
+        let victim = self
+            .list
+            .last_where(|node| !node.pinned && node.info.priority == floor)?;
+        let (entry, _) = self.map.get_key_value(&victim.borrow().key)?;
+        Some(&entry.key)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        self.set_pinned(target, true)
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        self.set_pinned(target, false)
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let (node, _) = self.best_match(target)?;
+        let info = node.borrow().info;
+        Some(info)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(
+            self.map
+                .iter()
+                .map(|(entry, node)| (&entry.key, node.borrow().info)),
+        )
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(
+            self.map
+                .iter()
+                .map(|(entry, node)| (&entry.key, node.borrow().value.clone(), entry.tolerance)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        let mut drained = Vec::with_capacity(self.len());
+        while let Some(tail) = self.list.remove_tail() {
+            self.map.remove(&tail.borrow().key);
+            let priority = tail.borrow().info.priority;
+            drained.push((priority, Self::into_entry(tail)));
+        }
+        self.unpinned.clear();
+        // stable, so that entries of the same priority stay least recent first
+        drained.sort_by_key(|(priority, _)| *priority);
+        Box::new(drained.into_iter().map(|(_, entry)| entry))
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)> {
+        let dropped: Vec<MapEntry<K>> = self
+            .map
+            .iter()
+            .filter(|(entry, node)| !keep(&entry.key, &node.borrow().value))
+            .map(|(entry, _)| entry.clone())
+            .collect();
+        dropped
+            .into_iter()
+            .map(|entry| {
+                let node = self.map.remove(&entry).unwrap();
+                self.list.remove(node.clone());
+                if !node.borrow().pinned {
+                    self.unpinned.remove(node.borrow().info.priority);
+                }
+                Self::into_entry(node)
+            })
+            .collect()
+    }
+
+    fn compact(&mut self) {
+        self.map.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        // the map keys are copies of the node keys, and each node has its own allocation
+        let node = 2 * size_of::<usize>() + size_of::<RefCell<Node<MapEntry<K>, V>>>();
+        let entries: usize = self
+            .map
+            .iter()
+            .map(|(entry, shared)| {
+                let node_ref = shared.borrow();
+                node + entry.key.heap_bytes()
+                    + node_ref.key.key.heap_bytes()
+                    + node_ref.value.heap_bytes()
+            })
+            .sum();
+        table_bytes(&self.map) + entries
+    }
 }
 
 impl<K, V> DefaultApproximateCache<K, V> for LruCache<K, V>
 where
-    K: ApproxComparable + Eq + Hash + Clone,
+    K: ApproxComparable + Eq + Hash + Clone + MaybeSync,
     V: Clone,
 {
     fn from_capacity(cap: usize) -> Self {
-        LruCache::new(cap)
+        LruCache::new(cap).expect("bucket capacity is checked by the owning cache")
+    }
+
+    fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    fn set_capacity(&mut self, cap: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        LruCache::set_capacity(self, cap)
+    }
+
+    fn evict(&mut self) -> Option<(K, V, Tolerance)> {
+        self.evict_least_recent()
     }
 }
 
 impl<K, V> LruCache<K, V> {
-    pub fn new(max_capacity: usize) -> Self {
-        assert!(max_capacity > 0);
-        Self {
+    pub fn new(max_capacity: usize) -> Result<Self> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        Ok(Self {
             max_capacity,
             map: HashMap::with_capacity(max_capacity),
             list: DoublyLinkedList::new(),
+            hit_rate: HitRateTracker::default(),
+            unpinned: PriorityCounts::default(),
+            dim: None,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: ApproxComparable + Eq + Hash + Clone + MaybeSync,
+    V: Clone,
+{
+    /// Changes the capacity, evicting the least recently used unpinned entries until the
+    /// cache fits in it, and returns them. Pinned entries stay, even above the new capacity.
+    pub fn set_capacity(&mut self, max_capacity: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        self.max_capacity = max_capacity;
+        let mut evicted = Vec::new();
+        while self.len() > self.max_capacity {
+            match self.evict_least_recent() {
+                Some(entry) => evicted.push(entry),
+                None => break,
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// Evicts the least recently used unpinned entry of the lowest priority, if any.
+    fn evict_least_recent(&mut self) -> Option<(K, V, Tolerance)> {
+        let floor = self.unpinned.floor()?;
+        let victim = self
+            .list
+            .remove_last_where(|node| !node.pinned && node.info.priority == floor)?;
+        self.unpinned.remove(floor);
+        trace_event!(
+            tolerance = victim.borrow().key.tolerance,
+            hits = victim.borrow().info.hits,
+            "evicted"
+        );
+        self.map.remove(&victim.borrow().key);
+        Some(Self::into_entry(victim))
+    }
+
+    /// Node of the closest entry that matches `target` within its own tolerance, along
+    /// with its distance to `target`.
+    fn best_match(&self, target: &K) -> Option<(SharedNode<MapEntry<K>, V>, f32)> {
+        self.check_dim(target).unwrap_or_else(|e| panic!("{e}"));
+        let (candidate, dist) = self.closest_key(|entry| {
+            entry
+                .key
+                .roughly_matches(target, entry.tolerance)
+                .then(|| target.fuzziness(&entry.key))
+        })?;
+        Some((self.map.get(candidate)?.clone(), dist))
+    }
+
+    /// Closest stored key for which `dist` is a number, along with that distance.
+    ///
+    /// With the `parallel` feature, the keys are gathered first so that the scan can be
+    /// split across threads: nodes are reference-counted, so the map itself cannot be
+    /// shared.
+    fn closest_key<F>(&self, dist: F) -> Option<(&MapEntry<K>, f32)>
+    where
+        F: Fn(&MapEntry<K>) -> Option<f32> + MaybeSync,
+    {
+        #[cfg(feature = "parallel")]
+        return scan::closest(&self.map.keys().collect::<Vec<_>>(), |entry| dist(entry))
+            .map(|(_, entry, dist)| (*entry, dist));
+        #[cfg(not(feature = "parallel"))]
+        return scan::closest(self.map.keys(), dist).map(|(_, entry, dist)| (entry, dist));
+    }
+
+    /// Up to `k` closest stored keys for which `dist` is a number, closest first, see
+    /// [`closest_key`](Self::closest_key).
+    fn k_closest_keys<F>(&self, k: usize, dist: F) -> Vec<(&MapEntry<K>, f32)>
+    where
+        F: Fn(&MapEntry<K>) -> Option<f32> + MaybeSync,
+    {
+        #[cfg(feature = "parallel")]
+        return scan::k_closest(&self.map.keys().collect::<Vec<_>>(), k, |entry| dist(entry))
+            .into_iter()
+            .map(|(_, entry, dist)| (*entry, dist))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        return scan::k_closest(self.map.keys(), k, dist)
+            .into_iter()
+            .map(|(_, entry, dist)| (entry, dist))
+            .collect();
+    }
+
+    /// Takes apart a node that was already unlinked from the list and removed from the map.
+    fn into_entry(node: SharedNode<MapEntry<K>, V>) -> (K, V, Tolerance) {
+        // this is the last strong reference, so the node can be moved out
+        let node = Rc::into_inner(node)
+            .expect("evicted node is still shared")
+            .into_inner();
+        (node.key.key, node.value, node.key.tolerance)
+    }
+
+    fn set_pinned(&mut self, target: &K, pinned: bool) -> bool {
+        match self.best_match(target) {
+            Some((node, _)) => {
+                let mut node = node.borrow_mut();
+                if node.pinned != pinned {
+                    match pinned {
+                        true => self.unpinned.remove(node.info.priority),
+                        false => self.unpinned.add(node.info.priority),
+                    }
+                    node.pinned = pinned;
+                }
+                true
+            }
+            None => false,
         }
     }
 }
@@ -120,7 +463,7 @@ mod tests {
     const TEST_TOLERANCE: f32 = 1e-8;
     #[test]
     fn test_lru_cache_basic_operations() {
-        let mut cache = LruCache::new(2);
+        let mut cache = LruCache::new(2).unwrap();
         cache.insert(1, 1, TEST_TOLERANCE); // Cache is {1=1}
         cache.insert(2, 2, TEST_TOLERANCE); // Cache is {1=1, 2=2}
         assert_eq!(cache.find(&1), Some(1)); // Returns 1, Cache is {2=2, 1=1}
@@ -134,7 +477,7 @@ mod tests {
 
     #[test]
     fn test_lru_cache_eviction_order() {
-        let mut cache = LruCache::new(3);
+        let mut cache = LruCache::new(3).unwrap();
         cache.insert(1, 1, TEST_TOLERANCE); // Cache is {1=1}
         cache.insert(2, 2, TEST_TOLERANCE); // Cache is {1=1, 2=2}
         cache.insert(3, 3, TEST_TOLERANCE); // Cache is {1=1, 2=2, 3=3}
@@ -148,7 +491,7 @@ mod tests {
 
     #[test]
     fn test_lru_cache_overwrite() {
-        let mut cache = LruCache::new(2);
+        let mut cache = LruCache::new(2).unwrap();
         cache.insert(1, 1, TEST_TOLERANCE); // Cache is {1=1}
         cache.insert(2, 2, TEST_TOLERANCE); // Cache is {1=1, 2=2}
         cache.insert(1, 10, TEST_TOLERANCE); // Overwrites key 1, Cache is {2=2, 1=10}
@@ -160,7 +503,7 @@ mod tests {
 
     #[test]
     fn test_lru_cache_capacity_one() {
-        let mut cache = LruCache::new(1);
+        let mut cache = LruCache::new(1).unwrap();
         cache.insert(1, 1, TEST_TOLERANCE); // Cache is {1=1}
         assert_eq!(cache.find(&1), Some(1)); // Returns 1
         cache.insert(2, 2, TEST_TOLERANCE); // Evicts key 1, Cache is {2=2}
@@ -169,8 +512,266 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    fn test_lru_cache_pinned_entry_survives() {
+        let mut cache = LruCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE); // Cache is {1=1}
+        assert!(cache.pin(&1));
+        cache.insert(2, 2, TEST_TOLERANCE); // Cache is {1=1 (pinned), 2=2}
+        cache.insert(3, 3, TEST_TOLERANCE); // Key 1 is the LRU but pinned, evicts key 2
+        assert_eq!(cache.find(&2), None);
+        cache.insert(1, 10, TEST_TOLERANCE); // Overwriting keeps the pin
+        cache.insert(4, 4, TEST_TOLERANCE); // Evicts key 3
+        assert_eq!(cache.find(&1), Some(10));
+        assert_eq!(cache.find(&3), None);
+
+        assert!(cache.unpin(&1));
+        cache.find(&4); // Key 1 is now the LRU
+        cache.insert(5, 5, TEST_TOLERANCE); // Evicts key 1
+        assert_eq!(cache.find(&1), None);
+        assert!(!cache.unpin(&1));
+    }
+
+    #[test]
+    fn test_lru_cache_all_pinned_rejects_newcomer() {
+        let mut cache = LruCache::new(1).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.pin(&1);
+        cache.insert(2, 2, TEST_TOLERANCE); // No room: the unpinned newcomer is dropped
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.find(&1), Some(1));
+        assert_eq!(cache.find(&2), None);
+    }
+
+    #[test]
+    fn test_lru_cache_memory_bytes() {
+        let mut cache: LruCache<i16, String> = LruCache::new(4).unwrap();
+        cache.insert(1, String::with_capacity(100), TEST_TOLERANCE);
+        let one = cache.memory_bytes();
+        assert!(one >= 100 + size_of::<String>());
+
+        cache.insert(2, String::with_capacity(100), TEST_TOLERANCE);
+        assert!(cache.memory_bytes() >= one + 100 + size_of::<String>());
+    }
+
+    #[test]
+    fn test_lru_cache_set_capacity() {
+        let mut cache = LruCache::new(4).unwrap();
+        for i in 1..=4 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        cache.find(&1);
+        cache.pin(&2);
+        let evicted = cache.set_capacity(2).unwrap();
+        // the least recently used unpinned entries go first
+        assert_eq!(
+            evicted,
+            vec![(3, 3, TEST_TOLERANCE), (4, 4, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.capacity(), 2);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.find(&1), Some(1));
+        assert_eq!(cache.find(&2), Some(2));
+
+        cache.pin(&1);
+        assert!(cache.set_capacity(1).unwrap().is_empty());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.set_capacity(0).is_err());
+    }
+
+    #[test]
+    fn test_lru_cache_entry_info() {
+        let mut cache = LruCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        cache.find(&2);
+        let before = cache.entry_info(&2).unwrap();
+        assert_eq!(before.hits, 1);
+        cache.find(&2);
+        let after = cache.entry_info(&2).unwrap();
+        assert_eq!(after.hits, 2);
+        assert!(after.last_access >= before.last_access);
+        assert_eq!(after.inserted_at, before.inserted_at);
+        assert!(cache.entry_info(&3).is_none());
+
+        let mut hits: Vec<(i16, u64)> = cache.entry_infos().map(|(k, i)| (*k, i.hits)).collect();
+        hits.sort();
+        assert_eq!(hits, vec![(1, 0), (2, 2)]);
+    }
+
+    #[test]
+    fn test_lru_cache_iter_and_drain() {
+        let mut cache = LruCache::new(3).unwrap();
+        cache.insert(1, 10, TEST_TOLERANCE);
+        cache.insert(2, 20, 2.0);
+        cache.insert(3, 30, TEST_TOLERANCE);
+        cache.find(&1); // Recency order is now {2, 3, 1}
+
+        let mut items: Vec<(i16, i16, f32)> = cache.iter().map(|(k, v, t)| (*k, v, t)).collect();
+        items.sort_by_key(|&(k, _, _)| k);
+        assert_eq!(
+            items,
+            vec![
+                (1, 10, TEST_TOLERANCE),
+                (2, 20, 2.0),
+                (3, 30, TEST_TOLERANCE)
+            ]
+        );
+
+        let drained: Vec<(i16, i16, f32)> = cache.drain().collect();
+        assert_eq!(
+            drained,
+            vec![
+                (2, 20, 2.0),
+                (3, 30, TEST_TOLERANCE),
+                (1, 10, TEST_TOLERANCE)
+            ]
+        );
+        assert!(cache.is_empty());
+
+        // the cache stays usable after draining
+        cache.insert(4, 40, TEST_TOLERANCE);
+        assert_eq!(cache.find(&4), Some(40));
+    }
+
+    #[test]
+    fn test_lru_cache_recent_hit_rate() {
+        let mut cache = LruCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        for _ in 0..3 {
+            cache.find(&1);
+        }
+        cache.find(&2);
+        assert!((cache.recent_hit_rate() - 0.75).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_lru_cache_find_k() {
+        let mut cache = LruCache::new(3).unwrap();
+        cache.insert(10, 10, 3.0);
+        cache.insert(13, 13, 3.0);
+        cache.insert(20, 20, 3.0);
+        assert_eq!(cache.find_k(&11, 3), vec![(10, 1.0), (13, 2.0)]);
+        cache.insert(30, 30, 3.0); // Evicts key 20, both matches were promoted
+        assert_eq!(cache.find(&20), None);
+        assert_eq!(cache.find(&10), Some(10));
+        assert_eq!(cache.find(&13), Some(13));
+    }
+
+    #[test]
+    fn test_lru_cache_next_victim() {
+        let mut cache = LruCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        assert_eq!(cache.next_victim(&3), None); // Not full yet
+        cache.insert(2, 2, TEST_TOLERANCE);
+        assert_eq!(cache.next_victim(&3), Some(&1));
+        cache.find(&1);
+        assert_eq!(cache.next_victim(&3), Some(&2));
+    }
+
+    #[test]
+    fn test_lru_cache_insert_evicting() {
+        let mut cache = LruCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        cache.find(&1);
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        // Overwriting a key is not an eviction
+        assert!(cache.insert_evicting(3, 30, TEST_TOLERANCE).is_empty());
+    }
+
+    #[test]
+    fn test_lru_cache_nearest() {
+        let mut cache = LruCache::new(2).unwrap();
+        assert_eq!(cache.nearest(&1), None);
+        cache.insert(10, 10, 1.0);
+        cache.insert(20, 20, 2.0);
+        assert_eq!(cache.nearest(&12), Some((2.0, 1.0)));
+    }
+
+    #[test]
     fn test_lru_cache_empty() {
-        let _cache: LruCache<i16, i16> = LruCache::new(0);
+        let cache: Result<LruCache<i16, i16>> = LruCache::new(0);
+        assert!(matches!(cache, Err(ProximityError::InvalidArgument(_))));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a key of dimension 8, got 16")]
+    fn test_insert_with_wrong_dimension() {
+        use crate::simulation::SimKey;
+
+        let mut cache = LruCache::new(2).unwrap();
+        cache.insert(SimKey(vec![0.0; 8]), 1, TEST_TOLERANCE);
+        assert_eq!(cache.key_dim(), Some(8));
+        cache.insert(SimKey(vec![0.0; 16]), 2, TEST_TOLERANCE);
+    }
+
+    #[test]
+    fn test_lru_cache_priority() {
+        let mut cache = LruCache::new(2).unwrap();
+        cache.insert_with_priority(1, 1, TEST_TOLERANCE, 1);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        // key 1 is the least recently used, but of a higher priority
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.entry_info(&1).unwrap().priority, 1);
+        cache.insert_with_priority(4, 4, TEST_TOLERANCE, 1);
+        // a newcomer of a lower priority than every entry goes first
+        assert_eq!(
+            cache.insert_evicting(5, 5, TEST_TOLERANCE),
+            vec![(5, 5, TEST_TOLERANCE)]
+        );
+    }
+
+    #[test]
+    fn test_lru_cache_priority_of_pinned_entries() {
+        let mut cache = LruCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert_with_priority(2, 2, TEST_TOLERANCE, 1);
+        // once key 1 is pinned, the lowest priority that can be evicted is 1
+        assert!(cache.pin(&1));
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(3, 3, TEST_TOLERANCE)]
+        );
+        // re-inserting a pinned key keeps it out of eviction
+        cache.insert(1, 10, TEST_TOLERANCE);
+        assert_eq!(cache.next_victim(&3), Some(&2));
+        assert!(cache.unpin(&1));
+        assert_eq!(cache.next_victim(&3), Some(&1));
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(1, 10, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.drain().count(), 2);
+        assert_eq!(cache.next_victim(&3), None);
+    }
+
+    #[test]
+    fn test_lru_cache_merge() {
+        use std::thread::sleep;
+        use std::time::Duration;
+
+        let mut cache = LruCache::new(3).unwrap();
+        let mut other = LruCache::new(3).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert_with_priority(2, 2, TEST_TOLERANCE, 1);
+        sleep(Duration::from_millis(1));
+        other.insert(3, 3, TEST_TOLERANCE);
+        sleep(Duration::from_millis(1));
+        other.insert(4, 4, TEST_TOLERANCE);
+        sleep(Duration::from_millis(1));
+        cache.find(&1);
+
+        // key 3 is the least recently used of priority 0
+        assert_eq!(cache.merge(other), vec![(3, 3, TEST_TOLERANCE)]);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.entry_info(&2).unwrap().priority, 1);
+        // key 1 was used last, so it is the most recent once merged
+        assert_eq!(cache.next_victim(&5), Some(&4));
     }
 }
@@ -0,0 +1,2 @@
+mod lru_k_cache;
+pub use lru_k_cache::{LruKCache, DEFAULT_LRU_K};
@@ -0,0 +1,68 @@
+/// Share of the matchable lookups that may land in another bucket than their match
+/// before [`LshDiagnosis`] suggests fewer hyperplanes.
+const MAX_ROUTING_MISS_RATE: f32 = 0.1;
+
+/// Share of the stored entries that probed buckets may hold on average before
+/// [`LshDiagnosis`] suggests more hyperplanes.
+const MAX_PROBED_SHARE: f32 = 0.25;
+
+/// How an [`LshCache`](crate::caching::LshCache) routed a sample of lookups, as found by
+/// [`diagnose`](crate::caching::LshCache::diagnose), to tell a tolerance too tight from
+/// hyperplanes that route lookups away from their matches.
+///
+/// Every sampled lookup is compared against every stored entry to find its true nearest
+/// key, which is _matchable_ if within the tolerance of its entry. A matchable lookup
+/// that the probed bucket does not answer is a routing miss, any other lookup without
+/// a match a tolerance miss.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LshDiagnosis {
+    /// Hyperplanes of the diagnosed cache, those of splits excluded.
+    pub num_hash: usize,
+    /// Queries of the sample, none if the cache was empty.
+    pub lookups: usize,
+    /// Lookups whose true nearest key was in the probed bucket.
+    pub nearest_in_bucket: usize,
+    /// Lookups within the tolerance of the nearest key of the probed bucket.
+    pub hits: usize,
+    /// Matchable lookups that the probed bucket did not answer.
+    pub routing_misses: usize,
+    /// Lookups whose true nearest key was out of tolerance.
+    pub tolerance_misses: usize,
+    /// Average number of entries in the probed buckets.
+    pub mean_bucket_len: f32,
+    /// Entries of the cache when diagnosed.
+    pub len: usize,
+}
+
+impl LshDiagnosis {
+    /// Share of the lookups whose true nearest key was in the probed bucket.
+    pub fn routing_recall(&self) -> f32 {
+        if self.lookups == 0 {
+            return 0.0;
+        }
+        self.nearest_in_bucket as f32 / self.lookups as f32
+    }
+
+    /// Share of the matchable lookups that landed in another bucket than their match.
+    pub fn routing_miss_rate(&self) -> f32 {
+        let matchable = self.hits + self.routing_misses;
+        if matchable == 0 {
+            return 0.0;
+        }
+        self.routing_misses as f32 / matchable as f32
+    }
+
+    /// A number of hyperplanes to try: one fewer if more than a tenth of the matchable
+    /// lookups were routed away from their match, one more if the probed buckets hold
+    /// over a quarter of the entries, so that routing barely prunes, and else as many.
+    /// Tolerance misses call for a larger tolerance, not other hyperplanes.
+    pub fn suggested_num_hash(&self) -> usize {
+        if self.routing_miss_rate() > MAX_ROUTING_MISS_RATE {
+            self.num_hash.saturating_sub(1).max(1)
+        } else if self.len > 0 && self.mean_bucket_len > MAX_PROBED_SHARE * self.len as f32 {
+            self.num_hash + 1
+        } else {
+            self.num_hash
+        }
+    }
+}
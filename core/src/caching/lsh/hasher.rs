@@ -1,8 +1,14 @@
-use rand::rngs::{StdRng, ThreadRng};
-use rand::{rng, Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rand_distr::StandardNormal;
 
+use crate::caching::HeapSize;
 use crate::numerics::{VectorLike, SIMD_LANECOUNT};
+use crate::{ProximityError, Result};
+
+/// Seed of unseeded hashers when there is no thread RNG to draw hyperplanes from.
+#[cfg(not(feature = "thread-rng"))]
+pub const FALLBACK_SEED: u64 = 0x5eed;
 
 pub struct SimHashHasher {
     stored_vectors_dim: usize,
@@ -13,9 +19,18 @@ pub struct SimHashHasher {
 impl SimHashHasher {
     /// Constructs a new hasher with `num_hash` hyperplanes in dimension `dim`,
     /// using a non‐seeded thread RNG.
+    ///
+    /// Without the `thread-rng` feature, e.g. on `wasm32-unknown-unknown` where there is
+    /// no entropy source, this is [`new_seeded`](Self::new_seeded) with [`FALLBACK_SEED`].
     pub fn new(num_hash: usize, stored_vectors_dim: usize) -> Self {
-        let mut rng: ThreadRng = rng();
-        Self::with_rng(num_hash, stored_vectors_dim, &mut rng)
+        #[cfg(feature = "thread-rng")]
+        {
+            Self::with_rng(num_hash, stored_vectors_dim, &mut rand::rng())
+        }
+        #[cfg(not(feature = "thread-rng"))]
+        {
+            Self::new_seeded(num_hash, stored_vectors_dim, FALLBACK_SEED)
+        }
     }
 
     /// Constructs a new hasher with `num_hash` hyperplanes in dimension `dim`,
@@ -25,9 +40,29 @@ impl SimHashHasher {
         Self::with_rng(num_hash, stored_vectors_dim, &mut rng)
     }
 
+    /// Rebuilds a hasher from the hyperplanes of another, see
+    /// [`projections`](Self::projections).
+    pub fn from_projections(stored_vectors_dim: usize, projections: Vec<Vec<f32>>) -> Result<Self> {
+        if stored_vectors_dim == 0 || !stored_vectors_dim.is_multiple_of(SIMD_LANECOUNT) {
+            return Err(ProximityError::InvalidArgument(format!(
+                "dimension must be a positive multiple of {SIMD_LANECOUNT}, got {stored_vectors_dim}"
+            )));
+        }
+        if let Some(proj) = projections.iter().find(|p| p.len() != stored_vectors_dim) {
+            return Err(ProximityError::DimensionMismatch {
+                expected: stored_vectors_dim,
+                found: proj.len(),
+            });
+        }
+        Ok(SimHashHasher {
+            stored_vectors_dim,
+            projections,
+        })
+    }
+
     fn with_rng<R: Rng>(num_hash: usize, stored_vectors_dim: usize, rng: &mut R) -> Self {
         assert!(
-            stored_vectors_dim % SIMD_LANECOUNT == 0,
+            stored_vectors_dim.is_multiple_of(SIMD_LANECOUNT),
             "dim must be multiple of SIMD_LANECOUNT"
         );
 
@@ -47,18 +82,40 @@ impl SimHashHasher {
     }
 
     /// Hashes `vector` to a `k`-length binary signature.
-    pub fn hash(&self, vector: &[f32]) -> Vec<bool> {
-        debug_assert!(
-            vector.len() == self.stored_vectors_dim,
-            "input vector has wrong dimension"
-        );
-        self.projections
+    pub fn hash(&self, vector: &[f32]) -> Result<Vec<bool>> {
+        let margins = self.margins(vector)?;
+        Ok(margins.into_iter().map(|dot| dot >= 0.0).collect())
+    }
+
+    /// Dot products of `vector` with the normals of the hyperplanes, whose signs make its
+    /// signature and magnitudes tell how close it is to flipping each bit.
+    pub fn margins(&self, vector: &[f32]) -> Result<Vec<f32>> {
+        if vector.len() != self.stored_vectors_dim {
+            return Err(ProximityError::DimensionMismatch {
+                expected: self.stored_vectors_dim,
+                found: vector.len(),
+            });
+        }
+        Ok(self
+            .projections
             .iter()
-            .map(|proj| {
-                let dot: f32 = vector.dot(proj);
-                dot >= 0.0
-            })
-            .collect()
+            .map(|proj| vector.dot(proj))
+            .collect())
+    }
+
+    pub fn dim(&self) -> usize {
+        self.stored_vectors_dim
+    }
+
+    /// Normals of the hyperplanes, one per signature bit.
+    pub fn projections(&self) -> &[Vec<f32>] {
+        &self.projections
+    }
+}
+
+impl HeapSize for SimHashHasher {
+    fn heap_bytes(&self) -> usize {
+        self.projections.heap_bytes()
     }
 }
 
@@ -82,6 +139,14 @@ mod tests {
         assert_ne!(h_unseeded.projections, h_seeded.projections);
     }
 
+    #[cfg(not(feature = "thread-rng"))]
+    #[test]
+    fn test_new_falls_back_to_fixed_seed() {
+        let unseeded = SimHashHasher::new(16, SIMD_LANECOUNT);
+        let seeded = SimHashHasher::new_seeded(16, SIMD_LANECOUNT, FALLBACK_SEED);
+        assert_eq!(unseeded.projections, seeded.projections);
+    }
+
     #[test]
     fn test_hash_seeded_matches_with_rng() {
         // compare new_seeded against manual with_rng
@@ -95,8 +160,8 @@ mod tests {
     fn test_hash_consistency_same_input() {
         let hasher = SimHashHasher::new_seeded(16, SIMD_LANECOUNT, 123);
         let vec = vec![1.0; SIMD_LANECOUNT];
-        let hash1 = hasher.hash(&vec);
-        let hash2 = hasher.hash(&vec);
+        let hash1 = hasher.hash(&vec).unwrap();
+        let hash2 = hasher.hash(&vec).unwrap();
         assert_eq!(hash1, hash2, "Hash must be consistent for same input");
     }
 
@@ -108,8 +173,8 @@ mod tests {
         let mut v2 = vec![0.0; dim];
         v1[0] = 1.0; // Unit vector along x
         v2[1] = 1.0; // Unit vector along y
-        let h1 = hasher.hash(&v1);
-        let h2 = hasher.hash(&v2);
+        let h1 = hasher.hash(&v1).unwrap();
+        let h2 = hasher.hash(&v2).unwrap();
 
         let hamming_distance: usize = h1.iter().zip(&h2).filter(|(a, b)| a != b).count();
         assert!(
@@ -130,10 +195,35 @@ mod tests {
         };
 
         let input = vec![2.0, -3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
-        let result = hasher.hash(&input);
+        let result = hasher.hash(&input).unwrap();
 
         // Expect: dot([2,-3], [1,0]) = 2 → true
         //         dot([2,-3], [0,1]) = -3 → false
         assert_eq!(result, vec![true, false]);
     }
+
+    #[test]
+    fn test_from_projections_round_trip() {
+        let hasher = SimHashHasher::new(4, SIMD_LANECOUNT);
+        let copy =
+            SimHashHasher::from_projections(SIMD_LANECOUNT, hasher.projections().to_vec()).unwrap();
+        let vec: Vec<f32> = (0..SIMD_LANECOUNT).map(|i| i as f32 - 3.5).collect();
+        assert_eq!(hasher.hash(&vec).unwrap(), copy.hash(&vec).unwrap());
+
+        assert!(SimHashHasher::from_projections(SIMD_LANECOUNT, vec![vec![1.0; 3]]).is_err());
+        assert!(SimHashHasher::from_projections(3, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_hash_wrong_dimension() {
+        let hasher = SimHashHasher::new_seeded(4, SIMD_LANECOUNT, 1);
+        let err = hasher.hash(&[1.0; 2 * SIMD_LANECOUNT]).unwrap_err();
+        assert!(matches!(
+            err,
+            ProximityError::DimensionMismatch {
+                expected: SIMD_LANECOUNT,
+                found
+            } if found == 2 * SIMD_LANECOUNT
+        ));
+    }
 }
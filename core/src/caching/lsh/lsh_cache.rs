@@ -1,70 +1,939 @@
 use crate::caching::approximate_cache::ApproximateCache;
 use crate::caching::approximate_cache::DefaultApproximateCache;
 use crate::caching::approximate_cache::Tolerance;
+use crate::caching::gdsf::weight;
+use crate::caching::lfu::DEFAULT_AGING_FACTOR;
+use crate::caching::memory::table_bytes;
+use crate::caching::ClockCache;
+use crate::caching::EntryInfo;
 use crate::caching::FifoCache;
+use crate::caching::GdsfCache;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::caching::LfuCache;
 use crate::caching::LruCache;
+use crate::caching::LruKCache;
+use crate::caching::MaybeSync;
+use crate::caching::S3FifoCache;
+use crate::caching::WTinyLfuCache;
 
 use crate::caching::lsh::hasher::SimHashHasher;
+use crate::caching::lsh::memo::{Route, RouteMemo};
+use crate::caching::lsh::LshDiagnosis;
+use crate::caching::lsh::ProbeSequence;
+use crate::caching::lsh::ShardRouter;
 use crate::numerics::ApproxComparable;
 use crate::numerics::VectorLike;
+use crate::numerics::SIMD_LANECOUNT;
+use crate::{ProximityError, Result};
+use std::borrow::Cow;
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use std::hash::Hash;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 /// A key-value store that uses cosine LSH to direct queries into fixed-size cache buckets.
 pub struct LshCache<C> {
     hasher: SimHashHasher,
     buckets: HashMap<Vec<bool>, C>,
     bucket_capacity: usize,
+    /// Bound on the entries of all buckets together, if any.
+    capacity: Option<usize>,
+    /// Whether buckets share `capacity` by their hits instead of being bounded by
+    /// `bucket_capacity`.
+    adaptive: bool,
+    /// Hits per bucket signature while adaptive, halved on every aging.
+    heat: HashMap<Vec<bool>, u64>,
+    /// Hits since the heat was last halved.
+    since_aging: u64,
+    /// Extra hyperplane of every split bucket, by the signature it had before the split.
+    splits: HashMap<Vec<bool>, SimHashHasher>,
+    /// Seed the hyperplanes were drawn from, if any, which those of splits derive from.
+    seed: Option<u64>,
+    /// Evictions of entries with hits after which a bucket splits, if buckets split.
+    split_threshold: Option<usize>,
+    /// Evictions of entries with hits per bucket signature, while buckets split.
+    useful_evictions: HashMap<Vec<bool>, usize>,
+    normalization: Normalization,
+    /// Routes of the most recent queries, if memoized.
+    memo: Option<RouteMemo>,
+    hit_rate: HitRateTracker,
 }
 
+/// How an [`LshCache`] compares keys once routed.
+///
+/// Routing never depends on the norm of keys, since the hyperplanes go through the
+/// origin, so keys are hashed as they are whatever the normalization.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Normalization {
+    /// Keys are stored and matched as they are, by L2 distance. Nothing is normalized,
+    /// so keys that are already unit vectors cost nothing extra.
+    #[default]
+    Raw,
+    /// Keys are scaled to unit norm before they are stored and matched, see
+    /// [`ApproxComparable::normalize`], so that they match by direction only: unit vectors
+    /// within `tol` of one another have a cosine similarity above `1 - tol² / 2`.
+    Cosine,
+}
+
+/// Extra signature bits a bucket may get from splits, past which it no longer splits,
+/// e.g. when it is full of near-identical keys that no hyperplane separates.
+const MAX_SPLIT_BITS: usize = 8;
+
 pub type LshFifoCache<K, V> = LshCache<FifoCache<K, V>>;
 pub type LshLruCache<K, V> = LshCache<LruCache<K, V>>;
+/// Buckets evict by LRU-2, see [`LruKCache`].
+pub type LshLruKCache<K, V> = LshCache<LruKCache<K, V>>;
+pub type LshLfuCache<K, V> = LshCache<LfuCache<K, V>>;
+/// Buckets evict by CLOCK, which only keeps a reference bit per entry, see [`ClockCache`].
+pub type LshClockCache<K, V> = LshCache<ClockCache<K, V>>;
+/// Every bucket has its own admission window and frequency sketch, see [`WTinyLfuCache`].
+pub type LshWTinyLfuCache<K, V> = LshCache<WTinyLfuCache<K, V>>;
+/// Every bucket has its own small and main queues and ghosts, see [`S3FifoCache`].
+pub type LshS3FifoCache<K, V> = LshCache<S3FifoCache<K, V>>;
+/// Buckets evict by Greedy-Dual-Size-Frequency, see [`GdsfCache`].
+pub type LshGdsfCache<K, V> = LshCache<GdsfCache<K, V>>;
+
+/// Recent hits of the bucket `sig`, plus one so that buckets never hit get a share.
+fn heat_of(heat: &HashMap<Vec<bool>, u64>, sig: &[bool]) -> u64 {
+    heat.get(sig).map_or(1, |hits| hits + 1)
+}
 
 impl<C> LshCache<C> {
-    pub fn new(num_hash: usize, dim: usize, bucket_capacity: usize, seed: Option<u64>) -> Self {
+    pub fn new(
+        num_hash: usize,
+        dim: usize,
+        bucket_capacity: usize,
+        seed: Option<u64>,
+    ) -> Result<Self> {
+        if dim == 0 || !dim.is_multiple_of(SIMD_LANECOUNT) {
+            return Err(ProximityError::InvalidArgument(format!(
+                "dimension must be a positive multiple of {SIMD_LANECOUNT}, got {dim}"
+            )));
+        }
+        if bucket_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "bucket capacity must be positive".into(),
+            ));
+        }
         let hasher = match seed {
             Some(s) => SimHashHasher::new_seeded(num_hash, dim, s),
             None => SimHashHasher::new(num_hash, dim),
         };
+        Ok(Self::with_hasher(hasher, bucket_capacity, seed))
+    }
+
+    /// Builds an empty cache routing keys of dimension `dim` with the given hyperplanes,
+    /// e.g. the [`projections`](Self::projections) of a cache being restored.
+    pub fn with_projections(
+        projections: Vec<Vec<f32>>,
+        dim: usize,
+        bucket_capacity: usize,
+    ) -> Result<Self> {
+        if bucket_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "bucket capacity must be positive".into(),
+            ));
+        }
+        let hasher = SimHashHasher::from_projections(dim, projections)?;
+        Ok(Self::with_hasher(hasher, bucket_capacity, None))
+    }
 
+    fn with_hasher(hasher: SimHashHasher, bucket_capacity: usize, seed: Option<u64>) -> Self {
         Self {
             hasher,
             buckets: HashMap::new(),
             bucket_capacity,
+            capacity: None,
+            adaptive: false,
+            heat: HashMap::new(),
+            since_aging: 0,
+            splits: HashMap::new(),
+            seed,
+            split_threshold: None,
+            useful_evictions: HashMap::new(),
+            normalization: Normalization::default(),
+            memo: None,
+            hit_rate: HitRateTracker::default(),
+        }
+    }
+
+    /// Normals of the hyperplanes that route keys into buckets, those of splits excluded:
+    /// a cache restored from them starts unsplit.
+    pub fn projections(&self) -> &[Vec<f32>] {
+        self.hasher.projections()
+    }
+
+    pub fn bucket_capacity(&self) -> usize {
+        self.bucket_capacity
+    }
+
+    /// Changes the capacity of every bucket, see
+    /// [`FifoCache::set_capacity`](crate::caching::FifoCache::set_capacity), and returns
+    /// the evicted entries. While [adaptive](Self::set_adaptive), the new capacity only
+    /// applies once adaptivity is turned off.
+    pub fn set_bucket_capacity<K, V>(
+        &mut self,
+        bucket_capacity: usize,
+    ) -> Result<Vec<(K, V, Tolerance)>>
+    where
+        K: ApproxComparable,
+        C: DefaultApproximateCache<K, V>,
+    {
+        if bucket_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "bucket capacity must be positive".into(),
+            ));
+        }
+        self.bucket_capacity = bucket_capacity;
+        self.resize_buckets()
+    }
+
+    /// Sets every bucket to the capacity it may currently grow to.
+    fn resize_buckets<K, V>(&mut self) -> Result<Vec<(K, V, Tolerance)>>
+    where
+        K: ApproxComparable,
+        C: DefaultApproximateCache<K, V>,
+    {
+        let limit = self.bucket_limit();
+        let mut evicted = Vec::new();
+        for bucket in self.buckets.values_mut() {
+            evicted.extend(bucket.set_capacity(limit)?);
+        }
+        Ok(evicted)
+    }
+
+    /// Capacity of every bucket: the global capacity while adaptive, as a single bucket
+    /// may then take all of it.
+    fn bucket_limit(&self) -> usize {
+        match (self.adaptive, self.capacity) {
+            (true, Some(capacity)) => capacity,
+            _ => self.bucket_capacity,
+        }
+    }
+
+    /// Bound on the number of entries across all buckets, if any.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Bounds the number of entries across all buckets, on top of the capacity of each
+    /// bucket, or lifts that bound with None. Above it, the fullest bucket evicts by its
+    /// own policy, so every insert over the bound looks at every bucket.
+    ///
+    /// Returns the entries evicted to fit in the new bound, or an error if the bound is
+    /// lifted while the cache is [adaptive](Self::set_adaptive).
+    pub fn set_capacity<K, V>(&mut self, capacity: Option<usize>) -> Result<Vec<(K, V, Tolerance)>>
+    where
+        K: ApproxComparable,
+        C: DefaultApproximateCache<K, V>,
+    {
+        if capacity == Some(0) {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        if self.adaptive && capacity.is_none() {
+            return Err(ProximityError::InvalidArgument(
+                "an adaptive cache needs a capacity".into(),
+            ));
+        }
+        self.capacity = capacity;
+        let mut evicted = self.evict_overflow(None);
+        if self.adaptive {
+            evicted.extend(self.resize_buckets()?);
+        }
+        Ok(evicted)
+    }
+
+    /// Whether buckets share the global capacity by their hits, see
+    /// [`set_adaptive`](Self::set_adaptive).
+    pub fn is_adaptive(&self) -> bool {
+        self.adaptive
+    }
+
+    /// Lets buckets grow and shrink under the global [capacity](Self::set_capacity)
+    /// instead of each being bounded by the bucket capacity, for when signatures are
+    /// skewed and most fixed-size buckets would stay empty.
+    ///
+    /// Every bucket may then grow until the cache is full. Above the capacity, the bucket
+    /// that most exceeds its share evicts, each bucket's share of the capacity being
+    /// proportional to its recent hits, plus one. Hits are halved every ten times the
+    /// capacity hits, so that buckets cooling down give their room back.
+    ///
+    /// Turning adaptivity off bounds every bucket by the bucket capacity again. Returns
+    /// the evicted entries, or an error if the cache has no global capacity.
+    pub fn set_adaptive<K, V>(&mut self, adaptive: bool) -> Result<Vec<(K, V, Tolerance)>>
+    where
+        K: ApproxComparable,
+        C: DefaultApproximateCache<K, V>,
+    {
+        if adaptive && self.capacity.is_none() {
+            return Err(ProximityError::InvalidArgument(
+                "an adaptive cache needs a capacity".into(),
+            ));
+        }
+        self.adaptive = adaptive;
+        self.heat.clear();
+        self.since_aging = 0;
+        let mut evicted = self.resize_buckets()?;
+        evicted.extend(self.evict_overflow(None));
+        Ok(evicted)
+    }
+
+    /// Counts a hit in the bucket `sig` towards its share of the capacity.
+    fn record_heat(&mut self, sig: &[bool]) {
+        let Some(capacity) = self.capacity.filter(|_| self.adaptive) else {
+            return;
+        };
+        match self.heat.get_mut(sig) {
+            Some(hits) => *hits += 1,
+            None => {
+                self.heat.insert(sig.to_vec(), 1);
+            }
         }
+        self.since_aging += 1;
+        if self.since_aging >= (capacity as u64).saturating_mul(DEFAULT_AGING_FACTOR) {
+            self.since_aging = 0;
+            self.heat.retain(|_, hits| {
+                *hits /= 2;
+                *hits > 0
+            });
+        }
+    }
+
+    /// Evicts from the fullest buckets while the cache is above its capacity, or from
+    /// those most above their share while adaptive. On ties, the bucket `spared` goes
+    /// last, e.g. the one an entry was just inserted into.
+    fn evict_overflow<K, V>(&mut self, spared: Option<&[bool]>) -> Vec<(K, V, Tolerance)>
+    where
+        K: ApproxComparable,
+        C: DefaultApproximateCache<K, V>,
+    {
+        let mut evicted = Vec::new();
+        let Some(capacity) = self.capacity else {
+            return evicted;
+        };
+        let mut len: usize = self.buckets.values().map(|bucket| bucket.len()).sum();
+        while len > capacity {
+            let (adaptive, heat) = (self.adaptive, &self.heat);
+            let total_heat: u64 = self.buckets.keys().map(|sig| heat_of(heat, sig)).sum();
+            let mut buckets: Vec<_> = self
+                .buckets
+                .iter_mut()
+                .map(|(sig, bucket)| {
+                    // share of the capacity, proportional to the heat of the bucket
+                    let share = if adaptive {
+                        capacity as f64 * heat_of(heat, sig) as f64 / total_heat as f64
+                    } else {
+                        0.0
+                    };
+                    let excess = bucket.len() as f64 - share;
+                    (excess, Some(sig.as_slice()) != spared, bucket)
+                })
+                .collect();
+            buckets.sort_by(|a, b| b.0.total_cmp(&a.0).then(b.1.cmp(&a.1)));
+            // a bucket whose entries are all pinned evicts nothing, the next one is tried
+            let Some(entry) = buckets
+                .into_iter()
+                .find_map(|(_, _, bucket)| bucket.evict())
+            else {
+                break;
+            };
+            evicted.push(entry);
+            len -= 1;
+        }
+        evicted
     }
 
-    fn signature(&self, key: &[f32]) -> Vec<bool> {
-        self.hasher.hash(key.normalized().as_ref())
+    /// The bucket `key` lands in, or None if no entry was ever inserted into it, or if
+    /// it was emptied before a [`compact`](ApproximateCache::compact).
+    ///
+    /// # Panics
+    /// If `key` is not of the cache dimension.
+    pub fn bucket(&self, key: &[f32]) -> Option<&C> {
+        self.buckets.get(&self.signature(key))
+    }
+
+    /// Shard `router` maps a bucket to. Splits do not move buckets, the halves of a
+    /// split bucket going where it went.
+    fn shard_of_bucket(&self, router: &ShardRouter, sig: &[bool]) -> usize {
+        router.shard(&sig[..self.hasher.projections().len()])
+    }
+
+    /// Copies of every entry, grouped by the shard `router` maps their signature to, e.g.
+    /// to hand them over to the nodes of a distributed cache.
+    pub fn entries_by_shard<K, V>(&self, router: &ShardRouter) -> Vec<Vec<(K, V, Tolerance)>>
+    where
+        K: ApproxComparable + Clone,
+        C: ApproximateCache<K, V>,
+    {
+        let mut shards: Vec<Vec<_>> = (0..router.num_shards()).map(|_| Vec::new()).collect();
+        for (sig, bucket) in &self.buckets {
+            shards[self.shard_of_bucket(router, sig)]
+                .extend(bucket.iter().map(|(k, v, tol)| (k.clone(), v, tol)));
+        }
+        shards
+    }
+
+    /// Removes the entries that `router` maps to another shard than `shard`, e.g. after
+    /// the number of shards changed, and returns them grouped by shard, that of `shard`
+    /// being empty.
+    pub fn take_foreign<K, V>(
+        &mut self,
+        router: &ShardRouter,
+        shard: usize,
+    ) -> Vec<Vec<(K, V, Tolerance)>>
+    where
+        K: ApproxComparable,
+        C: ApproximateCache<K, V>,
+    {
+        let mut shards: Vec<Vec<_>> = (0..router.num_shards()).map(|_| Vec::new()).collect();
+        let foreign: Vec<(Vec<bool>, usize)> = self
+            .buckets
+            .keys()
+            .map(|sig| (sig.clone(), self.shard_of_bucket(router, sig)))
+            .filter(|&(_, to)| to != shard)
+            .collect();
+        for (sig, to) in foreign {
+            if let Some(mut bucket) = self.buckets.remove(&sig) {
+                shards[to].extend(bucket.drain());
+            }
+            self.heat.remove(&sig);
+            self.useful_evictions.remove(&sig);
+        }
+        shards
+    }
+
+    /// Diagnoses how the cache routes `sample`, e.g. queries logged in production,
+    /// without counting them as lookups. See [`LshDiagnosis`].
+    ///
+    /// Every query is compared against every entry, so keep the sample small.
+    ///
+    /// # Panics
+    /// If a query is not of the cache dimension.
+    pub fn diagnose<K, V>(&self, sample: &[K]) -> LshDiagnosis
+    where
+        K: ApproxComparable + AsRef<[f32]> + Clone,
+        C: ApproximateCache<K, V>,
+    {
+        let mut diagnosis = LshDiagnosis {
+            num_hash: self.hasher.projections().len(),
+            len: self.buckets.values().map(|bucket| bucket.len()).sum(),
+            ..Default::default()
+        };
+        let mut probed_len = 0;
+        for target in sample {
+            let target = self.normalized(target);
+            let target = &*target;
+            let nearest = self
+                .buckets
+                .values()
+                .filter_map(|bucket| bucket.nearest(target))
+                .min_by(|(a, _), (b, _)| a.total_cmp(b));
+            let Some((distance, tolerance)) = nearest else {
+                continue;
+            };
+            let bucket = self.buckets.get(&self.route(target));
+            let in_bucket = bucket.and_then(|bucket| bucket.nearest(target));
+            diagnosis.lookups += 1;
+            probed_len += bucket.map_or(0, |bucket| bucket.len());
+            if in_bucket.is_some_and(|(d, _)| d <= distance) {
+                diagnosis.nearest_in_bucket += 1;
+            }
+            if in_bucket.is_some_and(|(d, tol)| d < tol) {
+                diagnosis.hits += 1;
+            } else if distance < tolerance {
+                diagnosis.routing_misses += 1;
+            } else {
+                diagnosis.tolerance_misses += 1;
+            }
+        }
+        if diagnosis.lookups > 0 {
+            diagnosis.mean_bucket_len = probed_len as f32 / diagnosis.lookups as f32;
+        }
+        diagnosis
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// Sets how keys are compared, see [`Normalization`]. Only keys inserted afterwards are
+    /// normalized, so it is meant to be set on an empty cache.
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        self.normalization = normalization;
+        if let Some(memo) = &mut self.memo {
+            memo.clear();
+        }
+    }
+
+    /// Number of recent queries whose route is memoized, if any.
+    pub fn memo_capacity(&self) -> Option<usize> {
+        self.memo.as_ref().map(|memo| memo.capacity())
+    }
+
+    /// Memoizes the route of the `capacity` most recent queries of
+    /// [`find`](ApproximateCache::find), or stops memoizing with None.
+    ///
+    /// A query repeated bit for bit, e.g. in a burst of identical requests, is then not
+    /// hashed again, and if it missed, its bucket is not scanned again until something is
+    /// inserted into it. Hits still go through their bucket, so that its policy sees them,
+    /// and so do misses for policies that count lookups, see
+    /// [`count_miss`](DefaultApproximateCache::count_miss).
+    pub fn set_memo_capacity(&mut self, capacity: Option<usize>) -> Result<()> {
+        if capacity == Some(0) {
+            return Err(ProximityError::InvalidArgument(
+                "memo capacity must be positive".into(),
+            ));
+        }
+        self.memo = capacity.map(RouteMemo::new);
+        Ok(())
+    }
+
+    /// Lookups whose route was memoized, since memoizing started.
+    pub fn memo_hits(&self) -> u64 {
+        self.memo.as_ref().map_or(0, |memo| memo.hits())
+    }
+
+    /// The key `target` is stored and looked up as.
+    fn normalized<'a, K: ApproxComparable + Clone>(&self, target: &'a K) -> Cow<'a, K> {
+        match self.normalization {
+            Normalization::Raw => Cow::Borrowed(target),
+            Normalization::Cosine => {
+                let mut owned = target.clone();
+                owned.normalize();
+                Cow::Owned(owned)
+            }
+        }
+    }
+
+    /// Signature of `key`, one bit per hyperplane, which picks the bucket it lands in.
+    /// Keys landing in a split bucket get one more bit per split.
+    ///
+    /// The hyperplanes go through the origin, so the signature does not depend on the
+    /// norm of `key` and it is hashed as is, without normalizing a copy.
+    ///
+    /// # Panics
+    /// If `key` is not of the cache dimension.
+    pub fn signature(&self, key: &[f32]) -> Vec<bool> {
+        self.scoped_signature(key, 0)
+    }
+
+    /// Signature of `key` among the keys of the same scope, see
+    /// [`ApproxComparable::scope_hash`]. The scope flips bits of the signature, so that
+    /// close keys of different scopes, which never match each other, land in different
+    /// buckets instead of competing for the capacity of the same one.
+    fn scoped_signature(&self, key: &[f32], scope: u64) -> Vec<bool> {
+        let mut sig = self.hasher.hash(key).unwrap_or_else(|e| panic!("{e}"));
+        for (i, bit) in sig.iter_mut().enumerate() {
+            *bit ^= (scope >> (i % 64)) & 1 == 1;
+        }
+        while let Some(split) = self.splits.get(&sig) {
+            sig.extend(split.hash(key).unwrap_or_else(|e| panic!("{e}")));
+        }
+        trace_event!(
+            signature = %sig.iter().map(|&bit| if bit { '1' } else { '0' }).collect::<String>(),
+            "routed"
+        );
+        sig
+    }
+
+    /// The signature `key` is stored and looked up under.
+    fn route<K: ApproxComparable + AsRef<[f32]>>(&self, key: &K) -> Vec<bool> {
+        self.scoped_signature(key.as_ref(), key.scope_hash())
+    }
+
+    /// Signatures of the buckets next to that of `key`, most likely to hold its neighbors
+    /// first, see [`ProbeSequence`]. They only have the bits of the
+    /// [`projections`](Self::projections), so they do not tell the halves of split
+    /// buckets apart.
+    ///
+    /// # Panics
+    /// If `key` is not of the cache dimension.
+    pub fn probe_sequence(&self, key: &[f32]) -> ProbeSequence {
+        let margins = self.hasher.margins(key).unwrap_or_else(|e| panic!("{e}"));
+        let signature: Vec<bool> = margins.iter().map(|&dot| dot >= 0.0).collect();
+        ProbeSequence::new(&signature, &margins).unwrap()
+    }
+
+    /// Number of buckets split so far.
+    pub fn splits(&self) -> usize {
+        self.splits.len()
+    }
+
+    /// Evictions of entries with hits after which a bucket splits, if buckets split.
+    pub fn split_threshold(&self) -> Option<usize> {
+        self.split_threshold
+    }
+
+    /// Lets a bucket split, see [`split_bucket`](Self::split_bucket), once it has evicted
+    /// `threshold` entries that had hits, which suggests it holds more useful entries
+    /// than it can. None stops splitting, past splits are kept.
+    ///
+    /// Detecting those evictions looks up the victim of every insert into a full bucket.
+    pub fn set_split_threshold(&mut self, threshold: Option<usize>) -> Result<()> {
+        if threshold == Some(0) {
+            return Err(ProximityError::InvalidArgument(
+                "split threshold must be positive".into(),
+            ));
+        }
+        self.split_threshold = threshold;
+        self.useful_evictions.clear();
+        Ok(())
+    }
+
+    /// Splits the bucket `key` lands in in two with an extra hyperplane, without
+    /// changing the signature of the other buckets, and reinserts its entries into the
+    /// halves, oldest victim first. Each half has the capacity of a bucket, so a hot
+    /// bucket stops thrashing without a larger `num_hash` for all keys.
+    ///
+    /// Reinserted entries lose their hits, priorities and pins. Returns None if the
+    /// bucket is empty or already split 8 times, or else the entries
+    /// that no longer fit, which only happens if pinned entries kept the bucket above
+    /// its capacity.
+    ///
+    /// # Panics
+    /// If `key` is not of the cache dimension.
+    pub fn split_bucket<K, V>(&mut self, key: &[f32]) -> Option<Vec<(K, V, Tolerance)>>
+    where
+        K: ApproxComparable + AsRef<[f32]>,
+        C: DefaultApproximateCache<K, V>,
+    {
+        let sig = self.signature(key);
+        self.split_at(sig)
+    }
+
+    fn split_at<K, V>(&mut self, sig: Vec<bool>) -> Option<Vec<(K, V, Tolerance)>>
+    where
+        K: ApproxComparable + AsRef<[f32]>,
+        C: DefaultApproximateCache<K, V>,
+    {
+        if sig.len() >= self.hasher.projections().len() + MAX_SPLIT_BITS {
+            return None;
+        }
+        let mut bucket = self.buckets.remove(&sig).filter(|b| !b.is_empty())?;
+        let entries: Vec<_> = bucket.drain().collect();
+        trace_event!(entries = entries.len(), depth = sig.len(), "split");
+        let split = match self.seed {
+            Some(seed) => {
+                let mut hasher = DefaultHasher::new();
+                sig.hash(&mut hasher);
+                SimHashHasher::new_seeded(1, self.hasher.dim(), seed ^ hasher.finish())
+            }
+            None => SimHashHasher::new(1, self.hasher.dim()),
+        };
+        self.useful_evictions.remove(&sig);
+        self.heat.remove(&sig);
+        self.splits.insert(sig, split);
+        if let Some(memo) = &mut self.memo {
+            memo.clear();
+        }
+        let limit = self.bucket_limit();
+        let mut evicted = Vec::new();
+        for (key, value, tol) in entries {
+            let half = self.route(&key);
+            evicted.extend(
+                self.buckets
+                    .entry(half)
+                    .or_insert_with(|| C::from_capacity(limit))
+                    .insert_evicting(key, value, tol),
+            );
+        }
+        Some(evicted)
+    }
+
+    /// Inserts `key` into its bucket with `insert`, then evicts above the global
+    /// capacity and splits the bucket if it keeps evicting entries that had hits.
+    fn insert_routed<K, V>(
+        &mut self,
+        mut key: K,
+        insert: impl FnOnce(&mut C, K) -> Vec<(K, V, Tolerance)>,
+    ) -> Vec<(K, V, Tolerance)>
+    where
+        K: ApproxComparable + AsRef<[f32]>,
+        C: DefaultApproximateCache<K, V>,
+    {
+        if self.normalization == Normalization::Cosine {
+            key.normalize();
+        }
+        let sig = self.route(&key);
+        if let Some(memo) = &mut self.memo {
+            memo.invalidate_misses(&sig);
+        }
+        let spared = self.capacity.map(|_| sig.clone());
+        let limit = self.bucket_limit();
+        let bucket = self
+            .buckets
+            .entry(sig.clone())
+            .or_insert_with(|| C::from_capacity(limit));
+        let useful = self.split_threshold.is_some()
+            && bucket
+                .next_victim(&key)
+                .and_then(|victim| bucket.entry_info(victim))
+                .is_some_and(|info| info.hits > 0);
+        let mut evicted = insert(bucket, key);
+        if let Some(spared) = spared {
+            evicted.extend(self.evict_overflow(Some(&spared)));
+        }
+        if useful {
+            let count = self.useful_evictions.entry(sig.clone()).or_default();
+            *count += 1;
+            if self
+                .split_threshold
+                .is_some_and(|threshold| *count >= threshold)
+            {
+                evicted.extend(self.split_at(sig).unwrap_or_default());
+            }
+        }
+        evicted
+    }
+}
+
+impl<K, V> LshCache<GdsfCache<K, V>>
+where
+    V: Clone + MaybeSync + 'static,
+    K: ApproxComparable + AsRef<[f32]> + MaybeSync + 'static,
+{
+    /// Inserts into the bucket of `key`, see [`GdsfCache::insert_with_cost`]. Costs and
+    /// sizes only weigh against the entries of the same bucket.
+    pub fn insert_with_cost(
+        &mut self,
+        key: K,
+        value: V,
+        tol: f32,
+        cost: f32,
+        size: usize,
+    ) -> Result<Vec<(K, V, Tolerance)>> {
+        let weight = weight(cost, size)?;
+        Ok(self.insert_routed(key, |bucket, key| {
+            bucket.insert_weighted(key, value, tol, 0, weight)
+        }))
     }
 }
 
 impl<K, V, C> ApproximateCache<K, V> for LshCache<C>
 where
-    V: Clone,
-    K: ApproxComparable + AsRef<[f32]>,
+    V: Clone + 'static,
+    K: ApproxComparable + AsRef<[f32]> + Clone + 'static,
     C: DefaultApproximateCache<K, V>,
 {
     /// Find a value by key, mutably accessing the bucket for potential reordering.
+    /// Repeated queries may skip hashing and scanning, see
+    /// [`set_memo_capacity`](LshCache::set_memo_capacity).
     fn find(&mut self, target: &K) -> Option<V> {
-        let sig = self.signature(target.as_ref());
-        let bucket = self.buckets.get_mut(&sig)?;
-        bucket.find(target)
+        trace_span!("find", cache = "lsh");
+        let query = target.as_ref();
+        let scope = target.scope_hash();
+        let route = self.memo.as_mut().and_then(|memo| memo.get(query, scope));
+        let normalized = self.normalized(target);
+        if let Some(route) = route.as_ref().filter(|route| route.miss) {
+            // not scanned, but still counted by policies that weigh keys by their lookups
+            if let Some(bucket) = self.buckets.get_mut(&*route.sig) {
+                bucket.count_miss(&normalized);
+            }
+            self.hit_rate.record(false);
+            return None;
+        }
+        let sig: Arc<[bool]> = match route {
+            Some(route) => route.sig,
+            None => self.route(&*normalized).into(),
+        };
+        trace_event!(bucket_len = ?self.buckets.get(&*sig).map(|bucket| bucket.len()), "bucket");
+        let found = self
+            .buckets
+            .get_mut(&*sig)
+            .and_then(|bucket| bucket.find(&normalized));
+        self.hit_rate.record(found.is_some());
+        if let Some(memo) = &mut self.memo {
+            let miss = found.is_none();
+            memo.record(
+                query,
+                scope,
+                Route {
+                    sig: sig.clone(),
+                    miss,
+                },
+            );
+        }
+        if found.is_some() {
+            self.record_heat(&sig);
+        }
+        found
     }
 
-    /// Insert a key-value pair, normalizing the key before hashing and storing.
-    fn insert(&mut self, key: K, value: V, tol: f32) {
-        let sig = self.signature(key.as_ref());
-        self.buckets
-            .entry(sig)
-            .or_insert_with(|| C::from_capacity(self.bucket_capacity))
-            .insert(key, value, tol);
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "lsh", k);
+        let target = self.normalized(target);
+        let target = &*target;
+        let sig = self.route(target);
+        trace_event!(bucket_len = ?self.buckets.get(&sig).map(|bucket| bucket.len()), "bucket");
+        let found = self
+            .buckets
+            .get_mut(&sig)
+            .map(|bucket| bucket.find_k(target, k))
+            .unwrap_or_default();
+        self.hit_rate.record(!found.is_empty());
+        if !found.is_empty() {
+            self.record_heat(&sig);
+        }
+        found
+    }
+
+    /// Insert a key-value pair into the bucket its key hashes to.
+    /// Priorities only weigh against the entries of the same bucket, also when the global
+    /// capacity evicts from the fullest buckets.
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tol: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "lsh", tolerance = tol, priority);
+        self.insert_routed(key, |bucket, key| {
+            bucket.insert_with_priority(key, value, tol, priority)
+        })
+    }
+
+    /// Only the bucket that `target` hashes to is scanned.
+    fn candidates(&self, target: &K) -> usize {
+        let target = self.normalized(target);
+        let sig = self.route(&*target);
+        self.buckets.get(&sig).map_or(0, |bucket| bucket.len())
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        Some(self.hasher.dim())
     }
 
     fn len(&self) -> usize {
         self.buckets.values().map(|b| b.len()).sum()
     }
+
+    /// Only the bucket that `target` hashes to is considered.
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        let target = self.normalized(target);
+        let target = &*target;
+        let sig = self.route(target);
+        self.buckets.get(&sig)?.nearest(target)
+    }
+
+    fn next_victim(&self, incoming: &K) -> Option<&K> {
+        let incoming = self.normalized(incoming);
+        let incoming = &*incoming;
+        let sig = self.route(incoming);
+        self.buckets.get(&sig)?.next_victim(incoming)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        let target = self.normalized(target);
+        let target = &*target;
+        let sig = self.route(target);
+        self.buckets
+            .get_mut(&sig)
+            .is_some_and(|bucket| bucket.pin(target))
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        let target = self.normalized(target);
+        let target = &*target;
+        let sig = self.route(target);
+        self.buckets
+            .get_mut(&sig)
+            .is_some_and(|bucket| bucket.unpin(target))
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let target = self.normalized(target);
+        let target = &*target;
+        let sig = self.route(target);
+        self.buckets.get(&sig)?.entry_info(target)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(
+            self.buckets
+                .values()
+                .flat_map(|bucket| bucket.entry_infos()),
+        )
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(self.buckets.values().flat_map(|bucket| bucket.iter()))
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        let drained: Vec<(K, V, Tolerance)> = self
+            .buckets
+            .drain()
+            .flat_map(|(_, mut bucket)| bucket.drain().collect::<Vec<_>>())
+            .collect();
+        Box::new(drained.into_iter())
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)>
+    where
+        K: Clone,
+    {
+        self.buckets
+            .values_mut()
+            .flat_map(|bucket| bucket.retain(&mut *keep))
+            .collect()
+    }
+
+    fn maintain(&mut self) {
+        self.buckets
+            .values_mut()
+            .for_each(|bucket| bucket.maintain());
+    }
+
+    fn compact(&mut self) {
+        // buckets are created on their first insert and kept when emptied
+        self.buckets.retain(|_, bucket| !bucket.is_empty());
+        self.buckets
+            .values_mut()
+            .for_each(|bucket| bucket.compact());
+        self.buckets.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let buckets: usize = self
+            .buckets
+            .iter()
+            .map(|(sig, bucket)| sig.heap_bytes() + bucket.memory_bytes())
+            .sum();
+        let heat: usize = self.heat.keys().map(|sig| sig.heap_bytes()).sum();
+        let splits: usize = self
+            .splits
+            .iter()
+            .map(|(sig, split)| sig.heap_bytes() + split.heap_bytes())
+            .sum();
+        let useful: usize = self
+            .useful_evictions
+            .keys()
+            .map(|sig| sig.heap_bytes())
+            .sum();
+        self.hasher.heap_bytes()
+            + self.memo.heap_bytes()
+            + table_bytes(&self.buckets)
+            + buckets
+            + table_bytes(&self.heat)
+            + heat
+            + table_bytes(&self.splits)
+            + splits
+            + table_bytes(&self.useful_evictions)
+            + useful
+    }
 }
 
 #[cfg(test)]
@@ -94,6 +963,13 @@ mod tests {
         }
     }
 
+    #[cfg(test)]
+    impl HeapSize for TestVecF32 {
+        fn heap_bytes(&self) -> usize {
+            self.0.heap_bytes()
+        }
+    }
+
     #[cfg(test)]
     impl ApproxComparable for TestVecF32 {
         fn fuzziness(&self, instore: &Self) -> f32 {
@@ -122,7 +998,7 @@ mod tests {
 
     #[test]
     fn test_lsh_fifo_cache_basic() {
-        let mut cache = LshFifoCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(42));
+        let mut cache = LshFifoCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(42)).unwrap();
 
         let k1 = TestVecF32(vec![0.1; DIM]);
         let k2 = TestVecF32(vec![-0.2; DIM]);
@@ -138,7 +1014,7 @@ mod tests {
 
     #[test]
     fn test_lsh_fifo_cache_eviction_order() {
-        let mut cache = LshFifoCache::new(NUM_HASH, DIM, 2, Some(123));
+        let mut cache = LshFifoCache::new(NUM_HASH, DIM, 2, Some(123)).unwrap();
 
         let k2 = TestVecF32(vec![1.0; DIM]);
         let k3 = TestVecF32(vec![2.0; DIM]);
@@ -156,7 +1032,7 @@ mod tests {
 
     #[test]
     fn test_lsh_fifo_cache_overwrite_behavior() {
-        let mut cache = LshFifoCache::new(NUM_HASH, DIM, 2, Some(77));
+        let mut cache = LshFifoCache::new(NUM_HASH, DIM, 2, Some(77)).unwrap();
 
         let k = TestVecF32(vec![1.0; DIM]);
         cache.insert(k.clone(), 111, TOL);
@@ -176,7 +1052,7 @@ mod tests {
 
     #[test]
     fn test_lsh_fifo_cache_capacity_one() {
-        let mut cache = LshFifoCache::new(NUM_HASH, DIM, 1, Some(321));
+        let mut cache = LshFifoCache::new(NUM_HASH, DIM, 1, Some(321)).unwrap();
 
         let k1 = TestVecF32(vec![2.0; DIM]);
         let k2 = TestVecF32(vec![1.0; DIM]);
@@ -193,7 +1069,7 @@ mod tests {
 
     #[test]
     fn test_lsh_lru_cache_basic() {
-        let mut cache = LshLruCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(99));
+        let mut cache = LshLruCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(99)).unwrap();
 
         let k1 = TestVecF32(vec![0.1; DIM]);
         let k2 = TestVecF32(vec![-0.1; DIM]);
@@ -210,7 +1086,7 @@ mod tests {
 
     #[test]
     fn test_lsh_lru_cache_eviction_order() {
-        let mut cache = LshLruCache::new(NUM_HASH, DIM, 2, Some(202));
+        let mut cache = LshLruCache::new(NUM_HASH, DIM, 2, Some(202)).unwrap();
 
         let k1 = TestVecF32(vec![1.0; DIM]);
         let k2 = TestVecF32(vec![2.0; DIM]);
@@ -230,7 +1106,7 @@ mod tests {
 
     #[test]
     fn test_lsh_lru_cache_overwrite_behavior() {
-        let mut cache = LshLruCache::new(NUM_HASH, DIM, 2, Some(303));
+        let mut cache = LshLruCache::new(NUM_HASH, DIM, 2, Some(303)).unwrap();
 
         let k = TestVecF32(vec![1.0; DIM]);
         cache.insert(k.clone(), 100, TOL);
@@ -244,7 +1120,7 @@ mod tests {
 
     #[test]
     fn test_lsh_lru_cache_capacity_one() {
-        let mut cache = LshLruCache::new(NUM_HASH, DIM, 1, Some(404));
+        let mut cache = LshLruCache::new(NUM_HASH, DIM, 1, Some(404)).unwrap();
 
         let k1 = TestVecF32(vec![2.0; DIM]);
         let k2 = TestVecF32(vec![1.0; DIM]);
@@ -261,4 +1137,568 @@ mod tests {
             "Only one key should remain in cache due to capacity 1"
         );
     }
+
+    #[test]
+    fn test_lsh_lru_cache_pinned_entry_survives() {
+        let mut cache = LshLruCache::new(NUM_HASH, DIM, 1, Some(505)).unwrap();
+
+        let k1 = TestVecF32(vec![2.0; DIM]);
+        let k2 = TestVecF32(vec![1.0; DIM]); // Same bucket as k1
+
+        cache.insert(k1.clone(), 1, TOL);
+        assert!(cache.pin(&k1));
+        cache.insert(k2.clone(), 2, TOL);
+        assert_eq!(cache.find(&k1), Some(1));
+        assert_eq!(cache.find(&k2), None);
+
+        assert!(cache.unpin(&k1));
+        cache.insert(k2.clone(), 2, TOL);
+        assert_eq!(cache.find(&k1), None);
+        assert_eq!(cache.find(&k2), Some(2));
+    }
+
+    #[test]
+    fn test_lsh_cache_entry_info() {
+        let mut cache = LshFifoCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(606)).unwrap();
+
+        let k1 = TestVecF32(vec![1.0; DIM]);
+        let k2 = TestVecF32(vec![-1.0; DIM]);
+        cache.insert(k1.clone(), 1, TOL);
+        cache.insert(k2.clone(), 2, TOL);
+        cache.find(&k1);
+
+        assert_eq!(cache.entry_info(&k1).unwrap().hits, 1);
+        assert_eq!(cache.entry_info(&k2).unwrap().hits, 0);
+        let total_hits: u64 = cache.entry_infos().map(|(_, info)| info.hits).sum();
+        assert_eq!(total_hits, 1);
+        assert_eq!(cache.entry_infos().count(), 2);
+    }
+
+    #[test]
+    fn test_lsh_cache_iter_and_drain() {
+        let mut cache = LshLruCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(707)).unwrap();
+
+        let k1 = TestVecF32(vec![1.0; DIM]);
+        let k2 = TestVecF32(vec![-1.0; DIM]);
+        cache.insert(k1.clone(), 1, TOL);
+        cache.insert(k2.clone(), 2, TOL);
+
+        let mut values: Vec<i32> = cache.iter().map(|(_, v, _)| v).collect();
+        values.sort();
+        assert_eq!(values, vec![1, 2]);
+
+        let mut drained: Vec<(TestVecF32, i32)> = cache.drain().map(|(k, v, _)| (k, v)).collect();
+        drained.sort_by_key(|(_, v)| *v);
+        assert_eq!(drained, vec![(k1.clone(), 1), (k2, 2)]);
+        assert!(cache.is_empty());
+        assert_eq!(cache.find(&k1), None);
+    }
+
+    #[test]
+    fn test_lsh_cache_recent_hit_rate() {
+        let mut cache = LshFifoCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(808)).unwrap();
+
+        let k1 = TestVecF32(vec![1.0; DIM]);
+        let k2 = TestVecF32(vec![-1.0; DIM]);
+        cache.insert(k1.clone(), 1, TOL);
+        cache.find(&k1);
+        cache.find(&k2); // Empty bucket, still counted as a miss
+        assert!((cache.recent_hit_rate() - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_lsh_cache_find_k() {
+        let mut cache = LshFifoCache::new(NUM_HASH, DIM, 3, Some(909)).unwrap();
+
+        let k1 = TestVecF32(vec![1.0; DIM]);
+        let k2 = TestVecF32(vec![1.1; DIM]); // Same bucket as k1
+        cache.insert(k1.clone(), 1, 1.0);
+        cache.insert(k2.clone(), 2, 1.0);
+
+        let found: Vec<i32> = cache.find_k(&k1, 2).into_iter().map(|(v, _)| v).collect();
+        assert_eq!(found, vec![1, 2]);
+        assert!(cache.find_k(&TestVecF32(vec![-1.0; DIM]), 2).is_empty());
+    }
+
+    #[test]
+    fn test_lsh_cache_next_victim() {
+        let mut cache = LshFifoCache::new(NUM_HASH, DIM, 1, Some(1010)).unwrap();
+
+        let k1 = TestVecF32(vec![1.0; DIM]);
+        let k2 = TestVecF32(vec![2.0; DIM]); // Same bucket as k1
+        let k3 = TestVecF32(vec![-1.0; DIM]); // Different bucket
+        assert_eq!(cache.next_victim(&k1), None);
+        cache.insert(k1.clone(), 1, TOL);
+        assert_eq!(cache.next_victim(&k2), Some(&k1));
+        assert_eq!(cache.next_victim(&k3), None);
+    }
+
+    #[test]
+    fn test_lsh_cache_candidates() {
+        let mut cache = LshFifoCache::new(NUM_HASH, DIM, 4, Some(1010)).unwrap();
+        let k1 = TestVecF32(vec![1.0; DIM]);
+        let k2 = TestVecF32(vec![2.0; DIM]); // Same bucket as k1
+        let k3 = TestVecF32(vec![-1.0; DIM]); // Different bucket
+        cache.insert(k1.clone(), 1, TOL);
+        cache.insert(k2, 2, TOL);
+        assert_eq!(cache.candidates(&k1), 2);
+        assert_eq!(cache.candidates(&k3), 0);
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let bad_dim: Result<LshLruCache<TestVecF32, i32>> =
+            LshCache::new(NUM_HASH, DIM + 1, 4, None);
+        assert!(matches!(bad_dim, Err(ProximityError::InvalidArgument(_))));
+        let empty: Result<LshFifoCache<TestVecF32, i32>> = LshCache::new(NUM_HASH, DIM, 0, None);
+        assert!(matches!(empty, Err(ProximityError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_with_projections_routes_alike() {
+        let cache: LshFifoCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, BUCKET_CAP, None).unwrap();
+        let restored: LshFifoCache<TestVecF32, i32> =
+            LshCache::with_projections(cache.projections().to_vec(), DIM, BUCKET_CAP).unwrap();
+        assert_eq!(restored.bucket_capacity(), BUCKET_CAP);
+        for i in 0..16 {
+            let key: Vec<f32> = (0..DIM).map(|j| ((i * DIM + j) as f32).sin()).collect();
+            assert_eq!(cache.signature(&key), restored.signature(&key));
+        }
+
+        let short = vec![vec![1.0; DIM - 1]];
+        let bad: Result<LshFifoCache<TestVecF32, i32>> =
+            LshCache::with_projections(short, DIM, BUCKET_CAP);
+        assert!(matches!(bad, Err(ProximityError::DimensionMismatch { .. })));
+    }
+
+    #[test]
+    fn test_bucket_holds_keys_of_same_signature() {
+        let mut cache: LshFifoCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(7)).unwrap();
+        let k1 = TestVecF32(vec![0.5; DIM]);
+        let k2 = TestVecF32(vec![-0.5; DIM]);
+        assert!(cache.bucket(&k1.0).is_none());
+        cache.insert(k1.clone(), 1, TOL);
+        cache.insert(k2.clone(), 2, TOL);
+
+        assert_eq!(cache.signature(&k1.0).len(), NUM_HASH);
+        // opposite vectors fall on opposite sides of every hyperplane
+        let flipped: Vec<bool> = cache.signature(&k1.0).iter().map(|bit| !bit).collect();
+        assert_eq!(cache.signature(&k2.0), flipped);
+        // same direction, same bucket
+        let bucket = cache.bucket(&[2.0; DIM]).unwrap();
+        let keys: Vec<_> = bucket.iter().map(|(k, _, _)| k.clone()).collect();
+        assert_eq!(keys, vec![k1]);
+    }
+
+    #[test]
+    fn test_cosine_normalization() {
+        let mut cache: LshFifoCache<Vec<f32>, i32> =
+            LshCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(7)).unwrap();
+        assert_eq!(cache.normalization(), Normalization::Raw);
+        cache.insert(vec![1.0; DIM], 1, 0.1);
+        assert_eq!(cache.find(&vec![3.0; DIM]), None);
+
+        cache.set_normalization(Normalization::Cosine);
+        cache.insert(vec![-2.0; DIM], 2, 0.1);
+        let (stored, _, _) = cache.iter().find(|(_, v, _)| *v == 2).unwrap();
+        assert!((stored.dot(stored) - 1.0).abs() < 1e-5);
+        assert_eq!(cache.find(&vec![-30.0; DIM]), Some(2));
+        assert_eq!(
+            cache.nearest(&vec![-0.5; DIM]).map(|(d, _)| d < 1e-5),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_diagnose_routing_and_tolerance_misses() {
+        let keys: Vec<Vec<f32>> = (0..64)
+            .map(|i| (0..DIM).map(|j| ((i * 7 + j * 3) as f32).sin()).collect())
+            .collect();
+        let noisy: Vec<Vec<f32>> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                key.iter()
+                    .enumerate()
+                    .map(|(j, x)| x + 0.2 * ((i * 13 + j * 5) as f32).cos())
+                    .collect()
+            })
+            .collect();
+
+        // fine buckets scatter noisy queries away from their key
+        let mut fine: LshFifoCache<Vec<f32>, i32> = LshCache::new(16, DIM, 64, Some(3)).unwrap();
+        for (i, key) in keys.iter().enumerate() {
+            fine.insert(key.clone(), i as i32, 1.0);
+        }
+        let diagnosis = fine.diagnose(&noisy);
+        assert_eq!(diagnosis.lookups, 64);
+        assert_eq!(diagnosis.tolerance_misses, 0);
+        assert!(diagnosis.routing_misses > 6, "{diagnosis:?}");
+        assert_eq!(diagnosis.suggested_num_hash(), 15);
+        assert_eq!(fine.recent_hit_rate(), 0.0);
+
+        // a single hyperplane routes well but barely prunes
+        let mut coarse: LshFifoCache<Vec<f32>, i32> = LshCache::new(1, DIM, 64, Some(3)).unwrap();
+        for (i, key) in keys.iter().enumerate() {
+            coarse.insert(key.clone(), i as i32, 0.01);
+        }
+        let diagnosis = coarse.diagnose(&keys[..8]);
+        assert_eq!(diagnosis.routing_recall(), 1.0);
+        assert_eq!(diagnosis.hits, 8);
+        assert!(diagnosis.mean_bucket_len > 16.0);
+        assert_eq!(diagnosis.suggested_num_hash(), 2);
+        // the noisy queries are out of tolerance wherever they land
+        assert_eq!(coarse.diagnose(&noisy).tolerance_misses, 64);
+
+        let empty: LshFifoCache<Vec<f32>, i32> = LshCache::new(1, DIM, 64, Some(3)).unwrap();
+        assert_eq!(empty.diagnose(&keys).lookups, 0);
+    }
+
+    #[test]
+    fn test_probe_sequence_flips_closest_hyperplanes_first() {
+        let cache: LshFifoCache<Vec<f32>, i32> = LshCache::new(NUM_HASH, DIM, 4, Some(7)).unwrap();
+        let key: Vec<f32> = (0..DIM).map(|j| (j as f32).sin()).collect();
+        let margins: Vec<f32> = cache
+            .projections()
+            .iter()
+            .map(|proj| key.dot(proj).abs())
+            .collect();
+        let closest = (0..NUM_HASH)
+            .min_by(|&a, &b| margins[a].total_cmp(&margins[b]))
+            .unwrap();
+
+        let signature = cache.signature(&key);
+        let probes: Vec<Vec<bool>> = cache.probe_sequence(&key).collect();
+        assert_eq!(probes.len(), (1 << NUM_HASH) - 1);
+        let flipped: Vec<usize> = (0..NUM_HASH)
+            .filter(|&i| probes[0][i] != signature[i])
+            .collect();
+        assert_eq!(flipped, [closest]);
+    }
+
+    #[test]
+    fn test_split_by_shard() {
+        let mut cache: LshFifoCache<Vec<f32>, i32> =
+            LshCache::new(NUM_HASH, DIM, 4, Some(7)).unwrap();
+        let keys: Vec<Vec<f32>> = (0..32)
+            .map(|i| (0..DIM).map(|j| ((i * 7 + j * 3) as f32).sin()).collect())
+            .collect();
+        for (i, key) in keys.iter().enumerate() {
+            cache.insert(key.clone(), i as i32, TOL);
+        }
+        let router = ShardRouter::new(3).unwrap();
+        let shards = cache.entries_by_shard(&router);
+        assert_eq!(shards.iter().map(Vec::len).sum::<usize>(), cache.len());
+        for (shard, entries) in shards.iter().enumerate() {
+            for (key, _, _) in entries {
+                assert_eq!(router.shard(&cache.signature(key)), shard);
+            }
+        }
+
+        let len = cache.len();
+        let foreign = cache.take_foreign(&router, 0);
+        assert!(foreign[0].is_empty());
+        assert_eq!(foreign[1].len(), shards[1].len());
+        assert_eq!(cache.len(), len - shards[1].len() - shards[2].len());
+        assert!(cache
+            .iter()
+            .all(|(key, _, _)| router.shard(&cache.signature(key)) == 0));
+    }
+
+    #[test]
+    fn test_normalized_keys_route_as_raw_keys() {
+        use crate::numerics::NormalizedVector;
+
+        let mut cache: LshFifoCache<NormalizedVector, i32> =
+            LshCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(7)).unwrap();
+        let raw: Vec<f32> = (0..DIM).map(|i| i as f32 - 3.5).collect();
+        let key = NormalizedVector::from(raw.clone());
+        assert_eq!(cache.signature(key.as_ref()), cache.signature(&raw));
+
+        cache.insert(key, 1, 0.5);
+        let near: Vec<f32> = raw.iter().map(|x| x + 0.1).collect();
+        assert_eq!(cache.find(&NormalizedVector::from(near)), Some(1));
+        // same direction, but farther than the tolerance
+        let scaled: Vec<f32> = raw.iter().map(|x| x * 2.0).collect();
+        assert_eq!(cache.find(&NormalizedVector::from(scaled)), None);
+    }
+
+    #[test]
+    fn test_set_bucket_capacity() {
+        let mut cache: LshLruCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, 3, Some(7)).unwrap();
+        // keys of the same direction share a bucket
+        let keys: Vec<_> = (1..=3).map(|i| TestVecF32(vec![i as f32; DIM])).collect();
+        for (i, key) in keys.iter().enumerate() {
+            cache.insert(key.clone(), i as i32, TOL);
+        }
+        cache.find(&keys[0]);
+        let evicted = cache.set_bucket_capacity(1).unwrap();
+        let evicted: Vec<_> = evicted.into_iter().map(|(_, v, _)| v).collect();
+        assert_eq!(evicted, vec![1, 2]);
+        assert_eq!(cache.bucket_capacity(), 1);
+        assert_eq!(cache.find(&keys[0]), Some(0));
+
+        // buckets created later get the new capacity too
+        let other = TestVecF32(vec![-1.0; DIM]);
+        cache.insert(other.clone(), 3, TOL);
+        cache.insert(TestVecF32(vec![-2.0; DIM]), 4, TOL);
+        assert_eq!(cache.find(&other), None);
+        assert!(cache.set_bucket_capacity::<TestVecF32, i32>(0).is_err());
+    }
+
+    #[test]
+    fn test_memory_bytes() {
+        let mut cache: LshLruCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(7)).unwrap();
+        // the hyperplanes are allocated upfront
+        let empty = cache.memory_bytes();
+        assert!(empty >= NUM_HASH * DIM * size_of::<f32>());
+
+        cache.insert(TestVecF32(vec![1.0; DIM]), 1, TOL);
+        let one = cache.memory_bytes();
+        assert!(one >= empty + DIM * size_of::<f32>());
+        cache.insert(TestVecF32(vec![-1.0; DIM]), 2, TOL);
+        assert!(cache.memory_bytes() > one);
+    }
+
+    #[test]
+    fn test_compact_drops_empty_buckets() {
+        let mut cache: LshLruCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(7)).unwrap();
+        let up = TestVecF32(vec![1.0; DIM]);
+        let down = TestVecF32(vec![-1.0; DIM]);
+        cache.insert(up.clone(), 1, TOL);
+        cache.insert(down.clone(), 2, TOL);
+        cache.set_capacity::<TestVecF32, i32>(Some(1)).unwrap();
+        let emptied = if cache.find(&up).is_some() {
+            &down
+        } else {
+            &up
+        };
+        assert!(cache.bucket(&emptied.0).unwrap().is_empty());
+
+        let before = cache.memory_bytes();
+        cache.compact();
+        assert!(cache.bucket(&emptied.0).is_none());
+        assert!(cache.memory_bytes() < before);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_global_capacity() {
+        let mut cache: LshFifoCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, 4, Some(7)).unwrap();
+        let up = |i: i32| TestVecF32(vec![i as f32; DIM]);
+        let down = |i: i32| TestVecF32(vec![-i as f32; DIM]);
+        for i in 1..=3 {
+            cache.insert(up(i), i, TOL);
+        }
+        cache.insert(down(1), -1, TOL);
+        assert_eq!(cache.capacity(), None);
+
+        // the fullest bucket evicts its oldest entry
+        let evicted = cache.set_capacity(Some(3)).unwrap();
+        let evicted: Vec<_> = evicted.into_iter().map(|(_, v, _)| v).collect();
+        assert_eq!(evicted, vec![1]);
+        assert_eq!(cache.len(), 3);
+
+        // on ties, the bucket just inserted into is spared
+        let evicted = cache.insert_evicting(down(2), -2, TOL);
+        let evicted: Vec<_> = evicted.into_iter().map(|(_, v, _)| v).collect();
+        assert_eq!(evicted, vec![2]);
+        assert_eq!(cache.find(&down(1)), Some(-1));
+        assert_eq!(cache.find(&down(2)), Some(-2));
+
+        assert!(cache.set_capacity::<TestVecF32, i32>(Some(0)).is_err());
+        assert!(cache
+            .set_capacity::<TestVecF32, i32>(None)
+            .unwrap()
+            .is_empty());
+        cache.insert(down(3), -3, TOL);
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn test_adaptive_bucket_capacity() {
+        let mut cache: LshFifoCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, 1, Some(7)).unwrap();
+        let up = |i: i32| TestVecF32(vec![i as f32; DIM]);
+        let down = |i: i32| TestVecF32(vec![-i as f32; DIM]);
+        let values = |evicted: Vec<(TestVecF32, i32, f32)>| -> Vec<i32> {
+            evicted.into_iter().map(|(_, v, _)| v).collect()
+        };
+        assert!(cache.set_adaptive::<TestVecF32, i32>(true).is_err());
+        cache.set_capacity::<TestVecF32, i32>(Some(4)).unwrap();
+        assert!(cache
+            .set_adaptive::<TestVecF32, i32>(true)
+            .unwrap()
+            .is_empty());
+        assert!(cache.is_adaptive());
+
+        // a bucket grows past the bucket capacity while the cache has room
+        for i in 1..=3 {
+            cache.insert(up(i), i, TOL);
+        }
+        cache.insert(down(1), -1, TOL);
+        assert_eq!(cache.len(), 4);
+        // without hits, buckets share the capacity evenly
+        assert_eq!(values(cache.insert_evicting(down(2), -2, TOL)), vec![1]);
+
+        // a hot bucket takes more of it
+        for _ in 0..4 {
+            cache.find(&up(2));
+        }
+        assert_eq!(values(cache.insert_evicting(up(4), 4, TOL)), vec![-1]);
+        assert_eq!(cache.bucket(&up(1).0).unwrap().len(), 3);
+
+        assert!(cache.set_capacity::<TestVecF32, i32>(None).is_err());
+        // turning adaptivity off bounds every bucket again
+        assert_eq!(
+            values(cache.set_adaptive::<TestVecF32, i32>(false).unwrap()),
+            vec![2, 3]
+        );
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_split_bucket() {
+        let mut cache: LshFifoCache<TestVecF32, i32> = LshCache::new(1, DIM, 4, Some(7)).unwrap();
+        let basis = |i: usize| {
+            let mut key = vec![0.0; DIM];
+            key[i] = 1.0;
+            TestVecF32(key)
+        };
+        let sig = cache.signature(&basis(0).0);
+        let keys: Vec<TestVecF32> = (0..DIM)
+            .map(basis)
+            .filter(|key| cache.signature(&key.0) == sig)
+            .take(3)
+            .collect();
+        for (i, key) in keys.iter().enumerate() {
+            cache.insert(key.clone(), i as i32, TOL);
+        }
+        assert!(cache
+            .split_bucket::<TestVecF32, i32>(&basis(0).0)
+            .unwrap()
+            .is_empty());
+        assert_eq!(cache.splits(), 1);
+        assert_eq!(cache.signature(&basis(0).0).len(), 2);
+        // the entries were rehashed into the halves, and are all found
+        assert_eq!(cache.len(), keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(cache.find(key), Some(i as i32));
+        }
+
+        // keys of the same direction are never separated, splitting stops
+        let mut cache: LshFifoCache<TestVecF32, i32> = LshCache::new(1, DIM, 4, Some(7)).unwrap();
+        let up = |i: i32| TestVecF32(vec![i as f32; DIM]);
+        assert!(cache.split_bucket::<TestVecF32, i32>(&up(1).0).is_none());
+        cache.insert(up(1), 1, TOL);
+        cache.insert(up(2), 2, TOL);
+        for _ in 0..MAX_SPLIT_BITS {
+            assert!(cache.split_bucket::<TestVecF32, i32>(&up(1).0).is_some());
+        }
+        assert!(cache.split_bucket::<TestVecF32, i32>(&up(1).0).is_none());
+        assert_eq!(cache.bucket(&up(1).0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_split_on_useful_evictions() {
+        let mut cache: LshLruCache<TestVecF32, i32> = LshCache::new(1, DIM, 1, Some(7)).unwrap();
+        assert!(cache.set_split_threshold(Some(0)).is_err());
+        cache.set_split_threshold(Some(1)).unwrap();
+        let basis = |i: usize| {
+            let mut key = vec![0.0; DIM];
+            key[i] = 1.0;
+            TestVecF32(key)
+        };
+        let sig = cache.signature(&basis(0).0);
+        let twin = (1..DIM)
+            .map(basis)
+            .find(|key| cache.signature(&key.0) == sig)
+            .unwrap();
+
+        // an entry never hit goes without a split
+        cache.insert(basis(0), 0, TOL);
+        cache.insert(twin.clone(), 1, TOL);
+        assert_eq!(cache.splits(), 0);
+
+        // one that was hit counts towards the threshold
+        cache.find(&twin);
+        let evicted = cache.insert_evicting(basis(0), 0, TOL);
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(cache.splits(), 1);
+    }
+
+    #[test]
+    fn test_lsh_gdsf_insert_with_cost() {
+        let mut cache: LshGdsfCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(7)).unwrap();
+        let up = |i: i32| TestVecF32(vec![i as f32; DIM]);
+        cache.insert_with_cost(up(1), 1, TOL, 10.0, 1).unwrap();
+        cache.insert(up(2), 2, TOL);
+        cache.find(&up(2));
+        // the bucket keeps the costlier entry over the more frequent one
+        let evicted = cache.insert_evicting(up(3), 3, TOL);
+        let evicted: Vec<_> = evicted.into_iter().map(|(_, v, _)| v).collect();
+        assert_eq!(evicted, vec![2]);
+        assert!(cache.insert_with_cost(up(4), 4, TOL, 1.0, 0).is_err());
+    }
+
+    #[test]
+    fn test_memoized_routes() {
+        let mut cache: LshLruCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(3)).unwrap();
+        assert!(cache.set_memo_capacity(Some(0)).is_err());
+        cache.set_memo_capacity(Some(4)).unwrap();
+        let key = TestVecF32(vec![1.0; DIM]);
+
+        assert_eq!(cache.find(&key), None);
+        assert_eq!(cache.find(&key), None);
+        assert_eq!(cache.memo_hits(), 1);
+        // the memoized miss is forgotten once its bucket gets an entry
+        cache.insert(key.clone(), 1, TOL);
+        for _ in 0..3 {
+            assert_eq!(cache.find(&key), Some(1));
+        }
+        assert_eq!(cache.memo_hits(), 4);
+        assert_eq!(cache.entry_info(&key).unwrap().hits, 3);
+        assert!((cache.recent_hit_rate() - 0.6).abs() < 1e-2);
+
+        // routes are forgotten once signatures change
+        cache.split_bucket::<TestVecF32, i32>(&key.0).unwrap();
+        assert_eq!(cache.find(&key), Some(1));
+        assert_eq!(cache.memo_hits(), 4);
+        cache.set_memo_capacity(None).unwrap();
+        assert_eq!(cache.memo_hits(), 0);
+    }
+
+    #[test]
+    fn test_memoized_misses_are_counted() {
+        let mut cache: LshWTinyLfuCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, BUCKET_CAP, Some(3)).unwrap();
+        cache.set_memo_capacity(Some(4)).unwrap();
+        let stored = TestVecF32(vec![1.0; DIM]);
+        // same direction, so same bucket, but out of tolerance
+        let query = TestVecF32(vec![2.0; DIM]);
+        cache.insert(stored, 1, TOL);
+        for _ in 0..3 {
+            assert_eq!(cache.find(&query), None);
+        }
+        assert_eq!(cache.memo_hits(), 2);
+        let sketch = cache.bucket(&query.0).unwrap().sketch();
+        assert_eq!(sketch.estimate(&query), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a key of dimension 8, got 16")]
+    fn test_find_with_wrong_dimension() {
+        let mut cache: LshLruCache<TestVecF32, i32> =
+            LshCache::new(NUM_HASH, DIM, 4, Some(1)).unwrap();
+        assert_eq!(cache.key_dim(), Some(DIM));
+        cache.find(&TestVecF32(vec![1.0; 2 * DIM]));
+    }
 }
@@ -0,0 +1,265 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasherDefault, Hasher};
+use std::mem::size_of;
+use std::sync::Arc;
+
+use crate::caching::memory::{slots_bytes, table_bytes};
+use crate::caching::HeapSize;
+
+/// What a memoized query was last routed to.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Route {
+    /// Shared, so that answering from the memo copies nothing.
+    pub(crate) sig: Arc<[bool]>,
+    /// Whether the query missed in its bucket, which holds until something is inserted
+    /// into that bucket: evictions never make a query match. A memoized miss skips the
+    /// scan of the bucket, but not its
+    /// [`count_miss`](crate::caching::approximate_cache::DefaultApproximateCache::count_miss).
+    pub(crate) miss: bool,
+}
+
+/// A memoized query, by the bits of its components and the hash of its scope, and its
+/// route.
+struct Memoized {
+    bits: Box<[u32]>,
+    scope: u64,
+    route: Route,
+}
+
+/// Hasher of keys that already are hashes, see [`query_hash`].
+#[derive(Default)]
+struct Prehashed(u64);
+
+impl Hasher for Prehashed {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, _bytes: &[u8]) {
+        unreachable!("only hashes are hashed")
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+}
+
+type ByHash = BuildHasherDefault<Prehashed>;
+
+/// FxHash of the bits of the components of `query` and of its `scope`, see
+/// [`ApproxComparable::scope_hash`](crate::numerics::ApproxComparable::scope_hash): a
+/// rotate, a xor and a multiply per component, so that looking a query up allocates
+/// nothing.
+fn query_hash(query: &[f32], scope: u64) -> u64 {
+    const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+    query.iter().fold(query.len() as u64 ^ scope, |hash, x| {
+        (hash.rotate_left(5) ^ u64::from(x.to_bits())).wrapping_mul(SEED)
+    })
+}
+
+fn same_query(memoized: &Memoized, query: &[f32], scope: u64) -> bool {
+    memoized.scope == scope
+        && memoized.bits.len() == query.len()
+        && memoized
+            .bits
+            .iter()
+            .zip(query)
+            .all(|(&b, x)| b == x.to_bits())
+}
+
+/// Routes of the most recent queries, by the bits of their components and their scope,
+/// so that a query repeated bit for bit skips hashing, and scanning too if it missed,
+/// while its bucket still counts the lookup. The oldest query is forgotten first.
+///
+/// Queries are keyed by their [`query_hash`], and two queries of the same hash take
+/// turns in one slot, the bits telling them apart.
+pub(crate) struct RouteMemo {
+    capacity: usize,
+    routes: HashMap<u64, Memoized, ByHash>,
+    /// Hashes of the memoized queries, oldest first.
+    order: VecDeque<u64>,
+    /// Hashes of the memoized queries that missed, by the bucket they missed in.
+    misses: HashMap<Vec<bool>, HashSet<u64, ByHash>>,
+    hits: u64,
+}
+
+impl RouteMemo {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            routes: HashMap::default(),
+            order: VecDeque::new(),
+            misses: HashMap::new(),
+            hits: 0,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Lookups answered so far.
+    pub(crate) fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub(crate) fn get(&mut self, query: &[f32], scope: u64) -> Option<Route> {
+        let memoized = self
+            .routes
+            .get(&query_hash(query, scope))
+            .filter(|memoized| same_query(memoized, query, scope))?;
+        self.hits += 1;
+        Some(memoized.route.clone())
+    }
+
+    pub(crate) fn record(&mut self, query: &[f32], scope: u64, route: Route) {
+        let hash = query_hash(query, scope);
+        if let Some(known) = self.routes.get_mut(&hash) {
+            let same = same_query(known, query, scope);
+            if same && known.route == route {
+                return;
+            }
+            let known_route = std::mem::replace(&mut known.route, route.clone());
+            if !same {
+                known.bits = query.iter().map(|x| x.to_bits()).collect();
+                known.scope = scope;
+            }
+            self.forget_miss(hash, &known_route);
+            self.index_miss(hash, &route);
+            return;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                if let Some(memoized) = self.routes.remove(&oldest) {
+                    self.forget_miss(oldest, &memoized.route);
+                }
+            }
+        }
+        self.index_miss(hash, &route);
+        self.order.push_back(hash);
+        let bits = query.iter().map(|x| x.to_bits()).collect();
+        self.routes.insert(hash, Memoized { bits, scope, route });
+    }
+
+    /// Forgets the misses in the bucket `sig`, e.g. once an entry is inserted into it.
+    pub(crate) fn invalidate_misses(&mut self, sig: &[bool]) {
+        let Some(hashes) = self.misses.remove(sig) else {
+            return;
+        };
+        for hash in hashes {
+            if let Some(memoized) = self.routes.get_mut(&hash) {
+                memoized.route.miss = false;
+            }
+        }
+    }
+
+    /// Forgets every route, e.g. once signatures change.
+    pub(crate) fn clear(&mut self) {
+        self.routes.clear();
+        self.order.clear();
+        self.misses.clear();
+    }
+
+    fn index_miss(&mut self, hash: u64, route: &Route) {
+        if route.miss {
+            self.misses
+                .entry(route.sig.to_vec())
+                .or_default()
+                .insert(hash);
+        }
+    }
+
+    fn forget_miss(&mut self, hash: u64, route: &Route) {
+        if !route.miss {
+            return;
+        }
+        if let Some(hashes) = self.misses.get_mut(&*route.sig) {
+            hashes.remove(&hash);
+            if hashes.is_empty() {
+                self.misses.remove(&*route.sig);
+            }
+        }
+    }
+}
+
+impl HeapSize for RouteMemo {
+    fn heap_bytes(&self) -> usize {
+        let routes: usize = self
+            .routes
+            .values()
+            .map(|memoized| {
+                slots_bytes::<u32>(memoized.bits.len())
+                    + slots_bytes::<bool>(memoized.route.sig.len())
+            })
+            .sum();
+        let misses: usize = self
+            .misses
+            .iter()
+            .map(|(sig, hashes)| sig.heap_bytes() + hashes.capacity() * (size_of::<u64>() + 1))
+            .sum();
+        table_bytes(&self.routes)
+            + routes
+            + slots_bytes::<u64>(self.order.capacity())
+            + table_bytes(&self.misses)
+            + misses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memo_forgets_oldest_and_invalidates_misses() {
+        let mut memo = RouteMemo::new(2);
+        let route = |bit: bool, miss: bool| Route {
+            sig: vec![bit].into(),
+            miss,
+        };
+        memo.record(&[1.0], 0, route(true, true));
+        memo.record(&[2.0], 0, route(false, true));
+        memo.record(&[1.0], 0, route(true, false));
+        memo.record(&[3.0], 0, route(true, true));
+        assert_eq!(memo.get(&[1.0], 0), None);
+        assert_eq!(memo.get(&[2.0], 0), Some(route(false, true)));
+        // bit for bit, so that -0.0 is another query than 0.0
+        memo.record(&[0.0], 0, route(true, true));
+        assert_eq!(memo.get(&[-0.0], 0), None);
+        assert_eq!(memo.get(&[2.0], 0), None);
+
+        memo.invalidate_misses(&[true]);
+        assert_eq!(memo.get(&[0.0], 0), Some(route(true, false)));
+        assert_eq!(memo.get(&[3.0], 0), Some(route(true, false)));
+        assert_eq!(memo.hits(), 3);
+        // only the misses of memoized queries are indexed
+        assert!(memo.misses.is_empty());
+        memo.record(&[4.0], 0, route(false, true));
+        assert_eq!(memo.misses[&vec![false]].len(), 1);
+        memo.record(&[4.0], 0, route(false, false));
+        assert!(memo.misses.is_empty());
+        memo.clear();
+        assert_eq!(memo.get(&[2.0], 0), None);
+    }
+
+    #[test]
+    fn test_memo_tells_colliding_queries_apart() {
+        let mut memo = RouteMemo::new(2);
+        let route = Route {
+            sig: vec![true].into(),
+            miss: true,
+        };
+        memo.record(&[1.0], 0, route.clone());
+        // a query of the same hash but other bits takes the slot over
+        let hash = query_hash(&[1.0], 0);
+        memo.routes.get_mut(&hash).unwrap().bits = Box::new([2.0f32.to_bits()]);
+        assert_eq!(memo.get(&[1.0], 0), None);
+        assert_eq!(memo.get(&[2.0], 0), None);
+        memo.record(&[1.0], 0, route.clone());
+        assert_eq!(memo.get(&[1.0], 0), Some(route.clone()));
+        assert_eq!(memo.order.len(), 1);
+        // the same bits in another scope are another query
+        assert_eq!(memo.get(&[1.0], 1), None);
+        memo.record(&[1.0], 1, route.clone());
+        assert_eq!(memo.get(&[1.0], 1), Some(route));
+    }
+}
@@ -1,5 +1,19 @@
-mod hasher;
+mod diagnosis;
+pub(crate) mod hasher;
 mod lsh_cache;
+mod memo;
+mod probe;
+mod shard_router;
+pub use diagnosis::LshDiagnosis;
 pub use lsh_cache::LshCache;
+pub use lsh_cache::LshClockCache;
 pub use lsh_cache::LshFifoCache;
+pub use lsh_cache::LshGdsfCache;
+pub use lsh_cache::LshLfuCache;
 pub use lsh_cache::LshLruCache;
+pub use lsh_cache::LshLruKCache;
+pub use lsh_cache::LshS3FifoCache;
+pub use lsh_cache::LshWTinyLfuCache;
+pub use lsh_cache::Normalization;
+pub use probe::ProbeSequence;
+pub use shard_router::ShardRouter;
@@ -0,0 +1,174 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::{ProximityError, Result};
+
+/// Signatures of the buckets next to that of a key, most likely to hold its neighbors
+/// first, for multi-probe LSH (Lv et al., 2007): a lookup that misses in its own bucket
+/// tries these next.
+///
+/// A neighbor lands in another bucket when it falls on the other side of some of the
+/// hyperplanes, which is likelier the closer the key is to them. Flipping the bits of
+/// a set of hyperplanes is scored by the sum of the squared margins of the key to them,
+/// its dot products with their normals, and sets are enumerated by increasing score.
+/// With equal margins, that is by increasing Hamming distance. Every other signature
+/// comes once, lazily, so take as many as there is time to probe.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::ProbeSequence;
+///
+/// // the key is closest to the second hyperplane, then to the third
+/// let probes = ProbeSequence::new(&[true, false, true], &[0.9, -0.1, 0.3]).unwrap();
+/// let probes: Vec<Vec<bool>> = probes.take(3).collect();
+/// assert_eq!(probes[0], [true, true, true]);
+/// assert_eq!(probes[1], [true, false, false]);
+/// assert_eq!(probes[2], [true, true, false]);
+/// ```
+pub struct ProbeSequence {
+    signature: Vec<bool>,
+    /// Bits by increasing squared margin, along with it.
+    order: Vec<(usize, f32)>,
+    /// Sets of positions in `order` to flip, by increasing score.
+    heap: BinaryHeap<Flips>,
+}
+
+/// A set of positions in the order of a [`ProbeSequence`], in increasing order.
+struct Flips {
+    score: f32,
+    positions: Vec<usize>,
+}
+
+impl PartialEq for Flips {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Flips {}
+
+impl PartialOrd for Flips {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Flips {
+    /// Reversed, so that the heap pops the lowest score, then the fewest flips.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .score
+            .total_cmp(&self.score)
+            .then_with(|| other.positions.len().cmp(&self.positions.len()))
+            .then_with(|| other.positions.cmp(&self.positions))
+    }
+}
+
+impl ProbeSequence {
+    /// Probes around `signature`, given the margins of the key to every hyperplane,
+    /// whose signs do not matter.
+    pub fn new(signature: &[bool], margins: &[f32]) -> Result<Self> {
+        if signature.len() != margins.len() {
+            return Err(ProximityError::InvalidArgument(format!(
+                "got {} margins for a signature of {} bits",
+                margins.len(),
+                signature.len()
+            )));
+        }
+        let mut order: Vec<(usize, f32)> = margins
+            .iter()
+            .map(|margin| margin * margin)
+            .enumerate()
+            .collect();
+        order.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        let mut heap = BinaryHeap::new();
+        if let Some(&(_, score)) = order.first() {
+            heap.push(Flips {
+                score,
+                positions: vec![0],
+            });
+        }
+        Ok(Self {
+            signature: signature.to_vec(),
+            order,
+            heap,
+        })
+    }
+}
+
+impl Iterator for ProbeSequence {
+    type Item = Vec<bool>;
+
+    fn next(&mut self) -> Option<Vec<bool>> {
+        let flips = self.heap.pop()?;
+        let last = *flips.positions.last().unwrap();
+        if let Some(&(_, next)) = self.order.get(last + 1) {
+            // every set is reached once: by shifting its last position from the one
+            // before, or by expanding the set without it
+            let (_, current) = self.order[last];
+            let mut shifted = flips.positions.clone();
+            *shifted.last_mut().unwrap() = last + 1;
+            self.heap.push(Flips {
+                score: flips.score - current + next,
+                positions: shifted,
+            });
+            let mut expanded = flips.positions.clone();
+            expanded.push(last + 1);
+            self.heap.push(Flips {
+                score: flips.score + next,
+                positions: expanded,
+            });
+        }
+        let mut signature = self.signature.clone();
+        for &position in &flips.positions {
+            let (bit, _) = self.order[position];
+            signature[bit] = !signature[bit];
+        }
+        Some(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_every_signature_once_by_score() {
+        let signature = [true, false, false, true, true];
+        let margins = [0.5, -0.2, 1.5, 0.05, -0.8];
+        let probes: Vec<Vec<bool>> = ProbeSequence::new(&signature, &margins).unwrap().collect();
+        assert_eq!(probes.len(), 31);
+        let distinct: HashSet<&Vec<bool>> = probes.iter().collect();
+        assert_eq!(distinct.len(), 31);
+        assert!(!distinct.contains(&signature.to_vec()));
+
+        let score = |probe: &Vec<bool>| -> f32 {
+            (0..5)
+                .filter(|&i| probe[i] != signature[i])
+                .map(|i| margins[i] * margins[i])
+                .sum()
+        };
+        assert!(probes
+            .windows(2)
+            .all(|pair| score(&pair[0]) <= score(&pair[1]) + 1e-6));
+    }
+
+    #[test]
+    fn test_equal_margins_follow_hamming_distance() {
+        let signature = [false; 6];
+        let probes = ProbeSequence::new(&signature, &[1.0; 6]).unwrap();
+        let distances: Vec<usize> = probes
+            .map(|probe| probe.iter().filter(|&&bit| bit).count())
+            .collect();
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(distances.iter().filter(|&&d| d == 1).count(), 6);
+    }
+
+    #[test]
+    fn test_invalid_or_empty_signatures() {
+        assert!(ProbeSequence::new(&[true, false], &[1.0]).is_err());
+        assert_eq!(ProbeSequence::new(&[], &[]).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{ProximityError, Result};
+
+/// Maps LSH signatures to `num_shards` shards, e.g. the nodes of a distributed cache,
+/// by jump consistent hashing (Lamping and Veach, 2014).
+///
+/// Every router over as many shards maps a signature to the same shard, in any process
+/// and on any platform, so nodes agree on where keys live without coordinating. Going
+/// from `n` to `m` shards only moves the signatures that have to move, a share of
+/// `|m - n| / max(m, n)` of them, from the removed shards or to the added ones.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::ShardRouter;
+///
+/// let (router, grown) = (ShardRouter::new(4).unwrap(), ShardRouter::new(5).unwrap());
+/// let signature = [true, false, true, true];
+/// let shard = router.shard(&signature);
+/// assert!(shard < 4);
+/// // a signature either stays or moves to the new shard
+/// assert!(matches!(router.moved_to(&grown, &signature), None | Some(4)));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShardRouter {
+    num_shards: u32,
+}
+
+impl ShardRouter {
+    pub fn new(num_shards: usize) -> Result<Self> {
+        match u32::try_from(num_shards) {
+            Ok(num_shards) if num_shards > 0 => Ok(ShardRouter { num_shards }),
+            _ => Err(ProximityError::InvalidArgument(format!(
+                "number of shards must be between 1 and {}, got {num_shards}",
+                u32::MAX
+            ))),
+        }
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.num_shards as usize
+    }
+
+    /// Shard of `signature`, below [`num_shards`](Self::num_shards).
+    pub fn shard(&self, signature: &[bool]) -> usize {
+        jump_hash(signature_hash(signature), self.num_shards) as usize
+    }
+
+    /// Shard of `signature` under `resharded` if it differs from this router's, to tell
+    /// which entries to hand over when the number of shards changes.
+    pub fn moved_to(&self, resharded: &ShardRouter, signature: &[bool]) -> Option<usize> {
+        let (from, to) = (self.shard(signature), resharded.shard(signature));
+        (from != to).then_some(to)
+    }
+}
+
+/// Stable hash of a signature: its bits packed 64 at a time, mixed with the splitmix64
+/// finalizer, so that it never depends on the standard library's hasher.
+fn signature_hash(signature: &[bool]) -> u64 {
+    let mut hash = mix(signature.len() as u64);
+    for chunk in signature.chunks(64) {
+        let word = chunk
+            .iter()
+            .enumerate()
+            .fold(0u64, |word, (i, &bit)| word | (u64::from(bit) << i));
+        hash = mix(hash ^ word);
+    }
+    hash
+}
+
+fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Bucket of `key` among `num_buckets`, which only changes to one of the added buckets
+/// when there are more of them.
+fn jump_hash(mut key: u64, num_buckets: u32) -> u32 {
+    let (mut bucket, mut next) = (-1i64, 0i64);
+    while next < i64::from(num_buckets) {
+        bucket = next;
+        key = key.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    bucket as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signatures() -> impl Iterator<Item = Vec<bool>> {
+        (0u32..4096).map(|i| (0..12).map(|bit| i >> bit & 1 == 1).collect())
+    }
+
+    #[test]
+    fn test_shards_are_balanced() {
+        let router = ShardRouter::new(8).unwrap();
+        let mut counts = [0; 8];
+        for signature in signatures() {
+            counts[router.shard(&signature)] += 1;
+        }
+        // 512 per shard on average
+        assert!(
+            counts.iter().all(|&n| (400..624).contains(&n)),
+            "{counts:?}"
+        );
+        assert!(ShardRouter::new(0).is_err());
+    }
+
+    #[test]
+    fn test_resharding_moves_few_signatures() {
+        let (router, grown) = (ShardRouter::new(8).unwrap(), ShardRouter::new(10).unwrap());
+        let mut moved = 0;
+        for signature in signatures() {
+            if let Some(to) = router.moved_to(&grown, &signature) {
+                assert!(to >= 8);
+                moved += 1;
+            }
+            // shrinking back moves the same signatures home
+            assert_eq!(
+                grown.moved_to(&router, &signature).is_some(),
+                router.moved_to(&grown, &signature).is_some()
+            );
+        }
+        // a fifth of 4096 is expected to move
+        assert!((650..1000).contains(&moved), "{moved}");
+    }
+
+    #[test]
+    fn test_signature_hash_is_stable() {
+        // pinned, since nodes of a distributed cache must agree on it
+        assert_eq!(ShardRouter::new(1000).unwrap().shard(&[true; 100]), 805);
+        assert_ne!(signature_hash(&[false]), signature_hash(&[false, false]));
+    }
+}
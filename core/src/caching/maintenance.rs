@@ -0,0 +1,123 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::numerics::ApproxComparable;
+
+/// Background thread running a maintenance task at a fixed interval, typically
+/// [`ApproximateCache::maintain`] on a shared cache, so that lookups never pay for it.
+///
+/// The thread stops when the handle is stopped or dropped.
+///
+/// # Example Usage
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+/// use proximity::caching::{ApproximateCache, FifoCache, MaintenanceThread};
+///
+/// let cache = Arc::new(Mutex::new(FifoCache::<i16, i16>::new(16).unwrap()));
+/// let maintenance = MaintenanceThread::for_cache(Arc::clone(&cache), Duration::from_secs(1));
+///
+/// cache.lock().unwrap().insert(1, 1, 0.5);
+/// maintenance.stop();
+/// ```
+pub struct MaintenanceThread {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MaintenanceThread {
+    /// Runs `task` every `interval`, the first time after one interval has passed.
+    pub fn spawn<F>(interval: Duration, mut task: F) -> Self
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                task();
+            }
+        });
+        Self {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// Maintains `cache` every `interval`, locking it only for the duration of
+    /// [`maintain`](ApproximateCache::maintain).
+    pub fn for_cache<K, V, C>(cache: Arc<Mutex<C>>, interval: Duration) -> Self
+    where
+        K: ApproxComparable,
+        C: ApproximateCache<K, V> + Send + 'static,
+    {
+        Self::spawn(interval, move || {
+            cache
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .maintain()
+        })
+    }
+
+    /// Stops the thread and waits for a running task to finish.
+    pub fn stop(self) {}
+}
+
+impl Drop for MaintenanceThread {
+    fn drop(&mut self) {
+        // dropping the sender wakes the thread up
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    use super::*;
+    use crate::caching::sketch::{AdmissionFilter, CountMinSketch};
+    use crate::caching::FifoCache;
+
+    #[test]
+    fn test_maintenance_runs_until_stopped() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&runs);
+        let maintenance = MaintenanceThread::spawn(Duration::from_millis(1), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while runs.load(Ordering::SeqCst) < 3 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        maintenance.stop();
+        let after_stop = runs.load(Ordering::SeqCst);
+        assert!(after_stop >= 3);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(runs.load(Ordering::SeqCst), after_stop);
+    }
+
+    #[test]
+    fn test_maintenance_ages_sketch() {
+        let sketch = CountMinSketch::with_sample_size(64, 2, 4).defer_aging();
+        let inner: FifoCache<i16, i16> = FifoCache::new(4).unwrap();
+        let cache = Arc::new(Mutex::new(AdmissionFilter::new(inner, sketch)));
+        for _ in 0..4 {
+            cache.lock().unwrap().find(&1);
+        }
+        assert_eq!(cache.lock().unwrap().sketch().estimate(&1i16), 4);
+        let maintenance =
+            MaintenanceThread::for_cache(Arc::clone(&cache), Duration::from_millis(1));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while cache.lock().unwrap().sketch().estimate(&1i16) == 4 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(maintenance);
+        assert_eq!(cache.lock().unwrap().sketch().estimate(&1i16), 2);
+    }
+}
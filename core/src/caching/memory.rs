@@ -0,0 +1,157 @@
+use std::collections::{HashMap, VecDeque};
+use std::mem::{size_of, size_of_val};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::caching::approximate_cache::Tolerance;
+
+/// Estimate of the heap memory owned by a value, on top of its own `size_of`, used by
+/// [`ApproximateCache::memory_bytes`](crate::caching::ApproximateCache::memory_bytes)
+/// and by the budget of a [`ByteBoundedCache`](crate::caching::ByteBoundedCache).
+///
+/// Implement it for custom keys or values to make them count. Values behind a shared
+/// pointer are counted in full by each owner, so shared data is counted more than once.
+pub trait HeapSize {
+    fn heap_bytes(&self) -> usize;
+}
+
+macro_rules! no_heap {
+    ($($t:ty),*) => {
+        $(impl HeapSize for $t {
+            fn heap_bytes(&self) -> usize {
+                0
+            }
+        })*
+    };
+}
+
+no_heap!(
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    ()
+);
+
+/// Borrowed data is owned elsewhere.
+impl<T: ?Sized> HeapSize for &T {
+    fn heap_bytes(&self) -> usize {
+        0
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_bytes(&self) -> usize {
+        self.iter().map(HeapSize::heap_bytes).sum()
+    }
+}
+
+impl HeapSize for String {
+    fn heap_bytes(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for str {
+    fn heap_bytes(&self) -> usize {
+        0
+    }
+}
+
+impl<T: HeapSize> HeapSize for [T] {
+    fn heap_bytes(&self) -> usize {
+        self.iter().map(HeapSize::heap_bytes).sum()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_bytes(&self) -> usize {
+        slots_bytes::<T>(self.capacity()) + self.as_slice().heap_bytes()
+    }
+}
+
+impl<T: HeapSize> HeapSize for VecDeque<T> {
+    fn heap_bytes(&self) -> usize {
+        slots_bytes::<T>(self.capacity()) + self.iter().map(HeapSize::heap_bytes).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_bytes(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_bytes)
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes() + self.1.heap_bytes()
+    }
+}
+
+impl<T: HeapSize + ?Sized> HeapSize for Box<T> {
+    fn heap_bytes(&self) -> usize {
+        size_of_val(&**self) + (**self).heap_bytes()
+    }
+}
+
+impl<T: HeapSize + ?Sized> HeapSize for Rc<T> {
+    fn heap_bytes(&self) -> usize {
+        2 * size_of::<usize>() + size_of_val(&**self) + (**self).heap_bytes()
+    }
+}
+
+impl<T: HeapSize + ?Sized> HeapSize for Arc<T> {
+    fn heap_bytes(&self) -> usize {
+        2 * size_of::<usize>() + size_of_val(&**self) + (**self).heap_bytes()
+    }
+}
+
+/// Bytes of a stored entry in a slot of its own, as counted by the default
+/// [`memory_bytes`](crate::caching::ApproximateCache::memory_bytes).
+pub(crate) fn entry_bytes<K: HeapSize, V: HeapSize>(key: &K, value: &V) -> usize {
+    size_of::<(K, V, Tolerance)>() + key.heap_bytes() + value.heap_bytes()
+}
+
+/// Bytes of a buffer of `capacity` slots of `T`, e.g. a `Vec` or a `VecDeque`.
+pub(crate) fn slots_bytes<T>(capacity: usize) -> usize {
+    capacity * size_of::<T>()
+}
+
+/// Bytes of the table of a hash map, one control byte per slot besides the entry.
+/// The entries' own heap memory is not included.
+pub(crate) fn table_bytes<K, V, S>(map: &HashMap<K, V, S>) -> usize {
+    map.capacity() * (size_of::<(K, V)>() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heap_bytes_of_containers() {
+        assert_eq!(7u64.heap_bytes(), 0);
+        assert_eq!("static".heap_bytes(), 0);
+
+        let v: Vec<f32> = Vec::with_capacity(8);
+        assert_eq!(v.heap_bytes(), 32);
+
+        let nested = vec![String::with_capacity(10), String::with_capacity(6)];
+        assert_eq!(nested.heap_bytes(), 2 * size_of::<String>() + 16);
+
+        let boxed: Box<[u8]> = vec![0; 5].into_boxed_slice();
+        assert_eq!(boxed.heap_bytes(), 5);
+        assert_eq!(Some(String::with_capacity(3)).heap_bytes(), 3);
+    }
+}
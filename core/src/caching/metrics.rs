@@ -0,0 +1,193 @@
+use metrics::{
+    counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Counter,
+    Gauge, Histogram, SharedString, Unit,
+};
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::clock::Instant;
+use crate::numerics::ApproxComparable;
+
+pub const HITS: &str = "proximity_cache_hits_total";
+pub const MISSES: &str = "proximity_cache_misses_total";
+pub const INSERTS: &str = "proximity_cache_inserts_total";
+pub const EVICTIONS: &str = "proximity_cache_evictions_total";
+pub const SCAN_LENGTH: &str = "proximity_cache_scan_length";
+pub const LOOKUP_SECONDS: &str = "proximity_cache_lookup_seconds";
+pub const ENTRIES: &str = "proximity_cache_entries";
+
+/// Registers units and help texts of the metrics emitted by [`MetricsCache`] with the
+/// installed recorder. Call it once, after installing the recorder.
+pub fn describe_metrics() {
+    describe_counter!(HITS, Unit::Count, "Lookups that found a matching entry");
+    describe_counter!(MISSES, Unit::Count, "Lookups that found no matching entry");
+    describe_counter!(INSERTS, Unit::Count, "Inserted entries");
+    describe_counter!(
+        EVICTIONS,
+        Unit::Count,
+        "Entries evicted or refused to make room for an insert"
+    );
+    describe_histogram!(
+        SCAN_LENGTH,
+        Unit::Count,
+        "Stored entries compared against per lookup"
+    );
+    describe_histogram!(LOOKUP_SECONDS, Unit::Seconds, "Lookup latency");
+    describe_gauge!(ENTRIES, Unit::Count, "Stored entries");
+}
+
+/// Wraps a cache to report its activity through the [`metrics`] facade, to whichever
+/// recorder (e.g. a Prometheus exporter) the application installed.
+///
+/// Every metric carries a `cache` label with the name given to [`new`](Self::new),
+/// so that several caches can report to the same recorder.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, LruCache, MetricsCache};
+///
+/// let mut cache = MetricsCache::new(LruCache::new(16).unwrap(), "embeddings");
+/// cache.insert(10 as i16, "Value 1", 2.0);
+/// assert_eq!(cache.find(&11), Some("Value 1"));
+/// ```
+pub struct MetricsCache<C> {
+    inner: C,
+    hits: Counter,
+    misses: Counter,
+    inserts: Counter,
+    evictions: Counter,
+    scan_length: Histogram,
+    lookup_seconds: Histogram,
+    entries: Gauge,
+}
+
+impl<C> MetricsCache<C> {
+    pub fn new(inner: C, name: impl Into<SharedString>) -> Self {
+        let name: SharedString = name.into();
+        Self {
+            inner,
+            hits: counter!(HITS, "cache" => name.clone()),
+            misses: counter!(MISSES, "cache" => name.clone()),
+            inserts: counter!(INSERTS, "cache" => name.clone()),
+            evictions: counter!(EVICTIONS, "cache" => name.clone()),
+            scan_length: histogram!(SCAN_LENGTH, "cache" => name.clone()),
+            lookup_seconds: histogram!(LOOKUP_SECONDS, "cache" => name.clone()),
+            entries: gauge!(ENTRIES, "cache" => name),
+        }
+    }
+
+    fn record_lookup(&self, hit: bool, scanned: usize, started: Instant) {
+        self.lookup_seconds.record(started.elapsed());
+        self.scan_length.record(scanned as f64);
+        if hit {
+            self.hits.increment(1);
+        } else {
+            self.misses.increment(1);
+        }
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for MetricsCache<C>
+where
+    K: ApproxComparable,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        default_tolerance, len, candidates, key_dim, nearest, next_victim, pin, unpin, entry_info,
+        entry_infos, iter, maintain, compact, recent_hit_rate, memory_bytes,
+    );
+
+    fn find(&mut self, target: &K) -> Option<V> {
+        let started = Instant::now();
+        let scanned = self.inner.candidates(target);
+        let found = self.inner.find(target);
+        self.record_lookup(found.is_some(), scanned, started);
+        found
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        let started = Instant::now();
+        let scanned = self.inner.candidates(target);
+        let found = self.inner.find_k(target, k);
+        self.record_lookup(!found.is_empty(), scanned, started);
+        found
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        let evicted = self
+            .inner
+            .insert_with_priority(key, value, tolerance, priority);
+        self.inserts.increment(1);
+        self.evictions.increment(evicted.len() as u64);
+        self.entries.set(self.inner.len() as f64);
+        evicted
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        self.entries.set(0.0);
+        self.inner.drain()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    use super::*;
+    use crate::caching::FifoCache;
+
+    type Recorded = Vec<(MetricKind, String, DebugValue)>;
+
+    fn value<'a>(recorded: &'a Recorded, kind: MetricKind, name: &str) -> &'a DebugValue {
+        recorded
+            .iter()
+            .find(|(k, n, _)| *k == kind && n == name)
+            .map(|(_, _, value)| value)
+            .unwrap_or_else(|| panic!("{name} was not recorded"))
+    }
+
+    #[test]
+    fn test_metrics_recorded() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            describe_metrics();
+            let mut cache = MetricsCache::new(FifoCache::new(1).unwrap(), "test");
+            cache.insert(1_i16, 1, 0.5);
+            cache.insert(5, 5, 0.5);
+            cache.find(&5);
+            cache.find(&1);
+            cache.find(&9);
+        });
+        // snapshots drain histograms, so take a single one
+        let recorded: Recorded = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key.kind(), key.key().name().to_string(), value))
+            .collect();
+        assert_eq!(
+            value(&recorded, MetricKind::Counter, HITS),
+            &DebugValue::Counter(1)
+        );
+        assert_eq!(
+            value(&recorded, MetricKind::Counter, MISSES),
+            &DebugValue::Counter(2)
+        );
+        assert_eq!(
+            value(&recorded, MetricKind::Counter, EVICTIONS),
+            &DebugValue::Counter(1)
+        );
+        assert!(matches!(
+            value(&recorded, MetricKind::Histogram, SCAN_LENGTH),
+            DebugValue::Histogram(lengths) if lengths.len() == 3
+        ));
+    }
+}
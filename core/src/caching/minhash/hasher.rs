@@ -0,0 +1,110 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[cfg(not(feature = "thread-rng"))]
+use crate::caching::lsh::hasher::FALLBACK_SEED;
+use crate::caching::HeapSize;
+
+/// The Mersenne prime 2^61 - 1, modulus of the hash functions.
+const PRIME: u64 = (1 << 61) - 1;
+
+/// MinHash signatures of sets of ids: for each of `num_hash` random hash functions, the
+/// smallest hash of an id of the set.
+///
+/// Two sets agree on each component of their signatures with a probability equal to
+/// their Jaccard similarity, so the signature routes similar sets alike, as the
+/// hyperplanes of an [`LshCache`](crate::caching::LshCache) do for vectors.
+pub struct MinHasher {
+    /// Coefficients `(a, b)` of each hash function `x -> (a * x + b) mod PRIME`.
+    coefficients: Vec<(u64, u64)>,
+}
+
+impl MinHasher {
+    /// Draws `num_hash` hash functions, from `seed` if given, which is deterministic.
+    ///
+    /// Without a seed nor the `thread-rng` feature, the functions are drawn from a fixed
+    /// fallback seed.
+    pub fn new(num_hash: usize, seed: Option<u64>) -> Self {
+        match seed {
+            Some(seed) => Self::with_rng(num_hash, &mut StdRng::seed_from_u64(seed)),
+            #[cfg(feature = "thread-rng")]
+            None => Self::with_rng(num_hash, &mut rand::rng()),
+            #[cfg(not(feature = "thread-rng"))]
+            None => Self::with_rng(num_hash, &mut StdRng::seed_from_u64(FALLBACK_SEED)),
+        }
+    }
+
+    fn with_rng<R: Rng>(num_hash: usize, rng: &mut R) -> Self {
+        let coefficients = (0..num_hash)
+            .map(|_| (rng.random_range(1..PRIME), rng.random_range(0..PRIME)))
+            .collect();
+        Self { coefficients }
+    }
+
+    pub fn num_hash(&self) -> usize {
+        self.coefficients.len()
+    }
+
+    /// Signature of the set `ids`, one minimum per hash function. Every component of
+    /// the signature of the empty set is `u64::MAX`, which no id hashes to.
+    pub fn signature(&self, ids: &[u32]) -> Vec<u64> {
+        self.coefficients
+            .iter()
+            .map(|&(a, b)| {
+                ids.iter()
+                    .map(|&id| {
+                        let hash =
+                            (u128::from(a) * u128::from(id) + u128::from(b)) % u128::from(PRIME);
+                        hash as u64
+                    })
+                    .min()
+                    .unwrap_or(u64::MAX)
+            })
+            .collect()
+    }
+
+    /// Estimated Jaccard similarity of the sets of two signatures of this hasher: the
+    /// fraction of components they agree on.
+    pub fn similarity(a: &[u64], b: &[u64]) -> f32 {
+        if a.is_empty() {
+            return 0.0;
+        }
+        let agree = a.iter().zip(b).filter(|(x, y)| x == y).count();
+        agree as f32 / a.len() as f32
+    }
+}
+
+impl HeapSize for MinHasher {
+    fn heap_bytes(&self) -> usize {
+        self.coefficients.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_estimates_similarity() {
+        let hasher = MinHasher::new(256, Some(42));
+        let a: Vec<u32> = (0..100).collect();
+        let b: Vec<u32> = (50..150).collect(); // Jaccard similarity of 1/3
+        let estimate = MinHasher::similarity(&hasher.signature(&a), &hasher.signature(&b));
+        assert!((estimate - 1.0 / 3.0).abs() < 0.1, "{estimate}");
+        assert_eq!(hasher.signature(&a), hasher.signature(&a));
+        assert_eq!(hasher.signature(&[]), vec![u64::MAX; 256]);
+    }
+
+    #[test]
+    fn test_seeded_hashers_agree() {
+        let ids = [3, 14, 15, 92];
+        assert_eq!(
+            MinHasher::new(8, Some(7)).signature(&ids),
+            MinHasher::new(8, Some(7)).signature(&ids)
+        );
+        assert_ne!(
+            MinHasher::new(8, Some(7)).signature(&ids),
+            MinHasher::new(8, Some(8)).signature(&ids)
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::table_bytes;
+use crate::caching::minhash::MinHasher;
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// A key-value store for set-valued keys, e.g. [`TokenSet`](crate::numerics::TokenSet),
+/// that uses MinHash to direct queries into fixed-size cache buckets.
+///
+/// A key lands in the bucket of its whole signature, so two keys of Jaccard similarity
+/// `s` share a bucket with a probability of `s ^ num_hash`: a few hash functions route
+/// near-duplicates together, more of them make smaller buckets that only hold closer
+/// keys.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, FifoCache, MinHashCache};
+/// use proximity::numerics::TokenSet;
+///
+/// let mut cache: MinHashCache<FifoCache<TokenSet, &str>> =
+///     MinHashCache::new(2, 16, Some(42)).unwrap();
+/// cache.insert(TokenSet::from(vec![1, 2, 3, 4]), "Value 1", 0.5);
+///
+/// assert_eq!(cache.find(&TokenSet::from(vec![1, 2, 3, 4])), Some("Value 1"));
+/// assert_eq!(cache.find(&TokenSet::from(vec![7, 8, 9])), None);
+/// ```
+pub struct MinHashCache<C> {
+    hasher: MinHasher,
+    buckets: HashMap<Vec<u64>, C>,
+    bucket_capacity: usize,
+    hit_rate: HitRateTracker,
+}
+
+impl<C> MinHashCache<C> {
+    pub fn new(num_hash: usize, bucket_capacity: usize, seed: Option<u64>) -> Result<Self> {
+        if num_hash == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "number of hash functions must be positive".into(),
+            ));
+        }
+        if bucket_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "bucket capacity must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            hasher: MinHasher::new(num_hash, seed),
+            buckets: HashMap::new(),
+            bucket_capacity,
+            hit_rate: HitRateTracker::default(),
+        })
+    }
+
+    /// The hash functions that route keys into buckets.
+    pub fn hasher(&self) -> &MinHasher {
+        &self.hasher
+    }
+
+    pub fn bucket_capacity(&self) -> usize {
+        self.bucket_capacity
+    }
+
+    /// Changes the capacity of every bucket, see
+    /// [`FifoCache::set_capacity`](crate::caching::FifoCache::set_capacity), and returns
+    /// the evicted entries.
+    pub fn set_bucket_capacity<K, V>(
+        &mut self,
+        bucket_capacity: usize,
+    ) -> Result<Vec<(K, V, Tolerance)>>
+    where
+        K: ApproxComparable,
+        C: DefaultApproximateCache<K, V>,
+    {
+        if bucket_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "bucket capacity must be positive".into(),
+            ));
+        }
+        self.bucket_capacity = bucket_capacity;
+        let mut evicted = Vec::new();
+        for bucket in self.buckets.values_mut() {
+            evicted.extend(bucket.set_capacity(bucket_capacity)?);
+        }
+        Ok(evicted)
+    }
+
+    /// The bucket `ids` lands in, or None if no entry was ever inserted into it, or if
+    /// it was emptied before a [`compact`](ApproximateCache::compact).
+    pub fn bucket(&self, ids: &[u32]) -> Option<&C> {
+        self.buckets.get(&self.signature(ids))
+    }
+
+    /// MinHash signature of `ids`, which picks the bucket it lands in.
+    pub fn signature(&self, ids: &[u32]) -> Vec<u64> {
+        self.hasher.signature(ids)
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for MinHashCache<C>
+where
+    V: Clone + 'static,
+    K: ApproxComparable + AsRef<[u32]> + 'static,
+    C: DefaultApproximateCache<K, V>,
+{
+    fn find(&mut self, target: &K) -> Option<V> {
+        trace_span!("find", cache = "minhash");
+        let sig = self.signature(target.as_ref());
+        let found = self
+            .buckets
+            .get_mut(&sig)
+            .and_then(|bucket| bucket.find(target));
+        self.hit_rate.record(found.is_some());
+        found
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "minhash", k);
+        let sig = self.signature(target.as_ref());
+        let found = self
+            .buckets
+            .get_mut(&sig)
+            .map(|bucket| bucket.find_k(target, k))
+            .unwrap_or_default();
+        self.hit_rate.record(!found.is_empty());
+        found
+    }
+
+    /// Priorities only weigh against the entries of the same bucket.
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tol: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "minhash", tolerance = tol, priority);
+        let sig = self.signature(key.as_ref());
+        self.buckets
+            .entry(sig)
+            .or_insert_with(|| C::from_capacity(self.bucket_capacity))
+            .insert_with_priority(key, value, tol, priority)
+    }
+
+    /// Only the bucket that `target` hashes to is scanned.
+    fn candidates(&self, target: &K) -> usize {
+        let sig = self.signature(target.as_ref());
+        self.buckets.get(&sig).map_or(0, |bucket| bucket.len())
+    }
+
+    fn len(&self) -> usize {
+        self.buckets.values().map(|b| b.len()).sum()
+    }
+
+    /// Only the bucket that `target` hashes to is considered.
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        let sig = self.signature(target.as_ref());
+        self.buckets.get(&sig)?.nearest(target)
+    }
+
+    /// Sets of any size share the cache.
+    fn key_dim(&self) -> Option<usize> {
+        None
+    }
+
+    fn next_victim(&self, incoming: &K) -> Option<&K> {
+        let sig = self.signature(incoming.as_ref());
+        self.buckets.get(&sig)?.next_victim(incoming)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        let sig = self.signature(target.as_ref());
+        self.buckets
+            .get_mut(&sig)
+            .is_some_and(|bucket| bucket.pin(target))
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        let sig = self.signature(target.as_ref());
+        self.buckets
+            .get_mut(&sig)
+            .is_some_and(|bucket| bucket.unpin(target))
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let sig = self.signature(target.as_ref());
+        self.buckets.get(&sig)?.entry_info(target)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(
+            self.buckets
+                .values()
+                .flat_map(|bucket| bucket.entry_infos()),
+        )
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(self.buckets.values().flat_map(|bucket| bucket.iter()))
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        let drained: Vec<(K, V, Tolerance)> = self
+            .buckets
+            .drain()
+            .flat_map(|(_, mut bucket)| bucket.drain().collect::<Vec<_>>())
+            .collect();
+        Box::new(drained.into_iter())
+    }
+
+    fn maintain(&mut self) {
+        self.buckets
+            .values_mut()
+            .for_each(|bucket| bucket.maintain());
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)>
+    where
+        K: Clone,
+    {
+        self.buckets
+            .values_mut()
+            .flat_map(|bucket| bucket.retain(&mut *keep))
+            .collect()
+    }
+
+    fn compact(&mut self) {
+        // buckets are created on their first insert and kept when emptied
+        self.buckets.retain(|_, bucket| !bucket.is_empty());
+        self.buckets
+            .values_mut()
+            .for_each(|bucket| bucket.compact());
+        self.buckets.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let buckets: usize = self
+            .buckets
+            .iter()
+            .map(|(sig, bucket)| sig.heap_bytes() + bucket.memory_bytes())
+            .sum();
+        self.hasher.heap_bytes() + table_bytes(&self.buckets) + buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::LruCache;
+    use crate::numerics::TokenSet;
+
+    const TOL: f32 = 0.3;
+
+    type Cache = MinHashCache<LruCache<TokenSet, i32>>;
+
+    fn ids(range: std::ops::Range<u32>) -> TokenSet {
+        range.collect()
+    }
+
+    #[test]
+    fn test_near_duplicate_sets_share_a_bucket() {
+        let mut cache = Cache::new(1, 8, Some(42)).unwrap();
+        let stored = ids(0..20);
+        cache.insert(stored.clone(), 1, TOL);
+        // with a single hash function, a set of similarity 0.9 shares the bucket with a
+        // probability of 0.9; the seed is fixed
+        let near = ids(0..19);
+        assert!(stored.fuzziness(&near) < TOL);
+        assert_eq!(cache.signature(near.ids()), cache.signature(stored.ids()));
+        assert_eq!(cache.find(&near), Some(1));
+        assert_eq!(cache.candidates(&near), 1);
+        assert_eq!(cache.find(&ids(100..120)), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_bucket_eviction() {
+        let mut cache = Cache::new(1, 1, Some(42)).unwrap();
+        cache.insert(ids(0..10), 1, TOL);
+        // another key of the same bucket
+        let other = ids(0..11);
+        assert_eq!(
+            cache.signature(other.ids()),
+            cache.signature(ids(0..10).ids())
+        );
+        assert_eq!(cache.next_victim(&other), Some(&ids(0..10)));
+        let evicted = cache.insert_evicting(other.clone(), 2, TOL);
+        assert_eq!(evicted, vec![(ids(0..10), 1, TOL)]);
+        assert_eq!(cache.find(&other), Some(2));
+
+        let evicted = cache.set_bucket_capacity::<TokenSet, i32>(1).unwrap();
+        assert!(evicted.is_empty());
+        let drained: Vec<i32> = cache.drain().map(|(_, v, _)| v).collect();
+        assert_eq!(drained, vec![2]);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(Cache::new(0, 8, None).is_err());
+        assert!(Cache::new(4, 0, None).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+mod hasher;
+mod minhash_cache;
+pub use hasher::MinHasher;
+pub use minhash_cache::MinHashCache;
@@ -1,13 +1,135 @@
 #![allow(unused_imports)]
 
+#[macro_use]
+mod forward;
+#[macro_use]
+mod trace;
+
+mod aggregate;
 mod approximate_cache;
+mod backing;
+mod batch_evicting;
+mod builder;
+mod byte_bounded;
+mod calibration;
+mod centroid;
+mod checkpoint;
+mod clock;
+mod cluster;
+#[cfg(feature = "tokio")]
+mod coalescing;
+#[cfg(feature = "config")]
+mod config;
+mod counter;
+mod dedup;
+mod default_tolerance;
+mod delta;
+mod entry_info;
 mod fifo;
+mod finite;
+mod gdsf;
+pub mod ghost;
+mod intern;
+mod lfu;
+mod linear;
 mod lru;
+mod lru_k;
 mod lsh;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+mod maintenance;
+mod memory;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod minhash;
+mod namespaced;
+mod negative;
+mod npz;
+mod priority;
+pub mod profiler;
+mod reduced;
+mod s3fifo;
+mod scan;
+mod scan_resistant;
+mod scan_tracked;
+mod set;
+mod shadow;
+mod sharded;
+#[cfg(all(unix, feature = "shm"))]
+mod shared;
+pub mod sketch;
+mod slots;
+mod snapshot;
+mod soft;
+mod stats;
+mod tinylfu;
+mod versioned;
+mod wal;
+mod warmup;
 
+pub use aggregate::{Reducer, Weighting};
 pub use approximate_cache::ApproximateCache;
+pub use backing::{BackedCache, BackingStore};
+pub use batch_evicting::BatchEvictingCache;
+pub use builder::{CacheBuilder, EvictionPolicy};
+pub use byte_bounded::ByteBoundedCache;
+pub use calibration::{CalibratedCache, Calibration};
+pub use centroid::CentroidCache;
+pub use checkpoint::Checkpointed;
+pub use clock::ClockCache;
+pub use cluster::ClusterCache;
+#[cfg(feature = "tokio")]
+pub use coalescing::{Aged, AsyncCache};
+#[cfg(feature = "config")]
+pub use config::{CacheConfig, Metric};
+pub use counter::{ApproxCounter, Decay, SharedCount};
+pub use dedup::Deduplicator;
+pub use default_tolerance::DefaultTolerance;
+pub use delta::DeltaSync;
+pub use entry_info::EntryInfo;
 pub use fifo::FifoCache;
+pub use finite::{FiniteKeys, NonFinitePolicy};
+pub use gdsf::GdsfCache;
+pub use intern::{InternedKeys, InternedVec, KeyInterner};
+pub use lfu::LfuCache;
+pub use linear::UnboundedLinearCache;
 pub use lru::LruCache;
+pub use lru_k::LruKCache;
 pub use lsh::LshCache;
+pub use lsh::LshClockCache;
+pub use lsh::LshDiagnosis;
 pub use lsh::LshFifoCache;
+pub use lsh::LshGdsfCache;
+pub use lsh::LshLfuCache;
 pub use lsh::LshLruCache;
+pub use lsh::LshLruKCache;
+pub use lsh::LshS3FifoCache;
+pub use lsh::LshWTinyLfuCache;
+pub use lsh::Normalization;
+pub use lsh::ProbeSequence;
+pub use lsh::ShardRouter;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub use maintenance::MaintenanceThread;
+pub use memory::HeapSize;
+#[cfg(feature = "metrics")]
+pub use metrics::MetricsCache;
+pub use minhash::{MinHashCache, MinHasher};
+pub use namespaced::{NamespaceStats, NamespacedCache};
+pub use negative::{Lookup, NegativeCache};
+pub use npz::NpzPersistence;
+pub use reduced::ReducedKeys;
+pub use s3fifo::S3FifoCache;
+pub use scan::MaybeSync;
+pub use scan_resistant::ScanResistantCache;
+pub use scan_tracked::ScanTrackedCache;
+pub use set::ApproxSet;
+pub use shadow::{Disagreement, ShadowComparator, ShadowReport};
+pub use sharded::ShardedCache;
+#[cfg(all(unix, feature = "shm"))]
+pub use shared::SharedLshCache;
+pub use snapshot::CacheView;
+pub use soft::{SoftLookup, SoftValueCache};
+pub use stats::{HitRateTracker, ScanStats};
+pub use tinylfu::WTinyLfuCache;
+pub use versioned::VersionedCache;
+pub use wal::WalCache;
+pub use warmup::{IndexWarmup, NpyWarmup};
@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::table_bytes;
+use crate::caching::HeapSize;
+use crate::numerics::{ApproxComparable, Scoped};
+use crate::{ProximityError, Result};
+
+/// Activity of one namespace of a [`NamespacedCache`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NamespaceStats {
+    /// Entries of the namespace currently stored.
+    pub len: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries of the namespace evicted, by the cache's policy or for lack of room.
+    pub evicted: u64,
+    /// Inserts refused because the namespace was at its quota.
+    pub rejected: u64,
+}
+
+impl NamespaceStats {
+    pub fn hit_rate(&self) -> f32 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f32 / lookups as f32
+    }
+}
+
+/// Wraps a cache of [`Scoped`] keys to serve several tenants at once, with quotas and
+/// stats per namespace, the scope of the keys.
+///
+/// Tenants share the entries, the capacity and the eviction policy of a single cache,
+/// so that entries of a quiet tenant make room for those of a busy one, instead of
+/// each tenant having a cache of its own sized for its peak. Lookups never match an
+/// entry of another namespace: namespaces are compared before vectors, so that entries
+/// of other tenants cost a comparison of namespaces only. Over an
+/// [`LshCache`](crate::caching::LshCache), the namespace is folded into the signature
+/// of a key, so that close keys of different tenants land in different buckets instead
+/// of competing for the capacity of one.
+///
+/// A namespace at its quota may only insert if the entry its insert would evict is its
+/// own. Its other inserts are refused and come back as the only evicted entry. The
+/// length of a namespace follows that of the cache, so that a key the cache replaces in
+/// place when re-inserted is counted once.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{FifoCache, NamespacedCache};
+///
+/// let mut cache = NamespacedCache::new(FifoCache::new(16).unwrap());
+/// cache.set_quota("tenant-b", Some(1)).unwrap();
+/// cache.insert_ns("tenant-a", 10 as i16, "a", 2.0);
+/// cache.insert_ns("tenant-b", 10, "b", 2.0);
+/// cache.insert_ns("tenant-b", 20, "b2", 2.0); // refused, tenant-b is at its quota
+///
+/// assert_eq!(cache.find_ns("tenant-a", 11), Some("a"));
+/// assert_eq!(cache.find_ns("tenant-b", 11), Some("b"));
+/// assert_eq!(cache.find_ns("tenant-b", 20), None);
+/// assert_eq!(cache.stats(&"tenant-b").rejected, 1);
+/// ```
+pub struct NamespacedCache<N, C> {
+    inner: C,
+    quotas: HashMap<N, usize>,
+    stats: HashMap<N, NamespaceStats>,
+}
+
+impl<N, C> NamespacedCache<N, C>
+where
+    N: Eq + Hash + Clone,
+{
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            quotas: HashMap::new(),
+            stats: HashMap::new(),
+        }
+    }
+
+    /// Bounds the entries of `ns` to `quota`, or lifts its bound if `None`. Entries
+    /// above a lowered quota stay until evicted.
+    pub fn set_quota(&mut self, ns: N, quota: Option<usize>) -> Result<()> {
+        match quota {
+            Some(0) => Err(ProximityError::InvalidArgument(
+                "namespace quota must be positive".into(),
+            )),
+            Some(quota) => {
+                self.quotas.insert(ns, quota);
+                Ok(())
+            }
+            None => {
+                self.quotas.remove(&ns);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn quota(&self, ns: &N) -> Option<usize> {
+        self.quotas.get(ns).copied()
+    }
+
+    /// Activity of `ns`, all zeros if it was never used.
+    pub fn stats(&self, ns: &N) -> NamespaceStats {
+        self.stats.get(ns).copied().unwrap_or_default()
+    }
+
+    /// Iterates over every namespace used so far along with its activity.
+    pub fn namespaces(&self) -> impl Iterator<Item = (&N, &NamespaceStats)> {
+        self.stats.iter()
+    }
+
+    /// Looks `key` up among the entries of `ns`.
+    pub fn find_ns<K, V>(&mut self, ns: N, key: K) -> Option<V>
+    where
+        K: ApproxComparable,
+        C: ApproximateCache<Scoped<N, K>, V>,
+    {
+        self.find(&Scoped::new(ns, key))
+    }
+
+    /// Inserts `key` in `ns`, and returns the entries evicted to make room for it, or
+    /// the newcomer itself if it was refused.
+    pub fn insert_ns<K, V>(
+        &mut self,
+        ns: N,
+        key: K,
+        value: V,
+        tolerance: Tolerance,
+    ) -> Vec<(Scoped<N, K>, V, Tolerance)>
+    where
+        K: ApproxComparable,
+        C: ApproximateCache<Scoped<N, K>, V>,
+    {
+        self.insert_evicting(Scoped::new(ns, key), value, tolerance)
+    }
+
+    /// Whether inserting `incoming` would currently be admitted under the quota of its
+    /// namespace.
+    pub fn admits<K, V>(&self, incoming: &Scoped<N, K>) -> bool
+    where
+        K: ApproxComparable,
+        C: ApproximateCache<Scoped<N, K>, V>,
+    {
+        let Some(&quota) = self.quotas.get(&incoming.scope) else {
+            return true;
+        };
+        self.stats(&incoming.scope).len < quota
+            || self
+                .inner
+                .next_victim(incoming)
+                .is_some_and(|victim| victim.scope == incoming.scope)
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn stats_mut(&mut self, ns: &N) -> &mut NamespaceStats {
+        if !self.stats.contains_key(ns) {
+            self.stats.insert(ns.clone(), NamespaceStats::default());
+        }
+        self.stats.get_mut(ns).unwrap()
+    }
+
+    fn record_lookup(&mut self, ns: &N, hit: bool) {
+        let stats = self.stats_mut(ns);
+        if hit {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+    }
+}
+
+impl<N, K, V, C> ApproximateCache<Scoped<N, K>, V> for NamespacedCache<N, C>
+where
+    N: Eq + Hash + Clone,
+    K: ApproxComparable,
+    C: ApproximateCache<Scoped<N, K>, V>,
+{
+    forward_cache!(inner: Scoped<N, K>, V =>
+        default_tolerance, len, candidates, key_dim, nearest, pin, unpin, entry_info, entry_infos,
+        iter, compact, recent_hit_rate,
+    );
+
+    fn find(&mut self, target: &Scoped<N, K>) -> Option<V> {
+        let found = self.inner.find(target);
+        self.record_lookup(&target.scope, found.is_some());
+        found
+    }
+
+    fn find_k(&mut self, target: &Scoped<N, K>, k: usize) -> Vec<(V, f32)> {
+        let found = self.inner.find_k(target, k);
+        self.record_lookup(&target.scope, !found.is_empty());
+        found
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: Scoped<N, K>,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(Scoped<N, K>, V, Tolerance)> {
+        if !self.admits(&key) {
+            self.stats_mut(&key.scope).rejected += 1;
+            return vec![(key, value, tolerance)];
+        }
+        let scope = key.scope.clone();
+        let len = self.inner.len();
+        let evicted = self
+            .inner
+            .insert_with_priority(key, value, tolerance, priority);
+        // the entries the cache gained, which the evicted ones then take off their
+        // namespaces: none for a key replaced in place, and a newcomer that is not
+        // admitted comes back among the evicted entries
+        self.stats_mut(&scope).len += (self.inner.len() + evicted.len()).saturating_sub(len);
+        for (key, _, _) in &evicted {
+            let stats = self.stats_mut(&key.scope);
+            stats.len = stats.len.saturating_sub(1);
+            stats.evicted += 1;
+        }
+        evicted
+    }
+
+    /// `None` for newcomers that their quota would refuse.
+    fn next_victim(&self, incoming: &Scoped<N, K>) -> Option<&Scoped<N, K>> {
+        if !self.admits(incoming) {
+            return None;
+        }
+        self.inner.next_victim(incoming)
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (Scoped<N, K>, V, Tolerance)> + '_> {
+        self.stats.values_mut().for_each(|stats| stats.len = 0);
+        self.inner.drain()
+    }
+
+    /// Also counts the entries of every namespace again.
+    fn maintain(&mut self) {
+        self.inner.maintain();
+        self.stats.values_mut().for_each(|stats| stats.len = 0);
+        let scopes: Vec<N> = self
+            .inner
+            .iter()
+            .map(|(key, _, _)| key.scope.clone())
+            .collect();
+        for scope in &scopes {
+            self.stats_mut(scope).len += 1;
+        }
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        Scoped<N, K>: HeapSize,
+        V: HeapSize,
+    {
+        self.inner.memory_bytes() + table_bytes(&self.quotas) + table_bytes(&self.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LruCache, LshFifoCache};
+    use crate::simulation::SimKey;
+
+    #[test]
+    fn test_namespaces_share_capacity() {
+        let mut cache = NamespacedCache::new(LruCache::new(3).unwrap());
+        cache.insert_ns(1u8, 10i16, "a", 2.0);
+        cache.insert_ns(2, 10, "b", 2.0);
+        cache.insert_ns(2, 20, "b", 2.0);
+        assert_eq!(cache.find_ns(1, 11), Some("a"));
+        assert_eq!(cache.find_ns(3, 11), None);
+
+        // the busy namespace evicts the least recently used entry, its own
+        let evicted = cache.insert_ns(1, 30, "a", 2.0);
+        assert_eq!(evicted[0].0, Scoped::new(2, 10));
+        let stats = cache.stats(&2);
+        assert_eq!((stats.len, stats.evicted), (1, 1));
+        assert_eq!(cache.stats(&1).len, 2);
+        assert_eq!(cache.stats(&1).hit_rate(), 1.0);
+        assert_eq!(cache.stats(&3).misses, 1);
+        assert_eq!(cache.namespaces().count(), 3);
+
+        cache.drain().for_each(drop);
+        assert_eq!(cache.stats(&1).len, 0);
+    }
+
+    #[test]
+    fn test_quota_lets_namespace_displace_only_itself() {
+        let mut cache = NamespacedCache::new(LruCache::new(3).unwrap());
+        cache.set_quota(1u8, Some(2)).unwrap();
+        cache.insert_ns(2, 10i16, 2, 2.0);
+        cache.insert_ns(1, 10, 1, 2.0);
+        cache.insert_ns(1, 20, 1, 2.0);
+        // at its quota, and the victim would be another namespace's entry
+        assert_eq!(cache.insert_ns(1, 30, 1, 2.0).len(), 1);
+        assert_eq!(cache.stats(&1).rejected, 1);
+        assert_eq!(cache.find_ns(1, 30), None);
+
+        // once the victim is its own, the namespace may insert
+        cache.find_ns(2, 10);
+        let evicted = cache.insert_ns(1, 30, 1, 2.0);
+        assert_eq!(evicted[0].0, Scoped::new(1, 10));
+        assert_eq!(cache.stats(&1).len, 2);
+        // a key replaced in place is not counted again
+        assert!(cache.insert_ns(1, 20, 2, 2.0).is_empty());
+        assert_eq!(cache.find_ns(1, 20), Some(2));
+        assert_eq!(cache.stats(&1).len, 2);
+        assert_eq!(cache.len(), 3);
+        assert!(cache.set_quota(1, Some(0)).is_err());
+        cache.set_quota(1, None).unwrap();
+        assert_eq!(cache.quota(&1), None);
+    }
+
+    #[test]
+    fn test_quota_holds_for_keys_close_to_stored_ones() {
+        let mut cache = NamespacedCache::new(FifoCache::new(8).unwrap());
+        cache.set_quota(1u8, Some(1)).unwrap();
+        cache.insert_ns(1, 10i16, 0, 2.0);
+        // each key matches the stored one, but the cache would store it besides it
+        for (key, value) in (11..15).zip(1..) {
+            assert_eq!(cache.insert_ns(1, key, value, 2.0).len(), 1);
+        }
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.stats(&1).len, 1);
+        assert_eq!(cache.stats(&1).rejected, 4);
+    }
+
+    #[test]
+    fn test_namespaces_over_lsh_buckets() {
+        let lsh = LshFifoCache::new(4, 8, 8, Some(3)).unwrap();
+        let mut cache = NamespacedCache::new(lsh);
+        cache.insert_ns("a", SimKey(vec![1.0; 8]), 1, 0.1);
+        cache.insert_ns("b", SimKey(vec![1.0; 8]), 2, 0.1);
+        assert_eq!(cache.find_ns("a", SimKey(vec![1.01; 8])), Some(1));
+        assert_eq!(cache.find_ns("b", SimKey(vec![1.01; 8])), Some(2));
+        assert_eq!(cache.find_ns("c", SimKey(vec![1.0; 8])), None);
+        cache.maintain();
+        assert_eq!(cache.stats(&"a").len, 1);
+        // the same vector in another namespace lands in another bucket
+        let bucket = |ns| {
+            let key = Scoped::new(ns, SimKey(vec![1.0; 8]));
+            cache.get_ref().candidates(&key)
+        };
+        assert_eq!((bucket("a"), bucket("b")), (1, 1));
+    }
+}
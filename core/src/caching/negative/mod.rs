@@ -0,0 +1,2 @@
+mod negative_cache;
+pub use negative_cache::{Lookup, NegativeCache};
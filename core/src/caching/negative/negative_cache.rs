@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::slots_bytes;
+use crate::caching::HeapSize;
+use crate::clock::Instant;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Outcome of a [`NegativeCache::lookup`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lookup<V> {
+    /// A stored value matched the query.
+    Hit(V),
+    /// No value matched, but a live negative entry says there is no useful answer.
+    KnownMiss,
+    /// Nothing is known about the query.
+    Miss,
+}
+
+struct NegativeEntry<K> {
+    key: K,
+    tol: Tolerance,
+    expires_at: Instant,
+}
+
+/// Wraps a cache so that misses can be cached too.
+///
+/// `insert_negative` records that queries close to a key have no useful answer,
+/// for a bounded time. Repeated nearby queries then come back as [`Lookup::KnownMiss`]
+/// instead of [`Lookup::Miss`] and can skip the expensive recomputation path.
+/// At most `max_negatives` negative entries are kept, the oldest being dropped first.
+///
+/// # Example Usage
+/// ```
+/// use std::time::Duration;
+/// use proximity::caching::{ApproximateCache, Lookup, LruCache, NegativeCache};
+///
+/// let mut cache = NegativeCache::new(LruCache::new(16).unwrap(), 16).unwrap();
+/// cache.insert(10 as i16, "Value 1", 2.0);
+/// cache.insert_negative(50, 2.0, Duration::from_secs(60));
+///
+/// assert_eq!(cache.lookup(&11), Lookup::Hit("Value 1"));
+/// assert_eq!(cache.lookup(&51), Lookup::KnownMiss);
+/// assert_eq!(cache.lookup(&30), Lookup::Miss);
+/// ```
+pub struct NegativeCache<K, C> {
+    inner: C,
+    max_negatives: usize,
+    negatives: VecDeque<NegativeEntry<K>>,
+}
+
+impl<K, C> NegativeCache<K, C>
+where
+    K: ApproxComparable,
+{
+    pub fn new(inner: C, max_negatives: usize) -> Result<Self> {
+        if max_negatives == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "negative capacity must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            inner,
+            max_negatives,
+            negatives: VecDeque::new(),
+        })
+    }
+
+    /// Records that queries within `tolerance` of `key` have no useful answer for the next `ttl`.
+    pub fn insert_negative(&mut self, key: K, tolerance: Tolerance, ttl: Duration) {
+        self.purge_expired();
+        self.negatives.push_back(NegativeEntry {
+            key,
+            tol: tolerance,
+            expires_at: Instant::now() + ttl,
+        });
+        if self.negatives.len() > self.max_negatives {
+            self.negatives.pop_front();
+        }
+    }
+
+    /// Whether a live negative entry matches `target`.
+    /// Expired entries are skipped here and dropped by inserts or [`maintain`](ApproximateCache::maintain).
+    pub fn is_known_miss(&self, target: &K) -> bool {
+        let now = Instant::now();
+        self.negatives
+            .iter()
+            .any(|entry| entry.expires_at > now && entry.key.roughly_matches(target, entry.tol))
+    }
+
+    /// Number of live negative entries.
+    /// How many negative entries are remembered at most.
+    pub fn max_negatives(&self) -> usize {
+        self.max_negatives
+    }
+
+    /// The wrapped cache.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// The wrapped cache, e.g. to resize it. Negative entries are left as they are.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn negative_len(&self) -> usize {
+        let now = Instant::now();
+        self.negatives
+            .iter()
+            .filter(|entry| entry.expires_at > now)
+            .count()
+    }
+
+    fn purge_expired(&mut self) {
+        let now = Instant::now();
+        self.negatives.retain(|entry| entry.expires_at > now);
+    }
+
+    /// Looks `target` up among stored values first, then among negative entries.
+    pub fn lookup<V>(&mut self, target: &K) -> Lookup<V>
+    where
+        C: ApproximateCache<K, V>,
+    {
+        match self.inner.find(target) {
+            Some(value) => Lookup::Hit(value),
+            None if self.is_known_miss(target) => Lookup::KnownMiss,
+            None => Lookup::Miss,
+        }
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for NegativeCache<K, C>
+where
+    K: ApproxComparable,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        find, find_k, insert_with_priority, default_tolerance, len, candidates, key_dim, nearest,
+        next_victim, pin, unpin, entry_info, entry_infos, iter, recent_hit_rate,
+    );
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        self.negatives.clear();
+        self.inner.drain()
+    }
+
+    /// Also drops expired negative entries.
+    fn maintain(&mut self) {
+        self.purge_expired();
+        self.inner.maintain();
+    }
+
+    fn compact(&mut self) {
+        self.negatives.shrink_to_fit();
+        self.inner.compact();
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.inner.memory_bytes()
+            + slots_bytes::<NegativeEntry<K>>(self.negatives.capacity())
+            + self
+                .negatives
+                .iter()
+                .map(|n| n.key.heap_bytes())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::FifoCache;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+    const LONG_TTL: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn test_negative_cache_lookup() {
+        let mut cache = NegativeCache::new(FifoCache::new(2).unwrap(), 2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert_negative(2, TEST_TOLERANCE, LONG_TTL);
+        assert_eq!(cache.lookup(&1), Lookup::Hit(1));
+        assert_eq!(cache.lookup(&2), Lookup::KnownMiss);
+        assert_eq!(cache.lookup(&3), Lookup::Miss);
+        // negatives are invisible through the regular cache API
+        assert_eq!(cache.find(&2), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_negative_cache_positive_wins() {
+        let mut cache = NegativeCache::new(FifoCache::new(2).unwrap(), 2).unwrap();
+        cache.insert_negative(1, TEST_TOLERANCE, LONG_TTL);
+        cache.insert(1, 10, TEST_TOLERANCE);
+        assert_eq!(cache.lookup(&1), Lookup::Hit(10));
+    }
+
+    #[test]
+    fn test_negative_cache_expiry() {
+        let mut cache: NegativeCache<i16, FifoCache<i16, i16>> =
+            NegativeCache::new(FifoCache::new(2).unwrap(), 2).unwrap();
+        cache.insert_negative(1, TEST_TOLERANCE, Duration::ZERO);
+        assert_eq!(cache.lookup(&1), Lookup::Miss);
+        assert_eq!(cache.negative_len(), 0);
+        assert_eq!(cache.negatives.len(), 1);
+        cache.maintain();
+        assert!(cache.negatives.is_empty());
+    }
+
+    #[test]
+    fn test_negative_cache_capacity() {
+        let mut cache: NegativeCache<i16, FifoCache<i16, i16>> =
+            NegativeCache::new(FifoCache::new(2).unwrap(), 2).unwrap();
+        cache.insert_negative(1, TEST_TOLERANCE, LONG_TTL);
+        cache.insert_negative(2, TEST_TOLERANCE, LONG_TTL);
+        cache.insert_negative(3, TEST_TOLERANCE, LONG_TTL); // Drops the negative for key 1
+        assert_eq!(cache.negative_len(), 2);
+        assert!(!cache.is_known_miss(&1));
+        assert!(cache.is_known_miss(&2));
+        assert!(cache.is_known_miss(&3));
+    }
+}
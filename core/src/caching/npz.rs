@@ -0,0 +1,346 @@
+use std::path::Path;
+
+use npyz::npz::{NpzArchive, NpzWriter};
+use npyz::{AutoSerialize, DType, Deserialize, Endianness, Field, TypeStr, WriterBuilder};
+
+use crate::caching::ApproximateCache;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Version of the archives written by [`export_npz`](NpzPersistence::export_npz).
+/// Archives of a later version are refused, those without one predate versioning and
+/// share the layout of version 1.
+const NPZ_FORMAT_VERSION: u32 = 1;
+
+/// Saving and restoring the contents of a vector cache as a `.npz` archive,
+/// readable with `numpy.load`.
+///
+/// The archive holds three parallel arrays: `keys` of shape `(n, d)` and dtype `f32`,
+/// `values` of shape `(n,)` and `tolerances` of shape `(n,)` and dtype `f32`, along with
+/// `format_version`, a `u32` array of shape `(1,)`. Arrays are written little-endian
+/// whatever the machine, and arrays of either byte order are read, so an archive loads
+/// on any machine. Usage metadata and eviction order are not preserved.
+pub trait NpzPersistence<K, V>: ApproximateCache<K, V>
+where
+    K: ApproxComparable,
+{
+    fn export_npz(&self, path: &Path) -> Result<()>;
+    /// Inserts every entry of an archive written by [`export_npz`](Self::export_npz),
+    /// evicting as needed. Returns the number of entries inserted.
+    fn import_npz(&mut self, path: &Path) -> Result<usize>;
+}
+
+impl<K, V, C> NpzPersistence<K, V> for C
+where
+    K: ApproxComparable + AsRef<[f32]> + From<Vec<f32>>,
+    V: AutoSerialize + Deserialize,
+    C: ApproximateCache<K, V> + ?Sized,
+{
+    fn export_npz(&self, path: &Path) -> Result<()> {
+        let mut keys = Vec::new();
+        let mut values = Vec::with_capacity(self.len());
+        let mut tolerances = Vec::with_capacity(self.len());
+        let mut dim = 0;
+        for (key, value, tolerance) in self.iter() {
+            let key = key.as_ref();
+            if values.is_empty() {
+                dim = key.len();
+            } else if key.len() != dim {
+                return Err(ProximityError::InvalidArgument(
+                    "stored keys have different dimensions".into(),
+                ));
+            }
+            keys.extend_from_slice(key);
+            values.push(value);
+            tolerances.push(tolerance);
+        }
+        let rows = values.len() as u64;
+
+        let mut npz = NpzWriter::create(path)?;
+        let mut writer = npz
+            .array::<u32>("format_version", Default::default())?
+            .dtype(little_endian(u32::default_dtype()))
+            .shape(&[1])
+            .begin_nd()?;
+        writer.push(&NPZ_FORMAT_VERSION)?;
+        writer.finish()?;
+        let mut writer = npz
+            .array::<f32>("keys", Default::default())?
+            .dtype(little_endian(f32::default_dtype()))
+            .shape(&[rows, dim as u64])
+            .begin_nd()?;
+        writer.extend(keys)?;
+        writer.finish()?;
+        let mut writer = npz
+            .array::<V>("values", Default::default())?
+            .dtype(little_endian(V::default_dtype()))
+            .shape(&[rows])
+            .begin_nd()?;
+        writer.extend(values)?;
+        writer.finish()?;
+        let mut writer = npz
+            .array::<f32>("tolerances", Default::default())?
+            .dtype(little_endian(f32::default_dtype()))
+            .shape(&[rows])
+            .begin_nd()?;
+        writer.extend(tolerances)?;
+        writer.finish()?;
+        npz.zip_writer().finish().map_err(std::io::Error::from)?;
+        Ok(())
+    }
+
+    fn import_npz(&mut self, path: &Path) -> Result<usize> {
+        let mut npz = NpzArchive::open(path)?;
+        if let Some(version) = npz.by_name("format_version")? {
+            match version.into_vec::<u32>()?[..] {
+                [version] if version <= NPZ_FORMAT_VERSION => {}
+                [version] => {
+                    return Err(ProximityError::InvalidData(format!(
+                        "archive of format version {version}, this version of proximity reads up to {NPZ_FORMAT_VERSION}"
+                    )))
+                }
+                _ => {
+                    return Err(ProximityError::InvalidData(
+                        "expected a single format version".into(),
+                    ))
+                }
+            }
+        }
+        let (keys, dim) = {
+            let keys = npz.by_name("keys")?.ok_or_else(|| missing("keys"))?;
+            let dim = match *keys.shape() {
+                [_, dim] => dim as usize,
+                ref shape => {
+                    return Err(ProximityError::InvalidData(format!(
+                        "expected keys of shape (n, d), got {shape:?}"
+                    )))
+                }
+            };
+            (keys.into_vec::<f32>()?, dim)
+        };
+        let values = npz
+            .by_name("values")?
+            .ok_or_else(|| missing("values"))?
+            .into_vec::<V>()?;
+        let tolerances = npz
+            .by_name("tolerances")?
+            .ok_or_else(|| missing("tolerances"))?
+            .into_vec::<f32>()?;
+        if values.len() != tolerances.len() || keys.len() != values.len() * dim {
+            return Err(ProximityError::InvalidData(
+                "keys, values and tolerances have different lengths".into(),
+            ));
+        }
+        if dim == 0 && !values.is_empty() {
+            return Err(ProximityError::InvalidData(
+                "expected keys of a positive dimension, got (n, 0)".into(),
+            ));
+        }
+
+        let mut count = 0;
+        let rows = keys.chunks_exact(dim.max(1)).map(<[f32]>::to_vec);
+        for ((key, value), tolerance) in rows.zip(values).zip(tolerances) {
+            self.insert(K::from(key), value, tolerance);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// `dtype` with its multi-byte numbers little-endian, e.g. `<f4` rather than the
+/// `>f4` that `default_dtype` gives on big-endian machines.
+fn little_endian(dtype: DType) -> DType {
+    match dtype {
+        DType::Plain(ty) if ty.endianness() == Endianness::Big => {
+            let little = format!("<{}", &ty.to_string()[1..]);
+            DType::Plain(little.parse::<TypeStr>().unwrap_or(ty))
+        }
+        DType::Array(len, inner) => DType::Array(len, Box::new(little_endian(*inner))),
+        DType::Record(fields) => DType::Record(
+            fields
+                .into_iter()
+                .map(|Field { name, dtype }| Field {
+                    name,
+                    dtype: little_endian(dtype),
+                })
+                .collect(),
+        ),
+        dtype => dtype,
+    }
+}
+
+fn missing(name: &str) -> ProximityError {
+    ProximityError::InvalidData(format!("missing array `{name}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LruCache};
+    use crate::simulation::SimKey;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+    const DIM: usize = 8;
+
+    #[test]
+    fn test_npz_roundtrip() {
+        let path = std::env::temp_dir().join("proximity_test_cache_roundtrip.npz");
+        let mut cache = LruCache::new(4).unwrap();
+        for i in 0..3 {
+            cache.insert(SimKey(vec![i as f32; DIM]), i as u64 * 10, 0.5 + i as f32);
+        }
+        cache.export_npz(&path).unwrap();
+
+        let mut restored = FifoCache::new(4).unwrap();
+        let count = restored.import_npz(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(restored.len(), 3);
+        for i in 0..3 {
+            let key = SimKey(vec![i as f32; DIM]);
+            assert_eq!(restored.find(&key), Some(i as u64 * 10));
+            let (dist, tolerance) = restored.nearest(&key).unwrap();
+            assert!(dist.abs() < TEST_TOLERANCE);
+            assert!((tolerance - (0.5 + i as f32)).abs() < TEST_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn test_empty_cache_roundtrip() {
+        let path = std::env::temp_dir().join("proximity_test_cache_empty.npz");
+        let cache: FifoCache<SimKey, u64> = FifoCache::new(4).unwrap();
+        cache.export_npz(&path).unwrap();
+        let mut restored: FifoCache<SimKey, u64> = FifoCache::new(4).unwrap();
+        let count = restored.import_npz(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(count, 0);
+        assert!(restored.is_empty());
+    }
+
+    #[test]
+    fn test_archive_is_versioned_and_little_endian() {
+        let path = std::env::temp_dir().join("proximity_test_cache_versioned.npz");
+        let mut cache = FifoCache::new(4).unwrap();
+        cache.insert(SimKey(vec![1.0; DIM]), 10u64, 0.5);
+        cache.export_npz(&path).unwrap();
+        let mut npz = NpzArchive::open(&path).unwrap();
+        for name in ["keys", "values", "tolerances", "format_version"] {
+            let descr = npz.by_name(name).unwrap().unwrap().dtype().descr();
+            assert!(descr.starts_with("'<"), "{name}: {descr}");
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            little_endian(DType::Plain(">f4".parse().unwrap())).descr(),
+            "'<f4'"
+        );
+    }
+
+    #[test]
+    fn test_import_big_endian_and_later_versions() {
+        let path = std::env::temp_dir().join("proximity_test_cache_big_endian.npz");
+        let write = |version: u32| {
+            let big = |descr: &str| DType::Plain(descr.parse().unwrap());
+            let mut npz = NpzWriter::create(&path).unwrap();
+            let mut writer = npz
+                .array::<u32>("format_version", Default::default())
+                .unwrap()
+                .dtype(big(">u4"))
+                .shape(&[1])
+                .begin_nd()
+                .unwrap();
+            writer.push(&version).unwrap();
+            writer.finish().unwrap();
+            for (name, data, shape) in [
+                ("keys", vec![2.0; DIM], vec![1, DIM as u64]),
+                ("tolerances", vec![0.5], vec![1]),
+            ] {
+                let mut writer = npz
+                    .array::<f32>(name, Default::default())
+                    .unwrap()
+                    .dtype(big(">f4"))
+                    .shape(&shape)
+                    .begin_nd()
+                    .unwrap();
+                writer.extend(data).unwrap();
+                writer.finish().unwrap();
+            }
+            let mut writer = npz
+                .array::<u64>("values", Default::default())
+                .unwrap()
+                .dtype(big(">u8"))
+                .shape(&[1])
+                .begin_nd()
+                .unwrap();
+            writer.push(&20).unwrap();
+            writer.finish().unwrap();
+            npz.zip_writer().finish().unwrap();
+        };
+
+        write(NPZ_FORMAT_VERSION);
+        let mut cache: FifoCache<SimKey, u64> = FifoCache::new(4).unwrap();
+        assert_eq!(cache.import_npz(&path).unwrap(), 1);
+        assert_eq!(cache.find(&SimKey(vec![2.0; DIM])), Some(20));
+
+        write(NPZ_FORMAT_VERSION + 1);
+        let mut cache: FifoCache<SimKey, u64> = FifoCache::new(4).unwrap();
+        let err = cache.import_npz(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::InvalidData(msg) if msg.contains("version")));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_import_missing_array() {
+        let path = std::env::temp_dir().join("proximity_test_cache_partial.npz");
+        let mut npz = NpzWriter::create(&path).unwrap();
+        let mut writer = npz
+            .array::<f32>("keys", Default::default())
+            .unwrap()
+            .default_dtype()
+            .shape(&[1, DIM as u64])
+            .begin_nd()
+            .unwrap();
+        writer.extend(vec![0.0; DIM]).unwrap();
+        writer.finish().unwrap();
+        npz.zip_writer().finish().unwrap();
+
+        let mut cache: FifoCache<SimKey, u64> = FifoCache::new(4).unwrap();
+        let err = cache.import_npz(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_import_keys_of_no_dimension() {
+        let path = std::env::temp_dir().join("proximity_test_cache_no_dim.npz");
+        let mut npz = NpzWriter::create(&path).unwrap();
+        let mut writer = npz
+            .array::<f32>("keys", Default::default())
+            .unwrap()
+            .default_dtype()
+            .shape(&[2, 0])
+            .begin_nd()
+            .unwrap();
+        writer.extend(Vec::new()).unwrap();
+        writer.finish().unwrap();
+        for name in ["values", "tolerances"] {
+            let mut writer = npz
+                .array::<f32>(name, Default::default())
+                .unwrap()
+                .default_dtype()
+                .shape(&[2])
+                .begin_nd()
+                .unwrap();
+            writer.extend(vec![1.0; 2]).unwrap();
+            writer.finish().unwrap();
+        }
+        npz.zip_writer().finish().unwrap();
+
+        let mut cache: FifoCache<SimKey, f32> = FifoCache::new(4).unwrap();
+        let err = cache.import_npz(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::InvalidData(msg) if msg.contains("dimension")));
+        assert!(cache.is_empty());
+    }
+}
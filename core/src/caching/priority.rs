@@ -0,0 +1,60 @@
+//! Bookkeeping of the priorities of the unpinned entries, shared by the caches that
+//! evict the lowest priority first.
+
+use std::collections::BTreeMap;
+
+/// Number of unpinned entries of each priority, so that the lowest priority to evict
+/// from is known without scanning the entries.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PriorityCounts {
+    counts: BTreeMap<u32, usize>,
+}
+
+impl PriorityCounts {
+    /// Counts an unpinned entry of priority `priority`.
+    pub(crate) fn add(&mut self, priority: u32) {
+        *self.counts.entry(priority).or_default() += 1;
+    }
+
+    /// Forgets an unpinned entry of priority `priority`, which must have been counted.
+    pub(crate) fn remove(&mut self, priority: u32) {
+        let count = self
+            .counts
+            .get_mut(&priority)
+            .expect("removed priority was counted");
+        *count -= 1;
+        if *count == 0 {
+            self.counts.remove(&priority);
+        }
+    }
+
+    /// Lowest priority of an unpinned entry, if any.
+    pub(crate) fn floor(&self) -> Option<u32> {
+        self.counts.keys().next().copied()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.counts.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_counts_floor() {
+        let mut counts = PriorityCounts::default();
+        assert_eq!(counts.floor(), None);
+        counts.add(3);
+        counts.add(1);
+        counts.add(1);
+        assert_eq!(counts.floor(), Some(1));
+        counts.remove(1);
+        assert_eq!(counts.floor(), Some(1));
+        counts.remove(1);
+        assert_eq!(counts.floor(), Some(3));
+        counts.clear();
+        assert_eq!(counts.floor(), None);
+    }
+}
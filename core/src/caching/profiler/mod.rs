@@ -0,0 +1,5 @@
+mod profiled_cache;
+mod reuse_profiler;
+
+pub use profiled_cache::ProfiledCache;
+pub use reuse_profiler::ReuseProfiler;
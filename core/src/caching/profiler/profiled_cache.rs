@@ -0,0 +1,88 @@
+use std::hash::Hash;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::profiler::ReuseProfiler;
+use crate::numerics::ApproxComparable;
+
+/// Wraps a cache to estimate, while it serves traffic, the hit rate it would have
+/// with other capacities. See [`ReuseProfiler`].
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::profiler::{ProfiledCache, ReuseProfiler};
+/// use proximity::caching::{ApproximateCache, LruCache};
+///
+/// let mut cache = ProfiledCache::new(LruCache::new(2).unwrap(), ReuseProfiler::new(1.0, 64));
+/// for key in [1 as i16, 2, 3] {
+///     cache.insert(key, key, 0.5);
+/// }
+/// cache.find(&1); // Miss with capacity 2, but a capacity of 3 would have hit
+/// assert_eq!(cache.profiler().estimated_hit_rate(2), 0.0);
+/// assert_eq!(cache.profiler().estimated_hit_rate(3), 1.0);
+/// ```
+pub struct ProfiledCache<K, C> {
+    inner: C,
+    profiler: ReuseProfiler<K>,
+}
+
+impl<K, C> ProfiledCache<K, C> {
+    pub fn new(inner: C, profiler: ReuseProfiler<K>) -> Self {
+        Self { inner, profiler }
+    }
+
+    pub fn profiler(&self) -> &ReuseProfiler<K> {
+        &self.profiler
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for ProfiledCache<K, C>
+where
+    K: ApproxComparable + Hash + Clone,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        default_tolerance, len, candidates, key_dim, nearest, next_victim, pin, unpin, entry_info,
+        entry_infos, iter, drain, retain, maintain, compact, recent_hit_rate, memory_bytes,
+    );
+
+    fn find(&mut self, target: &K) -> Option<V> {
+        self.profiler.record_lookup(target);
+        self.inner.find(target)
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        self.profiler.record_lookup(target);
+        self.inner.find_k(target, k)
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        self.profiler.record_insert(key.clone(), tolerance);
+        self.inner
+            .insert_with_priority(key, value, tolerance, priority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::FifoCache;
+
+    #[test]
+    fn test_profiled_cache_is_transparent() {
+        let mut cache = ProfiledCache::new(FifoCache::new(2).unwrap(), ReuseProfiler::new(1.0, 8));
+        cache.insert(1, 1, 0.5);
+        cache.insert(2, 2, 0.5);
+        assert_eq!(cache.find(&1), Some(1));
+        assert_eq!(cache.find(&3), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.profiler().sampled_lookups(), 2);
+        assert_eq!(cache.profiler().estimated_hit_rate(2), 0.5);
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::caching::approximate_cache::Tolerance;
+use crate::numerics::ApproxComparable;
+
+const SAMPLING_MODULUS: u64 = 1 << 24;
+
+/// Estimates the LRU hit rate of every capacity at once from a sample of the traffic (SHARDS).
+///
+/// Only keys whose hash falls below `sampling_rate` are tracked, so a lookup is
+/// either always or never sampled for a given key. For each sampled lookup,
+/// the profiler finds the reuse distance of the matching sampled entry, i.e.
+/// how many distinct sampled entries were used more recently. Scaled by
+/// `1 / sampling_rate`, this is the smallest LRU capacity that would have hit.
+///
+/// Keys are sampled on their exact bits, so near-identical keys are sampled independently.
+pub struct ReuseProfiler<K> {
+    sampling_rate: f64,
+    sampling_threshold: u64,
+    max_tracked: usize,
+    /// sampled entries, most recently used first
+    stack: VecDeque<(K, Tolerance)>,
+    /// histogram[d] = number of sampled lookups that hit at reuse distance d
+    histogram: Vec<u64>,
+    sampled_lookups: u64,
+}
+
+impl<K> ReuseProfiler<K>
+where
+    K: ApproxComparable + Hash,
+{
+    /// Samples a `sampling_rate` fraction of the keys and tracks at most `max_tracked` of them,
+    /// which is enough to estimate capacities up to `max_tracked / sampling_rate`.
+    pub fn new(sampling_rate: f64, max_tracked: usize) -> Self {
+        assert!(sampling_rate > 0.0 && sampling_rate <= 1.0);
+        assert!(max_tracked > 0);
+        Self {
+            sampling_rate,
+            sampling_threshold: (sampling_rate * SAMPLING_MODULUS as f64).ceil() as u64,
+            max_tracked,
+            stack: VecDeque::new(),
+            histogram: vec![0; max_tracked],
+            sampled_lookups: 0,
+        }
+    }
+
+    fn is_sampled(&self, key: &K) -> bool {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() % SAMPLING_MODULUS < self.sampling_threshold
+    }
+
+    /// Records a lookup of `target`.
+    pub fn record_lookup(&mut self, target: &K) {
+        if !self.is_sampled(target) {
+            return;
+        }
+        self.sampled_lookups += 1;
+        let matched = self
+            .stack
+            .iter()
+            .position(|(key, tol)| key.roughly_matches(target, *tol));
+        if let Some(distance) = matched {
+            self.histogram[distance] += 1;
+            let entry = self.stack.remove(distance).unwrap();
+            self.stack.push_front(entry);
+        }
+    }
+
+    /// Records that `key` was inserted.
+    pub fn record_insert(&mut self, key: K, tolerance: Tolerance) {
+        if !self.is_sampled(&key) {
+            return;
+        }
+        self.stack.push_front((key, tolerance));
+        self.stack.truncate(self.max_tracked);
+    }
+
+    /// Estimated hit rate of an LRU cache holding `capacity` entries, or `0.0` without samples.
+    pub fn estimated_hit_rate(&self, capacity: usize) -> f32 {
+        if self.sampled_lookups == 0 {
+            return 0.0;
+        }
+        let sampled_capacity = (capacity as f64 * self.sampling_rate).ceil() as usize;
+        let hits: u64 = self.histogram[..sampled_capacity.min(self.max_tracked)]
+            .iter()
+            .sum();
+        (hits as f64 / self.sampled_lookups as f64) as f32
+    }
+
+    /// Estimated `(capacity, hit rate)` for each of the given capacities.
+    pub fn hit_rate_curve(&self, capacities: &[usize]) -> Vec<(usize, f32)> {
+        capacities
+            .iter()
+            .map(|&cap| (cap, self.estimated_hit_rate(cap)))
+            .collect()
+    }
+
+    pub fn sampled_lookups(&self) -> u64 {
+        self.sampled_lookups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_exact_reuse_distances() {
+        let mut profiler = ReuseProfiler::new(1.0, 16);
+        profiler.record_insert(1i16, TEST_TOLERANCE);
+        profiler.record_insert(2, TEST_TOLERANCE);
+        profiler.record_insert(3, TEST_TOLERANCE);
+        profiler.record_lookup(&1); // Distance 2, stack is now {1, 3, 2}
+        profiler.record_lookup(&3); // Distance 1
+        profiler.record_lookup(&4); // Cold
+
+        assert_eq!(profiler.sampled_lookups(), 3);
+        let curve = profiler.hit_rate_curve(&[1, 2, 3, 100]);
+        let expected = [(1, 0.0), (2, 1.0 / 3.0), (3, 2.0 / 3.0), (100, 2.0 / 3.0)];
+        for ((cap, rate), (exp_cap, exp_rate)) in curve.into_iter().zip(expected) {
+            assert_eq!(cap, exp_cap);
+            assert!((rate - exp_rate).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_approximate_reuse() {
+        let mut profiler = ReuseProfiler::new(1.0, 16);
+        profiler.record_insert(10i16, 2.0);
+        profiler.record_lookup(&11);
+        assert_eq!(profiler.estimated_hit_rate(1), 1.0);
+    }
+
+    #[test]
+    fn test_sampled_estimate_is_close() {
+        // cyclic scan over 1000 keys: LRU hits iff the capacity holds the whole cycle
+        let mut profiler = ReuseProfiler::new(0.1, 1000);
+        for key in 0..1000i16 {
+            profiler.record_insert(key, TEST_TOLERANCE);
+        }
+        for _ in 0..3 {
+            for key in 0..1000i16 {
+                profiler.record_lookup(&key);
+            }
+        }
+        assert!(profiler.sampled_lookups() > 0);
+        assert!(profiler.estimated_hit_rate(500) < 0.1);
+        assert!(profiler.estimated_hit_rate(1200) > 0.9);
+    }
+}
@@ -0,0 +1,3 @@
+mod reduced_keys;
+
+pub use reduced_keys::ReducedKeys;
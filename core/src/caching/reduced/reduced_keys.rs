@@ -0,0 +1,206 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::numerics::{ApproxComparable, Projection};
+
+/// Wraps a cache to store and look up keys reduced by a [`Projection`], e.g. 1536-dimensional
+/// embeddings projected down to 64 components, so that the inner cache hashes and scans
+/// the small keys only.
+///
+/// Keys are given at the input dimension of the projection. Tolerances are multiplied by
+/// its distance scale on the way in, and distances and tolerances divided by it on the
+/// way out, so they keep the scale of the input keys. Keys coming out of the cache, e.g.
+/// from [`iter`](ApproximateCache::iter) or evictions, are the projected ones.
+///
+/// Distinct keys may project close to one another, so the reduced cache may return
+/// values of keys beyond the tolerance; the smaller the output dimension, the likelier.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, FifoCache, ReducedKeys};
+/// use proximity::numerics::Projection;
+///
+/// let projection = Projection::random(256, 32, 42).unwrap();
+/// let mut cache = ReducedKeys::new(FifoCache::new(4).unwrap(), projection);
+/// cache.insert(vec![1.0; 256], "Value 1", 2.0);
+///
+/// assert_eq!(cache.find(&vec![1.01; 256]), Some("Value 1"));
+/// assert_eq!(cache.find(&vec![-1.0; 256]), None);
+/// assert_eq!(cache.key_dim(), Some(256));
+/// ```
+pub struct ReducedKeys<C> {
+    inner: C,
+    projection: Projection,
+}
+
+impl<C> ReducedKeys<C> {
+    pub fn new(inner: C, projection: Projection) -> Self {
+        Self { inner, projection }
+    }
+
+    pub fn projection(&self) -> &Projection {
+        &self.projection
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// The key `target` is stored and looked up as.
+    ///
+    /// # Panics
+    /// If `target` is not of the input dimension of the projection.
+    pub fn reduce<K: AsRef<[f32]> + From<Vec<f32>>>(&self, target: &K) -> K {
+        K::from(self.projection.project(target.as_ref()))
+    }
+
+    fn scale(&self) -> f32 {
+        self.projection.distance_scale()
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for ReducedKeys<C>
+where
+    K: ApproxComparable + AsRef<[f32]> + From<Vec<f32>> + 'static,
+    V: 'static,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner => len, entry_infos, maintain, compact, recent_hit_rate);
+
+    fn find(&mut self, target: &K) -> Option<V> {
+        let target = self.reduce(target);
+        self.inner.find(&target)
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        let (target, scale) = (self.reduce(target), self.scale());
+        self.inner
+            .find_k(&target, k)
+            .into_iter()
+            .map(|(value, dist)| (value, dist / scale))
+            .collect()
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        let (key, scale) = (self.reduce(&key), self.scale());
+        self.inner
+            .insert_with_priority(key, value, tolerance * scale, priority)
+            .into_iter()
+            .map(|(key, value, tol)| (key, value, tol / scale))
+            .collect()
+    }
+
+    fn candidates(&self, target: &K) -> usize {
+        self.inner.candidates(&self.reduce(target))
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        Some(self.projection.input_dim())
+    }
+
+    fn default_tolerance(&self) -> Option<Tolerance> {
+        Some(self.inner.default_tolerance()? / self.scale())
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        let scale = self.scale();
+        self.inner
+            .nearest(&self.reduce(target))
+            .map(|(dist, tol)| (dist / scale, tol / scale))
+    }
+
+    /// `incoming` is at the input dimension, the victim at the output one.
+    fn next_victim(&self, incoming: &K) -> Option<&K> {
+        self.inner.next_victim(&self.reduce(incoming))
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        let target = self.reduce(target);
+        self.inner.pin(&target)
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        let target = self.reduce(target);
+        self.inner.unpin(&target)
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        self.inner.entry_info(&self.reduce(target))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        let scale = self.scale();
+        Box::new(
+            self.inner
+                .iter()
+                .map(move |(key, value, tol)| (key, value, tol / scale)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        let scale = self.scale();
+        Box::new(
+            self.inner
+                .drain()
+                .map(move |(key, value, tol)| (key, value, tol / scale)),
+        )
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.inner.memory_bytes() + self.projection.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{LruCache, LshLruCache};
+    use crate::simulation::SimKey;
+
+    #[test]
+    fn test_reduced_keys_rescale_tolerances() {
+        let sample: Vec<f32> = (0..64)
+            .flat_map(|i| {
+                let mut key = vec![0.0; 64];
+                key[i % 16] = (i / 16) as f32 + 1.0;
+                key
+            })
+            .collect();
+        let projection = Projection::pca(&sample, 64, 16, 5).unwrap();
+        let scale = projection.distance_scale();
+        let mut cache = ReducedKeys::new(LruCache::new(4).unwrap(), projection);
+
+        let key = SimKey::from(sample[..64].to_vec());
+        cache.insert(key.clone(), 1, 0.5);
+        assert_eq!(cache.find(&key), Some(1));
+        let (dist, tol) = cache.nearest(&key).unwrap();
+        assert!(dist < 1e-4);
+        assert!((tol - 0.5).abs() < 1e-5);
+        let (stored, _, stored_tol) = cache.iter().next().unwrap();
+        assert_eq!(stored.0.len(), 16);
+        assert!((stored_tol - 0.5).abs() < 1e-5);
+        assert!(scale > 0.0 && scale <= 1.0);
+    }
+
+    #[test]
+    fn test_reduced_keys_route_through_lsh() {
+        let projection = Projection::random(128, 16, 9).unwrap();
+        let mut cache = ReducedKeys::new(LshLruCache::new(4, 16, 4, Some(9)).unwrap(), projection);
+        let key: Vec<f32> = (0..128).map(|i| (i as f32 * 0.1).sin()).collect();
+        cache.insert(SimKey::from(key.clone()), 7, 0.5);
+        let near: Vec<f32> = key.iter().map(|x| x + 0.01).collect();
+        assert_eq!(cache.find(&SimKey::from(near)), Some(7));
+        assert_eq!(cache.candidates(&SimKey::from(key)), 1);
+    }
+}
@@ -0,0 +1,2 @@
+mod s3fifo_cache;
+pub use s3fifo_cache::S3FifoCache;
@@ -0,0 +1,575 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::ghost::GhostList;
+use crate::caching::memory::slots_bytes;
+use crate::caching::priority::PriorityCounts;
+use crate::caching::scan::MaybeSync;
+use crate::caching::slots::{self, Slot};
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Share of the capacity given to the small queue, in percent.
+const SMALL_PERCENT: usize = 10;
+/// Hits counted per entry, as the 2 bits of the S3-FIFO paper.
+const MAX_FREQ: u8 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Queue {
+    /// Newcomers, most of which are never looked up again.
+    Small,
+    /// Entries looked up while in the small queue, or readmitted from the ghosts.
+    Main,
+}
+
+struct CacheLine<K, V> {
+    key: K,
+    tol: Tolerance,
+    value: V,
+    pinned: bool,
+    info: EntryInfo,
+    queue: Queue,
+    /// Hits since the entry got to its queue or was last passed over, up to [`MAX_FREQ`].
+    freq: u8,
+}
+
+impl<K, V> Slot<K> for CacheLine<K, V> {
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn tolerance(&self) -> Tolerance {
+        self.tol
+    }
+
+    fn pinned(&self) -> bool {
+        self.pinned
+    }
+
+    fn pinned_mut(&mut self) -> &mut bool {
+        &mut self.pinned
+    }
+}
+
+impl<K, V> CacheLine<K, V> {
+    /// Whether eviction may take the entry, given the lowest priority of the unpinned
+    /// entries.
+    fn evictable(&self, floor: u32) -> bool {
+        !self.pinned && self.info.priority == floor
+    }
+}
+
+/// `S3FifoCache` is a bounded cache with approximate key matching support and S3-FIFO
+/// eviction, which quickly drops the many keys that are looked up only once.
+///
+/// Newcomers enter a small FIFO queue (10% of the capacity). Leaving it, an entry looked
+/// up more than once moves to the main FIFO queue, the others are evicted and their keys
+/// remembered as ghosts. A newcomer matching a ghost goes straight to the main queue.
+/// The main queue reinserts an entry that was looked up instead of evicting it, as
+/// [`ClockCache`](crate::caching::ClockCache) does, once per hit up to 3.
+///
+/// Ghosts are matched within the tolerance their entry was inserted with, and there are
+/// as many as the main queue holds entries.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, S3FifoCache};
+///
+/// let mut cache = S3FifoCache::new(2).unwrap();
+/// const TEST_TOL: f32 = 2.0;
+///
+/// cache.insert(10 as i16, "Value 1", TEST_TOL);
+/// cache.find(&10);
+/// cache.find(&10); // Key 10 will move to the main queue
+/// cache.insert(20, "Value 2", TEST_TOL);
+/// cache.insert(30, "Value 3", TEST_TOL); // Evicts key 20, looked up only once
+///
+/// assert_eq!(cache.find(&11), Some("Value 1"));
+/// assert!(cache.find(&20).is_none());
+/// ```
+pub struct S3FifoCache<K, V> {
+    max_capacity: usize,
+    small_capacity: usize,
+    /// Both queues, oldest first, told apart by their entries' [`Queue`].
+    items: Vec<CacheLine<K, V>>,
+    small_len: usize,
+    ghosts: GhostList<K>,
+    hit_rate: HitRateTracker,
+    /// Priorities of the unpinned entries, the ones eviction may take.
+    unpinned: PriorityCounts,
+    /// Dimension of the first vector key inserted.
+    dim: Option<usize>,
+}
+
+impl<K, V> ApproximateCache<K, V> for S3FifoCache<K, V>
+where
+    K: ApproxComparable + Clone + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn find(&mut self, target: &K) -> Option<V> {
+        trace_span!("find", cache = "s3-fifo");
+        let idx = slots::find(&self.items, self.dim, target, &mut self.hit_rate)?;
+        let entry = &mut self.items[idx];
+        entry.freq = (entry.freq + 1).min(MAX_FREQ);
+        entry.info.record_hit();
+        Some(entry.value.clone())
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "s3-fifo", k);
+        let matches = slots::find_k(&self.items, self.dim, target, k, &mut self.hit_rate);
+        matches
+            .into_iter()
+            .map(|(idx, dist)| {
+                let entry = &mut self.items[idx];
+                entry.freq = (entry.freq + 1).min(MAX_FREQ);
+                entry.info.record_hit();
+                (entry.value.clone(), dist)
+            })
+            .collect()
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "s3-fifo", tolerance, priority);
+        self.check_dim(&key).unwrap_or_else(|e| panic!("{e}"));
+        self.dim = self.dim.or(key.dimension());
+        // a newcomer of a lower priority than every evictable entry goes first
+        let full = self.items.len() >= self.max_capacity;
+        if full && self.unpinned.floor().is_some_and(|floor| floor > priority) {
+            return vec![(key, value, tolerance)];
+        }
+        // before evicting, which records ghosts that may push out the newcomer's
+        let queue = match self.ghosts.matches(&key) {
+            true => Queue::Main,
+            false => Queue::Small,
+        };
+        let mut evicted = self.evict_down_to(self.max_capacity - 1);
+        if self.items.len() >= self.max_capacity {
+            evicted.push((key, value, tolerance));
+            return evicted;
+        }
+        self.small_len += usize::from(queue == Queue::Small);
+        self.items.push(CacheLine {
+            key,
+            tol: tolerance,
+            value,
+            pinned: false,
+            info: EntryInfo::with_priority(priority),
+            queue,
+            freq: 0,
+        });
+        self.unpinned.add(priority);
+        evicted
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        slots::nearest(&self.items, self.dim, target)
+    }
+
+    fn next_victim(&self, _incoming: &K) -> Option<&K> {
+        if self.items.len() < self.max_capacity {
+            return None;
+        }
+        Some(&self.items[self.victim()?].key)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        self.set_pinned(target, true)
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        self.set_pinned(target, false)
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let (candidate, _) = slots::best_match(&self.items, self.dim, target)?;
+        Some(self.items[candidate].info)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(self.items.iter().map(|entry| (&entry.key, entry.info)))
+    }
+
+    /// Entries of both queues, oldest first.
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(
+            self.items
+                .iter()
+                .map(|entry| (&entry.key, entry.value.clone(), entry.tol)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        // the small queue gives up its entries first, then the main queue those looked
+        // up the least
+        self.items
+            .sort_by_key(|entry| (entry.info.priority, entry.queue == Queue::Main, entry.freq));
+        self.small_len = 0;
+        self.unpinned.clear();
+        Box::new(
+            self.items
+                .drain(..)
+                .map(|entry| (entry.key, entry.value, entry.tol)),
+        )
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)> {
+        self.items
+            .extract_if(.., |entry| {
+                let remove = !keep(&entry.key, &entry.value);
+                if remove {
+                    self.small_len -= usize::from(entry.queue == Queue::Small);
+                    if !entry.pinned {
+                        self.unpinned.remove(entry.info.priority);
+                    }
+                }
+                remove
+            })
+            .map(|entry| (entry.key, entry.value, entry.tol))
+            .collect()
+    }
+
+    fn compact(&mut self) {
+        self.items.shrink_to_fit();
+        self.ghosts.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let entries: usize = self
+            .items
+            .iter()
+            .map(|line| line.key.heap_bytes() + line.value.heap_bytes())
+            .sum();
+        slots_bytes::<CacheLine<K, V>>(self.items.capacity()) + entries + self.ghosts.heap_bytes()
+    }
+}
+
+impl<K, V> DefaultApproximateCache<K, V> for S3FifoCache<K, V>
+where
+    K: ApproxComparable + Clone + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn from_capacity(cap: usize) -> S3FifoCache<K, V> {
+        S3FifoCache::new(cap).expect("bucket capacity is checked by the owning cache")
+    }
+
+    fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    fn set_capacity(&mut self, cap: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        S3FifoCache::set_capacity(self, cap)
+    }
+
+    fn evict(&mut self) -> Option<(K, V, Tolerance)> {
+        self.evict_next()
+    }
+}
+
+impl<K, V> S3FifoCache<K, V>
+where
+    K: ApproxComparable,
+{
+    pub fn new(max_capacity: usize) -> Result<Self> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        let (small_capacity, ghost_capacity) = Self::queue_capacities(max_capacity);
+        Ok(Self {
+            max_capacity,
+            small_capacity,
+            items: Vec::with_capacity(max_capacity),
+            small_len: 0,
+            ghosts: GhostList::new(ghost_capacity),
+            hit_rate: HitRateTracker::default(),
+            unpinned: PriorityCounts::default(),
+            dim: None,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    pub fn small_capacity(&self) -> usize {
+        self.small_capacity
+    }
+
+    /// Keys recently evicted from the small queue.
+    pub fn ghosts(&self) -> &GhostList<K> {
+        &self.ghosts
+    }
+
+    fn queue_capacities(max_capacity: usize) -> (usize, usize) {
+        let small = (max_capacity * SMALL_PERCENT / 100).max(1);
+        (small, (max_capacity - small).max(1))
+    }
+
+    /// Index of the entry [`evict_next`](Self::evict_next) takes, without moving any.
+    fn victim(&self) -> Option<usize> {
+        let floor = self.unpinned.floor()?;
+        let evictable = |queue| {
+            self.items
+                .iter()
+                .enumerate()
+                .filter(move |(_, entry)| entry.queue == queue && entry.evictable(floor))
+        };
+        // the main queue passes over an entry once per hit, so it takes the first of
+        // the fewest hits
+        let main = evictable(Queue::Main).min_by_key(|(_, entry)| entry.freq);
+        let (mut small_len, mut promoted) = (self.small_len, None);
+        for (idx, entry) in evictable(Queue::Small) {
+            if small_len < self.small_capacity && (main.is_some() || promoted.is_some()) {
+                break;
+            }
+            if entry.freq <= 1 {
+                return Some(idx);
+            }
+            small_len -= 1;
+            promoted.get_or_insert(idx);
+        }
+        // promoted entries join the back of the main queue without hits
+        match main {
+            Some((idx, entry)) if entry.freq == 0 => Some(idx),
+            main => promoted.or(main.map(|(idx, _)| idx)),
+        }
+    }
+}
+
+impl<K, V> S3FifoCache<K, V>
+where
+    K: ApproxComparable + Clone + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    /// Changes the capacity, evicting unpinned entries until the cache fits in it, and
+    /// returns them. Pinned entries stay, even above the new capacity. The ghosts are
+    /// forgotten.
+    pub fn set_capacity(&mut self, max_capacity: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        self.max_capacity = max_capacity;
+        let (small_capacity, ghost_capacity) = Self::queue_capacities(max_capacity);
+        self.small_capacity = small_capacity;
+        let evicted = self.evict_down_to(max_capacity);
+        self.ghosts = GhostList::new(ghost_capacity);
+        Ok(evicted)
+    }
+
+    /// Evicts unpinned entries while the cache holds more than `len` of them.
+    fn evict_down_to(&mut self, len: usize) -> Vec<(K, V, Tolerance)> {
+        let mut evicted = Vec::new();
+        while self.items.len() > len {
+            match self.evict_next() {
+                Some(entry) => evicted.push(entry),
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    /// Takes the oldest evictable entry of the small queue while it is over its
+    /// capacity, or if the main queue has none, and else the main queue's. A small entry
+    /// looked up more than once moves to the main queue instead, and a main entry looked
+    /// up at all goes back to its end with a hit less, until an entry is evicted.
+    fn evict_next(&mut self) -> Option<(K, V, Tolerance)> {
+        let floor = self.unpinned.floor()?;
+        let victim = loop {
+            let oldest = |queue| {
+                self.items
+                    .iter()
+                    .position(|entry| entry.queue == queue && entry.evictable(floor))
+            };
+            let idx = match (oldest(Queue::Small), oldest(Queue::Main)) {
+                (Some(small), Some(_)) if self.small_len >= self.small_capacity => small,
+                (small, main) => main.or(small)?,
+            };
+            let entry = &mut self.items[idx];
+            match entry.queue {
+                Queue::Small if entry.freq > 1 => {
+                    entry.queue = Queue::Main;
+                    entry.freq = 0;
+                    self.small_len -= 1;
+                }
+                Queue::Main if entry.freq > 0 => entry.freq -= 1,
+                _ => break idx,
+            }
+            let entry = self.items.remove(idx);
+            self.items.push(entry);
+        };
+        let entry = self.items.remove(victim);
+        self.unpinned.remove(floor);
+        if entry.queue == Queue::Small {
+            self.small_len -= 1;
+            self.ghosts.record(entry.key.clone(), entry.tol);
+        }
+        trace_event!(
+            queue = ?entry.queue,
+            tolerance = entry.tol,
+            hits = entry.info.hits,
+            "evicted"
+        );
+        Some((entry.key, entry.value, entry.tol))
+    }
+
+    fn set_pinned(&mut self, target: &K, pinned: bool) -> bool {
+        let Some((idx, changed)) = slots::set_pinned(&mut self.items, self.dim, target, pinned)
+        else {
+            return false;
+        };
+        if changed {
+            let priority = self.items[idx].info.priority;
+            match pinned {
+                true => self.unpinned.remove(priority),
+                false => self.unpinned.add(priority),
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    fn keys(cache: &S3FifoCache<i16, i16>) -> Vec<i16> {
+        cache.iter().map(|(k, _, _)| *k).collect()
+    }
+
+    #[test]
+    fn test_s3fifo_one_hit_wonders_leave_first() {
+        let mut cache = S3FifoCache::new(10).unwrap();
+        assert_eq!(cache.small_capacity(), 1);
+        for i in 1..=10 {
+            cache.insert(i, i, TEST_TOLERANCE);
+            cache.find(&i);
+            cache.find(&i);
+        }
+        // the first newcomer moves every entry to the main queue, which evicts its oldest
+        for i in 11..=15 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        // the next ones, never looked up, leave in turn from the small queue
+        assert_eq!(keys(&cache), [2, 3, 4, 5, 6, 7, 8, 9, 10, 15]);
+        assert!(cache.ghosts().matches(&14));
+        assert!(!cache.ghosts().matches(&15));
+    }
+
+    #[test]
+    fn test_s3fifo_ghosts_go_to_the_main_queue() {
+        let mut cache = S3FifoCache::new(3).unwrap();
+        for i in 1..=4 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        // the small queue is over its capacity, so its oldest entry left as a ghost
+        assert_eq!(keys(&cache), [2, 3, 4]);
+        cache.insert(1, 1, TEST_TOLERANCE);
+        assert_eq!(keys(&cache), [3, 4, 1]);
+        // key 1 is in the main queue, out of the way of the small one
+        for i in 5..=7 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        assert_eq!(keys(&cache), [1, 6, 7]);
+    }
+
+    #[test]
+    fn test_s3fifo_main_queue_reinserts_hits() {
+        let mut cache = S3FifoCache::new(3).unwrap();
+        for i in [1, 2, 3, 4, 5, 1, 2] {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        cache.find(&1);
+        // keys 1 and 2 came back from the ghosts, key 3 too, pushing out key 5
+        cache.insert(3, 3, TEST_TOLERANCE);
+        assert_eq!(keys(&cache), [1, 2, 3]);
+        // the small queue is empty, and the main queue passes over key 1
+        assert_eq!(cache.next_victim(&6), Some(&2));
+        assert_eq!(
+            cache.insert_evicting(6, 6, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        assert_eq!(keys(&cache), [3, 1, 6]);
+    }
+
+    #[test]
+    fn test_s3fifo_next_victim_is_evicted() {
+        let mut cache = S3FifoCache::new(8).unwrap();
+        for i in 0..200i16 {
+            let key = (i * 7) % 23;
+            if cache.find(&key).is_none() {
+                let victim = cache.next_victim(&key).copied();
+                let evicted = cache.insert_evicting(key, key, TEST_TOLERANCE);
+                assert_eq!(victim, evicted.first().map(|(k, _, _)| *k), "at {i}");
+            }
+            cache.find(&(i % 5));
+        }
+        assert_eq!(cache.len(), 8);
+    }
+
+    #[test]
+    fn test_s3fifo_pinned_and_capacity() {
+        let mut cache = S3FifoCache::new(3).unwrap();
+        for i in 1..=3 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        cache.pin(&1);
+        assert_eq!(
+            cache.set_capacity(1).unwrap(),
+            vec![(2, 2, TEST_TOLERANCE), (3, 3, TEST_TOLERANCE)]
+        );
+        let rejected = cache.insert_evicting(4, 4, TEST_TOLERANCE);
+        assert_eq!(rejected, vec![(4, 4, TEST_TOLERANCE)]);
+        assert_eq!(cache.next_victim(&4), None);
+        assert!(cache.set_capacity(0).is_err());
+    }
+
+    #[test]
+    fn test_s3fifo_priority() {
+        let mut cache = S3FifoCache::new(2).unwrap();
+        cache.insert_with_priority(1, 1, TEST_TOLERANCE, 1);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        // key 1 is the oldest, but it is of a higher priority
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        cache.insert_with_priority(4, 4, TEST_TOLERANCE, 1);
+        // a newcomer of a lower priority than every entry goes first
+        assert_eq!(
+            cache.insert_evicting(5, 5, TEST_TOLERANCE),
+            vec![(5, 5, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.entry_info(&1).unwrap().priority, 1);
+    }
+}
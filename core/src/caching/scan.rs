@@ -0,0 +1,144 @@
+//! Linear scans shared by the caches that compare a query against every stored key.
+//!
+//! With the `parallel` feature, scans over at least [`PARALLEL_SCAN_THRESHOLD`] items
+//! are split across the rayon thread pool. Ties between equally distant items always go
+//! to the first one, so parallel and sequential scans return the same results.
+
+use std::cmp::Ordering;
+
+use crate::numerics::topk;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Number of items from which scans run in parallel.
+#[cfg(any(feature = "parallel", test))]
+pub(crate) const PARALLEL_SCAN_THRESHOLD: usize = 4096;
+
+/// Keys and values are shared across the scanning threads when the `parallel` feature
+/// is on, so they must then be [`Sync`]. Without it, every type qualifies.
+#[cfg(feature = "parallel")]
+pub trait MaybeSync: Sync {}
+#[cfg(feature = "parallel")]
+impl<T: Sync + ?Sized> MaybeSync for T {}
+
+/// Keys and values are shared across the scanning threads when the `parallel` feature
+/// is on, so they must then be [`Sync`]. Without it, every type qualifies.
+#[cfg(not(feature = "parallel"))]
+pub trait MaybeSync {}
+#[cfg(not(feature = "parallel"))]
+impl<T: ?Sized> MaybeSync for T {}
+
+fn closer<T>(x: &(usize, T, f32), y: &(usize, T, f32)) -> Ordering {
+    x.2.total_cmp(&y.2).then(x.0.cmp(&y.0))
+}
+
+/// Position, item and distance of the closest item for which `dist` is a number.
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn closest<'a, T, C, F>(items: C, dist: F) -> Option<(usize, &'a T, f32)>
+where
+    T: 'a,
+    C: IntoIterator<Item = &'a T>,
+    F: Fn(&T) -> Option<f32>,
+{
+    items
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, item)| Some((idx, item, dist(item).filter(|d| !d.is_nan())?)))
+        .min_by(closer)
+}
+
+/// Position, item and distance of the closest item for which `dist` is a number.
+#[cfg(feature = "parallel")]
+pub(crate) fn closest<'a, T, C, F>(items: C, dist: F) -> Option<(usize, &'a T, f32)>
+where
+    T: Sync + 'a,
+    C: Copy + IntoIterator<Item = &'a T> + IntoParallelIterator<Item = &'a T>,
+    <C as IntoIterator>::IntoIter: ExactSizeIterator,
+    <C as IntoParallelIterator>::Iter: IndexedParallelIterator,
+    F: Fn(&T) -> Option<f32> + Sync,
+{
+    let candidate =
+        |(idx, item): (usize, &'a T)| Some((idx, item, dist(item).filter(|d| !d.is_nan())?));
+    let sequential = items.into_iter();
+    if sequential.len() < PARALLEL_SCAN_THRESHOLD {
+        return sequential.enumerate().filter_map(candidate).min_by(closer);
+    }
+    items
+        .into_par_iter()
+        .enumerate()
+        .filter_map(candidate)
+        .min_by(closer)
+}
+
+/// Position, item and distance of the `k` closest items for which `dist` is a number,
+/// closest first.
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn k_closest<'a, T, C, F>(items: C, k: usize, dist: F) -> Vec<(usize, &'a T, f32)>
+where
+    T: 'a,
+    C: IntoIterator<Item = &'a T>,
+    F: Fn(&T) -> Option<f32>,
+{
+    let found: Vec<_> = items
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, item)| Some((idx, item, dist(item).filter(|d| !d.is_nan())?)))
+        .collect();
+    topk::select_smallest_k_by(found, k, closer)
+}
+
+/// Position, item and distance of the `k` closest items for which `dist` is a number,
+/// closest first.
+#[cfg(feature = "parallel")]
+pub(crate) fn k_closest<'a, T, C, F>(items: C, k: usize, dist: F) -> Vec<(usize, &'a T, f32)>
+where
+    T: Sync + 'a,
+    C: Copy + IntoIterator<Item = &'a T> + IntoParallelIterator<Item = &'a T>,
+    <C as IntoIterator>::IntoIter: ExactSizeIterator,
+    <C as IntoParallelIterator>::Iter: IndexedParallelIterator,
+    F: Fn(&T) -> Option<f32> + Sync,
+{
+    let candidate =
+        |(idx, item): (usize, &'a T)| Some((idx, item, dist(item).filter(|d| !d.is_nan())?));
+    let sequential = items.into_iter();
+    let found: Vec<_> = if sequential.len() < PARALLEL_SCAN_THRESHOLD {
+        sequential.enumerate().filter_map(candidate).collect()
+    } else {
+        items
+            .into_par_iter()
+            .enumerate()
+            .filter_map(candidate)
+            .collect()
+    };
+    topk::select_smallest_k_by(found, k, closer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_skips_nan_and_breaks_ties_by_position() {
+        let items = vec![3.0, f32::NAN, 1.0, 1.0, 2.0];
+        let (idx, item, dist) = closest(&items, |x: &f32| Some(*x)).unwrap();
+        assert_eq!((idx, *item, dist), (2, 1.0, 1.0));
+        assert!(closest(&items, |_: &f32| None).is_none());
+    }
+
+    #[test]
+    fn test_k_closest_large_scan() {
+        let items: Vec<f32> = (0..PARALLEL_SCAN_THRESHOLD * 2)
+            .map(|i| (i % 100) as f32)
+            .collect();
+        let found = k_closest(&items, usize::MAX, |x: &f32| (*x < 2.0).then_some(*x));
+        assert_eq!(found.len(), items.iter().filter(|x| **x < 2.0).count());
+        assert_eq!(found[0].0, 0);
+        assert_eq!(found[1].0, 100);
+        assert!(found.windows(2).all(|w| closer(&w[0], &w[1]).is_lt()));
+        let first = k_closest(&items, 3, |x: &f32| (*x < 2.0).then_some(*x));
+        assert_eq!(first, found[..3]);
+        let (idx, _, _) = closest(&items, |x: &f32| Some(*x)).unwrap();
+        assert_eq!(idx, 0);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::ghost::GhostList;
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Wraps a cache so that a key inserted for the first time does not evict anything:
+/// it is only remembered, without its value, and admitted if it is inserted again, so
+/// that a batch job streaming unique keys through the cache does not wipe its working
+/// set.
+///
+/// First-time keys are remembered in a [`GhostList`] of `doorkeeper_capacity` keys,
+/// which near-identical keys match within the tolerance they were inserted with. Keys
+/// that fit without evicting, e.g. while the cache or their LSH bucket fills up, are
+/// always admitted. Unlike an [`AdmissionFilter`](crate::caching::sketch::AdmissionFilter),
+/// no frequencies are kept, and the mode can be switched off between batch jobs.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, LruCache, ScanResistantCache};
+///
+/// let mut cache = ScanResistantCache::new(LruCache::new(1).unwrap(), 16).unwrap();
+/// cache.insert(1 as i16, "working set", 0.5);
+/// cache.insert(2, "one-off", 0.5); // bypassed, key 2 is remembered
+/// assert_eq!(cache.find(&1), Some("working set"));
+///
+/// cache.insert(2, "again", 0.5); // admitted on second touch
+/// assert_eq!(cache.find(&2), Some("again"));
+/// assert_eq!(cache.bypassed(), 1);
+/// ```
+pub struct ScanResistantCache<K, C> {
+    inner: C,
+    doorkeeper: GhostList<K>,
+    active: bool,
+    bypassed: u64,
+}
+
+impl<K, C> ScanResistantCache<K, C>
+where
+    K: ApproxComparable,
+{
+    /// Remembers up to `doorkeeper_capacity` first-time keys.
+    pub fn new(inner: C, doorkeeper_capacity: usize) -> Result<Self> {
+        if doorkeeper_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "doorkeeper capacity must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            inner,
+            doorkeeper: GhostList::new(doorkeeper_capacity),
+            active: true,
+            bypassed: 0,
+        })
+    }
+
+    /// Whether first-time keys are kept out, which is the case until switched off.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Switches the mode on or off, e.g. around a batch job. Switched off, every insert
+    /// goes through, and keys remembered so far are kept.
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+    }
+
+    /// Inserts that were kept out so far.
+    pub fn bypassed(&self) -> u64 {
+        self.bypassed
+    }
+
+    /// The first-time keys remembered.
+    pub fn doorkeeper(&self) -> &GhostList<K> {
+        &self.doorkeeper
+    }
+
+    /// Whether inserting `incoming` would currently be admitted.
+    pub fn admits<V>(&self, incoming: &K) -> bool
+    where
+        C: ApproximateCache<K, V>,
+    {
+        !self.active
+            || self.inner.next_victim(incoming).is_none()
+            || self.doorkeeper.matches(incoming)
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for ScanResistantCache<K, C>
+where
+    K: ApproxComparable + Clone,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        find, find_k, default_tolerance, len, candidates, key_dim, nearest, pin, unpin, entry_info,
+        entry_infos, iter, drain, retain, maintain, recent_hit_rate,
+    );
+
+    /// A first-time key that would evict an entry comes back as the only evicted entry.
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        if self.admits(&key) {
+            return self
+                .inner
+                .insert_with_priority(key, value, tolerance, priority);
+        }
+        self.doorkeeper.record(key.clone(), tolerance);
+        self.bypassed += 1;
+        vec![(key, value, tolerance)]
+    }
+
+    /// `None` for first-time keys that would be kept out.
+    fn next_victim(&self, incoming: &K) -> Option<&K> {
+        if !self.admits(incoming) {
+            return None;
+        }
+        self.inner.next_victim(incoming)
+    }
+
+    fn compact(&mut self) {
+        self.doorkeeper.shrink_to_fit();
+        self.inner.compact();
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.inner.memory_bytes() + self.doorkeeper.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{LruCache, LshLruCache};
+    use crate::simulation::SimKey;
+
+    #[test]
+    fn test_scan_does_not_wipe_working_set() {
+        let mut cache = ScanResistantCache::new(LruCache::new(2).unwrap(), 4).unwrap();
+        cache.insert(1i16, 1, 0.5);
+        cache.insert(10, 10, 0.5);
+        for key in 100..200 {
+            let evicted = cache.insert_evicting(key, key, 0.5);
+            assert_eq!(evicted, [(key, key, 0.5)]);
+        }
+        assert_eq!(cache.find(&1), Some(1));
+        assert_eq!(cache.find(&10), Some(10));
+        assert_eq!(cache.bypassed(), 100);
+        assert_eq!(cache.doorkeeper().len(), 4);
+
+        // a key seen recently enough is admitted, one forgotten is not
+        assert_eq!(cache.next_victim(&199), Some(&1));
+        cache.insert(199, 199, 0.5);
+        assert_eq!(cache.find(&199), Some(199));
+        assert_eq!(cache.next_victim(&100), None);
+
+        cache.set_active(false);
+        cache.insert(100, 100, 0.5);
+        assert_eq!(cache.find(&100), Some(100));
+        assert!(
+            ScanResistantCache::<i16, _>::new(LruCache::<i16, i16>::new(2).unwrap(), 0).is_err()
+        );
+    }
+
+    #[test]
+    fn test_buckets_fill_before_keeping_out() {
+        let lsh = LshLruCache::new(2, 8, 1, Some(5)).unwrap();
+        let mut cache = ScanResistantCache::new(lsh, 16).unwrap();
+        let key = |x: f32| SimKey(vec![x; 8]);
+        cache.insert(key(1.0), 1, 0.1);
+        cache.insert(key(-1.0), 2, 0.1);
+        assert_eq!(cache.len(), 2);
+        // near-identical to a remembered key, so admitted on its second touch
+        cache.insert(key(2.0), 3, 0.1);
+        assert_eq!(cache.find(&key(1.0)), Some(1));
+        cache.insert(key(2.01), 3, 0.1);
+        assert_eq!(cache.find(&key(2.0)), Some(3));
+        assert_eq!(cache.find(&key(1.0)), None);
+    }
+}
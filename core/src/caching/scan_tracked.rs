@@ -0,0 +1,105 @@
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::ScanStats;
+use crate::numerics::ApproxComparable;
+
+/// Wraps a cache to record how many stored keys every lookup compares against, as
+/// told by [`candidates`](ApproximateCache::candidates), in [`ScanStats`].
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, LruCache, ScanTrackedCache};
+///
+/// let mut cache = ScanTrackedCache::new(LruCache::<i16, &str>::new(4).unwrap());
+/// cache.find(&10);
+/// cache.insert(10, "Value 1", 2.0);
+/// cache.find(&11);
+///
+/// assert_eq!(cache.scan_stats().lookups(), 2);
+/// assert_eq!(cache.scan_stats().mean(), 0.5);
+/// ```
+pub struct ScanTrackedCache<C> {
+    inner: C,
+    stats: ScanStats,
+}
+
+impl<C> ScanTrackedCache<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            stats: ScanStats::default(),
+        }
+    }
+
+    pub fn scan_stats(&self) -> &ScanStats {
+        &self.stats
+    }
+
+    /// Clears the recorded scans, e.g. after a warm-up.
+    pub fn reset_scan_stats(&mut self) {
+        self.stats = ScanStats::default();
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for ScanTrackedCache<C>
+where
+    K: ApproxComparable,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        insert_with_priority, default_tolerance, len, candidates, key_dim, nearest, next_victim,
+        pin, unpin, entry_info, entry_infos, iter, drain, retain, maintain, compact, recent_hit_rate,
+        memory_bytes,
+    );
+
+    fn find(&mut self, target: &K) -> Option<V> {
+        self.stats.record(self.inner.candidates(target));
+        self.inner.find(target)
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        self.stats.record(self.inner.candidates(target));
+        self.inner.find_k(target, k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LshFifoCache};
+    use crate::simulation::{SimKey, TraceGenerator, Workload};
+
+    #[test]
+    fn test_lsh_scans_less_than_linear() {
+        let workload = Workload::Zipf {
+            pool_size: 200,
+            exponent: 1.0,
+            dim: 16,
+            noise: 0.001,
+        };
+        let mut linear = ScanTrackedCache::new(FifoCache::new(128).unwrap());
+        let mut lsh = ScanTrackedCache::new(LshFifoCache::new(6, 16, 128, Some(5)).unwrap());
+        for query in TraceGenerator::new(workload, 5).take(1000) {
+            let key = SimKey(query);
+            if linear.find(&key).is_none() {
+                linear.insert(key.clone(), 0, 0.05);
+            }
+            if lsh.find(&key).is_none() {
+                lsh.insert(key, 0, 0.05);
+            }
+        }
+        let (linear, lsh) = (linear.scan_stats(), lsh.scan_stats());
+        assert_eq!(linear.lookups(), 1000);
+        assert_eq!(linear.max(), 128);
+        assert!(
+            lsh.mean() < linear.mean() / 4.0,
+            "{} {}",
+            lsh.mean(),
+            linear.mean()
+        );
+        assert!(lsh.percentile(0.99) < linear.percentile(0.5));
+    }
+}
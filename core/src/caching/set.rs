@@ -0,0 +1,161 @@
+use std::marker::PhantomData;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+
+/// Keys with tolerances and no values, to tell whether something like a key was seen
+/// before, e.g. to deduplicate near-identical queries.
+///
+/// It is a thin front to a cache of `()` values, which take no memory, so that any
+/// policy, bounds or LSH routing of the caches apply, e.g. as built by a
+/// [`CacheBuilder`](crate::caching::CacheBuilder).
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproxSet, LruCache};
+///
+/// let mut seen = ApproxSet::new(LruCache::new(2).unwrap());
+/// assert!(!seen.check_and_insert(10 as i16, 2.0));
+/// assert!(seen.check_and_insert(11, 2.0));
+/// assert!(seen.contains(&9));
+/// assert_eq!(seen.len(), 1);
+/// ```
+pub struct ApproxSet<K, C> {
+    inner: C,
+    keys: PhantomData<fn() -> K>,
+}
+
+impl<K, C> ApproxSet<K, C>
+where
+    K: ApproxComparable,
+    C: ApproximateCache<K, ()>,
+{
+    /// A set over an empty cache.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            keys: PhantomData,
+        }
+    }
+
+    /// Whether a stored key matches `key` within its tolerance. Counts as a use of that
+    /// key for eviction.
+    pub fn contains(&mut self, key: &K) -> bool {
+        self.inner.find(key).is_some()
+    }
+
+    /// Whether a stored key matches `key`, without counting as a use of it.
+    pub fn peek(&self, key: &K) -> bool {
+        self.inner.entry_info(key).is_some()
+    }
+
+    /// Adds `key`, matching keys within `tolerance` of it, and returns the keys evicted
+    /// to make room for it, or `key` itself if it could not be admitted.
+    pub fn insert(&mut self, key: K, tolerance: Tolerance) -> Vec<K> {
+        self.inner
+            .insert_evicting(key, (), tolerance)
+            .into_iter()
+            .map(|(key, _, _)| key)
+            .collect()
+    }
+
+    /// Whether a stored key matches `key`, adding `key` if none does.
+    pub fn check_and_insert(&mut self, key: K, tolerance: Tolerance) -> bool {
+        if self.contains(&key) {
+            return true;
+        }
+        self.insert(key, tolerance);
+        false
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Iterates over every stored key along with its tolerance.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, Tolerance)> {
+        self.inner
+            .iter()
+            .map(|(key, _, tolerance)| (key, tolerance))
+    }
+
+    /// Removes every key and yields them in eviction order.
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, Tolerance)> + '_ {
+        self.inner
+            .drain()
+            .map(|(key, _, tolerance)| (key, tolerance))
+    }
+
+    /// Estimated heap memory of the set, see
+    /// [`memory_bytes`](ApproximateCache::memory_bytes).
+    pub fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+    {
+        self.inner.memory_bytes()
+    }
+
+    /// The cache holding the keys.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// The cache holding the keys, e.g. to pin them or maintain it.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{CacheBuilder, EvictionPolicy, FifoCache, LshFifoCache};
+    use crate::simulation::SimKey;
+
+    #[test]
+    fn test_membership_and_eviction() {
+        let mut set = ApproxSet::new(FifoCache::new(2).unwrap());
+        assert!(set.insert(1i16, 0.5).is_empty());
+        assert!(set.insert(5, 0.5).is_empty());
+        assert!(set.peek(&1));
+        assert_eq!(set.insert(9, 0.5), [1]);
+        assert!(!set.contains(&1));
+        assert!(set.contains(&5));
+        let keys: Vec<(i16, f32)> = set.drain().collect();
+        assert_eq!(keys, [(5, 0.5), (9, 0.5)]);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_dedup_over_built_and_lsh_caches() {
+        let key = |x: f32| SimKey(vec![x; 8]);
+        let built = CacheBuilder::new()
+            .policy(EvictionPolicy::Lru)
+            .capacity(8)
+            .build()
+            .unwrap();
+        let mut seen = ApproxSet::new(built);
+        let duplicates = [0.0, 0.01, 1.0, 1.02, 0.02]
+            .into_iter()
+            .filter(|&x| seen.check_and_insert(key(x), 0.1))
+            .count();
+        assert_eq!(duplicates, 3);
+        assert_eq!(seen.len(), 2);
+
+        let mut lsh = ApproxSet::new(LshFifoCache::new(4, 8, 8, Some(1)).unwrap());
+        lsh.insert(key(1.0), 0.1);
+        assert!(lsh.contains(&key(1.001)));
+        assert!(!lsh.contains(&key(-1.0)));
+        assert_eq!(lsh.iter().count(), 1);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::VecDeque;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+
+/// How a shadow cache fared against the primary cache of a [`ShadowComparator`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShadowReport {
+    pub lookups: u64,
+    pub primary_hits: u64,
+    pub shadow_hits: u64,
+    /// Lookups that hit in the primary cache only.
+    pub primary_only: u64,
+    /// Lookups that hit in the shadow cache only.
+    pub shadow_only: u64,
+}
+
+impl ShadowReport {
+    pub fn primary_hit_rate(&self) -> f32 {
+        self.rate(self.primary_hits)
+    }
+
+    pub fn shadow_hit_rate(&self) -> f32 {
+        self.rate(self.shadow_hits)
+    }
+
+    /// Share of the lookups on which the two caches disagreed.
+    pub fn disagreement_rate(&self) -> f32 {
+        self.rate(self.primary_only + self.shadow_only)
+    }
+
+    fn rate(&self, count: u64) -> f32 {
+        if self.lookups == 0 {
+            return 0.0;
+        }
+        count as f32 / self.lookups as f32
+    }
+}
+
+/// A lookup that hit in only one of the primary cache and a shadow cache.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Disagreement<K> {
+    pub key: K,
+    /// Index of the shadow cache among those of the comparator.
+    pub shadow: usize,
+    /// Whether the primary cache was the one that hit.
+    pub primary_hit: bool,
+}
+
+/// Wraps a primary cache, which serves every lookup, to replay its lookups and inserts
+/// against shadow caches, e.g. of another policy or capacity, and compare their hit
+/// rates without serving from them.
+///
+/// Shadow caches hold `()` values, so that they only cost their keys. They are given
+/// every insert of the primary cache, and the keys of the lookups on which a shadow
+/// disagreed with the primary are kept, the latest `max_disagreements` of them.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, LruCache, ShadowComparator};
+///
+/// let shadows = vec![LruCache::new(4).unwrap()];
+/// let mut cache = ShadowComparator::new(LruCache::new(1).unwrap(), shadows, 16);
+/// cache.insert(10 as i16, "Value 1", 2.0);
+/// cache.insert(20, "Value 2", 2.0);
+///
+/// assert_eq!(cache.find(&11), None);
+/// let report = cache.reports()[0];
+/// assert_eq!((report.primary_hits, report.shadow_hits), (0, 1));
+/// assert!(!cache.disagreements().next().unwrap().primary_hit);
+/// ```
+pub struct ShadowComparator<K, C, S> {
+    primary: C,
+    shadows: Vec<S>,
+    reports: Vec<ShadowReport>,
+    max_disagreements: usize,
+    disagreements: VecDeque<Disagreement<K>>,
+}
+
+impl<K, C, S> ShadowComparator<K, C, S> {
+    pub fn new(primary: C, shadows: Vec<S>, max_disagreements: usize) -> Self {
+        Self {
+            primary,
+            reports: vec![ShadowReport::default(); shadows.len()],
+            shadows,
+            max_disagreements,
+            disagreements: VecDeque::new(),
+        }
+    }
+
+    pub fn primary(&self) -> &C {
+        &self.primary
+    }
+
+    pub fn shadows(&self) -> &[S] {
+        &self.shadows
+    }
+
+    /// A report per shadow cache, in the order they were given.
+    pub fn reports(&self) -> &[ShadowReport] {
+        &self.reports
+    }
+
+    /// The latest lookups on which a shadow cache disagreed with the primary, oldest
+    /// first.
+    pub fn disagreements(&self) -> impl Iterator<Item = &Disagreement<K>> {
+        self.disagreements.iter()
+    }
+
+    /// Clears the reports and disagreements, e.g. once the shadow caches warmed up.
+    pub fn reset(&mut self) {
+        self.reports.fill(ShadowReport::default());
+        self.disagreements.clear();
+    }
+
+    /// Stops comparing, returning the primary cache.
+    pub fn into_primary(self) -> C {
+        self.primary
+    }
+}
+
+impl<K, V, C, S> ApproximateCache<K, V> for ShadowComparator<K, C, S>
+where
+    K: ApproxComparable + Clone,
+    C: ApproximateCache<K, V>,
+    S: ApproximateCache<K, ()>,
+{
+    forward_cache!(primary =>
+        default_tolerance, len, candidates, key_dim, nearest, next_victim, entry_info, entry_infos,
+        iter, recent_hit_rate,
+    );
+
+    fn find(&mut self, target: &K) -> Option<V> {
+        let found = self.primary.find(target);
+        let primary_hit = found.is_some();
+        for (shadow, (cache, report)) in self.shadows.iter_mut().zip(&mut self.reports).enumerate()
+        {
+            let shadow_hit = cache.find(target).is_some();
+            report.lookups += 1;
+            report.primary_hits += u64::from(primary_hit);
+            report.shadow_hits += u64::from(shadow_hit);
+            if primary_hit == shadow_hit {
+                continue;
+            }
+            if primary_hit {
+                report.primary_only += 1;
+            } else {
+                report.shadow_only += 1;
+            }
+            if self.max_disagreements > 0 {
+                if self.disagreements.len() == self.max_disagreements {
+                    self.disagreements.pop_front();
+                }
+                self.disagreements.push_back(Disagreement {
+                    key: target.clone(),
+                    shadow,
+                    primary_hit,
+                });
+            }
+        }
+        found
+    }
+
+    /// Answered by the primary cache only, and not compared.
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        self.primary.find_k(target, k)
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        for shadow in &mut self.shadows {
+            shadow.insert_with_priority(key.clone(), (), tolerance, priority);
+        }
+        self.primary
+            .insert_with_priority(key, value, tolerance, priority)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        for shadow in &mut self.shadows {
+            shadow.pin(target);
+        }
+        self.primary.pin(target)
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        for shadow in &mut self.shadows {
+            shadow.unpin(target);
+        }
+        self.primary.unpin(target)
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        for shadow in &mut self.shadows {
+            shadow.drain().for_each(drop);
+        }
+        self.primary.drain()
+    }
+
+    fn maintain(&mut self) {
+        self.shadows.iter_mut().for_each(S::maintain);
+        self.primary.maintain();
+    }
+
+    fn compact(&mut self) {
+        self.shadows.iter_mut().for_each(S::compact);
+        self.primary.compact();
+    }
+
+    /// Memory of the primary cache only.
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.primary.memory_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{ApproximateCache, CacheBuilder, EvictionPolicy, FifoCache, LruCache};
+    use crate::simulation::SimKey;
+
+    #[test]
+    fn test_reports_and_disagreements() {
+        let shadows = vec![LruCache::new(1).unwrap(), LruCache::new(4).unwrap()];
+        let mut cache = ShadowComparator::new(LruCache::new(2).unwrap(), shadows, 2);
+        for i in 0..3 {
+            cache.insert(i * 10, i, 1.0);
+        }
+        // 0 was evicted from the primary, 20 is everywhere and 10 is not in the smallest
+        // shadow
+        for target in [0, 10, 20, 30] {
+            cache.find(&target);
+        }
+        let reports = cache.reports();
+        assert_eq!(reports[0].lookups, 4);
+        assert_eq!((reports[0].primary_only, reports[0].shadow_only), (1, 0));
+        assert_eq!((reports[1].primary_only, reports[1].shadow_only), (0, 1));
+        assert_eq!(reports[1].shadow_hit_rate(), 0.75);
+        assert_eq!(reports[0].disagreement_rate(), 0.25);
+
+        let keys: Vec<_> = cache.disagreements().map(|d| (d.key, d.shadow)).collect();
+        assert_eq!(keys, [(0, 1), (10, 0)]);
+        cache.reset();
+        assert_eq!(cache.reports()[0], ShadowReport::default());
+        assert_eq!(cache.disagreements().count(), 0);
+    }
+
+    #[test]
+    fn test_shadows_of_other_policies() {
+        let shadows: Vec<Box<dyn ApproximateCache<SimKey, ()>>> =
+            [EvictionPolicy::Fifo, EvictionPolicy::Lfu]
+                .into_iter()
+                .map(|policy| {
+                    CacheBuilder::new()
+                        .policy(policy)
+                        .capacity(2)
+                        .build()
+                        .unwrap()
+                })
+                .collect();
+        let mut cache = ShadowComparator::new(FifoCache::new(2).unwrap(), shadows, 0);
+        let key = |i: usize| SimKey(vec![i as f32; 8]);
+        cache.insert(key(1), 1, 0.5);
+        cache.insert(key(2), 2, 0.5);
+        cache.find(&key(1));
+        cache.insert(key(3), 3, 0.5);
+        // FIFO evicted 1, LFU kept it since it was found once
+        assert_eq!(cache.find(&key(1)), None);
+        let reports = cache.reports();
+        assert_eq!(reports[0].shadow_only, 0);
+        assert_eq!(reports[1].shadow_only, 1);
+        assert_eq!(cache.disagreements().count(), 0);
+    }
+}
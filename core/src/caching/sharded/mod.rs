@@ -0,0 +1,2 @@
+mod sharded_cache;
+pub use sharded_cache::ShardedCache;
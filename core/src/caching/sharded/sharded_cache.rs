@@ -0,0 +1,484 @@
+use std::marker::PhantomData;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::lsh::hasher::SimHashHasher;
+use crate::caching::memory::slots_bytes;
+use crate::caching::CacheView;
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::numerics::ApproxComparable;
+use crate::numerics::VectorLike;
+use crate::numerics::SIMD_LANECOUNT;
+use crate::{ProximityError, Result};
+
+/// A cache shared between threads, which partitions keys across several inner caches
+/// by their cosine LSH signature. Each shard sits behind its own lock, so threads that
+/// hit different shards never wait on each other.
+///
+/// Keys with the same signature always land in the same shard, but two close keys on
+/// either side of a hyperplane do not, exactly like the buckets of an
+/// [`LshCache`](crate::caching::LshCache). Shards are best balanced when there is a
+/// power of two of them.
+///
+/// The methods mirror [`ApproximateCache`] but take `&self`. The trait itself is not
+/// implemented, because its borrowing methods ([`iter`](ApproximateCache::iter),
+/// [`next_victim`](ApproximateCache::next_victim)) cannot lend out keys held behind a
+/// lock. Use [`entries`](Self::entries) or [`into_shards`](Self::into_shards) instead.
+///
+/// # Example Usage
+/// ```
+/// use std::sync::Arc;
+/// use proximity::caching::{FifoCache, ShardedCache};
+/// use proximity::simulation::SimKey;
+///
+/// let shards = (0..4).map(|_| FifoCache::new(16).unwrap()).collect();
+/// let cache = Arc::new(ShardedCache::new(shards, 8, Some(42)).unwrap());
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|i| {
+///         let cache = Arc::clone(&cache);
+///         std::thread::spawn(move || cache.insert(SimKey(vec![i as f32 + 1.0; 8]), i, 0.5))
+///     })
+///     .collect();
+/// handles.into_iter().for_each(|h| h.join().unwrap());
+///
+/// assert_eq!(cache.len(), 4);
+/// assert_eq!(cache.find(&SimKey(vec![1.0; 8])), Some(0));
+/// ```
+pub struct ShardedCache<K, V, C> {
+    router: SimHashHasher,
+    shards: Vec<Mutex<C>>,
+    hit_rate: Mutex<HitRateTracker>,
+    // shards own the keys and values, the marker only ties them to the methods
+    _marker: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V, C> ShardedCache<K, V, C> {
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Normals of the hyperplanes that route keys to shards.
+    pub fn projections(&self) -> &[Vec<f32>] {
+        self.router.projections()
+    }
+
+    /// Gives the shards back, e.g. to iterate over or persist them.
+    pub fn into_shards(self) -> Vec<C> {
+        self.shards
+            .into_iter()
+            .map(|shard| shard.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .collect()
+    }
+
+    fn shard_index(&self, key: &[f32]) -> usize {
+        let signature = self.router.hash(key).unwrap_or_else(|e| panic!("{e}"));
+        let index = signature
+            .iter()
+            .fold(0, |acc, &bit| (acc << 1) | usize::from(bit));
+        index % self.shards.len()
+    }
+
+    // a panic inside one shard leaves it usable, so poisoning is ignored
+    fn lock(&self, index: usize) -> MutexGuard<'_, C> {
+        self.shards[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn shard_of(&self, key: &[f32]) -> MutexGuard<'_, C> {
+        self.lock(self.shard_index(key))
+    }
+
+    fn record(&self, hit: bool) {
+        self.hit_rate
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(hit);
+    }
+}
+
+impl<K, V, C> ShardedCache<K, V, C>
+where
+    K: ApproxComparable + AsRef<[f32]>,
+    C: ApproximateCache<K, V>,
+{
+    /// Shards keys of dimension `dim` across `shards`, which should be empty.
+    pub fn new(shards: Vec<C>, dim: usize, seed: Option<u64>) -> Result<Self> {
+        if dim == 0 || !dim.is_multiple_of(SIMD_LANECOUNT) {
+            return Err(ProximityError::InvalidArgument(format!(
+                "dimension must be a positive multiple of {SIMD_LANECOUNT}, got {dim}"
+            )));
+        }
+        if shards.is_empty() {
+            return Err(ProximityError::InvalidArgument(
+                "a sharded cache needs at least one shard".into(),
+            ));
+        }
+        // enough hyperplanes to tell every shard apart
+        let num_hash = (usize::BITS - (shards.len() - 1).leading_zeros()) as usize;
+        let router = match seed {
+            Some(s) => SimHashHasher::new_seeded(num_hash, dim, s),
+            None => SimHashHasher::new(num_hash, dim),
+        };
+        Ok(Self::with_router(shards, router))
+    }
+
+    /// Shards keys of dimension `dim` across `shards` with the given hyperplanes, e.g.
+    /// the [`projections`](Self::projections) of a cache being restored.
+    pub fn with_projections(
+        shards: Vec<C>,
+        dim: usize,
+        projections: Vec<Vec<f32>>,
+    ) -> Result<Self> {
+        if shards.is_empty() {
+            return Err(ProximityError::InvalidArgument(
+                "a sharded cache needs at least one shard".into(),
+            ));
+        }
+        let router = SimHashHasher::from_projections(dim, projections)?;
+        Ok(Self::with_router(shards, router))
+    }
+
+    fn with_router(shards: Vec<C>, router: SimHashHasher) -> Self {
+        Self {
+            router,
+            shards: shards.into_iter().map(Mutex::new).collect(),
+            hit_rate: Mutex::new(HitRateTracker::default()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn find(&self, target: &K) -> Option<V> {
+        let found = self.shard_of(target.as_ref()).find(target);
+        self.record(found.is_some());
+        found
+    }
+
+    /// See [`ApproximateCache::try_find`].
+    pub fn try_find(&self, target: &K) -> Result<Option<V>> {
+        self.check_dim(target)?;
+        Ok(self.find(target))
+    }
+
+    pub fn find_k(&self, target: &K, k: usize) -> Vec<(V, f32)> {
+        let found = self.shard_of(target.as_ref()).find_k(target, k);
+        self.record(!found.is_empty());
+        found
+    }
+
+    pub fn insert(&self, key: K, value: V, tolerance: f32) {
+        self.insert_evicting(key, value, tolerance);
+    }
+
+    pub fn insert_evicting(&self, key: K, value: V, tolerance: f32) -> Vec<(K, V, Tolerance)> {
+        self.shard_of(key.as_ref())
+            .insert_evicting(key, value, tolerance)
+    }
+
+    /// See [`ApproximateCache::try_insert`].
+    pub fn try_insert(&self, key: K, value: V, tolerance: f32) -> Result<Vec<(K, V, Tolerance)>> {
+        self.check_dim(&key)?;
+        Ok(self.insert_evicting(key, value, tolerance))
+    }
+
+    /// See [`ApproximateCache::insert_with_priority`]. Priorities only weigh against the
+    /// entries of the same shard.
+    pub fn insert_with_priority(
+        &self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        self.shard_of(key.as_ref())
+            .insert_with_priority(key, value, tolerance, priority)
+    }
+
+    pub fn len(&self) -> usize {
+        (0..self.shards.len()).map(|i| self.lock(i).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn key_dim(&self) -> usize {
+        self.router.dim()
+    }
+
+    pub fn check_dim(&self, key: &K) -> Result<()> {
+        let found = key.as_ref().len();
+        if found != self.router.dim() {
+            return Err(ProximityError::DimensionMismatch {
+                expected: self.router.dim(),
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    /// Only the shard that `target` hashes to is considered.
+    pub fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        self.shard_of(target.as_ref()).nearest(target)
+    }
+
+    pub fn pin(&self, target: &K) -> bool {
+        self.shard_of(target.as_ref()).pin(target)
+    }
+
+    pub fn unpin(&self, target: &K) -> bool {
+        self.shard_of(target.as_ref()).unpin(target)
+    }
+
+    pub fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        self.shard_of(target.as_ref()).entry_info(target)
+    }
+
+    /// Copies out every entry, one shard at a time, so concurrent inserts into
+    /// shards that were already visited are missed.
+    pub fn entries(&self) -> Vec<(K, V, Tolerance)>
+    where
+        K: Clone,
+    {
+        (0..self.shards.len())
+            .flat_map(|i| {
+                self.lock(i)
+                    .iter()
+                    .map(|(k, v, tol)| (k.clone(), v, tol))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Immutable copy of every entry, see [`entries`](Self::entries).
+    pub fn snapshot(&self) -> CacheView<K, V>
+    where
+        K: Clone,
+    {
+        CacheView::new(self.entries(), Some(self.key_dim()))
+    }
+
+    pub fn drain(&self) -> Vec<(K, V, Tolerance)> {
+        (0..self.shards.len())
+            .flat_map(|i| self.lock(i).drain().collect::<Vec<_>>())
+            .collect()
+    }
+
+    /// Merges every shard of `other` into the matching shard of this cache, one at a
+    /// time, see [`ApproximateCache::merge`], and returns the entries that did not fit.
+    /// Fails if `other` has a different number of shards or hyperplanes, as it would
+    /// route keys to different shards.
+    pub fn merge(&self, other: Self) -> Result<Vec<(K, V, Tolerance)>>
+    where
+        K: Clone,
+    {
+        if self.shards.len() != other.shards.len() || self.projections() != other.projections() {
+            return Err(ProximityError::InvalidArgument(
+                "cannot merge sharded caches that route keys differently".into(),
+            ));
+        }
+        let mut evicted = Vec::new();
+        for (i, shard) in other.into_shards().into_iter().enumerate() {
+            evicted.extend(self.lock(i).merge(shard));
+        }
+        Ok(evicted)
+    }
+
+    /// Maintains one shard at a time, so lookups only wait on the shard being maintained.
+    pub fn maintain(&self) {
+        for i in 0..self.shards.len() {
+            self.lock(i).maintain();
+        }
+    }
+
+    /// Compacts one shard at a time, see [`ApproximateCache::compact`].
+    pub fn compact(&self) {
+        for i in 0..self.shards.len() {
+            self.lock(i).compact();
+        }
+    }
+
+    /// Estimated heap memory of the shards and the router, see
+    /// [`ApproximateCache::memory_bytes`]. Shards are measured one at a time.
+    pub fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let shards: usize = (0..self.shards.len())
+            .map(|i| self.lock(i).memory_bytes())
+            .sum();
+        self.router.heap_bytes() + slots_bytes::<Mutex<C>>(self.shards.capacity()) + shards
+    }
+
+    pub fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .hit_rate()
+    }
+}
+
+impl<K, V, C> ShardedCache<K, V, C>
+where
+    K: ApproxComparable + AsRef<[f32]>,
+    C: DefaultApproximateCache<K, V>,
+{
+    /// Capacity of the first shard, which is that of every shard unless they were
+    /// built with different ones.
+    pub fn shard_capacity(&self) -> usize {
+        self.lock(0).capacity()
+    }
+
+    /// Changes the capacity of every shard, one at a time, see
+    /// [`FifoCache::set_capacity`](crate::caching::FifoCache::set_capacity), and
+    /// returns the evicted entries.
+    pub fn set_shard_capacity(&self, shard_capacity: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        if shard_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        let mut evicted = Vec::new();
+        for i in 0..self.shards.len() {
+            evicted.extend(self.lock(i).set_capacity(shard_capacity)?);
+        }
+        Ok(evicted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+    use crate::caching::FifoCache;
+    use crate::numerics::SIMD_LANECOUNT;
+    use crate::simulation::SimKey;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    fn key(i: usize) -> SimKey {
+        SimKey(
+            (0..SIMD_LANECOUNT)
+                .map(|j| ((i * 31 + j * 17) as f32).sin() * (i + 1) as f32)
+                .collect(),
+        )
+    }
+
+    fn sharded(
+        num_shards: usize,
+        capacity: usize,
+    ) -> ShardedCache<SimKey, usize, FifoCache<SimKey, usize>> {
+        let shards = (0..num_shards)
+            .map(|_| FifoCache::new(capacity).unwrap())
+            .collect();
+        ShardedCache::new(shards, SIMD_LANECOUNT, Some(3)).unwrap()
+    }
+
+    #[test]
+    fn test_sharded_find_and_insert() {
+        let cache = sharded(4, 64);
+        for i in 0..32 {
+            cache.insert(key(i), i, TEST_TOLERANCE);
+        }
+        assert_eq!(cache.len(), 32);
+        for i in 0..32 {
+            assert_eq!(cache.find(&key(i)), Some(i));
+        }
+        assert_eq!(cache.recent_hit_rate(), 1.0);
+        assert_eq!(cache.entries().len(), 32);
+        assert_eq!(cache.drain().len(), 32);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_sharded_set_shard_capacity() {
+        let cache = sharded(4, 64);
+        for i in 0..32 {
+            cache.insert(key(i), i, TEST_TOLERANCE);
+        }
+        let evicted = cache.set_shard_capacity(2).unwrap();
+        assert_eq!(cache.shard_capacity(), 2);
+        assert!(cache.len() <= 8);
+        assert_eq!(evicted.len() + cache.len(), 32);
+        assert!(cache.set_shard_capacity(0).is_err());
+    }
+
+    #[test]
+    fn test_sharded_merge() {
+        let (cache, other) = (sharded(4, 64), sharded(4, 64));
+        for i in 0..32 {
+            cache.insert(key(i), i, TEST_TOLERANCE);
+            other.insert(key(i + 32), i + 32, TEST_TOLERANCE);
+        }
+        assert!(cache.merge(other).unwrap().is_empty());
+        assert_eq!(cache.len(), 64);
+        for i in 0..64 {
+            assert_eq!(cache.find(&key(i)), Some(i));
+        }
+        // differently seeded caches route keys elsewhere
+        let shards = (0..4).map(|_| FifoCache::new(64).unwrap()).collect();
+        let reseeded = ShardedCache::new(shards, SIMD_LANECOUNT, Some(4)).unwrap();
+        assert!(cache.merge(reseeded).is_err());
+    }
+
+    #[test]
+    fn test_sharded_spreads_keys() {
+        let cache = sharded(4, 64);
+        for i in 0..64 {
+            cache.insert(key(i), i, TEST_TOLERANCE);
+        }
+        let sizes: Vec<usize> = cache.into_shards().iter().map(|s| s.len()).collect();
+        assert_eq!(sizes.iter().sum::<usize>(), 64);
+        assert!(sizes.iter().filter(|&&n| n > 0).count() > 1, "{sizes:?}");
+    }
+
+    #[test]
+    fn test_sharded_concurrent_access() {
+        let cache = Arc::new(sharded(8, 1024));
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    for i in (t * 100)..(t * 100 + 100) {
+                        cache.insert(key(i), i, TEST_TOLERANCE);
+                        assert!(cache.find(&key(i)).is_some());
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert!(!cache.is_empty());
+    }
+
+    #[test]
+    fn test_sharded_with_projections_routes_alike() {
+        let cache = sharded(4, 8);
+        let shards = (0..4).map(|_| FifoCache::new(8).unwrap()).collect();
+        let restored: ShardedCache<SimKey, usize, _> =
+            ShardedCache::with_projections(shards, SIMD_LANECOUNT, cache.projections().to_vec())
+                .unwrap();
+        for i in 0..32 {
+            let key = key(i);
+            assert_eq!(cache.shard_index(&key.0), restored.shard_index(&key.0));
+        }
+    }
+
+    #[test]
+    fn test_sharded_invalid_arguments() {
+        let none: Vec<FifoCache<SimKey, usize>> = Vec::new();
+        assert!(ShardedCache::new(none, SIMD_LANECOUNT, None).is_err());
+        let one = vec![FifoCache::<SimKey, usize>::new(4).unwrap()];
+        assert!(ShardedCache::new(one, 3, None).is_err());
+        let cache = sharded(1, 4);
+        assert_eq!(cache.num_shards(), 1);
+        assert!(cache.check_dim(&SimKey(vec![0.0; 3])).is_err());
+    }
+}
@@ -0,0 +1,3 @@
+mod segment;
+mod shared_cache;
+pub use shared_cache::SharedLshCache;
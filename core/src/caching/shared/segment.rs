@@ -0,0 +1,215 @@
+use std::ffi::CString;
+use std::io;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{ProximityError, Result};
+
+/// How long an opener waits for the creator of a segment to finish laying it out.
+const INIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A named POSIX shared-memory segment, mapped read-write into this process.
+pub(crate) struct Segment {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// the segment is only ever accessed through atomics or under its lock
+unsafe impl Send for Segment {}
+unsafe impl Sync for Segment {}
+
+fn shm_name(name: &str) -> Result<CString> {
+    if name.is_empty() || name.contains('/') {
+        return Err(ProximityError::InvalidArgument(format!(
+            "invalid segment name '{name}', it must be non-empty and contain no '/'"
+        )));
+    }
+    CString::new(format!("/{name}"))
+        .map_err(|_| ProximityError::InvalidArgument(format!("invalid segment name '{name}'")))
+}
+
+fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+struct Fd(libc::c_int);
+
+impl Drop for Fd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+impl Segment {
+    /// Maps the segment `name` of `len` bytes, creating it zero-filled if it does not
+    /// exist yet. Also tells whether this call created it.
+    pub(crate) fn open(name: &str, len: usize) -> Result<(Self, bool)> {
+        let path = shm_name(name)?;
+        let created = unsafe {
+            libc::shm_open(
+                path.as_ptr(),
+                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+                0o600,
+            )
+        };
+        let (fd, created) = match check(created) {
+            Ok(fd) => (Fd(fd), true),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let fd = check(unsafe { libc::shm_open(path.as_ptr(), libc::O_RDWR, 0) })?;
+                (Fd(fd), false)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if created {
+            if let Err(e) = check(unsafe { libc::ftruncate(fd.0, len as libc::off_t) }) {
+                unsafe { libc::shm_unlink(path.as_ptr()) };
+                return Err(e.into());
+            }
+        } else {
+            let found = Self::wait_for_size(&fd)?;
+            if found != len {
+                return Err(ProximityError::InvalidArgument(format!(
+                    "segment '{name}' holds {found} bytes, but this layout needs {len}"
+                )));
+            }
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.0,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error().into());
+        }
+        let ptr = NonNull::new(ptr.cast()).expect("mmap returned null");
+        Ok((Self { ptr, len }, created))
+    }
+
+    // the creator sizes the segment right after creating it, so an opener may briefly
+    // see it empty
+    fn wait_for_size(fd: &Fd) -> Result<usize> {
+        let deadline = Instant::now() + INIT_TIMEOUT;
+        loop {
+            let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+            check(unsafe { libc::fstat(fd.0, &mut stat) })?;
+            if stat.st_size > 0 {
+                return Ok(stat.st_size as usize);
+            }
+            if Instant::now() > deadline {
+                return Err(ProximityError::InvalidData(
+                    "timed out waiting for the segment to be created".into(),
+                ));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Removes the segment `name`. Processes that mapped it keep their mapping,
+    /// but later opens create a new, empty segment.
+    pub(crate) fn unlink(name: &str) -> Result<()> {
+        let path = shm_name(name)?;
+        check(unsafe { libc::shm_unlink(path.as_ptr()) })?;
+        Ok(())
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.len) };
+    }
+}
+
+/// Waits until `flag` is set, e.g. by the process that creates a segment.
+pub(crate) fn wait_until_set(flag: &AtomicU32) -> Result<()> {
+    let deadline = Instant::now() + INIT_TIMEOUT;
+    while flag.load(Ordering::Acquire) == 0 {
+        if Instant::now() > deadline {
+            return Err(ProximityError::InvalidData(
+                "timed out waiting for the segment to be initialized".into(),
+            ));
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    Ok(())
+}
+
+/// Spin lock living in shared memory, which holds the pid of its owner.
+///
+/// A lock whose owner died without releasing it is taken over by the next process
+/// that waits for it, so a crashed worker never blocks the others for good.
+pub(crate) struct ProcessLock<'a> {
+    word: &'a AtomicU32,
+}
+
+pub(crate) struct ProcessLockGuard<'a> {
+    word: &'a AtomicU32,
+}
+
+fn alive(pid: u32) -> bool {
+    let ret = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    ret == 0 || io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+impl<'a> ProcessLock<'a> {
+    pub(crate) fn new(word: &'a AtomicU32) -> Self {
+        Self { word }
+    }
+
+    pub(crate) fn lock(&self) -> ProcessLockGuard<'a> {
+        let pid = std::process::id();
+        let mut spins = 0u32;
+        loop {
+            match self
+                .word
+                .compare_exchange_weak(0, pid, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return ProcessLockGuard { word: self.word },
+                Err(owner) => {
+                    // checking the owner is a syscall, so only do it once in a while
+                    if owner != 0 && spins % 1024 == 1023 && !alive(owner) {
+                        let stolen = self.word.compare_exchange(
+                            owner,
+                            pid,
+                            Ordering::Acquire,
+                            Ordering::Relaxed,
+                        );
+                        if stolen.is_ok() {
+                            return ProcessLockGuard { word: self.word };
+                        }
+                    }
+                }
+            }
+            spins = spins.wrapping_add(1);
+            if spins < 64 {
+                std::hint::spin_loop();
+            } else {
+                thread::yield_now();
+            }
+        }
+    }
+}
+
+impl Drop for ProcessLockGuard<'_> {
+    fn drop(&mut self) {
+        self.word.store(0, Ordering::Release);
+    }
+}
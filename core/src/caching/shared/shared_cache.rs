@@ -0,0 +1,498 @@
+use std::mem::{align_of, size_of};
+use std::ptr::{self, addr_of_mut};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::lsh::hasher::SimHashHasher;
+use crate::caching::shared::segment::{self, ProcessLock, ProcessLockGuard, Segment};
+use crate::caching::HitRateTracker;
+use crate::numerics::ApproxComparable;
+use crate::numerics::VectorLike;
+use crate::numerics::SIMD_LANECOUNT;
+use crate::{ProximityError, Result};
+
+const MAGIC: u64 = u64::from_le_bytes(*b"PRXSHM01");
+const HEADER_SIZE: usize = 128;
+/// Enough buckets for any sensible signature, without letting a typo map terabytes.
+const MAX_NUM_HASH: usize = 20;
+
+#[repr(C)]
+struct Header {
+    magic: u64,
+    ready: AtomicU32,
+    lock: AtomicU32,
+    num_hash: u32,
+    dim: u32,
+    bucket_capacity: u32,
+    max_value_len: u32,
+    seed: u64,
+    clock: AtomicU64,
+    len: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+const _: () = assert!(size_of::<Header>() <= HEADER_SIZE);
+
+/// Precedes the key and value of every slot. A slot is empty when `inserted_at` is 0.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct SlotHeader {
+    inserted_at: u64,
+    tolerance: f32,
+    value_len: u32,
+}
+
+/// An LSH cache whose buckets live in a named shared-memory segment, so that several
+/// processes (e.g. forked web workers) share the same entries instead of each warming
+/// up its own copy.
+///
+/// Every process that opens the same name with the same layout (`num_hash`, `dim`,
+/// `bucket_capacity` and `max_value_len`) sees the same cache. The first one creates
+/// the segment and picks the hyperplanes' seed, which later ones read back. Each bucket
+/// holds up to `bucket_capacity` fixed-size slots and evicts its oldest entry when full.
+///
+/// Values are byte strings of at most `max_value_len` bytes, since only plain data can
+/// be shared between processes. Every operation takes a single lock in the segment,
+/// which is taken over if its owner dies while holding it. An entry being written by
+/// a process that dies at that moment is dropped rather than left half-written.
+///
+/// The segment outlives the processes that use it, until [`unlink`](Self::unlink).
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::SharedLshCache;
+///
+/// let name = format!("proximity-doc-{}", std::process::id());
+/// let writer = SharedLshCache::open(&name, 4, 8, 16, 64, Some(42)).unwrap();
+/// let reader = SharedLshCache::open(&name, 4, 8, 16, 64, None).unwrap();
+///
+/// writer.insert(&[1.0; 8], b"Value 1", 0.5).unwrap();
+/// assert_eq!(reader.find(&[1.1; 8]).unwrap(), Some(b"Value 1".to_vec()));
+/// SharedLshCache::unlink(&name).unwrap();
+/// ```
+pub struct SharedLshCache {
+    segment: Segment,
+    name: String,
+    hasher: SimHashHasher,
+    bucket_capacity: usize,
+    max_value_len: usize,
+    slot_size: usize,
+    hit_rate: Mutex<HitRateTracker>,
+}
+
+fn round_up(len: usize, align: usize) -> usize {
+    len.div_ceil(align) * align
+}
+
+impl SharedLshCache {
+    /// Opens the cache stored in the segment `name`, creating it if needed.
+    ///
+    /// Fails if the segment exists with another layout, or with another seed than
+    /// `seed` when one is given.
+    pub fn open(
+        name: &str,
+        num_hash: usize,
+        dim: usize,
+        bucket_capacity: usize,
+        max_value_len: usize,
+        seed: Option<u64>,
+    ) -> Result<Self> {
+        if num_hash == 0 || num_hash > MAX_NUM_HASH {
+            return Err(ProximityError::InvalidArgument(format!(
+                "number of hyperplanes must be between 1 and {MAX_NUM_HASH}, got {num_hash}"
+            )));
+        }
+        if dim == 0 || !dim.is_multiple_of(SIMD_LANECOUNT) {
+            return Err(ProximityError::InvalidArgument(format!(
+                "dimension must be a positive multiple of {SIMD_LANECOUNT}, got {dim}"
+            )));
+        }
+        if bucket_capacity == 0 || max_value_len == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "bucket capacity and maximum value length must be positive".into(),
+            ));
+        }
+        let too_large = || ProximityError::InvalidArgument("segment would be too large".into());
+        let slot_size = size_of::<f32>()
+            .checked_mul(dim)
+            .and_then(|key| key.checked_add(size_of::<SlotHeader>() + max_value_len))
+            .map(|len| round_up(len, align_of::<SlotHeader>()))
+            .ok_or_else(too_large)?;
+        let len = slot_size
+            .checked_mul(bucket_capacity << num_hash)
+            .and_then(|slots| slots.checked_add(HEADER_SIZE))
+            .ok_or_else(too_large)?;
+        let u32_of = |x: usize| u32::try_from(x).map_err(|_| too_large());
+        let layout = (
+            u32_of(num_hash)?,
+            u32_of(dim)?,
+            u32_of(bucket_capacity)?,
+            u32_of(max_value_len)?,
+        );
+
+        let (segment, created) = Segment::open(name, len)?;
+        let header = segment.as_ptr().cast::<Header>();
+        if created {
+            // the segment starts zeroed, so openers wait on `ready` until this is written
+            let seed = seed.unwrap_or_else(rand::random);
+            unsafe {
+                addr_of_mut!((*header).magic).write(MAGIC);
+                addr_of_mut!((*header).num_hash).write(layout.0);
+                addr_of_mut!((*header).dim).write(layout.1);
+                addr_of_mut!((*header).bucket_capacity).write(layout.2);
+                addr_of_mut!((*header).max_value_len).write(layout.3);
+                addr_of_mut!((*header).seed).write(seed);
+                (*header).ready.store(1, Ordering::Release);
+            }
+        }
+        let header = unsafe { &*header };
+        segment::wait_until_set(&header.ready)?;
+        if header.magic != MAGIC {
+            return Err(ProximityError::InvalidData(format!(
+                "segment '{name}' does not hold a cache"
+            )));
+        }
+        let found = (
+            header.num_hash,
+            header.dim,
+            header.bucket_capacity,
+            header.max_value_len,
+        );
+        if found != layout {
+            return Err(ProximityError::InvalidArgument(format!(
+                "segment '{name}' holds a cache with (num_hash, dim, bucket_capacity, \
+                 max_value_len) = {found:?}, not {layout:?}"
+            )));
+        }
+        if seed.is_some_and(|seed| seed != header.seed) {
+            return Err(ProximityError::InvalidArgument(format!(
+                "segment '{name}' holds a cache with another seed"
+            )));
+        }
+        Ok(Self {
+            hasher: SimHashHasher::new_seeded(num_hash, dim, header.seed),
+            segment,
+            name: name.to_string(),
+            bucket_capacity,
+            max_value_len,
+            slot_size,
+            hit_rate: Mutex::new(HitRateTracker::default()),
+        })
+    }
+
+    /// Removes the segment `name`. Caches already open keep working on the removed
+    /// segment, but the next [`open`](Self::open) starts from an empty one.
+    pub fn unlink(name: &str) -> Result<()> {
+        Segment::unlink(name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn key_dim(&self) -> usize {
+        self.hasher.dim()
+    }
+
+    pub fn bucket_capacity(&self) -> usize {
+        self.bucket_capacity
+    }
+
+    pub fn max_value_len(&self) -> usize {
+        self.max_value_len
+    }
+
+    /// Size of the segment in bytes.
+    pub fn segment_len(&self) -> usize {
+        self.segment.len()
+    }
+
+    pub fn check_dim(&self, key: &[f32]) -> Result<()> {
+        if key.len() != self.key_dim() {
+            return Err(ProximityError::DimensionMismatch {
+                expected: self.key_dim(),
+                found: key.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Value of the closest key in the bucket of `target` that matches it. Fails if
+    /// `target` is not of the dimension of the segment.
+    pub fn find(&self, target: &[f32]) -> Result<Option<Vec<u8>>> {
+        self.check_dim(target)?;
+        let bucket = self.bucket_of(target)?;
+        let found = {
+            let _guard = self.lock();
+            let mut closest: Option<(usize, f32)> = None;
+            for i in 0..self.bucket_capacity {
+                let slot = self.slot(bucket, i);
+                let header = unsafe { self.slot_header(slot) };
+                if header.inserted_at == 0 {
+                    continue;
+                }
+                let key = unsafe { self.key(slot) };
+                if key.roughly_matches(target, header.tolerance) {
+                    let dist = target.fuzziness(key);
+                    if closest.is_none_or(|(_, best)| dist < best) {
+                        closest = Some((i, dist));
+                    }
+                }
+            }
+            closest.map(|(i, _)| unsafe { self.value(self.slot(bucket, i)).to_vec() })
+        };
+        let counter = if found.is_some() {
+            &self.header().hits
+        } else {
+            &self.header().misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.hit_rate
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(found.is_some());
+        Ok(found)
+    }
+
+    /// Stores `value` under `key`, evicting the oldest entry of its bucket if it is full.
+    /// Fails if `key` is not of the dimension of the segment or `value` is longer than
+    /// its slots.
+    pub fn insert(&self, key: &[f32], value: &[u8], tolerance: Tolerance) -> Result<()> {
+        self.check_dim(key)?;
+        if value.len() > self.max_value_len {
+            return Err(ProximityError::InvalidArgument(format!(
+                "value of {} bytes does not fit in slots of {} bytes",
+                value.len(),
+                self.max_value_len
+            )));
+        }
+        let bucket = self.bucket_of(key)?;
+        let _guard = self.lock();
+        let header = self.header();
+        let mut target = 0;
+        let mut oldest = u64::MAX;
+        for i in 0..self.bucket_capacity {
+            let inserted_at = unsafe { self.slot_header(self.slot(bucket, i)) }.inserted_at;
+            if inserted_at < oldest {
+                (target, oldest) = (i, inserted_at);
+            }
+        }
+        if oldest == 0 {
+            header.len.fetch_add(1, Ordering::Relaxed);
+        }
+        let stamp = header.clock.fetch_add(1, Ordering::Relaxed) + 1;
+        let slot = self.slot(bucket, target);
+        unsafe {
+            // empty while being written, in case this process dies halfway
+            self.write_slot_header(slot, 0, tolerance, 0);
+            let key_ptr = slot.add(size_of::<SlotHeader>()).cast::<f32>();
+            ptr::copy_nonoverlapping(key.as_ptr(), key_ptr, key.len());
+            ptr::copy_nonoverlapping(value.as_ptr(), self.value_ptr(slot), value.len());
+            self.write_slot_header(slot, stamp, tolerance, value.len() as u32);
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.header().len.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Empties the cache for every process that uses it.
+    pub fn clear(&self) {
+        let _guard = self.lock();
+        for bucket in 0..1 << self.hasher_bits() {
+            for i in 0..self.bucket_capacity {
+                unsafe { self.write_slot_header(self.slot(bucket, i), 0, 0.0, 0) };
+            }
+        }
+        self.header().len.store(0, Ordering::Relaxed);
+    }
+
+    /// Lookups that found a match, from every process since the segment was created.
+    pub fn hits(&self) -> u64 {
+        self.header().hits.load(Ordering::Relaxed)
+    }
+
+    /// Lookups that found no match, from every process since the segment was created.
+    pub fn misses(&self) -> u64 {
+        self.header().misses.load(Ordering::Relaxed)
+    }
+
+    /// Hit rate over the most recent lookups of this process only.
+    pub fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .hit_rate()
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*self.segment.as_ptr().cast::<Header>() }
+    }
+
+    fn lock(&self) -> ProcessLockGuard<'_> {
+        ProcessLock::new(&self.header().lock).lock()
+    }
+
+    fn hasher_bits(&self) -> usize {
+        self.header().num_hash as usize
+    }
+
+    fn bucket_of(&self, key: &[f32]) -> Result<usize> {
+        let sig = self.hasher.hash(key)?;
+        Ok(sig
+            .iter()
+            .fold(0, |acc, &bit| (acc << 1) | usize::from(bit)))
+    }
+
+    fn slot(&self, bucket: usize, i: usize) -> *mut u8 {
+        let index = bucket * self.bucket_capacity + i;
+        unsafe {
+            self.segment
+                .as_ptr()
+                .add(HEADER_SIZE + index * self.slot_size)
+        }
+    }
+
+    // the accessors below must only be called with the lock held
+
+    unsafe fn slot_header(&self, slot: *mut u8) -> SlotHeader {
+        slot.cast::<SlotHeader>().read()
+    }
+
+    unsafe fn write_slot_header(&self, slot: *mut u8, inserted_at: u64, tol: f32, len: u32) {
+        slot.cast::<SlotHeader>().write(SlotHeader {
+            inserted_at,
+            tolerance: tol,
+            value_len: len,
+        });
+    }
+
+    unsafe fn key(&self, slot: *mut u8) -> &[f32] {
+        let key = slot.add(size_of::<SlotHeader>()).cast::<f32>();
+        std::slice::from_raw_parts(key, self.key_dim())
+    }
+
+    unsafe fn value_ptr(&self, slot: *mut u8) -> *mut u8 {
+        slot.add(size_of::<SlotHeader>() + self.key_dim() * size_of::<f32>())
+    }
+
+    unsafe fn value(&self, slot: *mut u8) -> &[u8] {
+        let len = self.slot_header(slot).value_len as usize;
+        std::slice::from_raw_parts(self.value_ptr(slot), len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    /// Unlinks the segment even if the test fails.
+    struct Name(String);
+
+    impl Name {
+        fn new(test: &str) -> Self {
+            Name(format!("proximity-{}-{test}", std::process::id()))
+        }
+    }
+
+    impl Drop for Name {
+        fn drop(&mut self) {
+            let _ = SharedLshCache::unlink(&self.0);
+        }
+    }
+
+    fn key(x: f32) -> Vec<f32> {
+        (0..SIMD_LANECOUNT).map(|i| x * (i as f32 + 1.0)).collect()
+    }
+
+    #[test]
+    fn test_entries_are_shared_between_handles() {
+        let name = Name::new("shared");
+        let a = SharedLshCache::open(&name.0, 2, SIMD_LANECOUNT, 4, 8, None).unwrap();
+        let b = SharedLshCache::open(&name.0, 2, SIMD_LANECOUNT, 4, 8, None).unwrap();
+        a.insert(&key(1.0), b"one", TEST_TOLERANCE).unwrap();
+        b.insert(&key(-1.0), b"two", TEST_TOLERANCE).unwrap();
+
+        assert_eq!(b.find(&key(1.0)).unwrap(), Some(b"one".to_vec()));
+        assert_eq!(a.find(&key(-1.0)).unwrap(), Some(b"two".to_vec()));
+        assert_eq!(a.find(&key(3.0)).unwrap(), None);
+        assert_eq!((a.len(), a.hits(), a.misses()), (2, 2, 1));
+        assert_eq!(b.recent_hit_rate(), 1.0);
+        b.clear();
+        assert!(a.is_empty());
+        assert_eq!(a.find(&key(1.0)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_full_bucket_evicts_oldest() {
+        let name = Name::new("evict");
+        let cache = SharedLshCache::open(&name.0, 1, SIMD_LANECOUNT, 2, 8, Some(3)).unwrap();
+        // positive multiples of one vector always share a bucket
+        for i in 1..=3 {
+            cache.insert(&key(i as f32), &[i], TEST_TOLERANCE).unwrap();
+        }
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.find(&key(1.0)).unwrap(), None);
+        assert_eq!(cache.find(&key(2.0)).unwrap(), Some(vec![2]));
+        assert_eq!(cache.find(&key(3.0)).unwrap(), Some(vec![3]));
+    }
+
+    #[test]
+    fn test_closest_match_wins() {
+        let name = Name::new("closest");
+        let cache = SharedLshCache::open(&name.0, 1, SIMD_LANECOUNT, 4, 8, None).unwrap();
+        cache.insert(&key(1.0), b"far", 10.0).unwrap();
+        cache.insert(&key(2.0), b"near", 10.0).unwrap();
+        assert_eq!(cache.find(&key(1.9)).unwrap(), Some(b"near".to_vec()));
+    }
+
+    #[test]
+    fn test_layout_and_value_checks() {
+        let name = Name::new("layout");
+        let cache = SharedLshCache::open(&name.0, 2, SIMD_LANECOUNT, 4, 8, Some(1)).unwrap();
+        assert!(SharedLshCache::open(&name.0, 2, SIMD_LANECOUNT, 5, 8, None).is_err());
+        assert!(SharedLshCache::open(&name.0, 2, SIMD_LANECOUNT, 4, 8, Some(2)).is_err());
+        assert!(SharedLshCache::open("a/b", 2, SIMD_LANECOUNT, 4, 8, None).is_err());
+        assert!(cache.insert(&key(1.0), &[0; 9], TEST_TOLERANCE).is_err());
+        assert!(matches!(
+            cache.check_dim(&[1.0; 3]),
+            Err(ProximityError::DimensionMismatch {
+                expected: SIMD_LANECOUNT,
+                found: 3
+            })
+        ));
+        assert!(matches!(
+            cache.insert(&[1.0; 3], b"short", TEST_TOLERANCE),
+            Err(ProximityError::DimensionMismatch { .. })
+        ));
+        assert!(matches!(
+            cache.find(&[1.0; 3]),
+            Err(ProximityError::DimensionMismatch { .. })
+        ));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_lock_of_dead_process_is_taken_over() {
+        let name = Name::new("dead");
+        let cache = SharedLshCache::open(&name.0, 1, SIMD_LANECOUNT, 2, 8, None).unwrap();
+        let mut child = Command::new("true").spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+        cache.header().lock.store(dead, Ordering::SeqCst);
+
+        cache.insert(&key(1.0), b"alive", TEST_TOLERANCE).unwrap();
+        assert_eq!(cache.find(&key(1.0)).unwrap(), Some(b"alive".to_vec()));
+    }
+}
@@ -0,0 +1,142 @@
+use std::hash::Hash;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::sketch::CountMinSketch;
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+
+/// Wraps a cache with a frequency-based admission policy (TinyLFU).
+///
+/// Every queried and inserted key is recorded in a [`CountMinSketch`].
+/// When an insert would evict an entry, the newcomer is only admitted if its
+/// estimated frequency is strictly higher than the victim's, so one-off keys
+/// cannot push out popular ones. Frequencies are tracked on exact key bits:
+/// near-identical queries count as different keys.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::sketch::{AdmissionFilter, CountMinSketch};
+/// use proximity::caching::{ApproximateCache, LruCache};
+///
+/// let mut cache = AdmissionFilter::new(LruCache::new(1).unwrap(), CountMinSketch::new(64, 4));
+/// cache.insert(1 as i16, "popular", 0.5);
+/// cache.find(&1);
+/// cache.insert(2, "one-off", 0.5); // Rejected: key 2 is rarer than key 1
+/// assert_eq!(cache.find(&1), Some("popular"));
+/// assert_eq!(cache.find(&2), None);
+/// ```
+pub struct AdmissionFilter<C> {
+    inner: C,
+    sketch: CountMinSketch,
+}
+
+impl<C> AdmissionFilter<C> {
+    pub fn new(inner: C, sketch: CountMinSketch) -> Self {
+        Self { inner, sketch }
+    }
+
+    /// The frequency estimates the admission decisions are based on.
+    pub fn sketch(&self) -> &CountMinSketch {
+        &self.sketch
+    }
+
+    /// Whether `incoming` would currently be admitted.
+    pub fn admits<K, V>(&self, incoming: &K) -> bool
+    where
+        K: ApproxComparable + Hash,
+        C: ApproximateCache<K, V>,
+    {
+        match self.inner.next_victim(incoming) {
+            Some(victim) => self.sketch.estimate(incoming) > self.sketch.estimate(victim),
+            None => true,
+        }
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for AdmissionFilter<C>
+where
+    K: ApproxComparable + Hash,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        default_tolerance, len, candidates, key_dim, nearest, next_victim, pin, unpin, entry_info,
+        entry_infos, iter, drain, retain, compact, recent_hit_rate,
+    );
+
+    fn find(&mut self, target: &K) -> Option<V> {
+        self.sketch.increment(target);
+        self.inner.find(target)
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        self.sketch.increment(target);
+        self.inner.find_k(target, k)
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        self.sketch.increment(&key);
+        if self.admits(&key) {
+            self.inner
+                .insert_with_priority(key, value, tolerance, priority)
+        } else {
+            vec![(key, value, tolerance)]
+        }
+    }
+
+    fn maintain(&mut self) {
+        self.sketch.age_if_due();
+        self.inner.maintain();
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.inner.memory_bytes() + self.sketch.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LruCache};
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_admission_rejects_rare_newcomer() {
+        let mut cache =
+            AdmissionFilter::new(LruCache::new(2).unwrap(), CountMinSketch::new(256, 4));
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert(2, 2, TEST_TOLERANCE); // Both admitted, the cache was not full
+        cache.find(&1);
+        cache.find(&2);
+        cache.insert(3, 3, TEST_TOLERANCE); // Would evict key 1, which is more popular
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.find(&3), None);
+        assert_eq!(cache.find(&1), Some(1));
+        assert_eq!(cache.find(&2), Some(2));
+    }
+
+    #[test]
+    fn test_admission_accepts_popular_newcomer() {
+        let mut cache =
+            AdmissionFilter::new(FifoCache::new(1).unwrap(), CountMinSketch::new(256, 4));
+        cache.insert(1, 1, TEST_TOLERANCE);
+        for _ in 0..3 {
+            cache.find(&2); // Repeated misses make key 2 popular
+        }
+        assert!(cache.admits(&2));
+        cache.insert(2, 2, TEST_TOLERANCE);
+        assert_eq!(cache.find(&2), Some(2));
+        assert_eq!(cache.find(&1), None);
+    }
+}
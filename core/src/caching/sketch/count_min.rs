@@ -0,0 +1,172 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::caching::HeapSize;
+
+/// A count-min sketch estimating how often items were seen, in constant memory.
+///
+/// Estimates never under-count, and over-count by at most `2N / width` with
+/// probability `1 - 2^-depth`, where `N` is the number of recorded items.
+/// To keep the estimate focused on recent traffic, every counter is halved
+/// once `sample_size` items have been recorded since the last halving,
+/// as in TinyLFU.
+#[derive(Clone, Debug)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u32>,
+    additions: u64,
+    sample_size: u64,
+    deferred_aging: bool,
+}
+
+impl CountMinSketch {
+    /// Sketch with `depth` rows of `width` counters, aged every `10 * width` additions.
+    pub fn new(width: usize, depth: usize) -> Self {
+        Self::with_sample_size(width, depth, 10 * width as u64)
+    }
+
+    /// Sketch aged every `sample_size` additions. A `sample_size` of zero disables aging.
+    pub fn with_sample_size(width: usize, depth: usize, sample_size: u64) -> Self {
+        assert!(width > 0 && depth > 0);
+        Self {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+            additions: 0,
+            sample_size,
+            deferred_aging: false,
+        }
+    }
+
+    /// Records one occurrence of `item`.
+    pub fn increment<T: Hash + ?Sized>(&mut self, item: &T) {
+        let (h1, h2) = Self::hash_pair(item);
+        for row in 0..self.depth {
+            let idx = self.index(row, h1, h2);
+            self.counters[idx] = self.counters[idx].saturating_add(1);
+        }
+        self.additions += 1;
+        if !self.deferred_aging {
+            self.age_if_due();
+        }
+    }
+
+    /// Leaves aging to [`age_if_due`](Self::age_if_due), e.g. from a maintenance thread,
+    /// instead of halving every counter in the middle of an `increment`.
+    pub fn defer_aging(mut self) -> Self {
+        self.deferred_aging = true;
+        self
+    }
+
+    /// Halves every counter if `sample_size` items were recorded since the last halving.
+    /// Returns whether it did.
+    pub fn age_if_due(&mut self) -> bool {
+        let due = self.sample_size > 0 && self.additions >= self.sample_size;
+        if due {
+            self.halve();
+        }
+        due
+    }
+
+    /// Estimated number of occurrences of `item` (an upper bound, up to aging).
+    pub fn estimate<T: Hash + ?Sized>(&self, item: &T) -> u32 {
+        let (h1, h2) = Self::hash_pair(item);
+        (0..self.depth)
+            .map(|row| self.counters[self.index(row, h1, h2)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Halves every counter, so that old occurrences weigh less than recent ones.
+    pub fn halve(&mut self) {
+        for counter in self.counters.iter_mut() {
+            *counter >>= 1;
+        }
+        self.additions /= 2;
+    }
+
+    pub fn clear(&mut self) {
+        self.counters.fill(0);
+        self.additions = 0;
+    }
+
+    /// Two independent hashes, combined as `h1 + row * h2` to index each row.
+    fn hash_pair<T: Hash + ?Sized>(item: &T) -> (u64, u64) {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let h = hasher.finish();
+        (h, h.rotate_left(32) | 1)
+    }
+
+    fn index(&self, row: usize, h1: u64, h2: u64) -> usize {
+        let col = h1.wrapping_add((row as u64).wrapping_mul(h2)) % self.width as u64;
+        row * self.width + col as usize
+    }
+}
+
+impl HeapSize for CountMinSketch {
+    fn heap_bytes(&self) -> usize {
+        self.counters.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_never_undercounts() {
+        let mut sketch = CountMinSketch::with_sample_size(64, 4, 0);
+        for i in 0..200u32 {
+            for _ in 0..(i % 7) {
+                sketch.increment(&i);
+            }
+        }
+        for i in 0..200u32 {
+            assert!(sketch.estimate(&i) >= i % 7);
+        }
+    }
+
+    #[test]
+    fn test_estimate_is_exact_when_sparse() {
+        let mut sketch = CountMinSketch::with_sample_size(1024, 4, 0);
+        for _ in 0..5 {
+            sketch.increment(&[1.0f32.to_bits(), 2.0f32.to_bits()]);
+        }
+        sketch.increment(&[3.0f32.to_bits(), 4.0f32.to_bits()]);
+        assert_eq!(sketch.estimate(&"never seen"), 0);
+        assert_eq!(sketch.estimate(&[1.0f32.to_bits(), 2.0f32.to_bits()]), 5);
+        assert_eq!(sketch.estimate(&[3.0f32.to_bits(), 4.0f32.to_bits()]), 1);
+    }
+
+    #[test]
+    fn test_aging_halves_counters() {
+        let mut sketch = CountMinSketch::with_sample_size(1024, 4, 8);
+        for _ in 0..7 {
+            sketch.increment(&1u8);
+        }
+        assert_eq!(sketch.estimate(&1u8), 7);
+        sketch.increment(&1u8); // 8th addition triggers the halving
+        assert_eq!(sketch.estimate(&1u8), 4);
+    }
+
+    #[test]
+    fn test_deferred_aging() {
+        let mut sketch = CountMinSketch::with_sample_size(1024, 4, 8).defer_aging();
+        for _ in 0..10 {
+            sketch.increment(&1u8);
+        }
+        assert_eq!(sketch.estimate(&1u8), 10);
+        assert!(sketch.age_if_due());
+        assert_eq!(sketch.estimate(&1u8), 5);
+        assert!(!sketch.age_if_due());
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut sketch = CountMinSketch::new(16, 2);
+        sketch.increment(&42u64);
+        sketch.clear();
+        assert_eq!(sketch.estimate(&42u64), 0);
+    }
+}
@@ -0,0 +1,5 @@
+mod admission;
+mod count_min;
+
+pub use admission::AdmissionFilter;
+pub use count_min::CountMinSketch;
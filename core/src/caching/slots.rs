@@ -0,0 +1,182 @@
+//! Lookups and pinning shared by the caches that keep their entries in a `Vec` of
+//! slots and scan all of them: LFU, LRU-K, Clock, GDSF and W-TinyLFU. Each policy
+//! only decides what a reference to a slot does and which slot to evict.
+
+use std::cmp::Ordering;
+
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::scan::{self, MaybeSync};
+use crate::caching::HitRateTracker;
+use crate::numerics::ApproxComparable;
+use crate::ProximityError;
+
+/// An entry of a cache of slots.
+pub(crate) trait Slot<K> {
+    fn key(&self) -> &K;
+    fn tolerance(&self) -> Tolerance;
+    fn pinned(&self) -> bool;
+    fn pinned_mut(&mut self) -> &mut bool;
+}
+
+/// Panics if `target` is not of dimension `dim`, rather than comparing vectors of
+/// different lengths.
+pub(crate) fn check_dim<K: ApproxComparable>(dim: Option<usize>, target: &K) {
+    if let (Some(expected), Some(found)) = (dim, target.dimension()) {
+        if expected != found {
+            panic!("{}", ProximityError::DimensionMismatch { expected, found });
+        }
+    }
+}
+
+/// Index of the closest slot that matches `target` within its own tolerance, along
+/// with its distance to `target`.
+pub(crate) fn best_match<K, S>(slots: &[S], dim: Option<usize>, target: &K) -> Option<(usize, f32)>
+where
+    K: ApproxComparable + MaybeSync,
+    S: Slot<K> + MaybeSync,
+{
+    check_dim(dim, target);
+    scan::closest(slots, |slot| {
+        slot.key()
+            .roughly_matches(target, slot.tolerance())
+            .then(|| target.fuzziness(slot.key()))
+    })
+    .map(|(idx, _, dist)| (idx, dist))
+}
+
+/// Like [`best_match`], for a lookup: traces the scan and records whether it hit. A
+/// traced miss reports the distance to the nearest key and that key's tolerance.
+pub(crate) fn find<K, S>(
+    slots: &[S],
+    dim: Option<usize>,
+    target: &K,
+    hit_rate: &mut HitRateTracker,
+) -> Option<usize>
+where
+    K: ApproxComparable + MaybeSync,
+    S: Slot<K> + MaybeSync,
+{
+    let candidate = best_match(slots, dim, target);
+    trace_event!(
+        candidates = slots.len(),
+        hit = candidate.is_some(),
+        best_fuzziness = ?candidate
+            .map(|(_, dist)| dist)
+            .or_else(|| nearest(slots, dim, target).map(|(dist, _)| dist)),
+        tolerance = ?candidate
+            .is_none()
+            .then(|| nearest(slots, dim, target).map(|(_, tol)| tol))
+            .flatten(),
+        "scanned"
+    );
+    hit_rate.record(candidate.is_some());
+    candidate.map(|(idx, _)| idx)
+}
+
+/// Indices of up to `k` slots that match `target`, closest first, along with their
+/// distance to it. Traces the scan and records whether it hit.
+pub(crate) fn find_k<K, S>(
+    slots: &[S],
+    dim: Option<usize>,
+    target: &K,
+    k: usize,
+    hit_rate: &mut HitRateTracker,
+) -> Vec<(usize, f32)>
+where
+    K: ApproxComparable + MaybeSync,
+    S: Slot<K> + MaybeSync,
+{
+    check_dim(dim, target);
+    let matches: Vec<(usize, f32)> = scan::k_closest(slots, k, |slot| {
+        slot.key()
+            .roughly_matches(target, slot.tolerance())
+            .then(|| target.fuzziness(slot.key()))
+    })
+    .into_iter()
+    .map(|(idx, _, dist)| (idx, dist))
+    .collect();
+    trace_event!(
+        candidates = slots.len(),
+        matches = matches.len(),
+        best_fuzziness = ?matches
+            .first()
+            .map(|(_, dist)| *dist)
+            .or_else(|| nearest(slots, dim, target).map(|(dist, _)| dist)),
+        tolerance = ?matches
+            .is_empty()
+            .then(|| nearest(slots, dim, target).map(|(_, tol)| tol))
+            .flatten(),
+        "scanned"
+    );
+    hit_rate.record(!matches.is_empty());
+    matches
+}
+
+/// Distance from `target` to the closest key, along with that slot's tolerance.
+pub(crate) fn nearest<K, S>(slots: &[S], dim: Option<usize>, target: &K) -> Option<(f32, Tolerance)>
+where
+    K: ApproxComparable + MaybeSync,
+    S: Slot<K> + MaybeSync,
+{
+    check_dim(dim, target);
+    scan::closest(slots, |slot| Some(target.fuzziness(slot.key())))
+        .map(|(_, slot, dist)| (dist, slot.tolerance()))
+}
+
+/// Pins or unpins the slot that `target` matches, and returns its index along with
+/// whether that changed it, or `None` if no slot matches.
+pub(crate) fn set_pinned<K, S>(
+    slots: &mut [S],
+    dim: Option<usize>,
+    target: &K,
+    pinned: bool,
+) -> Option<(usize, bool)>
+where
+    K: ApproxComparable + MaybeSync,
+    S: Slot<K> + MaybeSync,
+{
+    let (idx, _) = best_match(slots, dim, target)?;
+    let was = std::mem::replace(slots[idx].pinned_mut(), pinned);
+    Some((idx, was != pinned))
+}
+
+/// Indices of up to `k` unpinned slots, lowest first by `cmp`, in a single pass over
+/// the slots. This is what evicting the lowest slot `k` times picks, for policies whose
+/// order of the remaining slots does not change when one is evicted.
+pub(crate) fn lowest<K, S>(
+    slots: &[S],
+    k: usize,
+    mut cmp: impl FnMut(&S, &S) -> Ordering,
+) -> Vec<usize>
+where
+    S: Slot<K>,
+{
+    let mut by = |a: &usize, b: &usize| cmp(&slots[*a], &slots[*b]);
+    let mut victims: Vec<usize> = (0..slots.len())
+        .filter(|&idx| !slots[idx].pinned())
+        .collect();
+    if k < victims.len() {
+        victims.select_nth_unstable_by(k, &mut by);
+        victims.truncate(k);
+    }
+    victims.sort_by(by);
+    victims
+}
+
+/// Removes the slots at the distinct indices `victims`, and returns them in that order.
+pub(crate) fn remove_all<S>(slots: &mut Vec<S>, victims: &[usize]) -> Vec<S> {
+    let mut by_index: Vec<(usize, usize)> = victims
+        .iter()
+        .enumerate()
+        .map(|(rank, &idx)| (idx, rank))
+        .collect();
+    // the last slot, which swap_remove moves, is never a victim still to remove
+    by_index.sort_unstable_by(|a, b| b.cmp(a));
+    let mut removed: Vec<Option<S>> = std::iter::repeat_with(|| None)
+        .take(victims.len())
+        .collect();
+    for (idx, rank) in by_index {
+        removed[rank] = Some(slots.swap_remove(idx));
+    }
+    removed.into_iter().flatten().collect()
+}
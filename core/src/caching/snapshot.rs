@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::scan::{self, MaybeSync};
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+struct ViewEntry<K, V> {
+    key: K,
+    tol: Tolerance,
+    value: V,
+}
+
+/// Immutable copy of the entries of a cache, taken by
+/// [`snapshot`](crate::caching::ApproximateCache::snapshot).
+///
+/// Lookups take `&self` and never lock, so a view can be queried from many threads
+/// while the cache it was taken from keeps changing. Cloning a view is cheap, as clones
+/// share the same entries. Lookups scan every entry like an
+/// [`UnboundedLinearCache`](crate::caching::UnboundedLinearCache), so a view of an
+/// LSH cache may match keys across buckets, and they do not count as hits in the cache.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, FifoCache};
+///
+/// let mut cache = FifoCache::new(4).unwrap();
+/// cache.insert(10 as i16, "Value 1", 2.0);
+/// let view = cache.snapshot();
+/// cache.insert(20, "Value 2", 2.0);
+///
+/// std::thread::scope(|s| {
+///     s.spawn(|| assert_eq!(view.find(&11), Some("Value 1")));
+///     s.spawn(|| assert_eq!(view.find(&20), None));
+/// });
+/// ```
+pub struct CacheView<K, V> {
+    entries: Arc<[ViewEntry<K, V>]>,
+    dim: Option<usize>,
+}
+
+impl<K, V> Clone for CacheView<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: Arc::clone(&self.entries),
+            dim: self.dim,
+        }
+    }
+}
+
+impl<K, V> CacheView<K, V> {
+    pub(crate) fn new(
+        entries: impl IntoIterator<Item = (K, V, Tolerance)>,
+        dim: Option<usize>,
+    ) -> Self {
+        Self {
+            entries: entries
+                .into_iter()
+                .map(|(key, value, tol)| ViewEntry { key, tol, value })
+                .collect(),
+            dim,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Dimension of the keys of the cache the view was taken from, if it constrained it.
+    pub fn key_dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V, Tolerance)> {
+        self.entries.iter().map(|e| (&e.key, &e.value, e.tol))
+    }
+}
+
+impl<K, V> CacheView<K, V>
+where
+    K: ApproxComparable + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    /// Checks that `key` has the dimension of the keys of the view.
+    pub fn check_dim(&self, key: &K) -> Result<()> {
+        match (self.dim, key.dimension()) {
+            (Some(expected), Some(found)) if expected != found => {
+                Err(ProximityError::DimensionMismatch { expected, found })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn find(&self, target: &K) -> Option<V> {
+        scan::closest(&self.entries[..], |entry| {
+            entry
+                .key
+                .roughly_matches(target, entry.tol)
+                .then(|| target.fuzziness(&entry.key))
+        })
+        .map(|(_, entry, _)| entry.value.clone())
+    }
+
+    /// Like [`find`](Self::find), failing if `target` does not have the dimension of the
+    /// keys of the view.
+    pub fn try_find(&self, target: &K) -> Result<Option<V>> {
+        self.check_dim(target)?;
+        Ok(self.find(target))
+    }
+
+    /// Up to `k` matching values along with their distance to `target`, closest first.
+    pub fn find_k(&self, target: &K, k: usize) -> Vec<(V, f32)> {
+        scan::k_closest(&self.entries[..], k, |entry| {
+            entry
+                .key
+                .roughly_matches(target, entry.tol)
+                .then(|| target.fuzziness(&entry.key))
+        })
+        .into_iter()
+        .map(|(_, entry, dist)| (entry.value.clone(), dist))
+        .collect()
+    }
+
+    /// Distance from `target` to the closest key, along with that entry's tolerance.
+    pub fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        scan::closest(&self.entries[..], |entry| {
+            Some(target.fuzziness(&entry.key))
+        })
+        .map(|(_, entry, dist)| (dist, entry.tol))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::caching::{ApproximateCache, LruCache, LshFifoCache, ShardedCache};
+    use crate::numerics::SIMD_LANECOUNT;
+    use crate::simulation::SimKey;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_snapshot_is_isolated_from_writes() {
+        let mut cache = LruCache::new(2).unwrap();
+        cache.insert(1, 10, TEST_TOLERANCE);
+        cache.insert(2, 20, TEST_TOLERANCE);
+        let view = cache.snapshot();
+        cache.insert(3, 30, TEST_TOLERANCE);
+
+        assert_eq!(view.len(), 2);
+        assert_eq!(view.find(&1), Some(10));
+        assert_eq!(view.find(&3), None);
+        assert_eq!(cache.find(&1), None);
+        assert_eq!(view.find_k(&2, 5), vec![(20, 0.0)]);
+        assert_eq!(view.nearest(&4), Some((2.0, TEST_TOLERANCE)));
+    }
+
+    #[test]
+    fn test_snapshot_queried_from_many_threads() {
+        let mut cache = LshFifoCache::new(2, SIMD_LANECOUNT, 64, Some(1)).unwrap();
+        for i in 0..32 {
+            cache.insert(
+                SimKey(vec![i as f32 + 1.0; SIMD_LANECOUNT]),
+                i,
+                TEST_TOLERANCE,
+            );
+        }
+        let view = Arc::new(cache.snapshot());
+        assert_eq!(view.key_dim(), Some(SIMD_LANECOUNT));
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let view = Arc::clone(&view);
+                thread::spawn(move || {
+                    (t..32).step_by(4).all(|i| {
+                        view.find(&SimKey(vec![i as f32 + 1.0; SIMD_LANECOUNT])) == Some(i)
+                    })
+                })
+            })
+            .collect();
+        assert!(handles.into_iter().all(|h| h.join().unwrap()));
+    }
+
+    #[test]
+    fn test_sharded_snapshot() {
+        let shards = (0..2).map(|_| LruCache::new(8).unwrap()).collect();
+        let cache = ShardedCache::new(shards, SIMD_LANECOUNT, Some(5)).unwrap();
+        cache.insert(SimKey(vec![1.0; SIMD_LANECOUNT]), 1, TEST_TOLERANCE);
+        let view = cache.snapshot();
+        cache.insert(SimKey(vec![-1.0; SIMD_LANECOUNT]), 2, TEST_TOLERANCE);
+        assert_eq!(view.len(), 1);
+        assert_eq!(view.find(&SimKey(vec![1.0; SIMD_LANECOUNT])), Some(1));
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::{entry_bytes, slots_bytes};
+use crate::caching::HeapSize;
+use crate::clock::Instant;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Outcome of a [`SoftValueCache::lookup`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum SoftLookup<V> {
+    /// A stored value matched the query.
+    Hit(V),
+    /// No stored value matched, but the key of one that was dropped under memory pressure
+    /// did, this long ago.
+    Dropped(Duration),
+    /// Nothing is known about the query.
+    Miss,
+}
+
+struct Tombstone<K> {
+    key: K,
+    tol: Tolerance,
+    dropped_at: Instant,
+}
+
+/// Wraps a cache so that, past a memory watermark, it drops values but keeps their keys.
+///
+/// Entries are measured by [`HeapSize`] when inserted. While they take more than
+/// `watermark` bytes, the cache's policy picks entries to drop, and their keys and
+/// tolerances are kept as tombstones, so that nearby queries come back as
+/// [`SoftLookup::Dropped`] instead of [`SoftLookup::Miss`] and can take a cheaper
+/// recomputation path, e.g. from a coarser model. At most `max_tombstones` tombstones
+/// are kept, the oldest being forgotten first, and inserting a key that a tombstone
+/// matches lays that tombstone to rest.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, FifoCache, SoftLookup, SoftValueCache};
+///
+/// let mut cache = SoftValueCache::new(FifoCache::new(16).unwrap(), 200, 16).unwrap();
+/// cache.insert(10 as i16, vec![0u8; 64], 2.0);
+/// cache.insert(20, vec![0u8; 64], 2.0);
+/// cache.insert(30, vec![0u8; 64], 2.0);
+///
+/// assert!(matches!(cache.lookup(&11), SoftLookup::Dropped(_)));
+/// assert_eq!(cache.lookup(&21), SoftLookup::Hit(vec![0u8; 64]));
+/// assert_eq!(cache.lookup(&50), SoftLookup::Miss);
+/// ```
+pub struct SoftValueCache<K, C> {
+    inner: C,
+    watermark: usize,
+    bytes: usize,
+    max_tombstones: usize,
+    tombstones: VecDeque<Tombstone<K>>,
+}
+
+impl<K, C> SoftValueCache<K, C>
+where
+    K: ApproxComparable,
+{
+    /// Drops values past `watermark` bytes of an empty cache.
+    pub fn new(inner: C, watermark: usize, max_tombstones: usize) -> Result<Self> {
+        if watermark == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "memory watermark must be positive".into(),
+            ));
+        }
+        if max_tombstones == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "tombstone capacity must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            inner,
+            watermark,
+            bytes: 0,
+            max_tombstones,
+            tombstones: VecDeque::new(),
+        })
+    }
+
+    /// Estimated bytes of the stored entries.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn watermark(&self) -> usize {
+        self.watermark
+    }
+
+    /// Number of keys whose value was dropped.
+    pub fn tombstone_len(&self) -> usize {
+        self.tombstones.len()
+    }
+
+    /// The wrapped cache.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// When the value of a key matching `target` was dropped, if one was.
+    pub fn dropped_since(&self, target: &K) -> Option<Duration> {
+        self.tombstones
+            .iter()
+            .rev()
+            .find(|tombstone| tombstone.key.roughly_matches(target, tombstone.tol))
+            .map(|tombstone| tombstone.dropped_at.elapsed())
+    }
+
+    /// Looks `target` up among stored values first, then among dropped ones.
+    pub fn lookup<V>(&mut self, target: &K) -> SoftLookup<V>
+    where
+        C: ApproximateCache<K, V>,
+    {
+        match self.inner.find(target) {
+            Some(value) => SoftLookup::Hit(value),
+            None => self
+                .dropped_since(target)
+                .map_or(SoftLookup::Miss, SoftLookup::Dropped),
+        }
+    }
+
+    /// Changes the watermark, dropping values until the cache fits under it, and returns
+    /// the dropped entries.
+    pub fn set_watermark<V>(&mut self, watermark: usize) -> Result<Vec<(K, V, Tolerance)>>
+    where
+        K: HeapSize + Clone,
+        V: HeapSize,
+        C: DefaultApproximateCache<K, V>,
+    {
+        if watermark == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "memory watermark must be positive".into(),
+            ));
+        }
+        self.watermark = watermark;
+        let mut dropped = Vec::new();
+        self.relieve(&mut dropped);
+        Ok(dropped)
+    }
+
+    /// Drops values while over the watermark. Pinned entries are never dropped.
+    fn relieve<V>(&mut self, dropped: &mut Vec<(K, V, Tolerance)>)
+    where
+        K: HeapSize + Clone,
+        V: HeapSize,
+        C: DefaultApproximateCache<K, V>,
+    {
+        while self.bytes > self.watermark {
+            let Some(entry) = self.inner.evict() else {
+                break;
+            };
+            self.bytes = self.bytes.saturating_sub(entry_bytes(&entry.0, &entry.1));
+            self.tombstones.push_back(Tombstone {
+                key: entry.0.clone(),
+                tol: entry.2,
+                dropped_at: Instant::now(),
+            });
+            if self.tombstones.len() > self.max_tombstones {
+                self.tombstones.pop_front();
+            }
+            dropped.push(entry);
+        }
+    }
+}
+
+impl<K, V, C> ApproximateCache<K, V> for SoftValueCache<K, C>
+where
+    K: ApproxComparable + HeapSize + Clone,
+    V: HeapSize,
+    C: DefaultApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        find, find_k, default_tolerance, len, candidates, key_dim, nearest, next_victim, pin,
+        unpin, entry_info, entry_infos, iter, recent_hit_rate,
+    );
+
+    /// Entries evicted by the cache itself, e.g. when full, leave no tombstone. Those
+    /// dropped past the watermark come after them.
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        self.tombstones
+            .retain(|tombstone| !tombstone.key.roughly_matches(&key, tombstone.tol));
+        // a newcomer that is not admitted comes back among the evicted entries
+        self.bytes += entry_bytes(&key, &value);
+        let mut evicted = self
+            .inner
+            .insert_with_priority(key, value, tolerance, priority);
+        for (key, value, _) in &evicted {
+            self.bytes = self.bytes.saturating_sub(entry_bytes(key, value));
+        }
+        self.relieve(&mut evicted);
+        evicted
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        self.bytes = 0;
+        self.tombstones.clear();
+        self.inner.drain()
+    }
+
+    /// Also measures every entry again, and drops values if that puts the cache over
+    /// the watermark.
+    fn maintain(&mut self) {
+        self.inner.maintain();
+        self.bytes = self
+            .inner
+            .iter()
+            .map(|(key, value, _)| entry_bytes(key, &value))
+            .sum();
+        self.relieve(&mut Vec::new());
+    }
+
+    fn compact(&mut self) {
+        self.tombstones.shrink_to_fit();
+        self.inner.compact();
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.inner.memory_bytes()
+            + slots_bytes::<Tombstone<K>>(self.tombstones.capacity())
+            + self
+                .tombstones
+                .iter()
+                .map(|tombstone| tombstone.key.heap_bytes())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::FifoCache;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_values_dropped_past_watermark() {
+        let entry = entry_bytes(&0i16, &vec![0u8; 100]);
+        let mut cache = SoftValueCache::new(FifoCache::new(16).unwrap(), 2 * entry, 2).unwrap();
+        for i in 0..2 {
+            assert!(cache
+                .insert_evicting(i, vec![0u8; 100], TEST_TOLERANCE)
+                .is_empty());
+        }
+        let dropped = cache.insert_evicting(2, vec![0u8; 100], TEST_TOLERANCE);
+        assert_eq!(dropped.len(), 1);
+        assert!(matches!(cache.lookup(&0), SoftLookup::Dropped(_)));
+        assert_eq!(cache.lookup(&1), SoftLookup::Hit(vec![0u8; 100]));
+        assert_eq!(cache.lookup(&5), SoftLookup::<Vec<u8>>::Miss);
+        assert_eq!(cache.find(&0), None);
+
+        // the oldest tombstones are forgotten, and re-inserted keys have none
+        cache.set_watermark(entry).unwrap();
+        cache.insert(3, vec![0u8; 100], TEST_TOLERANCE);
+        assert_eq!(cache.tombstone_len(), 2);
+        assert!(cache.dropped_since(&0).is_none());
+        cache.insert(2, vec![0u8; 100], TEST_TOLERANCE);
+        assert!(cache.dropped_since(&2).is_none());
+        assert!(cache.dropped_since(&3).is_some());
+        assert!(cache.set_watermark::<Vec<u8>>(0).is_err());
+    }
+
+    #[test]
+    fn test_capacity_evictions_leave_no_tombstone() {
+        let mut cache = SoftValueCache::new(FifoCache::new(1).unwrap(), 1 << 20, 4).unwrap();
+        cache.insert(1i16, 1i16, TEST_TOLERANCE);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        assert_eq!(cache.lookup(&1), SoftLookup::Miss);
+        assert_eq!(cache.tombstone_len(), 0);
+        assert_eq!(cache.bytes(), entry_bytes(&2i16, &2i16));
+    }
+}
@@ -0,0 +1,186 @@
+/// Number of lookups that dominate the estimate of a freshly created [`HitRateTracker`].
+pub const DEFAULT_HIT_RATE_WINDOW: usize = 1000;
+
+/// Exponentially-decayed estimate of the hit rate over roughly the last `window` lookups.
+///
+/// Unlike lifetime counters, this reacts quickly when the hit rate collapses
+/// after a distribution shift. Each lookup has weight `(1 - 1/window)^age`,
+/// and the estimate is normalized by the total weight so it is unbiased
+/// even before `window` lookups have been observed.
+#[derive(Clone, Debug)]
+pub struct HitRateTracker {
+    decay: f64,
+    weighted_hits: f64,
+    total_weight: f64,
+}
+
+impl HitRateTracker {
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0);
+        Self {
+            decay: 1.0 - 1.0 / window as f64,
+            weighted_hits: 0.0,
+            total_weight: 0.0,
+        }
+    }
+
+    pub fn record(&mut self, hit: bool) {
+        self.weighted_hits = self.decay * self.weighted_hits + if hit { 1.0 } else { 0.0 };
+        self.total_weight = self.decay * self.total_weight + 1.0;
+    }
+
+    /// Current estimate in `[0, 1]`, or `0.0` if nothing was recorded yet.
+    pub fn hit_rate(&self) -> f32 {
+        if self.total_weight == 0.0 {
+            return 0.0;
+        }
+        (self.weighted_hits / self.total_weight) as f32
+    }
+}
+
+impl Default for HitRateTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_HIT_RATE_WINDOW)
+    }
+}
+
+/// Scan lengths below this are counted exactly, longer ones within an eighth.
+const EXACT_SCANS: usize = 16;
+
+/// Distribution of the number of stored keys that lookups compared against, to tell
+/// whether an LSH cache routes lookups to small buckets or degenerated into scanning.
+///
+/// Lengths are counted in buckets that split every power of two into eight, so that
+/// percentiles are exact below 16 and within 12.5% above, in constant memory.
+#[derive(Clone, Debug, Default)]
+pub struct ScanStats {
+    buckets: Vec<u64>,
+    lookups: u64,
+    total: u64,
+    max: usize,
+}
+
+impl ScanStats {
+    pub fn record(&mut self, scanned: usize) {
+        let bucket = scan_bucket(scanned);
+        if bucket >= self.buckets.len() {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+        self.lookups += 1;
+        self.total += scanned as u64;
+        self.max = self.max.max(scanned);
+    }
+
+    pub fn lookups(&self) -> u64 {
+        self.lookups
+    }
+
+    /// Average number of keys compared per lookup, or `0.0` if nothing was recorded yet.
+    pub fn mean(&self) -> f32 {
+        if self.lookups == 0 {
+            return 0.0;
+        }
+        self.total as f32 / self.lookups as f32
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Number of keys that a share `q` of the lookups compared against at most, e.g. the
+    /// median for `0.5`, rounded up to the end of its bucket. `0` if nothing was
+    /// recorded yet.
+    pub fn percentile(&self, q: f32) -> usize {
+        let rank = ((q.clamp(0.0, 1.0) as f64 * self.lookups as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return scan_bucket_end(bucket).min(self.max);
+            }
+        }
+        0
+    }
+}
+
+fn scan_bucket(scanned: usize) -> usize {
+    if scanned < EXACT_SCANS {
+        return scanned;
+    }
+    let log = (usize::BITS - 1 - scanned.leading_zeros()) as usize;
+    EXACT_SCANS + (log - 4) * 8 + ((scanned >> (log - 3)) & 7)
+}
+
+/// Longest scan counted in `bucket`.
+fn scan_bucket_end(bucket: usize) -> usize {
+    if bucket < EXACT_SCANS {
+        return bucket;
+    }
+    let (log, sub) = ((bucket - EXACT_SCANS) / 8 + 4, (bucket - EXACT_SCANS) % 8);
+    let width = 1usize << (log - 3);
+    (8 + sub) * width + (width - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_tracker() {
+        let tracker = HitRateTracker::default();
+        assert_eq!(tracker.hit_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_constant_rate_is_unbiased() {
+        let mut tracker = HitRateTracker::new(100);
+        for i in 0..10 {
+            tracker.record(i % 2 == 0);
+        }
+        assert!((tracker.hit_rate() - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_reacts_to_shift() {
+        let mut tracker = HitRateTracker::new(50);
+        for _ in 0..1000 {
+            tracker.record(true);
+        }
+        assert!(tracker.hit_rate() > 0.99);
+        for _ in 0..200 {
+            tracker.record(false);
+        }
+        assert!(tracker.hit_rate() < 0.05);
+    }
+
+    #[test]
+    fn test_scan_percentiles() {
+        let mut stats = ScanStats::default();
+        assert_eq!((stats.mean(), stats.percentile(0.5)), (0.0, 0));
+        for scanned in 1..=10 {
+            stats.record(scanned);
+        }
+        assert_eq!(stats.mean(), 5.5);
+        assert_eq!(stats.percentile(0.5), 5);
+        assert_eq!(stats.percentile(0.9), 9);
+        assert_eq!(stats.percentile(1.0), 10);
+
+        stats.record(1000);
+        assert_eq!(stats.max(), 1000);
+        assert_eq!(stats.percentile(1.0), 1000);
+        for scanned in [16, 100, 1000, 123_456] {
+            let end = scan_bucket_end(scan_bucket(scanned));
+            assert!(
+                end >= scanned && end - scanned <= scanned / 8,
+                "{scanned}: {end}"
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_window() {
+        let _tracker = HitRateTracker::new(0);
+    }
+}
@@ -0,0 +1,2 @@
+mod w_tinylfu_cache;
+pub use w_tinylfu_cache::WTinyLfuCache;
@@ -0,0 +1,576 @@
+use std::hash::Hash;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::DefaultApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::memory::slots_bytes;
+use crate::caching::scan::MaybeSync;
+use crate::caching::sketch::CountMinSketch;
+use crate::caching::slots::{self, Slot};
+use crate::caching::EntryInfo;
+use crate::caching::HeapSize;
+use crate::caching::HitRateTracker;
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Share of the capacity given to the admission window, in percent.
+const WINDOW_PERCENT: usize = 1;
+/// Share of the main space given to the protected segment, in percent.
+const PROTECTED_PERCENT: usize = 80;
+const SKETCH_DEPTH: usize = 4;
+/// Keeps estimates sharp in small caches, e.g. LSH buckets.
+const MIN_SKETCH_WIDTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Segment {
+    /// Main space, entries referenced once since they got there.
+    Probation,
+    /// Main space, entries referenced again while on probation.
+    Protected,
+    /// Newcomers, before they compete for the main space.
+    Window,
+}
+
+struct CacheLine<K, V> {
+    key: K,
+    tol: Tolerance,
+    value: V,
+    pinned: bool,
+    info: EntryInfo,
+    segment: Segment,
+    /// Logical time of the last reference, the insert included.
+    last: u64,
+}
+
+impl<K, V> Slot<K> for CacheLine<K, V> {
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn tolerance(&self) -> Tolerance {
+        self.tol
+    }
+
+    fn pinned(&self) -> bool {
+        self.pinned
+    }
+
+    fn pinned_mut(&mut self) -> &mut bool {
+        &mut self.pinned
+    }
+}
+
+/// `WTinyLfuCache` is a bounded cache with approximate key matching support and
+/// W-TinyLFU eviction, which adapts to both recency- and frequency-biased workloads.
+///
+/// Newcomers enter a small LRU admission window (1% of the capacity). The entry
+/// pushed out of the window then competes for the main space, a segmented LRU, against
+/// the main space's own victim: the one a [`CountMinSketch`] estimates to be queried
+/// less often is evicted. Within the main space, entries referenced again are protected
+/// (up to 80% of it) from entries on probation, which are evicted first.
+///
+/// Like [`AdmissionFilter`](crate::caching::sketch::AdmissionFilter), frequencies are
+/// tracked on exact key bits. Finding a victim scans every entry, as lookups do.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, WTinyLfuCache};
+///
+/// let mut cache = WTinyLfuCache::new(2).unwrap();
+/// const TEST_TOL: f32 = 2.0;
+///
+/// cache.insert(10 as i16, "Value 1", TEST_TOL);
+/// cache.find(&10);
+/// cache.insert(20, "Value 2", TEST_TOL);
+/// cache.insert(30, "Value 3", TEST_TOL); // Key 20 loses against key 10, queried before
+///
+/// assert_eq!(cache.find(&11), Some("Value 1"));
+/// assert!(cache.find(&20).is_none());
+/// ```
+pub struct WTinyLfuCache<K, V> {
+    max_capacity: usize,
+    window_capacity: usize,
+    protected_capacity: usize,
+    /// Logical clock, ticking on every reference.
+    clock: u64,
+    items: Vec<CacheLine<K, V>>,
+    sketch: CountMinSketch,
+    hit_rate: HitRateTracker,
+    /// Dimension of the first vector key inserted.
+    dim: Option<usize>,
+}
+
+impl<K, V> ApproximateCache<K, V> for WTinyLfuCache<K, V>
+where
+    K: ApproxComparable + Hash + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn find(&mut self, target: &K) -> Option<V> {
+        trace_span!("find", cache = "w-tinylfu");
+        self.sketch.increment(target);
+        let idx = slots::find(&self.items, self.dim, target, &mut self.hit_rate)?;
+        self.reference(idx);
+        Some(self.items[idx].value.clone())
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        trace_span!("find_k", cache = "w-tinylfu", k);
+        self.sketch.increment(target);
+        let matches = slots::find_k(&self.items, self.dim, target, k, &mut self.hit_rate);
+        matches
+            .into_iter()
+            .map(|(idx, dist)| {
+                self.reference(idx);
+                (self.items[idx].value.clone(), dist)
+            })
+            .collect()
+    }
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        trace_span!("insert", cache = "w-tinylfu", tolerance, priority);
+        self.check_dim(&key).unwrap_or_else(|e| panic!("{e}"));
+        self.dim = self.dim.or(key.dimension());
+        self.sketch.increment(&key);
+        self.clock += 1;
+        self.items.push(CacheLine {
+            key,
+            tol: tolerance,
+            value,
+            pinned: false,
+            info: EntryInfo::with_priority(priority),
+            segment: Segment::Window,
+            last: self.clock,
+        });
+        // the entry pushed out of the window, which only competes if the cache is full
+        let mut candidate = None;
+        if self.count(Segment::Window) > self.window_capacity {
+            candidate = self.lru_of(Segment::Window, None, None);
+            if let Some(idx) = candidate {
+                self.items[idx].segment = Segment::Probation;
+            }
+        }
+        let mut evicted = Vec::new();
+        while self.items.len() > self.max_capacity {
+            let Some(loser) = self.loser(candidate) else {
+                break;
+            };
+            if candidate == Some(loser) {
+                candidate = None;
+            }
+            if candidate == Some(self.items.len() - 1) {
+                candidate = Some(loser);
+            }
+            evicted.push(self.remove(loser));
+        }
+        evicted
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn key_dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    fn nearest(&self, target: &K) -> Option<(f32, Tolerance)> {
+        slots::nearest(&self.items, self.dim, target)
+    }
+
+    fn next_victim(&self, _incoming: &K) -> Option<&K> {
+        if self.items.len() < self.max_capacity {
+            return None;
+        }
+        let candidate = if self.count(Segment::Window) >= self.window_capacity {
+            self.lru_of(Segment::Window, None, None)
+        } else {
+            None
+        };
+        self.loser(candidate).map(|idx| &self.items[idx].key)
+    }
+
+    fn pin(&mut self, target: &K) -> bool {
+        slots::set_pinned(&mut self.items, self.dim, target, true).is_some()
+    }
+
+    fn unpin(&mut self, target: &K) -> bool {
+        slots::set_pinned(&mut self.items, self.dim, target, false).is_some()
+    }
+
+    fn entry_info(&self, target: &K) -> Option<EntryInfo> {
+        let (candidate, _) = slots::best_match(&self.items, self.dim, target)?;
+        Some(self.items[candidate].info)
+    }
+
+    fn entry_infos(&self) -> Box<dyn Iterator<Item = (&K, EntryInfo)> + '_> {
+        Box::new(self.items.iter().map(|entry| (&entry.key, entry.info)))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(
+            self.items
+                .iter()
+                .map(|entry| (&entry.key, entry.value.clone(), entry.tol)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        self.items
+            .sort_by_key(|entry| (entry.info.priority, entry.segment, entry.last));
+        Box::new(
+            self.items
+                .drain(..)
+                .map(|entry| (entry.key, entry.value, entry.tol)),
+        )
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)> {
+        self.items
+            .extract_if(.., |entry| !keep(&entry.key, &entry.value))
+            .map(|entry| (entry.key, entry.value, entry.tol))
+            .collect()
+    }
+
+    fn maintain(&mut self) {
+        self.sketch.age_if_due();
+    }
+
+    fn compact(&mut self) {
+        self.items.shrink_to_fit();
+    }
+
+    fn recent_hit_rate(&self) -> f32 {
+        self.hit_rate.hit_rate()
+    }
+
+    fn memory_bytes(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let entries: usize = self
+            .items
+            .iter()
+            .map(|line| line.key.heap_bytes() + line.value.heap_bytes())
+            .sum();
+        slots_bytes::<CacheLine<K, V>>(self.items.capacity()) + entries + self.sketch.heap_bytes()
+    }
+}
+
+impl<K, V> DefaultApproximateCache<K, V> for WTinyLfuCache<K, V>
+where
+    K: ApproxComparable + Hash + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    fn from_capacity(cap: usize) -> WTinyLfuCache<K, V> {
+        WTinyLfuCache::new(cap).expect("bucket capacity is checked by the owning cache")
+    }
+
+    fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    fn set_capacity(&mut self, cap: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        WTinyLfuCache::set_capacity(self, cap)
+    }
+
+    fn count_miss(&mut self, target: &K) {
+        self.sketch.increment(target);
+    }
+
+    fn evict(&mut self) -> Option<(K, V, Tolerance)> {
+        self.evict_lowest()
+    }
+}
+
+impl<K, V> WTinyLfuCache<K, V> {
+    /// Cache whose sketch has one counter per entry and row (at least 64), and ages
+    /// every 10 references per counter, as in the W-TinyLFU paper.
+    pub fn new(max_capacity: usize) -> Result<Self> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        let (window_capacity, protected_capacity) = Self::segment_capacities(max_capacity);
+        Ok(Self {
+            max_capacity,
+            window_capacity,
+            protected_capacity,
+            clock: 0,
+            items: Vec::with_capacity(max_capacity),
+            sketch: CountMinSketch::new(max_capacity.max(MIN_SKETCH_WIDTH), SKETCH_DEPTH),
+            hit_rate: HitRateTracker::default(),
+            dim: None,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    pub fn window_capacity(&self) -> usize {
+        self.window_capacity
+    }
+
+    /// The frequency estimates the main space is defended with.
+    pub fn sketch(&self) -> &CountMinSketch {
+        &self.sketch
+    }
+
+    /// Changes the capacity, evicting unpinned entries of the lowest priority from the
+    /// main space, then from the window, until the cache fits in it, and returns them. Pinned entries stay, even
+    /// above the new capacity. The sketch keeps its size.
+    pub fn set_capacity(&mut self, max_capacity: usize) -> Result<Vec<(K, V, Tolerance)>> {
+        if max_capacity == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "capacity must be positive".into(),
+            ));
+        }
+        self.max_capacity = max_capacity;
+        (self.window_capacity, self.protected_capacity) = Self::segment_capacities(max_capacity);
+        let mut evicted = Vec::new();
+        while self.items.len() > max_capacity {
+            match self.evict_lowest() {
+                Some(entry) => evicted.push(entry),
+                None => break,
+            }
+        }
+        while self.count(Segment::Window) > self.window_capacity {
+            let Some(idx) = self.lru_of(Segment::Window, None, None) else {
+                break;
+            };
+            self.items[idx].segment = Segment::Probation;
+        }
+        self.demote_overflow(None);
+        Ok(evicted)
+    }
+
+    fn segment_capacities(max_capacity: usize) -> (usize, usize) {
+        let window = (max_capacity * WINDOW_PERCENT / 100).max(1);
+        let main = max_capacity - window;
+        (window, main * PROTECTED_PERCENT / 100)
+    }
+
+    fn count(&self, segment: Segment) -> usize {
+        self.items
+            .iter()
+            .filter(|entry| entry.segment == segment)
+            .count()
+    }
+
+    /// Least recently referenced unpinned entry of `segment`, other than `except`, among
+    /// those of priority `floor` if given.
+    fn lru_of(&self, segment: Segment, except: Option<usize>, floor: Option<u32>) -> Option<usize> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|&(idx, entry)| {
+                entry.segment == segment
+                    && !entry.pinned
+                    && Some(idx) != except
+                    && floor.is_none_or(|floor| entry.info.priority == floor)
+            })
+            .min_by_key(|(_, entry)| entry.last)
+            .map(|(idx, _)| idx)
+    }
+
+    /// Lowest priority among the unpinned entries, the ones eviction takes first.
+    fn priority_floor(&self) -> Option<u32> {
+        self.items
+            .iter()
+            .filter(|entry| !entry.pinned)
+            .map(|entry| entry.info.priority)
+            .min()
+    }
+
+    /// Entry of priority `floor` the main space gives up first: on probation, or else
+    /// protected.
+    fn main_victim(&self, except: Option<usize>, floor: u32) -> Option<usize> {
+        self.lru_of(Segment::Probation, except, Some(floor))
+            .or(self.lru_of(Segment::Protected, except, Some(floor)))
+    }
+
+    /// Evicts the main space's victim of the lowest priority, or else the window's.
+    fn evict_lowest(&mut self) -> Option<(K, V, Tolerance)> {
+        let floor = self.priority_floor()?;
+        let victim =
+            self.main_victim(None, floor)
+                .or(self.lru_of(Segment::Window, None, Some(floor)))?;
+        Some(self.remove(victim))
+    }
+
+    /// Moves the least recently referenced protected entries back to probation while
+    /// the protected segment is over its capacity, sparing `spared`.
+    fn demote_overflow(&mut self, spared: Option<usize>) {
+        while self.count(Segment::Protected) > self.protected_capacity {
+            let Some(idx) = self.lru_of(Segment::Protected, spared, None) else {
+                break;
+            };
+            self.clock += 1;
+            self.items[idx].segment = Segment::Probation;
+            self.items[idx].last = self.clock;
+        }
+    }
+
+    fn remove(&mut self, idx: usize) -> (K, V, Tolerance) {
+        let entry = self.items.swap_remove(idx);
+        trace_event!(
+            segment = ?entry.segment,
+            tolerance = entry.tol,
+            hits = entry.info.hits,
+            "evicted"
+        );
+        (entry.key, entry.value, entry.tol)
+    }
+}
+
+impl<K, V> WTinyLfuCache<K, V>
+where
+    K: ApproxComparable + Hash + MaybeSync,
+    V: Clone + MaybeSync,
+{
+    /// Entry to evict for the window's `candidate` to stay: the main space's victim if
+    /// it is estimated to be queried less often than the candidate, the candidate
+    /// otherwise. Without a candidate, the main space's victim, or else the window's.
+    /// Only entries of the lowest priority compete, the candidate included.
+    fn loser(&self, candidate: Option<usize>) -> Option<usize> {
+        let floor = self.priority_floor()?;
+        let candidate = candidate.filter(|&idx| self.items[idx].info.priority == floor);
+        let victim = self.main_victim(candidate, floor);
+        match (candidate, victim) {
+            (Some(c), Some(v))
+                if self.sketch.estimate(&self.items[v].key)
+                    < self.sketch.estimate(&self.items[c].key) =>
+            {
+                Some(v)
+            }
+            (Some(c), _) => Some(c),
+            (None, Some(v)) => Some(v),
+            (None, None) => self.lru_of(Segment::Window, None, Some(floor)),
+        }
+    }
+
+    /// Records a hit, promoting the entry to the protected segment if it was on
+    /// probation.
+    fn reference(&mut self, idx: usize) {
+        self.clock += 1;
+        let entry = &mut self.items[idx];
+        entry.last = self.clock;
+        entry.info.record_hit();
+        if entry.segment == Segment::Probation {
+            entry.segment = Segment::Protected;
+            self.demote_overflow(Some(idx));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::LruCache;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    #[test]
+    fn test_w_tinylfu_segments() {
+        let cache: WTinyLfuCache<i16, i16> = WTinyLfuCache::new(200).unwrap();
+        assert_eq!(cache.window_capacity(), 2);
+        assert_eq!(cache.protected_capacity, 158);
+        let tiny: WTinyLfuCache<i16, i16> = WTinyLfuCache::new(1).unwrap();
+        assert_eq!((tiny.window_capacity(), tiny.protected_capacity), (1, 0));
+    }
+
+    #[test]
+    fn test_w_tinylfu_resists_scans() {
+        let mut tinylfu = WTinyLfuCache::new(10).unwrap();
+        let mut lru = LruCache::new(10).unwrap();
+        let popular = 0..8;
+        for _ in 0..3 {
+            for key in popular.clone() {
+                for cache in [
+                    &mut tinylfu as &mut dyn ApproximateCache<i16, i16>,
+                    &mut lru,
+                ] {
+                    if cache.find(&key).is_none() {
+                        cache.insert(key, key, TEST_TOLERANCE);
+                    }
+                }
+            }
+        }
+        // a scan of one-off keys flushes LRU, not the popular keys of W-TinyLFU
+        for key in 100..200 {
+            tinylfu.insert(key, key, TEST_TOLERANCE);
+            lru.insert(key, key, TEST_TOLERANCE);
+        }
+        let survivors = |cache: &mut dyn ApproximateCache<i16, i16>| {
+            popular
+                .clone()
+                .filter(|key| cache.find(key).is_some())
+                .count()
+        };
+        // a one-off key colliding with a popular one in the sketch may win its duel
+        assert!(survivors(&mut tinylfu) >= 7);
+        assert_eq!(survivors(&mut lru), 0);
+        assert_eq!(tinylfu.len(), 10);
+    }
+
+    #[test]
+    fn test_w_tinylfu_admits_popular_newcomer() {
+        let mut cache = WTinyLfuCache::new(2).unwrap();
+        cache.insert(1, 1, TEST_TOLERANCE);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        for _ in 0..3 {
+            cache.find(&3); // Repeated misses make key 3 popular
+        }
+        cache.insert(3, 3, TEST_TOLERANCE);
+        // key 2 leaves the window and loses against key 1, which is as rare
+        assert_eq!(cache.find(&2), None);
+        // key 3 then leaves the window and wins
+        assert_eq!(cache.next_victim(&4), Some(&1));
+        cache.insert(4, 4, TEST_TOLERANCE);
+        assert_eq!(cache.find(&3), Some(3));
+        assert_eq!(cache.find(&1), None);
+    }
+
+    #[test]
+    fn test_w_tinylfu_pinned_and_capacity() {
+        let mut cache = WTinyLfuCache::new(3).unwrap();
+        for i in 1..=3 {
+            cache.insert(i, i, TEST_TOLERANCE);
+        }
+        cache.pin(&1);
+        let evicted = cache.set_capacity(1).unwrap();
+        assert_eq!(evicted.len(), 2);
+        assert!(evicted.iter().all(|(k, _, _)| *k != 1));
+        let rejected = cache.insert_evicting(4, 4, TEST_TOLERANCE);
+        assert_eq!(rejected, vec![(4, 4, TEST_TOLERANCE)]);
+        assert_eq!(cache.find(&1), Some(1));
+        assert!(cache.set_capacity(0).is_err());
+    }
+
+    #[test]
+    fn test_w_tinylfu_priority() {
+        let mut cache = WTinyLfuCache::new(2).unwrap();
+        cache.insert_with_priority(1, 1, TEST_TOLERANCE, 1);
+        cache.insert(2, 2, TEST_TOLERANCE);
+        // key 1 left the window first, but is of a higher priority
+        assert_eq!(
+            cache.insert_evicting(3, 3, TEST_TOLERANCE),
+            vec![(2, 2, TEST_TOLERANCE)]
+        );
+        assert_eq!(cache.entry_info(&1).unwrap().priority, 1);
+        cache.insert_with_priority(4, 4, TEST_TOLERANCE, 1);
+        // a newcomer of a lower priority than every entry goes first
+        assert_eq!(
+            cache.insert_evicting(5, 5, TEST_TOLERANCE),
+            vec![(5, 5, TEST_TOLERANCE)]
+        );
+    }
+}
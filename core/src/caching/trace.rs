@@ -0,0 +1,90 @@
+//! Instrumentation of cache operations with `tracing`, compiled in only with the
+//! `tracing` feature. Fields of events are only evaluated when a subscriber listens
+//! at the `TRACE` level, so they may be expensive to compute.
+
+/// Emits a `TRACE` event with the `tracing` feature, and nothing without it.
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+/// Enters a `TRACE` span until the end of the enclosing block with the `tracing`
+/// feature, and does nothing without it.
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!($($arg)*).entered();
+    };
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use crate::caching::{ApproximateCache, FifoCache, LshFifoCache};
+    use crate::numerics::SIMD_LANECOUNT;
+    use crate::simulation::SimKey;
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn traced(f: impl FnOnce()) -> String {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+        let output = captured.0.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_miss_explained() {
+        let output = traced(|| {
+            let mut cache = FifoCache::new(1).unwrap();
+            cache.insert(1_i16, 1, 0.5);
+            cache.insert(10, 10, 0.5);
+            cache.find(&12);
+            cache.find(&10);
+        });
+        assert!(output.contains("evicted"), "{output}");
+        assert!(output.contains("candidates=1"), "{output}");
+        // a miss reports how far the nearest key was, and within what it would match
+        assert!(
+            output.contains("hit=false best_fuzziness=Some(2.0) tolerance=Some(0.5)"),
+            "{output}"
+        );
+        // the distance of the match, not of a second scan for the nearest key
+        assert!(
+            output.contains("hit=true best_fuzziness=Some(0.0)"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn test_bucket_routing_traced() {
+        let output = traced(|| {
+            let mut cache: LshFifoCache<SimKey, u8> =
+                LshFifoCache::new(4, SIMD_LANECOUNT, 4, Some(1)).unwrap();
+            cache.find(&SimKey(vec![1.0; SIMD_LANECOUNT]));
+        });
+        assert!(output.contains("signature="), "{output}");
+        assert!(output.contains("bucket_len=None"), "{output}");
+    }
+}
@@ -0,0 +1,242 @@
+use std::marker::PhantomData;
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::numerics::ApproxComparable;
+
+/// Wraps a cache so that every entry is tagged with a version, e.g. the epoch of the
+/// model that computed it, and entries of given versions can be dropped at once after
+/// a redeployment.
+///
+/// The wrapped cache stores values along with their version. Entries inserted through
+/// [`ApproximateCache`] are tagged with the current [`version`](Self::version), and
+/// [`insert_versioned`](Self::insert_versioned) tags them with any other.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, LruCache, VersionedCache};
+///
+/// let mut cache = VersionedCache::new(LruCache::new(4).unwrap());
+/// cache.insert(1 as i16, "model v0", 0.5);
+/// cache.set_version(1);
+/// cache.insert(10, "model v1", 0.5);
+///
+/// let dropped = cache.invalidate_before(1);
+/// assert_eq!(dropped, [(1, "model v0", 0.5)]);
+/// assert_eq!(cache.find(&1), None);
+/// assert_eq!(cache.find_versioned(&10), Some(("model v1", 1)));
+/// ```
+pub struct VersionedCache<K, V, C> {
+    inner: C,
+    version: u64,
+    invalidated: u64,
+    entries: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, C> VersionedCache<K, V, C> {
+    /// Tags entries with version 0 until [`set_version`](Self::set_version) is called.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            version: 0,
+            invalidated: 0,
+            entries: PhantomData,
+        }
+    }
+
+    /// The version that entries inserted through [`ApproximateCache`] are tagged with.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Tags entries inserted from now on with `version`. Stored entries keep theirs.
+    pub fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
+    /// Entries dropped by invalidations so far.
+    pub fn invalidated(&self) -> u64 {
+        self.invalidated
+    }
+
+    /// The wrapped cache.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Inserts an entry tagged with `version` instead of the current one.
+    pub fn insert_versioned(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        version: u64,
+    ) -> Vec<(K, V, Tolerance)>
+    where
+        K: ApproxComparable,
+        C: ApproximateCache<K, (V, u64)>,
+    {
+        strip(self.inner.insert_evicting(key, (value, version), tolerance))
+    }
+
+    /// Same as [`find`](ApproximateCache::find), along with the version of the value.
+    pub fn find_versioned(&mut self, target: &K) -> Option<(V, u64)>
+    where
+        K: ApproxComparable,
+        C: ApproximateCache<K, (V, u64)>,
+    {
+        self.inner.find(target)
+    }
+
+    /// Drops every entry whose version `stale` holds for, and returns them.
+    ///
+    /// Entries are dropped in place, see [`ApproximateCache::retain`], so that the
+    /// others keep their pins and usage, and nothing is rehashed, e.g. by an
+    /// [`LshCache`](crate::caching::LshCache).
+    pub fn invalidate_version(
+        &mut self,
+        mut stale: impl FnMut(u64) -> bool,
+    ) -> Vec<(K, V, Tolerance)>
+    where
+        K: ApproxComparable + Clone,
+        C: ApproximateCache<K, (V, u64)>,
+    {
+        let dropped = strip(self.inner.retain(&mut |_, (_, version)| !stale(*version)));
+        self.invalidated += dropped.len() as u64;
+        dropped
+    }
+
+    /// Drops every entry of a version below `version`, e.g. the current one after a
+    /// redeployment, and returns them.
+    pub fn invalidate_before(&mut self, version: u64) -> Vec<(K, V, Tolerance)>
+    where
+        K: ApproxComparable + Clone,
+        C: ApproximateCache<K, (V, u64)>,
+    {
+        self.invalidate_version(|stored| stored < version)
+    }
+}
+
+fn strip<K, V>(entries: Vec<(K, (V, u64), Tolerance)>) -> Vec<(K, V, Tolerance)> {
+    entries
+        .into_iter()
+        .map(|(key, (value, _), tol)| (key, value, tol))
+        .collect()
+}
+
+impl<K, V, C> ApproximateCache<K, V> for VersionedCache<K, V, C>
+where
+    K: ApproxComparable,
+    C: ApproximateCache<K, (V, u64)>,
+{
+    forward_cache!(inner =>
+        default_tolerance, len, candidates, key_dim, nearest, next_victim, pin, unpin, entry_info,
+        entry_infos, maintain, compact, recent_hit_rate, memory_bytes,
+    );
+
+    fn find(&mut self, target: &K) -> Option<V> {
+        self.inner.find(target).map(|(value, _)| value)
+    }
+
+    fn find_k(&mut self, target: &K, k: usize) -> Vec<(V, f32)> {
+        self.inner
+            .find_k(target, k)
+            .into_iter()
+            .map(|((value, _), distance)| (value, distance))
+            .collect()
+    }
+
+    /// Tags the entry with the current version.
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        strip(
+            self.inner
+                .insert_with_priority(key, (value, self.version), tolerance, priority),
+        )
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, V, Tolerance)> + '_> {
+        Box::new(
+            self.inner
+                .iter()
+                .map(|(key, (value, _), tol)| (key, value, tol)),
+        )
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        Box::new(
+            self.inner
+                .drain()
+                .map(|(key, (value, _), tol)| (key, value, tol)),
+        )
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&K, &V) -> bool) -> Vec<(K, V, Tolerance)>
+    where
+        K: Clone,
+    {
+        strip(self.inner.retain(&mut |key, (value, _)| keep(key, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{LruCache, LshLruCache};
+    use crate::simulation::SimKey;
+
+    #[test]
+    fn test_invalidate_keeps_survivors_in_order() {
+        let mut cache = VersionedCache::new(LruCache::new(4).unwrap());
+        cache.insert_versioned(1i16, 1, 0.5, 2);
+        cache.insert_versioned(10, 10, 0.5, 1);
+        cache.insert_versioned(20, 20, 0.5, 3);
+        cache.insert_with_priority(30, 30, 0.5, 0);
+        assert_eq!(cache.find_versioned(&30), Some((30, 0)));
+        cache.find(&10);
+        assert!(cache.pin(&10));
+        assert!(cache.invalidate_version(|version| version > 5).is_empty());
+
+        let mut dropped = cache.invalidate_version(|version| version == 0 || version == 3);
+        dropped.sort_by_key(|(key, ..)| *key);
+        assert_eq!(dropped, [(20, 20, 0.5), (30, 30, 0.5)]);
+        assert_eq!(cache.invalidated(), 2);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.find_versioned(&10), Some((10, 1)));
+
+        // 1 is still the least recently used, and 10 is still pinned
+        cache.insert(40, 40, 0.5);
+        cache.insert(50, 50, 0.5);
+        assert_eq!(cache.next_victim(&60), Some(&1));
+        cache.insert(60, 60, 0.5);
+        assert_eq!(cache.next_victim(&70), Some(&40));
+    }
+
+    #[test]
+    fn test_invalidate_keeps_lsh_router() {
+        let lsh = LshLruCache::new(4, 8, 2, Some(7)).unwrap();
+        let mut cache = VersionedCache::new(lsh);
+        let key = |x: f32| SimKey(vec![x; 8]);
+        let projections = cache.get_ref().projections().to_vec();
+        cache.insert_with_priority(key(1.0), 1, 0.1, 4);
+        cache.set_version(1);
+        cache.insert(key(-1.0), 2, 0.1);
+        assert_eq!(cache.find(&key(-1.0)), Some(2));
+
+        assert_eq!(cache.invalidate_before(1), [(key(1.0), 1, 0.1)]);
+        assert_eq!(cache.get_ref().projections(), projections);
+        assert_eq!(cache.find(&key(1.0)), None);
+        // the survivor keeps its hits
+        assert_eq!(cache.find(&key(-1.0)), Some(2));
+        assert_eq!(cache.entry_info(&key(-1.0)).unwrap().hits, 2);
+    }
+}
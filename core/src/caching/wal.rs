@@ -0,0 +1,588 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::caching::approximate_cache::ApproximateCache;
+use crate::caching::approximate_cache::Tolerance;
+use crate::caching::delta::{put_u32, take, take_f32, take_u32};
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Start of every log, followed by its format version.
+const WAL_MAGIC: &[u8; 4] = b"PXWL";
+/// Version of the logs written by [`WalCache`]. Logs of a later version are refused.
+const WAL_FORMAT_VERSION: u32 = 1;
+
+const INSERT: u8 = 1;
+const EVICT: u8 = 2;
+const CLEAR: u8 = 3;
+
+/// Wraps a vector cache to append every insert and eviction to a log, which
+/// [`open`](Self::open) replays to restore the cache after a crash.
+///
+/// The log starts with a magic number and its format version, and replay refuses a log
+/// of a later version than this one writes. Appending a record costs a write of its own
+/// size, unlike a full snapshot. Every
+/// record is framed by its length and a checksum, so that a record torn by a crash is
+/// detected and dropped along with anything after it on replay. Records reach the
+/// operating system as soon as they are appended, so they survive the process
+/// crashing; [`sync_every`](Self::sync_every) bounds how many are lost if the machine
+/// does. The log only grows until [`compact_log`](Self::compact_log) rewrites it as
+/// the entries of the cache, every [`compact_after`](Self::compact_after) records or
+/// when called. Compaction writes the entries least recently used first, so that
+/// replay brings them back in their order of use, but pins and hits are not logged.
+///
+/// Appends cannot fail inserts, so a failed one is kept for
+/// [`take_error`](Self::take_error). Keys are vectors of `f32` and values bytes, as in
+/// [`DeltaSync`](crate::caching::DeltaSync).
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, FifoCache, WalCache};
+/// use proximity::simulation::SimKey;
+///
+/// let path = std::env::temp_dir().join("proximity_doc_wal.log");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut cache = WalCache::open(FifoCache::new(4).unwrap(), &path).unwrap();
+/// cache.insert(SimKey(vec![1.0; 8]), b"Value 1".to_vec(), 1.0);
+/// drop(cache);
+///
+/// // after a crash
+/// let mut restarted = WalCache::open(FifoCache::new(4).unwrap(), &path).unwrap();
+/// assert_eq!(restarted.find(&SimKey(vec![1.1; 8])), Some(b"Value 1".to_vec()));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct WalCache<C> {
+    inner: C,
+    path: PathBuf,
+    log: File,
+    /// Records appended since the last compaction.
+    appended: usize,
+    unsynced: usize,
+    sync_every: Option<usize>,
+    compact_after: Option<usize>,
+    error: Option<ProximityError>,
+}
+
+impl<C> WalCache<C> {
+    /// Replays the log at `path` into `inner`, if there is one, and logs to it from
+    /// then on. A torn record at the end of the log is cut off.
+    ///
+    /// Fails, leaving the log as is, if it is not a log, is of a later format version,
+    /// or holds a record that cannot be read or keys of another dimension than the
+    /// cache's.
+    pub fn open<K, V>(mut inner: C, path: impl Into<PathBuf>) -> Result<Self>
+    where
+        K: ApproxComparable + AsRef<[f32]> + From<Vec<f32>>,
+        V: AsRef<[u8]> + for<'a> From<&'a [u8]>,
+        C: ApproximateCache<K, V>,
+    {
+        let path = path.into();
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let (entries, valid, appended) = match records(&bytes)? {
+            Some(records) => {
+                let (entries, valid, appended) = replay(records)?;
+                (entries, WAL_MAGIC.len() + 4 + valid, appended)
+            }
+            None => (Vec::new(), 0, 0),
+        };
+        if let Some(dim) = inner.key_dim() {
+            if let Some(found) = entries
+                .iter()
+                .map(|(key, ..)| key.len())
+                .find(|&d| d != dim)
+            {
+                return Err(ProximityError::DimensionMismatch {
+                    expected: dim,
+                    found,
+                });
+            }
+        }
+        for (key, value, tolerance, priority) in entries {
+            inner.insert_with_priority(K::from(key), V::from(&value), tolerance, priority);
+        }
+
+        let mut log = OpenOptions::new().create(true).append(true).open(&path)?;
+        log.set_len(valid as u64)?;
+        if valid == 0 {
+            log.write_all(&header())?;
+        }
+        Ok(Self {
+            inner,
+            path,
+            log,
+            appended,
+            unsynced: 0,
+            sync_every: None,
+            compact_after: None,
+            error: None,
+        })
+    }
+
+    /// Flushes the log to disk after every `records` records, so that at most as many
+    /// are lost if the machine crashes.
+    pub fn sync_every(mut self, records: usize) -> Result<Self> {
+        if records == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "syncs must be at least one record apart".into(),
+            ));
+        }
+        self.sync_every = Some(records);
+        Ok(self)
+    }
+
+    /// Compacts the log once `records` records were appended since the last compaction.
+    pub fn compact_after(mut self, records: usize) -> Result<Self> {
+        if records == 0 {
+            return Err(ProximityError::InvalidArgument(
+                "compactions must be at least one record apart".into(),
+            ));
+        }
+        self.compact_after = Some(records);
+        Ok(self)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The error of the last failed append or automatic compaction, clearing it.
+    pub fn take_error(&mut self) -> Option<ProximityError> {
+        self.error.take()
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Flushes the log to disk.
+    pub fn sync(&mut self) -> Result<()> {
+        self.log.sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Rewrites the log as one insert per entry of the cache, least recently used
+    /// first. The new log is written next to the old one and renamed over it, so a
+    /// crash while compacting leaves the old log intact.
+    pub fn compact_log<K, V>(&mut self) -> Result<()>
+    where
+        K: ApproxComparable + AsRef<[f32]>,
+        V: AsRef<[u8]>,
+        C: ApproximateCache<K, V>,
+    {
+        let mut infos = self.inner.entry_infos();
+        let mut entries: Vec<_> = self
+            .inner
+            .iter()
+            .map(|(key, value, tolerance)| {
+                let info = infos.next().map(|(_, info)| info);
+                let rank = info.map(|info| info.last_access);
+                let priority = info.map_or(0, |info| info.priority);
+                (rank, insert_record(key, &value, tolerance, priority))
+            })
+            .collect();
+        entries.sort_by_key(|(rank, _)| *rank);
+
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        writer.write_all(&header())?;
+        for (_, record) in &entries {
+            writer.write_all(&frame(record))?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        std::fs::rename(&partial, &self.path)?;
+        self.log = OpenOptions::new().append(true).open(&self.path)?;
+        self.appended = 0;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    fn append<K, V>(&mut self, records: &[Vec<u8>])
+    where
+        K: ApproxComparable + AsRef<[f32]>,
+        V: AsRef<[u8]>,
+        C: ApproximateCache<K, V>,
+    {
+        let framed: Vec<u8> = records.iter().flat_map(|record| frame(record)).collect();
+        if let Err(e) = self.log.write_all(&framed) {
+            self.error = Some(e.into());
+            return;
+        }
+        self.appended += records.len();
+        self.unsynced += records.len();
+        if self.sync_every.is_some_and(|n| self.unsynced >= n) {
+            if let Err(e) = self.sync() {
+                self.error = Some(e);
+            }
+        }
+        if self.compact_after.is_some_and(|n| self.appended >= n) {
+            if let Err(e) = self.compact_log() {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+fn put_key(record: &mut Vec<u8>, key: &[f32]) {
+    put_u32(record, key.len() as u32);
+    key.iter()
+        .for_each(|x| record.extend_from_slice(&x.to_le_bytes()));
+}
+
+fn insert_record<K, V>(key: &K, value: &V, tolerance: Tolerance, priority: u32) -> Vec<u8>
+where
+    K: AsRef<[f32]>,
+    V: AsRef<[u8]>,
+{
+    let mut record = vec![INSERT];
+    put_key(&mut record, key.as_ref());
+    record.extend_from_slice(&tolerance.to_le_bytes());
+    put_u32(&mut record, priority);
+    let value = value.as_ref();
+    put_u32(&mut record, value.len() as u32);
+    record.extend_from_slice(value);
+    record
+}
+
+fn evict_record<K: AsRef<[f32]>>(key: &K) -> Vec<u8> {
+    let mut record = vec![EVICT];
+    put_key(&mut record, key.as_ref());
+    record
+}
+
+fn header() -> Vec<u8> {
+    let mut header = WAL_MAGIC.to_vec();
+    put_u32(&mut header, WAL_FORMAT_VERSION);
+    header
+}
+
+/// The records of a log after its header, or `None` if the log is too short to hold a
+/// whole header, e.g. one whose creation a crash cut short. Fails if the log is not one,
+/// or is of a later format version.
+fn records(log: &[u8]) -> Result<Option<&[u8]>> {
+    if header().starts_with(log) {
+        return Ok(None);
+    }
+    let mut rest = log;
+    if take(&mut rest, WAL_MAGIC.len()).ok() != Some(&WAL_MAGIC[..]) {
+        return Err(ProximityError::InvalidData(
+            "not a write-ahead log of proximity".into(),
+        ));
+    }
+    let version = take_u32(&mut rest)
+        .map_err(|_| ProximityError::InvalidData("truncated log header".into()))?;
+    if version > WAL_FORMAT_VERSION {
+        return Err(ProximityError::InvalidData(format!(
+            "log of format version {version}, this version of proximity reads up to {WAL_FORMAT_VERSION}"
+        )));
+    }
+    Ok(Some(rest))
+}
+
+/// Prefixes a record with its length and checksum.
+fn frame(record: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(record.len() + 8);
+    put_u32(&mut framed, record.len() as u32);
+    put_u32(&mut framed, checksum(record));
+    framed.extend_from_slice(record);
+    framed
+}
+
+/// FNV-1a, enough to tell a torn record from a whole one.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn take_key(record: &mut &[u8]) -> Result<Vec<f32>> {
+    let dim = take_u32(record)? as usize;
+    let raw = take(record, dim.saturating_mul(4))?;
+    Ok(raw
+        .chunks_exact(4)
+        .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+        .collect())
+}
+
+type LoggedEntry = (Vec<f32>, Vec<u8>, Tolerance, u32);
+
+/// Entries left in the cache by the records of a log, in the order they were inserted,
+/// along with the length of the log up to its first torn record and the number of
+/// records before it. Fails on a whole record that cannot be read, e.g. one written by
+/// a later version, rather than dropping it.
+fn replay(mut log: &[u8]) -> Result<(Vec<LoggedEntry>, usize, usize)> {
+    let total = log.len();
+    let mut entries: Vec<Option<LoggedEntry>> = Vec::new();
+    // positions of the live entries of every key, oldest first, since a key may be
+    // inserted twice
+    let mut live: HashMap<Vec<u32>, VecDeque<usize>> = HashMap::new();
+    let bits = |key: &[f32]| key.iter().map(|x| x.to_bits()).collect::<Vec<u32>>();
+    let mut records = 0;
+    while let Some(mut record) = next_record(&mut log) {
+        let malformed = |_| ProximityError::InvalidData(format!("malformed log record {records}"));
+        match take(&mut record, 1).map_err(malformed)?[0] {
+            INSERT => {
+                let key = take_key(&mut record).map_err(malformed)?;
+                let tolerance = take_f32(&mut record).map_err(malformed)?;
+                let priority = take_u32(&mut record).map_err(malformed)?;
+                let len = take_u32(&mut record).map_err(malformed)? as usize;
+                let value = take(&mut record, len).map_err(malformed)?.to_vec();
+                live.entry(bits(&key)).or_default().push_back(entries.len());
+                entries.push(Some((key, value, tolerance, priority)));
+            }
+            EVICT => {
+                let key = take_key(&mut record).map_err(malformed)?;
+                if let Some(i) = live.get_mut(&bits(&key)).and_then(VecDeque::pop_front) {
+                    entries[i] = None;
+                }
+            }
+            CLEAR => {
+                entries.clear();
+                live.clear();
+            }
+            tag => {
+                return Err(ProximityError::InvalidData(format!(
+                    "unknown log record type {tag}"
+                )))
+            }
+        }
+        records += 1;
+    }
+    let entries = entries.into_iter().flatten().collect();
+    Ok((entries, total - log.len(), records))
+}
+
+/// Takes the next whole record off the log, or leaves the log as is if it ends or the
+/// next record is torn.
+fn next_record<'a>(log: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let mut rest = *log;
+    let len = take_u32(&mut rest).ok()? as usize;
+    let sum = take_u32(&mut rest).ok()?;
+    let record = take(&mut rest, len).ok()?;
+    if checksum(record) != sum {
+        return None;
+    }
+    *log = rest;
+    Some(record)
+}
+
+impl<K, V, C> ApproximateCache<K, V> for WalCache<C>
+where
+    K: ApproxComparable + AsRef<[f32]>,
+    V: AsRef<[u8]>,
+    C: ApproximateCache<K, V>,
+{
+    forward_cache!(inner =>
+        find, find_k, default_tolerance, len, candidates, key_dim, nearest, next_victim, pin,
+        unpin, entry_info, entry_infos, iter, maintain, compact, recent_hit_rate, memory_bytes,
+    );
+
+    fn insert_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        tolerance: f32,
+        priority: u32,
+    ) -> Vec<(K, V, Tolerance)> {
+        let insert = insert_record(&key, &value, tolerance, priority);
+        let len = self.inner.len();
+        // the newcomer's key moves into the cache, and back out if it is refused, so its
+        // buffer tells it apart from an older copy of the same key evicted to make room
+        let newcomer = key.as_ref().as_ptr();
+        let evicted = self
+            .inner
+            .insert_with_priority(key, value, tolerance, priority);
+        let grew = self.inner.len() > len;
+        let mut records = Vec::with_capacity(evicted.len() + 1);
+        let mut refused = false;
+        for (key, ..) in &evicted {
+            // a refused newcomer is returned as evicted, but never entered the cache
+            if !grew && key.as_ref().as_ptr() == newcomer {
+                refused = true;
+            } else {
+                records.push(evict_record(key));
+            }
+        }
+        if !refused {
+            records.insert(0, insert);
+        }
+        if !records.is_empty() {
+            self.append(&records);
+        }
+        evicted
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (K, V, Tolerance)> + '_> {
+        self.append(&[vec![CLEAR]]);
+        self.inner.drain()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LruCache};
+    use crate::simulation::SimKey;
+
+    fn key(i: usize) -> SimKey {
+        SimKey(vec![i as f32; 8])
+    }
+
+    fn open(path: &Path, capacity: usize) -> WalCache<FifoCache<SimKey, Vec<u8>>> {
+        WalCache::open(FifoCache::new(capacity).unwrap(), path).unwrap()
+    }
+
+    #[test]
+    fn test_replay_inserts_and_evictions() {
+        let path = std::env::temp_dir().join("proximity_test_wal_replay.log");
+        let _ = std::fs::remove_file(&path);
+        let mut cache = open(&path, 2);
+        for i in 0..4 {
+            cache.insert(key(i), vec![i as u8], 0.5);
+        }
+        assert!(cache.take_error().is_none());
+        drop(cache);
+
+        // a larger cache only gets back what was left in the smaller one
+        let mut restored = open(&path, 8);
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored.find(&key(3)), Some(vec![3]));
+        assert!(restored.find(&key(0)).is_none());
+        restored.drain().for_each(drop);
+        drop(restored);
+        assert!(open(&path, 8).is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_refused_newcomer_is_not_logged() {
+        let path = std::env::temp_dir().join("proximity_test_wal_refused.log");
+        let _ = std::fs::remove_file(&path);
+        let mut cache = open(&path, 2);
+        cache.insert(key(1), vec![1], 0.5);
+        cache.insert(key(2), vec![2], 0.5);
+        assert!(cache.pin(&key(1)) && cache.pin(&key(2)));
+        let evicted = cache.insert_evicting(key(3), vec![3], 0.5);
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(cache.appended, 2);
+        drop(cache);
+
+        let mut restored = open(&path, 4);
+        assert_eq!(restored.len(), 2);
+        assert!(restored.find(&key(3)).is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replaced_key_is_logged() {
+        let path = std::env::temp_dir().join("proximity_test_wal_replaced.log");
+        let _ = std::fs::remove_file(&path);
+        let mut cache = open(&path, 1);
+        cache.insert(key(1), b"old".to_vec(), 0.5);
+        // the older copy of the key is evicted, and the newcomer takes its place
+        let evicted = cache.insert_evicting(key(1), b"new".to_vec(), 0.5);
+        assert_eq!(evicted.len(), 1);
+        drop(cache);
+
+        let mut restored = open(&path, 1);
+        assert_eq!(restored.find(&key(1)), Some(b"new".to_vec()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_torn_record_is_cut_off() {
+        let path = std::env::temp_dir().join("proximity_test_wal_torn.log");
+        let _ = std::fs::remove_file(&path);
+        let mut cache = open(&path, 4);
+        cache.insert(key(1), vec![1], 0.5);
+        cache.insert(key(2), vec![2], 0.5);
+        drop(cache);
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        let mut restored = open(&path, 4);
+        assert_eq!(restored.len(), 1);
+        // appends go after the last whole record
+        restored.insert(key(3), vec![3], 0.5);
+        drop(restored);
+        let mut restored = open(&path, 4);
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored.find(&key(3)), Some(vec![3]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_log_is_refused() {
+        let path = std::env::temp_dir().join("proximity_test_wal_version.log");
+        let _ = std::fs::remove_file(&path);
+        let mut cache = open(&path, 4);
+        cache.insert(key(1), vec![1], 0.5);
+        drop(cache);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[4..8].copy_from_slice(&(WAL_FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+
+        let result = WalCache::open(FifoCache::<SimKey, Vec<u8>>::new(4).unwrap(), &path);
+        assert!(matches!(result, Err(ProximityError::InvalidData(_))));
+        // the log is left as is
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+        std::fs::write(&path, b"not a log").unwrap();
+        let result = WalCache::open(FifoCache::<SimKey, Vec<u8>>::new(4).unwrap(), &path);
+        assert!(matches!(result, Err(ProximityError::InvalidData(_))));
+        // a header cut short holds no records
+        std::fs::write(&path, &WAL_MAGIC[..2]).unwrap();
+        assert!(open(&path, 4).is_empty());
+        assert_eq!(std::fs::read(&path).unwrap(), header());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_compaction_keeps_the_order_of_use() {
+        let path = std::env::temp_dir().join("proximity_test_wal_compact.log");
+        let _ = std::fs::remove_file(&path);
+        let mut cache = WalCache::open(LruCache::new(2).unwrap(), &path)
+            .unwrap()
+            .compact_after(6)
+            .unwrap();
+        for i in 0..4 {
+            cache.insert(key(i), vec![i as u8], 0.5);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        // four inserts and two evictions
+        assert_eq!(cache.appended, 0);
+        assert!(cache.find(&key(2)).is_some());
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        cache.compact_log().unwrap();
+        let compacted = std::fs::metadata(&path).unwrap().len();
+        drop(cache);
+
+        let mut restored: WalCache<LruCache<SimKey, Vec<u8>>> =
+            WalCache::open(LruCache::new(2).unwrap(), &path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), compacted);
+        // key 3 is the least recently used, so it goes first
+        restored.insert(key(4), vec![4], 0.5);
+        assert!(restored.find(&key(3)).is_none());
+        assert!(restored.find(&key(2)).is_some());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,290 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use npyz::{Deserialize, NpyFile};
+
+use crate::caching::ApproximateCache;
+use crate::fs::{stream_npy, IndexVectors};
+use crate::numerics::ApproxComparable;
+use crate::{ProximityError, Result};
+
+/// Rows read from the dataset at a time, and between two progress reports.
+const WARMUP_CHUNK_ROWS: usize = 4096;
+
+/// Filling a vector cache from a dataset of `.npy` files, e.g. embeddings computed
+/// offline and their answers, before it serves queries.
+///
+/// The keys are a 2D array of shape `(n, d)`, of any dtype that
+/// [`stream_npy`] reads, and the values a 1D array of shape `(n,)`. Both are streamed,
+/// so that only a chunk of rows is held in memory at a time besides the cache, and every
+/// entry is inserted under the same tolerance, evicting as the cache's policy decides.
+///
+/// # Example Usage
+/// ```
+/// use npyz::WriterBuilder;
+/// use proximity::caching::{ApproximateCache, FifoCache, NpyWarmup};
+/// use proximity::simulation::SimKey;
+/// use std::fs::File;
+///
+/// let keys = std::env::temp_dir().join("warmup_keys.npy");
+/// let mut writer = npyz::WriteOptions::new()
+///     .default_dtype()
+///     .shape(&[2, 8])
+///     .writer(File::create(&keys).unwrap())
+///     .begin_nd()
+///     .unwrap();
+/// writer.extend((0..16).map(|x| (x / 8) as f32)).unwrap();
+/// writer.finish().unwrap();
+/// let values = std::env::temp_dir().join("warmup_values.npy");
+/// let mut writer = npyz::WriteOptions::new()
+///     .default_dtype()
+///     .shape(&[2])
+///     .writer(File::create(&values).unwrap())
+///     .begin_nd()
+///     .unwrap();
+/// writer.extend([10i64, 20]).unwrap();
+/// writer.finish().unwrap();
+///
+/// let mut cache: FifoCache<SimKey, i64> = FifoCache::new(4).unwrap();
+/// let mut reports = Vec::new();
+/// let inserted = cache
+///     .warm_from_npy_with_progress(&keys, &values, 0.5, |done, total| {
+///         reports.push((done, total))
+///     })
+///     .unwrap();
+/// assert_eq!(inserted, 2);
+/// assert_eq!(reports, [(2, 2)]);
+/// assert_eq!(cache.find(&SimKey(vec![1.0; 8])), Some(20));
+/// # std::fs::remove_file(keys).unwrap();
+/// # std::fs::remove_file(values).unwrap();
+/// ```
+pub trait NpyWarmup<K, V>: ApproximateCache<K, V>
+where
+    K: ApproxComparable,
+{
+    /// Inserts every row of `keys_path` with the matching value of `values_path`, and
+    /// returns the number of rows inserted.
+    ///
+    /// Nothing is inserted if the arrays are of different lengths or the keys of
+    /// another dimension than those stored. A malformed file met midway leaves the
+    /// rows before it inserted.
+    fn warm_from_npy(
+        &mut self,
+        keys_path: &Path,
+        values_path: &Path,
+        tolerance: f32,
+    ) -> Result<usize> {
+        self.warm_from_npy_with_progress(keys_path, values_path, tolerance, |_, _| {})
+    }
+    /// Same as [`warm_from_npy`](Self::warm_from_npy), calling `progress(inserted, total)`
+    /// after every chunk of rows.
+    fn warm_from_npy_with_progress(
+        &mut self,
+        keys_path: &Path,
+        values_path: &Path,
+        tolerance: f32,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<usize>;
+}
+
+impl<K, V, C> NpyWarmup<K, V> for C
+where
+    K: ApproxComparable + From<Vec<f32>>,
+    V: Deserialize,
+    C: ApproximateCache<K, V> + ?Sized,
+{
+    fn warm_from_npy_with_progress(
+        &mut self,
+        keys_path: &Path,
+        values_path: &Path,
+        tolerance: f32,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<usize> {
+        let keys = stream_npy(keys_path, WARMUP_CHUNK_ROWS)?;
+        let values = NpyFile::new(BufReader::new(File::open(values_path)?))?;
+        let total = keys.rows();
+        match *values.shape() {
+            [rows] if rows as usize == total => {}
+            ref shape => {
+                return Err(ProximityError::InvalidData(format!(
+                    "expected values of shape ({total},), got {shape:?}"
+                )))
+            }
+        }
+        if let Some(expected) = self.key_dim().filter(|&dim| total > 0 && dim != keys.dim()) {
+            return Err(ProximityError::DimensionMismatch {
+                expected,
+                found: keys.dim(),
+            });
+        }
+        let dim = keys.dim();
+        let mut values = values
+            .data::<V>()
+            .map_err(|e| ProximityError::InvalidData(e.to_string()))?;
+        let mut inserted = 0;
+        for chunk in keys {
+            for row in chunk?.chunks_exact(dim) {
+                let value = values.next().ok_or_else(|| {
+                    ProximityError::InvalidData("values end before the keys".into())
+                })??;
+                self.insert(K::from(row.to_vec()), value, tolerance);
+                inserted += 1;
+            }
+            progress(inserted, total);
+        }
+        Ok(inserted)
+    }
+}
+
+/// Filling a vector cache from the vectors of an existing ANN index, e.g. one read by
+/// [`read_hnswlib`](crate::fs::read_hnswlib) or [`read_annoy`](crate::fs::read_annoy),
+/// each answered by its label, so that a production index seeds the cache without being
+/// exported first.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, IndexWarmup, LruCache};
+/// use proximity::fs::IndexVectors;
+/// use proximity::simulation::SimKey;
+///
+/// let index = IndexVectors {
+///     vectors: (0..16).map(|x| (x / 8) as f32).collect(),
+///     labels: vec![10, 20],
+///     dim: 8,
+/// };
+/// let mut cache: LruCache<SimKey, u32> = LruCache::new(4).unwrap();
+/// assert_eq!(cache.warm_from_index(&index, 0.5).unwrap(), 2);
+/// assert_eq!(cache.find(&SimKey(vec![1.0; 8])), Some(20));
+/// ```
+pub trait IndexWarmup<K, V>: ApproximateCache<K, V>
+where
+    K: ApproxComparable,
+{
+    /// Inserts every vector of `index` with its label as the value, and returns the
+    /// number of vectors inserted.
+    ///
+    /// Nothing is inserted if the vectors are of another dimension than those stored,
+    /// or if a label does not fit in a value.
+    fn warm_from_index(&mut self, index: &IndexVectors, tolerance: f32) -> Result<usize>;
+}
+
+impl<K, V, C> IndexWarmup<K, V> for C
+where
+    K: ApproxComparable + From<Vec<f32>>,
+    V: TryFrom<u64>,
+    C: ApproximateCache<K, V> + ?Sized,
+{
+    fn warm_from_index(&mut self, index: &IndexVectors, tolerance: f32) -> Result<usize> {
+        if let Some(expected) = self
+            .key_dim()
+            .filter(|&dim| !index.is_empty() && dim != index.dim)
+        {
+            return Err(ProximityError::DimensionMismatch {
+                expected,
+                found: index.dim,
+            });
+        }
+        let values = index
+            .labels
+            .iter()
+            .map(|&label| {
+                V::try_from(label).map_err(|_| {
+                    ProximityError::InvalidData(format!("label {label} does not fit in a value"))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for ((row, _), value) in index.rows().zip(values) {
+            self.insert(K::from(row.to_vec()), value, tolerance);
+        }
+        Ok(index.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::LruCache;
+    use crate::simulation::SimKey;
+    use npyz::{AutoSerialize, WriterBuilder};
+
+    fn write_npy<T: AutoSerialize>(name: &str, data: Vec<T>, shape: &[u64]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let mut writer = npyz::WriteOptions::new()
+            .default_dtype()
+            .shape(shape)
+            .writer(File::create(&path).unwrap())
+            .begin_nd()
+            .unwrap();
+        writer.extend(data).unwrap();
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_warm_in_chunks() {
+        let rows = WARMUP_CHUNK_ROWS + 2;
+        let keys: Vec<f32> = (0..rows).flat_map(|row| vec![row as f32; 8]).collect();
+        let keys = write_npy("proximity_test_warm_keys.npy", keys, &[rows as u64, 8]);
+        let values = write_npy(
+            "proximity_test_warm_values.npy",
+            (0..rows as u32).collect(),
+            &[rows as u64],
+        );
+        let mut cache: LruCache<SimKey, u32> = LruCache::new(8).unwrap();
+        let mut reports = Vec::new();
+        let inserted = cache
+            .warm_from_npy_with_progress(&keys, &values, 0.1, |done, total| {
+                reports.push((done, total))
+            })
+            .unwrap();
+        std::fs::remove_file(&keys).unwrap();
+        std::fs::remove_file(&values).unwrap();
+        assert_eq!(inserted, rows);
+        assert_eq!(reports, [(WARMUP_CHUNK_ROWS, rows), (rows, rows)]);
+        assert_eq!(cache.len(), 8);
+        let last = rows - 1;
+        assert_eq!(cache.find(&SimKey(vec![last as f32; 8])), Some(last as u32));
+    }
+
+    #[test]
+    fn test_warm_mismatches() {
+        let keys = write_npy("proximity_test_warm_bad_keys.npy", vec![1.0f32; 8], &[1, 8]);
+        let values = write_npy("proximity_test_warm_bad_values.npy", vec![1i64, 2], &[2]);
+        let mut cache: LruCache<SimKey, i64> = LruCache::new(8).unwrap();
+        let err = cache.warm_from_npy(&keys, &values, 0.1).unwrap_err();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+
+        let values = write_npy("proximity_test_warm_bad_values.npy", vec![1i64], &[1]);
+        cache.insert(SimKey(vec![0.0; 16]), 0, 0.1);
+        let err = cache.warm_from_npy(&keys, &values, 0.1).unwrap_err();
+        std::fs::remove_file(&keys).unwrap();
+        std::fs::remove_file(&values).unwrap();
+        assert!(matches!(err, ProximityError::DimensionMismatch { .. }));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_warm_from_index() {
+        let index = IndexVectors {
+            vectors: (0..24).map(|x| (x / 8) as f32).collect(),
+            labels: vec![5, 300, 7],
+            dim: 8,
+        };
+        let mut cache: LruCache<SimKey, u8> = LruCache::new(8).unwrap();
+        let err = cache.warm_from_index(&index, 0.1).unwrap_err();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+        assert_eq!(cache.len(), 0);
+
+        let mut cache: LruCache<SimKey, u64> = LruCache::new(8).unwrap();
+        assert_eq!(cache.warm_from_index(&index, 0.1).unwrap(), 3);
+        assert_eq!(cache.find(&SimKey(vec![1.0; 8])), Some(300));
+        let narrow = IndexVectors {
+            vectors: vec![0.0; 4],
+            labels: vec![1],
+            dim: 4,
+        };
+        let err = cache.warm_from_index(&narrow, 0.1).unwrap_err();
+        assert!(matches!(err, ProximityError::DimensionMismatch { .. }));
+    }
+}
@@ -0,0 +1,7 @@
+//! `std::time::Instant` panics on `wasm32-unknown-unknown`, where the browser's clock
+//! is read instead.
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub use std::time::Instant;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub use web_time::Instant;
@@ -0,0 +1,47 @@
+use std::{error, fmt, io};
+
+/// Errors returned by the fallible operations of this crate.
+#[derive(Debug)]
+pub enum ProximityError {
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// A file's contents are malformed or of an unsupported type.
+    InvalidData(String),
+    /// An argument is out of the accepted range.
+    InvalidArgument(String),
+    /// A key does not have the number of components the cache works with.
+    DimensionMismatch { expected: usize, found: usize },
+    /// A key has NaN or infinite components and the cache rejects them.
+    NonFiniteKey,
+}
+
+pub type Result<T> = std::result::Result<T, ProximityError>;
+
+impl fmt::Display for ProximityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProximityError::Io(e) => write!(f, "I/O error: {e}"),
+            ProximityError::InvalidData(msg) => write!(f, "invalid data: {msg}"),
+            ProximityError::InvalidArgument(msg) => write!(f, "invalid argument: {msg}"),
+            ProximityError::DimensionMismatch { expected, found } => {
+                write!(f, "expected a key of dimension {expected}, got {found}")
+            }
+            ProximityError::NonFiniteKey => write!(f, "key has NaN or infinite components"),
+        }
+    }
+}
+
+impl error::Error for ProximityError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ProximityError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProximityError {
+    fn from(e: io::Error) -> Self {
+        ProximityError::Io(e)
+    }
+}
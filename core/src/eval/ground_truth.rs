@@ -0,0 +1,116 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::numerics::topk::k_smallest_distances;
+use crate::numerics::{ApproxComparable, SIMD_LANECOUNT};
+
+/// Exact nearest neighbor of every query among the rows of `base`, as `(row, distance)`.
+///
+/// `base` and `queries` are row-major matrices of dimension `dim`, a multiple of [`SIMD_LANECOUNT`].
+/// Queries are processed in parallel with the `parallel` feature; the result is `None` for every
+/// query if `base` is empty.
+pub fn brute_force_nearest(base: &[f32], queries: &[f32], dim: usize) -> Vec<Option<(usize, f32)>> {
+    assert!(dim > 0 && dim.is_multiple_of(SIMD_LANECOUNT));
+    assert!(base.len().is_multiple_of(dim));
+    assert!(queries.len().is_multiple_of(dim));
+
+    query_rows(queries, dim)
+        .map(|query| {
+            base.chunks_exact(dim)
+                .map(|row| query.fuzziness(row))
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        })
+        .collect()
+}
+
+/// Exact `k` nearest neighbors of every query among the rows of `base`, closest first, as
+/// `(row, distance)`, with ties going to the first row.
+///
+/// Same layout as [`brute_force_nearest`]; only the `k` smallest distances of each query
+/// are selected, without sorting all of them.
+pub fn brute_force_k_nearest(
+    base: &[f32],
+    queries: &[f32],
+    dim: usize,
+    k: usize,
+) -> Vec<Vec<(usize, f32)>> {
+    assert!(dim > 0 && dim.is_multiple_of(SIMD_LANECOUNT));
+    assert!(base.len().is_multiple_of(dim));
+    assert!(queries.len().is_multiple_of(dim));
+
+    query_rows(queries, dim)
+        .map(|query| {
+            let distances: Vec<f32> = base
+                .chunks_exact(dim)
+                .map(|row| query.fuzziness(row))
+                .collect();
+            k_smallest_distances(&distances, k)
+        })
+        .collect()
+}
+
+/// Rows of `queries`, split across the rayon thread pool with the `parallel` feature.
+#[cfg(feature = "parallel")]
+fn query_rows(queries: &[f32], dim: usize) -> rayon::slice::ChunksExact<'_, f32> {
+    queries.par_chunks_exact(dim)
+}
+
+/// Rows of `queries`, split across the rayon thread pool with the `parallel` feature.
+#[cfg(not(feature = "parallel"))]
+fn query_rows(queries: &[f32], dim: usize) -> std::slice::ChunksExact<'_, f32> {
+    queries.chunks_exact(dim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    /// Row-major matrix of points whose first two coordinates are given.
+    fn points(coords: &[(f32, f32)]) -> Vec<f32> {
+        coords
+            .iter()
+            .flat_map(|&(x, y)| {
+                let mut row = vec![0.0; SIMD_LANECOUNT];
+                row[0] = x;
+                row[1] = y;
+                row
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_brute_force_nearest() {
+        let base = points(&[(0.0, 0.0), (3.0, 4.0), (10.0, 10.0)]);
+        let queries = points(&[(3.0, 3.0), (9.0, 11.0), (0.0, -1.0)]);
+        let nearest = brute_force_nearest(&base, &queries, SIMD_LANECOUNT);
+
+        let expected = [(1, 1.0), (2, 2f32.sqrt()), (0, 1.0)];
+        for (found, (row, dist)) in nearest.into_iter().zip(expected) {
+            let (found_row, found_dist) = found.unwrap();
+            assert_eq!(found_row, row);
+            assert!((found_dist - dist).abs() < TEST_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn test_brute_force_k_nearest() {
+        let base = points(&[(0.0, 0.0), (3.0, 4.0), (10.0, 10.0), (3.0, 4.0)]);
+        let queries = points(&[(3.0, 3.0)]);
+        let nearest = brute_force_k_nearest(&base, &queries, SIMD_LANECOUNT, 3);
+
+        let rows: Vec<usize> = nearest[0].iter().map(|&(row, _)| row).collect();
+        assert_eq!(rows, vec![1, 3, 0]);
+        assert!((nearest[0][2].1 - 18f32.sqrt()).abs() < TEST_TOLERANCE);
+        let single = brute_force_nearest(&base, &queries, SIMD_LANECOUNT);
+        assert_eq!(single[0], nearest[0].first().copied());
+    }
+
+    #[test]
+    fn test_empty_base() {
+        let nearest = brute_force_nearest(&[], &[1.0; SIMD_LANECOUNT], SIMD_LANECOUNT);
+        assert_eq!(nearest, vec![None]);
+    }
+}
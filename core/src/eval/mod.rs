@@ -0,0 +1,5 @@
+mod ground_truth;
+mod recall;
+
+pub use ground_truth::{brute_force_k_nearest, brute_force_nearest};
+pub use recall::{evaluate, populate, EvalReport};
@@ -0,0 +1,187 @@
+use crate::caching::ApproximateCache;
+use crate::eval::brute_force_nearest;
+use crate::numerics::ApproxComparable;
+use crate::simulation::SimKey;
+
+/// Quality of a cache's answers compared to an exact search over the keys it holds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EvalReport {
+    pub queries: u64,
+    /// Queries for which some stored key lies within the tolerance.
+    pub answerable: u64,
+    /// Queries the cache returned a value for.
+    pub hits: u64,
+    /// Hits whose returned key lies within the tolerance.
+    pub correct_hits: u64,
+    /// Distance of the returned key minus distance of the exact nearest stored key, for every hit.
+    pub distance_errors: Vec<f32>,
+}
+
+impl EvalReport {
+    /// Fraction of answerable queries that got a correct answer.
+    pub fn recall(&self) -> f32 {
+        if self.answerable == 0 {
+            return 0.0;
+        }
+        self.correct_hits as f32 / self.answerable as f32
+    }
+
+    /// Fraction of hits whose returned key lies outside the tolerance.
+    pub fn false_accept_rate(&self) -> f32 {
+        if self.hits == 0 {
+            return 0.0;
+        }
+        (self.hits - self.correct_hits) as f32 / self.hits as f32
+    }
+
+    pub fn mean_distance_error(&self) -> f32 {
+        if self.distance_errors.is_empty() {
+            return 0.0;
+        }
+        self.distance_errors.iter().sum::<f32>() / self.distance_errors.len() as f32
+    }
+
+    /// Distance error below which a fraction `q` of hits fall.
+    pub fn distance_error_quantile(&self, q: f32) -> f32 {
+        assert!((0.0..=1.0).contains(&q));
+        if self.distance_errors.is_empty() {
+            return 0.0;
+        }
+        let mut errors = self.distance_errors.clone();
+        let idx = (q * (errors.len() - 1) as f32).round() as usize;
+        *errors.select_nth_unstable_by(idx, f32::total_cmp).1
+    }
+}
+
+/// Inserts every row of `dataset` into `cache`, with its row index as value.
+pub fn populate<C>(cache: &mut C, dataset: &[f32], dim: usize, tolerance: f32)
+where
+    C: ApproximateCache<SimKey, usize> + ?Sized,
+{
+    assert!(dim > 0);
+    for (idx, row) in dataset.chunks_exact(dim).enumerate() {
+        cache.insert(SimKey(row.to_vec()), idx, tolerance);
+    }
+}
+
+/// Runs every query against `cache` and compares its answers to an exact search.
+///
+/// Values stored in `cache` must be row indices into `dataset`, as inserted by [`populate`].
+/// Ground truth is computed over the keys the cache still holds, so capacity evictions
+/// do not count against it; only the cache's search strategy does.
+/// An answer is correct if its key lies within `tolerance` of the query.
+pub fn evaluate<C>(
+    cache: &mut C,
+    dataset: &[f32],
+    queries: &[f32],
+    dim: usize,
+    tolerance: f32,
+) -> EvalReport
+where
+    C: ApproximateCache<SimKey, usize> + ?Sized,
+{
+    assert!(dim > 0);
+    let resident: Vec<usize> = cache.iter().map(|(_, idx, _)| idx).collect();
+    let row = |idx: usize| &dataset[idx * dim..(idx + 1) * dim];
+    let resident_rows: Vec<f32> = resident.iter().flat_map(|&idx| row(idx)).copied().collect();
+    let truth = brute_force_nearest(&resident_rows, queries, dim);
+
+    let mut report = EvalReport::default();
+    for (query, exact) in queries.chunks_exact(dim).zip(truth) {
+        report.queries += 1;
+        let exact_dist = exact.map(|(_, dist)| dist);
+        if exact_dist.is_some_and(|dist| dist < tolerance) {
+            report.answerable += 1;
+        }
+        let Some(found) = cache.find(&SimKey(query.to_vec())) else {
+            continue;
+        };
+        report.hits += 1;
+        let found_dist = query.fuzziness(row(found));
+        if found_dist < tolerance {
+            report.correct_hits += 1;
+        }
+        // a hit implies a resident key, hence an exact answer
+        report
+            .distance_errors
+            .push(found_dist - exact_dist.unwrap_or(found_dist));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LshLruCache};
+    use crate::simulation::{TraceGenerator, Workload};
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+    const DIM: usize = 16;
+
+    /// Row-major matrix of `DIM`-dimensional points whose first coordinate is given.
+    fn points(xs: &[f32]) -> Vec<f32> {
+        xs.iter()
+            .flat_map(|&x| {
+                let mut row = vec![0.0; DIM];
+                row[0] = x;
+                row
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_linear_cache_has_full_recall() {
+        let dataset = points(&[0.0, 10.0]);
+        let queries = points(&[0.1, 9.9, 5.0]);
+        let mut cache = FifoCache::new(2).unwrap();
+        populate(&mut cache, &dataset, DIM, 0.5);
+        let report = evaluate(&mut cache, &dataset, &queries, DIM, 0.5);
+
+        assert_eq!(report.queries, 3);
+        assert_eq!(report.answerable, 2);
+        assert_eq!(report.hits, 2);
+        assert_eq!(report.recall(), 1.0);
+        assert_eq!(report.false_accept_rate(), 0.0);
+        assert!(report.mean_distance_error().abs() < TEST_TOLERANCE);
+    }
+
+    #[test]
+    fn test_loose_entries_are_false_accepts() {
+        let dataset = points(&[0.0]);
+        let queries = points(&[1.0, 0.1]);
+        let mut cache = FifoCache::new(1).unwrap();
+        // entries accept matches up to 2.0 but answers are only correct within 0.5
+        populate(&mut cache, &dataset, DIM, 2.0);
+        let report = evaluate(&mut cache, &dataset, &queries, DIM, 0.5);
+
+        assert_eq!(report.hits, 2);
+        assert_eq!(report.correct_hits, 1);
+        assert_eq!(report.answerable, 1);
+        assert_eq!(report.false_accept_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_lsh_recall_is_bounded_by_linear() {
+        let workload = Workload::Zipf {
+            pool_size: 300,
+            exponent: 0.5,
+            dim: DIM,
+            noise: 0.02,
+        };
+        let mut generator = TraceGenerator::new(workload, 3);
+        let dataset: Vec<f32> = generator.by_ref().take(300).flatten().collect();
+        let queries: Vec<f32> = generator.take(500).flatten().collect();
+
+        let mut linear = FifoCache::new(300).unwrap();
+        populate(&mut linear, &dataset, DIM, 0.1);
+        let linear_report = evaluate(&mut linear, &dataset, &queries, DIM, 0.1);
+        let mut lsh = LshLruCache::new(8, DIM, 300, Some(3)).unwrap();
+        populate(&mut lsh, &dataset, DIM, 0.1);
+        let lsh_report = evaluate(&mut lsh, &dataset, &queries, DIM, 0.1);
+
+        assert!(linear_report.answerable > 0);
+        assert_eq!(linear_report.recall(), 1.0);
+        assert!(lsh_report.recall() <= linear_report.recall());
+        assert!(lsh_report.distance_error_quantile(1.0) >= 0.0);
+    }
+}
@@ -0,0 +1,77 @@
+use std::path::Path;
+
+use crate::{ProximityError, Result};
+
+/// A dataset in the HDF5 layout distributed by ann-benchmarks.
+///
+/// Vectors are flattened row-major; `train` and `test` share the dimension `dim`,
+/// and `neighbors` holds the ids of the `k` exact nearest training vectors of each test vector.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnnBenchmark {
+    pub train: Vec<f32>,
+    pub test: Vec<f32>,
+    pub neighbors: Vec<i32>,
+    pub dim: usize,
+    pub k: usize,
+}
+
+impl AnnBenchmark {
+    /// Reads the `train`, `test` and `neighbors` datasets of an ann-benchmarks file.
+    /// `train` and `test` can be fed directly into [`crate::eval::evaluate`] as the
+    /// dataset and the queries.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = hdf5::File::open(path).map_err(hdf5_error)?;
+        let (train, dim) = read_matrix::<f32>(&file, "train")?;
+        let (test, test_dim) = read_matrix::<f32>(&file, "test")?;
+        if test_dim != dim {
+            return Err(ProximityError::InvalidData(format!(
+                "train vectors have dimension {dim} but test vectors {test_dim}"
+            )));
+        }
+        let (neighbors, k) = read_matrix::<i32>(&file, "neighbors")?;
+        if neighbors.len() != test.len() / dim.max(1) * k {
+            return Err(ProximityError::InvalidData(
+                "neighbors and test vectors have different counts".into(),
+            ));
+        }
+        Ok(AnnBenchmark {
+            train,
+            test,
+            neighbors,
+            dim,
+            k,
+        })
+    }
+
+    pub fn train_len(&self) -> usize {
+        self.train.len() / self.dim.max(1)
+    }
+
+    pub fn test_len(&self) -> usize {
+        self.test.len() / self.dim.max(1)
+    }
+
+    /// Ids of the exact nearest training vectors of test vector `idx`, closest first.
+    pub fn neighbors_of(&self, idx: usize) -> &[i32] {
+        &self.neighbors[idx * self.k..(idx + 1) * self.k]
+    }
+}
+
+fn hdf5_error(e: hdf5::Error) -> ProximityError {
+    ProximityError::Io(std::io::Error::other(e))
+}
+
+/// Reads a 2D dataset, converting it to `T`. Returns the flattened rows and their length.
+fn read_matrix<T: hdf5::H5Type>(file: &hdf5::File, name: &str) -> Result<(Vec<T>, usize)> {
+    let dataset = file.dataset(name).map_err(hdf5_error)?;
+    let cols = match *dataset.shape() {
+        [_, cols] => cols,
+        ref shape => {
+            return Err(ProximityError::InvalidData(format!(
+                "expected `{name}` to be 2D, got shape {shape:?}"
+            )))
+        }
+    };
+    let data = dataset.read_raw::<T>().map_err(hdf5_error)?;
+    Ok((data, cols))
+}
@@ -0,0 +1,283 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{ProximityError, Result};
+
+/// Vectors read out of an ANN index file, flattened row-major, along with the label
+/// each was added to the index under.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexVectors {
+    pub vectors: Vec<f32>,
+    pub labels: Vec<u64>,
+    pub dim: usize,
+}
+
+impl IndexVectors {
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Every vector along with its label, in the order of the index.
+    pub fn rows(&self) -> impl Iterator<Item = (&[f32], u64)> + '_ {
+        self.vectors
+            .chunks_exact(self.dim.max(1))
+            .zip(self.labels.iter().copied())
+    }
+}
+
+/// The metric an Annoy index was built with, which decides the layout of its nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnnoyMetric {
+    Angular,
+    Euclidean,
+    Manhattan,
+    Dot,
+}
+
+impl AnnoyMetric {
+    /// Bytes before the vector in a node.
+    fn vector_offset(self) -> usize {
+        match self {
+            // n_descendants, children[2]
+            AnnoyMetric::Angular => 12,
+            // n_descendants, a or dot_factor, children[2]
+            AnnoyMetric::Euclidean | AnnoyMetric::Manhattan | AnnoyMetric::Dot => 16,
+        }
+    }
+}
+
+impl FromStr for AnnoyMetric {
+    type Err = ProximityError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "angular" => Ok(AnnoyMetric::Angular),
+            "euclidean" => Ok(AnnoyMetric::Euclidean),
+            "manhattan" => Ok(AnnoyMetric::Manhattan),
+            "dot" => Ok(AnnoyMetric::Dot),
+            other => Err(ProximityError::InvalidArgument(format!(
+                "unknown Annoy metric '{other}', expected angular, euclidean, manhattan or dot"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for AnnoyMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AnnoyMetric::Angular => "angular",
+            AnnoyMetric::Euclidean => "euclidean",
+            AnnoyMetric::Manhattan => "manhattan",
+            AnnoyMetric::Dot => "dot",
+        })
+    }
+}
+
+/// Header of an index saved by hnswlib's `save_index`, in bytes.
+const HNSWLIB_HEADER: usize = 96;
+/// Bit of the third byte of an element's level-0 links marking it deleted.
+const HNSWLIB_DELETED: u8 = 0x01;
+
+fn usize_at(bytes: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+}
+
+fn f32s(bytes: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    bytes
+        .chunks_exact(4)
+        .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+}
+
+/// Reads the vectors and labels of an index saved by hnswlib with a float space (`l2`,
+/// `ip` or `cosine`). Elements marked deleted are skipped.
+///
+/// Vectors are read as stored, so those of a `cosine` index come out normalized. Only
+/// the level-0 records are read, a record at a time, so that the graph is never held in
+/// memory.
+pub fn read_hnswlib(path: &Path) -> Result<IndexVectors> {
+    let file = File::open(path)?;
+    let file_bytes = file.metadata()?.len() as usize;
+    let mut reader = BufReader::new(file);
+    let mut header = [0u8; HNSWLIB_HEADER];
+    reader.read_exact(&mut header)?;
+    let links_offset = usize_at(&header, 0);
+    let elements = usize_at(&header, 16);
+    let element_bytes = usize_at(&header, 24);
+    let label_offset = usize_at(&header, 32);
+    let data_offset = usize_at(&header, 40);
+    let data_bytes = label_offset.saturating_sub(data_offset);
+    let level0_bytes = elements
+        .checked_mul(element_bytes)
+        .and_then(|bytes| bytes.checked_add(HNSWLIB_HEADER));
+    if data_bytes == 0
+        || !data_bytes.is_multiple_of(4)
+        || element_bytes < label_offset.saturating_add(8)
+        || element_bytes < links_offset.saturating_add(3)
+        || level0_bytes.is_none_or(|bytes| bytes > file_bytes)
+    {
+        return Err(ProximityError::InvalidData(format!(
+            "not an hnswlib index of float vectors: elements of {element_bytes} bytes, \
+             data at {data_offset} and labels at {label_offset}"
+        )));
+    }
+    let dim = data_bytes / 4;
+    let mut index = IndexVectors {
+        vectors: Vec::with_capacity(elements * dim),
+        labels: Vec::with_capacity(elements),
+        dim,
+    };
+    let mut element = vec![0u8; element_bytes];
+    for _ in 0..elements {
+        reader.read_exact(&mut element)?;
+        if element[links_offset + 2] & HNSWLIB_DELETED != 0 {
+            continue;
+        }
+        index
+            .vectors
+            .extend(f32s(&element[data_offset..label_offset]));
+        index.labels.push(usize_at(&element, label_offset) as u64);
+    }
+    Ok(index)
+}
+
+/// Reads the items of an index saved by Annoy with float vectors of dimension `dim`,
+/// which the file does not record, nor the `metric`. Labels are the item ids, and ids
+/// never added are skipped.
+///
+/// The number of items is read off the roots, at the end of the file, then the items
+/// are read a node at a time from the start, so that the trees are never held in memory.
+pub fn read_annoy(path: &Path, dim: usize, metric: AnnoyMetric) -> Result<IndexVectors> {
+    if dim == 0 {
+        return Err(ProximityError::InvalidArgument(
+            "vector dimension must be positive".into(),
+        ));
+    }
+    let offset = metric.vector_offset();
+    let node_bytes = offset + 4 * dim;
+    let mut reader = BufReader::new(File::open(path)?);
+    let file_bytes = reader.seek(SeekFrom::End(0))? as usize;
+    if file_bytes == 0 || !file_bytes.is_multiple_of(node_bytes) {
+        return Err(ProximityError::InvalidData(format!(
+            "{file_bytes} bytes do not hold {metric} nodes of {dim} dimensions"
+        )));
+    }
+    let mut node = vec![0u8; node_bytes];
+    reader.seek(SeekFrom::Start((file_bytes - node_bytes) as u64))?;
+    reader.read_exact(&mut node)?;
+    let descendants = |node: &[u8]| i32::from_le_bytes(node[..4].try_into().unwrap());
+    let items = descendants(&node);
+    if items < 0 || items as usize > file_bytes / node_bytes {
+        return Err(ProximityError::InvalidData(format!(
+            "roots count {items} items in a file of {} nodes",
+            file_bytes / node_bytes
+        )));
+    }
+    reader.seek(SeekFrom::Start(0))?;
+    let mut index = IndexVectors {
+        dim,
+        ..Default::default()
+    };
+    for id in 0..items as u64 {
+        reader.read_exact(&mut node)?;
+        if descendants(&node) != 1 {
+            continue;
+        }
+        index.vectors.extend(f32s(&node[offset..]));
+        index.labels.push(id);
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn write(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn vector_bytes(x: f32, dim: usize) -> Vec<u8> {
+        (0..dim).flat_map(|_| x.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_read_hnswlib() {
+        // M0 = 2, so the level-0 links are a count and 2 ids
+        let (dim, links) = (3, 12);
+        let element_bytes = links + 4 * dim + 8;
+        let mut bytes = Vec::new();
+        for field in [0, 8, 3, element_bytes, links + 4 * dim, links] {
+            bytes.extend((field as u64).to_le_bytes());
+        }
+        bytes.extend(0i32.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        for field in [1u64, 2, 1] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.extend(1.0f64.to_le_bytes());
+        bytes.extend(100u64.to_le_bytes());
+        assert_eq!(bytes.len(), HNSWLIB_HEADER);
+        for (x, label, deleted) in [(1.0, 40u64, false), (2.0, 7, true), (3.0, 9, false)] {
+            bytes.extend([0, 0, deleted as u8, 0]);
+            bytes.extend([0; 8]);
+            bytes.extend(vector_bytes(x, dim));
+            bytes.extend(label.to_le_bytes());
+        }
+        // the upper levels, which are not read
+        bytes.extend([0; 12]);
+        let path = write("proximity_test_index.hnsw", &bytes);
+        let index = read_hnswlib(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(index.dim, 3);
+        assert_eq!(index.labels, [40, 9]);
+        assert_eq!(index.vectors, [1.0, 1.0, 1.0, 3.0, 3.0, 3.0]);
+
+        let path = write(
+            "proximity_test_bad_index.hnsw",
+            &bytes[..HNSWLIB_HEADER - 8],
+        );
+        let err = read_hnswlib(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::Io(_)));
+    }
+
+    #[test]
+    fn test_read_annoy() {
+        let dim = 2;
+        let node = |descendants: i32, x: f32| {
+            let mut node = descendants.to_le_bytes().to_vec();
+            node.extend([0; 12]);
+            node.extend(vector_bytes(x, dim));
+            node
+        };
+        // items 0 and 2, id 1 never added, then a split node and two roots
+        let mut bytes = Vec::new();
+        for (descendants, x) in [(1, 1.0), (0, 0.0), (1, 3.0), (2, 0.5), (3, 0.0), (3, 0.0)] {
+            bytes.extend(node(descendants, x));
+        }
+        let path = write("proximity_test_index.annoy", &bytes);
+        let metric: AnnoyMetric = "euclidean".parse().unwrap();
+        let index = read_annoy(&path, dim, metric).unwrap();
+        assert_eq!(index.labels, [0, 2]);
+        assert_eq!(
+            index.rows().collect::<Vec<_>>(),
+            [(&[1.0, 1.0][..], 0), (&[3.0, 3.0][..], 2)]
+        );
+
+        // nodes of another metric are of another size
+        let err = read_annoy(&path, dim, AnnoyMetric::Angular).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+        assert!("hamming".parse::<AnnoyMetric>().is_err());
+    }
+}
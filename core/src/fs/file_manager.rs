@@ -1,23 +1,492 @@
-use std::{fs, path::Path};
+use std::{fs, io::Read, path::Path};
 
-pub fn read_from_file_f32(path: &Path) -> Vec<f32> {
-    let file_u8 = fs::read(path).unwrap();
+use npyz::{half::f16, DType, NpyFile, Order, TypeChar};
 
-    // Each record is 516 bytes: 4-byte header + 512 bytes (128 f32)
-    let mut out = Vec::with_capacity(file_u8.len() / 516 * 128);
+use crate::{ProximityError, Result};
 
-    for rec in file_u8.chunks_exact(516) {
-        // skip the first 4 bytes
-        for b in rec[4..].chunks_exact(4) {
-            // safe because chunks_exact(4) yields exactly 4 bytes
-            out.push(f32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+/// Reads a file in the `.fvecs`/`.bvecs`/`.ivecs` layout used by the SIFT and GIST benchmarks:
+/// each record is a little-endian `i32` dimension followed by that many components of
+/// `component_size` bytes. Returns the flattened decoded vectors and their dimension.
+fn read_vecs<T>(
+    path: &Path,
+    component_size: usize,
+    decode: impl Fn(&[u8]) -> T,
+) -> Result<(Vec<T>, usize)> {
+    let bytes = fs::read(path)?;
+    if bytes.is_empty() {
+        return Ok((Vec::new(), 0));
+    }
+    if bytes.len() < 4 {
+        return Err(ProximityError::InvalidData("truncated vecs file".into()));
+    }
+    let dim = record_dim(bytes[..4].try_into().unwrap())?;
+    let record_len = component_size
+        .checked_mul(dim)
+        .and_then(|len| len.checked_add(4))
+        .ok_or_else(|| ProximityError::InvalidData(format!("vecs dimension {dim} too large")))?;
+    if !bytes.len().is_multiple_of(record_len) {
+        return Err(ProximityError::InvalidData("truncated vecs file".into()));
+    }
+
+    let mut out = Vec::with_capacity(bytes.len() / record_len * dim);
+    for rec in bytes.chunks_exact(record_len) {
+        let rec_dim = i32::from_le_bytes(rec[..4].try_into().unwrap());
+        if rec_dim as usize != dim {
+            return Err(ProximityError::InvalidData(format!(
+                "vecs records have dimensions {dim} and {rec_dim}"
+            )));
+        }
+        out.extend(rec[4..].chunks_exact(component_size).map(&decode));
+    }
+    Ok((out, dim))
+}
+
+/// Dimension read from the header of a vecs record, which must be positive.
+fn record_dim(header: [u8; 4]) -> Result<usize> {
+    let dim = i32::from_le_bytes(header);
+    if dim <= 0 {
+        return Err(ProximityError::InvalidData(format!(
+            "vecs dimension must be positive, got {dim}"
+        )));
+    }
+    Ok(dim as usize)
+}
+
+/// Dimension of the vectors in a `.fvecs`/`.bvecs`/`.ivecs` file, read from its first record.
+pub fn read_vecs_dim(path: &Path) -> Result<usize> {
+    let mut header = [0; 4];
+    fs::File::open(path)?.read_exact(&mut header)?;
+    record_dim(header)
+}
+
+/// Reads a `.fvecs` file of `f32` vectors. Returns the flattened vectors and their dimension.
+pub fn read_fvecs(path: &Path) -> Result<(Vec<f32>, usize)> {
+    read_vecs(path, 4, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Reads a `.bvecs` file of `u8` vectors. Returns the flattened vectors and their dimension.
+pub fn read_bvecs(path: &Path) -> Result<(Vec<u8>, usize)> {
+    read_vecs(path, 1, |b| b[0])
+}
+
+/// Reads an `.ivecs` file of `i32` vectors, typically ground-truth neighbor ids.
+/// Returns the flattened vectors and their dimension.
+pub fn read_ivecs(path: &Path) -> Result<(Vec<i32>, usize)> {
+    read_vecs(path, 4, |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Writes flattened `dim`-dimensional vectors to a `.fvecs` file.
+pub fn write_fvecs(path: &Path, data: &[f32], dim: usize) -> Result<()> {
+    if dim == 0 || !data.len().is_multiple_of(dim) {
+        return Err(ProximityError::InvalidArgument(format!(
+            "{} values do not form vectors of dimension {dim}",
+            data.len()
+        )));
+    }
+    let mut bytes = Vec::with_capacity(data.len() / dim * (4 + 4 * dim));
+    for vector in data.chunks_exact(dim) {
+        bytes.extend_from_slice(&(dim as i32).to_le_bytes());
+        for x in vector {
+            bytes.extend_from_slice(&x.to_le_bytes());
         }
     }
-    out
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Flattened vectors of a `.fvecs` file, see [`read_fvecs`] to also get their dimension.
+pub fn read_from_file_f32(path: &Path) -> Result<Vec<f32>> {
+    Ok(read_fvecs(path)?.0)
 }
 
-pub fn read_from_npy(path: &Path) -> Vec<u8> {
-    let bytes = std::fs::read(path).unwrap();
-    let npy = npyz::NpyFile::new(&bytes[..]).unwrap();
-    npy.into_vec().unwrap()
+/// Reads a 2D `.npy` array of shape `(n, d)` and converts it to `f32`.
+/// Returns the flattened rows in row-major order and their dimension `d`.
+///
+/// Supported dtypes are `f32`, `f64`, `f16`, `i8` and `u8`, in either byte order.
+/// Fortran-ordered arrays are transposed to row-major.
+pub fn read_from_npy(path: &Path) -> Result<(Vec<f32>, usize)> {
+    let bytes = fs::read(path)?;
+    let npy = NpyFile::new(&bytes[..])?;
+    let (rows, dim) = match *npy.shape() {
+        [rows, dim] => (rows as usize, dim as usize),
+        ref shape => {
+            return Err(ProximityError::InvalidData(format!(
+                "expected a 2D array, got shape {shape:?}"
+            )))
+        }
+    };
+    let order = npy.order();
+    let DType::Plain(type_str) = npy.dtype() else {
+        return Err(ProximityError::InvalidData(
+            "structured arrays are not supported".into(),
+        ));
+    };
+
+    let data: Vec<f32> = match (type_str.type_char(), type_str.size_field()) {
+        (TypeChar::Float, 4) => npy.into_vec::<f32>()?,
+        (TypeChar::Float, 8) => npy
+            .into_vec::<f64>()?
+            .into_iter()
+            .map(|x| x as f32)
+            .collect(),
+        (TypeChar::Float, 2) => npy.into_vec::<f16>()?.into_iter().map(f32::from).collect(),
+        (TypeChar::Int, 1) => npy.into_vec::<i8>()?.into_iter().map(f32::from).collect(),
+        (TypeChar::Uint, 1) => npy.into_vec::<u8>()?.into_iter().map(f32::from).collect(),
+        _ => {
+            return Err(ProximityError::InvalidData(format!(
+                "unsupported dtype {type_str}"
+            )))
+        }
+    };
+
+    if order == Order::Fortran {
+        let mut transposed = vec![0.0; data.len()];
+        for (col, column) in data.chunks_exact(rows.max(1)).enumerate() {
+            for (row, &x) in column.iter().enumerate() {
+                transposed[row * dim + col] = x;
+            }
+        }
+        return Ok((transposed, dim));
+    }
+    Ok((data, dim))
+}
+
+/// Appends the rows of the fixed-size-list float column `column` of `batch` to `out`,
+/// checking that they have dimension `dim`, or setting it if this is the first batch.
+#[cfg(feature = "arrow")]
+fn append_embeddings(
+    batch: &arrow_array::RecordBatch,
+    column: &str,
+    out: &mut Vec<f32>,
+    dim: &mut Option<usize>,
+) -> Result<()> {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float16Type, Float32Type, Float64Type};
+    use arrow_array::Array;
+
+    let array = batch
+        .column_by_name(column)
+        .ok_or_else(|| ProximityError::InvalidData(format!("no column `{column}`")))?;
+    let list = array.as_fixed_size_list_opt().ok_or_else(|| {
+        ProximityError::InvalidData(format!("column `{column}` is not a fixed-size list"))
+    })?;
+    if list.null_count() > 0 {
+        return Err(ProximityError::InvalidData(format!(
+            "column `{column}` contains null embeddings"
+        )));
+    }
+    let row_dim = list.value_length() as usize;
+    if *dim.get_or_insert(row_dim) != row_dim {
+        return Err(ProximityError::InvalidData(format!(
+            "column `{column}` has rows of different dimensions"
+        )));
+    }
+
+    let values = list.values();
+    if let Some(floats) = values.as_primitive_opt::<Float32Type>() {
+        out.extend(floats.values().iter());
+    } else if let Some(doubles) = values.as_primitive_opt::<Float64Type>() {
+        out.extend(doubles.values().iter().map(|&x| x as f32));
+    } else if let Some(halves) = values.as_primitive_opt::<Float16Type>() {
+        out.extend(halves.values().iter().map(|&x| x.to_f32()));
+    } else {
+        return Err(ProximityError::InvalidData(format!(
+            "column `{column}` holds {} instead of floats",
+            values.data_type()
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the embeddings stored in the fixed-size-list float column `column` of a Parquet file.
+/// Returns the flattened rows as `f32` and their dimension.
+#[cfg(feature = "arrow")]
+pub fn read_parquet_embeddings(path: &Path, column: &str) -> Result<(Vec<f32>, usize)> {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::arrow::ProjectionMask;
+
+    let file = fs::File::open(path)?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+        .map_err(|e| ProximityError::InvalidData(e.to_string()))?;
+    let root = builder
+        .schema()
+        .index_of(column)
+        .map_err(|e| ProximityError::InvalidData(e.to_string()))?;
+    let mask = ProjectionMask::roots(builder.parquet_schema(), [root]);
+    let reader = builder
+        .with_projection(mask)
+        .build()
+        .map_err(|e| ProximityError::InvalidData(e.to_string()))?;
+
+    let mut out = Vec::new();
+    let mut dim = None;
+    for batch in reader {
+        let batch = batch.map_err(|e| ProximityError::InvalidData(e.to_string()))?;
+        append_embeddings(&batch, column, &mut out, &mut dim)?;
+    }
+    Ok((out, dim.unwrap_or(0)))
+}
+
+/// Reads the embeddings stored in the fixed-size-list float column `column` of an
+/// Arrow IPC file. Returns the flattened rows as `f32` and their dimension.
+#[cfg(feature = "arrow")]
+pub fn read_arrow_embeddings(path: &Path, column: &str) -> Result<(Vec<f32>, usize)> {
+    let file = fs::File::open(path)?;
+    let reader = arrow_ipc::reader::FileReader::try_new(std::io::BufReader::new(file), None)
+        .map_err(|e| ProximityError::InvalidData(e.to_string()))?;
+
+    let mut out = Vec::new();
+    let mut dim = None;
+    for batch in reader {
+        let batch = batch.map_err(|e| ProximityError::InvalidData(e.to_string()))?;
+        append_embeddings(&batch, column, &mut out, &mut dim)?;
+    }
+    Ok((out, dim.unwrap_or(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fvecs_roundtrip() {
+        let path = std::env::temp_dir().join("proximity_test_fvecs_roundtrip.fvecs");
+        let data: Vec<f32> = (0..24).map(|x| x as f32 * 0.5).collect();
+        write_fvecs(&path, &data, 8).unwrap();
+        let (read, dim) = read_fvecs(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(dim, 8);
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn test_vecs_bad_dimension_is_invalid_data() {
+        let path = std::env::temp_dir().join("proximity_test_vecs_bad_dimension.fvecs");
+        for dim in [0i32, -1, i32::MAX] {
+            let mut bytes = dim.to_le_bytes().to_vec();
+            bytes.extend_from_slice(&[0; 8]);
+            fs::write(&path, bytes).unwrap();
+            assert!(matches!(
+                read_fvecs(&path),
+                Err(ProximityError::InvalidData(_))
+            ));
+            assert!(matches!(
+                read_bvecs(&path),
+                Err(ProximityError::InvalidData(_))
+            ));
+        }
+        fs::write(&path, (-4i32).to_le_bytes()).unwrap();
+        assert!(matches!(
+            read_vecs_dim(&path),
+            Err(ProximityError::InvalidData(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn write_npy<T: npyz::AutoSerialize>(
+        name: &str,
+        data: Vec<T>,
+        shape: &[u64],
+        order: Order,
+    ) -> std::path::PathBuf {
+        use npyz::WriterBuilder;
+
+        let path = std::env::temp_dir().join(name);
+        let file = fs::File::create(&path).unwrap();
+        let mut writer = npyz::WriteOptions::new()
+            .default_dtype()
+            .shape(shape)
+            .order(order)
+            .writer(file)
+            .begin_nd()
+            .unwrap();
+        writer.extend(data).unwrap();
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_npy_dtypes() {
+        let expected = vec![1.0, -2.0, 3.5, 0.0, 4.0, 8.0];
+        let paths = [
+            write_npy(
+                "proximity_test_f32.npy",
+                expected.clone(),
+                &[2, 3],
+                Order::C,
+            ),
+            write_npy(
+                "proximity_test_f64.npy",
+                expected.iter().map(|&x| f64::from(x)).collect(),
+                &[2, 3],
+                Order::C,
+            ),
+            write_npy(
+                "proximity_test_f16.npy",
+                expected.iter().map(|&x| f16::from_f32(x)).collect(),
+                &[2, 3],
+                Order::C,
+            ),
+        ];
+        for path in paths {
+            let (read, dim) = read_from_npy(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(dim, 3);
+            assert_eq!(read, expected);
+        }
+
+        let path = write_npy(
+            "proximity_test_i8.npy",
+            vec![-1i8, 2, 3, 4],
+            &[2, 2],
+            Order::C,
+        );
+        let (read, dim) = read_from_npy(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(dim, 2);
+        assert_eq!(read, vec![-1.0, 2.0, 3.0, 4.0]);
+
+        let path = write_npy("proximity_test_u8.npy", vec![255u8, 0], &[1, 2], Order::C);
+        assert_eq!(read_from_npy(&path).unwrap(), (vec![255.0, 0.0], 2));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_npy_fortran_order() {
+        // columns (1, 2, 3) and (4, 5, 6)
+        let path = write_npy(
+            "proximity_test_fortran.npy",
+            vec![1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0],
+            &[3, 2],
+            Order::Fortran,
+        );
+        let (read, dim) = read_from_npy(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(dim, 2);
+        assert_eq!(read, vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+    }
+
+    #[test]
+    fn test_read_npy_mismatches() {
+        let path = write_npy("proximity_test_1d.npy", vec![1.0f32, 2.0], &[2], Order::C);
+        let err = read_from_npy(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+
+        let path = write_npy("proximity_test_i32.npy", vec![1i32, 2], &[1, 2], Order::C);
+        let err = read_from_npy(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+    }
+
+    #[cfg(feature = "arrow")]
+    fn embedding_batch(dim: i32, rows: &[f32]) -> arrow_array::RecordBatch {
+        use arrow_array::{ArrayRef, FixedSizeListArray, Float32Array, Int64Array};
+        use arrow_schema::{DataType, Field};
+        use std::sync::Arc;
+
+        let item = Arc::new(Field::new("item", DataType::Float32, false));
+        let embeddings =
+            FixedSizeListArray::new(item, dim, Arc::new(Float32Array::from(rows.to_vec())), None);
+        let ids = Int64Array::from_iter_values(0..rows.len() as i64 / dim as i64);
+        arrow_array::RecordBatch::try_from_iter([
+            ("id", Arc::new(ids) as ArrayRef),
+            ("embedding", Arc::new(embeddings) as ArrayRef),
+        ])
+        .unwrap()
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_read_parquet_embeddings() {
+        let path = std::env::temp_dir().join("proximity_test_embeddings.parquet");
+        let first = embedding_batch(2, &[1.0, 2.0, 3.0, 4.0]);
+        let second = embedding_batch(2, &[5.0, 6.0]);
+        let file = fs::File::create(&path).unwrap();
+        let mut writer = parquet::arrow::ArrowWriter::try_new(file, first.schema(), None).unwrap();
+        writer.write(&first).unwrap();
+        writer.write(&second).unwrap();
+        writer.close().unwrap();
+
+        let (read, dim) = read_parquet_embeddings(&path, "embedding").unwrap();
+        let err = read_parquet_embeddings(&path, "id").unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(dim, 2);
+        assert_eq!(read, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_read_arrow_embeddings() {
+        let path = std::env::temp_dir().join("proximity_test_embeddings.arrow");
+        let first = embedding_batch(3, &[1.0, 2.0, 3.0]);
+        let mismatched = embedding_batch(2, &[4.0, 5.0]);
+        let file = fs::File::create(&path).unwrap();
+        let mut writer = arrow_ipc::writer::FileWriter::try_new(file, &first.schema()).unwrap();
+        writer.write(&first).unwrap();
+        writer.finish().unwrap();
+
+        let (read, dim) = read_arrow_embeddings(&path, "embedding").unwrap();
+        assert_eq!(dim, 3);
+        assert_eq!(read, vec![1.0, 2.0, 3.0]);
+
+        let mut out = Vec::new();
+        let mut dim = Some(3);
+        let err = append_embeddings(&mismatched, "embedding", &mut out, &mut dim).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+    }
+
+    /// Writes records of the given dimension and raw component bytes.
+    fn write_records(name: &str, dim: i32, records: &[&[u8]]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let mut bytes = Vec::new();
+        for rec in records {
+            bytes.extend_from_slice(&dim.to_le_bytes());
+            bytes.extend_from_slice(rec);
+        }
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_bvecs() {
+        let path = write_records("proximity_test_read.bvecs", 3, &[&[1, 2, 3], &[250, 0, 7]]);
+        let (read, dim) = read_bvecs(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(dim, 3);
+        assert_eq!(read, vec![1, 2, 3, 250, 0, 7]);
+    }
+
+    #[test]
+    fn test_read_ivecs() {
+        let rec: Vec<u8> = [7i32, -1].iter().flat_map(|x| x.to_le_bytes()).collect();
+        let path = write_records("proximity_test_read.ivecs", 2, &[&rec]);
+        let (read, dim) = read_ivecs(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(dim, 2);
+        assert_eq!(read, vec![7, -1]);
+    }
+
+    #[test]
+    fn test_fvecs_of_any_dimension() {
+        let path = std::env::temp_dir().join("proximity_test_fvecs_dim.fvecs");
+        let data: Vec<f32> = (0..12).map(|x| x as f32).collect();
+        write_fvecs(&path, &data, 3).unwrap();
+        let read = read_from_file_f32(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn test_mixed_dimensions() {
+        let path = write_records("proximity_test_mixed.bvecs", 2, &[&[1, 2], &[3, 4]]);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[6] = 1;
+        fs::write(&path, bytes).unwrap();
+        let err = read_bvecs(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+    }
 }
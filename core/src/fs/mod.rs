@@ -1,2 +1,11 @@
+#[cfg(feature = "hdf5")]
+mod ann_benchmarks;
+mod ann_index;
 pub mod file_manager;
+mod stream;
 pub mod vector_type;
+
+#[cfg(feature = "hdf5")]
+pub use ann_benchmarks::AnnBenchmark;
+pub use ann_index::{read_annoy, read_hnswlib, AnnoyMetric, IndexVectors};
+pub use stream::{stream_npy, stream_vectors, NpyStream, VectorStream};
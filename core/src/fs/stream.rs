@@ -0,0 +1,373 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, ErrorKind, Read},
+    path::Path,
+};
+
+use npyz::{half::f16, DType, NpyFile, Order, TypeChar};
+
+use crate::{ProximityError, Result};
+
+/// On-disk layout of the rows read by a [`VectorStream`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Layout {
+    /// Records of an `i32` dimension followed by `f32` components.
+    Fvecs,
+    /// Records of an `i32` dimension followed by `u8` components.
+    Bvecs,
+    /// Headerless little-endian `f32` rows.
+    Raw,
+}
+
+impl Layout {
+    fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("fvecs") => Layout::Fvecs,
+            Some("bvecs") => Layout::Bvecs,
+            _ => Layout::Raw,
+        }
+    }
+
+    fn header_len(self) -> usize {
+        match self {
+            Layout::Fvecs | Layout::Bvecs => 4,
+            Layout::Raw => 0,
+        }
+    }
+
+    fn component_size(self) -> usize {
+        match self {
+            Layout::Fvecs | Layout::Raw => 4,
+            Layout::Bvecs => 1,
+        }
+    }
+}
+
+/// Iterator over a vector file in chunks of at most `chunk_rows` rows, see [`stream_vectors`].
+pub struct VectorStream {
+    reader: BufReader<File>,
+    layout: Layout,
+    dim: usize,
+    chunk_rows: usize,
+    record: Vec<u8>,
+    done: bool,
+}
+
+/// Streams the rows of a vector file as flattened `f32` chunks of at most `chunk_rows` rows,
+/// so that only one chunk is held in memory at a time.
+///
+/// `.fvecs` and `.bvecs` files are recognized by their extension, and every record header
+/// must match `dim`. Any other file is read as headerless little-endian `f32` rows.
+/// Chunks are only read when the iterator is advanced, so a slow consumer never makes
+/// the stream read ahead.
+pub fn stream_vectors(path: &Path, dim: usize, chunk_rows: usize) -> Result<VectorStream> {
+    if dim == 0 || chunk_rows == 0 {
+        return Err(ProximityError::InvalidArgument(
+            "dimension and chunk size must be positive".into(),
+        ));
+    }
+    let layout = Layout::of(path);
+    let record_len = layout.header_len() + dim * layout.component_size();
+    let file = File::open(path)?;
+    Ok(VectorStream {
+        reader: BufReader::with_capacity(record_len * chunk_rows, file),
+        layout,
+        dim,
+        chunk_rows,
+        record: vec![0; record_len],
+        done: false,
+    })
+}
+
+impl VectorStream {
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Reads one record into `self.record`. Returns `false` at a clean end of file.
+    fn read_record(&mut self) -> Result<bool> {
+        let mut filled = 0;
+        while filled < self.record.len() {
+            match self.reader.read(&mut self.record[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => {
+                    return Err(ProximityError::InvalidData(
+                        "file ends in the middle of a vector".into(),
+                    ))
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(true)
+    }
+
+    fn read_chunk(&mut self) -> Result<Vec<f32>> {
+        let mut chunk = Vec::with_capacity(self.chunk_rows * self.dim);
+        for _ in 0..self.chunk_rows {
+            if !self.read_record()? {
+                self.done = true;
+                break;
+            }
+            let (header, body) = self.record.split_at(self.layout.header_len());
+            if !header.is_empty() {
+                let rec_dim = i32::from_le_bytes(header.try_into().unwrap());
+                if rec_dim as usize != self.dim {
+                    return Err(ProximityError::InvalidData(format!(
+                        "expected vectors of dimension {}, found {rec_dim}",
+                        self.dim
+                    )));
+                }
+            }
+            match self.layout {
+                Layout::Fvecs | Layout::Raw => chunk.extend(
+                    body.chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                ),
+                Layout::Bvecs => chunk.extend(body.iter().map(|&b| f32::from(b))),
+            }
+        }
+        Ok(chunk)
+    }
+}
+
+impl Iterator for VectorStream {
+    type Item = Result<Vec<f32>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_chunk() {
+            Ok(chunk) if chunk.is_empty() => None,
+            Ok(chunk) => Some(Ok(chunk)),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Iterator over the rows of a `.npy` file in chunks of at most `chunk_rows` rows, see
+/// [`stream_npy`].
+pub struct NpyStream {
+    components: Box<dyn Iterator<Item = io::Result<f32>> + Send>,
+    rows: usize,
+    dim: usize,
+    chunk_rows: usize,
+    done: bool,
+}
+
+/// Streams the rows of a 2D `.npy` array of shape `(n, d)` as flattened `f32` chunks of
+/// at most `chunk_rows` rows, so that only one chunk is held in memory at a time.
+///
+/// Supports the dtypes of [`read_from_npy`](crate::fs::file_manager::read_from_npy), in
+/// either byte order, but only row-major arrays: Fortran-ordered ones cannot be read a
+/// row at a time.
+pub fn stream_npy(path: &Path, chunk_rows: usize) -> Result<NpyStream> {
+    if chunk_rows == 0 {
+        return Err(ProximityError::InvalidArgument(
+            "chunk size must be positive".into(),
+        ));
+    }
+    let npy = NpyFile::new(BufReader::new(File::open(path)?))?;
+    let (rows, dim) = match *npy.shape() {
+        [rows, dim] => (rows as usize, dim as usize),
+        ref shape => {
+            return Err(ProximityError::InvalidData(format!(
+                "expected a 2D array, got shape {shape:?}"
+            )))
+        }
+    };
+    if npy.order() == Order::Fortran {
+        return Err(ProximityError::InvalidData(
+            "Fortran-ordered arrays cannot be streamed".into(),
+        ));
+    }
+    let DType::Plain(type_str) = npy.dtype() else {
+        return Err(ProximityError::InvalidData(
+            "structured arrays are not supported".into(),
+        ));
+    };
+    let dtype_error = |e: npyz::DTypeError| ProximityError::InvalidData(e.to_string());
+    let components: Box<dyn Iterator<Item = io::Result<f32>> + Send> =
+        match (type_str.type_char(), type_str.size_field()) {
+            (TypeChar::Float, 4) => Box::new(npy.data::<f32>().map_err(dtype_error)?),
+            (TypeChar::Float, 8) => Box::new(
+                npy.data::<f64>()
+                    .map_err(dtype_error)?
+                    .map(|x| x.map(|x| x as f32)),
+            ),
+            (TypeChar::Float, 2) => Box::new(
+                npy.data::<f16>()
+                    .map_err(dtype_error)?
+                    .map(|x| x.map(f32::from)),
+            ),
+            (TypeChar::Int, 1) => Box::new(
+                npy.data::<i8>()
+                    .map_err(dtype_error)?
+                    .map(|x| x.map(f32::from)),
+            ),
+            (TypeChar::Uint, 1) => Box::new(
+                npy.data::<u8>()
+                    .map_err(dtype_error)?
+                    .map(|x| x.map(f32::from)),
+            ),
+            _ => {
+                return Err(ProximityError::InvalidData(format!(
+                    "unsupported dtype {type_str}"
+                )))
+            }
+        };
+    Ok(NpyStream {
+        components,
+        rows,
+        dim,
+        chunk_rows,
+        done: rows == 0 || dim == 0,
+    })
+}
+
+impl NpyStream {
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Number of rows of the whole array, as given by its header.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    fn read_chunk(&mut self) -> Result<Vec<f32>> {
+        let len = self.chunk_rows * self.dim;
+        let mut chunk = Vec::with_capacity(len);
+        for component in self.components.by_ref().take(len) {
+            chunk.push(component?);
+        }
+        if chunk.len() < len {
+            self.done = true;
+        }
+        if !chunk.len().is_multiple_of(self.dim) {
+            return Err(ProximityError::InvalidData(
+                "file ends in the middle of a vector".into(),
+            ));
+        }
+        Ok(chunk)
+    }
+}
+
+impl Iterator for NpyStream {
+    type Item = Result<Vec<f32>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_chunk() {
+            Ok(chunk) if chunk.is_empty() => None,
+            Ok(chunk) => Some(Ok(chunk)),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::file_manager::write_fvecs;
+
+    #[test]
+    fn test_stream_fvecs_in_chunks() {
+        let path = std::env::temp_dir().join("proximity_test_stream.fvecs");
+        let data: Vec<f32> = (0..20).map(|x| x as f32).collect();
+        write_fvecs(&path, &data, 4).unwrap();
+        let chunks: Vec<Vec<f32>> = stream_vectors(&path, 4, 2)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lens: Vec<usize> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(lens, vec![8, 8, 4]);
+        assert_eq!(chunks.concat(), data);
+    }
+
+    #[test]
+    fn test_stream_raw_and_bvecs() {
+        let raw = std::env::temp_dir().join("proximity_test_stream.bin");
+        let bytes: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        std::fs::write(&raw, bytes).unwrap();
+        let chunks: Vec<_> = stream_vectors(&raw, 2, 8).unwrap().collect();
+        std::fs::remove_file(&raw).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].as_ref().unwrap(), &vec![1.0, 2.0, 3.0, 4.0]);
+
+        let bvecs = std::env::temp_dir().join("proximity_test_stream.bvecs");
+        std::fs::write(&bvecs, [2, 0, 0, 0, 7, 255]).unwrap();
+        let chunks: Vec<_> = stream_vectors(&bvecs, 2, 8).unwrap().collect();
+        std::fs::remove_file(&bvecs).unwrap();
+        assert_eq!(chunks[0].as_ref().unwrap(), &vec![7.0, 255.0]);
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        let path = std::env::temp_dir().join("proximity_test_stream_dim.fvecs");
+        write_fvecs(&path, &[0.0; 8], 8).unwrap();
+        let mut stream = stream_vectors(&path, 4, 1).unwrap();
+        let err = stream.next().unwrap().unwrap_err();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+        assert!(stream.next().is_none());
+
+        // half a record
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(20);
+        std::fs::write(&path, bytes).unwrap();
+        let mut stream = stream_vectors(&path, 8, 1).unwrap();
+        let err = stream.next().unwrap().unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+    }
+
+    #[test]
+    fn test_stream_npy_in_chunks() {
+        use npyz::WriterBuilder;
+
+        let write = |name: &str, order: Order| {
+            let path = std::env::temp_dir().join(name);
+            let mut writer = npyz::WriteOptions::new()
+                .default_dtype()
+                .shape(&[5, 2])
+                .order(order)
+                .writer(File::create(&path).unwrap())
+                .begin_nd()
+                .unwrap();
+            writer.extend((0..10).map(f64::from)).unwrap();
+            writer.finish().unwrap();
+            path
+        };
+        let path = write("proximity_test_stream.npy", Order::C);
+        let stream = stream_npy(&path, 2).unwrap();
+        assert_eq!((stream.rows(), stream.dim()), (5, 2));
+        let chunks: Vec<Vec<f32>> = stream.collect::<Result<_>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lens: Vec<usize> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(lens, vec![4, 4, 2]);
+        assert_eq!(
+            chunks.concat(),
+            (0..10).map(|x| x as f32).collect::<Vec<_>>()
+        );
+
+        let path = write("proximity_test_stream_fortran.npy", Order::Fortran);
+        let err = stream_npy(&path, 2).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ProximityError::InvalidData(_)));
+    }
+}
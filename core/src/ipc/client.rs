@@ -0,0 +1,100 @@
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use crate::ipc::protocol::{read_frame, write_frame, IpcStats, Request, Response};
+use crate::{ProximityError, Result};
+
+/// Client side of the IPC protocol, see [`IpcServer`](crate::ipc::IpcServer).
+///
+/// Errors raised by the daemon, such as a key of the wrong dimension, come back as
+/// [`ProximityError::InvalidArgument`] and leave the connection usable.
+///
+/// # Example Usage
+/// ```no_run
+/// use proximity::ipc::IpcClient;
+///
+/// let mut client = IpcClient::connect("/tmp/proximity.sock").unwrap();
+/// client.insert(&[1.0; 8], b"Value 1", Some(0.5)).unwrap();
+/// assert_eq!(client.find(&[1.0; 8]).unwrap(), Some(b"Value 1".to_vec()));
+/// ```
+pub struct IpcClient {
+    stream: UnixStream,
+}
+
+fn unexpected() -> ProximityError {
+    ProximityError::InvalidData("unexpected response from the daemon".into())
+}
+
+impl IpcClient {
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            stream: UnixStream::connect(path)?,
+        })
+    }
+
+    fn call(&mut self, request: Request) -> Result<Response> {
+        write_frame(&mut self.stream, &request.encode())?;
+        let body = read_frame(&mut self.stream)?.ok_or_else(|| {
+            ProximityError::InvalidData("the daemon closed the connection".into())
+        })?;
+        match Response::decode(&body, &request)? {
+            Response::Error(msg) => Err(ProximityError::InvalidArgument(msg)),
+            response => Ok(response),
+        }
+    }
+
+    pub fn find(&mut self, key: &[f32]) -> Result<Option<Vec<u8>>> {
+        match self.call(Request::Find(key.to_vec()))? {
+            Response::Found(value) => Ok(value),
+            _ => Err(unexpected()),
+        }
+    }
+
+    /// Looks up every key in a single round trip.
+    pub fn batch_find(&mut self, keys: &[Vec<f32>]) -> Result<Vec<Option<Vec<u8>>>> {
+        match self.call(Request::BatchFind(keys.to_vec()))? {
+            Response::BatchFound(values) => Ok(values),
+            _ => Err(unexpected()),
+        }
+    }
+
+    /// Inserts with the given tolerance, or else the daemon cache's default one, and
+    /// returns how many entries left the cache to make room.
+    pub fn insert(&mut self, key: &[f32], value: &[u8], tolerance: Option<f32>) -> Result<usize> {
+        let request = Request::Insert {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            tolerance,
+        };
+        match self.call(request)? {
+            Response::Inserted(evicted) => Ok(evicted as usize),
+            _ => Err(unexpected()),
+        }
+    }
+
+    pub fn len(&mut self) -> Result<usize> {
+        match self.call(Request::Len)? {
+            Response::Len(len) => Ok(len as usize),
+            _ => Err(unexpected()),
+        }
+    }
+
+    pub fn is_empty(&mut self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Empties the daemon cache, for every client.
+    pub fn clear(&mut self) -> Result<()> {
+        match self.call(Request::Clear)? {
+            Response::Cleared => Ok(()),
+            _ => Err(unexpected()),
+        }
+    }
+
+    pub fn stats(&mut self) -> Result<IpcStats> {
+        match self.call(Request::Stats)? {
+            Response::Stats(stats) => Ok(stats),
+            _ => Err(unexpected()),
+        }
+    }
+}
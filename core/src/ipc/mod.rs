@@ -0,0 +1,7 @@
+mod client;
+mod protocol;
+mod server;
+
+pub use client::IpcClient;
+pub use protocol::IpcStats;
+pub use server::IpcServer;
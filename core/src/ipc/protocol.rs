@@ -0,0 +1,408 @@
+//! Wire format shared by [`IpcServer`](super::IpcServer) and [`IpcClient`](super::IpcClient).
+//!
+//! Every message is a frame: its length as a little-endian `u32`, then its body.
+//! A request body starts with an opcode, a response body with a status byte, `0` for
+//! success or `1` followed by an UTF-8 error message. Integers and floats are
+//! little-endian, keys are a `u32` dimension followed by that many `f32`, and byte
+//! strings are a `u32` length followed by the bytes.
+
+use std::io::{Read, Write};
+
+use crate::{ProximityError, Result};
+
+/// Largest frame either side accepts, so a corrupt length never allocates gigabytes.
+pub(crate) const MAX_FRAME_LEN: usize = 1 << 26;
+
+const FIND: u8 = 1;
+const BATCH_FIND: u8 = 2;
+const INSERT: u8 = 3;
+const LEN: u8 = 4;
+const CLEAR: u8 = 5;
+const STATS: u8 = 6;
+
+const OK: u8 = 0;
+const ERROR: u8 = 1;
+
+/// Counters reported by a cache daemon.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IpcStats {
+    pub len: usize,
+    /// Lookups that found a match, from every client since the daemon started.
+    pub hits: u64,
+    /// Lookups that found no match, from every client since the daemon started.
+    pub misses: u64,
+    pub recent_hit_rate: f32,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Request {
+    Find(Vec<f32>),
+    BatchFind(Vec<Vec<f32>>),
+    Insert {
+        key: Vec<f32>,
+        value: Vec<u8>,
+        tolerance: Option<f32>,
+    },
+    Len,
+    Clear,
+    Stats,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Response {
+    Error(String),
+    Found(Option<Vec<u8>>),
+    BatchFound(Vec<Option<Vec<u8>>>),
+    Inserted(u32),
+    Len(u64),
+    Cleared,
+    Stats(IpcStats),
+}
+
+fn invalid(what: &str) -> ProximityError {
+    ProximityError::InvalidData(format!("malformed message: {what}"))
+}
+
+/// Reads one frame, or `None` if the peer closed the connection between frames.
+pub(crate) fn read_frame(reader: &mut impl Read) -> Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(invalid("frame too long"));
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+pub(crate) fn write_frame(writer: &mut impl Write, body: &[u8]) -> Result<()> {
+    if body.len() > MAX_FRAME_LEN {
+        return Err(ProximityError::InvalidArgument(format!(
+            "message of {} bytes exceeds the limit of {MAX_FRAME_LEN}",
+            body.len()
+        )));
+    }
+    writer.write_all(&(body.len() as u32).to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, x: u8) -> &mut Self {
+        self.0.push(x);
+        self
+    }
+
+    fn u32(&mut self, x: u32) -> &mut Self {
+        self.0.extend_from_slice(&x.to_le_bytes());
+        self
+    }
+
+    fn u64(&mut self, x: u64) -> &mut Self {
+        self.0.extend_from_slice(&x.to_le_bytes());
+        self
+    }
+
+    fn f32(&mut self, x: f32) -> &mut Self {
+        self.0.extend_from_slice(&x.to_le_bytes());
+        self
+    }
+
+    fn key(&mut self, key: &[f32]) -> &mut Self {
+        self.u32(key.len() as u32);
+        key.iter().for_each(|&x| {
+            self.f32(x);
+        });
+        self
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.u32(bytes.len() as u32);
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    fn found(&mut self, value: &Option<Vec<u8>>) -> &mut Self {
+        match value {
+            Some(value) => self.u8(1).bytes(value),
+            None => self.u8(0),
+        }
+    }
+}
+
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid("truncated"));
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn key(&mut self) -> Result<Vec<f32>> {
+        let dim = self.u32()? as usize;
+        let raw = self.take(dim.checked_mul(4).ok_or_else(|| invalid("key too long"))?)?;
+        Ok(raw
+            .chunks_exact(4)
+            .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+            .collect())
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn found(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.bytes()?)),
+            _ => Err(invalid("bad presence flag")),
+        }
+    }
+
+    fn count(&mut self) -> Result<usize> {
+        let count = self.u32()? as usize;
+        // every item takes at least one byte, which bounds preallocation
+        if count > self.0.len() {
+            return Err(invalid("truncated"));
+        }
+        Ok(count)
+    }
+
+    fn finish<T>(&self, decoded: T) -> Result<T> {
+        if !self.0.is_empty() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(decoded)
+    }
+}
+
+impl Request {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut e = Encoder::default();
+        match self {
+            Request::Find(key) => {
+                e.u8(FIND).key(key);
+            }
+            Request::BatchFind(keys) => {
+                e.u8(BATCH_FIND).u32(keys.len() as u32);
+                keys.iter().for_each(|key| {
+                    e.key(key);
+                });
+            }
+            Request::Insert {
+                key,
+                value,
+                tolerance,
+            } => {
+                e.u8(INSERT).key(key).bytes(value);
+                match tolerance {
+                    Some(tol) => e.u8(1).f32(*tol),
+                    None => e.u8(0),
+                };
+            }
+            Request::Len => {
+                e.u8(LEN);
+            }
+            Request::Clear => {
+                e.u8(CLEAR);
+            }
+            Request::Stats => {
+                e.u8(STATS);
+            }
+        }
+        e.0
+    }
+
+    pub(crate) fn decode(body: &[u8]) -> Result<Self> {
+        let mut d = Decoder(body);
+        let request = match d.u8()? {
+            FIND => Request::Find(d.key()?),
+            BATCH_FIND => {
+                let count = d.count()?;
+                Request::BatchFind((0..count).map(|_| d.key()).collect::<Result<_>>()?)
+            }
+            INSERT => Request::Insert {
+                key: d.key()?,
+                value: d.bytes()?,
+                tolerance: match d.u8()? {
+                    0 => None,
+                    _ => Some(d.f32()?),
+                },
+            },
+            LEN => Request::Len,
+            CLEAR => Request::Clear,
+            STATS => Request::Stats,
+            other => return Err(invalid(&format!("unknown opcode {other}"))),
+        };
+        d.finish(request)
+    }
+}
+
+impl Response {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut e = Encoder::default();
+        if let Response::Error(msg) = self {
+            e.u8(ERROR).0.extend_from_slice(msg.as_bytes());
+            return e.0;
+        }
+        e.u8(OK);
+        match self {
+            Response::Error(_) => unreachable!(),
+            Response::Found(value) => {
+                e.found(value);
+            }
+            Response::BatchFound(values) => {
+                e.u32(values.len() as u32);
+                values.iter().for_each(|value| {
+                    e.found(value);
+                });
+            }
+            Response::Inserted(evicted) => {
+                e.u32(*evicted);
+            }
+            Response::Len(len) => {
+                e.u64(*len);
+            }
+            Response::Cleared => {}
+            Response::Stats(stats) => {
+                e.u64(stats.len as u64)
+                    .u64(stats.hits)
+                    .u64(stats.misses)
+                    .f32(stats.recent_hit_rate);
+            }
+        }
+        e.0
+    }
+
+    /// Decodes the response to `request`, whose kind decides the layout of the body.
+    pub(crate) fn decode(body: &[u8], request: &Request) -> Result<Self> {
+        let mut d = Decoder(body);
+        if d.u8()? == ERROR {
+            return Ok(Response::Error(String::from_utf8_lossy(d.0).into_owned()));
+        }
+        let response = match request {
+            Request::Find(_) => Response::Found(d.found()?),
+            Request::BatchFind(_) => {
+                let count = d.count()?;
+                Response::BatchFound((0..count).map(|_| d.found()).collect::<Result<_>>()?)
+            }
+            Request::Insert { .. } => Response::Inserted(d.u32()?),
+            Request::Len => Response::Len(d.u64()?),
+            Request::Clear => Response::Cleared,
+            Request::Stats => Response::Stats(IpcStats {
+                len: d.u64()? as usize,
+                hits: d.u64()?,
+                misses: d.u64()?,
+                recent_hit_rate: d.f32()?,
+            }),
+        };
+        d.finish(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_round_trip() {
+        let requests = [
+            Request::Find(vec![1.0, -2.5]),
+            Request::BatchFind(vec![vec![1.0], vec![], vec![3.0, 4.0]]),
+            Request::Insert {
+                key: vec![0.5; 8],
+                value: b"value".to_vec(),
+                tolerance: Some(0.1),
+            },
+            Request::Insert {
+                key: vec![0.5; 8],
+                value: vec![],
+                tolerance: None,
+            },
+            Request::Len,
+            Request::Clear,
+            Request::Stats,
+        ];
+        for request in requests {
+            assert_eq!(Request::decode(&request.encode()).unwrap(), request);
+        }
+    }
+
+    #[test]
+    fn test_responses_round_trip() {
+        let pairs = [
+            (Request::Find(vec![]), Response::Found(Some(b"v".to_vec()))),
+            (Request::Find(vec![]), Response::Found(None)),
+            (
+                Request::BatchFind(vec![]),
+                Response::BatchFound(vec![None, Some(vec![1, 2])]),
+            ),
+            (Request::Len, Response::Len(7)),
+            (Request::Clear, Response::Cleared),
+            (Request::Len, Response::Error("no".into())),
+            (
+                Request::Stats,
+                Response::Stats(IpcStats {
+                    len: 1,
+                    hits: 2,
+                    misses: 3,
+                    recent_hit_rate: 0.4,
+                }),
+            ),
+        ];
+        for (request, response) in pairs {
+            let decoded = Response::decode(&response.encode(), &request).unwrap();
+            assert_eq!(decoded, response);
+        }
+    }
+
+    #[test]
+    fn test_malformed_bodies_are_rejected() {
+        assert!(Request::decode(&[]).is_err());
+        assert!(Request::decode(&[42]).is_err());
+        assert!(Request::decode(&[FIND, 2, 0, 0, 0, 0, 0]).is_err());
+        assert!(Request::decode(&[LEN, 0]).is_err());
+        assert!(Request::decode(&[BATCH_FIND, 255, 255, 255, 255]).is_err());
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, b"abc").unwrap();
+        let mut reader = &buffer[..];
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(b"abc".to_vec()));
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+        let too_long = (MAX_FRAME_LEN as u32 + 1).to_le_bytes();
+        assert!(read_frame(&mut &too_long[..]).is_err());
+    }
+}
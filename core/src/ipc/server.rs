@@ -0,0 +1,325 @@
+use std::io;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::caching::{ApproximateCache, NonFinitePolicy};
+use crate::ipc::protocol::{read_frame, write_frame, IpcStats, Request, Response, MAX_FRAME_LEN};
+use crate::numerics::PlainVector;
+use crate::{ProximityError, Result};
+
+type Job = (Request, mpsc::Sender<Response>);
+
+/// Daemon side of the IPC protocol: serves one cache over a Unix socket, so that the
+/// cache outlives the processes that use it, e.g. a notebook kernel that gets restarted.
+///
+/// Each client connection is read on its own thread, but every request is run against
+/// the cache on the thread that called [`serve`](Self::serve), in arrival order, so the
+/// cache needs neither to be [`Send`] nor to be locked.
+///
+/// Keys are float vectors and values are opaque bytes. Keys with NaN or infinite
+/// components are refused unless [`non_finite`](Self::non_finite) says otherwise. The
+/// socket file is removed when the server is dropped.
+///
+/// # Example Usage
+/// ```no_run
+/// use proximity::caching::FifoCache;
+/// use proximity::ipc::IpcServer;
+///
+/// let mut cache = FifoCache::new(1024).unwrap();
+/// let server = IpcServer::bind("/tmp/proximity.sock").unwrap();
+/// server.serve(&mut cache).unwrap();
+/// ```
+pub struct IpcServer {
+    listener: UnixListener,
+    path: PathBuf,
+    non_finite: NonFinitePolicy,
+}
+
+struct Counters {
+    hits: u64,
+    misses: u64,
+}
+
+impl IpcServer {
+    /// Listens on `path`. A socket file left behind by a daemon that is gone is replaced,
+    /// but binding fails if a daemon still answers on it.
+    pub fn bind(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(ProximityError::InvalidArgument(format!(
+                    "a daemon already listens on {}",
+                    path.display()
+                )));
+            }
+            std::fs::remove_file(path)?;
+        }
+        Ok(Self {
+            listener: UnixListener::bind(path)?,
+            path: path.to_path_buf(),
+            non_finite: NonFinitePolicy::Reject,
+        })
+    }
+
+    /// What to do with keys that have NaN or infinite components.
+    pub fn non_finite(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite = policy;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Serves clients with `cache` until accepting connections fails and every client
+    /// is gone.
+    pub fn serve<C>(&self, cache: &mut C) -> Result<()>
+    where
+        C: ApproximateCache<PlainVector, Vec<u8>> + ?Sized,
+    {
+        let listener = self.listener.try_clone()?;
+        let (jobs, received) = mpsc::channel::<Job>();
+        let acceptor = thread::spawn(move || -> io::Result<()> {
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let jobs = jobs.clone();
+                thread::spawn(move || serve_client(stream, jobs));
+            }
+            Ok(())
+        });
+        let mut counters = Counters { hits: 0, misses: 0 };
+        for (request, reply) in received {
+            // the client may have hung up in the meantime
+            let _ = reply.send(answer(cache, &mut counters, self.non_finite, request));
+        }
+        acceptor
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+        Ok(())
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn serve_client(mut stream: UnixStream, jobs: mpsc::Sender<Job>) {
+    // any I/O error means the client is gone
+    while let Ok(Some(body)) = read_frame(&mut stream) {
+        let response = match Request::decode(&body) {
+            Ok(request) => {
+                let (reply, response) = mpsc::channel();
+                if jobs.send((request, reply)).is_err() {
+                    return;
+                }
+                match response.recv() {
+                    Ok(response) => response,
+                    Err(_) => return,
+                }
+            }
+            // the frame itself was read whole, so the connection can carry on
+            Err(e) => Response::Error(e.to_string()),
+        };
+        let mut body = response.encode();
+        if body.len() > MAX_FRAME_LEN {
+            // e.g. a batch of large values, which the client can ask for in smaller batches
+            body = Response::Error(format!(
+                "reply of {} bytes exceeds the limit of {MAX_FRAME_LEN}",
+                body.len()
+            ))
+            .encode();
+        }
+        if write_frame(&mut stream, &body).is_err() {
+            return;
+        }
+    }
+}
+
+fn checked_key<C>(cache: &C, non_finite: NonFinitePolicy, key: Vec<f32>) -> Result<PlainVector>
+where
+    C: ApproximateCache<PlainVector, Vec<u8>> + ?Sized,
+{
+    let mut key = PlainVector(key);
+    cache.check_dim(&key)?;
+    non_finite.apply(&mut key)?;
+    Ok(key)
+}
+
+fn find<C>(
+    cache: &mut C,
+    counters: &mut Counters,
+    non_finite: NonFinitePolicy,
+    key: Vec<f32>,
+) -> Result<Option<Vec<u8>>>
+where
+    C: ApproximateCache<PlainVector, Vec<u8>> + ?Sized,
+{
+    let key = checked_key(cache, non_finite, key)?;
+    let found = cache.find(&key);
+    if found.is_some() {
+        counters.hits += 1;
+    } else {
+        counters.misses += 1;
+    }
+    Ok(found)
+}
+
+fn answer<C>(
+    cache: &mut C,
+    counters: &mut Counters,
+    non_finite: NonFinitePolicy,
+    request: Request,
+) -> Response
+where
+    C: ApproximateCache<PlainVector, Vec<u8>> + ?Sized,
+{
+    let response = match request {
+        Request::Find(key) => find(cache, counters, non_finite, key).map(Response::Found),
+        Request::BatchFind(keys) => keys
+            .into_iter()
+            .map(|key| find(cache, counters, non_finite, key))
+            .collect::<Result<_>>()
+            .map(Response::BatchFound),
+        Request::Insert {
+            key,
+            value,
+            tolerance,
+        } => match tolerance.or(cache.default_tolerance()) {
+            Some(tol) if tol.is_nan() || tol < 0.0 => Err(ProximityError::InvalidArgument(
+                format!("tolerance must be non-negative, got {tol}"),
+            )),
+            Some(tol) => checked_key(cache, non_finite, key)
+                .map(|key| Response::Inserted(cache.insert_evicting(key, value, tol).len() as u32)),
+            None => Err(ProximityError::InvalidArgument(
+                "no tolerance given and the cache has no default tolerance".into(),
+            )),
+        },
+        Request::Len => Ok(Response::Len(cache.len() as u64)),
+        Request::Clear => {
+            cache.drain().for_each(drop);
+            Ok(Response::Cleared)
+        }
+        Request::Stats => Ok(Response::Stats(IpcStats {
+            len: cache.len(),
+            hits: counters.hits,
+            misses: counters.misses,
+            recent_hit_rate: cache.recent_hit_rate(),
+        })),
+    };
+    response.unwrap_or_else(|e| Response::Error(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{ApproximateCache, DefaultTolerance, FifoCache};
+    use crate::ipc::IpcClient;
+    use crate::numerics::SIMD_LANECOUNT;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    fn socket(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("proximity-{}-{test}.sock", std::process::id()))
+    }
+
+    /// Serves a FIFO cache of `capacity` entries on a detached thread.
+    fn spawn_daemon(path: &Path, capacity: usize, tolerance: Option<f32>) {
+        let server = IpcServer::bind(path).unwrap();
+        thread::spawn(move || {
+            let fifo = FifoCache::new(capacity).unwrap();
+            let mut cache: Box<dyn ApproximateCache<PlainVector, Vec<u8>>> = match tolerance {
+                Some(tol) => Box::new(DefaultTolerance::new(fifo, tol).unwrap()),
+                None => Box::new(fifo),
+            };
+            server.serve(cache.as_mut()).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_clients_share_the_daemon_cache() {
+        let path = socket("share");
+        spawn_daemon(&path, 2, None);
+        let mut a = IpcClient::connect(&path).unwrap();
+        let key = vec![1.0; SIMD_LANECOUNT];
+        assert_eq!(a.insert(&key, b"one", Some(TEST_TOLERANCE)).unwrap(), 0);
+        drop(a);
+
+        // a new client, as after a notebook restart, sees the warm cache
+        let mut b = IpcClient::connect(&path).unwrap();
+        assert_eq!(b.find(&key).unwrap(), Some(b"one".to_vec()));
+        let other = vec![2.0; SIMD_LANECOUNT];
+        assert_eq!(
+            b.batch_find(&[key.clone(), other.clone()]).unwrap(),
+            vec![Some(b"one".to_vec()), None]
+        );
+        b.insert(&other, b"two", Some(TEST_TOLERANCE)).unwrap();
+        assert_eq!(
+            b.insert(&[3.0; SIMD_LANECOUNT], b"three", Some(TEST_TOLERANCE))
+                .unwrap(),
+            1
+        );
+
+        let stats = b.stats().unwrap();
+        assert_eq!((stats.len, stats.hits, stats.misses), (2, 2, 1));
+        b.clear().unwrap();
+        assert_eq!(b.len().unwrap(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_errors_are_reported_to_the_client() {
+        let path = socket("errors");
+        spawn_daemon(&path, 4, Some(0.5));
+        let mut client = IpcClient::connect(&path).unwrap();
+        client.insert(&[1.0; SIMD_LANECOUNT], b"one", None).unwrap();
+        assert!(matches!(
+            client.find(&[1.0; 3]),
+            Err(ProximityError::InvalidArgument(msg)) if msg.contains("dimension")
+        ));
+        assert!(client
+            .insert(&[1.0; SIMD_LANECOUNT], b"x", Some(-1.0))
+            .is_err());
+        assert!(matches!(
+            client.find(&[f32::NAN; SIMD_LANECOUNT]),
+            Err(ProximityError::InvalidArgument(msg)) if msg.contains("NaN")
+        ));
+        // the connection survives errors
+        assert_eq!(client.len().unwrap(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reply_too_long_is_an_error() {
+        let path = socket("too-long");
+        spawn_daemon(&path, 4, Some(0.5));
+        let mut client = IpcClient::connect(&path).unwrap();
+        let key = vec![1.0; SIMD_LANECOUNT];
+        client
+            .insert(&key, &vec![0; MAX_FRAME_LEN / 2], None)
+            .unwrap();
+        assert!(matches!(
+            client.batch_find(&[key.clone(), key.clone()]),
+            Err(ProximityError::InvalidArgument(msg)) if msg.contains("exceeds")
+        ));
+        assert_eq!(
+            client.find(&key).unwrap().map(|value| value.len()),
+            Some(MAX_FRAME_LEN / 2)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_bind_replaces_stale_socket_only() {
+        let path = socket("stale");
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let server = IpcServer::bind(&path).unwrap();
+        assert!(IpcServer::bind(&path).is_err());
+        drop(server);
+        assert!(!path.exists());
+    }
+}
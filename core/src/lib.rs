@@ -4,5 +4,13 @@ extern crate npyz;
 extern crate test;
 
 pub mod caching;
+mod clock;
+mod error;
+pub mod eval;
 pub mod fs;
+#[cfg(all(unix, feature = "ipc"))]
+pub mod ipc;
 pub mod numerics;
+pub mod simulation;
+
+pub use error::{ProximityError, Result};
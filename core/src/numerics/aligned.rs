@@ -0,0 +1,177 @@
+use std::mem::size_of;
+
+use crate::caching::HeapSize;
+use crate::numerics::{ApproxComparable, VectorLike, SIMD_LANECOUNT};
+
+/// Alignment of the buffer of an [`AlignedVector`], in bytes: a cache line, and the
+/// widest SIMD register.
+pub const KEY_ALIGNMENT: usize = 64;
+const BLOCK_LEN: usize = KEY_ALIGNMENT / size_of::<f32>();
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C, align(64))]
+struct Block([f32; BLOCK_LEN]);
+
+/// Vector key of any dimension, stored in a [`KEY_ALIGNMENT`]-aligned buffer padded with
+/// zeros up to a multiple of [`SIMD_LANECOUNT`].
+///
+/// The zeros add nothing to distances, so keys of a dimension that is not a multiple of
+/// [`SIMD_LANECOUNT`] compare with the SIMD kernels of [`VectorLike`] as they are, with
+/// every load aligned. [`dimension`](ApproxComparable::dimension) is that of the original
+/// vector, but [`as_ref`](AsRef::as_ref) exposes the padded one, e.g. to an
+/// [`LshCache`](crate::caching::LshCache), whose dimension must then be the padded one.
+///
+/// # Example Usage
+/// ```
+/// use proximity::numerics::{AlignedVector, ApproxComparable};
+///
+/// let a = AlignedVector::from(vec![1.0; 3]);
+/// let b = AlignedVector::from(&[1.0, 1.0, 4.0][..]);
+/// assert_eq!(a.as_slice(), &[1.0; 3]);
+/// assert_eq!(a.padded(), &[1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+/// assert_eq!(a.fuzziness(&b), 3.0);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AlignedVector {
+    blocks: Vec<Block>,
+    len: usize,
+}
+
+impl AlignedVector {
+    /// Number of components of the original vector.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of components once padded.
+    pub fn padded_len(&self) -> usize {
+        self.len.next_multiple_of(SIMD_LANECOUNT)
+    }
+
+    /// Components of the original vector.
+    pub fn as_slice(&self) -> &[f32] {
+        &self.padded()[..self.len]
+    }
+
+    /// Components of the original vector followed by the zeros padding them.
+    pub fn padded(&self) -> &[f32] {
+        // SAFETY: blocks are arrays of f32 without padding between or after them, and
+        // the padded length never exceeds the components they hold
+        unsafe { std::slice::from_raw_parts(self.blocks.as_ptr().cast::<f32>(), self.padded_len()) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [f32] {
+        // SAFETY: see `padded`
+        unsafe { std::slice::from_raw_parts_mut(self.blocks.as_mut_ptr().cast::<f32>(), self.len) }
+    }
+
+    pub fn to_vec(&self) -> Vec<f32> {
+        self.as_slice().to_vec()
+    }
+}
+
+impl From<&[f32]> for AlignedVector {
+    fn from(vector: &[f32]) -> Self {
+        let blocks = vector
+            .chunks(BLOCK_LEN)
+            .map(|chunk| {
+                let mut block = [0.0; BLOCK_LEN];
+                block[..chunk.len()].copy_from_slice(chunk);
+                Block(block)
+            })
+            .collect();
+        AlignedVector {
+            blocks,
+            len: vector.len(),
+        }
+    }
+}
+
+impl From<Vec<f32>> for AlignedVector {
+    fn from(vector: Vec<f32>) -> Self {
+        Self::from(vector.as_slice())
+    }
+}
+
+impl AsRef<[f32]> for AlignedVector {
+    fn as_ref(&self) -> &[f32] {
+        self.padded()
+    }
+}
+
+impl PartialEq for AlignedVector {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl ApproxComparable for AlignedVector {
+    #[inline]
+    fn roughly_matches(&self, instore: &Self, tolerance: f32) -> bool {
+        self.padded().l2_dist_squared(instore.padded()) < tolerance * tolerance
+    }
+
+    #[inline]
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        self.padded().l2_dist(instore.padded())
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.len)
+    }
+
+    fn is_finite(&self) -> bool {
+        self.as_slice().is_finite()
+    }
+
+    fn sanitize(&mut self) {
+        self.as_mut_slice().sanitize();
+    }
+
+    fn normalize(&mut self) {
+        self.as_mut_slice().normalize();
+    }
+}
+
+impl HeapSize for AlignedVector {
+    fn heap_bytes(&self) -> usize {
+        self.blocks.capacity() * size_of::<Block>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_padding_and_alignment() {
+        for len in [0, 1, SIMD_LANECOUNT, BLOCK_LEN + 1, 100] {
+            let v: Vec<f32> = (0..len).map(|i| i as f32).collect();
+            let aligned = AlignedVector::from(v.clone());
+            assert_eq!(aligned.as_slice(), &v[..]);
+            assert_eq!(aligned.padded().len() % SIMD_LANECOUNT, 0);
+            assert!(aligned.padded()[len..].iter().all(|&x| x == 0.0));
+            assert_eq!(aligned.padded().as_ptr() as usize % KEY_ALIGNMENT, 0);
+            assert_eq!(aligned.dimension(), Some(len));
+        }
+    }
+
+    #[test]
+    fn test_distance_ignores_padding() {
+        let u: Vec<f32> = (0..13).map(|i| i as f32 * 0.5).collect();
+        let v: Vec<f32> = (0..13).map(|i| (i % 3) as f32).collect();
+        let spec: f32 = u.iter().zip(&v).map(|(x, y)| (x - y) * (x - y)).sum();
+        let (au, av) = (AlignedVector::from(u), AlignedVector::from(v));
+        assert!((au.fuzziness(&av) - spec.sqrt()).abs() < 1e-5);
+        assert!(au.roughly_matches(&av, spec.sqrt() + 0.1));
+
+        let mut broken = AlignedVector::from(vec![f32::NAN, 1.0, f32::INFINITY]);
+        assert!(!broken.is_finite());
+        broken.sanitize();
+        assert_eq!(broken.as_slice(), &[0.0, 1.0, f32::MAX]);
+    }
+}
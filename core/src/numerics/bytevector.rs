@@ -0,0 +1,150 @@
+use std::simd::num::{SimdInt, SimdUint};
+use std::simd::Simd;
+
+/// Number of byte components loaded at once, widened to as many `i32` lanes.
+pub const BYTE_LANECOUNT: usize = 16;
+type SimdI32 = Simd<i32, BYTE_LANECOUNT>;
+
+/// Distances between byte vectors, e.g. SIFT descriptors read from a `.bvecs` file,
+/// computed in integer arithmetic without converting the vectors to `f32`.
+///
+/// Unlike [`VectorLike`](crate::numerics::VectorLike), any length is accepted: the
+/// components past the last full chunk of [`BYTE_LANECOUNT`] are summed one by one.
+pub trait ByteVectorLike {
+    /// Squared L2 distance, exact as long as the vectors have fewer than 66051 components.
+    fn l2_dist_squared(&self, othr: &Self) -> u32;
+    /// L1 (Manhattan) distance.
+    fn l1_dist(&self, othr: &Self) -> u32;
+}
+
+macro_rules! byte_vector_like {
+    ($t:ty) => {
+        impl ByteVectorLike for [$t] {
+            /// # Panics
+            ///
+            /// Panics in debug mode if the two vectors have different lengths.
+            /// In release mode, the longest vector will be silently truncated.
+            #[inline]
+            fn l2_dist_squared(&self, othr: &[$t]) -> u32 {
+                debug_assert!(self.len() == othr.len());
+
+                let mut accumulated = SimdI32::splat(0);
+                let self_chunks = self.chunks_exact(BYTE_LANECOUNT);
+                let othr_chunks = othr.chunks_exact(BYTE_LANECOUNT);
+                let tail: u32 = self_chunks
+                    .remainder()
+                    .iter()
+                    .zip(othr_chunks.remainder())
+                    .map(|(&x, &y)| (i32::from(x) - i32::from(y)).pow(2) as u32)
+                    .sum();
+
+                for (slice_self, slice_othr) in self_chunks.zip(othr_chunks) {
+                    // widen in-register, a difference of bytes fits an i32 squared
+                    let vx: SimdI32 = Simd::<$t, BYTE_LANECOUNT>::from_slice(slice_self).cast();
+                    let vy: SimdI32 = Simd::<$t, BYTE_LANECOUNT>::from_slice(slice_othr).cast();
+                    let diff = vx - vy;
+                    accumulated += diff * diff;
+                }
+
+                accumulated.cast::<u32>().reduce_sum() + tail
+            }
+
+            /// # Panics
+            ///
+            /// Panics in debug mode if the two vectors have different lengths.
+            /// In release mode, the longest vector will be silently truncated.
+            #[inline]
+            fn l1_dist(&self, othr: &[$t]) -> u32 {
+                debug_assert!(self.len() == othr.len());
+
+                let mut accumulated = SimdI32::splat(0);
+                let self_chunks = self.chunks_exact(BYTE_LANECOUNT);
+                let othr_chunks = othr.chunks_exact(BYTE_LANECOUNT);
+                let tail: u32 = self_chunks
+                    .remainder()
+                    .iter()
+                    .zip(othr_chunks.remainder())
+                    .map(|(&x, &y)| (i32::from(x) - i32::from(y)).unsigned_abs())
+                    .sum();
+
+                for (slice_self, slice_othr) in self_chunks.zip(othr_chunks) {
+                    let vx: SimdI32 = Simd::<$t, BYTE_LANECOUNT>::from_slice(slice_self).cast();
+                    let vy: SimdI32 = Simd::<$t, BYTE_LANECOUNT>::from_slice(slice_othr).cast();
+                    accumulated += (vx - vy).abs();
+                }
+
+                accumulated.cast::<u32>().reduce_sum() + tail
+            }
+        }
+    };
+}
+
+byte_vector_like!(u8);
+byte_vector_like!(i8);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{QuickCheck, TestResult};
+
+    fn l2_spec<T: Copy + Into<i32>>(v1: &[T], v2: &[T]) -> u32 {
+        v1.iter()
+            .zip(v2)
+            .map(|(&x, &y)| (x.into() - y.into()).pow(2) as u32)
+            .sum()
+    }
+
+    fn l1_spec<T: Copy + Into<i32>>(v1: &[T], v2: &[T]) -> u32 {
+        v1.iter()
+            .zip(v2)
+            .map(|(&x, &y)| (x.into() - y.into()).unsigned_abs())
+            .sum()
+    }
+
+    #[test]
+    fn simd_matches_spec() {
+        fn qc_simd_matches_spec(u: Vec<u8>, v: Vec<u8>, s: Vec<i8>, t: Vec<i8>) -> TestResult {
+            let len = u.len().min(v.len());
+            let (u, v) = (&u[..len], &v[..len]);
+            let len = s.len().min(t.len());
+            let (s, t) = (&s[..len], &t[..len]);
+            TestResult::from_bool(
+                u.l2_dist_squared(v) == l2_spec(u, v)
+                    && u.l1_dist(v) == l1_spec(u, v)
+                    && s.l2_dist_squared(t) == l2_spec(s, t)
+                    && s.l1_dist(t) == l1_spec(s, t),
+            )
+        }
+
+        QuickCheck::new().tests(10_000).quickcheck(
+            qc_simd_matches_spec as fn(Vec<u8>, Vec<u8>, Vec<i8>, Vec<i8>) -> TestResult,
+        );
+    }
+
+    #[test]
+    fn extreme_components() {
+        let (zeros, full) = ([0u8; 128], [255u8; 128]);
+        assert_eq!(zeros.l2_dist_squared(&full), 128 * 255 * 255);
+        assert_eq!(zeros.l1_dist(&full), 128 * 255);
+        let (low, high) = ([i8::MIN; 20], [i8::MAX; 20]);
+        assert_eq!(low.l2_dist_squared(&high), 20 * 255 * 255);
+        assert_eq!(high.l1_dist(&low), 20 * 255);
+    }
+
+    #[test]
+    fn byte_keys_in_cache() {
+        use crate::caching::{ApproximateCache, FifoCache};
+        use crate::numerics::ApproxComparable;
+
+        let stored: Vec<u8> = vec![10; 128];
+        let mut near = stored.clone();
+        near[0] = 13;
+        near[127] = 6;
+        assert_eq!(near.fuzziness(&stored), 5.0);
+
+        let mut cache = FifoCache::new(4).unwrap();
+        cache.insert(stored, 1, 6.0);
+        assert_eq!(cache.find(&near), Some(1));
+        assert_eq!(cache.find(&vec![0u8; 128]), None);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::numerics::VectorLike;
+use crate::numerics::{ByteVectorLike, VectorLike};
 
 pub trait ApproxComparable {
     #[inline]
@@ -6,12 +6,49 @@ pub trait ApproxComparable {
         self.fuzziness(instore) < tolerance
     }
     fn fuzziness(&self, instore: &Self) -> f32;
+    /// Number of components of a vector key, or `None` for keys that are not vectors.
+    fn dimension(&self) -> Option<usize> {
+        None
+    }
+    /// Whether every component of the key is finite, i.e. neither NaN nor infinite.
+    fn is_finite(&self) -> bool {
+        true
+    }
+    /// Replaces NaN components with zero and infinite ones with the largest finite value
+    /// of the same sign.
+    fn sanitize(&mut self) {}
+    /// Scales a vector key to unit L2 norm, leaving zero vectors as they are. Keys that
+    /// are not vectors of floats are left as they are.
+    fn normalize(&mut self) {}
+    /// Hash of the part of the key that only matches an equal part, e.g. the scope of a
+    /// [`Scoped`](crate::numerics::Scoped) key, or 0 for keys without one. LSH caches
+    /// fold it into the signature of the key, so that keys of different scopes do not
+    /// crowd the same buckets.
+    fn scope_hash(&self) -> u64 {
+        0
+    }
+}
+
+fn sanitized(x: f32) -> f32 {
+    if x.is_nan() {
+        0.0
+    } else {
+        x.clamp(f32::MIN, f32::MAX)
+    }
 }
 
 impl ApproxComparable for f32 {
     fn fuzziness(&self, instore: &Self) -> f32 {
         (self - instore).abs()
     }
+
+    fn is_finite(&self) -> bool {
+        f32::is_finite(*self)
+    }
+
+    fn sanitize(&mut self) {
+        *self = sanitized(*self);
+    }
 }
 
 impl ApproxComparable for [f32] {
@@ -24,8 +61,98 @@ impl ApproxComparable for [f32] {
     fn fuzziness(&self, instore: &Self) -> f32 {
         self.l2_dist_squared(instore).sqrt()
     }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.len())
+    }
+
+    fn is_finite(&self) -> bool {
+        self.iter().all(|x| x.is_finite())
+    }
+
+    fn sanitize(&mut self) {
+        for x in self.iter_mut() {
+            *x = sanitized(*x);
+        }
+    }
+
+    fn normalize(&mut self) {
+        let norm = self.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            self.iter_mut().for_each(|x| *x /= norm);
+        }
+    }
 }
 
+impl ApproxComparable for Vec<f32> {
+    #[inline]
+    fn roughly_matches(&self, instore: &Self, tolerance: f32) -> bool {
+        self.as_slice().roughly_matches(instore, tolerance)
+    }
+
+    #[inline]
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        self.as_slice().fuzziness(instore)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.len())
+    }
+
+    fn is_finite(&self) -> bool {
+        self.as_slice().is_finite()
+    }
+
+    fn sanitize(&mut self) {
+        self.as_mut_slice().sanitize();
+    }
+
+    fn normalize(&mut self) {
+        self.as_mut_slice().normalize();
+    }
+}
+
+/// Byte vectors are compared by L2 distance, as `f32` vectors are, but the distance is
+/// computed on the bytes.
+macro_rules! byte_approx_comparable {
+    ($t:ty) => {
+        impl ApproxComparable for [$t] {
+            #[inline]
+            fn roughly_matches(&self, target: &[$t], tolerance: f32) -> bool {
+                (self.l2_dist_squared(target) as f32) < tolerance * tolerance
+            }
+
+            #[inline]
+            fn fuzziness(&self, instore: &Self) -> f32 {
+                (self.l2_dist_squared(instore) as f32).sqrt()
+            }
+
+            fn dimension(&self) -> Option<usize> {
+                Some(self.len())
+            }
+        }
+
+        impl ApproxComparable for Vec<$t> {
+            #[inline]
+            fn roughly_matches(&self, instore: &Self, tolerance: f32) -> bool {
+                self.as_slice().roughly_matches(instore, tolerance)
+            }
+
+            #[inline]
+            fn fuzziness(&self, instore: &Self) -> f32 {
+                self.as_slice().fuzziness(instore)
+            }
+
+            fn dimension(&self) -> Option<usize> {
+                Some(self.len())
+            }
+        }
+    };
+}
+
+byte_approx_comparable!(u8);
+byte_approx_comparable!(i8);
+
 impl ApproxComparable for i16 {
     fn fuzziness(&self, instore: &Self) -> f32 {
         let fself = f32::from(*self);
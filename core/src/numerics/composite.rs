@@ -0,0 +1,241 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+
+/// Key component that only matches an equal component, e.g. the id of the model that
+/// produced an embedding.
+///
+/// Its fuzziness is 0 for equal components and infinite otherwise, so a composite key
+/// with a mismatched `Exact` component never matches, whatever the tolerance.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Exact<T>(pub T);
+
+impl<T: PartialEq> ApproxComparable for Exact<T> {
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        if self.0 == instore.0 {
+            0.0
+        } else {
+            f32::INFINITY
+        }
+    }
+}
+
+impl<T: HeapSize> HeapSize for Exact<T> {
+    fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes()
+    }
+}
+
+/// Key component whose fuzziness is scaled by `weight`, to balance the components of a
+/// composite key against one another.
+///
+/// The weight of the queried key applies.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weighted<T> {
+    pub key: T,
+    pub weight: f32,
+}
+
+impl<T> Weighted<T> {
+    pub fn new(key: T, weight: f32) -> Self {
+        Self { key, weight }
+    }
+}
+
+impl<T: ApproxComparable> ApproxComparable for Weighted<T> {
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        self.weight * self.key.fuzziness(&instore.key)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.key.dimension()
+    }
+
+    fn is_finite(&self) -> bool {
+        self.key.is_finite()
+    }
+
+    fn sanitize(&mut self) {
+        self.key.sanitize();
+    }
+
+    fn normalize(&mut self) {
+        self.key.normalize();
+    }
+
+    fn scope_hash(&self) -> u64 {
+        self.key.scope_hash()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Weighted<T> {
+    fn heap_bytes(&self) -> usize {
+        self.key.heap_bytes()
+    }
+}
+
+/// Key that only matches keys of the same scope, e.g. a tenant or a model, and otherwise
+/// compares as its inner key.
+///
+/// Unlike a tuple with an [`Exact`] component, it keeps the tolerance semantics of the
+/// inner key and exposes its vector, so it can be routed by an
+/// [`LshCache`](crate::caching::LshCache), which routes close keys of different scopes
+/// to different buckets, see [`ApproxComparable::scope_hash`]. Keys of different scopes
+/// never match.
+///
+/// # Example Usage
+/// ```
+/// use proximity::numerics::{ApproxComparable, Scoped};
+///
+/// let a = Scoped::new("model-a", vec![1.0; 8]);
+/// let b = Scoped::new("model-b", vec![1.0; 8]);
+/// assert!(a.roughly_matches(&Scoped::new("model-a", vec![1.1; 8]), 0.5));
+/// assert!(!a.roughly_matches(&b, 0.5));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Scoped<S, K> {
+    pub scope: S,
+    pub key: K,
+}
+
+impl<S, K> Scoped<S, K> {
+    pub fn new(scope: S, key: K) -> Self {
+        Self { scope, key }
+    }
+}
+
+impl<S: PartialEq + Hash, K: ApproxComparable> ApproxComparable for Scoped<S, K> {
+    #[inline]
+    fn roughly_matches(&self, instore: &Self, tolerance: f32) -> bool {
+        self.scope == instore.scope && self.key.roughly_matches(&instore.key, tolerance)
+    }
+
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        if self.scope == instore.scope {
+            self.key.fuzziness(&instore.key)
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.key.dimension()
+    }
+
+    fn is_finite(&self) -> bool {
+        self.key.is_finite()
+    }
+
+    fn sanitize(&mut self) {
+        self.key.sanitize();
+    }
+
+    fn normalize(&mut self) {
+        self.key.normalize();
+    }
+
+    fn scope_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.scope.hash(&mut hasher);
+        self.key.scope_hash().hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl<S, K: AsRef<[f32]>> AsRef<[f32]> for Scoped<S, K> {
+    fn as_ref(&self) -> &[f32] {
+        self.key.as_ref()
+    }
+}
+
+impl<S: HeapSize, K: HeapSize> HeapSize for Scoped<S, K> {
+    fn heap_bytes(&self) -> usize {
+        self.scope.heap_bytes() + self.key.heap_bytes()
+    }
+}
+
+/// Tuples compare component-wise and sum the fuzziness of their components, which
+/// [`Weighted`] and [`Exact`] components adjust. Their dimension is the total dimension
+/// of their vector components.
+macro_rules! composite_tuple {
+    ($($t:ident . $i:tt),+) => {
+        impl<$($t: ApproxComparable),+> ApproxComparable for ($($t,)+) {
+            fn fuzziness(&self, instore: &Self) -> f32 {
+                0.0 $(+ self.$i.fuzziness(&instore.$i))+
+            }
+
+            fn dimension(&self) -> Option<usize> {
+                [$(self.$i.dimension()),+].into_iter().flatten().reduce(|a, b| a + b)
+            }
+
+            fn is_finite(&self) -> bool {
+                $(self.$i.is_finite())&&+
+            }
+
+            fn sanitize(&mut self) {
+                $(self.$i.sanitize();)+
+            }
+        }
+    };
+}
+
+composite_tuple!(A.0, B.1);
+composite_tuple!(A.0, B.1, C.2);
+composite_tuple!(A.0, B.1, C.2, D.3);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Vector whose first components are `x` and `y`, so that its length is `|(x, y)|`.
+    fn vec2(x: f32, y: f32) -> Vec<f32> {
+        let mut v = vec![0.0; 8];
+        v[0] = x;
+        v[1] = y;
+        v
+    }
+
+    #[test]
+    fn test_exact_components_gate_matches() {
+        let stored = (Exact(7u64), vec2(0.0, 0.0));
+        assert!((Exact(7u64), vec2(0.3, 0.4)).roughly_matches(&stored, 0.6));
+        assert!(!(Exact(7u64), vec2(0.3, 0.4)).roughly_matches(&stored, 0.4));
+        assert!(!(Exact(8u64), vec2(0.0, 0.0)).roughly_matches(&stored, f32::MAX));
+        assert_eq!(stored.dimension(), Some(8));
+    }
+
+    #[test]
+    fn test_weighted_components_sum() {
+        let stored = (Weighted::new(1.0f32, 2.0), Weighted::new(10.0f32, 0.5));
+        let query = (Weighted::new(1.5f32, 2.0), Weighted::new(12.0f32, 0.5));
+        assert_eq!(query.fuzziness(&stored), 2.0 * 0.5 + 0.5 * 2.0);
+        assert_eq!(query.dimension(), None);
+        let mut nan = (f32::NAN, Exact(1));
+        assert!(!nan.is_finite());
+        nan.sanitize();
+        assert!(nan.is_finite());
+    }
+
+    #[test]
+    fn test_scoped_keys() {
+        let a = Scoped::new(1u8, vec2(3.0, 4.0));
+        assert_eq!(a.fuzziness(&Scoped::new(1, vec2(0.0, 0.0))), 5.0);
+        assert_eq!(a.fuzziness(&Scoped::new(2, vec2(3.0, 4.0))), f32::INFINITY);
+        assert_eq!(a.as_ref(), vec2(3.0, 4.0).as_slice());
+        assert_eq!(a.dimension(), Some(8));
+    }
+
+    #[test]
+    fn test_scoped_keys_in_lsh_cache() {
+        use crate::caching::{ApproximateCache, LshFifoCache};
+
+        let mut cache = LshFifoCache::new(4, 8, 4, Some(42)).unwrap();
+        cache.insert(Scoped::new(1u8, vec2(1.0, 0.0)), "model 1", 0.5);
+        assert_eq!(cache.find(&Scoped::new(1, vec2(1.0, 0.1))), Some("model 1"));
+        assert_eq!(cache.find(&Scoped::new(2, vec2(1.0, 0.0))), None);
+        cache.insert(Scoped::new(2, vec2(1.0, 0.0)), "model 2", 0.5);
+        assert_eq!(cache.find(&Scoped::new(2, vec2(1.0, 0.0))), Some("model 2"));
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -27,7 +27,7 @@ impl VectorLike for [f32] {
     #[inline]
     fn l2_dist_squared(&self, othr: &[f32]) -> f32 {
         debug_assert!(self.len() == othr.len());
-        debug_assert!(self.len() % SIMD_LANECOUNT == 0);
+        debug_assert!(self.len().is_multiple_of(SIMD_LANECOUNT));
 
         let mut intermediate_sum_x8 = Simd::<f32, SIMD_LANECOUNT>::splat(0.0);
 
@@ -47,7 +47,7 @@ impl VectorLike for [f32] {
     #[inline]
     fn dot(&self, othr: &[f32]) -> f32 {
         debug_assert!(self.len() == othr.len());
-        debug_assert!(self.len() % SIMD_LANECOUNT == 0);
+        debug_assert!(self.len().is_multiple_of(SIMD_LANECOUNT));
 
         // accumulator vector of zeroes
         let mut accumulated = Simd::<f32, SIMD_LANECOUNT>::splat(0.0);
@@ -116,7 +116,7 @@ mod tests {
         suspect.is_finite() && suspect >= 0.0
     }
 
-    fn l2_spec<'a>(v1: &[f32], v2: &[f32]) -> f32 {
+    fn l2_spec(v1: &[f32], v2: &[f32]) -> f32 {
         v1.iter()
             .zip(v2.iter())
             .map(|(&x, &y)| {
@@ -134,9 +134,9 @@ mod tests {
                 return TestResult::discard();
             }
             let testvec = &totest[0..usable_length];
-            let selfsim = testvec.l2_dist(&testvec);
+            let selfsim = testvec.l2_dist(testvec);
             let to_check = is_valid_l2(selfsim) && close(selfsim, 0.0);
-            return TestResult::from_bool(to_check);
+            TestResult::from_bool(to_check)
         }
 
         QuickCheck::new()
@@ -156,11 +156,11 @@ mod tests {
             let min_length = all_vecs.iter().map(|x| x.len()).min().unwrap() / 8 * 8;
             let all_vectors: Vec<&[f32]> = all_vecs.iter().map(|vec| &vec[..min_length]).collect();
 
-            let d1_squared = all_vectors[0].l2_dist_squared(&all_vectors[1]);
-            let d2_squared = all_vectors[2].l2_dist_squared(&all_vectors[3]);
+            let d1_squared = all_vectors[0].l2_dist_squared(all_vectors[1]);
+            let d2_squared = all_vectors[2].l2_dist_squared(all_vectors[3]);
 
-            let d1_root = all_vectors[0].l2_dist(&all_vectors[1]);
-            let d2_root = all_vectors[2].l2_dist(&all_vectors[3]);
+            let d1_root = all_vectors[0].l2_dist(all_vectors[1]);
+            let d2_root = all_vectors[2].l2_dist(all_vectors[3]);
 
             let sanity_check1 = (d1_squared < d2_squared) == (d1_root < d2_root);
             let sanity_check2 = (d1_squared <= d2_squared) == (d1_root <= d2_root);
@@ -177,7 +177,7 @@ mod tests {
         fn qc_simd_matches_spec(u: Vec<f32>, v: Vec<f32>) -> TestResult {
             let min_length = u.len().min(v.len()) / 8 * 8;
             let (u_f32v, v_f32v) = (&u[0..min_length], &v[0..min_length]);
-            let simd = u_f32v.l2_dist_squared(&v_f32v);
+            let simd = u_f32v.l2_dist_squared(v_f32v);
             let spec = l2_spec(u_f32v, v_f32v);
 
             if simd.is_infinite() {
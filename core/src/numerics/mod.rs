@@ -1,5 +1,22 @@
+mod aligned;
+mod bytevector;
 mod comp;
+mod composite;
 mod f32vector;
+mod normalized;
+mod plain;
+mod projection;
+mod sketched;
+mod sparse;
+pub mod topk;
 
+pub use aligned::{AlignedVector, KEY_ALIGNMENT};
+pub use bytevector::{ByteVectorLike, BYTE_LANECOUNT};
 pub use comp::ApproxComparable;
+pub use composite::{Exact, Scoped, Weighted};
 pub use f32vector::{VectorLike, SIMD_LANECOUNT};
+pub use normalized::NormalizedVector;
+pub use plain::PlainVector;
+pub use projection::Projection;
+pub use sketched::{SignSketch, Sketched, DEFAULT_MAX_FLIPS, SKETCH_BITS};
+pub use sparse::TokenSet;
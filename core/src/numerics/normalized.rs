@@ -0,0 +1,143 @@
+use crate::caching::HeapSize;
+use crate::numerics::{ApproxComparable, VectorLike};
+
+/// Vector key stored as its unit vector along with its L2 norm, both computed once when
+/// the key is built rather than on every lookup.
+///
+/// Keys still compare by the L2 distance between the original vectors, derived from the
+/// dot product of the unit vectors and the norms, so a cache holding them behaves as one
+/// holding the original vectors. It dereferences to the unit vector, which is what an
+/// [`LshCache`](crate::caching::LshCache) hashes.
+///
+/// As for [`VectorLike`], the dimension must be a multiple of
+/// [`SIMD_LANECOUNT`](crate::numerics::SIMD_LANECOUNT).
+///
+/// # Example Usage
+/// ```
+/// use proximity::numerics::{ApproxComparable, NormalizedVector};
+///
+/// let a = NormalizedVector::from(vec![3.0; 8]);
+/// let b = NormalizedVector::from(vec![4.0; 8]);
+/// assert!((a.norm() - 72f32.sqrt()).abs() < 1e-5);
+/// assert!((a.fuzziness(&b) - 8f32.sqrt()).abs() < 1e-3);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NormalizedVector {
+    unit: Vec<f32>,
+    norm: f32,
+}
+
+impl NormalizedVector {
+    /// The vector divided by its norm, or zeros if the norm is zero.
+    pub fn unit(&self) -> &[f32] {
+        &self.unit
+    }
+
+    /// L2 norm of the original vector.
+    pub fn norm(&self) -> f32 {
+        self.norm
+    }
+
+    /// The original vector, up to rounding.
+    pub fn to_vec(&self) -> Vec<f32> {
+        self.unit.iter().map(|x| x * self.norm).collect()
+    }
+
+    /// Squared L2 distance between the original vectors.
+    fn dist_squared(&self, other: &Self) -> f32 {
+        let cross = 2.0 * self.norm * other.norm * self.unit.dot(&other.unit);
+        // rounding may leave close vectors slightly below zero
+        (self.norm * self.norm + other.norm * other.norm - cross).max(0.0)
+    }
+}
+
+impl From<&[f32]> for NormalizedVector {
+    fn from(vector: &[f32]) -> Self {
+        NormalizedVector {
+            unit: vector.normalized(),
+            norm: vector.dot(vector).sqrt(),
+        }
+    }
+}
+
+impl From<Vec<f32>> for NormalizedVector {
+    fn from(vector: Vec<f32>) -> Self {
+        Self::from(vector.as_slice())
+    }
+}
+
+impl AsRef<[f32]> for NormalizedVector {
+    fn as_ref(&self) -> &[f32] {
+        &self.unit
+    }
+}
+
+impl ApproxComparable for NormalizedVector {
+    #[inline]
+    fn roughly_matches(&self, instore: &Self, tolerance: f32) -> bool {
+        self.dist_squared(instore) < tolerance * tolerance
+    }
+
+    #[inline]
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        self.dist_squared(instore).sqrt()
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.unit.len())
+    }
+
+    fn is_finite(&self) -> bool {
+        self.norm.is_finite() && self.unit.is_finite()
+    }
+
+    /// Sanitizes the original vector, then normalizes it again.
+    fn sanitize(&mut self) {
+        if !self.is_finite() {
+            let mut original = self.to_vec();
+            original.sanitize();
+            *self = Self::from(original);
+        }
+    }
+
+    /// Keeps the unit vector only.
+    fn normalize(&mut self) {
+        if self.norm > 0.0 {
+            self.norm = 1.0;
+        }
+    }
+}
+
+impl HeapSize for NormalizedVector {
+    fn heap_bytes(&self) -> usize {
+        self.unit.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_original_distance() {
+        let u: Vec<f32> = (0..16).map(|i| i as f32 - 4.0).collect();
+        let v: Vec<f32> = (0..16).map(|i| (i % 5) as f32).collect();
+        let (nu, nv) = (
+            NormalizedVector::from(u.clone()),
+            NormalizedVector::from(&v[..]),
+        );
+        assert!((nu.fuzziness(&nv) - u.fuzziness(&v)).abs() < 1e-3);
+        assert!((nu.norm() - u.dot(&u).sqrt()).abs() < 1e-5);
+        assert!((nu.unit().dot(nu.unit()) - 1.0).abs() < 1e-5);
+        assert!(nu.roughly_matches(&nu, 1e-3));
+        assert!(!nu.roughly_matches(&nv, u.fuzziness(&v) - 0.1));
+        assert!(nu.to_vec().fuzziness(&u) < 1e-4);
+
+        let zero = NormalizedVector::from(vec![0.0; 8]);
+        assert_eq!(zero.unit(), &[0.0; 8]);
+        assert_eq!(
+            zero.fuzziness(&NormalizedVector::from(vec![2.0; 8])),
+            8f32.sqrt() * 2.0
+        );
+    }
+}
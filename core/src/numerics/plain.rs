@@ -0,0 +1,96 @@
+use std::hash::{Hash, Hasher};
+
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+
+/// Vector key compared by L2 distance, as a `[f32]` is, that is equal to another and
+/// hashes alike when their components are the same bit for bit, so that it fits every
+/// cache, e.g. an [`LruCache`](crate::caching::LruCache), which needs `Eq` and `Hash`.
+///
+/// As for [`VectorLike`](crate::numerics::VectorLike), the dimension must be a multiple
+/// of [`SIMD_LANECOUNT`](crate::numerics::SIMD_LANECOUNT).
+///
+/// # Example Usage
+/// ```
+/// use proximity::numerics::{ApproxComparable, PlainVector};
+///
+/// let a = PlainVector::from(vec![1.0; 8]);
+/// let b = PlainVector::from(vec![2.0; 8]);
+/// assert!((a.fuzziness(&b) - 8f32.sqrt()).abs() < 1e-5);
+/// assert_ne!(PlainVector::from(vec![0.0; 8]), PlainVector::from(vec![-0.0; 8]));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PlainVector(pub Vec<f32>);
+
+impl PartialEq for PlainVector {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| a.to_bits() == b.to_bits())
+    }
+}
+
+impl Eq for PlainVector {}
+
+impl Hash for PlainVector {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for &val in &self.0 {
+            state.write_u32(val.to_bits());
+        }
+    }
+}
+
+impl From<Vec<f32>> for PlainVector {
+    fn from(vector: Vec<f32>) -> Self {
+        PlainVector(vector)
+    }
+}
+
+impl AsRef<[f32]> for PlainVector {
+    fn as_ref(&self) -> &[f32] {
+        &self.0
+    }
+}
+
+impl AsMut<[f32]> for PlainVector {
+    fn as_mut(&mut self) -> &mut [f32] {
+        &mut self.0
+    }
+}
+
+impl ApproxComparable for PlainVector {
+    #[inline]
+    fn roughly_matches(&self, instore: &Self, tolerance: f32) -> bool {
+        self.0.as_slice().roughly_matches(&instore.0, tolerance)
+    }
+
+    #[inline]
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        self.0.as_slice().fuzziness(&instore.0)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+
+    fn is_finite(&self) -> bool {
+        self.0.as_slice().is_finite()
+    }
+
+    fn sanitize(&mut self) {
+        self.0.as_mut_slice().sanitize()
+    }
+
+    fn normalize(&mut self) {
+        self.0.as_mut_slice().normalize()
+    }
+}
+
+impl HeapSize for PlainVector {
+    fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes()
+    }
+}
@@ -0,0 +1,244 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+
+use crate::caching::HeapSize;
+use crate::numerics::{VectorLike, SIMD_LANECOUNT};
+use crate::{ProximityError, Result};
+
+/// Rounds of subspace iteration when fitting a PCA projection.
+const PCA_ITERATIONS: usize = 32;
+
+/// Linear map from keys of `input_dim` components down to `output_dim` components, to
+/// make hashing and scanning high-dimensional keys, e.g. 1536-dimensional embeddings,
+/// cheaper at some cost in accuracy.
+///
+/// L2 distances between projected keys are about [`distance_scale`](Self::distance_scale)
+/// times the original ones, which tolerances are multiplied by to keep their meaning.
+/// Both dimensions must be multiples of [`SIMD_LANECOUNT`].
+///
+/// # Example Usage
+/// ```
+/// use proximity::numerics::{Projection, VectorLike};
+///
+/// let projection = Projection::random(64, 16, 42).unwrap();
+/// let (a, b) = (vec![1.0; 64], vec![1.5; 64]);
+/// let (pa, pb) = (projection.project(&a), projection.project(&b));
+/// assert_eq!(pa.len(), 16);
+/// // distances are preserved up to the distortion of a random projection
+/// assert!((pa.l2_dist(&pb) / a.l2_dist(&b) - 1.0).abs() < 0.6);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Projection {
+    /// Subtracted from keys before projecting them.
+    mean: Vec<f32>,
+    /// One row of `input_dim` components per output component.
+    rows: Vec<Vec<f32>>,
+    distance_scale: f32,
+}
+
+impl Projection {
+    /// Johnson-Lindenstrauss projection onto `output_dim` Gaussian directions drawn from
+    /// `seed`, scaled so that distances are preserved on average. Caches whose keys are
+    /// compared must use the same seed.
+    pub fn random(input_dim: usize, output_dim: usize, seed: u64) -> Result<Self> {
+        check_dims(input_dim, output_dim)?;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let scale = (output_dim as f32).sqrt().recip();
+        let rows = (0..output_dim)
+            .map(|_| {
+                (0..input_dim)
+                    .map(|_| scale * rng.sample::<f32, _>(StandardNormal))
+                    .collect()
+            })
+            .collect();
+        Ok(Projection {
+            mean: vec![0.0; input_dim],
+            rows,
+            distance_scale: 1.0,
+        })
+    }
+
+    /// Projection onto the `output_dim` principal components of `sample`, row-major keys
+    /// of `input_dim` components, found by subspace iteration started from `seed`.
+    ///
+    /// Projected distances only keep the variance along those components, so the
+    /// distance scale is the square root of the share of the sample variance they hold.
+    pub fn pca(sample: &[f32], input_dim: usize, output_dim: usize, seed: u64) -> Result<Self> {
+        check_dims(input_dim, output_dim)?;
+        if !sample.len().is_multiple_of(input_dim) || sample.len() / input_dim <= output_dim {
+            return Err(ProximityError::InvalidArgument(format!(
+                "PCA needs more than {output_dim} sample keys of dimension {input_dim}"
+            )));
+        }
+        let count = (sample.len() / input_dim) as f32;
+        let mut mean = vec![0.0; input_dim];
+        for key in sample.chunks_exact(input_dim) {
+            for (m, x) in mean.iter_mut().zip(key) {
+                *m += x / count;
+            }
+        }
+        let centered: Vec<Vec<f32>> = sample
+            .chunks_exact(input_dim)
+            .map(|key| key.iter().zip(&mean).map(|(x, m)| x - m).collect())
+            .collect();
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut basis: Vec<Vec<f32>> = (0..output_dim)
+            .map(|_| (0..input_dim).map(|_| rng.sample(StandardNormal)).collect())
+            .collect();
+        orthonormalize(&mut basis, &mut rng);
+        for _ in 0..PCA_ITERATIONS {
+            // basis <- covariance * basis, without forming the covariance matrix
+            let mut next = vec![vec![0.0; input_dim]; output_dim];
+            for key in &centered {
+                for (direction, acc) in basis.iter().zip(next.iter_mut()) {
+                    let coord = key.dot(direction);
+                    for (a, x) in acc.iter_mut().zip(key) {
+                        *a += coord * x;
+                    }
+                }
+            }
+            basis = next;
+            orthonormalize(&mut basis, &mut rng);
+        }
+
+        let total: f32 = centered.iter().map(|key| key.dot(key)).sum();
+        let kept: f32 = centered
+            .iter()
+            .flat_map(|key| basis.iter().map(|direction| key.dot(direction).powi(2)))
+            .sum();
+        let distance_scale = if total > 0.0 {
+            (kept / total).clamp(0.0, 1.0).sqrt()
+        } else {
+            1.0
+        };
+        Ok(Projection {
+            mean,
+            rows: basis,
+            distance_scale,
+        })
+    }
+
+    pub fn input_dim(&self) -> usize {
+        self.mean.len()
+    }
+
+    pub fn output_dim(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Expected ratio of projected to original distances.
+    pub fn distance_scale(&self) -> f32 {
+        self.distance_scale
+    }
+
+    /// Projects `key` down to [`output_dim`](Self::output_dim) components.
+    ///
+    /// # Panics
+    /// If `key` is not of the input dimension.
+    pub fn project(&self, key: &[f32]) -> Vec<f32> {
+        if key.len() != self.input_dim() {
+            let err = ProximityError::DimensionMismatch {
+                expected: self.input_dim(),
+                found: key.len(),
+            };
+            panic!("{err}");
+        }
+        let centered: Vec<f32> = key.iter().zip(&self.mean).map(|(x, m)| x - m).collect();
+        self.rows.iter().map(|row| centered.dot(row)).collect()
+    }
+}
+
+impl HeapSize for Projection {
+    fn heap_bytes(&self) -> usize {
+        self.mean.heap_bytes() + self.rows.heap_bytes()
+    }
+}
+
+fn check_dims(input_dim: usize, output_dim: usize) -> Result<()> {
+    for dim in [input_dim, output_dim] {
+        if dim == 0 || !dim.is_multiple_of(SIMD_LANECOUNT) {
+            return Err(ProximityError::InvalidArgument(format!(
+                "dimension must be a positive multiple of {SIMD_LANECOUNT}, got {dim}"
+            )));
+        }
+    }
+    if output_dim > input_dim {
+        return Err(ProximityError::InvalidArgument(format!(
+            "cannot project {input_dim} dimensions onto {output_dim}"
+        )));
+    }
+    Ok(())
+}
+
+/// Gram-Schmidt, replacing directions that vanish, e.g. beyond the rank of a sample, by
+/// random ones.
+fn orthonormalize<R: Rng>(basis: &mut [Vec<f32>], rng: &mut R) {
+    for i in 0..basis.len() {
+        loop {
+            let (done, rest) = basis.split_at_mut(i);
+            let direction = &mut rest[0];
+            for previous in done.iter() {
+                let overlap = direction.dot(previous);
+                for (x, p) in direction.iter_mut().zip(previous) {
+                    *x -= overlap * p;
+                }
+            }
+            let norm = direction.dot(direction).sqrt();
+            if norm > 1e-6 {
+                direction.iter_mut().for_each(|x| *x /= norm);
+                break;
+            }
+            direction
+                .iter_mut()
+                .for_each(|x| *x = rng.sample(StandardNormal));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_dimensions() {
+        assert!(Projection::random(64, 12, 0).is_err());
+        assert!(Projection::random(16, 32, 0).is_err());
+        assert!(Projection::pca(&[0.0; 64], 16, 8, 0).is_err());
+    }
+
+    #[test]
+    fn test_random_projection_is_seeded() {
+        let a = Projection::random(32, 8, 7).unwrap();
+        let b = Projection::random(32, 8, 7).unwrap();
+        let key = vec![0.5; 32];
+        assert_eq!(a.project(&key), b.project(&key));
+        assert_eq!(a.distance_scale(), 1.0);
+    }
+
+    #[test]
+    fn test_pca_finds_the_spread_directions() {
+        // keys only vary along the first 8 axes, the others are constant
+        let mut rng = StdRng::seed_from_u64(3);
+        let sample: Vec<f32> = (0..200)
+            .flat_map(|_| {
+                let mut key = vec![2.0; 32];
+                for x in &mut key[..8] {
+                    *x = rng.sample::<f32, _>(StandardNormal) * 5.0;
+                }
+                key
+            })
+            .collect();
+        let projection = Projection::pca(&sample, 32, 8, 1).unwrap();
+        assert!((projection.distance_scale() - 1.0).abs() < 1e-3);
+
+        let (a, b) = (&sample[..32], &sample[32..64]);
+        let (pa, pb) = (projection.project(a), projection.project(b));
+        assert!((pa.l2_dist(&pb) - a.l2_dist(b)).abs() < 1e-2);
+        // the constant axes are dropped
+        let mut shifted = a.to_vec();
+        shifted[20] += 3.0;
+        assert!(projection.project(&shifted).l2_dist(&pa) < 1e-2);
+    }
+}
@@ -0,0 +1,216 @@
+use std::simd::num::SimdUint;
+use std::simd::Simd;
+
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+
+/// Number of bits of a [`SignSketch`].
+pub const SKETCH_BITS: usize = 128;
+const SKETCH_WORDS: usize = SKETCH_BITS / 64;
+
+/// Flips between sketches up to which [`Sketched`] keys are compared exactly, unless
+/// set with [`Sketched::new`].
+pub const DEFAULT_MAX_FLIPS: u32 = 32;
+
+/// 16-byte summary of the direction of a vector: every bit is the side of a hyperplane
+/// through the origin the vector lies on, so close vectors tend to have sketches that
+/// differ in few bits.
+///
+/// Component `i` only weighs on bit `i % 128`, with a fixed pseudo-random sign, so that
+/// sketching costs a single pass over the vector. Vectors of at most 128 components get
+/// one bit per component.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SignSketch([u64; SKETCH_WORDS]);
+
+impl SignSketch {
+    /// Number of bits that differ between the sketches.
+    #[inline]
+    pub fn hamming(&self, other: &Self) -> u32 {
+        let diff = Simd::from_array(self.0) ^ Simd::from_array(other.0);
+        diff.count_ones().reduce_sum() as u32
+    }
+}
+
+impl From<&[f32]> for SignSketch {
+    fn from(vector: &[f32]) -> Self {
+        let mut sums = [0.0f32; SKETCH_BITS];
+        for (i, &x) in vector.iter().enumerate() {
+            let sum = &mut sums[i % SKETCH_BITS];
+            if component_sign(i) {
+                *sum -= x;
+            } else {
+                *sum += x;
+            }
+        }
+        let mut words = [0u64; SKETCH_WORDS];
+        for (bit, sum) in sums.iter().enumerate() {
+            if *sum >= 0.0 {
+                words[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        SignSketch(words)
+    }
+}
+
+/// Pseudo-random sign of component `i`, the same for every vector (splitmix64 finalizer).
+fn component_sign(i: usize) -> bool {
+    let mut z = (i as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (z ^ (z >> 31)) >> 63 == 1
+}
+
+/// Vector key carrying the [`SignSketch`] of its vector, computed once when the key is
+/// built, to skip the exact comparison of keys pointing in clearly different directions.
+///
+/// Two keys only match if their sketches differ in at most `max_flips` bits, the larger
+/// of both keys, and if the inner keys match. The Hamming distance of the sketches costs
+/// a couple of instructions, against a pass over the vectors for the inner keys, so
+/// caches scanning many entries spend most of a lookup on the few that pass.
+///
+/// The sketch is a heuristic: a stored key within the tolerance of the query may be
+/// skipped, more often with a low `max_flips`, with keys of many components near zero, or
+/// with a tolerance large against the norm of the keys. Distances, e.g. those returned by
+/// [`find_k`](crate::caching::ApproximateCache::find_k) or
+/// [`nearest`](crate::caching::ApproximateCache::nearest), are those of the inner keys.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{ApproximateCache, FifoCache};
+/// use proximity::numerics::Sketched;
+///
+/// let mut cache = FifoCache::new(4).unwrap();
+/// cache.insert(Sketched::from(vec![1.0f32; 16]), "Value 1", 1.0);
+/// assert_eq!(cache.find(&Sketched::from(vec![1.1f32; 16])), Some("Value 1"));
+/// // within the tolerance, but of the opposite direction
+/// cache.insert(Sketched::new(vec![0.1f32; 16], 0), "Value 2", 1.0);
+/// assert_eq!(cache.find(&Sketched::new(vec![-0.1f32; 16], 0)), None);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sketched<K> {
+    key: K,
+    sketch: SignSketch,
+    max_flips: u32,
+}
+
+impl<K: AsRef<[f32]>> Sketched<K> {
+    /// Sketches `key`, which then only matches keys whose sketch differs in at most
+    /// `max_flips` of the [`SKETCH_BITS`] bits, unless theirs allows more.
+    pub fn new(key: K, max_flips: u32) -> Self {
+        let sketch = SignSketch::from(key.as_ref());
+        Sketched {
+            key,
+            sketch,
+            max_flips,
+        }
+    }
+}
+
+impl<K> Sketched<K> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn sketch(&self) -> &SignSketch {
+        &self.sketch
+    }
+
+    pub fn max_flips(&self) -> u32 {
+        self.max_flips
+    }
+
+    /// Whether the sketches are close enough for the keys to be compared.
+    #[inline]
+    pub fn may_match(&self, other: &Self) -> bool {
+        self.sketch.hamming(&other.sketch) <= self.max_flips.max(other.max_flips)
+    }
+}
+
+/// Sketches the key, up to [`DEFAULT_MAX_FLIPS`] flips apart.
+impl<K: AsRef<[f32]>> From<K> for Sketched<K> {
+    fn from(key: K) -> Self {
+        Self::new(key, DEFAULT_MAX_FLIPS)
+    }
+}
+
+impl<K: AsRef<[f32]>> AsRef<[f32]> for Sketched<K> {
+    fn as_ref(&self) -> &[f32] {
+        self.key.as_ref()
+    }
+}
+
+impl<K: ApproxComparable + AsRef<[f32]>> ApproxComparable for Sketched<K> {
+    #[inline]
+    fn roughly_matches(&self, instore: &Self, tolerance: f32) -> bool {
+        self.may_match(instore) && self.key.roughly_matches(&instore.key, tolerance)
+    }
+
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        self.key.fuzziness(&instore.key)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.key.dimension()
+    }
+
+    fn is_finite(&self) -> bool {
+        self.key.is_finite()
+    }
+
+    /// Sanitizes the inner key, then sketches it again.
+    fn sanitize(&mut self) {
+        self.key.sanitize();
+        self.sketch = SignSketch::from(self.key.as_ref());
+    }
+
+    /// Scaling does not change the sketch.
+    fn normalize(&mut self) {
+        self.key.normalize();
+    }
+}
+
+impl<K: HeapSize> HeapSize for Sketched<K> {
+    fn heap_bytes(&self) -> usize {
+        self.key.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sketch_hamming() {
+        let v: Vec<f32> = (0..256).map(|i| (i as f32).sin()).collect();
+        let opposite: Vec<f32> = v.iter().map(|x| -x).collect();
+        let scaled: Vec<f32> = v.iter().map(|x| 3.0 * x).collect();
+        let sketch = SignSketch::from(&v[..]);
+        assert_eq!(sketch.hamming(&sketch), 0);
+        assert_eq!(sketch.hamming(&SignSketch::from(&scaled[..])), 0);
+        // sums of exactly zero are the only bits that do not flip
+        assert!(sketch.hamming(&SignSketch::from(&opposite[..])) > 120);
+    }
+
+    #[test]
+    fn test_prefilter_keeps_close_keys() {
+        let v: Vec<f32> = (0..64).map(|i| (i as f32 * 0.7).cos()).collect();
+        let near: Vec<f32> = v.iter().map(|x| x + 0.01).collect();
+        let far: Vec<f32> = v.iter().map(|x| -x).collect();
+        let (sv, snear) = (Sketched::from(v.clone()), Sketched::from(near.clone()));
+        assert!(sv.may_match(&snear));
+        assert!(sv.roughly_matches(&snear, 1.0));
+        assert_eq!(sv.fuzziness(&snear), v.fuzziness(&near));
+
+        let sfar = Sketched::from(far.clone());
+        assert!(!sv.may_match(&sfar));
+        assert!(!sv.roughly_matches(&sfar, f32::INFINITY));
+        // the distance is still that of the vectors
+        assert_eq!(sv.fuzziness(&sfar), v.fuzziness(&far));
+        // unless a key allows every flip
+        assert!(Sketched::new(far, SKETCH_BITS as u32).roughly_matches(&sv, f32::INFINITY));
+    }
+}
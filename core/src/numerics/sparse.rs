@@ -0,0 +1,116 @@
+use crate::caching::HeapSize;
+use crate::numerics::ApproxComparable;
+
+/// Set-valued key, e.g. the token ids of a prompt or the active features of a sparse
+/// vector, kept as a sorted list of distinct ids.
+///
+/// Keys are compared by Jaccard distance, `1 - |A ∩ B| / |A ∪ B|`, between 0 for equal
+/// sets and 1 for disjoint ones, so tolerances are fractions of ids that may differ.
+///
+/// # Example Usage
+/// ```
+/// use proximity::numerics::{ApproxComparable, TokenSet};
+///
+/// let a = TokenSet::from(vec![3, 1, 2, 4]);
+/// let b = TokenSet::from(vec![1, 2, 3, 5]);
+/// assert_eq!(a.ids(), &[1, 2, 3, 4]);
+/// assert!((a.fuzziness(&b) - 0.4).abs() < 1e-6); // 3 shared ids out of 5
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TokenSet(Vec<u32>);
+
+impl TokenSet {
+    /// The ids of the set, in increasing order.
+    pub fn ids(&self) -> &[u32] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Number of ids in both sets.
+    pub fn intersection_len(&self, other: &Self) -> usize {
+        let (mut a, mut b) = (self.0.iter().peekable(), other.0.iter().peekable());
+        let mut shared = 0;
+        while let (Some(x), Some(y)) = (a.peek(), b.peek()) {
+            match x.cmp(y) {
+                std::cmp::Ordering::Less => {
+                    a.next();
+                }
+                std::cmp::Ordering::Greater => {
+                    b.next();
+                }
+                std::cmp::Ordering::Equal => {
+                    shared += 1;
+                    a.next();
+                    b.next();
+                }
+            }
+        }
+        shared
+    }
+}
+
+/// Sorts and deduplicates the ids.
+impl From<Vec<u32>> for TokenSet {
+    fn from(mut ids: Vec<u32>) -> Self {
+        ids.sort_unstable();
+        ids.dedup();
+        TokenSet(ids)
+    }
+}
+
+impl FromIterator<u32> for TokenSet {
+    fn from_iter<I: IntoIterator<Item = u32>>(ids: I) -> Self {
+        TokenSet::from(ids.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl AsRef<[u32]> for TokenSet {
+    fn as_ref(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+impl ApproxComparable for TokenSet {
+    /// Jaccard distance, two empty sets being equal.
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        let shared = self.intersection_len(instore);
+        let union = self.0.len() + instore.0.len() - shared;
+        if union == 0 {
+            return 0.0;
+        }
+        1.0 - shared as f32 / union as f32
+    }
+}
+
+impl HeapSize for TokenSet {
+    fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jaccard_distance() {
+        let a: TokenSet = [5, 1, 1, 3].into_iter().collect();
+        assert_eq!(a.ids(), &[1, 3, 5]);
+        let b = TokenSet::from(vec![1, 3, 7, 9]);
+        assert_eq!(a.intersection_len(&b), 2);
+        assert_eq!(a.fuzziness(&b), 1.0 - 2.0 / 5.0);
+        assert_eq!(a.fuzziness(&a), 0.0);
+        assert_eq!(a.fuzziness(&TokenSet::from(vec![2, 4])), 1.0);
+        assert_eq!(TokenSet::default().fuzziness(&TokenSet::default()), 0.0);
+        assert_eq!(a.fuzziness(&TokenSet::default()), 1.0);
+        assert!(a.roughly_matches(&b, 0.7));
+        assert!(!a.roughly_matches(&b, 0.5));
+    }
+}
@@ -0,0 +1,149 @@
+//! Selection of the k smallest items, e.g. distances, without sorting all of them.
+//!
+//! [`smallest_k`] keeps a heap of at most k items and suits streams, while
+//! [`select_smallest_k_by`] partitions a buffer in place with quickselect and suits
+//! distances already collected. Both take O(n log k) time or better, against O(n log n)
+//! for a full sort, and return the selected items smallest first.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// `f32` ordered by [`f32::total_cmp`], so that it can be sorted or kept in a heap.
+/// Negative NaN sorts before every number and positive NaN after.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TotalF32(pub f32);
+
+impl PartialEq for TotalF32 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for TotalF32 {}
+
+impl PartialOrd for TotalF32 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TotalF32 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// The `k` smallest items, smallest first, keeping at most `k` of them at once in a
+/// bounded heap. Equal items may come in any order.
+pub fn smallest_k<T: Ord>(items: impl IntoIterator<Item = T>, k: usize) -> Vec<T> {
+    if k == 0 {
+        return Vec::new();
+    }
+    let mut heap = BinaryHeap::with_capacity(k);
+    for item in items {
+        if heap.len() < k {
+            heap.push(item);
+        } else if heap.peek().is_some_and(|largest| item < *largest) {
+            heap.pop();
+            heap.push(item);
+        }
+    }
+    heap.into_sorted_vec()
+}
+
+/// The `k` smallest items of `items` by `compare`, smallest first, partitioning the
+/// buffer with quickselect before sorting the selected items only.
+pub fn select_smallest_k_by<T, F>(mut items: Vec<T>, k: usize, mut compare: F) -> Vec<T>
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    if k == 0 {
+        return Vec::new();
+    }
+    if k < items.len() {
+        items.select_nth_unstable_by(k - 1, &mut compare);
+        items.truncate(k);
+    }
+    items.sort_by(compare);
+    items
+}
+
+/// Positions and values of the `k` smallest distances, closest first, with ties going to
+/// the first position. NaN distances are never selected.
+pub fn k_smallest_distances(distances: &[f32], k: usize) -> Vec<(usize, f32)> {
+    smallest_k(
+        distances
+            .iter()
+            .enumerate()
+            .filter(|(_, dist)| !dist.is_nan())
+            .map(|(idx, &dist)| (TotalF32(dist), idx)),
+        k,
+    )
+    .into_iter()
+    .map(|(dist, idx)| (idx, dist.0))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{QuickCheck, TestResult};
+
+    fn sorted_spec(distances: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let mut all: Vec<(usize, f32)> = distances
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, dist)| !dist.is_nan())
+            .collect();
+        all.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        all.truncate(k);
+        all
+    }
+
+    #[test]
+    fn selection_matches_sort() {
+        fn qc_selection_matches_sort(distances: Vec<f32>, k: u8) -> TestResult {
+            let k = usize::from(k % 16);
+            let spec = sorted_spec(&distances, k);
+            let by_heap = k_smallest_distances(&distances, k);
+            let by_select = select_smallest_k_by(
+                distances
+                    .iter()
+                    .copied()
+                    .enumerate()
+                    .filter(|(_, dist)| !dist.is_nan())
+                    .collect(),
+                k,
+                |a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)),
+            );
+            let same = |found: &[(usize, f32)]| {
+                found.len() == spec.len()
+                    && found
+                        .iter()
+                        .zip(&spec)
+                        .all(|(a, b)| a.0 == b.0 && a.1.to_bits() == b.1.to_bits())
+            };
+            TestResult::from_bool(same(&by_heap) && same(&by_select))
+        }
+
+        QuickCheck::new()
+            .tests(10_000)
+            .quickcheck(qc_selection_matches_sort as fn(Vec<f32>, u8) -> TestResult);
+    }
+
+    #[test]
+    fn test_ties_and_bounds() {
+        let distances = [3.0, 1.0, f32::NAN, 1.0, 2.0];
+        assert_eq!(
+            k_smallest_distances(&distances, 3),
+            vec![(1, 1.0), (3, 1.0), (4, 2.0)]
+        );
+        assert_eq!(k_smallest_distances(&distances, 10).len(), 4);
+        assert!(k_smallest_distances(&distances, 0).is_empty());
+        assert_eq!(
+            smallest_k([TotalF32(-0.0), TotalF32(0.0)], 1),
+            [TotalF32(-0.0)]
+        );
+    }
+}
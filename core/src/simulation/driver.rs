@@ -0,0 +1,267 @@
+use std::cell::Cell;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use crate::caching::ApproximateCache;
+use crate::clock::Instant;
+use crate::numerics::ApproxComparable;
+
+thread_local! {
+    static COMPARISONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Dense vector key used by the simulator.
+///
+/// Every call to `roughly_matches` is counted, which measures how many stored
+/// keys a cache actually scans per lookup, whatever its internal structure.
+#[derive(Clone, Debug)]
+pub struct SimKey(pub Vec<f32>);
+
+impl PartialEq for SimKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| a.to_bits() == b.to_bits())
+    }
+}
+
+impl Eq for SimKey {}
+
+impl Hash for SimKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for &val in &self.0 {
+            state.write_u32(val.to_bits());
+        }
+    }
+}
+
+impl From<Vec<f32>> for SimKey {
+    fn from(vector: Vec<f32>) -> Self {
+        SimKey(vector)
+    }
+}
+
+impl AsRef<[f32]> for SimKey {
+    fn as_ref(&self) -> &[f32] {
+        self.0.as_ref()
+    }
+}
+
+impl AsMut<[f32]> for SimKey {
+    fn as_mut(&mut self) -> &mut [f32] {
+        self.0.as_mut()
+    }
+}
+
+impl ApproxComparable for SimKey {
+    #[inline]
+    fn roughly_matches(&self, instore: &Self, tolerance: f32) -> bool {
+        COMPARISONS.with(|count| count.set(count.get() + 1));
+        (&self.0 as &[f32]).roughly_matches(&instore.0, tolerance)
+    }
+
+    #[inline]
+    fn fuzziness(&self, instore: &Self) -> f32 {
+        (&self.0 as &[f32]).fuzziness(&instore.0)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+
+    fn is_finite(&self) -> bool {
+        (&self.0 as &[f32]).is_finite()
+    }
+
+    fn sanitize(&mut self) {
+        (&mut self.0 as &mut [f32]).sanitize()
+    }
+
+    fn normalize(&mut self) {
+        (&mut self.0 as &mut [f32]).normalize()
+    }
+}
+
+/// Outcome of a [`simulate`] run.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SimulationReport {
+    pub lookups: u64,
+    pub hits: u64,
+    /// Total number of stored keys compared against queries.
+    pub scanned: u64,
+    /// Total time spent in `find`, excluding the inserts that follow misses.
+    pub lookup_time: Duration,
+}
+
+impl SimulationReport {
+    pub fn hit_rate(&self) -> f32 {
+        if self.lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f32 / self.lookups as f32
+    }
+
+    /// Average number of stored keys compared against each query.
+    pub fn mean_scan(&self) -> f32 {
+        if self.lookups == 0 {
+            return 0.0;
+        }
+        self.scanned as f32 / self.lookups as f32
+    }
+
+    /// Average time spent in `find` per query.
+    pub fn mean_latency(&self) -> Duration {
+        if self.lookups == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(self.lookup_time.as_secs_f64() / self.lookups as f64)
+    }
+}
+
+/// Replays `queries` against `cache`, inserting every missed query with `tolerance`.
+///
+/// Inserted values are the index of the query in the stream.
+pub fn simulate<C, I>(cache: &mut C, queries: I, tolerance: f32) -> SimulationReport
+where
+    C: ApproximateCache<SimKey, u64> + ?Sized,
+    I: IntoIterator<Item = Vec<f32>>,
+{
+    let mut report = SimulationReport::default();
+    for (idx, query) in queries.into_iter().enumerate() {
+        let key = SimKey(query);
+        if !lookup(cache, &key, &mut report) {
+            cache.insert(key, idx as u64, tolerance);
+        }
+    }
+    report
+}
+
+/// Replays `queries` against every cache of `shadows` side by side, inserting every
+/// missed query with `tolerance`, and returns a report per cache.
+///
+/// Shadow caches hold `()` values, so that they only keep keys and the metadata of
+/// their policy: comparing policies or capacities on a real trace costs the keys, not
+/// the values the trace would have cached. The caches are typically built by
+/// [`CacheBuilder`](crate::caching::CacheBuilder), as boxes.
+///
+/// # Example Usage
+/// ```
+/// use proximity::caching::{CacheBuilder, EvictionPolicy};
+/// use proximity::simulation::{simulate_shadows, TraceGenerator, Workload};
+///
+/// let mut shadows = Vec::new();
+/// for policy in [EvictionPolicy::Lru, EvictionPolicy::Lfu] {
+///     for capacity in [64, 256] {
+///         let builder = CacheBuilder::new().policy(policy).capacity(capacity);
+///         shadows.push(builder.build().unwrap());
+///     }
+/// }
+/// let workload = Workload::Zipf { pool_size: 512, exponent: 1.0, dim: 8, noise: 0.001 };
+/// let trace = TraceGenerator::new(workload, 42).take(1000);
+///
+/// let reports = simulate_shadows(&mut shadows, trace, 0.1);
+/// // a larger cache of the same policy hits at least as often
+/// assert!(reports[1].hits >= reports[0].hits);
+/// ```
+pub fn simulate_shadows<C, I>(
+    shadows: &mut [C],
+    queries: I,
+    tolerance: f32,
+) -> Vec<SimulationReport>
+where
+    C: ApproximateCache<SimKey, ()>,
+    I: IntoIterator<Item = Vec<f32>>,
+{
+    let mut reports = vec![SimulationReport::default(); shadows.len()];
+    for query in queries {
+        let key = SimKey(query);
+        for (cache, report) in shadows.iter_mut().zip(&mut reports) {
+            if !lookup(cache, &key, report) {
+                cache.insert(key.clone(), (), tolerance);
+            }
+        }
+    }
+    reports
+}
+
+/// Looks `key` up in `cache`, recording it in `report`, and tells whether it hit.
+fn lookup<C, V>(cache: &mut C, key: &SimKey, report: &mut SimulationReport) -> bool
+where
+    C: ApproximateCache<SimKey, V> + ?Sized,
+{
+    let before = COMPARISONS.with(Cell::get);
+    let start = Instant::now();
+    let found = cache.find(key);
+    report.lookup_time += start.elapsed();
+    report.scanned += COMPARISONS.with(Cell::get) - before;
+    report.lookups += 1;
+    let hit = found.is_some();
+    report.hits += u64::from(hit);
+    hit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::{FifoCache, LshLruCache};
+    use crate::simulation::{TraceGenerator, Workload};
+
+    const DIM: usize = 16;
+
+    #[test]
+    fn test_simulate_counts_scans() {
+        let mut cache = FifoCache::new(4).unwrap();
+        let queries = vec![vec![1.0; DIM], vec![2.0; DIM], vec![1.0; DIM]];
+        let report = simulate(&mut cache, queries, 0.5);
+        assert_eq!(report.lookups, 3);
+        assert_eq!(report.hits, 1);
+        // an empty cache, then one stored key, then two stored keys
+        assert_eq!(report.scanned, 3);
+        assert_eq!(report.mean_scan(), 1.0);
+    }
+
+    #[test]
+    fn test_lsh_scans_less_than_linear() {
+        let workload = Workload::Zipf {
+            pool_size: 200,
+            exponent: 1.0,
+            dim: DIM,
+            noise: 0.001,
+        };
+        let queries: Vec<Vec<f32>> = TraceGenerator::new(workload, 5).take(2000).collect();
+
+        let mut linear = FifoCache::new(256).unwrap();
+        let linear_report = simulate(&mut linear, queries.clone(), 0.05);
+        let mut lsh = LshLruCache::new(6, DIM, 64, Some(5)).unwrap();
+        let lsh_report = simulate(&mut lsh, queries, 0.05);
+
+        assert!(linear_report.hit_rate() > 0.5);
+        assert!(lsh_report.hit_rate() > 0.5);
+        assert!(lsh_report.mean_scan() < linear_report.mean_scan());
+    }
+
+    #[test]
+    fn test_shadows_match_full_caches() {
+        let workload = Workload::Zipf {
+            pool_size: 100,
+            exponent: 1.0,
+            dim: DIM,
+            noise: 0.001,
+        };
+        let queries: Vec<Vec<f32>> = TraceGenerator::new(workload, 3).take(500).collect();
+
+        let mut full = FifoCache::new(32).unwrap();
+        let full_report = simulate(&mut full, queries.clone(), 0.05);
+        let mut shadows = [FifoCache::new(32).unwrap(), FifoCache::new(64).unwrap()];
+        let reports = simulate_shadows(&mut shadows, queries, 0.05);
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].hits, full_report.hits);
+        assert_eq!(reports[0].scanned, full_report.scanned);
+        assert!(reports[1].hits >= reports[0].hits);
+        assert_eq!(shadows[1].len(), 64);
+    }
+}
@@ -0,0 +1,5 @@
+mod driver;
+mod workload;
+
+pub use driver::{simulate, simulate_shadows, SimKey, SimulationReport};
+pub use workload::{TraceGenerator, Workload};
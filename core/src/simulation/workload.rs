@@ -0,0 +1,215 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, StandardNormal, Zipf};
+
+use crate::numerics::{VectorLike, SIMD_LANECOUNT};
+
+/// Shape of a synthetic query stream.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Workload {
+    /// Queries revisit a fixed pool of `pool_size` unit vectors with Zipfian popularity.
+    /// Each query is the pool vector plus gaussian noise of standard deviation `noise`.
+    Zipf {
+        pool_size: usize,
+        exponent: f64,
+        dim: usize,
+        noise: f32,
+    },
+    /// Queries are drawn around `clusters` centers that random-walk by `drift` after each query.
+    DriftingClusters {
+        clusters: usize,
+        dim: usize,
+        spread: f32,
+        drift: f32,
+    },
+    /// A uniformly chosen pool vector is repeated `burst_len` times in a row, with noise.
+    Bursty {
+        pool_size: usize,
+        burst_len: usize,
+        dim: usize,
+        noise: f32,
+    },
+}
+
+impl Workload {
+    pub fn dim(&self) -> usize {
+        match *self {
+            Workload::Zipf { dim, .. }
+            | Workload::DriftingClusters { dim, .. }
+            | Workload::Bursty { dim, .. } => dim,
+        }
+    }
+}
+
+/// Infinite, deterministic stream of queries following a [`Workload`].
+///
+/// # Example Usage
+/// ```
+/// use proximity::simulation::{TraceGenerator, Workload};
+///
+/// let workload = Workload::Zipf { pool_size: 100, exponent: 1.1, dim: 16, noise: 0.01 };
+/// let queries: Vec<Vec<f32>> = TraceGenerator::new(workload, 42).take(1000).collect();
+/// assert_eq!(queries.len(), 1000);
+/// assert!(queries.iter().all(|q| q.len() == 16));
+/// ```
+pub struct TraceGenerator {
+    workload: Workload,
+    rng: StdRng,
+    /// pool vectors or cluster centers, depending on the workload
+    anchors: Vec<Vec<f32>>,
+    zipf: Option<Zipf<f64>>,
+    burst_left: usize,
+    burst_anchor: usize,
+}
+
+impl TraceGenerator {
+    pub fn new(workload: Workload, seed: u64) -> Self {
+        let dim = workload.dim();
+        assert!(
+            dim > 0 && dim.is_multiple_of(SIMD_LANECOUNT),
+            "dim must be a positive multiple of SIMD_LANECOUNT"
+        );
+        let mut rng = StdRng::seed_from_u64(seed);
+        let anchor_count = match workload {
+            Workload::Zipf { pool_size, .. } | Workload::Bursty { pool_size, .. } => pool_size,
+            Workload::DriftingClusters { clusters, .. } => clusters,
+        };
+        assert!(anchor_count > 0);
+        let anchors = (0..anchor_count)
+            .map(|_| gaussian(&mut rng, dim, 1.0).normalized())
+            .collect();
+        let zipf = match workload {
+            Workload::Zipf {
+                pool_size,
+                exponent,
+                ..
+            } => Some(Zipf::new(pool_size as f64, exponent).expect("invalid zipf exponent")),
+            _ => None,
+        };
+        Self {
+            workload,
+            rng,
+            anchors,
+            zipf,
+            burst_left: 0,
+            burst_anchor: 0,
+        }
+    }
+
+    fn around(&mut self, anchor: usize, noise: f32) -> Vec<f32> {
+        let dim = self.workload.dim();
+        let offset = gaussian(&mut self.rng, dim, noise);
+        self.anchors[anchor]
+            .iter()
+            .zip(offset)
+            .map(|(a, o)| a + o)
+            .collect()
+    }
+}
+
+impl Iterator for TraceGenerator {
+    type Item = Vec<f32>;
+
+    fn next(&mut self) -> Option<Vec<f32>> {
+        let query = match self.workload {
+            Workload::Zipf { noise, .. } => {
+                // zipf samples are ranks in [1, pool_size]
+                let rank = self.zipf.as_ref().unwrap().sample(&mut self.rng) as usize;
+                self.around(rank - 1, noise)
+            }
+            Workload::DriftingClusters {
+                clusters,
+                dim,
+                spread,
+                drift,
+            } => {
+                let cluster = self.rng.random_range(0..clusters);
+                let query = self.around(cluster, spread);
+                for center in self.anchors.iter_mut() {
+                    let step = gaussian(&mut self.rng, dim, drift);
+                    center.iter_mut().zip(step).for_each(|(c, s)| *c += s);
+                }
+                query
+            }
+            Workload::Bursty {
+                pool_size,
+                burst_len,
+                noise,
+                ..
+            } => {
+                if self.burst_left == 0 {
+                    self.burst_anchor = self.rng.random_range(0..pool_size);
+                    self.burst_left = burst_len.max(1);
+                }
+                self.burst_left -= 1;
+                self.around(self.burst_anchor, noise)
+            }
+        };
+        Some(query)
+    }
+}
+
+fn gaussian<R: Rng>(rng: &mut R, dim: usize, std_dev: f32) -> Vec<f32> {
+    (0..dim)
+        .map(|_| rng.sample::<f32, _>(StandardNormal) * std_dev)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIM: usize = SIMD_LANECOUNT * 2;
+
+    #[test]
+    fn test_generator_is_deterministic() {
+        let workload = Workload::DriftingClusters {
+            clusters: 4,
+            dim: DIM,
+            spread: 0.1,
+            drift: 0.01,
+        };
+        let a: Vec<Vec<f32>> = TraceGenerator::new(workload.clone(), 7).take(50).collect();
+        let b: Vec<Vec<f32>> = TraceGenerator::new(workload, 7).take(50).collect();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_zipf_without_noise_repeats_pool_vectors() {
+        let workload = Workload::Zipf {
+            pool_size: 10,
+            exponent: 1.5,
+            dim: DIM,
+            noise: 0.0,
+        };
+        let mut queries: Vec<Vec<f32>> = TraceGenerator::new(workload, 1).take(500).collect();
+        queries.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        queries.dedup();
+        assert!(queries.len() <= 10);
+    }
+
+    #[test]
+    fn test_bursts_repeat() {
+        let workload = Workload::Bursty {
+            pool_size: 1000,
+            burst_len: 5,
+            dim: DIM,
+            noise: 0.0,
+        };
+        let queries: Vec<Vec<f32>> = TraceGenerator::new(workload, 3).take(10).collect();
+        assert!(queries[..5].iter().all(|q| *q == queries[0]));
+        assert!(queries[5..].iter().all(|q| *q == queries[5]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bad_dimension() {
+        let workload = Workload::Bursty {
+            pool_size: 1,
+            burst_len: 1,
+            dim: SIMD_LANECOUNT + 1,
+            noise: 0.0,
+        };
+        let _ = TraceGenerator::new(workload, 0);
+    }
+}
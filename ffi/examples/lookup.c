@@ -0,0 +1,45 @@
+/* Inserts a vector and looks up a close one.
+ *
+ *   cargo build --release
+ *   cc examples/lookup.c -Iinclude -Ltarget/release -lproximity -o lookup
+ *   LD_LIBRARY_PATH=target/release ./lookup
+ */
+#include <stdio.h>
+#include <string.h>
+
+#include "proximity.h"
+
+int main(void) {
+    ProximityCache *cache = proximity_cache_new(PROXIMITY_POLICY_LRU, 64, 4, 8, 42);
+    if (cache == NULL) {
+        fprintf(stderr, "error: %s\n", proximity_last_error());
+        return 1;
+    }
+
+    float key[8] = {1, 2, 3, 4, 5, 6, 7, 8};
+    const char *value = "Value 1";
+    if (proximity_cache_insert(cache, key, 8, (const uint8_t *)value, strlen(value), 0.5f)
+        != PROXIMITY_STATUS_OK) {
+        fprintf(stderr, "error: %s\n", proximity_last_error());
+        proximity_cache_free(cache);
+        return 1;
+    }
+
+    key[0] += 0.1f;
+    ProximityBytes found;
+    switch (proximity_cache_find(cache, key, 8, &found)) {
+    case PROXIMITY_STATUS_OK:
+        printf("hit: %.*s\n", (int)found.len, (const char *)found.data);
+        proximity_bytes_free(found);
+        break;
+    case PROXIMITY_STATUS_MISS:
+        printf("miss\n");
+        break;
+    case PROXIMITY_STATUS_ERROR:
+        fprintf(stderr, "error: %s\n", proximity_last_error());
+        break;
+    }
+
+    proximity_cache_free(cache);
+    return 0;
+}
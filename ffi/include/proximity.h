@@ -0,0 +1,157 @@
+#ifndef PROXIMITY_H
+#define PROXIMITY_H
+
+/* Generated by cbindgen from ffi/src/lib.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Which entry a full cache (or LSH bucket) evicts.
+ */
+typedef enum ProximityPolicy {
+  PROXIMITY_POLICY_LRU = 0,
+  PROXIMITY_POLICY_FIFO = 1,
+  /**
+   * LRU-2: evicts the entry whose second most recent use is the oldest.
+   */
+  PROXIMITY_POLICY_LRU_K = 2,
+  /**
+   * Evicts the least frequently used entry, frequencies aging over time.
+   */
+  PROXIMITY_POLICY_LFU = 3,
+  /**
+   * Evicts the first entry not used since the clock hand last passed it.
+   */
+  PROXIMITY_POLICY_CLOCK = 4,
+  /**
+   * W-TinyLFU: admits newcomers to the main space by estimated frequency.
+   */
+  PROXIMITY_POLICY_W_TINY_LFU = 5,
+  /**
+   * Greedy-Dual-Size-Frequency, every entry at cost 1 and size 1.
+   */
+  PROXIMITY_POLICY_GDSF = 6,
+  /**
+   * S3-FIFO: moves newcomers looked up more than once from a small queue to the main one.
+   */
+  PROXIMITY_POLICY_S3_FIFO = 7,
+} ProximityPolicy;
+
+/**
+ * Outcome of a fallible call.
+ */
+typedef enum ProximityStatus {
+  PROXIMITY_STATUS_OK = 0,
+  /**
+   * A lookup found no matching key.
+   */
+  PROXIMITY_STATUS_MISS = 1,
+  /**
+   * The call failed, see `proximity_last_error`.
+   */
+  PROXIMITY_STATUS_ERROR = 2,
+} ProximityStatus;
+
+/**
+ * An approximate cache mapping float vectors to byte strings.
+ *
+ * A cache is not thread-safe: calls on the same cache must not overlap.
+ */
+typedef struct ProximityCache ProximityCache;
+
+/**
+ * A value copied out of a cache, to be released with `proximity_bytes_free`.
+ */
+typedef struct ProximityBytes {
+  uint8_t *data;
+  size_t len;
+} ProximityBytes;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a cache holding up to `capacity` entries, or NULL on error, e.g. when
+ * `policy` is not a `ProximityPolicy`.
+ *
+ * With `num_hash` > 0, keys of dimension `dim` are routed into buckets by `num_hash`
+ * random hyperplanes drawn from `seed`, and `capacity` bounds each bucket. With
+ * `num_hash` = 0, `dim` and `seed` are ignored.
+ */
+struct ProximityCache *proximity_cache_new(uint32_t policy,
+                                           size_t capacity,
+                                           size_t num_hash,
+                                           size_t dim,
+                                           uint64_t seed);
+
+/**
+ * Destroys a cache created by `proximity_cache_new`. Does nothing on NULL.
+ *
+ * # Safety
+ * `cache` must be NULL or a cache that was not freed yet.
+ */
+void proximity_cache_free(struct ProximityCache *cache);
+
+/**
+ * Looks up the value of the closest key matching the `key_len` floats at `key`.
+ *
+ * Returns PROXIMITY_STATUS_OK and fills `out` on a hit, PROXIMITY_STATUS_MISS
+ * otherwise. The value in `out` belongs to the caller, who releases it with
+ * `proximity_bytes_free`.
+ *
+ * # Safety
+ * `cache` must be a live cache, `key` must point to `key_len` floats and `out` must
+ * point to writable memory.
+ */
+enum ProximityStatus proximity_cache_find(struct ProximityCache *cache,
+                                          const float *key,
+                                          size_t key_len,
+                                          struct ProximityBytes *out);
+
+/**
+ * Stores a copy of the `value_len` bytes at `value` under the `key_len` floats at
+ * `key`, matching lookups within `tolerance` of it, evicting an entry if full.
+ *
+ * # Safety
+ * `cache` must be a live cache, `key` must point to `key_len` floats and `value`
+ * to `value_len` bytes.
+ */
+enum ProximityStatus proximity_cache_insert(struct ProximityCache *cache,
+                                            const float *key,
+                                            size_t key_len,
+                                            const uint8_t *value,
+                                            size_t value_len,
+                                            float tolerance);
+
+/**
+ * Number of entries in the cache, or 0 if `cache` is NULL.
+ *
+ * # Safety
+ * `cache` must be NULL or a live cache.
+ */
+size_t proximity_cache_len(const struct ProximityCache *cache);
+
+/**
+ * Releases a value returned by `proximity_cache_find`.
+ *
+ * # Safety
+ * `bytes` must come from `proximity_cache_find` and not have been freed yet.
+ */
+void proximity_bytes_free(struct ProximityBytes bytes);
+
+/**
+ * Message of the last error raised on this thread, or NULL if there was none.
+ * It stays valid until the next failing call on this thread.
+ */
+const char *proximity_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PROXIMITY_H */
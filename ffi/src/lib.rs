@@ -0,0 +1,378 @@
+//! C API of the approximate caches, for consumers outside of Rust and Python.
+//!
+//! `include/proximity.h` is generated from this file by the build script. Every
+//! function is prefixed with `proximity_`, fallible ones return a [`ProximityStatus`]
+//! or a null pointer, and [`proximity_last_error`] tells what went wrong.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use proximity::caching::{ApproximateCache, CacheBuilder, EvictionPolicy, NonFinitePolicy};
+use proximity::numerics::PlainVector;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Outcome of a fallible call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProximityStatus {
+    Ok = 0,
+    /// A lookup found no matching key.
+    Miss = 1,
+    /// The call failed, see `proximity_last_error`.
+    Error = 2,
+}
+
+/// Which entry a full cache (or LSH bucket) evicts.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProximityPolicy {
+    Lru = 0,
+    Fifo = 1,
+    /// LRU-2: evicts the entry whose second most recent use is the oldest.
+    LruK = 2,
+    /// Evicts the least frequently used entry, frequencies aging over time.
+    Lfu = 3,
+    /// Evicts the first entry not used since the clock hand last passed it.
+    Clock = 4,
+    /// W-TinyLFU: admits newcomers to the main space by estimated frequency.
+    WTinyLfu = 5,
+    /// Greedy-Dual-Size-Frequency, every entry at cost 1 and size 1.
+    Gdsf = 6,
+    /// S3-FIFO: moves newcomers looked up more than once from a small queue to the main one.
+    S3Fifo = 7,
+}
+
+impl ProximityPolicy {
+    const ALL: [Self; 8] = [
+        Self::Lru,
+        Self::Fifo,
+        Self::LruK,
+        Self::Lfu,
+        Self::Clock,
+        Self::WTinyLfu,
+        Self::Gdsf,
+        Self::S3Fifo,
+    ];
+
+    /// The policy of value `policy`, which C may hand over out of range.
+    fn from_raw(policy: u32) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|&known| known as u32 == policy)
+            .ok_or_else(|| format!("unknown eviction policy {policy}"))
+    }
+}
+
+/// A value copied out of a cache, to be released with `proximity_bytes_free`.
+#[repr(C)]
+pub struct ProximityBytes {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+/// An approximate cache mapping float vectors to byte strings.
+///
+/// A cache is not thread-safe: calls on the same cache must not overlap.
+pub struct ProximityCache {
+    inner: Box<dyn ApproximateCache<PlainVector, Vec<u8>>>,
+}
+
+fn set_last_error(msg: String) {
+    // messages never contain NUL bytes, but an empty message beats a panic
+    let msg = CString::new(msg).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(msg));
+}
+
+/// Runs `f`, turning errors and panics into `on_error` and recording their message.
+fn guarded<T>(on_error: T, f: impl FnOnce() -> Result<T, String>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(msg)) => {
+            set_last_error(msg);
+            on_error
+        }
+        Err(panic) => {
+            let msg = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".into());
+            set_last_error(format!("panic: {msg}"));
+            on_error
+        }
+    }
+}
+
+unsafe fn cache_mut<'a>(cache: *mut ProximityCache) -> Result<&'a mut ProximityCache, String> {
+    cache.as_mut().ok_or_else(|| "cache is null".to_string())
+}
+
+unsafe fn slice<'a, T>(data: *const T, len: usize, what: &str) -> Result<&'a [T], String> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if data.is_null() {
+        return Err(format!("{what} is null"));
+    }
+    Ok(std::slice::from_raw_parts(data, len))
+}
+
+impl ProximityCache {
+    fn key(&self, data: *const f32, len: usize) -> Result<PlainVector, String> {
+        let key = PlainVector(unsafe { slice(data, len, "key") }?.to_vec());
+        NonFinitePolicy::Reject
+            .check(&key)
+            .map_err(|e| e.to_string())?;
+        Ok(key)
+    }
+}
+
+/// Creates a cache holding up to `capacity` entries, or NULL on error, e.g. when
+/// `policy` is not a `ProximityPolicy`.
+///
+/// With `num_hash` > 0, keys of dimension `dim` are routed into buckets by `num_hash`
+/// random hyperplanes drawn from `seed`, and `capacity` bounds each bucket. With
+/// `num_hash` = 0, `dim` and `seed` are ignored.
+#[no_mangle]
+pub extern "C" fn proximity_cache_new(
+    policy: u32,
+    capacity: usize,
+    num_hash: usize,
+    dim: usize,
+    seed: u64,
+) -> *mut ProximityCache {
+    guarded(ptr::null_mut(), || {
+        let policy = match ProximityPolicy::from_raw(policy)? {
+            ProximityPolicy::Lru => EvictionPolicy::Lru,
+            ProximityPolicy::Fifo => EvictionPolicy::Fifo,
+            ProximityPolicy::LruK => EvictionPolicy::LruK,
+            ProximityPolicy::Lfu => EvictionPolicy::Lfu,
+            ProximityPolicy::Clock => EvictionPolicy::Clock,
+            ProximityPolicy::WTinyLfu => EvictionPolicy::WTinyLfu,
+            ProximityPolicy::Gdsf => EvictionPolicy::Gdsf,
+            ProximityPolicy::S3Fifo => EvictionPolicy::S3Fifo,
+        };
+        let mut builder = CacheBuilder::new().policy(policy).capacity(capacity);
+        if num_hash > 0 {
+            builder = builder.lsh(num_hash, dim, Some(seed));
+        }
+        let inner = builder.build().map_err(|e| e.to_string())?;
+        Ok(Box::into_raw(Box::new(ProximityCache { inner })))
+    })
+}
+
+/// Destroys a cache created by `proximity_cache_new`. Does nothing on NULL.
+///
+/// # Safety
+/// `cache` must be NULL or a cache that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn proximity_cache_free(cache: *mut ProximityCache) {
+    if !cache.is_null() {
+        drop(Box::from_raw(cache));
+    }
+}
+
+/// Looks up the value of the closest key matching the `key_len` floats at `key`.
+///
+/// Returns PROXIMITY_STATUS_OK and fills `out` on a hit, PROXIMITY_STATUS_MISS
+/// otherwise. The value in `out` belongs to the caller, who releases it with
+/// `proximity_bytes_free`.
+///
+/// # Safety
+/// `cache` must be a live cache, `key` must point to `key_len` floats and `out` must
+/// point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn proximity_cache_find(
+    cache: *mut ProximityCache,
+    key: *const f32,
+    key_len: usize,
+    out: *mut ProximityBytes,
+) -> ProximityStatus {
+    guarded(ProximityStatus::Error, || {
+        let cache = cache_mut(cache)?;
+        if out.is_null() {
+            return Err("out is null".into());
+        }
+        let key = cache.key(key, key_len)?;
+        let Some(value) = cache.inner.try_find(&key).map_err(|e| e.to_string())? else {
+            return Ok(ProximityStatus::Miss);
+        };
+        let value = Box::into_raw(value.into_boxed_slice());
+        out.write(ProximityBytes {
+            data: value.cast(),
+            len: value.len(),
+        });
+        Ok(ProximityStatus::Ok)
+    })
+}
+
+/// Stores a copy of the `value_len` bytes at `value` under the `key_len` floats at
+/// `key`, matching lookups within `tolerance` of it, evicting an entry if full.
+///
+/// # Safety
+/// `cache` must be a live cache, `key` must point to `key_len` floats and `value`
+/// to `value_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn proximity_cache_insert(
+    cache: *mut ProximityCache,
+    key: *const f32,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+    tolerance: f32,
+) -> ProximityStatus {
+    guarded(ProximityStatus::Error, || {
+        let cache = cache_mut(cache)?;
+        if tolerance.is_nan() || tolerance < 0.0 {
+            return Err(format!("tolerance must be non-negative, got {tolerance}"));
+        }
+        let key = cache.key(key, key_len)?;
+        let value = slice(value, value_len, "value")?.to_vec();
+        cache
+            .inner
+            .try_insert(key, value, tolerance)
+            .map_err(|e| e.to_string())?;
+        Ok(ProximityStatus::Ok)
+    })
+}
+
+/// Number of entries in the cache, or 0 if `cache` is NULL.
+///
+/// # Safety
+/// `cache` must be NULL or a live cache.
+#[no_mangle]
+pub unsafe extern "C" fn proximity_cache_len(cache: *const ProximityCache) -> usize {
+    cache.as_ref().map_or(0, |cache| cache.inner.len())
+}
+
+/// Releases a value returned by `proximity_cache_find`.
+///
+/// # Safety
+/// `bytes` must come from `proximity_cache_find` and not have been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn proximity_bytes_free(bytes: ProximityBytes) {
+    if !bytes.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            bytes.data, bytes.len,
+        )));
+    }
+}
+
+/// Message of the last error raised on this thread, or NULL if there was none.
+/// It stays valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn proximity_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |msg| msg.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    const TEST_TOLERANCE: f32 = 1e-8;
+
+    fn last_error() -> String {
+        let msg = proximity_last_error();
+        assert!(!msg.is_null());
+        unsafe { CStr::from_ptr(msg) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_insert_find_free() {
+        let cache = proximity_cache_new(ProximityPolicy::Lru as u32, 2, 0, 0, 0);
+        assert!(!cache.is_null());
+        let key = [1.0_f32; 8];
+        let value = b"Value 1";
+        unsafe {
+            assert_eq!(
+                proximity_cache_insert(cache, key.as_ptr(), 8, value.as_ptr(), 7, TEST_TOLERANCE),
+                ProximityStatus::Ok
+            );
+            assert_eq!(proximity_cache_len(cache), 1);
+
+            let mut out = ProximityBytes {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+            assert_eq!(
+                proximity_cache_find(cache, key.as_ptr(), 8, &mut out),
+                ProximityStatus::Ok
+            );
+            assert_eq!(std::slice::from_raw_parts(out.data, out.len), value);
+            proximity_bytes_free(out);
+
+            let other = [2.0_f32; 8];
+            let mut missed = ProximityBytes {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+            assert_eq!(
+                proximity_cache_find(cache, other.as_ptr(), 8, &mut missed),
+                ProximityStatus::Miss
+            );
+            proximity_cache_free(cache);
+        }
+    }
+
+    #[test]
+    fn test_lsh_cache() {
+        let cache = proximity_cache_new(ProximityPolicy::Fifo as u32, 4, 4, 8, 42);
+        assert!(!cache.is_null());
+        let key = [0.5_f32; 8];
+        unsafe {
+            proximity_cache_insert(cache, key.as_ptr(), 8, ptr::null(), 0, 0.1);
+            let mut out = ProximityBytes {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+            assert_eq!(
+                proximity_cache_find(cache, key.as_ptr(), 8, &mut out),
+                ProximityStatus::Ok
+            );
+            assert_eq!(out.len, 0);
+            proximity_bytes_free(out);
+            proximity_cache_free(cache);
+        }
+    }
+
+    #[test]
+    fn test_errors_are_reported() {
+        assert!(proximity_cache_new(ProximityPolicy::Lru as u32, 0, 0, 0, 0).is_null());
+        assert!(last_error().contains("capacity"));
+        assert!(proximity_cache_new(8, 2, 0, 0, 0).is_null());
+        assert_eq!(last_error(), "unknown eviction policy 8");
+
+        let cache = proximity_cache_new(ProximityPolicy::Lru as u32, 2, 4, 8, 0);
+        let short = [1.0_f32; 3];
+        let nan = [f32::NAN; 8];
+        unsafe {
+            let status = proximity_cache_insert(cache, short.as_ptr(), 3, ptr::null(), 0, 0.1);
+            assert_eq!(status, ProximityStatus::Error);
+            assert!(last_error().contains("dimension"));
+
+            let status = proximity_cache_insert(cache, nan.as_ptr(), 8, ptr::null(), 0, 0.1);
+            assert_eq!(status, ProximityStatus::Error);
+
+            let status = proximity_cache_insert(cache, ptr::null(), 8, ptr::null(), 0, 0.1);
+            assert_eq!(status, ProximityStatus::Error);
+            assert_eq!(last_error(), "key is null");
+
+            let status = proximity_cache_find(ptr::null_mut(), nan.as_ptr(), 8, ptr::null_mut());
+            assert_eq!(status, ProximityStatus::Error);
+            proximity_cache_free(cache);
+        }
+    }
+}